@@ -0,0 +1,11 @@
+// Generates the gRPC service of proto/agent.proto when the `grpc` feature is on
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/agent.proto");
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["proto/agent.proto"], ["proto"])?;
+        tonic_build::configure().build_client(false).compile_fds(descriptors)?;
+    }
+    Ok(())
+}
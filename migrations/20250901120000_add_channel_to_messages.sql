@@ -0,0 +1,3 @@
+-- Scope messages to a chat channel (e.g. a Discord channel); NULL for the local CLI
+ALTER TABLE messages ADD COLUMN channel_id TEXT;
+CREATE INDEX messages_channel_id_idx ON messages (channel_id, id);
@@ -0,0 +1,19 @@
+-- Group messages into sessions so earlier conversations can be resumed
+CREATE TABLE sessions (
+    id UUID PRIMARY KEY,
+    channel_id TEXT UNIQUE,
+    created_at TIMESTAMP DEFAULT now()
+);
+
+ALTER TABLE messages ADD COLUMN session_id UUID REFERENCES sessions (id);
+CREATE INDEX messages_session_id_idx ON messages (session_id, id);
+
+-- Move existing per-channel history into one session per channel
+INSERT INTO sessions (id, channel_id)
+SELECT gen_random_uuid(), channel_id FROM messages WHERE channel_id IS NOT NULL GROUP BY channel_id;
+
+UPDATE messages SET session_id = sessions.id
+FROM sessions WHERE messages.channel_id = sessions.channel_id;
+
+DROP INDEX messages_channel_id_idx;
+ALTER TABLE messages DROP COLUMN channel_id;
@@ -0,0 +1,13 @@
+-- Audit log of every tool the agent invoked
+CREATE TABLE tool_calls (
+    id BIGSERIAL PRIMARY KEY,
+    session_id UUID REFERENCES sessions (id),
+    tool_name TEXT NOT NULL,
+    args JSONB NOT NULL,
+    result TEXT NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    success BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX tool_calls_session_id_idx ON tool_calls (session_id, id);
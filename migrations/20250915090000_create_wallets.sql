@@ -0,0 +1,9 @@
+-- Wallet addresses the agent generated or derived, so they are known across runs.
+-- Private keys and mnemonics are never stored here.
+CREATE TABLE wallets (
+    address TEXT PRIMARY KEY,
+    source TEXT NOT NULL, -- 'generated' or 'hd'
+    derivation_index INTEGER,
+    label TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- Running summary of turns compacted out of a session's history, and how many of the
+-- session's messages (oldest first) it covers
+ALTER TABLE sessions ADD COLUMN summary TEXT;
+ALTER TABLE sessions ADD COLUMN summarized_messages BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Token usage and estimated cost accumulated over each session
+ALTER TABLE sessions ADD COLUMN requests BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN input_tokens BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN output_tokens BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0;
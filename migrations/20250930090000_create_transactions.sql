@@ -0,0 +1,19 @@
+-- Transactions the agent submitted, tracked until they are mined or dropped
+CREATE TABLE transactions (
+    tx_hash TEXT PRIMARY KEY,
+    session_id UUID REFERENCES sessions (id),
+    chain_id BIGINT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_address TEXT,
+    value TEXT NOT NULL, -- wei, as a decimal string
+    nonce BIGINT NOT NULL,
+    data TEXT,
+    description TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'confirmed', 'failed', or 'dropped'
+    block_number BIGINT,
+    reported BOOLEAN NOT NULL DEFAULT false, -- whether the outcome was shown to the user
+    submitted_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX transactions_status_idx ON transactions (status);
@@ -0,0 +1,7 @@
+-- Address signed in with Ethereum (EIP-4361) that owns a session; NULL for CLI and Discord sessions
+ALTER TABLE sessions ADD COLUMN owner_address TEXT;
+CREATE INDEX sessions_owner_address_idx ON sessions (owner_address);
+
+-- Owner of a wallet, taken from the session it was generated or derived in
+ALTER TABLE wallets ADD COLUMN owner_address TEXT;
+CREATE INDEX wallets_owner_address_idx ON wallets (owner_address);
@@ -0,0 +1,12 @@
+-- Results of scheduled jobs, kept whether or not anyone was watching when they ran
+CREATE TABLE scheduled_runs (
+    id BIGSERIAL PRIMARY KEY,
+    job_name TEXT NOT NULL,
+    input TEXT NOT NULL, -- the prompt, or the tool call with its arguments
+    output TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX scheduled_runs_job_name_idx ON scheduled_runs (job_name, started_at);
@@ -0,0 +1,17 @@
+-- Price alerts the user set up; each one fires once when the token's USD price crosses its threshold
+CREATE TABLE price_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    session_id UUID REFERENCES sessions (id),
+    owner_address TEXT, -- owner of the session the alert was created in
+    token TEXT NOT NULL, -- symbol or contract address, as given to the price tool
+    condition TEXT NOT NULL, -- 'above' or 'below'
+    threshold DOUBLE PRECISION NOT NULL, -- USD
+    status TEXT NOT NULL DEFAULT 'active', -- 'active', 'triggered', or 'cancelled'
+    triggered_price DOUBLE PRECISION,
+    explanation TEXT, -- written by the agent when the alert fired
+    reported BOOLEAN NOT NULL DEFAULT false, -- whether the firing was shown in the session
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    triggered_at TIMESTAMP
+);
+
+CREATE INDEX price_alerts_status_idx ON price_alerts (status);
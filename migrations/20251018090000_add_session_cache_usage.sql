@@ -0,0 +1,3 @@
+-- Input tokens written to and read from the prompt cache, counted apart from input_tokens
+ALTER TABLE sessions ADD COLUMN cache_write_tokens BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN cache_read_tokens BIGINT NOT NULL DEFAULT 0;
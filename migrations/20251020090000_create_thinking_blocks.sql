@@ -0,0 +1,10 @@
+-- The model's extended thinking, kept apart from the messages so it is never replayed
+CREATE TABLE thinking_blocks (
+    id BIGSERIAL PRIMARY KEY,
+    session_id UUID REFERENCES sessions (id),
+    model TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX thinking_blocks_session_id_idx ON thinking_blocks (session_id, id);
@@ -0,0 +1,2 @@
+-- The model that was active for each message, for cost analysis across model switches
+ALTER TABLE messages ADD COLUMN model TEXT;
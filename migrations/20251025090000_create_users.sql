@@ -0,0 +1,24 @@
+-- People sharing a server deployment, identified by the address they sign in with
+CREATE TABLE users (
+    address TEXT PRIMARY KEY,
+    name TEXT,
+    -- Transaction policy for the user's sessions in place of the deployment's policy file,
+    -- in the policy.json format
+    policy JSONB,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Long-lived API keys acting as a user, for scripts and services; only a hash of each key is kept
+CREATE TABLE api_keys (
+    key_hash TEXT PRIMARY KEY,
+    address TEXT NOT NULL REFERENCES users (address) ON DELETE CASCADE,
+    label TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP
+);
+CREATE INDEX api_keys_address_idx ON api_keys (address);
+
+-- Addresses that already signed in and own sessions become users
+INSERT INTO users (address)
+SELECT DISTINCT owner_address FROM sessions WHERE owner_address IS NOT NULL
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,4 @@
+-- What a user may have the agent do: 'viewer' (read-only tools), 'operator' (also sends and
+-- signs within policy), or 'admin' (also changes keystore accounts and users' policies)
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'operator'
+    CHECK (role IN ('viewer', 'operator', 'admin'));
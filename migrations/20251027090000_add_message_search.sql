@@ -0,0 +1,4 @@
+-- Full-text search over conversation history (`/search`, GET /history/search)
+ALTER TABLE messages ADD COLUMN search tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', content)) STORED;
+CREATE INDEX messages_search_idx ON messages USING GIN (search);
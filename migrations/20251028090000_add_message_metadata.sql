@@ -0,0 +1,14 @@
+-- What each assistant message took to produce: the prompt (including cached) and completion
+-- tokens of its turn's model calls, and how long the turn ran. NULL for user messages.
+ALTER TABLE messages ADD COLUMN input_tokens BIGINT;
+ALTER TABLE messages ADD COLUMN output_tokens BIGINT;
+ALTER TABLE messages ADD COLUMN latency_ms BIGINT;
+
+-- Every message has a time; rows stored without one take their session's
+UPDATE messages SET created_at = COALESCE((SELECT created_at FROM sessions WHERE id = messages.session_id), now())
+WHERE created_at IS NULL;
+ALTER TABLE messages ALTER COLUMN created_at SET NOT NULL;
+
+-- The user message whose turn made each tool call
+ALTER TABLE tool_calls ADD COLUMN message_id INTEGER REFERENCES messages (id) ON DELETE SET NULL;
+CREATE INDEX tool_calls_message_id_idx ON tool_calls (message_id);
@@ -0,0 +1,12 @@
+-- Labels for the addresses a user sends to, e.g. "mom" or "cold storage"
+CREATE TABLE address_book (
+    id BIGSERIAL PRIMARY KEY,
+    owner_address TEXT, -- signed-in user the entry belongs to; NULL for the CLI and Discord
+    label TEXT NOT NULL,
+    address TEXT NOT NULL, -- lowercase hex, resolved when the entry was added
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- One entry per label and owner, whatever its case
+CREATE UNIQUE INDEX address_book_label_idx ON address_book (COALESCE(owner_address, ''), lower(label));
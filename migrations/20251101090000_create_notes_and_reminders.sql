@@ -0,0 +1,25 @@
+-- Notes the user asked the agent to remember, kept across sessions
+CREATE TABLE notes (
+    id BIGSERIAL PRIMARY KEY,
+    owner_address TEXT, -- signed-in user the note belongs to; NULL for the CLI and Discord
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX notes_owner_idx ON notes (owner_address);
+
+-- Timed reminders; each fires once when it is due, in the session it was set in and on the
+-- notification channels
+CREATE TABLE reminders (
+    id BIGSERIAL PRIMARY KEY,
+    session_id UUID REFERENCES sessions (id),
+    owner_address TEXT, -- signed-in user the reminder belongs to; NULL for the CLI and Discord
+    message TEXT NOT NULL,
+    due_at TIMESTAMP NOT NULL, -- UTC
+    status TEXT NOT NULL DEFAULT 'active', -- 'active', 'fired', or 'cancelled'
+    reported BOOLEAN NOT NULL DEFAULT false, -- whether the firing was shown in the session
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    fired_at TIMESTAMP
+);
+
+CREATE INDEX reminders_due_idx ON reminders (status, due_at);
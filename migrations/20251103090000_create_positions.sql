@@ -0,0 +1,23 @@
+-- Buys, sells, and transfers of the user's accounts, for cost basis and PnL; a position is the
+-- replay of its entries, oldest first
+CREATE TABLE positions (
+    id BIGSERIAL PRIMARY KEY,
+    owner_address TEXT, -- signed-in user the entry belongs to; NULL for the CLI and Discord
+    chain_id BIGINT NOT NULL,
+    account TEXT NOT NULL, -- lowercase hex of the account holding the position
+    token TEXT NOT NULL, -- symbol, 'ETH' for the native currency
+    token_address TEXT, -- lowercase hex of the ERC-20 contract; NULL for ETH
+    kind TEXT NOT NULL, -- 'buy', 'sell', 'transfer_in', or 'transfer_out'
+    amount TEXT NOT NULL, -- in the token's smallest unit, as a decimal string
+    decimals INTEGER NOT NULL, -- of the token, 18 for ETH
+    price_usd TEXT, -- USD per whole token when the entry happened, as a decimal string; NULL when unknown
+    tx_hash TEXT,
+    source TEXT NOT NULL, -- 'swap', 'send', or 'import'
+    occurred_at TIMESTAMP NOT NULL, -- UTC
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX positions_account_idx ON positions (owner_address, chain_id, account);
+-- A transaction is recorded once, whether it was seen when sent or imported later
+CREATE UNIQUE INDEX positions_transaction_idx ON positions (COALESCE(owner_address, ''), chain_id, account, tx_hash, COALESCE(token_address, ''), kind)
+    WHERE tx_hash IS NOT NULL;
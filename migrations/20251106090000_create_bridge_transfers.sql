@@ -0,0 +1,18 @@
+-- Cross-chain transfers sent by the bridge tool. The source transaction is tracked in
+-- transactions; this follows the transfer until it arrives on the destination chain
+CREATE TABLE bridge_transfers (
+    tx_hash TEXT PRIMARY KEY, -- the source transaction
+    session_id UUID REFERENCES sessions (id),
+    bridge TEXT NOT NULL, -- LI.FI's key of the bridge used, e.g. 'stargate'
+    from_chain_id BIGINT NOT NULL,
+    to_chain_id BIGINT NOT NULL,
+    description TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'done', 'partial', 'refunded', or 'failed'
+    destination_tx_hash TEXT,
+    received TEXT, -- amount and token received, e.g. '99.8 USDC'
+    reported BOOLEAN NOT NULL DEFAULT false, -- whether the outcome was shown to the user
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX bridge_transfers_status_idx ON bridge_transfers (status);
@@ -0,0 +1,16 @@
+-- Sign-In with Ethereum state of the HTTP API, so that sign-ins survive restarts and every
+-- replica accepts them: the nonces issued for sign-in messages, each usable once, and the
+-- bearer tokens issued for signed messages
+CREATE TABLE auth_nonces (
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE auth_tokens (
+    token_hash TEXT PRIMARY KEY, -- only a hash of each token is kept, as for API keys
+    address TEXT NOT NULL REFERENCES users (address) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX auth_tokens_expires_idx ON auth_tokens (expires_at);
@@ -0,0 +1,10 @@
+-- Values sent under each transaction policy, so that its daily limit holds across restarts and
+-- replicas. A row is reserved when a transaction passes the policy and deleted if it is never sent.
+CREATE TABLE policy_spends (
+    id UUID PRIMARY KEY,
+    scope TEXT NOT NULL, -- whose policy: 'deployment', or a user's address
+    value NUMERIC(78, 0) NOT NULL, -- wei
+    spent_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX policy_spends_scope_idx ON policy_spends (scope, spent_at);
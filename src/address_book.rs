@@ -0,0 +1,115 @@
+// The address book (`address_book` tool): labels such as "mom", "cold storage", or "uniswap
+// router" for addresses the user sends to, kept in the database for each signed-in user, with
+// one book shared by the CLI and Discord. Recipients may be given by label; a label that isn't
+// in the book is refused rather than guessed at, and sends to an address that isn't in it come
+// with a warning.
+
+use ethers::types::Address;
+use std::str::FromStr;
+
+/// Longest label kept
+const MAX_LABEL_LEN: usize = 64;
+
+/// Where a send goes
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub address: Address,
+    /// Its label in the address book
+    pub label: Option<String>,
+    /// Whether there was an address book to check it against
+    pub checked: bool,
+}
+
+impl Recipient {
+    /// "mom (0x…)" for recipients in the address book
+    pub fn display(&self) -> Option<String> {
+        self.label.as_ref().map(|label| format!("{} ({:?})", label, self.address))
+    }
+
+    /// Shown with a transaction to an address that isn't in the address book
+    pub fn warning(&self) -> Option<String> {
+        (self.checked && self.label.is_none()).then(|| format!(
+            "Warning: {:?} is not in the address book. Make sure the user confirmed this address; \
+             it can be saved under a label with the address_book tool.",
+            self.address,
+        ))
+    }
+}
+
+/// The label to store, trimmed; refused when it could be taken for an address or ENS name
+pub fn check_label(label: &str) -> anyhow::Result<&str> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(anyhow::anyhow!("Please provide a label"));
+    }
+    if label.len() > MAX_LABEL_LEN {
+        return Err(anyhow::anyhow!("Labels are at most {} characters", MAX_LABEL_LEN));
+    }
+    if Address::from_str(label).is_ok() || label.starts_with("0x") || label.contains('.') {
+        return Err(anyhow::anyhow!("'{}' looks like an address or ENS name; labels can't be one or contain dots", label));
+    }
+    Ok(label)
+}
+
+/// Whether a recipient is given as a label rather than an address or ENS name
+pub fn is_label(input: &str) -> bool {
+    Address::from_str(input).is_err() && !input.contains('.')
+}
+
+/// Why a send to a label that isn't in the address book is refused
+pub fn unknown_label(label: &str, has_book: bool) -> String {
+    match has_book {
+        true => format!(
+            "Unknown recipient '{}': it is not in the address book, nor an address or ENS name. Nothing was sent. \
+             Ask the user for the address, and add it with the address_book tool if they want to send by label.",
+            label,
+        ),
+        false => format!(
+            "Unknown recipient '{}': it is not an address or ENS name, and the address book needs a database \
+             (DATABASE_URL). Nothing was sent.",
+            label,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::policy::{Policy, PolicyEngine};
+    use crate::test_support::database;
+    use crate::tool_error::ToolError;
+    use crate::tools::{execute_tool, ToolContext};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn sends_to_address_book_labels_and_refuses_unknown_ones() {
+        let mut config = Config::default();
+        // Never reached: sends stop at the missing key, after the recipient is resolved
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        let pool = database(&mut config).await;
+        let base = ToolContext::new(Arc::new(config), Arc::new(PolicyEngine::new(Policy::default())), Some(pool), None);
+        let alice = ToolContext { user: Some(format!("{:?}", ethers::types::Address::random())), ..base.clone() };
+        let bob = ToolContext { user: Some(format!("{:?}", ethers::types::Address::random())), ..base };
+        let mom = format!("{:?}", ethers::types::Address::random());
+
+        let saved = execute_tool(&alice, "address_book", &json!({ "operation": "add", "label": "mom", "address": mom })).await.unwrap();
+        assert!(saved.contains("Saved 'mom'"), "{}", saved);
+        let refused = execute_tool(&alice, "address_book", &json!({ "operation": "add", "label": "mom.eth", "address": mom })).await.unwrap_err();
+        assert!(matches!(refused, ToolError::InvalidArgs(_)), "{:?}", refused);
+        let list = |ctx: ToolContext| async move { execute_tool(&ctx, "address_book", &json!({ "operation": "list" })).await.unwrap() };
+        assert!(list(alice.clone()).await.contains(&mom));
+        assert!(!list(bob.clone()).await.contains(&mom));
+
+        let send = json!({ "operation": "send", "from_address": format!("{:?}", ethers::types::Address::random()), "to_address": "Mom", "amount": "0.05" });
+        let error = execute_tool(&alice, "eth_wallet", &send).await.unwrap_err();
+        assert!(error.message().contains("No key found") && !error.message().contains("Unknown recipient"), "{:?}", error);
+        // Bob has no "mom", so nothing is guessed for him
+        let error = execute_tool(&bob, "eth_wallet", &send).await.unwrap_err();
+        assert!(matches!(error, ToolError::InvalidArgs(_)) && error.message().contains("Unknown recipient 'Mom'"), "{:?}", error);
+
+        execute_tool(&alice, "address_book", &json!({ "operation": "remove", "label": "MOM" })).await.unwrap();
+        assert!(execute_tool(&alice, "eth_wallet", &send).await.unwrap_err().message().contains("Unknown recipient"));
+    }
+}
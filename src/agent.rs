@@ -0,0 +1,141 @@
+use crate::anthropic::{provider, run_turn, LlmProvider};
+use crate::conversation::Conversation;
+use crate::personality::Personality;
+use crate::tools::ToolContext;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The agent for programmatic use: a model provider, a persona, and the context its tools
+/// run with
+pub struct Agent {
+    provider: Arc<dyn LlmProvider>,
+    ctx: ToolContext,
+    personality: Option<Personality>,
+}
+
+/// Tool calls that would carry out a request, as returned by `plan`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ToolPlan {
+    /// What the plan does, in a sentence
+    pub summary: String,
+    /// Calls in the order they should run
+    pub steps: Vec<PlannedCall>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PlannedCall {
+    /// Name of the tool
+    pub tool: String,
+    /// Arguments of the call, matching the tool's input schema
+    pub arguments: serde_json::Value,
+    /// Why this call is needed
+    pub reason: String,
+}
+
+// The JSON value in a model answer: the whole answer, the body of a ```json fence, or the
+// span from the first opening bracket to the last closing one
+fn json_in(answer: &str) -> &str {
+    let answer = answer.trim();
+    if let Some(fenced) = answer.strip_prefix("```").and_then(|rest| rest.strip_suffix("```")) {
+        return fenced.trim_start_matches("json").trim();
+    }
+    let start = answer.find(['{', '[']);
+    let end = answer.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &answer[start..=end],
+        _ => answer,
+    }
+}
+
+impl Agent {
+    /// An agent answering through the configured provider
+    pub fn new(ctx: ToolContext, personality: Option<Personality>) -> anyhow::Result<Self> {
+        Ok(Agent::with_provider(provider(&ctx.config.anthropic)?, ctx, personality))
+    }
+
+    pub fn with_provider(provider: Arc<dyn LlmProvider>, ctx: ToolContext, personality: Option<Personality>) -> Self {
+        Agent { provider, ctx, personality }
+    }
+
+    /// Answer `prompt` with a value of type `T` rather than prose. The JSON Schema of `T` is
+    /// added to the prompt, and an answer that doesn't deserialize is sent back with the error
+    /// up to `[anthropic] structured_retries` times. The model can still use tools first.
+    pub async fn chat_structured<T: DeserializeOwned + JsonSchema>(&self, prompt: &str, conversation: &Conversation) -> anyhow::Result<T> {
+        let config = &self.ctx.config.anthropic;
+        let schema = serde_json::to_string_pretty(&schemars::schema_for!(T))?;
+        let mut conversation = conversation.clone();
+        let mut prompt = format!("{}\n\nReply with only a JSON value matching this JSON Schema, and no other text:\n{}", prompt, schema);
+        let mut attempt = 0;
+        loop {
+            let answer = run_turn(self.provider.as_ref(), config, &prompt, &[], self.personality.as_ref(), &conversation, &self.ctx).await?;
+            let error = match serde_json::from_str(json_in(&answer)) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if attempt == config.structured_retries {
+                return Err(anyhow::anyhow!("The model did not answer with valid JSON after {} attempts: {}", attempt + 1, error));
+            }
+            attempt += 1;
+            tracing::warn!(attempt, error = %error, "Asking again for a structured answer that didn't parse");
+            conversation.push("user", &prompt);
+            conversation.push("assistant", &answer);
+            prompt = format!("That answer is not valid: {}. Reply again with only the JSON value, matching the schema.", error);
+        }
+    }
+
+    /// The tool calls the agent would make for `request`, without making them. Sends only
+    /// simulate, as in dry-run mode, in case the model calls a tool anyway.
+    pub async fn plan(&self, request: &str) -> anyhow::Result<ToolPlan> {
+        let agent = Agent {
+            provider: self.provider.clone(),
+            ctx: ToolContext { dry_run: true, ..self.ctx.clone() },
+            personality: self.personality.clone(),
+        };
+        let prompt = format!("Plan the tool calls that would carry out this request, without making them: {}", request);
+        agent.chat_structured(&prompt, &Conversation::default()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::mock_llm::MockLlm;
+    use crate::test_support::context;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn returns_a_structured_plan() {
+        let ctx = context(Config::default());
+        let mock = Arc::new(MockLlm::new(vec![json!(
+            "```json\n{\"summary\": \"Check the balance\", \"steps\": [{\"tool\": \"eth_wallet\", \"arguments\": {\"operation\": \"balance\"}, \"reason\": \"Asked for it\"}]}\n```"
+        )]));
+        let agent = Agent::with_provider(mock.clone(), ctx, None);
+
+        let plan = agent.plan("What's my balance?").await.unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        assert_eq!(plan.steps[0].tool, "eth_wallet");
+        let prompt = mock.requests()[0]["messages"][0]["content"][0]["text"].as_str().unwrap().to_string();
+        assert!(prompt.contains("JSON Schema") && prompt.contains("\"steps\""), "{}", prompt);
+    }
+
+    #[tokio::test]
+    async fn asks_again_for_invalid_structured_answers() {
+        let ctx = context(Config::default());
+        let mock = Arc::new(MockLlm::new(vec![
+            json!("Sure! Here is the plan."),
+            json!({ "content": [{ "type": "text", "text": "{\"summary\": \"Nothing to do\", \"steps\": []}" }] }),
+        ]));
+        let agent = Agent::with_provider(mock.clone(), ctx, None);
+
+        let plan: ToolPlan = agent.chat_structured("Plan nothing", &Conversation::default()).await.unwrap();
+        assert_eq!(plan.summary, "Nothing to do");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        let retry = requests[1]["messages"].as_array().unwrap();
+        assert_eq!(retry.len(), 3);
+        assert!(retry[2]["content"][0]["text"].as_str().unwrap().starts_with("That answer is not valid"));
+    }
+}
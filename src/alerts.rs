@@ -0,0 +1,113 @@
+use crate::anthropic;
+use crate::config::Config;
+use crate::db::{active_price_alerts, take_triggered_price_alerts, trigger_price_alert, PriceAlertRecord};
+use crate::notify::{self, Kind, Notification};
+use crate::policy::PolicyEngine;
+use crate::prices::{self, TokenPrice};
+use crate::scheduler;
+use crate::tools::ToolContext;
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Whether a price is on the alerting side of the threshold
+pub fn crossed(condition: &str, threshold: f64, price: f64) -> bool {
+    match condition {
+        "above" => price >= threshold,
+        "below" => price <= threshold,
+        _ => false,
+    }
+}
+
+/// Condition of an alert in words, e.g. "ETH below $2000.00"
+pub fn describe_rule(alert: &PriceAlertRecord) -> String {
+    format!("{} {} ${:.2}", alert.token, alert.condition, alert.threshold)
+}
+
+// Ask the model for a short note on what the move means for the user. The alert fires with
+// the plain price if this fails.
+async fn explain(config: &Arc<Config>, policy: &Arc<PolicyEngine>, pool: &Pool<Postgres>, alert: &PriceAlertRecord, price: &TokenPrice) -> Option<String> {
+    if !config.alerts.explain {
+        return None;
+    }
+    let ctx = ToolContext::new(config.clone(), policy.clone(), Some(pool.clone()), alert.session_id);
+    let system = "You explain price alerts to the user of a crypto wallet assistant. In two or three \
+                  short sentences, say what happened to the price and what the user may want to \
+                  consider. Use only the figures given; do not speculate about news or give \
+                  financial advice.";
+    let prompt = format!("The user's alert \"{}\" fired.\nCurrent price: {}", describe_rule(alert), prices::format_price(&alert.token, price));
+    match anthropic::complete(&config.anthropic, &ctx, system, &prompt).await {
+        Ok(text) => Some(text.trim().to_string()),
+        Err(e) => {
+            tracing::warn!(alert_id = alert.id, error = %e, "Failed to explain price alert");
+            None
+        },
+    }
+}
+
+// Check every active alert once, fetching each token's price a single time
+async fn check(config: &Arc<Config>, policy: &Arc<PolicyEngine>, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    let mut by_token: BTreeMap<String, Vec<PriceAlertRecord>> = BTreeMap::new();
+    for alert in active_price_alerts(pool).await? {
+        by_token.entry(alert.token.to_lowercase()).or_default().push(alert);
+    }
+    for (token, alerts) in by_token {
+        let price = match prices::token_price(&config.prices, &token).await {
+            Ok(price) => price,
+            Err(e) => {
+                tracing::warn!(token = %token, error = %e, "Failed to fetch price for alerts");
+                continue;
+            },
+        };
+        let Some(usd) = price.usd else { continue };
+        for alert in alerts.iter().filter(|alert| crossed(&alert.condition, alert.threshold, usd)) {
+            let summary = prices::format_price(&alert.token, &price);
+            let explanation = explain(config, policy, pool, alert, &price).await
+                .map_or(summary.clone(), |text| format!("{}\n{}", summary, text));
+            // Skip alerts cancelled while the explanation was written
+            if !trigger_price_alert(pool, alert.id, usd, &explanation).await? {
+                continue;
+            }
+            tracing::info!(alert_id = alert.id, token = %alert.token, price = usd, "Price alert fired");
+            let title = format!("Price alert #{}: {}", alert.id, describe_rule(alert));
+            notify::send(&config.notify, &Notification::new(Kind::PriceAlert, title, explanation)).await;
+        }
+    }
+    Ok(())
+}
+
+/// Check the active price alerts in the background on the `[alerts]` schedule
+pub fn spawn(config: Arc<Config>, policy: Arc<PolicyEngine>, pool: Pool<Postgres>) {
+    if !config.alerts.enabled {
+        return;
+    }
+    let schedule = match scheduler::parse_schedule(&config.alerts.schedule) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            tracing::error!(error = %e, "Price alerts are not checked");
+            return;
+        },
+    };
+    tokio::spawn(async move {
+        while scheduler::sleep_until_next(&schedule).await {
+            if let Err(e) = check(&config, &policy, &pool).await {
+                tracing::warn!(error = %e, "Failed to check price alerts");
+            }
+        }
+    });
+}
+
+/// Alerts created in this session that fired since they were last reported
+pub async fn take_updates(pool: Option<&Pool<Postgres>>, session_id: Uuid) -> Vec<String> {
+    let Some(pool) = pool else { return Vec::new() };
+    match take_triggered_price_alerts(pool, session_id).await {
+        Ok(alerts) => alerts.iter()
+            .map(|alert| format!("Price alert #{} ({}) fired: {}", alert.id, describe_rule(alert), alert.explanation.as_deref().unwrap_or_default()))
+            .collect(),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load price alert updates");
+            Vec::new()
+        },
+    }
+}
@@ -1,25 +1,81 @@
+use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
-use std::future::Future;
-use std::pin::Pin;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use crate::config::AnthropicConfig;
+use crate::conversation::Conversation;
+use crate::db::save_thinking;
+use crate::injection_guard::{self, Strictness};
+use crate::memory;
+use crate::mock_llm;
 use crate::personality::Personality;
-use crate::tools::{execute_tool, get_available_tools};
+use crate::redact::{redact, RedactedStream};
+use crate::tools::{execute_tool, execute_tool_as, tools_for, ToolContext};
+use crate::usage::{self, Usage};
+use crate::users::{required_role, Role};
 
 #[derive(Serialize)]
-struct AnthropicRequest {
+pub struct AnthropicRequest {
     model: String,
     max_tokens: u32,
-    system: Option<String>,
+    system: Option<Vec<SystemBlock>>,
     messages: Vec<Message>,
     tools: Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<Thinking>,
+}
+
+/// Turns on extended thinking, with the tokens the model may spend on it before answering
+#[derive(Serialize, Clone, Copy)]
+struct Thinking {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    budget_tokens: u32,
 }
 
+// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// Budget `/think` turns thinking on with when none is configured
+pub const DEFAULT_THINKING_BUDGET: u32 = 4096;
+
 #[derive(Serialize, Clone)]
 struct AnthropicTool {
     name: String,
     description: String,
     input_schema: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+/// Marks the end of a prefix of the request (tools, then system, then messages) that the API
+/// may cache and reuse for later requests starting with the same prefix
+#[derive(Serialize, Clone, Copy)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+const EPHEMERAL: CacheControl = CacheControl { kind: "ephemeral" };
+
+#[derive(Serialize, Clone)]
+struct SystemBlock {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+impl SystemBlock {
+    fn text(text: String, cache_control: Option<CacheControl>) -> Self {
+        SystemBlock { kind: "text", text, cache_control }
+    }
 }
 
 #[derive(Serialize, Clone)]
@@ -34,6 +90,59 @@ pub struct Message {
     name: Option<String>,
 }
 
+impl Message {
+    /// Plain text message, used to replay stored conversation history
+    pub fn text(role: &str, text: &str) -> Self {
+        Message {
+            role: role.to_string(),
+            content: vec![ContentBlock::Text {
+                text: text.to_string(),
+            }],
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+    
+    pub fn role(&self) -> &str {
+        &self.role
+    }
+    
+    /// The text blocks of the message joined together; tool blocks are ignored
+    pub fn plain_text(&self) -> String {
+        joined_text(&self.content)
+    }
+
+    /// Whether this carries the results of the tool calls in the message before it
+    pub fn is_tool_result(&self) -> bool {
+        self.content.iter().any(|block| matches!(block, ContentBlock::ToolResult { .. }))
+    }
+
+    /// Assistant message asking for one tool call
+    #[cfg(test)]
+    pub fn tool_use(id: &str, name: &str, input: serde_json::Value) -> Self {
+        Message { content: vec![ContentBlock::ToolUse { id: id.to_string(), name: name.to_string(), input }], ..Message::text("assistant", "") }
+    }
+
+    /// User message with the result of one tool call
+    #[cfg(test)]
+    pub fn tool_result(id: &str, content: &str) -> Self {
+        Message {
+            content: vec![ContentBlock::ToolResult { tool_use_id: id.to_string(), content: content.to_string(), is_error: None }],
+            ..Message::text("user", "")
+        }
+    }
+}
+
+/// Rebuild conversation history from stored (role, content) rows. Leading assistant
+/// turns are dropped because the API expects the conversation to start with the user.
+pub fn history_from_rows(rows: &[(String, String)]) -> Vec<Message> {
+    rows.iter()
+        .skip_while(|(role, _)| role != "user")
+        .map(|(role, content)| Message::text(role, content))
+        .collect()
+}
+
 #[derive(Serialize, Clone)]
 struct AnthropicToolCall {
     id: String,
@@ -56,16 +165,49 @@ enum ContentBlock {
     ToolResult {
         tool_use_id: String,
         content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
     },
+    // Thinking blocks are sent back unchanged while the model is using tools, as the API
+    // requires, but are never part of the answer
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String, signature: String },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+    #[serde(rename = "image")]
+    Image { source: ImageSource },
+}
+
+/// Where an image attached to a prompt comes from: inline data, or a URL the API fetches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ImageSource {
+    #[serde(rename = "base64")]
+    Base64 { media_type: String, data: String },
+    #[serde(rename = "url")]
+    Url { url: String },
 }
 
 #[derive(Deserialize, Debug)]
-struct AnthropicResponse {
+pub struct AnthropicResponse {
     content: Vec<ContentBlock>,
     #[serde(default)]
     tool_calls: Vec<AnthropicToolCallResponse>,
-   
- 
+    #[serde(default)]
+    usage: Usage,
+}
+
+impl AnthropicResponse {
+    /// The content blocks, as the API sends them
+    #[cfg(test)]
+    pub fn content_json(&self) -> serde_json::Value {
+        serde_json::to_value(&self.content).unwrap()
+    }
+
+    #[cfg(test)]
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -91,42 +233,108 @@ struct AnthropicError {
     message: String,
 }
 
+/// Where model requests go: the Messages API, or a script standing in for it
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    async fn send(&self, config: &AnthropicConfig, req: &AnthropicRequest) -> anyhow::Result<AnthropicResponse>;
 
+    /// `send`, calling `on_text` with each piece of answer text as it arrives. Providers that
+    /// can't stream hand over each text block whole.
+    async fn stream(&self, config: &AnthropicConfig, req: &AnthropicRequest, on_text: &(dyn for<'t> Fn(&'t str) + Send + Sync)) -> anyhow::Result<AnthropicResponse> {
+        let response = self.send(config, req).await?;
+        for block in &response.content {
+            if let ContentBlock::Text { text } = block {
+                on_text(text);
+            }
+        }
+        Ok(response)
+    }
+}
 
+/// The Anthropic Messages API
+#[derive(Default)]
+pub struct AnthropicApi {
+    client: Client,
+}
 
-pub async fn call_anthropic_with_personality(prompt: &str, personality: Option<&Personality>) -> anyhow::Result<String> {
+#[async_trait]
+impl LlmProvider for AnthropicApi {
+    async fn send(&self, config: &AnthropicConfig, req: &AnthropicRequest) -> anyhow::Result<AnthropicResponse> {
+        send_request(&self.client, config, req).await
+    }
+
+    async fn stream(&self, config: &AnthropicConfig, req: &AnthropicRequest, on_text: &(dyn for<'t> Fn(&'t str) + Send + Sync)) -> anyhow::Result<AnthropicResponse> {
+        stream_request(&self.client, config, req, on_text).await
+    }
+}
+
+/// The model answering for `personality`: its own, or the configured one
+pub fn model_for(config: &AnthropicConfig, personality: Option<&Personality>) -> String {
+    personality.and_then(|p| p.model.clone()).unwrap_or_else(|| config.model.clone())
+}
+
+/// The configured provider: the scripted responses in `mock_fixture` if it is set, else the API
+pub fn provider(config: &AnthropicConfig) -> anyhow::Result<Arc<dyn LlmProvider>> {
+    match &config.mock_fixture {
+        Some(path) => Ok(mock_llm::shared(path)?),
+        None => Ok(Arc::new(AnthropicApi::default())),
+    }
+}
+
+pub async fn call_anthropic_with_personality(config: &AnthropicConfig, prompt: &str, images: &[ImageSource], personality: Option<&Personality>, conversation: &Conversation, ctx: &ToolContext) -> anyhow::Result<String> {
     // Check if this is a direct ETH send command before passing to Claude
-    if prompt.to_lowercase().starts_with("send") && prompt.contains("ETH") {
+    let may_send = personality.is_none_or(|p| p.allows_tool("eth_wallet", Some("send")));
+    if may_send && images.is_empty() && prompt.to_lowercase().starts_with("send") && prompt.contains("ETH") {
         // This looks like an ETH send command, try to execute it directly
         let args = serde_json::json!({
             "operation": "send",
             "raw_command": prompt
         });
         
-        match crate::tools::execute_tool("eth_wallet", &args).await {
-            Ok(result) => return Ok(result),
-            Err(e) => return Ok(format!("Error executing ETH transaction: {}", e)),
-        }
+        // A failed send is an error, carrying the ToolError so callers can tell why it failed
+        return execute_tool(ctx, "eth_wallet", &args).await.map_err(anyhow::Error::msg);
     }
     
     // Otherwise, proceed with normal Claude processing
-    call_anthropic_with_tools(prompt, personality, Vec::new()).await
+    call_anthropic_with_tools(config, prompt, images, personality, conversation, ctx).await
 }
 
-pub fn call_anthropic_with_tools<'a>(
-    prompt: &'a str, 
-    personality: Option<&'a Personality>,
-    previous_messages: Vec<Message>
-) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + 'a>> {
-    Box::pin(async move {
-    let api_key = env::var("ANTHROPIC_API_KEY")?;
-    let client = Client::new();
+pub async fn call_anthropic_with_tools(
+    config: &AnthropicConfig,
+    prompt: &str,
+    images: &[ImageSource],
+    personality: Option<&Personality>,
+    conversation: &Conversation,
+    ctx: &ToolContext,
+) -> anyhow::Result<String> {
+    run_turn(provider(config)?.as_ref(), config, prompt, images, personality, conversation, ctx).await
+}
 
-    // Create messages vector
-    let mut messages = previous_messages;
+/// One turn of the conversation with `provider`: model round trips, running the tools it asks
+/// for, until it gives a final answer. `images` go with the prompt, ahead of its text.
+#[tracing::instrument(name = "turn", skip_all, fields(session_id = ?ctx.session_id, images = images.len()))]
+pub async fn run_turn(
+    provider: &dyn LlmProvider,
+    config: &AnthropicConfig,
+    prompt: &str,
+    images: &[ImageSource],
+    personality: Option<&Personality>,
+    conversation: &Conversation,
+    ctx: &ToolContext,
+) -> anyhow::Result<String> {
+    // Create messages vector, starting with the persona's example exchanges as prior turns
+    let mut messages: Vec<Message> = personality
+        .map(|p| p.examples.iter()
+            .flat_map(|example| [Message::text("user", &example.user), Message::text("assistant", &example.assistant)])
+            .collect())
+        .unwrap_or_default();
+    messages.extend(conversation.messages.iter().cloned());
     
-    // Create system prompt with personality if provided
+    // Create system prompt with personality if provided. The persona and tool instructions stay
+    // the same from turn to turn, so they go first and can be cached; the summary and
+    // recollections after them change.
     let mut system_prompt_parts = Vec::new();
+    let mut changing_parts = Vec::new();
     
     if let Some(persona) = personality {
         system_prompt_parts.push(format!(
@@ -145,8 +353,8 @@ pub fn call_anthropic_with_tools<'a>(
         ));
     }
     
-    // Add tool usage instructions to system prompt
-    let tools = get_available_tools();
+    // Add tool usage instructions to system prompt, limited to the persona's allowed tools
+    let tools = tools_for(personality);
     if !tools.is_empty() {
         system_prompt_parts.push(format!(
             "\n\nYou have access to the following tools:\n{}\n\n\
@@ -160,198 +368,389 @@ pub fn call_anthropic_with_tools<'a>(
                 .join("\n")
         ));
     }
+    let strictness = Strictness::from_config(&ctx.config.tools.injection_guard);
+    if !tools.is_empty() {
+        system_prompt_parts.extend(injection_guard::system_rule(strictness));
+    }
     
-    let system_prompt = if !system_prompt_parts.is_empty() {
-        Some(system_prompt_parts.join("\n\n"))
-    } else {
-        None
-    };
+    // Add the summary of older turns that were compacted out of the history
+    changing_parts.extend(conversation.summary_block());
+    
+    // Add related excerpts from earlier sessions (long-term memory)
+    if let Some(pool) = &ctx.pool {
+        match memory::recall(pool, &ctx.config.memory, ctx.session_id, ctx.user.as_deref(), prompt).await {
+            Ok(recollections) => changing_parts.extend(memory::format_recollections(&recollections)),
+            Err(e) => tracing::warn!(error = %e, "Failed to recall memories"),
+        }
+    }
+    
+    let cache_control = config.prompt_caching.then_some(EPHEMERAL);
+    let mut system_prompt = Vec::new();
+    if !system_prompt_parts.is_empty() {
+        system_prompt.push(SystemBlock::text(system_prompt_parts.join("\n\n"), cache_control));
+    }
+    if !changing_parts.is_empty() {
+        system_prompt.push(SystemBlock::text(changing_parts.join("\n\n"), None));
+    }
+    let system_prompt = (!system_prompt.is_empty()).then_some(system_prompt);
     
     // Add user message if there are no previous messages or we need to add a new prompt
-    if messages.is_empty() || !prompt.is_empty() {
+    if messages.is_empty() || !prompt.is_empty() || !images.is_empty() {
+        let mut content: Vec<ContentBlock> = images.iter()
+            .map(|image| ContentBlock::Image { source: image.clone() })
+            .collect();
+        if !prompt.is_empty() || content.is_empty() {
+            content.push(ContentBlock::Text {
+                text: prompt.to_string(),
+            });
+        }
         messages.push(Message {
             role: "user".to_string(),
-            content: vec![ContentBlock::Text {
-                text: prompt.to_string(),
-            }],
+            content,
             tool_calls: None,
             tool_call_id: None,
             name: None,
         });
     }
     
-    // Convert tools to Anthropic format
+    // Convert tools to Anthropic format, caching the definitions up to the last one
+    let tool_count = tools.len();
     let anthropic_tools = if !tools.is_empty() {
-        let mut anthropic_tools = Vec::new();
-        
-        for tool in tools {
-            let input_schema = match tool.name.as_str() {
-                "get_weather" => serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "city": {
-                            "type": "string",
-                            "description": "The city to get weather for"
-                        }
-                    },
-                    "required": ["city"]
-                }),
-                "get_time" => serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "timezone": {
-                            "type": "string",
-                            "description": "Optional timezone (e.g., 'UTC', 'America/New_York'). If not provided, local time is returned."
-                        }
-                    }
-                }),
-                "eth_wallet" => serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "operation": {
-                            "type": "string",
-                            "description": "The operation to perform: 'generate', 'balance', or 'send'"
-                        },
-                        "address": {
-                            "type": "string",
-                            "description": "Ethereum address for 'balance' operation"
-                        },
-                        "from_address": {
-                            "type": "string",
-                            "description": "Sender's Ethereum address for 'send' operation"
-                        },
-                        "to_address": {
-                            "type": "string",
-                            "description": "Recipient's Ethereum address for 'send' operation"
-                        },
-                        "amount": {
-                            "type": "string",
-                            "description": "Amount of ETH to send for 'send' operation"
-                        },
-                        "private_key": {
-                            "type": "string",
-                            "description": "Private key for the sender's address (required for 'send' operation if the wallet is not stored)"
-                        }
-                    },
-                    "required": ["operation"]
-                }),
-                _ => serde_json::json!({"type": "object", "properties": {}}),
-            };
-            
-            anthropic_tools.push(AnthropicTool {
+        Some(tools.into_iter()
+            .enumerate()
+            .map(|(i, tool)| AnthropicTool {
                 name: tool.name,
                 description: tool.description,
-                input_schema,
-            });
-        }
-        
-        Some(anthropic_tools)
+                input_schema: tool.input_schema,
+                cache_control: if i + 1 == tool_count { cache_control } else { None },
+            })
+            .collect::<Vec<_>>())
     } else {
         None
     };
     
-    let req = AnthropicRequest {
-        model: "claude-3-opus-20240229".to_string(),
-        max_tokens: 1024,
-        system: system_prompt,
-        messages: messages.clone(), // Clone here to keep ownership
-        tools: anthropic_tools,
+    // At least one round trip is needed to get an answer
+    let max_iterations = config.max_tool_iterations.max(1);
+
+    // The persona's generation settings override the configured defaults
+    let model = model_for(config, personality);
+    let max_tokens = personality.and_then(|p| p.max_tokens).unwrap_or(config.max_tokens);
+    let temperature = personality.and_then(|p| p.temperature);
+    let top_p = personality.and_then(|p| p.top_p);
+
+    // The thinking budget comes out of max_tokens, so the answer gets max_tokens on top of it.
+    // Sampling settings can't be changed while thinking.
+    let thinking = (config.thinking_budget_tokens > 0).then(|| Thinking {
+        kind: "enabled",
+        budget_tokens: config.thinking_budget_tokens.max(MIN_THINKING_BUDGET),
+    });
+    let (max_tokens, temperature, top_p) = match thinking {
+        Some(thinking) => (max_tokens + thinking.budget_tokens, None, None),
+        None => (max_tokens, temperature, top_p),
     };
 
+    for iteration in 0..max_iterations {
+        let req = AnthropicRequest {
+            model: model.clone(),
+            max_tokens,
+            system: system_prompt.clone(),
+            messages: messages.clone(), // Clone here to keep ownership
+            tools: anthropic_tools.clone(),
+            temperature,
+            top_p,
+            thinking,
+        };
+
+        tracing::debug!(iteration, model = %model, messages = messages.len(), "Sending request to Anthropic");
+        // Frontends showing the answer as it is written get it streamed, piece by piece, redacted
+        // as a whole so that a secret split across pieces is caught
+        let response_data = match &ctx.deltas {
+            Some(deltas) => {
+                let redacted = Mutex::new(RedactedStream::default());
+                let response = provider.stream(config, &req, &|text| {
+                    if let Some(ready) = redacted.lock().unwrap().push(text) {
+                        deltas.lock().unwrap().push(ready);
+                    }
+                }).await?;
+                let rest = redacted.into_inner().unwrap().finish();
+                if !rest.is_empty() {
+                    deltas.lock().unwrap().push(rest);
+                }
+                response
+            },
+            None => provider.send(config, &req).await?,
+        };
+        usage::record(ctx, &model, response_data.usage).await;
+        keep_thinking(config, ctx, &model, &response_data.content).await;
+
+        // Collect every tool call in the response (tool_use blocks in content,
+        // falling back to the legacy tool_calls array)
+        let mut tool_uses: Vec<(String, String, serde_json::Value)> = response_data.content.iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } => Some((id.clone(), name.clone(), input.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let mut assistant_content = response_data.content.clone();
+        if tool_uses.is_empty() {
+            for tool_call in &response_data.tool_calls {
+                tool_uses.push((tool_call.id.clone(), tool_call.name.clone(), tool_call.parameters.clone()));
+                assistant_content.push(ContentBlock::ToolUse {
+                    id: tool_call.id.clone(),
+                    name: tool_call.name.clone(),
+                    input: tool_call.parameters.clone(),
+                });
+            }
+        }
+
+        if tool_uses.is_empty() {
+            tracing::debug!(iteration, "Model returned a final answer");
+            // A secret the user pasted in stays out of the transcript even if the model repeats it
+            return Ok(redact(&final_text(&response_data.content)).into_owned());
+        }
+        tracing::info!(iteration, tools = ?tool_uses.iter().map(|(_, name, _)| name.as_str()).collect::<Vec<_>>(), "Model requested tool calls");
+
+        // Read-only tools run concurrently, and any other tool on its own once those before it
+        // are done, so each send is recorded against the policy's daily limit before the next is
+        // checked. Results stay in the order of the tool_use blocks.
+        let mut tool_results = Vec::with_capacity(tool_uses.len());
+        for batch in tool_batches(&tool_uses) {
+            tool_results.extend(futures::future::join_all(tool_uses[batch].iter()
+                .map(|(tool_id, tool_name, tool_parameters)| run_tool_use(ctx, personality, strictness, tool_id, tool_name, tool_parameters))).await);
+        }
+
+        // Echo the assistant turn (text and tool_use blocks) back to the conversation
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: assistant_content,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        });
+
+        // Add all tool results as a single user message with tool_result content
+        messages.push(Message {
+            role: "user".to_string(),
+            content: tool_results,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        });
+    }
+
+    tracing::warn!(max_iterations, "Tool loop hit the iteration cap");
+    Err(anyhow::anyhow!(
+        "Stopped after {} tool iterations without a final answer",
+        max_iterations
+    ))
+}
+
+// Runs of consecutive read-only tool calls, and each other call on its own
+fn tool_batches(tool_uses: &[(String, String, serde_json::Value)]) -> Vec<Range<usize>> {
+    let read_only = |(_, name, input): &(String, String, serde_json::Value)| {
+        required_role(name, input.get("operation").and_then(|v| v.as_str())) == Role::Viewer
+    };
+    let mut batches: Vec<Range<usize>> = Vec::new();
+    for (i, tool_use) in tool_uses.iter().enumerate() {
+        match batches.last_mut() {
+            Some(batch) if read_only(tool_use) && read_only(&tool_uses[batch.start]) => batch.end = i + 1,
+            _ => batches.push(i..i + 1),
+        }
+    }
+    batches
+}
+
+// Run one requested tool and turn its output into the tool_result block for the model
+async fn run_tool_use(ctx: &ToolContext, personality: Option<&Personality>, strictness: Strictness, tool_id: &str, tool_name: &str, tool_parameters: &serde_json::Value) -> ContentBlock {
+    // The model only sees allowed tools, but enforce the allowlist here too
+    let tool_result = execute_tool_as(ctx, personality, tool_name, tool_parameters).await;
+    // Failures go back as JSON with their kind, flagged so the model knows the call failed
+    let (content, is_error) = match tool_result {
+        Ok(output) => (output, None),
+        Err(e) => (e.to_json(), Some(true)),
+    };
+    // Secrets (e.g. a generated key) never reach the model. Either can carry text from
+    // outside (pages, metadata, node errors), so both are guarded against instructions
+    // hidden in it.
+    ContentBlock::ToolResult {
+        tool_use_id: tool_id.to_string(),
+        content: injection_guard::guard(strictness, tool_name, &redact(&content)),
+        is_error,
+    }
+}
+
+// Send one request to the Messages API and parse the response
+async fn send_request(client: &Client, config: &AnthropicConfig, req: &AnthropicRequest) -> anyhow::Result<AnthropicResponse> {
+    let api_key = config.api_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No Anthropic API key configured; set ANTHROPIC_API_KEY"))?;
     let response = client
-        .post("https://api.anthropic.com/v1/messages")
+        .post(&config.api_url)
         .header("x-api-key", api_key)
         .header("anthropic-version", "2023-06-01")
         .header("content-type", "application/json")
-        .json(&req)
+        .json(req)
         .send()
         .await?;
-        
+
     // Get the response text
     let response_text = response.text().await?;
-    
+
     // Try to parse as error response first
     if let Ok(error_response) = serde_json::from_str::<AnthropicErrorResponse>(&response_text) {
-        return Err(anyhow::anyhow!("Anthropic API error: {}: {}", 
-            error_response.error.error_type, 
+        return Err(anyhow::anyhow!("Anthropic API error: {}: {}",
+            error_response.error.error_type,
             error_response.error.message));
     }
-    
+
     // If not an error, parse as successful response
-    let response_data: AnthropicResponse = match serde_json::from_str(&response_text) {
-        Ok(data) => data,
+    match serde_json::from_str(&response_text) {
+        Ok(data) => Ok(data),
         Err(e) => {
-            println!("Failed to parse response: {}", e);
-            println!("Response text: {}", response_text);
-            return Err(anyhow::anyhow!("Failed to parse Anthropic response: {}", e));
+            tracing::error!(error = %e, response = %response_text, "Failed to parse Anthropic response");
+            Err(anyhow::anyhow!("Failed to parse Anthropic response: {}", e))
         }
-    };
+    }
+}
 
-    // Check if there are tool calls in the response (either in tool_calls or content)
-    let mut has_tool_call = false;
-    let mut tool_name = String::new();
-    let mut tool_id = String::new();
-    let mut tool_parameters = serde_json::Value::Null;
-    
-    // First check for tool_use in content
-    for content_block in &response_data.content {
-        if let ContentBlock::ToolUse { id, name, input } = content_block {
-            has_tool_call = true;
-            tool_name = name.clone();
-            tool_id = id.clone();
-            tool_parameters = input.clone();
-            break;
+#[derive(Serialize)]
+struct StreamingRequest<'a> {
+    #[serde(flatten)]
+    req: &'a AnthropicRequest,
+    stream: bool,
+}
+
+// Send one request to the Messages API with `stream` on, reading its server-sent events as
+// they arrive
+async fn stream_request(client: &Client, config: &AnthropicConfig, req: &AnthropicRequest, on_text: &(dyn for<'t> Fn(&'t str) + Send + Sync)) -> anyhow::Result<AnthropicResponse> {
+    let api_key = config.api_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No Anthropic API key configured; set ANTHROPIC_API_KEY"))?;
+    let mut response = client
+        .post(&config.api_url)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", "2023-06-01")
+        .header("content-type", "application/json")
+        .json(&StreamingRequest { req, stream: true })
+        .send()
+        .await?;
+
+    // Errors before the stream starts come back as a plain JSON body
+    if !response.status().is_success() {
+        let response_text = response.text().await?;
+        return Err(match serde_json::from_str::<AnthropicErrorResponse>(&response_text) {
+            Ok(error_response) => anyhow::anyhow!("Anthropic API error: {}: {}", error_response.error.error_type, error_response.error.message),
+            Err(_) => anyhow::anyhow!("Anthropic API error: {}", response_text),
+        });
+    }
+
+    let mut streamed = StreamedResponse::default();
+    while let Some(chunk) = response.chunk().await? {
+        streamed.bytes(&chunk, on_text)?;
+    }
+    streamed.finish()
+}
+
+/// A response put back together from the server-sent events of a streamed request
+#[derive(Default)]
+pub struct StreamedResponse {
+    // Bytes received after the last complete event; a chunk can end inside a character
+    buffer: Vec<u8>,
+    // Content blocks as JSON, by index, with tool inputs as the JSON text received so far
+    blocks: Vec<(serde_json::Value, String)>,
+    usage: Usage,
+}
+
+impl StreamedResponse {
+    /// Take in a chunk of the response body, handling each event it completes
+    pub fn bytes(&mut self, chunk: &[u8], on_text: &(dyn for<'t> Fn(&'t str) + Send + Sync)) -> anyhow::Result<()> {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            self.event(&String::from_utf8_lossy(&event), on_text)?;
         }
+        Ok(())
     }
-    
-    // If no tool_use in content, check the tool_calls array (legacy format)
-    if !has_tool_call && !response_data.tool_calls.is_empty() {
-        has_tool_call = true;
-        let tool_call = &response_data.tool_calls[0];
-        tool_name = tool_call.name.clone();
-        tool_id = tool_call.id.clone();
-        tool_parameters = tool_call.parameters.clone();
+
+    /// Take in one event (its `event:` and `data:` lines), passing text deltas to `on_text`
+    pub fn event(&mut self, event: &str, on_text: &(dyn for<'t> Fn(&'t str) + Send + Sync)) -> anyhow::Result<()> {
+        let Some(data) = event.lines().find_map(|line| line.strip_prefix("data:")) else { return Ok(()) };
+        let data: serde_json::Value = serde_json::from_str(data.trim())?;
+        let index = data["index"].as_u64().unwrap_or_default() as usize;
+        match data["type"].as_str().unwrap_or_default() {
+            "message_start" => self.usage = serde_json::from_value(data["message"]["usage"].clone()).unwrap_or_default(),
+            "content_block_start" => {
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, (serde_json::Value::Null, String::new()));
+                }
+                self.blocks[index] = (data["content_block"].clone(), String::new());
+            },
+            "content_block_delta" => {
+                let Some((block, json)) = self.blocks.get_mut(index) else { return Ok(()) };
+                let delta = &data["delta"];
+                let append = |block: &mut serde_json::Value, field: &str, piece: &serde_json::Value| {
+                    let text = format!("{}{}", block[field].as_str().unwrap_or_default(), piece.as_str().unwrap_or_default());
+                    block[field] = serde_json::Value::String(text);
+                };
+                match delta["type"].as_str().unwrap_or_default() {
+                    "text_delta" => {
+                        on_text(delta["text"].as_str().unwrap_or_default());
+                        append(block, "text", &delta["text"]);
+                    },
+                    "input_json_delta" => json.push_str(delta["partial_json"].as_str().unwrap_or_default()),
+                    "thinking_delta" => append(block, "thinking", &delta["thinking"]),
+                    "signature_delta" => append(block, "signature", &delta["signature"]),
+                    _ => {},
+                }
+            },
+            "content_block_stop" => {
+                if let Some((block, json)) = self.blocks.get_mut(index)
+                    && !json.trim().is_empty()
+                {
+                    block["input"] = serde_json::from_str(json)
+                        .map_err(|e| anyhow::anyhow!("Streamed tool input is not valid JSON: {}", e))?;
+                }
+            },
+            "message_delta" => {
+                if let Some(output_tokens) = data["usage"]["output_tokens"].as_u64() {
+                    self.usage.output_tokens = output_tokens;
+                }
+            },
+            "error" => return Err(anyhow::anyhow!("Anthropic API error: {}: {}",
+                data["error"]["type"].as_str().unwrap_or_default(), data["error"]["message"].as_str().unwrap_or_default())),
+            _ => {},
+        }
+        Ok(())
     }
-    
-    if has_tool_call {
-        // Execute the tool
-        let tool_result = execute_tool(&tool_name, &tool_parameters).await?;
-        
-        // Create a tool response message with tool_use content
-        let tool_response_message = Message {
-            role: "assistant".to_string(),
-            content: vec![ContentBlock::ToolUse {
-                id: tool_id.clone(),
-                name: tool_name.clone(),
-                input: tool_parameters.clone(),
-            }],
-            tool_calls: None,
-            tool_call_id: None,
-            name: None,
-        };
-        
-        // Add the tool response message to the conversation
-        let mut new_messages = messages.clone();
-        new_messages.push(tool_response_message);
-        
-        // Add the tool result message as a user message with tool_result content
-        new_messages.push(Message {
-            role: "user".to_string(),
-            content: vec![ContentBlock::ToolResult {
-                tool_use_id: tool_id.clone(),
-                content: tool_result,
-            }],
-            tool_calls: None,
-            tool_call_id: None,
-            name: None,
-        });
-        
-        // Call the API again with the tool result
-        return call_anthropic_with_tools("", personality, new_messages).await;
+
+    pub fn finish(self) -> anyhow::Result<AnthropicResponse> {
+        let content = self.blocks.into_iter()
+            .filter(|(block, _)| !block.is_null())
+            .map(|(block, _)| serde_json::from_value(block))
+            .collect::<Result<_, _>>()
+            .map_err(|e| anyhow::anyhow!("Failed to parse streamed Anthropic response: {}", e))?;
+        Ok(AnthropicResponse { content, tool_calls: Vec::new(), usage: self.usage })
     }
-    
-    // If no tool calls, return the text response
-    let response_text = response_data.content.iter()
+}
+
+/// Single prompt completion without tools or history, for internal tasks such as summarization
+pub async fn complete(config: &AnthropicConfig, ctx: &ToolContext, system: &str, prompt: &str) -> anyhow::Result<String> {
+    let req = AnthropicRequest {
+        model: config.model.clone(),
+        max_tokens: config.max_tokens,
+        system: Some(vec![SystemBlock::text(system.to_string(), None)]),
+        messages: vec![Message::text("user", prompt)],
+        tools: None,
+        temperature: None,
+        top_p: None,
+        thinking: None,
+    };
+    let response = provider(config)?.send(config, &req).await?;
+    usage::record(ctx, &config.model, response.usage).await;
+    Ok(final_text(&response.content))
+}
+
+fn joined_text(content: &[ContentBlock]) -> String {
+    content.iter()
         .filter_map(|block| {
             match block {
                 ContentBlock::Text { text } => Some(text.clone()),
@@ -359,15 +758,171 @@ pub fn call_anthropic_with_tools<'a>(
             }
         })
         .collect::<Vec<String>>()
-        .join("");
-        
+        .join("")
+}
+
+// Hand the thinking in a response to the frontend showing it, and store it when
+// `persist_thinking` is set. Redacted thinking is encrypted and skipped.
+async fn keep_thinking(config: &AnthropicConfig, ctx: &ToolContext, model: &str, content: &[ContentBlock]) {
+    let thinking: Vec<&str> = content.iter()
+        .filter_map(|block| match block {
+            ContentBlock::Thinking { thinking, .. } if !thinking.trim().is_empty() => Some(thinking.as_str()),
+            _ => None,
+        })
+        .collect();
+    if thinking.is_empty() {
+        return;
+    }
+    if let Some(shown) = &ctx.thinking {
+        shown.lock().unwrap().extend(thinking.iter().map(|text| redact(text).into_owned()));
+    }
+    if config.persist_thinking
+        && let Some(pool) = &ctx.pool
+        && let Err(e) = save_thinking(pool, ctx.session_id, model, &thinking.join("\n\n")).await
+    {
+        tracing::warn!(error = %e, "Failed to store the model's thinking");
+    }
+}
+
+// Join the text blocks of a response into the final answer
+fn final_text(content: &[ContentBlock]) -> String {
+    let response_text = joined_text(content);
+
     // If the response is empty, add a fallback message
-    let response_text = if response_text.trim().is_empty() {
+    if response_text.trim().is_empty() {
         "I'm processing your request...".to_string()
     } else {
         response_text
-    };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::mock_llm::MockLlm;
+    use crate::test_support::context;
+    use crate::tool_error::ToolError;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn fails_direct_sends_with_the_tool_error() {
+        let ctx = context(Config::default());
+        let prompt = "send 0.1 ETH to 0x000000000000000000000000000000000000dEaD";
+        let error = call_anthropic_with_personality(&ctx.config.anthropic, prompt, &[], None, &Conversation::default(), &ctx).await.unwrap_err();
+        let tool_error = error.downcast_ref::<ToolError>().expect("a ToolError");
+        assert_eq!(error.to_string(), tool_error.message());
+    }
+
+    #[test]
+    fn runs_only_read_only_tools_together() {
+        let call = |name: &str, operation: &str| (String::new(), name.to_string(), json!({ "operation": operation }));
+        let tool_uses = [
+            call("get_time", ""), call("eth_wallet", "balance"),
+            call("eth_wallet", "send"), call("stake", "deposit"),
+            call("dex_swap", "quote"), call("lending", "health_factor"), call("bridge", "execute"),
+        ];
+        assert_eq!(tool_batches(&tool_uses), [0..2, 2..3, 3..4, 4..6, 6..7]);
+        assert!(tool_batches(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn marks_the_tools_and_persona_prompt_for_caching() {
+        let ctx = context(Config::default());
+        let mut conversation = Conversation::default();
+        conversation.summary = Some("The user asked about gas earlier.".to_string());
+        let mock = MockLlm::new(vec![json!("Hello.")]);
 
-    Ok(response_text)
-    })
+        run_turn(&mock, &ctx.config.anthropic, "Hi", &[], None, &conversation, &ctx).await.unwrap();
+        let request = &mock.requests()[0];
+        let system = request["system"].as_array().unwrap();
+        assert_eq!(system.len(), 2);
+        assert_eq!(system[0]["cache_control"], json!({ "type": "ephemeral" }));
+        assert!(system[1]["text"].as_str().unwrap().contains("asked about gas") && system[1].get("cache_control").is_none());
+        let tools = request["tools"].as_array().unwrap();
+        assert_eq!(tools.last().unwrap()["cache_control"], json!({ "type": "ephemeral" }));
+        assert!(tools[0].get("cache_control").is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_thinking_out_of_the_answer() {
+        let mut config = Config::default();
+        config.anthropic.thinking_budget_tokens = 2048;
+        config.anthropic.max_tokens = 1000;
+        let mut ctx = context(config);
+        ctx.thinking = Some(Arc::default());
+        let thinking = json!({ "type": "thinking", "thinking": "The user wants the time.", "signature": "sig" });
+        let mock = MockLlm::new(vec![
+            json!({ "content": [thinking, { "type": "tool_use", "id": "t1", "name": "get_time", "input": {} }] }),
+            json!({ "content": [{ "type": "redacted_thinking", "data": "opaque" }, { "type": "text", "text": "It is noon." }] }),
+        ]);
+
+        let answer = run_turn(&mock, &ctx.config.anthropic, "Time?", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        assert_eq!(answer, "It is noon.");
+        assert_eq!(*ctx.thinking.as_ref().unwrap().lock().unwrap(), vec!["The user wants the time.".to_string()]);
+        let requests = mock.requests();
+        assert_eq!(requests[0]["thinking"], json!({ "type": "enabled", "budget_tokens": 2048 }));
+        assert_eq!(requests[0]["max_tokens"], 3048);
+        // The thinking block goes back with the tool_use it led to
+        assert_eq!(requests[1]["messages"][1]["content"][0], thinking);
+    }
+
+    #[tokio::test]
+    async fn sends_attached_images_ahead_of_the_prompt() {
+        let ctx = context(Config::default());
+        let mock = MockLlm::new(vec![json!("A swap of 1 ETH.")]);
+        let image = ImageSource::Url { url: "https://example.com/tx.png".to_string() };
+
+        run_turn(&mock, &ctx.config.anthropic, "What is this?", &[image], None, &Conversation::default(), &ctx).await.unwrap();
+        let content = &mock.requests()[0]["messages"][0]["content"];
+        assert_eq!(content[0], json!({ "type": "image", "source": { "type": "url", "url": "https://example.com/tx.png" } }));
+        assert_eq!(content[1]["text"], "What is this?");
+    }
+
+    #[test]
+    fn reassembles_streamed_responses() {
+        let events = [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Checking "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"the balance."}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_01","name":"eth_wallet","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"operation\": \"bal"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"ance\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":40}}"#,
+        ];
+        let pieces = Mutex::new(Vec::new());
+        let mut streamed = StreamedResponse::default();
+        for data in events {
+            streamed.event(&format!("event: x\ndata: {}\n\n", data), &|text| pieces.lock().unwrap().push(text.to_string())).unwrap();
+        }
+        let response = streamed.finish().unwrap();
+        assert_eq!(*pieces.lock().unwrap(), ["Checking ", "the balance."]);
+        assert_eq!(response.content_json(), json!([
+            { "type": "text", "text": "Checking the balance." },
+            { "type": "tool_use", "id": "toolu_01", "name": "eth_wallet", "input": { "operation": "balance" } },
+        ]));
+        assert_eq!((response.usage().input_tokens, response.usage().output_tokens), (12, 40));
+
+        let mut failed = StreamedResponse::default();
+        let error = r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(failed.event(error, &|_| {}).unwrap_err().to_string().contains("Overloaded"));
+    }
+
+    #[tokio::test]
+    async fn streams_answer_text_to_frontends() {
+        let mock = MockLlm::new(vec![
+            json!({ "content": [{ "type": "text", "text": "Let me check." }, { "type": "tool_use", "id": "t1", "name": "get_time", "input": {} }] }),
+            json!("It is noon."),
+        ]);
+        let mut ctx = context(Config::default());
+        let deltas = Arc::new(Mutex::new(Vec::new()));
+        ctx.deltas = Some(deltas.clone());
+
+        let answer = run_turn(&mock, &ctx.config.anthropic, "What time is it?", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        assert_eq!(answer, "It is noon.");
+        assert_eq!(*deltas.lock().unwrap(), ["Let me check.", "It is noon."]);
+    }
 }
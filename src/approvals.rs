@@ -0,0 +1,460 @@
+// Token approval audits (`approvals` tool). The ERC-20 allowances and NFT approvals an owner has
+// granted are found in its `Approval` and `ApprovalForAll` logs, from the explorer API when it has
+// a key or an `eth_getLogs` scan of recent blocks otherwise, and each is checked against the
+// token's current state, since later approvals, transfers, and spending change or clear them.
+// Unlimited approvals to contracts whose source isn't verified are flagged, as are those to
+// accounts without code; revoking sets an allowance to zero or removes the operator.
+
+use crate::config::{ApprovalsConfig, EtherscanConfig};
+use crate::report;
+use crate::units;
+use ethers::prelude::*;
+use ethers::utils::keccak256;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+abigen!(
+    ApprovalToken,
+    r#"[
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function getApproved(uint256 tokenId) external view returns (address)
+        function isApprovedForAll(address owner, address operator) external view returns (bool)
+        function approve(address spender, uint256 amount) external returns (bool)
+        function setApprovalForAll(address operator, bool approved) external
+    ]"#
+);
+
+// Blocks per `eth_getLogs` request, within what public RPC providers accept
+const LOG_CHUNK_BLOCKS: u64 = 10_000;
+// Logs per page of the explorer's getLogs, its maximum
+const EXPLORER_PAGE: usize = 1000;
+// Most approvals checked on-chain in one audit
+const MAX_CHECKED: usize = 200;
+
+/// What an approval lets the spender move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// An ERC-20 allowance
+    Allowance,
+    /// One ERC-721 token
+    Token(U256),
+    /// Every ERC-721 or ERC-1155 token of the collection, through `setApprovalForAll`
+    Operator,
+}
+
+/// What is known about a spender
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spender {
+    /// A contract with verified source on the explorer, and its name
+    Verified(String),
+    Unverified,
+    /// An account without code
+    NotContract,
+    /// A contract whose source couldn't be checked, for want of an explorer API key
+    Unknown,
+}
+
+/// An approval still in force
+#[derive(Debug, Clone)]
+pub struct Approval {
+    pub token: Address,
+    pub symbol: String,
+    pub spender: Address,
+    pub kind: Kind,
+    /// Allowance left, for an ERC-20
+    pub amount: U256,
+    pub decimals: u8,
+    pub spender_info: Spender,
+}
+
+impl Approval {
+    /// Whether the spender may take any amount: an allowance of at least 2^96 - 1 (wallets
+    /// approve the largest uint256, tokens with 96-bit balances such as UNI cap it there), or an
+    /// operator of a whole collection
+    pub fn unlimited(&self) -> bool {
+        match self.kind {
+            Kind::Allowance => self.amount >= (U256::one() << 96) - 1,
+            Kind::Token(_) => false,
+            Kind::Operator => true,
+        }
+    }
+
+    /// Unlimited and to a spender nobody can read the code of
+    pub fn flagged(&self) -> bool {
+        self.unlimited() && matches!(self.spender_info, Spender::Unverified | Spender::NotContract)
+    }
+}
+
+/// The approvals an owner has outstanding
+#[derive(Debug, Clone)]
+pub struct Audit {
+    pub owner: Address,
+    pub approvals: Vec<Approval>,
+    /// First block scanned over RPC; None when the explorer's logs covered the whole history
+    pub scanned_from: Option<u64>,
+    /// Whether more logs or approvals were found than were read or checked
+    pub truncated: bool,
+}
+
+fn approval_topic() -> H256 {
+    H256::from(keccak256("Approval(address,address,uint256)"))
+}
+
+fn approval_for_all_topic() -> H256 {
+    H256::from(keccak256("ApprovalForAll(address,address,bool)"))
+}
+
+// The contract that emitted a log, and its topics
+type RawLog = (Address, Vec<H256>);
+
+// The owner's approval logs over the chain's whole history, and whether `max_logs` cut them short
+async fn explorer_logs(config: &EtherscanConfig, chain_id: u64, owner: Address, max_logs: u64) -> anyhow::Result<(Vec<RawLog>, bool)> {
+    let owner_topic = format!("{:?}", H256::from(owner));
+    let (mut logs, mut truncated) = (Vec::new(), false);
+    for event in [approval_topic(), approval_for_all_topic()] {
+        let event = format!("{:?}", event);
+        let (mut page, mut read) = (1, 0);
+        loop {
+            let (page_text, offset) = (page.to_string(), EXPLORER_PAGE.to_string());
+            let query = [
+                ("module", "logs"),
+                ("action", "getLogs"),
+                ("fromBlock", "0"),
+                ("toBlock", "latest"),
+                ("topic0", event.as_str()),
+                ("topic0_1_opr", "and"),
+                ("topic1", owner_topic.as_str()),
+                ("page", page_text.as_str()),
+                ("offset", offset.as_str()),
+            ];
+            let body = report::request(config, chain_id, &query).await?;
+            let items = match (body["status"].as_str(), body["result"].as_array()) {
+                (Some("1"), Some(items)) => items.clone(),
+                // No logs come as an error status
+                (_, Some(items)) if items.is_empty() => Vec::new(),
+                _ => return Err(anyhow::anyhow!("The explorer API refused getLogs: {}", report::refusal(&body))),
+            };
+            for item in &items {
+                let contract = serde_json::from_value(item["address"].clone());
+                let topics = serde_json::from_value(item["topics"].clone());
+                if let (Ok(contract), Ok(topics)) = (contract, topics) {
+                    logs.push((contract, topics));
+                }
+            }
+            read += items.len() as u64;
+            if items.len() < EXPLORER_PAGE {
+                break;
+            }
+            if read >= max_logs {
+                truncated = true;
+                break;
+            }
+            page += 1;
+        }
+    }
+    Ok((logs, truncated))
+}
+
+// The owner's approval logs over the last `scan_blocks` blocks, and the first block scanned
+async fn scanned_logs<M: Middleware + 'static>(client: Arc<M>, owner: Address, scan_blocks: u64) -> anyhow::Result<(Vec<RawLog>, u64)> {
+    let latest = client.get_block_number().await?.as_u64();
+    let start = latest.saturating_sub(scan_blocks.saturating_sub(1));
+    let mut logs = Vec::new();
+    let mut from = start;
+    while from <= latest {
+        let to = (from + LOG_CHUNK_BLOCKS - 1).min(latest);
+        let filter = Filter::new()
+            .from_block(from)
+            .to_block(to)
+            .topic0(vec![approval_topic(), approval_for_all_topic()])
+            .topic1(H256::from(owner));
+        logs.extend(client.get_logs(&filter).await?.into_iter().map(|log| (log.address, log.topics)));
+        from = to + 1;
+    }
+    Ok((logs, start))
+}
+
+// The token, spender, and kind of every approval the logs granted, revoked ones included
+fn granted(logs: &[RawLog]) -> BTreeSet<(Address, Address, Kind)> {
+    let approval = approval_topic();
+    logs.iter()
+        .filter_map(|(contract, topics)| {
+            let spender = Address::from(*topics.get(2)?);
+            // Approving the zero address is how ERC-721 approvals are cleared
+            if spender.is_zero() {
+                return None;
+            }
+            let kind = match (topics[0] == approval, topics.len()) {
+                (true, 3) => Kind::Allowance,
+                (true, 4) => Kind::Token(U256::from_big_endian(topics[3].as_bytes())),
+                (false, 3) => Kind::Operator,
+                _ => return None,
+            };
+            Some((*contract, spender, kind))
+        })
+        .collect()
+}
+
+// What the approval still allows: the allowance for an ERC-20, one for an NFT still approved,
+// and None once it was spent, revoked, or replaced
+async fn outstanding<M: Middleware + 'static>(token: &ApprovalToken<M>, owner: Address, spender: Address, kind: Kind) -> Option<U256> {
+    match kind {
+        Kind::Allowance => token.allowance(owner, spender).call().await.ok().filter(|amount| !amount.is_zero()),
+        Kind::Token(token_id) => (token.get_approved(token_id).call().await.ok()? == spender).then(U256::one),
+        Kind::Operator => token.is_approved_for_all(owner, spender).call().await.ok()?.then(U256::one),
+    }
+}
+
+async fn spender_info<M: Middleware + 'static>(client: Arc<M>, config: &EtherscanConfig, chain_id: u64, explorer: bool, spender: Address) -> Spender {
+    match client.get_code(spender, None).await {
+        Ok(code) if code.is_empty() => return Spender::NotContract,
+        Ok(_) if explorer => {},
+        _ => return Spender::Unknown,
+    }
+    let address = format!("{:?}", spender);
+    let query = [("module", "contract"), ("action", "getsourcecode"), ("address", address.as_str())];
+    match report::request(config, chain_id, &query).await {
+        Ok(body) => match (body["result"][0]["SourceCode"].as_str(), body["result"][0]["ContractName"].as_str()) {
+            (Some(""), _) => Spender::Unverified,
+            (Some(_), name) => Spender::Verified(name.unwrap_or_default().to_string()),
+            _ => Spender::Unknown,
+        },
+        Err(e) => {
+            tracing::warn!(spender = %address, error = %e, "Could not check the spender's source");
+            Spender::Unknown
+        },
+    }
+}
+
+/// The approvals `owner` has outstanding on `chain_id`
+pub async fn audit<M: Middleware + 'static>(client: Arc<M>, config: &ApprovalsConfig, etherscan: &EtherscanConfig, chain_id: u64, owner: Address) -> anyhow::Result<Audit> {
+    let explorer = etherscan.api_key.as_deref().is_some_and(|key| !key.is_empty());
+    let (logs, scanned_from, mut truncated) = match explorer {
+        true => {
+            let (logs, truncated) = explorer_logs(etherscan, chain_id, owner, config.max_logs).await?;
+            (logs, None, truncated)
+        },
+        false => {
+            let (logs, start) = scanned_logs(client.clone(), owner, config.scan_blocks).await?;
+            (logs, Some(start), false)
+        },
+    };
+    let granted = granted(&logs);
+    truncated |= granted.len() > MAX_CHECKED;
+
+    let mut tokens: HashMap<Address, (String, u8)> = HashMap::new();
+    let mut spenders: HashMap<Address, Spender> = HashMap::new();
+    let mut approvals = Vec::new();
+    for (address, spender, kind) in granted.into_iter().take(MAX_CHECKED) {
+        let token = ApprovalToken::new(address, client.clone());
+        let Some(amount) = outstanding(&token, owner, spender, kind).await else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = tokens.entry(address) {
+            let symbol = token.symbol().call().await.unwrap_or_else(|_| format!("{:?}", address));
+            let decimals = match kind {
+                Kind::Allowance => token.decimals().call().await.unwrap_or(0),
+                _ => 0,
+            };
+            entry.insert((symbol, decimals));
+        }
+        if let Entry::Vacant(entry) = spenders.entry(spender) {
+            entry.insert(spender_info(client.clone(), etherscan, chain_id, explorer, spender).await);
+        }
+        let (symbol, decimals) = tokens[&address].clone();
+        approvals.push(Approval { token: address, symbol, spender, kind, amount, decimals, spender_info: spenders[&spender].clone() });
+    }
+    // Flagged approvals first, then by token
+    approvals.sort_by_key(|approval| (!approval.flagged(), approval.symbol.to_lowercase(), approval.kind));
+    Ok(Audit { owner, approvals, scanned_from, truncated })
+}
+
+fn describe_spender(spender: &Spender) -> String {
+    match spender {
+        Spender::Verified(name) if name.is_empty() => "verified contract".to_string(),
+        Spender::Verified(name) => format!("verified contract {}", name),
+        Spender::Unverified => "unverified contract".to_string(),
+        Spender::NotContract => "not a contract".to_string(),
+        Spender::Unknown => "contract, verification unknown".to_string(),
+    }
+}
+
+/// What the approval allows, e.g. `unlimited USDC allowance`
+pub fn describe_grant(approval: &Approval) -> String {
+    match approval.kind {
+        Kind::Allowance if approval.unlimited() => format!("unlimited {} allowance", approval.symbol),
+        Kind::Allowance => format!("allowance of {} {}", units::format_token(approval.amount, approval.decimals as u32), approval.symbol),
+        Kind::Token(token_id) => format!("approval of {} #{}", approval.symbol, token_id),
+        Kind::Operator => format!("operator approval of every {} token", approval.symbol),
+    }
+}
+
+pub fn describe_audit(audit: &Audit, chain_name: &str) -> String {
+    let source = match audit.scanned_from {
+        Some(block) => format!("from logs since block {}; set an [etherscan] API key to cover the whole history", block),
+        None => "from the explorer's logs".to_string(),
+    };
+    if audit.approvals.is_empty() {
+        return format!("No outstanding approvals of {:?} on {} ({})", audit.owner, chain_name, source);
+    }
+    let mut lines = vec![format!("Approvals of {:?} on {} ({}):", audit.owner, chain_name, source)];
+    for approval in &audit.approvals {
+        lines.push(format!("- {}{} on {:?} to {:?} ({})",
+                           if approval.flagged() { "FLAGGED: " } else { "" },
+                           describe_grant(approval), approval.token, approval.spender, describe_spender(&approval.spender_info)));
+    }
+    let flagged = audit.approvals.iter().filter(|approval| approval.flagged()).count();
+    if flagged > 0 {
+        lines.push(format!("{} flagged: unlimited approvals to unverified contracts or to accounts that aren't contracts. Revoke them unless the user knows the spender", flagged));
+    }
+    if audit.truncated {
+        lines.push(format!("More approvals were found than checked; only the first {} were", MAX_CHECKED));
+    }
+    lines.join("\n")
+}
+
+/// The approval of `spender` on `token` and the call that revokes it: the operator approval or
+/// allowance it holds, or its approval of `token_id`
+pub async fn revoke_call<M: Middleware + 'static>(client: Arc<M>, token: Address, owner: Address, spender: Address, token_id: Option<U256>) -> anyhow::Result<(Approval, Bytes)> {
+    let contract = ApprovalToken::new(token, client);
+    let symbol = contract.symbol().call().await.unwrap_or_else(|_| format!("{:?}", token));
+    let kinds = match token_id {
+        Some(token_id) => vec![Kind::Token(token_id)],
+        None => vec![Kind::Operator, Kind::Allowance],
+    };
+    for kind in kinds {
+        let Some(amount) = outstanding(&contract, owner, spender, kind).await else {
+            continue;
+        };
+        let call = match kind {
+            Kind::Allowance => contract.approve(spender, U256::zero()).calldata(),
+            Kind::Token(token_id) => contract.approve(Address::zero(), token_id).calldata(),
+            Kind::Operator => contract.set_approval_for_all(spender, false).calldata(),
+        };
+        let decimals = match kind {
+            Kind::Allowance => contract.decimals().call().await.unwrap_or(0),
+            _ => 0,
+        };
+        let approval = Approval { token, symbol, spender, kind, amount, decimals, spender_info: Spender::Unknown };
+        return Ok((approval, call.unwrap_or_default()));
+    }
+    Err(anyhow::anyhow!("{:?} has no outstanding approval of {} for {:?}", owner, match token_id {
+        Some(token_id) => format!("{} #{}", symbol, token_id),
+        None => symbol,
+    }, spender))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context, returns, rpc_node, serve, EthCall};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn audits_approvals_and_flags_unlimited_ones_to_unverified_contracts() {
+        use axum::extract::Query;
+        use ethers::abi::Token;
+
+        let [usdc, apes] = [0x72, 0x90].map(Address::repeat_byte);
+        let [router, drainer, spent, wallet] = [0x81, 0x82, 0x83, 0x84].map(Address::repeat_byte);
+        let owner = Address::repeat_byte(0x11);
+        let topic = |address: Address| format!("{:?}", H256::from(address));
+        let [approval, approval_for_all] = ["Approval(address,address,uint256)", "ApprovalForAll(address,address,bool)"].map(|event| format!("{:?}", H256::from(keccak256(event))));
+        // USDC allowances to a verified router and an unverified contract (both unlimited) and
+        // one since spent; an unverified operator of every APES token, and APES #7 approved to a
+        // wallet
+        let logs = [
+            (usdc, vec![approval.clone(), topic(owner), topic(router)]),
+            (usdc, vec![approval.clone(), topic(owner), topic(drainer)]),
+            (usdc, vec![approval.clone(), topic(owner), topic(spent)]),
+            (apes, vec![approval_for_all.clone(), topic(owner), topic(drainer)]),
+            (apes, vec![approval.clone(), topic(owner), topic(wallet), format!("{:?}", H256::from_low_u64_be(7))]),
+        ];
+        let log = |(address, topics): &(Address, Vec<String>)| json!({ "address": format!("{:?}", address), "topics": topics, "data": "0x", "blockNumber": "0xf9e" });
+        let rpc_logs: Vec<_> = logs.iter().map(log).collect();
+        let rpc = rpc_node(move |method, params| match method {
+            "eth_blockNumber" => Some(json!("0x1000")),
+            "eth_getLogs" => {
+                assert_eq!(params[0]["fromBlock"], "0xf9d");
+                Some(json!(rpc_logs))
+            },
+            "eth_getCode" => Some(json!(if params[0] == format!("{:?}", wallet) { "0x" } else { "0x6080" })),
+            "eth_call" => {
+                let call = EthCall::parse(params);
+                let to = call.to;
+                let tokens = match to {
+                    _ if call.is("symbol()") => vec![Token::String(if to == usdc { "USDC" } else { "APES" }.to_string())],
+                    _ if to == usdc && call.is("decimals()") => vec![Token::Uint(6.into())],
+                    _ if to == usdc && call.is("allowance(address,address)") => vec![Token::Uint(if call.address(1) == spent { U256::zero() } else { U256::MAX })],
+                    _ if to == apes && call.is("isApprovedForAll(address,address)") => vec![Token::Bool(call.address(1) == drainer)],
+                    _ if to == apes && call.is("getApproved(uint256)") => vec![Token::Address(wallet)],
+                    _ => return None,
+                };
+                returns(&tokens)
+            },
+            method => panic!("unexpected RPC call {}", method),
+        }).await;
+        let explorer = axum::Router::new().route("/", axum::routing::get(move |Query(query): Query<HashMap<String, String>>| async move {
+            assert_eq!(query["apikey"], "explorer-key");
+            match query["module"].as_str() {
+                "logs" => {
+                    assert_eq!(query["topic1"], topic(owner));
+                    let found: Vec<_> = logs.iter().filter(|(_, topics)| topics[0] == query["topic0"]).map(log).collect();
+                    axum::Json(json!({ "status": "1", "message": "OK", "result": found }))
+                },
+                _ => {
+                    let verified = query["address"] == format!("{:?}", router);
+                    axum::Json(json!({ "status": "1", "result": [{
+                        "SourceCode": if verified { "contract SwapRouter02 {}" } else { "" },
+                        "ContractName": if verified { "SwapRouter02" } else { "" },
+                    }] }))
+                },
+            }
+        }));
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(rpc);
+        config.chain.chain_id = 1;
+        config.chain.name = "Ethereum".to_string();
+        config.approvals.scan_blocks = 100;
+        let account = format!("{:?}", owner);
+        let list = json!({ "operation": "list", "account": account });
+
+        // Without an explorer key the recent blocks are scanned, and sources can't be checked
+        let scanned = execute_tool(&context(config.clone()), "approvals", &list).await.unwrap();
+        assert!(scanned.starts_with(&format!("Approvals of {:?} on Ethereum (from logs since block 3997; set an [etherscan] API key to cover the whole history):\n\
+                                              - approval of APES #7 on {:?} to {:?} (not a contract)\n\
+                                              - operator approval of every APES token on {:?} to {:?} (contract, verification unknown)\n", owner, apes, wallet, apes, drainer)), "{}", scanned);
+        assert!(!scanned.contains("FLAGGED") && !scanned.contains(&format!("{:?}", spent)), "{}", scanned);
+
+        config.etherscan.api_url = serve(explorer).await;
+        config.etherscan.api_key = Some("explorer-key".to_string());
+        let ctx = context(config);
+        let audit = execute_tool(&ctx, "approvals", &list).await.unwrap();
+        assert!(audit.starts_with(&format!("Approvals of {:?} on Ethereum (from the explorer's logs):\n\
+                                            - FLAGGED: operator approval of every APES token on {:?} to {:?} (unverified contract)\n\
+                                            - FLAGGED: unlimited USDC allowance on {:?} to {:?} (unverified contract)\n\
+                                            - approval of APES #7 on {:?} to {:?} (not a contract)\n\
+                                            - unlimited USDC allowance on {:?} to {:?} (verified contract SwapRouter02)\n\
+                                            2 flagged: unlimited approvals to unverified contracts or to accounts that aren't contracts",
+                                           owner, apes, drainer, usdc, drainer, apes, wallet, usdc, router)), "{}", audit);
+
+        let revoke = |token: Address, spender: Address, token_id: Option<&str>| json!({
+            "operation": "revoke", "account": account, "token": format!("{:?}", token), "spender": format!("{:?}", spender), "token_id": token_id,
+        });
+        let allowance = execute_tool(&ctx, "approvals", &revoke(usdc, drainer, None)).await.unwrap_err();
+        assert!(allowance.message().starts_with(&format!("Revocations need confirm=true. Show the user this and send it only once they confirm:\n\
+                                                          Revoke the unlimited USDC allowance on {:?} to {:?} from {:?}", usdc, drainer, owner)), "{}", allowance.message());
+        let operator = execute_tool(&ctx, "approvals", &revoke(apes, drainer, None)).await.unwrap_err();
+        assert!(operator.message().contains("Revoke the operator approval of every APES token"), "{}", operator.message());
+        let single = execute_tool(&ctx, "approvals", &revoke(apes, wallet, Some("7"))).await.unwrap_err();
+        assert!(single.message().contains("Revoke the approval of APES #7"), "{}", single.message());
+        let gone = execute_tool(&ctx, "approvals", &revoke(usdc, spent, None)).await.unwrap_err();
+        assert!(gone.message().contains(&format!("{:?} has no outstanding approval of USDC for {:?}", owner, spent)), "{}", gone.message());
+    }
+}
@@ -0,0 +1,400 @@
+// Cross-chain transfers (`bridge` tool) through the LI.FI aggregator API, which finds a route
+// over the bridges it integrates (Across, Stargate, Hop, and others) and returns the transaction
+// that starts it on the source chain. The transfer completes with a transaction on the
+// destination chain once the bridge delivers, which LI.FI's status endpoint reports; the tracker
+// polls it for transfers still on their way.
+
+use crate::chains;
+use crate::config::BridgeConfig;
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::format_units;
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// LI.FI's address for a chain's native currency
+pub const NATIVE_TOKEN: Address = Address::zero();
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Token {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl Token {
+    pub fn format(&self, amount: U256) -> String {
+        let value = format_units(amount, self.decimals as u32).unwrap_or_else(|_| amount.to_string());
+        let value = if value.contains('.') { value.trim_end_matches('0').trim_end_matches('.').to_string() } else { value };
+        format!("{} {}", value, self.symbol)
+    }
+}
+
+/// A route for a transfer and the transaction that starts it
+#[derive(Debug, Clone)]
+pub struct Quote {
+    /// LI.FI's key of the bridge, which its status endpoint takes
+    pub bridge: String,
+    pub bridge_name: String,
+    pub from_token: Token,
+    pub to_token: Token,
+    pub from_amount: U256,
+    pub to_amount: U256,
+    /// Least that arrives at the configured slippage
+    pub to_amount_min: U256,
+    pub fees_usd: f64,
+    pub gas_usd: f64,
+    pub duration_secs: u64,
+    /// Contract the sent token must be approved for (none for the native currency)
+    pub approval_address: Option<Address>,
+    pub to: Address,
+    pub data: Bytes,
+    pub value: U256,
+}
+
+/// A transfer to quote
+pub struct Transfer<'a> {
+    pub from_chain: u64,
+    pub to_chain: u64,
+    pub from_token: Address,
+    /// Address or symbol on the destination chain, which LI.FI resolves
+    pub to_token: &'a str,
+    pub amount: U256,
+    pub from_address: Address,
+    pub to_address: Address,
+}
+
+/// Where a transfer is: LI.FI's `PENDING`, `DONE`, `FAILED`, or `NOT_FOUND` (not indexed yet)
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub state: String,
+    /// For `DONE`: `COMPLETED`, `PARTIAL` (another token arrived), or `REFUNDED`
+    pub substatus: Option<String>,
+    pub message: Option<String>,
+    pub to_chain: Option<u64>,
+    pub destination_tx_hash: Option<String>,
+    /// Amount and token that arrived
+    pub received: Option<String>,
+}
+
+impl Status {
+    /// The tracker's status of the transfer: `pending`, `done`, `partial`, `refunded`, or `failed`
+    pub fn outcome(&self) -> &'static str {
+        match (self.state.as_str(), self.substatus.as_deref()) {
+            ("DONE", Some("PARTIAL")) => "partial",
+            ("DONE", Some("REFUNDED")) => "refunded",
+            ("DONE", _) => "done",
+            ("FAILED" | "INVALID", _) => "failed",
+            _ => "pending",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawQuote {
+    tool: String,
+    tool_details: Option<ToolDetails>,
+    action: RawAction,
+    estimate: RawEstimate,
+    transaction_request: RawTransaction,
+}
+
+#[derive(Deserialize)]
+struct ToolDetails {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAction {
+    from_token: Token,
+    to_token: Token,
+    from_amount: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawEstimate {
+    to_amount: String,
+    to_amount_min: String,
+    approval_address: Option<Address>,
+    #[serde(default)]
+    execution_duration: f64,
+    #[serde(default)]
+    fee_costs: Vec<Cost>,
+    #[serde(default)]
+    gas_costs: Vec<Cost>,
+}
+
+#[derive(Deserialize)]
+struct Cost {
+    #[serde(rename = "amountUSD")]
+    amount_usd: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawTransaction {
+    to: Address,
+    data: Bytes,
+    value: Option<U256>,
+}
+
+fn total_usd(costs: &[Cost]) -> f64 {
+    costs.iter().filter_map(|cost| cost.amount_usd.as_deref()?.parse::<f64>().ok()).fold(0.0, |total, usd| total + usd)
+}
+
+fn parse_amount(value: &str) -> anyhow::Result<U256> {
+    U256::from_dec_str(value).map_err(|_| anyhow::anyhow!("LI.FI returned an invalid amount {}", value))
+}
+
+async fn get(config: &BridgeConfig, path: &str, query: &[(&str, String)]) -> anyhow::Result<serde_json::Value> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut request = client.get(format!("{}{}", config.api_url.trim_end_matches('/'), path)).query(query);
+    if let Some(key) = config.api_key.as_deref().filter(|key| !key.is_empty()) {
+        request = request.header("x-lifi-api-key", key);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await
+        .map_err(|_| anyhow::anyhow!("LI.FI returned {}", status))?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("LI.FI returned {}: {}", status, body["message"].as_str().unwrap_or("no reason given")));
+    }
+    Ok(body)
+}
+
+/// A destination chain given by name or chain id, which must be one of `[bridge] chains`
+pub fn destination(config: &BridgeConfig, source_chain_id: u64, input: &str) -> anyhow::Result<u64> {
+    let input = input.trim();
+    let chain_id = match input.parse::<u64>() {
+        Ok(id) => Some(id),
+        Err(_) => config.chains.iter().copied().find(|id| {
+            let name = chain_name(*id);
+            name.eq_ignore_ascii_case(input) || name.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case(input))
+        }),
+    };
+    match chain_id {
+        Some(id) if id == source_chain_id => Err(anyhow::anyhow!("{} is the chain the transfer is sent from", chain_name(id))),
+        Some(id) if config.chains.contains(&id) => Ok(id),
+        _ => Err(anyhow::anyhow!("Transfers can go to {}; add others to [bridge] chains",
+                                 config.chains.iter().filter(|id| **id != source_chain_id).map(|id| format!("{} ({})", chain_name(*id), id)).collect::<Vec<_>>().join(", "))),
+    }
+}
+
+/// Name of a chain for output, from the chain registry
+pub fn chain_name(chain_id: u64) -> String {
+    chains::find(chain_id).map_or_else(|| format!("chain {}", chain_id), |chain| chain.name.to_string())
+}
+
+pub async fn quote(config: &BridgeConfig, transfer: &Transfer<'_>) -> anyhow::Result<Quote> {
+    let query = [
+        ("fromChain", transfer.from_chain.to_string()),
+        ("toChain", transfer.to_chain.to_string()),
+        ("fromToken", format!("{:?}", transfer.from_token)),
+        ("toToken", transfer.to_token.to_string()),
+        ("fromAmount", transfer.amount.to_string()),
+        ("fromAddress", format!("{:?}", transfer.from_address)),
+        ("toAddress", format!("{:?}", transfer.to_address)),
+        ("slippage", (config.slippage_bps as f64 / 10_000.0).to_string()),
+        ("integrator", config.integrator.clone()),
+    ];
+    let body = get(config, "/quote", &query).await?;
+    let raw: RawQuote = serde_json::from_value(body).map_err(|e| anyhow::anyhow!("Unexpected quote from LI.FI: {}", e))?;
+    let native = raw.action.from_token.address == NATIVE_TOKEN;
+    Ok(Quote {
+        bridge_name: raw.tool_details.map_or_else(|| raw.tool.clone(), |details| details.name),
+        bridge: raw.tool,
+        from_amount: parse_amount(&raw.action.from_amount)?,
+        from_token: raw.action.from_token,
+        to_token: raw.action.to_token,
+        to_amount: parse_amount(&raw.estimate.to_amount)?,
+        to_amount_min: parse_amount(&raw.estimate.to_amount_min)?,
+        fees_usd: total_usd(&raw.estimate.fee_costs),
+        gas_usd: total_usd(&raw.estimate.gas_costs),
+        duration_secs: raw.estimate.execution_duration.max(0.0) as u64,
+        approval_address: raw.estimate.approval_address.filter(|_| !native),
+        to: raw.transaction_request.to,
+        data: raw.transaction_request.data,
+        value: raw.transaction_request.value.unwrap_or_default(),
+    })
+}
+
+pub fn describe_duration(secs: u64) -> String {
+    match secs {
+        0..=90 => format!("{} s", secs),
+        _ => format!("{} min", secs.div_ceil(60)),
+    }
+}
+
+pub fn describe_quote(quote: &Quote, from_chain: u64, to_chain: u64) -> String {
+    let mut lines = vec![
+        format!("Bridge {} from {} to {} via {}", quote.from_token.format(quote.from_amount), chain_name(from_chain), chain_name(to_chain), quote.bridge_name),
+        format!("Receive: about {} (at least {})", quote.to_token.format(quote.to_amount), quote.to_token.format(quote.to_amount_min)),
+        format!("Fees: ${:.2}, gas about ${:.2}", quote.fees_usd, quote.gas_usd),
+        format!("Estimated time: {}", describe_duration(quote.duration_secs)),
+    ];
+    if let Some(spender) = quote.approval_address {
+        lines.push(format!("Needs an allowance of {} for {:?}", quote.from_token.symbol, spender));
+    }
+    lines.join("\n")
+}
+
+/// Status of the transfer a source transaction started
+pub async fn status(config: &BridgeConfig, tx_hash: &str, bridge: Option<&str>, from_chain: u64, to_chain: Option<u64>) -> anyhow::Result<Status> {
+    let mut query = vec![("txHash", tx_hash.to_string()), ("fromChain", from_chain.to_string())];
+    if let Some(bridge) = bridge {
+        query.push(("bridge", bridge.to_string()));
+    }
+    if let Some(to_chain) = to_chain {
+        query.push(("toChain", to_chain.to_string()));
+    }
+    let body = get(config, "/status", &query).await?;
+    let receiving = &body["receiving"];
+    let received = match (receiving["amount"].as_str(), serde_json::from_value::<Token>(receiving["token"].clone())) {
+        (Some(amount), Ok(token)) => Some(token.format(parse_amount(amount)?)),
+        _ => None,
+    };
+    Ok(Status {
+        state: body["status"].as_str().unwrap_or("NOT_FOUND").to_string(),
+        substatus: body["substatus"].as_str().map(str::to_string),
+        message: body["substatusMessage"].as_str().map(str::to_string),
+        to_chain: receiving["chainId"].as_u64().or(to_chain),
+        destination_tx_hash: receiving["txHash"].as_str().map(str::to_string),
+        received,
+    })
+}
+
+pub fn describe_status(status: &Status) -> String {
+    let to_chain = status.to_chain.map_or_else(|| "the destination chain".to_string(), chain_name);
+    let mut line = match status.outcome() {
+        "done" => format!("Arrived on {}", to_chain),
+        "partial" => format!("Arrived on {}, but as another token than asked for", to_chain),
+        "refunded" => "Refunded on the source chain".to_string(),
+        "failed" => "Failed".to_string(),
+        _ if status.state == "NOT_FOUND" => "Not picked up by the bridge yet".to_string(),
+        _ => format!("On its way to {}", to_chain),
+    };
+    if let Some(received) = &status.received {
+        line.push_str(&format!(": {}", received));
+    }
+    if let Some(hash) = &status.destination_tx_hash {
+        line.push_str(&format!("\nDestination transaction: {}", hash));
+    }
+    if let Some(message) = &status.message {
+        line.push_str(&format!("\n{}", message));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::db::{create_session, pending_bridge_transfers, save_transaction, update_transaction_status};
+    use crate::test_support::{context, database, returns, rpc_node, serve, EthCall};
+    use crate::tools::execute_tool;
+    use crate::tracker;
+    use axum::extract::Query;
+    use axum::routing::get;
+    use ethers::abi::Token;
+    use ethers::types::H256;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    // Ethereum, with a node that knows a USDC token and a LI.FI API that quotes 100 of it to Base
+    // through Stargate and reports every transfer as arrived
+    async fn ethereum(usdc: Address, base_usdc: Address, spender: Address) -> Config {
+        let rpc = rpc_node(|_, params| {
+            let call = EthCall::parse(params);
+            if call.is("decimals()") {
+                returns(&[Token::Uint(6.into())])
+            } else if call.is("symbol()") {
+                returns(&[Token::String("USDC".to_string())])
+            } else {
+                panic!("unexpected RPC call {}", params)
+            }
+        }).await;
+        let token = |address: Address| json!({ "address": format!("{:?}", address), "symbol": "USDC", "decimals": 6 });
+        let lifi = axum::Router::new()
+            .route("/quote", get(move |Query(query): Query<HashMap<String, String>>| async move {
+                assert_eq!((query["fromChain"].as_str(), query["toChain"].as_str(), query["toToken"].as_str()), ("1", "8453", "USDC"));
+                assert_eq!((query["fromAmount"].as_str(), query["slippage"].as_str()), ("100000000", "0.005"));
+                axum::Json(json!({
+                    "tool": "stargate",
+                    "toolDetails": { "name": "Stargate" },
+                    "action": { "fromToken": token(usdc), "toToken": token(base_usdc), "fromAmount": "100000000" },
+                    "estimate": {
+                        "toAmount": "99900000", "toAmountMin": "99400000", "approvalAddress": format!("{:?}", spender), "executionDuration": 150,
+                        "feeCosts": [{ "amountUSD": "0.06" }, { "amountUSD": "0.04" }], "gasCosts": [{ "amountUSD": "1.25" }],
+                    },
+                    "transactionRequest": { "to": format!("{:?}", spender), "data": "0x1234", "value": "0x0" },
+                }))
+            }))
+            .route("/status", get(move |Query(query): Query<HashMap<String, String>>| async move {
+                assert_eq!(query["fromChain"], "1");
+                axum::Json(json!({
+                    "status": "DONE", "substatus": "COMPLETED",
+                    "receiving": { "txHash": format!("0x{}", "cd".repeat(32)), "chainId": 8453, "amount": "99900000", "token": token(base_usdc) },
+                }))
+            }));
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(rpc);
+        config.chain.chain_id = 1;
+        config.chain.name = "Ethereum".to_string();
+        config.bridge.api_url = serve(lifi).await;
+        config
+    }
+
+    #[tokio::test]
+    async fn quotes_bridge_transfers_and_reports_their_status() {
+        let [usdc, base_usdc, spender, sender] = [0x72, 0x73, 0x74, 0x11].map(Address::repeat_byte);
+        let ctx = context(ethereum(usdc, base_usdc, spender).await);
+        let transfer = |operation: &str, to_chain: &str| json!({
+            "operation": operation, "token": format!("{:?}", usdc), "amount": "100", "to_chain": to_chain, "account": format!("{:?}", sender),
+        });
+
+        let quote = execute_tool(&ctx, "bridge", &transfer("quote", "base")).await.unwrap();
+        assert!(quote.starts_with(&format!("Bridge 100 USDC from Ethereum to Base via Stargate\n\
+                                            Receive: about 99.9 USDC (at least 99.4 USDC)\nFees: $0.10, gas about $1.25\nEstimated time: 3 min\n\
+                                            Needs an allowance of USDC for {:?}\nSender: {:?}", spender, sender)), "{}", quote);
+        let unconfirmed = execute_tool(&ctx, "bridge", &transfer("execute", "8453")).await.unwrap_err();
+        assert!(unconfirmed.message().starts_with("Bridge transfers need confirm=true"), "{}", unconfirmed.message());
+        let same_chain = execute_tool(&ctx, "bridge", &transfer("quote", "Ethereum")).await.unwrap_err();
+        assert!(same_chain.message().contains("Ethereum is the chain the transfer is sent from"), "{}", same_chain.message());
+        let unlisted = execute_tool(&ctx, "bridge", &transfer("quote", "Gnosis")).await.unwrap_err();
+        assert!(unlisted.message().contains("Transfers can go to OP Mainnet (10), Base (8453), Arbitrum One (42161), Polygon (137)"), "{}", unlisted.message());
+
+        let source = format!("{:?}", H256::random());
+        let status = execute_tool(&ctx, "bridge", &json!({ "operation": "status", "tx_hash": source })).await.unwrap();
+        assert!(status.starts_with(&format!("Bridge transfer {} from Ethereum\nArrived on Base: 99.9 USDC\nDestination transaction: 0x{}", source, "cd".repeat(32))), "{}", status);
+    }
+
+    // A sent transfer is followed until it arrives and then reported once
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn tracks_sent_transfers_until_they_arrive() {
+        let [usdc, base_usdc, spender, sender] = [0x72, 0x73, 0x74, 0x11].map(Address::repeat_byte);
+        let mut config = ethereum(usdc, base_usdc, spender).await;
+        let pool = database(&mut config).await;
+        let source = format!("{:?}", H256::random());
+        let session_id = Uuid::new_v4();
+        create_session(&pool, session_id).await.unwrap();
+        let description = "Bridge 100 USDC from Ethereum to Base via Stargate";
+        save_transaction(&pool, Some(session_id), 1, &source, &format!("{:?}", sender), Some(&format!("{:?}", spender)), "0", 3, Some("0x1234"), description).await.unwrap();
+        update_transaction_status(&pool, &source, "confirmed", Some(100), true).await.unwrap();
+        tracker::record_bridge(Some(&pool), Some(session_id), source.parse().unwrap(), "stargate", 1, 8453, description).await;
+        let pending = pending_bridge_transfers(&pool, 1).await.unwrap();
+        let record = pending.into_iter().find(|record| record.tx_hash == source).unwrap();
+        assert_eq!(record.source_status.as_deref(), Some("confirmed"));
+        let status = status(&config.bridge, &source, Some("stargate"), 1, Some(8453)).await.unwrap();
+        assert!(tracker::record_bridge_status(&pool, &record, &status, false).await.unwrap());
+        assert_eq!(tracker::take_updates(Some(&pool), session_id).await,
+                   vec![format!("Your bridge transfer {} ({}) arrived on Base (99.9 USDC) in 0x{}", source, description, "cd".repeat(32))]);
+        assert!(tracker::take_updates(Some(&pool), session_id).await.is_empty());
+    }
+}
@@ -0,0 +1,528 @@
+// Exact arithmetic for the `calc` tool, since models get 18-decimal amounts wrong. Numbers are
+// rationals over big integers, so `0.1 + 0.2` is `0.3` and nothing is ever rounded until the
+// result is shown. Amounts may carry an ETH unit (`wei`, `gwei`, `eth`, …) and are converted
+// with `in`, e.g. `1.5 eth - 21000 * 30 gwei in gwei`; `%` and `bps` are plain fractions, and
+// `of` multiplies, e.g. `0.3% of 2500`.
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+// Longest expression evaluated
+const MAX_LEN: usize = 1000;
+// Largest numerator or denominator kept, so `10^10^10` is refused instead of filling memory
+const MAX_BITS: u64 = 8192;
+// Largest power raised to
+const MAX_EXPONENT: u32 = 1000;
+// Decimals shown for results that don't terminate
+const DECIMALS: usize = 18;
+
+// Units of ETH amounts, as powers of ten of wei
+const AMOUNT_UNITS: &[(&str, u32)] = &[
+    ("wei", 0),
+    ("kwei", 3),
+    ("mwei", 6),
+    ("gwei", 9),
+    ("szabo", 12),
+    ("finney", 15),
+    ("eth", 18),
+    ("ether", 18),
+];
+
+/// An exact fraction, kept reduced with a positive denominator
+#[derive(Debug, Clone, PartialEq)]
+struct Ratio {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Ratio {
+    fn new(num: BigInt, den: BigInt) -> anyhow::Result<Ratio> {
+        if den.is_zero() {
+            return Err(anyhow::anyhow!("Division by zero"));
+        }
+        let divisor = num.gcd(&den);
+        let (mut num, mut den) = (num / &divisor, den / &divisor);
+        if den.is_negative() {
+            num = -num;
+            den = -den;
+        }
+        if num.bits() > MAX_BITS || den.bits() > MAX_BITS {
+            return Err(anyhow::anyhow!("The numbers involved are too large to work with"));
+        }
+        Ok(Ratio { num, den })
+    }
+
+    fn integer(value: BigInt) -> Ratio {
+        Ratio { num: value, den: BigInt::one() }
+    }
+
+    /// `10^exponent`, for negative exponents too
+    fn power_of_ten(exponent: i32) -> Ratio {
+        let power = BigInt::from(10).pow(exponent.unsigned_abs());
+        match exponent < 0 {
+            true => Ratio { num: BigInt::one(), den: power },
+            false => Ratio::integer(power),
+        }
+    }
+
+    fn add(&self, other: &Ratio) -> anyhow::Result<Ratio> {
+        Ratio::new(&self.num * &other.den + &other.num * &self.den, &self.den * &other.den)
+    }
+
+    fn sub(&self, other: &Ratio) -> anyhow::Result<Ratio> {
+        self.add(&other.neg())
+    }
+
+    fn mul(&self, other: &Ratio) -> anyhow::Result<Ratio> {
+        Ratio::new(&self.num * &other.num, &self.den * &other.den)
+    }
+
+    fn div(&self, other: &Ratio) -> anyhow::Result<Ratio> {
+        Ratio::new(&self.num * &other.den, &self.den * &other.num)
+    }
+
+    fn neg(&self) -> Ratio {
+        Ratio { num: -&self.num, den: self.den.clone() }
+    }
+
+    fn pow(&self, exponent: &Ratio) -> anyhow::Result<Ratio> {
+        if !exponent.den.is_one() {
+            return Err(anyhow::anyhow!("Only whole-number powers can be computed exactly"));
+        }
+        let magnitude = u32::try_from(exponent.num.abs())
+            .ok()
+            .filter(|magnitude| *magnitude <= MAX_EXPONENT)
+            .ok_or_else(|| anyhow::anyhow!("Powers above {} are not computed", MAX_EXPONENT))?;
+        // Checked before raising, so a huge power fails fast instead of being built
+        if self.num.bits().max(self.den.bits()) * magnitude as u64 > MAX_BITS {
+            return Err(anyhow::anyhow!("The numbers involved are too large to work with"));
+        }
+        let (num, den) = (self.num.pow(magnitude), self.den.pow(magnitude));
+        match exponent.num.is_negative() {
+            true => Ratio::new(den, num),
+            false => Ratio::new(num, den),
+        }
+    }
+
+    /// Decimal text, rounded half away from zero to `decimals` places when the fraction
+    /// doesn't terminate; the flag tells whether it is exact
+    fn to_decimal(&self, decimals: usize) -> (String, bool) {
+        let scale = BigInt::from(10).pow(decimals as u32);
+        let scaled = self.num.abs() * &scale;
+        let exact = (&scaled % &self.den).is_zero();
+        let rounded: BigInt = (scaled * 2 + &self.den) / (&self.den * 2);
+        let digits = format!("{:0>width$}", rounded.to_string(), width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+        let sign = if self.num.is_negative() && !rounded.is_zero() { "-" } else { "" };
+        let text = match fraction.is_empty() {
+            true => format!("{}{}", sign, whole),
+            false => format!("{}{}.{}", sign, whole, fraction),
+        };
+        (text, exact)
+    }
+}
+
+/// A number, and how many times it is an amount of wei (1 for amounts, 0 for plain numbers,
+/// 2 for an amount times an amount)
+#[derive(Debug, Clone)]
+struct Value {
+    ratio: Ratio,
+    dimension: i32,
+}
+
+impl Value {
+    fn plain(ratio: Ratio) -> Value {
+        Value { ratio, dimension: 0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Ratio),
+    Word(String),
+    Symbol(char),
+}
+
+// Decimal digits with an optional fraction and exponent (`1_000.5e-3`), or hex (`0x2a`)
+fn number(chars: &[char], start: usize) -> anyhow::Result<(Ratio, usize)> {
+    let mut end = start;
+    if chars[start] == '0' && chars.get(start + 1).is_some_and(|c| *c == 'x' || *c == 'X') {
+        end += 2;
+        while chars.get(end).is_some_and(|c| c.is_ascii_hexdigit() || *c == '_') {
+            end += 1;
+        }
+        let digits: String = chars[start + 2..end].iter().filter(|c| **c != '_').collect();
+        let value = BigInt::parse_bytes(digits.as_bytes(), 16)
+            .ok_or_else(|| anyhow::anyhow!("Invalid hex number 0x{}", digits))?;
+        return Ok((Ratio::integer(value), end));
+    }
+    while chars.get(end).is_some_and(|c| c.is_ascii_digit() || *c == '_' || *c == '.') {
+        end += 1;
+    }
+    let mantissa: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+    let mut exponent: i32 = 0;
+    // An exponent only when digits follow, so `2 eth` isn't read as `2e…`
+    if chars.get(end).is_some_and(|c| *c == 'e' || *c == 'E') {
+        let mut digits_at = end + 1;
+        if chars.get(digits_at).is_some_and(|c| *c == '-' || *c == '+') {
+            digits_at += 1;
+        }
+        if chars.get(digits_at).is_some_and(|c| c.is_ascii_digit()) {
+            let mut exponent_end = digits_at;
+            while chars.get(exponent_end).is_some_and(|c| c.is_ascii_digit()) {
+                exponent_end += 1;
+            }
+            let text: String = chars[end + 1..exponent_end].iter().collect();
+            exponent = text.parse().ok()
+                .filter(|exponent: &i32| exponent.unsigned_abs() <= MAX_EXPONENT)
+                .ok_or_else(|| anyhow::anyhow!("Exponent {} is out of range", text))?;
+            end = exponent_end;
+        }
+    }
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((&mantissa, ""));
+    if fraction.contains('.') || (whole.is_empty() && fraction.is_empty()) {
+        return Err(anyhow::anyhow!("Invalid number {}", mantissa));
+    }
+    let digits = BigInt::parse_bytes(format!("{}{}", whole, fraction).as_bytes(), 10)
+        .ok_or_else(|| anyhow::anyhow!("Invalid number {}", mantissa))?;
+    let value = Ratio::integer(digits).mul(&Ratio::power_of_ten(exponent - fraction.len() as i32))?;
+    Ok((value, end))
+}
+
+fn tokenize(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+            let (value, end) = number(&chars, i)?;
+            tokens.push(Token::Number(value));
+            i = end;
+        } else if c.is_alphabetic() {
+            let start = i;
+            while chars.get(i).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else {
+            let symbol = match c {
+                '×' => '*',
+                '÷' => '/',
+                '+' | '-' | '*' | '/' | '^' | '%' | '(' | ')' | ',' => c,
+                other => return Err(anyhow::anyhow!("Unexpected '{}' in the expression", other)),
+            };
+            // `**` is the same power as `^`
+            if symbol == '*' && chars.get(i + 1) == Some(&'*') {
+                tokens.push(Token::Symbol('^'));
+                i += 2;
+                continue;
+            }
+            tokens.push(Token::Symbol(symbol));
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+fn amount_unit(word: &str) -> Option<u32> {
+    AMOUNT_UNITS.iter().find(|(name, _)| *name == word).map(|(_, decimals)| *decimals)
+}
+
+// A fraction unit: `%` as a word, and basis points
+fn fraction_unit(word: &str) -> Option<Ratio> {
+    match word {
+        "percent" => Some(Ratio::power_of_ten(-2)),
+        "bps" | "bp" => Some(Ratio::power_of_ten(-4)),
+        _ => None,
+    }
+}
+
+fn describe_unit(dimension: i32) -> String {
+    match dimension {
+        0 => "a plain number".to_string(),
+        1 => "an ETH amount".to_string(),
+        n => format!("wei^{}", n),
+    }
+}
+
+// Recursive descent, loosest binding first: `+ -`, then `* / of`, then unary minus, then `^`,
+// then units and `%` after a number
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expression(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.term()?;
+        loop {
+            let add = match self.peek() {
+                Some(Token::Symbol('+')) => true,
+                Some(Token::Symbol('-')) => false,
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let right = self.term()?;
+            if value.dimension != right.dimension {
+                return Err(anyhow::anyhow!("Can't {} {} and {}; give both the same kind of unit",
+                                           if add { "add" } else { "subtract" },
+                                           describe_unit(value.dimension), describe_unit(right.dimension)));
+            }
+            value.ratio = if add { value.ratio.add(&right.ratio)? } else { value.ratio.sub(&right.ratio)? };
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.unary()?;
+        loop {
+            let multiply = match self.peek() {
+                Some(Token::Symbol('*')) => true,
+                Some(Token::Word(word)) if word == "of" => true,
+                Some(Token::Symbol('/')) => false,
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let right = self.unary()?;
+            value = match multiply {
+                true => Value { ratio: value.ratio.mul(&right.ratio)?, dimension: value.dimension + right.dimension },
+                false => Value { ratio: value.ratio.div(&right.ratio)?, dimension: value.dimension - right.dimension },
+            };
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Value> {
+        if self.eat('-') {
+            let value = self.unary()?;
+            return Ok(Value { ratio: value.ratio.neg(), ..value });
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> anyhow::Result<Value> {
+        let base = self.postfix()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        // Right-associative: 2^3^2 is 2^9
+        let exponent = self.unary()?;
+        if exponent.dimension != 0 {
+            return Err(anyhow::anyhow!("A power must be a plain number"));
+        }
+        let whole = i32::try_from(exponent.ratio.num.clone()).ok().filter(|_| exponent.ratio.den.is_one());
+        Ok(Value {
+            ratio: base.ratio.pow(&exponent.ratio)?,
+            dimension: base.dimension * whole.unwrap_or(0),
+        })
+    }
+
+    fn postfix(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Symbol('%')) => {
+                    self.position += 1;
+                    value.ratio = value.ratio.mul(&Ratio::power_of_ten(-2))?;
+                },
+                Some(Token::Word(word)) if fraction_unit(word).is_some() => {
+                    let factor = fraction_unit(word).unwrap();
+                    self.position += 1;
+                    value.ratio = value.ratio.mul(&factor)?;
+                },
+                Some(Token::Word(word)) if amount_unit(word).is_some() => {
+                    let decimals = amount_unit(word).unwrap();
+                    if value.dimension != 0 {
+                        return Err(anyhow::anyhow!("'{}' follows a value that already has a unit", word));
+                    }
+                    self.position += 1;
+                    value = Value { ratio: value.ratio.mul(&Ratio::power_of_ten(decimals as i32))?, dimension: 1 };
+                },
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn arguments(&mut self, function: &str) -> anyhow::Result<Vec<Value>> {
+        if !self.eat('(') {
+            return Err(anyhow::anyhow!("{} takes its arguments in parentheses, e.g. {}(1, 2)", function, function));
+        }
+        let mut arguments = vec![self.expression()?];
+        while self.eat(',') {
+            arguments.push(self.expression()?);
+        }
+        if !self.eat(')') {
+            return Err(anyhow::anyhow!("Missing ')' after the arguments of {}", function));
+        }
+        Ok(arguments)
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Value> {
+        match self.next() {
+            Some(Token::Number(ratio)) => Ok(Value::plain(ratio)),
+            Some(Token::Symbol('(')) => {
+                let value = self.expression()?;
+                if !self.eat(')') {
+                    return Err(anyhow::anyhow!("Missing ')'"));
+                }
+                Ok(value)
+            },
+            Some(Token::Word(word)) => match word.as_str() {
+                "abs" => {
+                    let arguments = self.arguments(&word)?;
+                    let [value] = <[Value; 1]>::try_from(arguments)
+                        .map_err(|_| anyhow::anyhow!("abs takes one argument"))?;
+                    Ok(Value { ratio: Ratio { num: value.ratio.num.abs(), den: value.ratio.den }, ..value })
+                },
+                "min" | "max" => {
+                    let arguments = self.arguments(&word)?;
+                    let dimension = arguments[0].dimension;
+                    if arguments.iter().any(|argument| argument.dimension != dimension) {
+                        return Err(anyhow::anyhow!("The arguments of {} must all have the same kind of unit", word));
+                    }
+                    let mut best = arguments[0].clone();
+                    for argument in &arguments[1..] {
+                        let greater = argument.ratio.sub(&best.ratio)?.num.is_positive();
+                        if greater == (word == "max") && argument.ratio != best.ratio {
+                            best = argument.clone();
+                        }
+                    }
+                    Ok(best)
+                },
+                _ if amount_unit(&word).is_some() || fraction_unit(&word).is_some() =>
+                    Err(anyhow::anyhow!("'{}' must follow a number, e.g. 1 {}", word, word)),
+                _ => Err(anyhow::anyhow!("Unknown name '{}' (functions: abs, min, max; units: wei, gwei, eth, %, bps)", word)),
+            },
+            Some(Token::Symbol(symbol)) => Err(anyhow::anyhow!("Unexpected '{}'", symbol)),
+            None => Err(anyhow::anyhow!("The expression ends too early")),
+        }
+    }
+}
+
+// The unit asked for after `in`, `to`, or `as`: a power of ten of wei for amounts, or a fraction
+enum Target {
+    Amount(String, u32),
+    Fraction(String, Ratio),
+}
+
+fn target(tokens: &[Token]) -> anyhow::Result<Target> {
+    match tokens {
+        [Token::Symbol('%')] => Ok(Target::Fraction("%".to_string(), Ratio::power_of_ten(-2))),
+        [Token::Word(word)] => match (amount_unit(word), fraction_unit(word)) {
+            (Some(decimals), _) => Ok(Target::Amount(word.clone(), decimals)),
+            (_, Some(factor)) => Ok(Target::Fraction(if word == "percent" { "%".to_string() } else { word.clone() }, factor)),
+            _ => Err(anyhow::anyhow!("Unknown unit '{}' to convert to (expected wei, gwei, eth, %, or bps)", word)),
+        },
+        _ => Err(anyhow::anyhow!("Expected one unit to convert to, e.g. 'in gwei'")),
+    }
+}
+
+fn shown(ratio: &Ratio) -> String {
+    match ratio.to_decimal(DECIMALS) {
+        (text, true) => text,
+        (text, false) => format!("≈ {}", text),
+    }
+}
+
+/// Evaluate an expression, returning the result as text: exact decimals, with `≈` before
+/// results rounded to 18 decimals. Amounts without a unit to convert to are shown in ETH and wei.
+pub fn evaluate(expression: &str) -> anyhow::Result<String> {
+    if expression.trim().is_empty() {
+        return Err(anyhow::anyhow!("Please provide an expression, e.g. '1.5 eth in gwei'"));
+    }
+    if expression.len() > MAX_LEN {
+        return Err(anyhow::anyhow!("Expressions are at most {} characters", MAX_LEN));
+    }
+    let mut tokens = tokenize(expression)?;
+    let conversion = tokens.iter()
+        .rposition(|token| matches!(token, Token::Word(word) if word == "in" || word == "to" || word == "as"))
+        .map(|at| tokens.split_off(at))
+        .map(|rest| target(&rest[1..]))
+        .transpose()?;
+    let mut parser = Parser { tokens, position: 0 };
+    let value = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(anyhow::anyhow!("Unexpected {} after the expression", match token {
+            Token::Number(_) => "number".to_string(),
+            Token::Word(word) => format!("'{}'", word),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+        }));
+    }
+    match (conversion, value.dimension) {
+        (Some(Target::Amount(unit, decimals)), 1) =>
+            Ok(format!("{} {}", shown(&value.ratio.div(&Ratio::power_of_ten(decimals as i32))?), unit)),
+        (Some(Target::Amount(unit, _)), dimension) =>
+            Err(anyhow::anyhow!("The result is {}, so it can't be shown in {}", describe_unit(dimension), unit)),
+        (Some(Target::Fraction(unit, factor)), 0) => {
+            let amount = shown(&value.ratio.div(&factor)?);
+            Ok(if unit == "%" { format!("{}%", amount) } else { format!("{} {}", amount, unit) })
+        },
+        (Some(Target::Fraction(unit, _)), dimension) =>
+            Err(anyhow::anyhow!("The result is {}, so it can't be shown in {}", describe_unit(dimension), unit)),
+        (None, 0) => Ok(shown(&value.ratio)),
+        (None, 1) => Ok(format!("{} ETH ({} wei)",
+                                shown(&value.ratio.div(&Ratio::power_of_ten(18))?),
+                                shown(&value.ratio))),
+        (None, dimension) => Err(anyhow::anyhow!(
+            "The result is in {}; divide by an amount to get a plain number, or multiply by one to get an amount",
+            describe_unit(dimension))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::context;
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn calculates_amounts_exactly() {
+        let ctx = context(Config::default());
+        let cases = [
+            ("0.1 + 0.2", "0.3"),
+            ("1.5 eth in gwei", "1500000000 gwei"),
+            ("2 eth - 21000 * 30 gwei", "1.99937 ETH (1999370000000000000 wei)"),
+            ("0.3% of 2500", "7.5"),
+            ("25 bps of 1e18 wei in eth", "0.0025 eth"),
+            ("(1850 - 1700) / 1700 in %", "≈ 8.823529411764705882%"),
+            ("1 / 3", "≈ 0.333333333333333333"),
+            ("-2^2 + 2**-2", "-3.75"),
+            ("0xde0b6b3a7640000 wei", "1 ETH (1000000000000000000 wei)"),
+            ("max(1 eth, 900 finney) / 4", "0.25 ETH (250000000000000000 wei)"),
+        ];
+        for (expression, expected) in cases {
+            let result = execute_tool(&ctx, "calc", &json!({ "expression": expression })).await.unwrap();
+            assert_eq!(result, format!("{} = {}", expression, expected));
+        }
+
+        for expression in ["1 / 0", "1 eth + 1", "1 eth * 1 eth", "2 ^ 0.5", "10 ^ 10 ^ 10", "1 in gwei"] {
+            let error = execute_tool(&ctx, "calc", &json!({ "expression": expression })).await.unwrap_err();
+            assert_eq!(error.kind(), "invalid_args", "{}: {:?}", expression, error);
+        }
+    }
+}
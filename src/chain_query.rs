@@ -0,0 +1,291 @@
+// Readable breakdowns of blocks, transactions, and receipts (`chain_query` tool), so "what
+// happened in tx 0x…" gets an answer without an explorer. Calldata and logs are decoded with
+// the ABIs in `abi_dir` and the common token, WETH, Uniswap, Safe, and ERC-4337 events; the
+// selectors and topics none of them has are looked up in signature databases (see signatures.rs).
+
+use crate::config::Config;
+use crate::contracts::{self, format_params, token_to_json};
+use crate::signatures;
+use crate::units;
+use ethers::abi::{Event, Function, FunctionExt, HumanReadableParser, RawLog, Token};
+use ethers::types::{Address, Block, Log, Transaction, TransactionReceipt, H256, U256};
+
+/// Most transaction hashes listed for a block
+const MAX_BLOCK_TRANSACTIONS: usize = 10;
+
+// Events decoded without an ABI. ERC-20 and ERC-721 share the Transfer and Approval topics, and
+// are told apart by how many arguments are indexed.
+const KNOWN_EVENTS: &[&str] = &[
+    "event Transfer(address indexed from, address indexed to, uint256 value)",
+    "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)",
+    "event Approval(address indexed owner, address indexed spender, uint256 value)",
+    "event Approval(address indexed owner, address indexed approved, uint256 indexed tokenId)",
+    "event ApprovalForAll(address indexed owner, address indexed operator, bool approved)",
+    "event TransferSingle(address indexed operator, address indexed from, address indexed to, uint256 id, uint256 value)",
+    "event TransferBatch(address indexed operator, address indexed from, address indexed to, uint256[] ids, uint256[] values)",
+    "event Deposit(address indexed dst, uint256 wad)",
+    "event Withdrawal(address indexed src, uint256 wad)",
+    "event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)",
+    "event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)",
+    "event OwnershipTransferred(address indexed previousOwner, address indexed newOwner)",
+    "event ExecutionSuccess(bytes32 txHash, uint256 payment)",
+    "event ExecutionFailure(bytes32 txHash, uint256 payment)",
+    "event UserOperationEvent(bytes32 indexed userOpHash, address indexed sender, address indexed paymaster, uint256 nonce, bool success, uint256 actualGasCost, uint256 actualGasUsed)",
+];
+
+/// Functions and events calldata and logs are decoded with
+pub struct Decoders {
+    pub functions: Vec<Function>,
+    pub events: Vec<Event>,
+}
+
+impl Decoders {
+    /// Those of the ABIs in `abi_dir`, then the common events
+    pub fn known(abi_dir: &str) -> Decoders {
+        let abis: Vec<_> = contracts::list_abis(abi_dir).unwrap_or_default().iter()
+            .filter_map(|name| contracts::load_abi(abi_dir, name).ok())
+            .collect();
+        let functions = abis.iter().flat_map(|abi| abi.functions().cloned()).collect();
+        let mut events: Vec<Event> = abis.iter().flat_map(|abi| abi.events().cloned()).collect();
+        events.extend(KNOWN_EVENTS.iter().filter_map(|signature| HumanReadableParser::parse_event(signature).ok()));
+        Decoders { functions, events }
+    }
+
+    /// The known ones, and what the signature databases have for the selector of `input` and
+    /// the log topics they don't cover
+    pub async fn for_transaction(config: &Config, input: Option<&[u8]>, logs: &[Log]) -> Decoders {
+        let mut decoders = Decoders::known(&config.abi_dir);
+        if let Some(selector) = input.and_then(|input| input.get(..4)).map(|selector| <[u8; 4]>::try_from(selector).unwrap())
+            && !decoders.functions.iter().any(|function| function.short_signature() == selector)
+        {
+            decoders.functions.extend(signatures::functions(&config.signatures, selector).await);
+        }
+        let mut unknown: Vec<H256> = Vec::new();
+        for topic in logs.iter().filter_map(|log| log.topics.first()) {
+            if !unknown.contains(topic) && !decoders.events.iter().any(|event| event.signature() == *topic) {
+                unknown.push(*topic);
+            }
+        }
+        for topic in unknown {
+            decoders.events.extend(signatures::events(&config.signatures, &format!("{:?}", topic)).await);
+        }
+        decoders
+    }
+}
+
+// Decoded only when its arguments re-encode to exactly the given bytes, so another function
+// or event sharing the selector or topic isn't taken for it
+fn decode_exactly(function: &Function, data: &[u8]) -> Option<Vec<Token>> {
+    let tokens = function.decode_input(data).ok()?;
+    (ethers::abi::encode(&tokens) == data).then_some(tokens)
+}
+
+/// Lines for what a transaction's input calls, e.g. `Call: transfer(address,uint256)` and
+/// `Arguments: to=0x…, amount=1000`
+pub fn describe_call(functions: &[Function], to: Option<Address>, input: &[u8]) -> Vec<String> {
+    if input.is_empty() {
+        return vec!["Input: none (plain ETH transfer)".to_string()];
+    }
+    if to.is_none() || input.len() < 4 {
+        return vec![format!("Input: {} bytes", input.len())];
+    }
+    let decoded = functions.iter()
+        .filter(|function| function.short_signature() == input[..4])
+        .find_map(|function| decode_exactly(function, &input[4..]).map(|tokens| (function, tokens)));
+    let Some((function, tokens)) = decoded else {
+        return vec![format!("Input: function 0x{} ({} bytes of calldata)", hex::encode(&input[..4]), input.len())];
+    };
+    let mut lines = vec![format!("Call: {}", function.abi_signature())];
+    if !tokens.is_empty() {
+        let arguments: Vec<String> = function.inputs.iter().zip(&tokens)
+            .map(|(param, token)| {
+                let value = match token_to_json(token) {
+                    serde_json::Value::String(text) => text,
+                    other => other.to_string(),
+                };
+                if param.name.is_empty() { value } else { format!("{}={}", param.name, value) }
+            })
+            .collect();
+        lines.push(format!("Arguments: {}", arguments.join(", ")));
+    }
+    lines
+}
+
+// Whether a decoded log used up exactly its data, so an event with the same topic but other
+// indexed arguments isn't taken for it
+fn fits(event: &Event, data: &[u8], params: &[ethers::abi::LogParam]) -> bool {
+    let unindexed: Vec<Token> = event.inputs.iter().zip(params)
+        .filter(|(input, _)| !input.indexed)
+        .map(|(_, param)| param.value.clone())
+        .collect();
+    ethers::abi::encode(&unindexed) == data
+}
+
+/// One log as `Transfer on 0x…: from=0x…, to=0x…, value=1000`, or its raw topic when no known
+/// event matches
+pub fn describe_log(events: &[Event], log: &Log) -> String {
+    let decoded = log.topics.first().and_then(|topic0| {
+        events.iter()
+            .filter(|event| event.signature() == *topic0)
+            .find_map(|event| {
+                let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+                event.parse_log(raw).ok()
+                    .filter(|parsed| fits(event, &log.data, &parsed.params))
+                    .map(|parsed| (event, parsed))
+            })
+    });
+    match (decoded, log.topics.first()) {
+        (Some((event, parsed)), _) => format!("{} on {:?}: {}", event.name, log.address, format_params(&parsed.params)),
+        (None, Some(topic0)) => format!("Unknown event {:?} on {:?} ({} topics, {} bytes of data)",
+                                        topic0, log.address, log.topics.len(), log.data.len()),
+        (None, None) => format!("Anonymous log on {:?} ({} bytes of data)", log.address, log.data.len()),
+    }
+}
+
+fn describe_logs(events: &[Event], logs: &[Log]) -> String {
+    if logs.is_empty() {
+        return "Logs: none".to_string();
+    }
+    let lines: Vec<String> = logs.iter().enumerate()
+        .map(|(i, log)| format!("{}. {}", i + 1, describe_log(events, log)))
+        .collect();
+    format!("Logs ({}):\n{}", logs.len(), lines.join("\n"))
+}
+
+fn timestamp(seconds: U256) -> String {
+    chrono::DateTime::from_timestamp(seconds.low_u64() as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| seconds.to_string())
+}
+
+pub fn format_block(block: &Block<H256>) -> String {
+    let mut lines = vec![
+        format!("Block {}", block.number.map(|number| number.to_string()).unwrap_or_else(|| "pending".to_string())),
+        format!("Hash: {}", block.hash.map(|hash| format!("{:?}", hash)).unwrap_or_else(|| "pending".to_string())),
+        format!("Time: {}", timestamp(block.timestamp)),
+    ];
+    if let Some(miner) = block.author {
+        lines.push(format!("Fee recipient: {:?}", miner));
+    }
+    let used = if block.gas_limit.is_zero() { 0.0 } else { block.gas_used.as_u128() as f64 / block.gas_limit.as_u128() as f64 * 100.0 };
+    lines.push(format!("Gas used: {} of {} ({:.1}%)", block.gas_used, block.gas_limit, used));
+    if let Some(base_fee) = block.base_fee_per_gas {
+        lines.push(format!("Base fee: {} gwei", units::format_gwei(base_fee)));
+    }
+    lines.push(format!("Transactions: {}", block.transactions.len()));
+    lines.extend(block.transactions.iter().take(MAX_BLOCK_TRANSACTIONS).map(|hash| format!("- {:?}", hash)));
+    if block.transactions.len() > MAX_BLOCK_TRANSACTIONS {
+        lines.push(format!("- and {} more", block.transactions.len() - MAX_BLOCK_TRANSACTIONS));
+    }
+    lines.join("\n")
+}
+
+// Status, gas, and fee lines of a mined transaction
+fn receipt_lines(receipt: &TransactionReceipt) -> Vec<String> {
+    let status = match receipt.status.map(|status| status.as_u64()) {
+        Some(1) => "success",
+        Some(_) => "reverted",
+        None => "unknown (pre-Byzantium)",
+    };
+    let mut lines = vec![format!("Status: {} in block {}", status, receipt.block_number.unwrap_or_default())];
+    if let Some(contract) = receipt.contract_address {
+        lines.push(format!("Contract created: {:?}", contract));
+    }
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    match receipt.effective_gas_price {
+        Some(price) => lines.push(format!("Gas used: {} at {} gwei (fee: {} ETH)",
+                                          gas_used, units::format_gwei(price), units::format_eth(gas_used * price))),
+        None => lines.push(format!("Gas used: {}", gas_used)),
+    }
+    lines
+}
+
+/// A transaction with what it called and, once it is mined, its receipt and decoded logs
+pub fn format_transaction(tx: &Transaction, receipt: Option<&TransactionReceipt>, decoders: &Decoders) -> String {
+    let mut lines = vec![
+        format!("Transaction {:?}", tx.hash),
+        format!("From: {:?}", tx.from),
+        match tx.to {
+            Some(to) => format!("To: {:?}", to),
+            None => "To: (contract creation)".to_string(),
+        },
+        format!("Value: {} ETH", units::format_eth(tx.value)),
+        format!("Nonce: {}", tx.nonce),
+    ];
+    lines.extend(describe_call(&decoders.functions, tx.to, &tx.input));
+    match receipt {
+        Some(receipt) => {
+            lines.extend(receipt_lines(receipt));
+            lines.push(describe_logs(&decoders.events, &receipt.logs));
+        },
+        None => lines.push("Status: pending (not mined yet)".to_string()),
+    }
+    lines.join("\n")
+}
+
+pub fn format_receipt(receipt: &TransactionReceipt, decoders: &Decoders) -> String {
+    let mut lines = vec![
+        format!("Receipt of {:?}", receipt.transaction_hash),
+        format!("From: {:?}", receipt.from),
+    ];
+    if let Some(to) = receipt.to {
+        lines.push(format!("To: {:?}", to));
+    }
+    lines.extend(receipt_lines(receipt));
+    lines.push(describe_logs(&decoders.events, &receipt.logs));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::context;
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn breaks_transactions_down_into_decoded_logs() {
+        let decoders = Decoders::known("assets/abis");
+        let (from, to, token) = (Address::random(), Address::random(), Address::random());
+        let topic = |address: Address| H256::from(address);
+        let erc20 = Log {
+            address: token,
+            topics: vec![ethers::utils::keccak256("Transfer(address,address,uint256)").into(), topic(from), topic(to)],
+            data: ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(2500))]).into(),
+            ..Default::default()
+        };
+        // ERC-721 transfers share the topic but index the token ID
+        let erc721 = Log {
+            topics: vec![erc20.topics[0], topic(from), topic(to), H256::from_low_u64_be(7)],
+            data: Default::default(),
+            ..erc20.clone()
+        };
+        let unknown = Log { topics: vec![H256::random()], ..erc20.clone() };
+
+        let tx = Transaction { hash: H256::random(), from, to: Some(token), input: vec![0xa9, 0x05, 0x9c, 0xbb, 0].into(), ..Default::default() };
+        let receipt = TransactionReceipt {
+            transaction_hash: tx.hash,
+            status: Some(1.into()),
+            block_number: Some(42.into()),
+            gas_used: Some(50_000.into()),
+            effective_gas_price: Some(U256::exp10(9)),
+            logs: vec![erc20, erc721, unknown.clone()],
+            ..Default::default()
+        };
+        let breakdown = format_transaction(&tx, Some(&receipt), &decoders);
+        assert!(breakdown.contains("Input: function 0xa9059cbb (5 bytes of calldata)"), "{}", breakdown);
+        assert!(breakdown.contains("Status: success in block 42\nGas used: 50000 at 1 gwei (fee: 0.00005 ETH)"), "{}", breakdown);
+        assert!(breakdown.contains(&format!("1. Transfer on {:?}: from={:?}, to={:?}, value=2500", token, from, to)), "{}", breakdown);
+        assert!(breakdown.contains(&format!("2. Transfer on {:?}: from={:?}, to={:?}, tokenId=7", token, from, to)), "{}", breakdown);
+        assert!(breakdown.contains(&format!("3. Unknown event {:?}", unknown.topics[0])), "{}", breakdown);
+        assert!(format_transaction(&tx, None, &decoders).contains("Status: pending"));
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        let ctx = context(config);
+        let invalid = execute_tool(&ctx, "chain_query", &json!({ "operation": "transaction", "hash": "0x1234" })).await.unwrap_err();
+        assert_eq!(invalid.kind(), "invalid_args");
+        let block = execute_tool(&ctx, "chain_query", &json!({ "operation": "block", "block": "soon" })).await.unwrap_err();
+        assert!(block.message().contains("Invalid block"), "{:?}", block);
+    }
+}
@@ -0,0 +1,111 @@
+// Registry of known chains: names, whether they are testnets, and their block explorers. Tool
+// results get explorer links for the transaction hashes and addresses in them, on the
+// configured chain's explorer (`chain.explorer_url`, or the registry's for its chain id).
+
+use crate::config::ChainConfig;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Most links added to one tool result
+const MAX_LINKS: usize = 10;
+
+pub struct Chain {
+    pub id: u64,
+    pub name: &'static str,
+    pub testnet: bool,
+    pub explorer: Option<&'static str>,
+}
+
+pub const CHAINS: &[Chain] = &[
+    Chain { id: 1, name: "Ethereum", testnet: false, explorer: Some("https://etherscan.io") },
+    Chain { id: 11155111, name: "Sepolia", testnet: true, explorer: Some("https://sepolia.etherscan.io") },
+    Chain { id: 17000, name: "Holesky", testnet: true, explorer: Some("https://holesky.etherscan.io") },
+    Chain { id: 8453, name: "Base", testnet: false, explorer: Some("https://basescan.org") },
+    Chain { id: 84532, name: "Base Sepolia", testnet: true, explorer: Some("https://sepolia.basescan.org") },
+    Chain { id: 10, name: "OP Mainnet", testnet: false, explorer: Some("https://optimistic.etherscan.io") },
+    Chain { id: 11155420, name: "OP Sepolia", testnet: true, explorer: Some("https://sepolia-optimism.etherscan.io") },
+    Chain { id: 42161, name: "Arbitrum One", testnet: false, explorer: Some("https://arbiscan.io") },
+    Chain { id: 421614, name: "Arbitrum Sepolia", testnet: true, explorer: Some("https://sepolia.arbiscan.io") },
+    Chain { id: 137, name: "Polygon", testnet: false, explorer: Some("https://polygonscan.com") },
+    Chain { id: 80002, name: "Polygon Amoy", testnet: true, explorer: Some("https://amoy.polygonscan.com") },
+    Chain { id: 56, name: "BNB Smart Chain", testnet: false, explorer: Some("https://bscscan.com") },
+    Chain { id: 100, name: "Gnosis", testnet: false, explorer: Some("https://gnosisscan.io") },
+    Chain { id: 31337, name: "Anvil", testnet: true, explorer: None },
+];
+
+lazy_static! {
+    // A whole 32-byte hash or 20-byte address, not part of a longer hex string
+    static ref HEX: Regex = Regex::new(r"\b0x(?:[0-9a-fA-F]{64}|[0-9a-fA-F]{40})\b").unwrap();
+}
+
+pub fn find(chain_id: u64) -> Option<&'static Chain> {
+    CHAINS.iter().find(|chain| chain.id == chain_id)
+}
+
+/// Block explorer of the configured chain, without a trailing slash
+pub fn explorer(chain: &ChainConfig) -> Option<String> {
+    match &chain.explorer_url {
+        Some(url) if url.is_empty() => None,
+        Some(url) => Some(url.trim_end_matches('/').to_string()),
+        None => find(chain.chain_id).and_then(|known| known.explorer).map(str::to_string),
+    }
+}
+
+/// Explorer page of a transaction hash or an address
+pub fn link(explorer: &str, hex: &str) -> String {
+    let kind = if hex.len() == 66 { "tx" } else { "address" };
+    format!("{}/{}/{}", explorer, kind, hex)
+}
+
+/// `output` with a link to the explorer page of each hash and address in it, in the order
+/// they first appear
+pub fn with_links(chain: &ChainConfig, output: String) -> String {
+    let Some(explorer) = explorer(chain) else { return output };
+    let mut found: Vec<&str> = Vec::new();
+    for hex in HEX.find_iter(&output).map(|m| m.as_str()) {
+        if !found.iter().any(|seen| seen.eq_ignore_ascii_case(hex)) {
+            found.push(hex);
+        }
+    }
+    if found.is_empty() {
+        return output;
+    }
+    let links: Vec<String> = found.iter().take(MAX_LINKS).map(|hex| format!("- {}", link(&explorer, hex))).collect();
+    format!("{}\nExplorer links:\n{}", output, links.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::context;
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn links_hashes_and_addresses_to_the_chain_explorer() {
+        let mut config = Config::default();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        let dir = std::env::temp_dir().join(format!("explorer-wallets-{}", Uuid::new_v4()));
+        config.signer.keystore_dir = dir.to_string_lossy().into_owned();
+        let created = execute_tool(&context(config.clone()), "eth_wallet", &json!({ "operation": "generate" })).await.unwrap();
+        let address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().to_string();
+        assert!(created.contains(&format!("Explorer links:\n- https://sepolia.etherscan.io/address/{}", address)), "{}", created);
+
+        let hash = format!("0x{}", "ab".repeat(32));
+        let linked = with_links(&config.chain, format!("Sent {} to {} ({})", hash, address, address.to_lowercase()));
+        assert!(linked.ends_with(&format!("- https://sepolia.etherscan.io/tx/{}\n- https://sepolia.etherscan.io/address/{}", hash, address)), "{}", linked);
+
+        config.chain.chain_id = 8453;
+        assert!(with_links(&config.chain, hash.clone()).contains("https://basescan.org/tx/"));
+        config.chain.explorer_url = Some("https://explorer.example/".to_string());
+        assert!(with_links(&config.chain, hash.clone()).ends_with(&format!("https://explorer.example/tx/{}", hash)));
+        config.chain.explorer_url = Some(String::new());
+        assert_eq!(with_links(&config.chain, hash.clone()), hash);
+        config.chain.explorer_url = None;
+        config.chain.chain_id = 31337;
+        assert_eq!(with_links(&config.chain, hash.clone()), hash);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,385 @@
+use crate::config::{Config, RetentionConfig, DEFAULT_CONFIG_PATH};
+use crate::db::{
+    delete_api_keys, delete_user, find_user, list_users, run_migrations, save_api_key, save_user, set_user_policy, set_user_role,
+};
+use crate::export::{self, ExportFormat};
+use crate::keystore;
+use crate::policy::Policy;
+use crate::retention;
+use crate::tools::{execute_tool, get_available_tools, get_tools_as_json, ToolContext};
+use crate::users::{key_hash, new_api_key, user_address, Role};
+use crate::wallet;
+use serde::Deserialize;
+use clap::{Parser, Subcommand};
+use sqlx::{Pool, Postgres};
+use std::io::IsTerminal;
+use uuid::Uuid;
+
+/// Onchain AI agent: chat with Claude, serve the HTTP API, or use its subsystems directly
+#[derive(Parser)]
+#[command(name = "agent", version, about)]
+pub struct Cli {
+    /// Configuration file
+    #[arg(long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: String,
+
+    /// Persona to use, by file name in the personalities directory (overrides the config)
+    #[arg(long, global = true)]
+    pub persona: Option<String>,
+
+    /// Simulate value-moving operations and report what would happen, without sending anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Show the model's extended thinking in the chat
+    #[arg(long, global = true)]
+    pub verbose: bool,
+
+    /// Print replies as the model wrote them, without rendering their markdown (the default
+    /// when stdout isn't a terminal)
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Chat in a full-screen terminal interface with tool activity and wallet status panes
+    /// (requires the `tui` feature)
+    #[arg(long, global = true)]
+    pub tui: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Chat with the agent interactively (the default)
+    Chat {
+        /// Resume an earlier session with its history preloaded
+        #[arg(long)]
+        session: Option<Uuid>,
+    },
+    /// Answer one prompt, print the answer to stdout, and exit: 0 when answered, 1 when the
+    /// turn failed, 2 for an empty prompt, 3 when answered but a tool call failed
+    Ask {
+        /// The prompt, or `-` to read it from stdin
+        prompt: String,
+    },
+    /// Serve the HTTP API
+    Serve {
+        /// Port to listen on (defaults to `server.port` from the config)
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Run the agent as a Discord bot (requires the `discord` feature)
+    Discord,
+    /// Serve the agent's tools to MCP clients, over stdio or (with --port) HTTP
+    McpServe {
+        /// Serve streamable HTTP on this port of 127.0.0.1 instead of stdio
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Serve the agent as the gRPC service of proto/agent.proto on 127.0.0.1 (requires the
+    /// `grpc` feature)
+    GrpcServe {
+        #[arg(long, default_value_t = 50051)]
+        port: u16,
+    },
+    /// Chat over newline-delimited JSON-RPC on stdin/stdout, for editors and other host programs
+    Rpc,
+    /// Print the tool calls the agent would make for a request, as JSON, without making them
+    Plan {
+        request: String,
+    },
+    /// Ethereum wallet operations
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+    /// Inspect the tools available to the agent
+    #[command(subcommand)]
+    Tools(ToolsCommand),
+    /// Write a session's messages, tool calls, and transactions to a file, as Markdown for
+    /// reports or as JSON for `import`
+    Export {
+        #[arg(long)]
+        session: Uuid,
+        #[arg(long, value_enum, default_value = "md")]
+        format: ExportFormat,
+        /// File to write (default stdout)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Add a session exported as JSON from this or another deployment, or `-` for stdin
+    Import {
+        file: String,
+    },
+    /// Database maintenance
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Manage the users of a server deployment and their API keys
+    #[command(subcommand)]
+    Users(UsersCommand),
+}
+
+#[derive(Subcommand)]
+pub enum WalletCommand {
+    /// Generate a new random wallet in the keystore
+    New {
+        /// Name of the account, which tool calls and chat refer to it by (default wallet-N)
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Add an existing private key to the keystore under a name, reading the key from stdin
+    Import {
+        name: String,
+    },
+    /// Make a BIP-39 mnemonic the HD wallet, storing it in the keystore and reading it from stdin
+    ImportMnemonic,
+    /// List the keystore and HD wallet accounts by name, with their ETH balances
+    List,
+    /// Show the ETH balance of an address or ENS name
+    Balance {
+        address: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ToolsCommand {
+    /// List tool names and descriptions
+    List {
+        /// Print the full tool definitions, including input schemas, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Apply pending database migrations
+    Migrate,
+    /// Delete stored messages past the `[retention]` limits, or the ones given here
+    Prune {
+        /// Delete messages older than this many days
+        #[arg(long)]
+        max_age_days: Option<u32>,
+        /// Keep only this many of the latest messages of each session
+        #[arg(long)]
+        max_messages_per_session: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UsersCommand {
+    /// Add a user by the address they sign in with, or rename one
+    Add {
+        address: String,
+        #[arg(long)]
+        name: Option<String>,
+        /// Role of a new user: viewers only read, operators also send within their policy, and
+        /// admins also change keystore accounts and users' policies
+        #[arg(long, value_enum, default_value_t = Role::Operator)]
+        role: Role,
+    },
+    /// List users, with their roles and whether they have a policy of their own
+    List,
+    /// Change a user's role
+    Role {
+        address: String,
+        #[arg(value_enum)]
+        role: Role,
+    },
+    /// Remove a user and revoke their API keys; their sessions and history are kept
+    Remove {
+        address: String,
+    },
+    /// Issue an API key acting as a user; it is printed once and only its hash is kept
+    Key {
+        address: String,
+        /// What the key is for
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Revoke every API key of a user
+    Revoke {
+        address: String,
+    },
+    /// Give a user a transaction policy of their own, in the policy.json format; without a
+    /// file, they go back to the deployment's
+    Policy {
+        address: String,
+        file: Option<String>,
+    },
+}
+
+/// Run a wallet subcommand through the same tool the agent uses, so it is audited too
+pub async fn run_wallet(ctx: &ToolContext, command: WalletCommand) -> anyhow::Result<()> {
+    let args = match command {
+        WalletCommand::New { name } => serde_json::json!({ "operation": "generate", "label": name }),
+        WalletCommand::Import { name } => return import_key(ctx, &name),
+        WalletCommand::ImportMnemonic => return import_mnemonic(ctx),
+        WalletCommand::List => serde_json::json!({ "operation": "wallets" }),
+        WalletCommand::Balance { address } => serde_json::json!({ "operation": "balance", "address": address }),
+    };
+
+    // A failed tool call exits with a failing status, for scripts
+    let output = execute_tool(ctx, "eth_wallet", &args).await.map_err(|e| anyhow::anyhow!(e))?;
+    println!("{}", output);
+    Ok(())
+}
+
+// The key is read here rather than through the tool, so that it never reaches the audit log
+fn import_key(ctx: &ToolContext, name: &str) -> anyhow::Result<()> {
+    if std::env::var("KEYSTORE_PASSWORD").unwrap_or_default().is_empty() {
+        return Err(anyhow::anyhow!("Set KEYSTORE_PASSWORD to the password the keystore is encrypted with"));
+    }
+    if std::io::stdin().is_terminal() {
+        eprint!("Private key for {}: ", name);
+    }
+    let mut private_key = String::new();
+    std::io::stdin().read_line(&mut private_key)?;
+    let (address, _) = keystore::import(&ctx.config.signer.keystore_dir, name, &private_key)?;
+    println!("Imported {:?} as {} into {}", address, name, ctx.config.signer.keystore_dir);
+    Ok(())
+}
+
+// Like keys, the phrase is read here so that it never reaches the audit log or a prompt
+fn import_mnemonic(ctx: &ToolContext) -> anyhow::Result<()> {
+    if std::io::stdin().is_terminal() {
+        eprint!("Mnemonic: ");
+    }
+    let mut phrase = String::new();
+    std::io::stdin().read_line(&mut phrase)?;
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    let first = wallet::import_mnemonic(&phrase)?;
+    keystore::save_mnemonic(&ctx.config.signer.keystore_dir, &phrase)?;
+    println!("Imported the HD wallet into {}; its first account is #0 {:?}", ctx.config.signer.keystore_dir, first.address);
+    Ok(())
+}
+
+pub fn run_tools(command: ToolsCommand) -> anyhow::Result<()> {
+    match command {
+        ToolsCommand::List { json: true } => println!("{}", get_tools_as_json()?),
+        ToolsCommand::List { json: false } => {
+            for tool in get_available_tools() {
+                println!("{:<16} {}", tool.name, tool.description);
+            }
+        },
+    }
+    Ok(())
+}
+
+pub async fn run_db(config: &Config, pool: Option<&Pool<Postgres>>, command: DbCommand) -> anyhow::Result<()> {
+    let pool = pool.ok_or_else(|| anyhow::anyhow!("Database is not available; check DATABASE_URL"))?;
+    match command {
+        DbCommand::Migrate => {
+            run_migrations(pool).await?;
+            println!("Database is up to date");
+        },
+        DbCommand::Prune { max_age_days, max_messages_per_session } => {
+            let retention = RetentionConfig {
+                max_age_days: max_age_days.unwrap_or(config.retention.max_age_days),
+                max_messages_per_session: max_messages_per_session.unwrap_or(config.retention.max_messages_per_session),
+                ..config.retention.clone()
+            };
+            if retention.max_age_days == 0 && retention.max_messages_per_session == 0 {
+                return Err(anyhow::anyhow!("No retention limit is set; set them under [retention] or pass --max-age-days or --max-messages-per-session"));
+            }
+            let pruned = retention::prune(&retention, pool).await?;
+            let mut parts = Vec::new();
+            if retention.max_age_days > 0 {
+                parts.push(format!("{} older than {} days", pruned.expired, retention.max_age_days));
+            }
+            if retention.max_messages_per_session > 0 {
+                parts.push(format!("{} beyond the latest {} of their session", pruned.beyond_limit, retention.max_messages_per_session));
+            }
+            println!("Deleted {} messages: {}", pruned.total(), parts.join(", "));
+        },
+    }
+    Ok(())
+}
+
+pub async fn run_export(pool: Option<&Pool<Postgres>>, session: Uuid, format: ExportFormat, output: Option<&str>) -> anyhow::Result<()> {
+    let pool = pool.ok_or_else(|| anyhow::anyhow!("Database is not available; check DATABASE_URL"))?;
+    let text = export::export(pool, session, format).await?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, text)?;
+            eprintln!("Exported session {} to {}", session, path);
+        },
+        None => println!("{}", text),
+    }
+    Ok(())
+}
+
+pub async fn run_import(pool: Option<&Pool<Postgres>>, file: &str) -> anyhow::Result<()> {
+    let pool = pool.ok_or_else(|| anyhow::anyhow!("Database is not available; check DATABASE_URL"))?;
+    let json = match file {
+        "-" => std::io::read_to_string(std::io::stdin())?,
+        path => std::fs::read_to_string(path)?,
+    };
+    let id = export::import(pool, &json).await?;
+    println!("Imported as session {}; resume it with `agent chat --session {}`", id, id);
+    Ok(())
+}
+
+pub async fn run_users(pool: Option<&Pool<Postgres>>, command: UsersCommand) -> anyhow::Result<()> {
+    let pool = pool.ok_or_else(|| anyhow::anyhow!("Database is not available; check DATABASE_URL"))?;
+    match command {
+        UsersCommand::Add { address, name, role } => {
+            let address = user_address(&address)?;
+            save_user(pool, &address, name.as_deref(), role.as_str()).await?;
+            println!("{} is a user", address);
+        },
+        UsersCommand::List => {
+            for user in list_users(pool).await? {
+                let policy = if user.policy.is_some() { "own policy" } else { "deployment policy" };
+                println!("{}  {:<20} {:<9} {}", user.address, user.name.unwrap_or_default(), user.role, policy);
+            }
+        },
+        UsersCommand::Role { address, role } => {
+            let address = user_address(&address)?;
+            if !set_user_role(pool, &address, role.as_str()).await? {
+                return Err(anyhow::anyhow!("{} is not a user", address));
+            }
+            println!("{} is now a {}", address, role);
+        },
+        UsersCommand::Remove { address } => {
+            let address = user_address(&address)?;
+            if !delete_user(pool, &address).await? {
+                return Err(anyhow::anyhow!("{} is not a user", address));
+            }
+            println!("Removed {}", address);
+        },
+        UsersCommand::Key { address, label } => {
+            let address = user_address(&address)?;
+            if find_user(pool, &address).await?.is_none() {
+                return Err(anyhow::anyhow!("{} is not a user; add them with `agent users add` first", address));
+            }
+            let key = new_api_key();
+            save_api_key(pool, &key_hash(&key), &address, label.as_deref()).await?;
+            eprintln!("API key for {} (it is not shown again):", address);
+            println!("{}", key);
+        },
+        UsersCommand::Revoke { address } => {
+            let address = user_address(&address)?;
+            println!("Revoked {} API key(s) of {}", delete_api_keys(pool, &address).await?, address);
+        },
+        UsersCommand::Policy { address, file } => {
+            let address = user_address(&address)?;
+            let policy = match &file {
+                Some(file) => {
+                    let policy: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+                    // Refuse a policy that would block all of the user's transactions
+                    Policy::deserialize(&policy).map_err(|e| anyhow::anyhow!("Invalid policy in {}: {}", file, e))?;
+                    Some(policy)
+                },
+                None => None,
+            };
+            if !set_user_policy(pool, &address, policy.as_ref()).await? {
+                return Err(anyhow::anyhow!("{} is not a user", address));
+            }
+            match file {
+                Some(file) => println!("{} now has the policy in {}", address, file),
+                None => println!("{} now has the deployment's policy", address),
+            }
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+// State shared between the replicas of one deployment through Redis (`[redis]`, built with the
+// `redis` feature):
+//
+// - the latest messages and summary of each session, so that a replica picking up a session
+//   another one served doesn't load its history from Postgres again
+// - outputs of cached tool calls (`[tools.cache_ttl_secs]`)
+// - a lock per signing account, so that two replicas never pick a nonce for one account at
+//   the same time
+//
+// Without Redis, each replica keeps these to itself.
+
+use crate::config::RedisConfig;
+use crate::store::MessageStore;
+use ethers::types::{Address, H256};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use crate::db::MessageStats;
+#[cfg(feature = "redis")]
+use crate::redact::redact;
+#[cfg(feature = "redis")]
+use async_trait::async_trait;
+#[cfg(feature = "redis")]
+use rand::Rng;
+#[cfg(feature = "redis")]
+use redis::aio::ConnectionManager;
+#[cfg(feature = "redis")]
+use std::sync::OnceLock;
+#[cfg(feature = "redis")]
+use uuid::Uuid;
+
+// How often a send waiting for an account's lock tries again
+#[cfg(feature = "redis")]
+const LOCK_RETRY: Duration = Duration::from_millis(250);
+
+// Deletes a lock only while it is still the one this replica took
+#[cfg(feature = "redis")]
+const RELEASE_LOCK: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) end return 0";
+
+// Appends a message to a session's cached history, unless the cache was dropped; a later load
+// fills it again from the database
+#[cfg(feature = "redis")]
+const APPEND_MESSAGE: &str = "\
+    if redis.call('EXISTS', KEYS[1]) == 0 then return 0 end \
+    redis.call('INCR', KEYS[1]) \
+    redis.call('RPUSH', KEYS[2], ARGV[1]) \
+    redis.call('LTRIM', KEYS[2], -tonumber(ARGV[2]), -1) \
+    redis.call('PEXPIRE', KEYS[1], ARGV[3]) \
+    redis.call('PEXPIRE', KEYS[2], ARGV[3]) \
+    return 1";
+
+#[cfg(feature = "redis")]
+struct Shared {
+    conn: ConnectionManager,
+    config: RedisConfig,
+}
+
+#[cfg(feature = "redis")]
+static SHARED: OnceLock<Shared> = OnceLock::new();
+
+#[cfg(feature = "redis")]
+fn shared() -> Option<(&'static RedisConfig, ConnectionManager)> {
+    SHARED.get().map(|shared| (&shared.config, shared.conn.clone()))
+}
+
+/// Connect to `[redis] url`, once at startup. Without it, or when it can't be reached, this
+/// replica keeps its state to itself.
+#[cfg(feature = "redis")]
+pub async fn connect(config: &RedisConfig) {
+    let Some(url) = &config.url else { return };
+    let connected = async { redis::Client::open(url.as_str())?.get_connection_manager().await }.await;
+    match connected {
+        Ok(conn) => {
+            tracing::info!("Connected to Redis; sharing session history, tool results, and signing locks");
+            let _ = SHARED.set(Shared { conn, config: config.clone() });
+        },
+        Err(e) => tracing::error!(error = %e, "Failed to connect to Redis; state is not shared with other replicas"),
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn connect(config: &RedisConfig) {
+    if config.url.is_some() {
+        tracing::warn!("REDIS_URL is set, but Redis support is not enabled; rebuild with `--features redis`");
+    }
+}
+
+/// Output of a cached tool call that any replica stored
+#[cfg(feature = "redis")]
+pub async fn cached_output(key: H256) -> Option<String> {
+    let (config, mut conn) = shared()?;
+    redis::cmd("GET").arg(format!("{}tool:{}", config.key_prefix, hex::encode(key)))
+        .query_async(&mut conn).await
+        .inspect_err(|e| tracing::warn!(error = %e, "Failed to read a tool result from Redis"))
+        .ok()
+        .flatten()
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn cached_output(_key: H256) -> Option<String> {
+    None
+}
+
+#[cfg(feature = "redis")]
+pub async fn cache_output(key: H256, ttl: Duration, output: &str) {
+    let Some((config, mut conn)) = shared() else { return };
+    let stored: redis::RedisResult<()> = redis::cmd("SET").arg(format!("{}tool:{}", config.key_prefix, hex::encode(key)))
+        .arg(output).arg("PX").arg(ttl.as_millis() as u64)
+        .query_async(&mut conn).await;
+    if let Err(e) = stored {
+        tracing::warn!(error = %e, "Failed to store a tool result in Redis");
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn cache_output(_key: H256, _ttl: Duration, _output: &str) {}
+
+/// Held by the replica signing for an account; release it once the transaction was submitted
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub struct AccountLock {
+    key: String,
+    token: String,
+}
+
+/// Take the signing lock of an account, waiting up to `[redis] lock_wait_secs` for another
+/// replica to release it; None without Redis
+#[cfg(feature = "redis")]
+pub async fn lock_account(from: Address, chain_id: u64) -> anyhow::Result<Option<AccountLock>> {
+    let Some((config, mut conn)) = shared() else { return Ok(None) };
+    let key = format!("{}lock:{}:{:?}", config.key_prefix, chain_id, from);
+    let token = hex::encode(rand::thread_rng().r#gen::<[u8; 16]>());
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(config.lock_wait_secs);
+    loop {
+        let taken: Option<String> = redis::cmd("SET").arg(&key).arg(&token).arg("NX").arg("PX").arg(config.lock_ttl_secs * 1000)
+            .query_async(&mut conn).await
+            .map_err(|e| anyhow::anyhow!("Could not take the signing lock of {:?}: {}", from, e))?;
+        if taken.is_some() {
+            return Ok(Some(AccountLock { key, token }));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(anyhow::anyhow!("Another replica is still sending from {:?}; try again shortly", from));
+        }
+        tokio::time::sleep(LOCK_RETRY).await;
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn lock_account(_from: Address, _chain_id: u64) -> anyhow::Result<Option<AccountLock>> {
+    Ok(None)
+}
+
+impl AccountLock {
+    #[cfg(feature = "redis")]
+    pub async fn release(self) {
+        let Some((_, mut conn)) = shared() else { return };
+        let released: redis::RedisResult<i64> = redis::Script::new(RELEASE_LOCK).key(&self.key).arg(&self.token)
+            .invoke_async(&mut conn).await;
+        if let Err(e) = released {
+            tracing::warn!(error = %e, lock = %self.key, "Failed to release a signing lock; it expires on its own");
+        }
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub async fn release(self) {}
+}
+
+/// `inner` with each session's latest messages and summary cached in Redis, when connected
+#[cfg(feature = "redis")]
+pub fn cached_store(inner: Arc<dyn MessageStore>) -> Arc<dyn MessageStore> {
+    match SHARED.get() {
+        Some(_) => Arc::new(CachedStore { inner }),
+        None => inner,
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+pub fn cached_store(inner: Arc<dyn MessageStore>) -> Arc<dyn MessageStore> {
+    inner
+}
+
+/// Drop every cached session, after their stored history changed under the cache (pruning)
+#[cfg(feature = "redis")]
+pub async fn forget_sessions() {
+    let Some((config, mut conn)) = shared() else { return };
+    let pattern = format!("{}session:*", config.key_prefix);
+    let mut cursor = 0u64;
+    loop {
+        let scanned: redis::RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(500)
+            .query_async(&mut conn).await;
+        let (next, keys) = match scanned {
+            Ok(scanned) => scanned,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to drop cached sessions from Redis");
+                return;
+            },
+        };
+        if !keys.is_empty() {
+            let _: redis::RedisResult<i64> = redis::cmd("DEL").arg(&keys).query_async(&mut conn).await;
+        }
+        if next == 0 {
+            return;
+        }
+        cursor = next;
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn forget_sessions() {}
+
+// Sessions' latest messages, as a count of the session's messages and a list of the last
+// `cached_messages` of them (JSON [role, content]), and their summary, in front of the store
+// that keeps them
+#[cfg(feature = "redis")]
+struct CachedStore {
+    inner: Arc<dyn MessageStore>,
+}
+
+#[cfg(feature = "redis")]
+impl CachedStore {
+    fn keys(config: &RedisConfig, session_id: Uuid) -> (String, String, String) {
+        let key = |part: &str| format!("{}session:{}:{}", config.key_prefix, session_id, part);
+        (key("count"), key("messages"), key("summary"))
+    }
+
+    // The session's message count and latest messages, loaded into the cache when it has none
+    async fn window(&self, session_id: Uuid) -> anyhow::Result<(i64, Vec<(String, String)>)> {
+        let (config, mut conn) = shared().ok_or_else(|| anyhow::anyhow!("Redis is not connected"))?;
+        let (count_key, messages_key, _) = Self::keys(config, session_id);
+        let ttl = config.session_ttl_secs * 1000;
+        let (count, entries): (Option<i64>, Vec<String>) = redis::pipe().atomic()
+            .cmd("GET").arg(&count_key)
+            .cmd("LRANGE").arg(&messages_key).arg(0).arg(-1)
+            .cmd("PEXPIRE").arg(&count_key).arg(ttl).ignore()
+            .cmd("PEXPIRE").arg(&messages_key).arg(ttl).ignore()
+            .query_async(&mut conn).await?;
+        if let Some(count) = count {
+            let messages = entries.iter().filter_map(|entry| serde_json::from_str(entry).ok()).collect();
+            return Ok((count, messages));
+        }
+
+        let rows = self.inner.load_messages(session_id, 0, config.cached_messages).await?;
+        let count = rows.last().map_or(0, |(position, _, _)| *position);
+        let messages: Vec<(String, String)> = rows.into_iter().map(|(_, role, content)| (role, content)).collect();
+        let mut fill = redis::pipe();
+        fill.atomic().cmd("DEL").arg(&messages_key).ignore();
+        if !messages.is_empty() {
+            let entries: Vec<String> = messages.iter().map(|message| serde_json::json!(message).to_string()).collect();
+            fill.cmd("RPUSH").arg(&messages_key).arg(entries).ignore()
+                .cmd("PEXPIRE").arg(&messages_key).arg(ttl).ignore();
+        }
+        fill.cmd("SET").arg(&count_key).arg(count).arg("PX").arg(ttl).ignore();
+        fill.query_async::<()>(&mut conn).await?;
+        Ok((count, messages))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl MessageStore for CachedStore {
+    async fn create_session(&self, session_id: Uuid) -> anyhow::Result<()> {
+        self.inner.create_session(session_id).await
+    }
+
+    async fn session_exists(&self, session_id: Uuid) -> anyhow::Result<bool> {
+        self.inner.session_exists(session_id).await
+    }
+
+    async fn save_message(&self, session_id: Uuid, role: &str, content: &str, model: &str, stats: Option<MessageStats>) -> anyhow::Result<i32> {
+        let id = self.inner.save_message(session_id, role, content, model, stats).await?;
+        if let Some((config, mut conn)) = shared() {
+            let (count_key, messages_key, _) = Self::keys(config, session_id);
+            let entry = serde_json::json!((role, redact(content))).to_string();
+            let appended: redis::RedisResult<i64> = redis::Script::new(APPEND_MESSAGE)
+                .key(&count_key).key(&messages_key)
+                .arg(entry).arg(config.cached_messages).arg(config.session_ttl_secs * 1000)
+                .invoke_async(&mut conn).await;
+            if let Err(e) = appended {
+                tracing::warn!(error = %e, %session_id, "Failed to cache a message in Redis");
+                let _: redis::RedisResult<i64> = redis::cmd("DEL").arg(&count_key).arg(&messages_key).query_async(&mut conn).await;
+            }
+        }
+        Ok(id)
+    }
+
+    async fn load_messages(&self, session_id: Uuid, offset: i64, limit: i64) -> anyhow::Result<Vec<(i64, String, String)>> {
+        let (count, messages) = match self.window(session_id).await {
+            Ok(window) => window,
+            Err(e) => {
+                tracing::warn!(error = %e, %session_id, "Failed to read cached history from Redis");
+                return self.inner.load_messages(session_id, offset, limit).await;
+            },
+        };
+        // Served from the cache when it holds every message asked for
+        let first = count - messages.len() as i64 + 1;
+        let start = (offset + 1).max(count - limit.max(0) + 1).max(1);
+        if first > start {
+            return self.inner.load_messages(session_id, offset, limit).await;
+        }
+        Ok(messages.into_iter().enumerate()
+            .map(|(n, (role, content))| (first + n as i64, role, content))
+            .filter(|(position, _, _)| *position >= start)
+            .collect())
+    }
+
+    async fn load_summary(&self, session_id: Uuid) -> anyhow::Result<(Option<String>, i64)> {
+        let Some((config, mut conn)) = shared() else { return self.inner.load_summary(session_id).await };
+        let (_, _, summary_key) = Self::keys(config, session_id);
+        let cached: Option<String> = redis::cmd("GET").arg(&summary_key).query_async(&mut conn).await.unwrap_or(None);
+        if let Some(summary) = cached.and_then(|cached| serde_json::from_str(&cached).ok()) {
+            return Ok(summary);
+        }
+        let summary = self.inner.load_summary(session_id).await?;
+        let _: redis::RedisResult<()> = redis::cmd("SET").arg(&summary_key).arg(serde_json::json!(summary).to_string())
+            .arg("PX").arg(config.session_ttl_secs * 1000)
+            .query_async(&mut conn).await;
+        Ok(summary)
+    }
+
+    async fn save_summary(&self, session_id: Uuid, summary: &str, summarized_messages: i64) -> anyhow::Result<()> {
+        self.inner.save_summary(session_id, summary, summarized_messages).await?;
+        if let Some((config, mut conn)) = shared() {
+            let (_, _, summary_key) = Self::keys(config, session_id);
+            let cached = serde_json::json!((redact(summary), summarized_messages)).to_string();
+            let stored: redis::RedisResult<()> = redis::cmd("SET").arg(&summary_key).arg(cached)
+                .arg("PX").arg(config.session_ttl_secs * 1000)
+                .query_async(&mut conn).await;
+            if let Err(e) = stored {
+                tracing::warn!(error = %e, %session_id, "Failed to cache a summary in Redis");
+                let _: redis::RedisResult<i64> = redis::cmd("DEL").arg(&summary_key).query_async(&mut conn).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::database;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL and a Redis server at REDIS_URL"]
+    async fn shares_history_tool_results_and_signing_locks_through_redis() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL must point at a Redis server");
+        let redis = RedisConfig { url: Some(url), lock_wait_secs: 1, ..Default::default() };
+        connect(&redis).await;
+
+        let store = cached_store(Arc::new(pool.clone()));
+        let session_id = Uuid::new_v4();
+        store.create_session(session_id).await.unwrap();
+        for text in ["one", "two", "three"] {
+            store.save_message(session_id, "user", text, "claude-test", None).await.unwrap();
+        }
+        assert_eq!(store.load_messages(session_id, 0, 10).await.unwrap().len(), 3);
+        store.save_message(session_id, "assistant", "four", "claude-test", None).await.unwrap();
+        // Served from the cache, which has the message saved after it was filled
+        sqlx::query("UPDATE messages SET content = 'changed' WHERE session_id = $1").bind(session_id).execute(&pool).await.unwrap();
+        let rows = store.load_messages(session_id, 1, 2).await.unwrap();
+        assert_eq!(rows, [(3, "user".to_string(), "three".to_string()), (4, "assistant".to_string(), "four".to_string())]);
+        store.save_summary(session_id, "Counted to two", 2).await.unwrap();
+        assert_eq!(store.load_summary(session_id).await.unwrap(), (Some("Counted to two".to_string()), 2));
+
+        let key = H256::repeat_byte(42);
+        cache_output(key, std::time::Duration::from_secs(10), "cached output").await;
+        assert_eq!(cached_output(key).await.as_deref(), Some("cached output"));
+
+        let account = ethers::types::Address::random();
+        let lock = lock_account(account, 1).await.unwrap().expect("locked");
+        assert!(lock_account(account, 1).await.is_err(), "a second replica waits, then gives up");
+        lock.release().await;
+        lock_account(account, 1).await.unwrap().expect("locked again").release().await;
+    }
+}
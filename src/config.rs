@@ -0,0 +1,1366 @@
+use crate::models::{load_models, ModelRegistry};
+use crate::users::Role;
+use figment::providers::{Env, Format, Serialized, Toml};
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Default location of the configuration file
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// Multicall3 is deployed at the same address on nearly every chain
+const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+/// Agent configuration, loaded from `config.toml` and overridden by environment variables.
+/// Every field has a default, so the file may be partial or missing entirely.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Directory of persona files, one `<name>.json` per persona
+    pub personalities_dir: String,
+    /// Persona used unless `--persona` picks another
+    pub persona: String,
+    pub policy_path: String,
+    /// Registry of models `/model` can switch to
+    pub models_path: String,
+    /// Models loaded from `models_path`
+    #[serde(skip)]
+    pub models: ModelRegistry,
+    pub abi_dir: String,
+    /// File the chat prompt keeps its input history in; empty for none
+    pub history_path: String,
+    /// Simulate value-moving operations and report what would happen without sending anything
+    pub dry_run: bool,
+    pub anthropic: AnthropicConfig,
+    pub tools: ToolsConfig,
+    pub plugins: PluginsConfig,
+    pub mcp: McpConfig,
+    pub chain: ChainConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub server: ServerConfig,
+    pub discord: DiscordConfig,
+    pub memory: MemoryConfig,
+    pub context: ContextConfig,
+    pub prices: PricesConfig,
+    pub dex: DexConfig,
+    pub portfolio: PortfolioConfig,
+    pub nft: NftConfig,
+    pub ipfs: IpfsConfig,
+    pub events: EventsConfig,
+    pub scheduler: SchedulerConfig,
+    pub alerts: AlertsConfig,
+    pub reminders: RemindersConfig,
+    pub gas: GasConfig,
+    pub notify: NotifyConfig,
+    pub tracker: TrackerConfig,
+    pub retention: RetentionConfig,
+    pub faucet: FaucetConfig,
+    pub signatures: SignaturesConfig,
+    pub dune: DuneConfig,
+    pub search: SearchConfig,
+    pub fetch: FetchConfig,
+    pub workspace: WorkspaceConfig,
+    pub etherscan: EtherscanConfig,
+    pub snapshot: SnapshotConfig,
+    pub governor: GovernorConfig,
+    pub staking: StakingConfig,
+    pub lending: LendingConfig,
+    pub bridge: BridgeConfig,
+    pub approvals: ApprovalsConfig,
+    pub safe: SafeConfig,
+    pub account_abstraction: AccountAbstractionConfig,
+    pub signer: SignerConfig,
+    pub ledger: LedgerConfig,
+    pub kms: KmsConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnthropicConfig {
+    pub api_key: Option<String>,
+    pub api_url: String,
+    pub model: String,
+    pub max_tokens: u32,
+    /// Maximum number of model round trips per turn
+    pub max_tool_iterations: usize,
+    /// Times a structured answer that doesn't parse is asked for again
+    pub structured_retries: u32,
+    /// Mark the tool definitions and the persona's system prompt as cacheable, so requests
+    /// that repeat them are billed and answered faster
+    pub prompt_caching: bool,
+    /// Tokens the model may spend on extended thinking before it answers; 0 turns thinking off
+    /// (toggled in chat with `/think`)
+    pub thinking_budget_tokens: u32,
+    /// Store the model's thinking in the `thinking_blocks` table, for debugging
+    pub persist_thinking: bool,
+    /// Price overrides by exact model name, used for cost estimates
+    pub prices: HashMap<String, ModelPrice>,
+    /// JSON file of scripted responses to answer with instead of calling the API, for tests
+    pub mock_fixture: Option<String>,
+}
+
+/// Limits on tool calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolsConfig {
+    /// Seconds a tool call may run before it is stopped and reported to the model as timed out
+    pub timeout_secs: u64,
+    /// Timeouts for particular tools, by tool name, in place of timeout_secs
+    pub timeouts: BTreeMap<String, u64>,
+    /// Seconds an identical call reuses the last output for, by tool name or `tool.operation`.
+    /// Tools not listed are not cached.
+    pub cache_ttl_secs: BTreeMap<String, u64>,
+    /// How tool results are protected against instructions hidden in them: "off", "wrap"
+    /// (delimited and declared as data), or "strip" (also with directive-like phrases removed)
+    pub injection_guard: String,
+}
+
+/// Tools loaded from WebAssembly components (built with the `plugins` feature)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    /// Directory searched for `.wasm` plugins at startup
+    pub dir: String,
+    /// What each plugin may reach outside its sandbox, by file name without `.wasm`
+    pub grants: BTreeMap<String, PluginGrants>,
+}
+
+/// Capabilities granted to a plugin; by default it has none
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginGrants {
+    /// Host directories the plugin may read, at the same path inside the sandbox
+    pub dirs: Vec<String>,
+    /// Whether the plugin may resolve names and open sockets
+    pub network: bool,
+}
+
+/// Model Context Protocol servers whose tools the agent offers alongside its own
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct McpConfig {
+    /// Servers by name; their tools are offered as `<name>__<tool>`
+    pub servers: BTreeMap<String, McpServerConfig>,
+}
+
+/// How to reach an MCP server: a program spoken to over stdio, or a streamable HTTP URL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct McpServerConfig {
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    /// Environment variables for the program, on top of the agent's own
+    pub env: BTreeMap<String, String>,
+    pub url: Option<String>,
+    /// Headers sent with every HTTP request, e.g. Authorization
+    pub headers: BTreeMap<String, String>,
+}
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainConfig {
+    /// Network name shown in tool output
+    pub name: String,
+    /// Chain id used when signing transactions
+    pub chain_id: u64,
+    /// `http(s)://` or `ws(s)://` JSON-RPC endpoint, or several separated by commas for failover
+    pub rpc_url: Option<String>,
+    /// Multicall3 contract batching read-only calls
+    pub multicall: String,
+    /// Private RPC that transactions can be sent through instead of the public mempool, such
+    /// as Flashbots Protect
+    pub private_rpc_url: Option<String>,
+    /// Send transactions through `private_rpc_url` unless one asks otherwise
+    pub private_transactions: bool,
+    /// Block explorer linked from tool results, in place of the chain registry's for
+    /// `chain_id`; empty for no links
+    pub explorer_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    /// Apply pending migrations when connecting
+    pub auto_migrate: bool,
+}
+
+/// Redis shared by the replicas of one deployment (requires the `redis` feature)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedisConfig {
+    pub url: Option<String>,
+    /// Start of every key, to share one Redis between deployments
+    pub key_prefix: String,
+    /// How long a session's history stays cached after it was last used
+    pub session_ttl_secs: u64,
+    /// Latest messages of a session kept in the cache
+    pub cached_messages: i64,
+    /// How long an account's signing lock is held at most, should its replica die holding it
+    pub lock_ttl_secs: u64,
+    /// How long a send waits for another replica to release the account's lock
+    pub lock_wait_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// Domain (host and port) that Sign-In with Ethereum messages must be issued for
+    pub domain: String,
+    /// How long a sign-in stays valid
+    pub auth_ttl_secs: u64,
+    /// Let any address sign in and become a user; otherwise only those added with `users add`
+    pub allow_signup: bool,
+    /// Role of users who sign up by signing in
+    pub signup_role: Role,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+    pub token: Option<String>,
+    /// Channel where the bot answers every message, not just mentions
+    pub channel_id: Option<u64>,
+    /// Roles of Discord users by user ID, as with the users of a server deployment
+    pub roles: HashMap<String, Role>,
+    /// Role of Discord users not in `roles`
+    pub default_role: Role,
+}
+
+impl DiscordConfig {
+    /// The role of the Discord user with this ID
+    #[cfg_attr(not(feature = "discord"), allow(dead_code))]
+    pub fn role_of(&self, user_id: u64) -> Role {
+        self.roles.get(&user_id.to_string()).copied().unwrap_or(self.default_role)
+    }
+}
+
+/// Long-term memory: stored messages are embedded into pgvector and the closest ones are
+/// added to the system prompt in later sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    pub enabled: bool,
+    pub api_key: Option<String>,
+    /// OpenAI-compatible embeddings endpoint
+    pub embedding_url: String,
+    pub embedding_model: String,
+    /// Size of the vectors returned by the embedding model
+    pub dimensions: usize,
+    /// Maximum number of excerpts recalled per prompt
+    pub top_k: usize,
+    /// Cosine similarity below which excerpts are ignored
+    pub min_similarity: f64,
+}
+
+/// History size limits; older turns beyond them are summarized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    /// Estimated history size (in tokens) above which older turns are summarized
+    pub max_history_tokens: usize,
+    /// Number of most recent messages always kept verbatim
+    pub keep_recent_messages: usize,
+}
+
+/// Market price lookups for the `get_token_price` and `chainlink_price` tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PricesConfig {
+    pub coingecko_url: String,
+    /// Demo API key, for higher rate limits than anonymous access
+    pub coingecko_api_key: Option<String>,
+    /// CoinGecko asset platform used for contract address lookups
+    pub coingecko_platform: String,
+    /// How long a fetched price is reused before asking CoinGecko again
+    pub cache_ttl_secs: u64,
+    /// Chainlink aggregator addresses on the configured chain, by pair (e.g. "ETH/USD")
+    pub chainlink_feeds: BTreeMap<String, String>,
+}
+
+/// Uniswap V3 deployment used by the `dex_swap` tool, on the configured chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DexConfig {
+    pub quoter: String,
+    pub router: String,
+    pub factory: String,
+    /// Wrapped ETH, used when swapping native ETH
+    pub weth: String,
+    /// Pool fee tier in hundredths of a basis point (3000 = 0.3%)
+    pub fee_tier: u32,
+    /// Slippage tolerance in basis points (50 = 0.5%)
+    pub slippage_bps: u32,
+    /// Token addresses by symbol, so swaps can name tokens instead of addresses
+    pub tokens: BTreeMap<String, String>,
+}
+
+/// Chains the `portfolio` tool reads besides the configured one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortfolioConfig {
+    pub chains: Vec<PortfolioChainConfig>,
+}
+
+/// Another chain to read balances on, with the tokens to check there
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioChainConfig {
+    pub name: String,
+    /// JSON-RPC endpoint, or several separated by commas
+    pub rpc_url: String,
+    #[serde(default = "default_multicall")]
+    pub multicall: String,
+    /// Symbol of the chain's native currency, used to price it
+    #[serde(default = "default_native_symbol")]
+    pub native_symbol: String,
+    /// Token addresses by symbol
+    #[serde(default)]
+    pub tokens: BTreeMap<String, String>,
+}
+
+fn default_multicall() -> String {
+    MULTICALL3_ADDRESS.to_string()
+}
+
+fn default_native_symbol() -> String {
+    "ETH".to_string()
+}
+
+/// NFT lookups and minting for the `nft` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NftConfig {
+    /// Alchemy API key, for listing every NFT an address owns
+    pub alchemy_api_key: Option<String>,
+    /// Alchemy network subdomain for the configured chain
+    pub alchemy_network: String,
+    /// Gateway serving `ipfs://` metadata and images
+    pub ipfs_gateway: String,
+    /// Contract minted on when a mint doesn't name one
+    pub mint_contract: Option<String>,
+    /// Human-readable signature of its mint function
+    pub mint_function: String,
+}
+
+/// IPFS pinning for the `ipfs` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpfsConfig {
+    /// "pinata", "web3storage", or "node" (a Kubo RPC API)
+    pub provider: String,
+    /// API base URL, if not the provider's default
+    pub api_url: Option<String>,
+    /// Pinata JWT or web3.storage token
+    pub api_token: Option<String>,
+    /// Directory files are uploaded from
+    pub uploads_dir: String,
+}
+
+/// On-chain log subscriptions that notify the agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    pub enabled: bool,
+    /// WebSocket RPC endpoint the logs are subscribed through
+    pub ws_url: Option<String>,
+    /// Have the agent respond to each event, rather than only showing it
+    pub trigger_agent: bool,
+    pub filters: Vec<EventFilterConfig>,
+}
+
+/// Logs of one event, optionally from one contract and with indexed arguments fixed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventFilterConfig {
+    pub name: String,
+    /// Emitting contract; any contract when unset
+    pub address: Option<String>,
+    /// Human-readable event signature, e.g. `event Transfer(address indexed from, address indexed to, uint256 value)`
+    pub event: String,
+    /// Values of indexed arguments to match, by name
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+}
+
+/// Prompts and tools run on a schedule without the user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub enabled: bool,
+    pub jobs: Vec<ScheduledJobConfig>,
+}
+
+/// One recurring job: a prompt for the agent, or a single tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJobConfig {
+    pub name: String,
+    /// Cron expression in local time, e.g. `0 9 * * *` for every day at 9:00; a leading seconds
+    /// field is optional
+    pub schedule: String,
+    /// Message the agent handles as if the user had sent it
+    pub prompt: Option<String>,
+    /// Tool to call instead of a prompt, with `args` as its input
+    pub tool: Option<String>,
+    #[serde(default)]
+    pub args: serde_json::Value,
+    /// Webhook the result is posted to as JSON
+    pub notify_url: Option<String>,
+}
+
+/// Price alerts created with the `price_alert` tool, checked on a cron schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    pub enabled: bool,
+    /// When active alerts are checked against current prices
+    pub schedule: String,
+    /// Have the agent explain each alert that fires, rather than only stating the price
+    pub explain: bool,
+}
+
+/// Reminders set with the `remind_me` tool, fired on a cron schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemindersConfig {
+    pub enabled: bool,
+    /// When due reminders are looked for
+    pub schedule: String,
+}
+
+/// Gas fee reports, and sends that wait for the base fee to drop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GasConfig {
+    /// Recent blocks the priority fee percentiles are taken over
+    pub history_blocks: u64,
+    /// When waiting sends check the base fee, as a cron expression
+    pub check_schedule: String,
+    /// How long a send waits for the base fee to drop before giving up
+    pub max_wait_hours: u64,
+}
+
+/// Where background notifications (transaction outcomes, price alerts, reminders, scheduled job
+/// results, on-chain events) are delivered. Each channel is used once its settings are filled in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub telegram: TelegramNotifyConfig,
+    pub discord: DiscordNotifyConfig,
+    pub email: EmailNotifyConfig,
+    pub desktop: DesktopNotifyConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelegramNotifyConfig {
+    pub bot_token: Option<String>,
+    pub chat_id: Option<String>,
+    /// Kinds of notifications sent here (`transaction`, `price_alert`, `reminder`, `scheduler`, `event`); all when empty
+    pub kinds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordNotifyConfig {
+    pub webhook_url: Option<String>,
+    pub kinds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailNotifyConfig {
+    pub smtp_host: Option<String>,
+    /// 465 connects over TLS; other ports upgrade with STARTTLS
+    pub smtp_port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub kinds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesktopNotifyConfig {
+    pub enabled: bool,
+    pub kinds: Vec<String>,
+}
+
+/// Background tracking of submitted transactions until they are mined
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerConfig {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+}
+
+/// How long stored messages are kept, so that a long-running deployment's history doesn't
+/// grow forever; 0 keeps them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Delete messages older than this
+    pub max_age_days: u32,
+    /// Keep only this many of the latest messages of each session
+    pub max_messages_per_session: u32,
+    /// How often long-running modes prune in the background
+    pub prune_interval_secs: u64,
+}
+
+/// Test ETH for wallets (`eth_wallet` `faucet`), on testnets only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaucetConfig {
+    /// Faucet APIs, tried in order for the chain they serve
+    pub apis: Vec<FaucetApiConfig>,
+    /// Sent to the faucet APIs as a bearer token
+    pub api_key: Option<String>,
+    /// Funded keystore account that sends test ETH when no faucet API serves the chain or
+    /// they all fail
+    pub account: Option<String>,
+    /// ETH the faucet account sends per request
+    pub amount: String,
+    /// Seconds before the same address may be funded again
+    pub cooldown_secs: u64,
+    /// Fund the wallets the agent generates as soon as they are created
+    pub fund_new_wallets: bool,
+}
+
+/// A faucet API, which takes a POST of `{"address": "0x...", "chain_id": N}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetApiConfig {
+    pub chain_id: u64,
+    pub url: String,
+}
+
+/// Function and event signature databases, for the selectors and event topics no ABI in
+/// `abi_dir` has
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignaturesConfig {
+    /// Look unknown selectors and topics up online
+    pub enabled: bool,
+    /// Sourcify signature database lookup endpoint, asked first
+    pub sourcify_url: String,
+    /// 4byte.directory API, asked when Sourcify has no match
+    pub fourbyte_url: String,
+    /// File the signatures found are kept in, so each is only looked up once
+    pub cache_path: String,
+}
+
+/// Dune Analytics API used by the `dune_query` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuneConfig {
+    pub api_url: String,
+    pub api_key: Option<String>,
+    /// Engine size executions run on: "medium" or "large"
+    pub performance: String,
+    /// Rows fetched and shown of a result
+    pub max_rows: u32,
+    /// Seconds to wait for an execution before leaving it running
+    pub wait_secs: u64,
+    pub poll_interval_secs: u64,
+    /// Saved query IDs by name, so they can be asked for by name
+    pub queries: BTreeMap<String, u64>,
+}
+
+/// Web search backend of the `web_search` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// "brave", "serpapi", or "tavily"
+    pub provider: String,
+    /// Search endpoint, if not the provider's default
+    pub api_url: Option<String>,
+    pub api_key: Option<String>,
+    /// Results returned when a call doesn't ask for a number
+    pub max_results: usize,
+}
+
+/// Pages read by the `fetch_url` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// Most tokens of page text returned, when a call doesn't ask for fewer
+    pub max_tokens: usize,
+    /// Most bytes of a page downloaded
+    pub max_bytes: usize,
+    /// Also fetch hosts on private, loopback, and link-local addresses (for local development)
+    pub allow_private_hosts: bool,
+}
+
+/// Directory the `read_file`, `write_file`, and `list_dir` tools work in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    pub dir: String,
+    /// Largest file read or written
+    pub max_file_bytes: u64,
+}
+
+/// Etherscan-compatible explorer API, for the transaction history of `generate_report` and
+/// `pnl` imports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EtherscanConfig {
+    /// Etherscan's multichain API by default, which picks the chain by `chainid`
+    pub api_url: String,
+    pub api_key: Option<String>,
+    /// Most entries fetched of each list (transactions, internal transfers, token transfers)
+    pub max_transactions: u64,
+}
+
+/// Snapshot governance for the `snapshot` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// GraphQL API proposals and voting power are read from
+    pub hub_url: String,
+    /// Where signed votes are sent
+    pub sequencer_url: String,
+    /// Optional hub API key, for higher rate limits
+    pub api_key: Option<String>,
+    /// Spaces whose active proposals are listed, e.g. "aave.eth"
+    pub spaces: Vec<String>,
+    /// App name recorded with each vote
+    pub app: String,
+}
+
+/// OpenZeppelin Governor contracts used by the `governor` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GovernorConfig {
+    /// Governor addresses by name, e.g. "ens"
+    pub contracts: BTreeMap<String, String>,
+    /// How far back proposals are looked for, in blocks
+    pub lookback_blocks: u64,
+    /// Blocks per eth_getLogs request, within the RPC provider's limit
+    pub log_chunk_blocks: u64,
+}
+
+/// Liquid staking protocols used by the `stake` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StakingConfig {
+    /// Deployments by chain; those on the configured chain are used
+    pub protocols: Vec<StakingProtocolConfig>,
+}
+
+/// A liquid staking deployment on one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakingProtocolConfig {
+    /// Name tool calls use, e.g. "lido"
+    pub name: String,
+    /// "lido" or "rocket_pool"
+    pub kind: String,
+    pub chain_id: u64,
+    /// Lido's stETH contract, or Rocket Pool's RocketStorage (which has the addresses of its
+    /// other contracts)
+    pub contract: String,
+    /// Lido's withdrawal queue, for unstaking
+    #[serde(default)]
+    pub withdrawal_queue: Option<String>,
+}
+
+/// Money markets used by the `lending` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LendingConfig {
+    /// Deployments by chain; those on the configured chain are used
+    pub markets: Vec<LendingMarketConfig>,
+    /// Health factor below which withdrawals and borrows are warned about and need the user to
+    /// accept the risk
+    pub min_health_factor: f64,
+}
+
+/// A money market deployment on one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LendingMarketConfig {
+    /// Name tool calls use, e.g. "aave"
+    pub name: String,
+    /// "aave_v3" or "compound_v3"
+    pub kind: String,
+    pub chain_id: u64,
+    /// Aave's Pool, or the Compound Comet of one base asset
+    pub contract: String,
+}
+
+/// LI.FI bridge aggregator used by the `bridge` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    pub api_url: String,
+    /// Optional; raises LI.FI's rate limits
+    pub api_key: Option<String>,
+    /// Name transfers are reported to LI.FI under
+    pub integrator: String,
+    /// Most the received amount may fall short of the quote, in basis points
+    pub slippage_bps: u32,
+    /// Chain ids transfers may go to from the configured chain
+    pub chains: Vec<u64>,
+}
+
+/// Token approval audits of the `approvals` tool. Approvals are found in the owner's `Approval`
+/// and `ApprovalForAll` logs, from the `[etherscan]` API when it has a key and otherwise from an
+/// `eth_getLogs` scan of recent blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalsConfig {
+    /// Blocks back from the latest that the `eth_getLogs` scan covers
+    pub scan_blocks: u64,
+    /// Most approval logs read from the explorer
+    pub max_logs: u64,
+}
+
+/// Safe multisig operated by the `safe_multisig` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeConfig {
+    /// Safe Transaction Service for the configured chain
+    pub transaction_service_url: String,
+    pub api_key: Option<String>,
+    /// Safe used when a tool call doesn't name one
+    pub address: Option<String>,
+}
+
+/// ERC-4337 smart accounts used by the `smart_account` tool (EntryPoint v0.6, SimpleAccount)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountAbstractionConfig {
+    /// Bundler JSON-RPC endpoint for the configured chain
+    pub bundler_url: Option<String>,
+    /// ERC-7677 paymaster endpoint that sponsors gas, if any
+    pub paymaster_url: Option<String>,
+    pub entry_point: String,
+    /// SimpleAccountFactory deploying the accounts
+    pub factory: String,
+}
+
+/// Where send and sign operations get their keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignerConfig {
+    /// "local" (keystore and HD wallet accounts), "ledger" (connected Ledger accounts), or
+    /// "kms" (the key under `[kms]`)
+    pub backend: String,
+    /// Directory of the named accounts' encrypted keystore files
+    pub keystore_dir: String,
+    /// Still accept a `private_key` argument in tool calls (deprecated; import keys with
+    /// `wallet import` and send from the account's name instead)
+    pub allow_private_key_args: bool,
+}
+
+/// Ledger hardware wallet accounts (built with the `ledger` feature)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LedgerConfig {
+    /// Account path layout: "ledger_live" (m/44'/60'/N'/0/0) or "legacy" (m/44'/60'/0'/N)
+    pub derivation: String,
+}
+
+/// Remote key for the "kms" signer backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KmsConfig {
+    /// "aws" (built with the `aws` feature) or "gcp"
+    pub provider: String,
+    /// AWS key ID, alias, or ARN, or a GCP key version resource name
+    pub key_id: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            personalities_dir: "assets/personalities".to_string(),
+            persona: "aero".to_string(),
+            policy_path: "assets/policy.json".to_string(),
+            models_path: "models.toml".to_string(),
+            history_path: ".chat_history".to_string(),
+            models: ModelRegistry::default(),
+            abi_dir: "assets/abis".to_string(),
+            dry_run: false,
+            anthropic: AnthropicConfig::default(),
+            tools: ToolsConfig::default(),
+            plugins: PluginsConfig::default(),
+            mcp: McpConfig::default(),
+            chain: ChainConfig::default(),
+            database: DatabaseConfig::default(),
+            redis: RedisConfig::default(),
+            server: ServerConfig::default(),
+            discord: DiscordConfig::default(),
+            memory: MemoryConfig::default(),
+            context: ContextConfig::default(),
+            prices: PricesConfig::default(),
+            dex: DexConfig::default(),
+            portfolio: PortfolioConfig::default(),
+            nft: NftConfig::default(),
+            ipfs: IpfsConfig::default(),
+            events: EventsConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            alerts: AlertsConfig::default(),
+            reminders: RemindersConfig::default(),
+            gas: GasConfig::default(),
+            notify: NotifyConfig::default(),
+            tracker: TrackerConfig::default(),
+            retention: RetentionConfig::default(),
+            faucet: FaucetConfig::default(),
+            signatures: SignaturesConfig::default(),
+            dune: DuneConfig::default(),
+            search: SearchConfig::default(),
+            fetch: FetchConfig::default(),
+            workspace: WorkspaceConfig::default(),
+            etherscan: EtherscanConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            governor: GovernorConfig::default(),
+            staking: StakingConfig::default(),
+            lending: LendingConfig::default(),
+            bridge: BridgeConfig::default(),
+            approvals: ApprovalsConfig::default(),
+            safe: SafeConfig::default(),
+            account_abstraction: AccountAbstractionConfig::default(),
+            signer: SignerConfig::default(),
+            ledger: LedgerConfig::default(),
+            kms: KmsConfig::default(),
+        }
+    }
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        AnthropicConfig {
+            api_key: None,
+            api_url: "https://api.anthropic.com/v1/messages".to_string(),
+            model: "claude-3-opus-20240229".to_string(),
+            max_tokens: 1024,
+            max_tool_iterations: 10,
+            structured_retries: 2,
+            prompt_caching: true,
+            thinking_budget_tokens: 0,
+            persist_thinking: false,
+            prices: HashMap::new(),
+            mock_fixture: None,
+        }
+    }
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        ToolsConfig {
+            timeout_secs: 120,
+            timeouts: BTreeMap::new(),
+            cache_ttl_secs: BTreeMap::new(),
+            injection_guard: "strip".to_string(),
+        }
+    }
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        PluginsConfig {
+            dir: "plugins".to_string(),
+            grants: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            name: "Sepolia".to_string(),
+            chain_id: 11155111,
+            rpc_url: None,
+            multicall: default_multicall(),
+            private_rpc_url: None,
+            private_transactions: false,
+            explorer_url: None,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: None,
+            auto_migrate: true,
+        }
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            url: None,
+            key_prefix: "agent:".to_string(),
+            session_ttl_secs: 600,
+            cached_messages: 100,
+            lock_ttl_secs: 60,
+            lock_wait_secs: 30,
+        }
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        DiscordConfig {
+            token: None,
+            channel_id: None,
+            roles: HashMap::new(),
+            default_role: Role::Viewer,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            port: 3000,
+            domain: "localhost:3000".to_string(),
+            auth_ttl_secs: 86_400,
+            allow_signup: false,
+            signup_role: Role::Viewer,
+        }
+    }
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig {
+            enabled: false,
+            api_key: None,
+            embedding_url: "https://api.voyageai.com/v1/embeddings".to_string(),
+            embedding_model: "voyage-3-lite".to_string(),
+            dimensions: 512,
+            top_k: 5,
+            min_similarity: 0.3,
+        }
+    }
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        ContextConfig {
+            max_history_tokens: 8000,
+            keep_recent_messages: 10,
+        }
+    }
+}
+
+impl Default for PricesConfig {
+    fn default() -> Self {
+        PricesConfig {
+            coingecko_url: "https://api.coingecko.com/api/v3".to_string(),
+            coingecko_api_key: None,
+            coingecko_platform: "ethereum".to_string(),
+            cache_ttl_secs: 60,
+            // Feeds on Sepolia, the default chain
+            chainlink_feeds: BTreeMap::from([
+                ("BTC/USD".to_string(), "0x1b44F3514812d835EB1BDB0acB33d3fA3351Ee43".to_string()),
+                ("ETH/USD".to_string(), "0x694AA1769357215DE4FAC081bf1f309aDC325306".to_string()),
+                ("LINK/USD".to_string(), "0xc59E3633BAAC79493d908e63626716e204A45EdF".to_string()),
+            ]),
+        }
+    }
+}
+
+impl Default for DexConfig {
+    // The Uniswap V3 deployment on Sepolia, the default chain
+    fn default() -> Self {
+        DexConfig {
+            quoter: "0xEd1f6473345F45b75F8179591dd5bA1888cf2FB3".to_string(),
+            router: "0x3bFA4769FB09eefC5a80d6E87c3B9C650f7Ae48E".to_string(),
+            factory: "0x0227628f3F023bb0B980b67D528571c95c6DaC1c".to_string(),
+            weth: "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14".to_string(),
+            fee_tier: 3000,
+            slippage_bps: 50,
+            tokens: BTreeMap::from([
+                ("USDC".to_string(), "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238".to_string()),
+                ("WETH".to_string(), "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14".to_string()),
+            ]),
+        }
+    }
+}
+
+impl Default for NftConfig {
+    fn default() -> Self {
+        NftConfig {
+            alchemy_api_key: None,
+            alchemy_network: "eth-sepolia".to_string(),
+            ipfs_gateway: "https://ipfs.io".to_string(),
+            mint_contract: None,
+            mint_function: "function safeMint(address to, string uri)".to_string(),
+        }
+    }
+}
+
+impl Default for IpfsConfig {
+    fn default() -> Self {
+        IpfsConfig {
+            provider: "node".to_string(),
+            api_url: None,
+            api_token: None,
+            uploads_dir: "assets/uploads".to_string(),
+        }
+    }
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            enabled: false,
+            ws_url: None,
+            trigger_agent: true,
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        AlertsConfig {
+            enabled: true,
+            schedule: "* * * * *".to_string(),
+            explain: true,
+        }
+    }
+}
+
+impl Default for RemindersConfig {
+    fn default() -> Self {
+        RemindersConfig {
+            enabled: true,
+            schedule: "* * * * *".to_string(),
+        }
+    }
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        GasConfig {
+            history_blocks: 20,
+            check_schedule: "* * * * *".to_string(),
+            max_wait_hours: 24,
+        }
+    }
+}
+
+impl Default for EmailNotifyConfig {
+    fn default() -> Self {
+        EmailNotifyConfig {
+            smtp_host: None,
+            smtp_port: 587,
+            username: None,
+            password: None,
+            from: None,
+            to: Vec::new(),
+            kinds: Vec::new(),
+        }
+    }
+}
+
+impl Default for FaucetConfig {
+    fn default() -> Self {
+        FaucetConfig {
+            apis: Vec::new(),
+            api_key: None,
+            account: None,
+            amount: "0.01".to_string(),
+            cooldown_secs: 86400,
+            fund_new_wallets: false,
+        }
+    }
+}
+
+impl Default for SignaturesConfig {
+    fn default() -> Self {
+        SignaturesConfig {
+            enabled: true,
+            sourcify_url: "https://api.4byte.sourcify.dev/signature-database/v1/lookup".to_string(),
+            fourbyte_url: "https://www.4byte.directory/api/v1".to_string(),
+            cache_path: ".signature_cache.json".to_string(),
+        }
+    }
+}
+
+impl Default for DuneConfig {
+    fn default() -> Self {
+        DuneConfig {
+            api_url: "https://api.dune.com/api/v1".to_string(),
+            api_key: None,
+            performance: "medium".to_string(),
+            max_rows: 25,
+            wait_secs: 90,
+            poll_interval_secs: 2,
+            queries: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            provider: "brave".to_string(),
+            api_url: None,
+            api_key: None,
+            max_results: 5,
+        }
+    }
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            max_tokens: 4000,
+            max_bytes: 2 * 1024 * 1024,
+            allow_private_hosts: false,
+        }
+    }
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        WorkspaceConfig {
+            dir: "workspace".to_string(),
+            max_file_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl Default for EtherscanConfig {
+    fn default() -> Self {
+        EtherscanConfig {
+            api_url: "https://api.etherscan.io/v2/api".to_string(),
+            api_key: None,
+            max_transactions: 10000,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            hub_url: "https://hub.snapshot.org/graphql".to_string(),
+            sequencer_url: "https://seq.snapshot.org/".to_string(),
+            api_key: None,
+            spaces: Vec::new(),
+            app: "onchain-agent".to_string(),
+        }
+    }
+}
+
+impl Default for GovernorConfig {
+    fn default() -> Self {
+        GovernorConfig {
+            contracts: BTreeMap::new(),
+            lookback_blocks: 200_000,
+            log_chunk_blocks: 10_000,
+        }
+    }
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        let protocol = |name: &str, kind: &str, chain_id, contract: &str, withdrawal_queue: Option<&str>| StakingProtocolConfig {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            chain_id,
+            contract: contract.to_string(),
+            withdrawal_queue: withdrawal_queue.map(str::to_string),
+        };
+        StakingConfig {
+            protocols: vec![
+                protocol("lido", "lido", 1, "0xae7ab96520DE3A18E5e111B5EaAb095312D7fE84", Some("0x889edC2eDab5f40e902b864aD4d7AdE8E412F9B1")),
+                protocol("rocket_pool", "rocket_pool", 1, "0x1d8f8f00cfa6758d7bE78336684788Fb0ee0Fa46", None),
+                protocol("lido", "lido", 17000, "0x3F1c547b21f65e10480dE3ad8E19fAAC46C95034", Some("0xc7cc160b58F8Bb0baC94b80847E2CF2800565C50")),
+                protocol("rocket_pool", "rocket_pool", 17000, "0x594Fb75D3dc2DFa0150Ad03F99F97817747dd4E1", None),
+                protocol("lido", "lido", 11155111, "0x3e3FE7dBc6B4C189E7128855dD526361c49b40Af", Some("0x1583C7b3f4C3B008720E6BcE5726336b0aB25fdd")),
+            ],
+        }
+    }
+}
+
+impl Default for LendingConfig {
+    fn default() -> Self {
+        let market = |name: &str, kind: &str, chain_id, contract: &str| LendingMarketConfig {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            chain_id,
+            contract: contract.to_string(),
+        };
+        LendingConfig {
+            markets: vec![
+                market("aave", "aave_v3", 1, "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2"),
+                market("compound_usdc", "compound_v3", 1, "0xc3d688B66703497DAA19211EEdff47f25384cdc3"),
+                market("compound_weth", "compound_v3", 1, "0xA17581A9E3356d9A858b789D68B4d866e593aE94"),
+                market("aave", "aave_v3", 11155111, "0x6Ae43d3271ff6888e7Fc43Fd7321a503ff738951"),
+                market("compound_usdc", "compound_v3", 11155111, "0xAec1F48e02Cfb822Be958B68C7957156EB3F0b6e"),
+            ],
+            min_health_factor: 1.5,
+        }
+    }
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        BridgeConfig {
+            api_url: "https://li.quest/v1".to_string(),
+            api_key: None,
+            integrator: "onchain-agent-template".to_string(),
+            slippage_bps: 50,
+            chains: vec![1, 10, 8453, 42161, 137],
+        }
+    }
+}
+
+impl Default for ApprovalsConfig {
+    fn default() -> Self {
+        ApprovalsConfig {
+            scan_blocks: 50000,
+            max_logs: 5000,
+        }
+    }
+}
+
+impl Default for SafeConfig {
+    fn default() -> Self {
+        SafeConfig {
+            transaction_service_url: "https://safe-transaction-sepolia.safe.global".to_string(),
+            api_key: None,
+            address: None,
+        }
+    }
+}
+
+impl Default for AccountAbstractionConfig {
+    // The canonical EntryPoint v0.6 and its SimpleAccountFactory, deployed on Sepolia and most chains
+    fn default() -> Self {
+        AccountAbstractionConfig {
+            bundler_url: None,
+            paymaster_url: None,
+            entry_point: "0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789".to_string(),
+            factory: "0x9406Cc6185a346906296840746125a0E44976454".to_string(),
+        }
+    }
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig {
+            backend: "local".to_string(),
+            keystore_dir: "keystore".to_string(),
+            allow_private_key_args: false,
+        }
+    }
+}
+
+impl Default for KmsConfig {
+    fn default() -> Self {
+        KmsConfig {
+            provider: "aws".to_string(),
+            key_id: None,
+        }
+    }
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        LedgerConfig {
+            derivation: "ledger_live".to_string(),
+        }
+    }
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            enabled: true,
+            poll_interval_secs: 15,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            max_age_days: 0,
+            max_messages_per_session: 0,
+            prune_interval_secs: 3600,
+        }
+    }
+}
+
+// Plain environment variables (the familiar names, and secrets best kept in .env), mapped
+// onto their config keys. Later entries win, so ETH_RPC_URL takes precedence over SEPOLIA_RPC_URL.
+const ENV_ALIASES: &[(&str, &str)] = &[
+    ("ANTHROPIC_API_KEY", "anthropic.api_key"),
+    ("MAX_TOOL_ITERATIONS", "anthropic.max_tool_iterations"),
+    ("THINKING_BUDGET_TOKENS", "anthropic.thinking_budget_tokens"),
+    ("SEPOLIA_RPC_URL", "chain.rpc_url"),
+    ("ETH_RPC_URL", "chain.rpc_url"),
+    ("ETH_WS_URL", "events.ws_url"),
+    ("DATABASE_URL", "database.url"),
+    ("REDIS_URL", "redis.url"),
+    ("POLICY_PATH", "policy_path"),
+    ("MODELS_PATH", "models_path"),
+    ("CHAT_HISTORY_PATH", "history_path"),
+    ("ABI_DIR", "abi_dir"),
+    ("WORKSPACE_DIR", "workspace.dir"),
+    ("PLUGINS_DIR", "plugins.dir"),
+    ("DRY_RUN", "dry_run"),
+    ("PORT", "server.port"),
+    ("DISCORD_TOKEN", "discord.token"),
+    ("DISCORD_CHANNEL_ID", "discord.channel_id"),
+    ("EMBEDDING_API_KEY", "memory.api_key"),
+    ("COINGECKO_API_KEY", "prices.coingecko_api_key"),
+    ("ALCHEMY_API_KEY", "nft.alchemy_api_key"),
+    ("IPFS_PROVIDER", "ipfs.provider"),
+    ("IPFS_API_TOKEN", "ipfs.api_token"),
+    ("TELEGRAM_BOT_TOKEN", "notify.telegram.bot_token"),
+    ("TELEGRAM_CHAT_ID", "notify.telegram.chat_id"),
+    ("DISCORD_WEBHOOK_URL", "notify.discord.webhook_url"),
+    ("SMTP_PASSWORD", "notify.email.password"),
+    ("FAUCET_API_KEY", "faucet.api_key"),
+    ("FAUCET_ACCOUNT", "faucet.account"),
+    ("DUNE_API_KEY", "dune.api_key"),
+    ("SEARCH_PROVIDER", "search.provider"),
+    ("SEARCH_API_KEY", "search.api_key"),
+    ("ETHERSCAN_API_KEY", "etherscan.api_key"),
+    ("SNAPSHOT_API_KEY", "snapshot.api_key"),
+    ("LIFI_API_KEY", "bridge.api_key"),
+    ("SAFE_API_KEY", "safe.api_key"),
+    ("SAFE_ADDRESS", "safe.address"),
+    ("BUNDLER_URL", "account_abstraction.bundler_url"),
+    ("PAYMASTER_URL", "account_abstraction.paymaster_url"),
+    ("SIGNER_BACKEND", "signer.backend"),
+    ("KEYSTORE_DIR", "signer.keystore_dir"),
+    ("KMS_PROVIDER", "kms.provider"),
+    ("KMS_KEY_ID", "kms.key_id"),
+];
+
+/// Load the configuration: built-in defaults, then the TOML file at `path` (if it exists),
+/// then the plain variables above, then `AGENT_`-prefixed variables such as
+/// `AGENT_ANTHROPIC__MODEL` (`__` separates nested keys).
+pub fn load_config(path: &str) -> anyhow::Result<Config> {
+    let mut figment = Figment::from(Serialized::defaults(Config::default()))
+        .merge(Toml::file(path));
+    for (var, key) in ENV_ALIASES {
+        // Variables left empty in .env mean "unset" rather than an empty value
+        if std::env::var(var).is_ok_and(|value| !value.is_empty()) {
+            figment = figment.merge(Env::raw().only(&[var]).map(move |_| (*key).into()));
+        }
+    }
+    let mut config: Config = figment
+        .merge(Env::prefixed("AGENT_").split("__"))
+        .extract()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    config.models = load_models(&config.models_path);
+    Ok(config)
+}
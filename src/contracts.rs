@@ -0,0 +1,202 @@
+use ethers::abi::token::{LenientTokenizer, Tokenizer};
+use ethers::abi::{Abi, Function, FunctionExt, LogParam, ParamType, StateMutability, Token};
+use std::fs;
+use std::path::Path;
+
+/// Names of the ABIs available in `abi_dir`, one `<Name>.json` file per contract (file stems, sorted)
+pub fn list_abis(abi_dir: &str) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(abi_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("json")
+            && let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+        {
+            names.push(stem.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Load an ABI by name. Accepts a plain ABI array or a Hardhat/Foundry artifact with an `abi` key.
+pub fn load_abi(abi_dir: &str, name: &str) -> anyhow::Result<Abi> {
+    // Only allow bare names so the lookup can't escape the ABI directory
+    if name.is_empty() || Path::new(name).components().count() != 1 || name.contains("..") {
+        return Err(anyhow::anyhow!("Invalid ABI name: {}", name));
+    }
+    let file_name = if name.ends_with(".json") { name.to_string() } else { format!("{}.json", name) };
+    let path = Path::new(abi_dir).join(file_name);
+    let data = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Could not read ABI {}: {}", path.display(), e))?;
+    
+    let json: serde_json::Value = serde_json::from_str(&data)?;
+    let abi_json = match json.get("abi") {
+        Some(abi) => abi.clone(),
+        None => json,
+    };
+    Ok(serde_json::from_value(abi_json)?)
+}
+
+/// Find a function by name, or by full signature such as `balanceOf(address)` for overloads
+pub fn find_function<'a>(abi: &'a Abi, function: &str) -> anyhow::Result<&'a Function> {
+    if function.contains('(') {
+        let wanted: String = function.chars().filter(|c| !c.is_whitespace()).collect();
+        return abi.functions()
+            .find(|f| f.abi_signature() == wanted)
+            .ok_or_else(|| anyhow::anyhow!("Function {} not found in ABI", function));
+    }
+    abi.function(function)
+        .map_err(|_| anyhow::anyhow!("Function {} not found in ABI", function))
+}
+
+/// Human-readable list of the functions in an ABI, e.g. `balanceOf(address) view returns (uint256)`
+pub fn describe_functions(abi: &Abi) -> Vec<String> {
+    abi.functions()
+        .map(|f| {
+            let mutability = match f.state_mutability {
+                StateMutability::Pure => " pure",
+                StateMutability::View => " view",
+                StateMutability::NonPayable => "",
+                StateMutability::Payable => " payable",
+            };
+            let outputs = f.outputs.iter().map(|p| p.kind.to_string()).collect::<Vec<_>>().join(",");
+            format!("{}{} returns ({})", f.abi_signature(), mutability, outputs)
+        })
+        .collect()
+}
+
+/// Encode calldata for a function from JSON arguments given in ABI order
+pub fn encode_call(function: &Function, args: &[serde_json::Value]) -> anyhow::Result<Vec<u8>> {
+    if args.len() != function.inputs.len() {
+        return Err(anyhow::anyhow!(
+            "{} expects {} argument(s) but {} were given",
+            function.abi_signature(),
+            function.inputs.len(),
+            args.len()
+        ));
+    }
+    let tokens = function.inputs.iter()
+        .zip(args)
+        .map(|(param, value)| json_to_token(&param.kind, value)
+            .map_err(|e| anyhow::anyhow!("Invalid value for {} ({}): {}", param.name, param.kind, e)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(function.encode_input(&tokens)?)
+}
+
+/// Decode return data into a JSON object keyed by output name (or position when unnamed)
+pub fn decode_output(function: &Function, data: &[u8]) -> anyhow::Result<serde_json::Value> {
+    let tokens = function.decode_output(data)?;
+    let mut result = serde_json::Map::new();
+    for (index, (param, token)) in function.outputs.iter().zip(tokens).enumerate() {
+        let key = if param.name.is_empty() { index.to_string() } else { param.name.clone() };
+        result.insert(key, token_to_json(&token));
+    }
+    Ok(serde_json::Value::Object(result))
+}
+
+fn json_to_token(kind: &ParamType, value: &serde_json::Value) -> anyhow::Result<Token> {
+    match (kind, value) {
+        (ParamType::Array(inner), serde_json::Value::Array(items)) => Ok(Token::Array(
+            items.iter().map(|item| json_to_token(inner, item)).collect::<anyhow::Result<_>>()?,
+        )),
+        (ParamType::FixedArray(inner, size), serde_json::Value::Array(items)) => {
+            if items.len() != *size {
+                return Err(anyhow::anyhow!("expected {} elements, got {}", size, items.len()));
+            }
+            Ok(Token::FixedArray(
+                items.iter().map(|item| json_to_token(inner, item)).collect::<anyhow::Result<_>>()?,
+            ))
+        },
+        (ParamType::Tuple(kinds), serde_json::Value::Array(items)) => {
+            if items.len() != kinds.len() {
+                return Err(anyhow::anyhow!("expected {} tuple fields, got {}", kinds.len(), items.len()));
+            }
+            Ok(Token::Tuple(
+                kinds.iter().zip(items).map(|(kind, item)| json_to_token(kind, item)).collect::<anyhow::Result<_>>()?,
+            ))
+        },
+        (_, serde_json::Value::String(text)) => Ok(LenientTokenizer::tokenize(kind, text)?),
+        (_, other) => Ok(LenientTokenizer::tokenize(kind, &other.to_string())?),
+    }
+}
+
+/// Convert a decoded token into JSON. Integers become decimal strings so no precision is lost.
+pub fn token_to_json(token: &Token) -> serde_json::Value {
+    match token {
+        Token::Address(address) => serde_json::Value::String(format!("{:?}", address)),
+        Token::Uint(value) => serde_json::Value::String(value.to_string()),
+        Token::Int(value) => serde_json::Value::String(ethers::types::I256::from_raw(*value).to_string()),
+        Token::Bool(value) => serde_json::Value::Bool(*value),
+        Token::String(value) => serde_json::Value::String(value.clone()),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => serde_json::Value::String(format!("0x{}", hex::encode(bytes))),
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+            serde_json::Value::Array(items.iter().map(token_to_json).collect())
+        },
+    }
+}
+
+/// Decoded event arguments as `name=value` pairs, e.g. `from=0x…, to=0x…, value=1000`
+pub fn format_params(params: &[LogParam]) -> String {
+    params.iter()
+        .map(|param| match token_to_json(&param.value) {
+            serde_json::Value::String(text) => format!("{}={}", param.name, text),
+            other => format!("{}={}", param.name, other),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Selectors of the built-in Solidity revert payloads
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decode revert data into a readable reason: `Error(string)`, `Panic(uint256)`,
+/// or a custom error declared in the ABI
+pub fn decode_revert(abi: Option<&Abi>, data: &[u8]) -> String {
+    if data.is_empty() {
+        return "reverted without a reason".to_string();
+    }
+    if data.len() < 4 {
+        return format!("reverted with data 0x{}", hex::encode(data));
+    }
+    let (selector, payload) = data.split_at(4);
+    
+    if selector == ERROR_STRING_SELECTOR
+        && let Ok(tokens) = ethers::abi::decode(&[ParamType::String], payload)
+        && let Some(Token::String(reason)) = tokens.first()
+    {
+        return reason.clone();
+    }
+    
+    if selector == PANIC_SELECTOR
+        && let Ok(tokens) = ethers::abi::decode(&[ParamType::Uint(256)], payload)
+        && let Some(Token::Uint(code)) = tokens.first()
+    {
+        let meaning = match code.low_u64() {
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum value",
+            0x22 => "invalid storage byte array",
+            0x31 => "pop on empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to uninitialized function",
+            _ => "unknown panic code",
+        };
+        return format!("panic 0x{:02x}: {}", code.low_u64(), meaning);
+    }
+    
+    if let Some(abi) = abi {
+        for error in abi.errors() {
+            if error.signature().as_bytes()[..4] == *selector
+                && let Ok(tokens) = error.decode(payload)
+            {
+                let args = tokens.iter().map(|t| token_to_json(t).to_string()).collect::<Vec<_>>().join(", ");
+                return format!("{}({})", error.name, args);
+            }
+        }
+    }
+    
+    format!("reverted with unknown error 0x{}", hex::encode(data))
+}
@@ -0,0 +1,190 @@
+use crate::anthropic::{complete, history_from_rows, Message};
+use crate::store::MessageStore;
+use crate::tools::ToolContext;
+use uuid::Uuid;
+
+const SUMMARY_PROMPT: &str = "You maintain the memory of a conversation between a user and an AI agent. \
+    Summarize the conversation turns you are given, merged with the existing summary if there is one. \
+    Keep every fact, decision, address, amount, transaction hash, and open question that later turns may need. \
+    Be concise and write the summary as plain bullet points.";
+
+/// Rough token count for a text (about four characters per token), used to size the history
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// The history sent to the model: a running summary of older turns plus the recent turns verbatim
+#[derive(Clone, Default)]
+pub struct Conversation {
+    pub summary: Option<String>,
+    pub messages: Vec<Message>,
+    // How many of the session's stored messages are covered by `summary` (or were skipped)
+    summarized_messages: i64,
+}
+
+impl Conversation {
+    /// Load a session's summary and up to `limit` of the stored messages after it
+    pub async fn load(store: &dyn MessageStore, session_id: Uuid, limit: i64) -> anyhow::Result<Self> {
+        let (summary, summarized_messages) = store.load_summary(session_id).await?;
+        let rows = store.load_messages(session_id, summarized_messages, limit).await?;
+
+        // Messages between the summary and the loaded window are dropped, as are leading
+        // assistant messages (the API requires the history to start with the user)
+        let first_position = rows.first().map_or(summarized_messages + 1, |(position, _, _)| *position);
+        let rows: Vec<(String, String)> = rows.into_iter().map(|(_, role, content)| (role, content)).collect();
+        let messages = history_from_rows(&rows);
+        let skipped = (rows.len() - messages.len()) as i64;
+
+        Ok(Conversation {
+            summary,
+            messages,
+            summarized_messages: first_position - 1 + skipped,
+        })
+    }
+
+    pub fn push(&mut self, role: &str, text: &str) {
+        self.messages.push(Message::text(role, text));
+    }
+
+    pub fn estimated_tokens(&self) -> usize {
+        self.summary.as_deref().map_or(0, estimate_tokens)
+            + self.messages.iter().map(|m| estimate_tokens(&m.plain_text())).sum::<usize>()
+    }
+
+    /// System prompt section carrying the summary of compacted turns
+    pub fn summary_block(&self) -> Option<String> {
+        self.summary.as_ref()
+            .map(|summary| format!("Summary of the earlier part of this conversation:\n{}", summary))
+    }
+
+    /// Summarize older turns into the running summary once the history exceeds the configured
+    /// size, keeping the most recent messages verbatim. The summary is saved with the session.
+    pub async fn compact(&mut self, ctx: &ToolContext) -> anyhow::Result<()> {
+        let limits = &ctx.config.context;
+        if self.estimated_tokens() <= limits.max_history_tokens {
+            return Ok(());
+        }
+
+        // Split at a user message so the remaining history still starts with the user, and not
+        // at tool results, which must stay with the tool_use they answer
+        let mut split = self.messages.len().saturating_sub(limits.keep_recent_messages);
+        while split < self.messages.len() && (self.messages[split].role() != "user" || self.messages[split].is_tool_result()) {
+            split += 1;
+        }
+        if split == 0 || split == self.messages.len() {
+            return Ok(());
+        }
+
+        let transcript = self.messages[..split].iter()
+            .map(|m| format!("{}: {}", m.role(), m.plain_text()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let prompt = match &self.summary {
+            Some(summary) => format!("Existing summary:\n{}\n\nNew conversation turns:\n{}", summary, transcript),
+            None => format!("Conversation turns:\n{}", transcript),
+        };
+        let summary = complete(&ctx.config.anthropic, ctx, SUMMARY_PROMPT, &prompt).await?;
+
+        self.messages.drain(..split);
+        self.summary = Some(summary);
+        self.summarized_messages += split as i64;
+        tracing::info!(summarized = split, estimated_tokens = self.estimated_tokens(), "Compacted conversation history");
+
+        if let (Some(session_id), Some(summary)) = (ctx.session_id, &self.summary)
+            && let Err(e) = ctx.store.save_summary(session_id, summary, self.summarized_messages).await
+        {
+            tracing::error!(error = %e, "Failed to save conversation summary");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::mock_llm;
+    use crate::test_support::context;
+    use serde_json::json;
+
+    // A history of two swaps and a balance check, each answered with a tool call, then thanks
+    fn history() -> Vec<Message> {
+        vec![
+            Message::text("user", "Swap 1 ETH for USDC on Uniswap, please."),
+            Message::tool_use("t1", "eth_swap", json!({ "amount": "1", "token_in": "ETH", "token_out": "USDC" })),
+            Message::tool_result("t1", "Swapped 1 ETH for 2,431.12 USDC"),
+            Message::text("assistant", "Done: 1 ETH became 2,431.12 USDC."),
+            Message::text("user", "And what is my ETH balance now?"),
+            Message::tool_use("t2", "eth_wallet", json!({ "operation": "balance" })),
+            Message::tool_result("t2", "Balance: 0.42 ETH"),
+            Message::text("assistant", "You have 0.42 ETH left."),
+            Message::text("user", "Thanks!"),
+            Message::text("assistant", "You're welcome."),
+        ]
+    }
+
+    // A context whose model answers from `responses`, in a fixture of its own
+    fn summarizing_context(responses: serde_json::Value, max_history_tokens: usize, keep_recent_messages: usize) -> (ToolContext, String) {
+        let fixture = std::env::temp_dir().join(format!("summaries-{}.json", Uuid::new_v4()));
+        std::fs::write(&fixture, responses.to_string()).unwrap();
+        let mut config = Config::default();
+        config.anthropic.mock_fixture = Some(fixture.to_string_lossy().into_owned());
+        config.context.max_history_tokens = max_history_tokens;
+        config.context.keep_recent_messages = keep_recent_messages;
+        // Loaded once, the script is kept by its path
+        let path = fixture.to_string_lossy().into_owned();
+        mock_llm::shared(&path).unwrap();
+        std::fs::remove_file(&fixture).unwrap();
+        (context(config), path)
+    }
+
+    #[test]
+    fn counts_tokens_per_message() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("gas"), 1);
+        assert_eq!(estimate_tokens("Thanks!"), 2);
+        // Characters, not bytes
+        assert_eq!(estimate_tokens("€€€€"), 1);
+        let conversation = Conversation { summary: Some("The user swapped.".to_string()), messages: history(), summarized_messages: 0 };
+        let texts = ["Swap 1 ETH for USDC on Uniswap, please.", "Done: 1 ETH became 2,431.12 USDC.", "And what is my ETH balance now?",
+                     "You have 0.42 ETH left.", "Thanks!", "You're welcome.", "The user swapped."];
+        assert_eq!(conversation.estimated_tokens(), texts.iter().map(|text| estimate_tokens(text)).sum::<usize>());
+    }
+
+    #[tokio::test]
+    async fn leaves_a_history_under_the_threshold_alone() {
+        let (ctx, _) = summarizing_context(json!([]), 8000, 4);
+        let mut conversation = Conversation { messages: history(), ..Conversation::default() };
+        conversation.compact(&ctx).await.unwrap();
+        assert_eq!((conversation.messages.len(), conversation.summary), (10, None));
+    }
+
+    #[tokio::test]
+    async fn summarizes_older_turns_and_keeps_tool_results_with_their_calls() {
+        // The last four messages start at the balance check's tool result, which can't be
+        // separated from its tool_use, so only the thanks are kept
+        let (ctx, fixture) = summarizing_context(json!(["- Swapped 1 ETH for 2,431.12 USDC\n- 0.42 ETH left"]), 20, 4);
+        let mut conversation = Conversation { messages: history(), ..Conversation::default() };
+        conversation.compact(&ctx).await.unwrap();
+
+        let kept: Vec<(String, String)> = conversation.messages.iter().map(|m| (m.role().to_string(), m.plain_text())).collect();
+        assert_eq!(kept, [("user".to_string(), "Thanks!".to_string()), ("assistant".to_string(), "You're welcome.".to_string())]);
+        assert_eq!(conversation.summary.as_deref(), Some("- Swapped 1 ETH for 2,431.12 USDC\n- 0.42 ETH left"));
+        assert_eq!(conversation.summarized_messages, 8);
+        assert!(conversation.summary_block().unwrap().starts_with("Summary of the earlier part of this conversation:\n"));
+
+        let requests = mock_llm::shared(&fixture).unwrap().requests();
+        let transcript = requests[0]["messages"][0]["content"][0]["text"].as_str().unwrap();
+        assert!(transcript.starts_with("Conversation turns:\nuser: Swap 1 ETH") && transcript.contains("assistant: You have 0.42 ETH left."), "{}", transcript);
+        assert!(!transcript.contains("Thanks!"), "{}", transcript);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_history_when_every_split_would_orphan_a_tool_result() {
+        // Ending at the balance check's tool result, with only the tool call and its result to keep
+        let (ctx, _) = summarizing_context(json!([]), 20, 2);
+        let mut conversation = Conversation { messages: history()[..7].to_vec(), ..Conversation::default() };
+        conversation.compact(&ctx).await.unwrap();
+        assert_eq!((conversation.messages.len(), conversation.summary), (7, None));
+    }
+}
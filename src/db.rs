@@ -1,32 +1,1306 @@
+use chrono::NaiveDateTime;
+use crate::config::DatabaseConfig;
+use crate::redact::{redact, redact_json};
+use crate::usage::{Usage, UsageTotals};
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
+use uuid::Uuid;
 
-pub async fn get_db_pool() -> Option<Pool<Postgres>> {
-    match std::env::var("DATABASE_URL") {
-        Ok(db_url) => {
-            match sqlx::PgPool::connect(&db_url).await {
+pub async fn get_db_pool(config: &DatabaseConfig) -> Option<Pool<Postgres>> {
+    match &config.url {
+        Some(db_url) => {
+            match sqlx::PgPool::connect(db_url).await {
                 Ok(pool) => {
-                    println!("Successfully connected to database");
+                    tracing::info!("Successfully connected to database");
+                    // Bring the schema up to date before anything queries it
+                    if config.auto_migrate
+                        && let Err(e) = run_migrations(&pool).await
+                    {
+                        tracing::error!(error = %e, "Failed to run database migrations");
+                        return None;
+                    }
                     Some(pool)
                 },
                 Err(e) => {
-                    eprintln!("Failed to connect to Postgres: {}", e);
+                    tracing::error!(error = %e, "Failed to connect to Postgres");
                     None
                 }
             }
         },
-        Err(e) => {
-            eprintln!("DATABASE_URL not set: {}", e);
+        None => {
+            tracing::warn!("No database URL configured; running without persistence");
             None
         }
     }
 }
 
-pub async fn save_message(pool: &Pool<Postgres>, role: &str, content: &str) -> sqlx::Result<()> {
-    sqlx::query("INSERT INTO messages (role, content) VALUES ($1, $2)")
+/// Apply the migrations embedded from `migrations/` that have not run yet
+pub async fn run_migrations(pool: &Pool<Postgres>) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!("./migrations").run(pool).await
+}
+
+pub async fn create_session(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO sessions (id) VALUES ($1)")
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Create a session owned by an address signed in to the HTTP API
+pub async fn create_owned_session(pool: &Pool<Postgres>, session_id: Uuid, owner_address: &str) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO sessions (id, owner_address) VALUES ($1, $2)")
+        .bind(session_id)
+        .bind(owner_address)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SessionRecord {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub requests: i64,
+    pub cost_usd: f64,
+}
+
+/// Sessions owned by a signed-in address, newest first
+pub async fn list_owned_sessions(pool: &Pool<Postgres>, owner_address: &str) -> sqlx::Result<Vec<SessionRecord>> {
+    sqlx::query_as(
+        "SELECT id, created_at, requests, cost_usd FROM sessions WHERE owner_address = $1 \
+         ORDER BY created_at DESC",
+    )
+    .bind(owner_address)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn session_owned_by(pool: &Pool<Postgres>, session_id: Uuid, owner_address: &str) -> sqlx::Result<bool> {
+    let row: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM sessions WHERE id = $1 AND owner_address = $2")
+        .bind(session_id)
+        .bind(owner_address)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+pub async fn session_exists(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<bool> {
+    let row: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+/// Find the session bound to a chat channel (e.g. a Discord channel), creating it on first use
+#[cfg(feature = "discord")]
+pub async fn get_or_create_channel_session(pool: &Pool<Postgres>, channel_id: &str) -> sqlx::Result<Uuid> {
+    let (session_id,): (Uuid,) = sqlx::query_as(
+        "INSERT INTO sessions (id, channel_id) VALUES ($1, $2) \
+         ON CONFLICT (channel_id) DO UPDATE SET channel_id = EXCLUDED.channel_id \
+         RETURNING id",
+    )
+    .bind(Uuid::new_v4())
+    .bind(channel_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(session_id)
+}
+
+/// What producing an answer took: the prompt and completion tokens of the model calls of its
+/// turn, and how long the turn ran
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessageStats {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub latency_ms: i64,
+}
+
+/// Store a message, with the model that was active when it was sent and, for answers, what
+/// the turn took, returning its id. Secrets in it are redacted first, so raw keys never reach
+/// the table.
+pub async fn save_message(pool: &Pool<Postgres>, session_id: Uuid, role: &str, content: &str, model: &str, stats: Option<MessageStats>) -> sqlx::Result<i32> {
+    let redacted = redact(content);
+    if redacted != content {
+        tracing::warn!(%session_id, role, "Redacted secrets from a message before storing it");
+    }
+    let (id,): (i32,) = sqlx::query_as(
+        "INSERT INTO messages (session_id, role, content, model, input_tokens, output_tokens, latency_ms) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+    )
+    .bind(session_id)
+    .bind(role)
+    .bind(redacted.as_ref())
+    .bind(model)
+    .bind(stats.map(|stats| stats.input_tokens))
+    .bind(stats.map(|stats| stats.output_tokens))
+    .bind(stats.map(|stats| stats.latency_ms))
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Store the model's thinking from one response, for debugging
+pub async fn save_thinking(pool: &Pool<Postgres>, session_id: Option<Uuid>, model: &str, content: &str) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO thinking_blocks (session_id, model, content) VALUES ($1, $2, $3)")
+        .bind(session_id)
+        .bind(model)
+        .bind(redact(content).as_ref())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Load the most recent messages of a session after the first `offset`, oldest first, as
+/// (position, role, content) rows where position counts the session's messages from 1
+pub async fn load_session_messages(pool: &Pool<Postgres>, session_id: Uuid, offset: i64, limit: i64) -> sqlx::Result<Vec<(i64, String, String)>> {
+    let mut rows: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT position, role, content FROM ( \
+             SELECT row_number() OVER (ORDER BY id) AS position, role, content \
+             FROM messages WHERE session_id = $1 \
+         ) numbered WHERE position > $2 ORDER BY position DESC LIMIT $3",
+    )
+    .bind(session_id)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.reverse();
+    Ok(rows)
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MessageRecord {
+    #[serde(default)]
+    pub id: i32,
+    pub role: String,
+    pub content: String,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub input_tokens: Option<i64>,
+    #[serde(default)]
+    pub output_tokens: Option<i64>,
+    #[serde(default)]
+    pub latency_ms: Option<i64>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+/// Every message of a session, oldest first
+pub async fn all_session_messages(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<MessageRecord>> {
+    sqlx::query_as(
+        "SELECT id, role, content, model, input_tokens, output_tokens, latency_ms, created_at \
+         FROM messages WHERE session_id = $1 ORDER BY id",
+    )
+        .bind(session_id)
+        .fetch_all(pool)
+        .await
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SearchHit {
+    pub session_id: Uuid,
+    pub role: String,
+    /// The matching part of the message, with the matched words in **bold**
+    pub snippet: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+/// Messages matching a web-style search query ("gas fees", "swap -usdc", "\"exact phrase\""),
+/// best match first, optionally only from the sessions of one owner
+pub async fn search_messages(pool: &Pool<Postgres>, query: &str, owner_address: Option<&str>, limit: i64) -> sqlx::Result<Vec<SearchHit>> {
+    sqlx::query_as(
+        "SELECT m.session_id, m.role, \
+             ts_headline('english', m.content, q, 'StartSel=**, StopSel=**, MaxWords=30, MinWords=10, MaxFragments=2') AS snippet, \
+             m.created_at \
+         FROM messages m, websearch_to_tsquery('english', $1) q \
+         WHERE m.search @@ q AND m.session_id IS NOT NULL \
+         AND ($2::text IS NULL OR m.session_id IN (SELECT id FROM sessions WHERE owner_address = $2)) \
+         ORDER BY ts_rank(m.search, q) DESC, m.id DESC LIMIT $3",
+    )
+    .bind(query)
+    .bind(owner_address)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Delete messages stored more than `days` days ago, and return how many were deleted
+pub async fn prune_messages_older_than(pool: &Pool<Postgres>, days: i32) -> sqlx::Result<i64> {
+    prune_messages(pool, "DELETE FROM messages WHERE created_at < now() - make_interval(days => $1) RETURNING session_id", days).await
+}
+
+/// Delete all but the latest `keep` messages of each session, and return how many were deleted
+pub async fn prune_messages_beyond(pool: &Pool<Postgres>, keep: i32) -> sqlx::Result<i64> {
+    prune_messages(pool, "DELETE FROM messages WHERE id IN ( \
+                              SELECT id FROM ( \
+                                  SELECT id, row_number() OVER (PARTITION BY session_id ORDER BY id DESC) AS newer \
+                                  FROM messages WHERE session_id IS NOT NULL \
+                              ) numbered WHERE newer > $1 \
+                          ) RETURNING session_id", keep).await
+}
+
+// Run a DELETE of a session's oldest messages, taking them off the count the session's summary
+// covers, since history is loaded by position from the first message still stored
+async fn prune_messages(pool: &Pool<Postgres>, delete: &str, bound: i32) -> sqlx::Result<i64> {
+    sqlx::query_scalar(&format!(
+        "WITH deleted AS ({}), \
+         counts AS (SELECT session_id, count(*) AS n FROM deleted GROUP BY session_id), \
+         updated AS ( \
+             UPDATE sessions SET summarized_messages = GREATEST(summarized_messages - counts.n, 0) \
+             FROM counts WHERE sessions.id = counts.session_id \
+         ) \
+         SELECT COALESCE(sum(n), 0)::BIGINT FROM counts",
+        delete
+    ))
+    .bind(bound)
+    .fetch_one(pool)
+    .await
+}
+
+/// The running summary of a session's compacted history and how many messages it covers
+pub async fn load_session_summary(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<(Option<String>, i64)> {
+    sqlx::query_as("SELECT summary, summarized_messages FROM sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await
+        .map(|row| row.unwrap_or((None, 0)))
+}
+
+pub async fn save_session_summary(pool: &Pool<Postgres>, session_id: Uuid, summary: &str, summarized_messages: i64) -> sqlx::Result<()> {
+    sqlx::query("UPDATE sessions SET summary = $2, summarized_messages = $3 WHERE id = $1")
+        .bind(session_id)
+        .bind(redact(summary).as_ref())
+        .bind(summarized_messages)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Add one model response's token usage and estimated cost to the session totals
+pub async fn add_session_usage(pool: &Pool<Postgres>, session_id: Uuid, usage: Usage, cost_usd: f64) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE sessions SET requests = requests + 1, input_tokens = input_tokens + $2, \
+         output_tokens = output_tokens + $3, cache_write_tokens = cache_write_tokens + $4, \
+         cache_read_tokens = cache_read_tokens + $5, cost_usd = cost_usd + $6 WHERE id = $1",
+    )
+    .bind(session_id)
+    .bind(usage.input_tokens as i64)
+    .bind(usage.output_tokens as i64)
+    .bind(usage.cache_creation_input_tokens as i64)
+    .bind(usage.cache_read_input_tokens as i64)
+    .bind(cost_usd)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn load_session_usage(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<UsageTotals> {
+    let row: Option<(i64, i64, i64, i64, i64, f64)> = sqlx::query_as(
+        "SELECT requests, input_tokens, output_tokens, cache_write_tokens, cache_read_tokens, cost_usd FROM sessions WHERE id = $1",
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(requests, input_tokens, output_tokens, cache_write_tokens, cache_read_tokens, cost_usd)| UsageTotals {
+        requests: requests as u64,
+        input_tokens: input_tokens as u64,
+        output_tokens: output_tokens as u64,
+        cache_write_tokens: cache_write_tokens as u64,
+        cache_read_tokens: cache_read_tokens as u64,
+        cost_usd,
+    }).unwrap_or_default())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn save_tool_call(
+    pool: &Pool<Postgres>,
+    session_id: Option<Uuid>,
+    message_id: Option<i32>,
+    tool_name: &str,
+    args: &serde_json::Value,
+    result: &str,
+    duration_ms: i64,
+    success: bool,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO tool_calls (session_id, message_id, tool_name, args, result, duration_ms, success) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(session_id)
+    .bind(message_id)
+    .bind(tool_name)
+    .bind(redact_json(args))
+    .bind(redact(result).as_ref())
+    .bind(duration_ms)
+    .bind(success)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ToolCallRecord {
+    pub id: i64,
+    pub session_id: Option<Uuid>,
+    /// The user message whose turn made the call
+    #[serde(default)]
+    pub message_id: Option<i32>,
+    pub tool_name: String,
+    pub args: serde_json::Value,
+    pub result: String,
+    pub duration_ms: i64,
+    pub success: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// Record a wallet the agent generated or derived; recording it again keeps the most recent label.
+/// The wallet belongs to the owner of the session it was created in, if that session has one.
+pub async fn save_wallet(
+    pool: &Pool<Postgres>,
+    session_id: Option<Uuid>,
+    address: &str,
+    source: &str,
+    derivation_index: Option<i32>,
+    label: Option<&str>,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO wallets (address, source, derivation_index, label, owner_address) \
+         VALUES ($1, $2, $3, $4, (SELECT owner_address FROM sessions WHERE id = $5)) \
+         ON CONFLICT (address) DO UPDATE SET label = COALESCE(EXCLUDED.label, wallets.label), \
+         owner_address = COALESCE(wallets.owner_address, EXCLUDED.owner_address)",
+    )
+    .bind(address)
+    .bind(source)
+    .bind(derivation_index)
+    .bind(label)
+    .bind(session_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WalletRecord {
+    pub address: String,
+    pub source: String,
+    pub derivation_index: Option<i32>,
+    pub label: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn list_owned_wallets(pool: &Pool<Postgres>, owner_address: &str) -> sqlx::Result<Vec<WalletRecord>> {
+    sqlx::query_as(
+        "SELECT address, source, derivation_index, label, created_at FROM wallets \
+         WHERE owner_address = $1 ORDER BY created_at",
+    )
+    .bind(owner_address)
+    .fetch_all(pool)
+    .await
+}
+
+/// Most recent tool calls, newest first, optionally limited to one session and to the
+/// sessions of one owner
+pub async fn list_tool_calls(pool: &Pool<Postgres>, session_id: Option<Uuid>, owner_address: Option<&str>, limit: i64) -> sqlx::Result<Vec<ToolCallRecord>> {
+    sqlx::query_as(
+        "SELECT id, session_id, message_id, tool_name, args, result, duration_ms, success, created_at \
+         FROM tool_calls WHERE ($1::uuid IS NULL OR session_id = $1) \
+         AND ($3::text IS NULL OR session_id IN (SELECT id FROM sessions WHERE owner_address = $3)) \
+         ORDER BY id DESC LIMIT $2",
+    )
+    .bind(session_id)
+    .bind(limit)
+    .bind(owner_address)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TransactionRecord {
+    pub tx_hash: String,
+    pub session_id: Option<Uuid>,
+    pub chain_id: i64,
+    pub from_address: String,
+    pub to_address: Option<String>,
+    pub value: String,
+    pub nonce: i64,
+    pub data: Option<String>,
+    pub description: String,
+    pub status: String,
+    pub block_number: Option<i64>,
+    pub submitted_at: NaiveDateTime,
+}
+
+const TRANSACTION_COLUMNS: &str = "tx_hash, session_id, chain_id, from_address, to_address, value, nonce, data, \
+                                   description, status, block_number, submitted_at";
+
+#[allow(clippy::too_many_arguments)]
+pub async fn save_transaction(
+    pool: &Pool<Postgres>,
+    session_id: Option<Uuid>,
+    chain_id: i64,
+    tx_hash: &str,
+    from_address: &str,
+    to_address: Option<&str>,
+    value: &str,
+    nonce: i64,
+    data: Option<&str>,
+    description: &str,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO transactions (tx_hash, session_id, chain_id, from_address, to_address, value, nonce, data, description) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (tx_hash) DO NOTHING",
+    )
+    .bind(tx_hash)
+    .bind(session_id)
+    .bind(chain_id)
+    .bind(from_address)
+    .bind(to_address)
+    .bind(value)
+    .bind(nonce)
+    .bind(data)
+    .bind(description)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Transactions on a chain that have not been mined or dropped yet, oldest first
+pub async fn pending_transactions(pool: &Pool<Postgres>, chain_id: i64) -> sqlx::Result<Vec<TransactionRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM transactions WHERE status = 'pending' AND chain_id = $1 ORDER BY submitted_at",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn find_transaction(pool: &Pool<Postgres>, tx_hash: &str) -> sqlx::Result<Option<TransactionRecord>> {
+    sqlx::query_as(&format!("SELECT {} FROM transactions WHERE tx_hash = $1", TRANSACTION_COLUMNS))
+        .bind(tx_hash)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn update_transaction_status(pool: &Pool<Postgres>, tx_hash: &str, status: &str, block_number: Option<i64>, reported: bool) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE transactions SET status = $2, block_number = $3, reported = reported OR $4, updated_at = now() \
+         WHERE tx_hash = $1",
+    )
+    .bind(tx_hash)
+    .bind(status)
+    .bind(block_number)
+    .bind(reported)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A session's transactions whose outcome hasn't been reported yet, marking them as reported
+pub async fn take_transaction_updates(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<TransactionRecord>> {
+    sqlx::query_as(&format!(
+        "UPDATE transactions SET reported = true \
+         WHERE session_id = $1 AND status <> 'pending' AND NOT reported \
+         RETURNING {}",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BridgeTransferRecord {
+    pub tx_hash: String,
+    pub bridge: String,
+    pub from_chain_id: i64,
+    pub to_chain_id: i64,
+    pub description: String,
+    pub status: String,
+    pub destination_tx_hash: Option<String>,
+    pub received: Option<String>,
+    /// Status of the source transaction, where a query joins it in
+    #[sqlx(default)]
+    pub source_status: Option<String>,
+}
+
+const BRIDGE_TRANSFER_COLUMNS: &str = "b.tx_hash, b.bridge, b.from_chain_id, b.to_chain_id, b.description, \
+                                       b.status, b.destination_tx_hash, b.received";
+
+pub async fn save_bridge_transfer(pool: &Pool<Postgres>, session_id: Option<Uuid>, tx_hash: &str, bridge: &str, from_chain_id: i64, to_chain_id: i64, description: &str) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO bridge_transfers (tx_hash, session_id, bridge, from_chain_id, to_chain_id, description) \
+         VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (tx_hash) DO NOTHING",
+    )
+    .bind(tx_hash)
+    .bind(session_id)
+    .bind(bridge)
+    .bind(from_chain_id)
+    .bind(to_chain_id)
+    .bind(description)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Transfers from a chain that have not arrived or failed yet, with their source transaction's
+/// status, oldest first
+pub async fn pending_bridge_transfers(pool: &Pool<Postgres>, from_chain_id: i64) -> sqlx::Result<Vec<BridgeTransferRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {}, COALESCE(t.status, 'pending') AS source_status FROM bridge_transfers b \
+         LEFT JOIN transactions t ON t.tx_hash = b.tx_hash \
+         WHERE b.status = 'pending' AND b.from_chain_id = $1 ORDER BY b.created_at",
+        BRIDGE_TRANSFER_COLUMNS
+    ))
+    .bind(from_chain_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn find_bridge_transfer(pool: &Pool<Postgres>, tx_hash: &str) -> sqlx::Result<Option<BridgeTransferRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {}, t.status AS source_status FROM bridge_transfers b \
+         LEFT JOIN transactions t ON t.tx_hash = b.tx_hash WHERE b.tx_hash = $1",
+        BRIDGE_TRANSFER_COLUMNS
+    ))
+    .bind(tx_hash)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_bridge_transfer(pool: &Pool<Postgres>, tx_hash: &str, status: &str, destination_tx_hash: Option<&str>, received: Option<&str>, reported: bool) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE bridge_transfers SET status = $2, destination_tx_hash = COALESCE($3, destination_tx_hash), \
+         received = COALESCE($4, received), reported = reported OR $5, updated_at = now() WHERE tx_hash = $1",
+    )
+    .bind(tx_hash)
+    .bind(status)
+    .bind(destination_tx_hash)
+    .bind(received)
+    .bind(reported)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A session's transfers whose arrival (or failure) hasn't been reported yet, marking them as
+/// reported
+pub async fn take_bridge_updates(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<BridgeTransferRecord>> {
+    sqlx::query_as(&format!(
+        "UPDATE bridge_transfers b SET reported = true \
+         WHERE session_id = $1 AND status <> 'pending' AND NOT reported \
+         RETURNING {}",
+        BRIDGE_TRANSFER_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn save_scheduled_run(pool: &Pool<Postgres>, job_name: &str, input: &str, output: &str, success: bool, started_at: NaiveDateTime) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO scheduled_runs (job_name, input, output, success, started_at) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(job_name)
+    .bind(input)
+    .bind(output)
+    .bind(success)
+    .bind(started_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ScheduledRunRecord {
+    pub job_name: String,
+    pub output: String,
+    pub success: bool,
+    pub started_at: NaiveDateTime,
+}
+
+/// The most recent run of each scheduled job
+pub async fn latest_scheduled_runs(pool: &Pool<Postgres>) -> sqlx::Result<Vec<ScheduledRunRecord>> {
+    sqlx::query_as(
+        "SELECT DISTINCT ON (job_name) job_name, output, success, started_at FROM scheduled_runs \
+         ORDER BY job_name, started_at DESC",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PriceAlertRecord {
+    pub id: i64,
+    pub session_id: Option<Uuid>,
+    pub token: String,
+    pub condition: String,
+    pub threshold: f64,
+    pub status: String,
+    pub triggered_price: Option<f64>,
+    pub explanation: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub triggered_at: Option<NaiveDateTime>,
+}
+
+const PRICE_ALERT_COLUMNS: &str = "id, session_id, token, condition, threshold, status, triggered_price, explanation, \
+                                   created_at, triggered_at";
+
+/// Record a new active alert, owned by the owner of the session it was created in
+pub async fn save_price_alert(pool: &Pool<Postgres>, session_id: Option<Uuid>, token: &str, condition: &str, threshold: f64) -> sqlx::Result<i64> {
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO price_alerts (session_id, owner_address, token, condition, threshold) \
+         VALUES ($1, (SELECT owner_address FROM sessions WHERE id = $1), $2, $3, $4) RETURNING id",
+    )
+    .bind(session_id)
+    .bind(token)
+    .bind(condition)
+    .bind(threshold)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Alerts visible from a session: those with the same owner (or with none, for CLI and Discord
+/// sessions), newest first
+pub async fn list_price_alerts(pool: &Pool<Postgres>, session_id: Option<Uuid>, include_inactive: bool) -> sqlx::Result<Vec<PriceAlertRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM price_alerts \
+         WHERE owner_address IS NOT DISTINCT FROM (SELECT owner_address FROM sessions WHERE id = $1) \
+         AND ($2 OR status = 'active') ORDER BY id DESC",
+        PRICE_ALERT_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(include_inactive)
+    .fetch_all(pool)
+    .await
+}
+
+/// Cancel an active alert visible from a session; false if there is no such alert
+pub async fn cancel_price_alert(pool: &Pool<Postgres>, session_id: Option<Uuid>, id: i64) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE price_alerts SET status = 'cancelled' \
+         WHERE id = $1 AND status = 'active' \
+         AND owner_address IS NOT DISTINCT FROM (SELECT owner_address FROM sessions WHERE id = $2)",
+    )
+    .bind(id)
+    .bind(session_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn active_price_alerts(pool: &Pool<Postgres>) -> sqlx::Result<Vec<PriceAlertRecord>> {
+    sqlx::query_as(&format!("SELECT {} FROM price_alerts WHERE status = 'active' ORDER BY id", PRICE_ALERT_COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+/// Mark an alert as fired; false if it was cancelled or fired in the meantime
+pub async fn trigger_price_alert(pool: &Pool<Postgres>, id: i64, price: f64, explanation: &str) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE price_alerts SET status = 'triggered', triggered_price = $2, explanation = $3, triggered_at = now() \
+         WHERE id = $1 AND status = 'active'",
+    )
+    .bind(id)
+    .bind(price)
+    .bind(explanation)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// A session's alerts that fired since they were last reported, marking them as reported
+pub async fn take_triggered_price_alerts(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<PriceAlertRecord>> {
+    sqlx::query_as(&format!(
+        "UPDATE price_alerts SET reported = true \
+         WHERE session_id = $1 AND status = 'triggered' AND NOT reported \
+         RETURNING {}",
+        PRICE_ALERT_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UserRecord {
+    pub address: String,
+    pub name: Option<String>,
+    /// 'viewer', 'operator', or 'admin'
+    pub role: String,
+    pub policy: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Add a user with `role`, or rename one that exists when `name` is given; an existing user
+/// keeps their role
+pub async fn save_user(pool: &Pool<Postgres>, address: &str, name: Option<&str>, role: &str) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO users (address, name, role) VALUES ($1, $2, $3) \
+         ON CONFLICT (address) DO UPDATE SET name = COALESCE(EXCLUDED.name, users.name)",
+    )
+    .bind(address)
+    .bind(name)
+    .bind(role)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn find_user(pool: &Pool<Postgres>, address: &str) -> sqlx::Result<Option<UserRecord>> {
+    sqlx::query_as("SELECT address, name, role, policy, created_at FROM users WHERE address = $1")
+        .bind(address)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn list_users(pool: &Pool<Postgres>) -> sqlx::Result<Vec<UserRecord>> {
+    sqlx::query_as("SELECT address, name, role, policy, created_at FROM users ORDER BY created_at")
+        .fetch_all(pool)
+        .await
+}
+
+/// Remove a user and their API keys; their sessions and history are kept. False if there was
+/// no such user.
+pub async fn delete_user(pool: &Pool<Postgres>, address: &str) -> sqlx::Result<bool> {
+    let result = sqlx::query("DELETE FROM users WHERE address = $1")
+        .bind(address)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_user_role(pool: &Pool<Postgres>, address: &str, role: &str) -> sqlx::Result<bool> {
+    let result = sqlx::query("UPDATE users SET role = $2 WHERE address = $1")
+        .bind(address)
         .bind(role)
-        .bind(content)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Set the transaction policy of a user's sessions; None goes back to the deployment's
+pub async fn set_user_policy(pool: &Pool<Postgres>, address: &str, policy: Option<&serde_json::Value>) -> sqlx::Result<bool> {
+    let result = sqlx::query("UPDATE users SET policy = $2 WHERE address = $1")
+        .bind(address)
+        .bind(policy)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Keep the hash of a new API key for a user
+pub async fn save_api_key(pool: &Pool<Postgres>, key_hash: &str, address: &str, label: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO api_keys (key_hash, address, label) VALUES ($1, $2, $3)")
+        .bind(key_hash)
+        .bind(address)
+        .bind(label)
         .execute(pool)
         .await?;
     Ok(())
 }
 
+/// The user an API key (by hash) acts as, noting that it was used
+pub async fn api_key_user(pool: &Pool<Postgres>, key_hash: &str) -> sqlx::Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as("UPDATE api_keys SET last_used_at = now() WHERE key_hash = $1 RETURNING address")
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(address,)| address))
+}
+
+/// Revoke every API key of a user, returning how many there were
+pub async fn delete_api_keys(pool: &Pool<Postgres>, address: &str) -> sqlx::Result<u64> {
+    let result = sqlx::query("DELETE FROM api_keys WHERE address = $1")
+        .bind(address)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Record a nonce issued for a sign-in message, usable once for `ttl_secs`, dropping the
+/// nonces that expired unused
+pub async fn save_auth_nonce(pool: &Pool<Postgres>, nonce: &str, ttl_secs: u64) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM auth_nonces WHERE expires_at <= now()").execute(pool).await?;
+    sqlx::query("INSERT INTO auth_nonces (nonce, expires_at) VALUES ($1, now() + make_interval(secs => $2))")
+        .bind(nonce)
+        .bind(ttl_secs as f64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Use up a sign-in nonce; false if it was never issued, already used, or expired
+pub async fn take_auth_nonce(pool: &Pool<Postgres>, nonce: &str) -> sqlx::Result<bool> {
+    let row: Option<(bool,)> = sqlx::query_as("DELETE FROM auth_nonces WHERE nonce = $1 RETURNING expires_at > now()")
+        .bind(nonce)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some_and(|(valid,)| valid))
+}
+
+/// Keep the hash of a sign-in token for a user until it expires, dropping expired tokens
+pub async fn save_auth_token(pool: &Pool<Postgres>, token_hash: &str, address: &str, ttl_secs: u64) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM auth_tokens WHERE expires_at <= now()").execute(pool).await?;
+    sqlx::query("INSERT INTO auth_tokens (token_hash, address, expires_at) VALUES ($1, $2, now() + make_interval(secs => $3))")
+        .bind(token_hash)
+        .bind(address)
+        .bind(ttl_secs as f64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The user a sign-in token (by hash) was issued to, while it hasn't expired
+pub async fn auth_token_user(pool: &Pool<Postgres>, token_hash: &str) -> sqlx::Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT address FROM auth_tokens WHERE token_hash = $1 AND expires_at > now()")
+        .bind(token_hash)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(address,)| address))
+}
+
+/// Reserve `value` wei under a policy's `scope` unless, with the values sent in the last 24 hours,
+/// it would exceed `max` wei. Returns the wei already sent and whether the value was reserved.
+pub async fn reserve_policy_spend(pool: &Pool<Postgres>, scope: &str, id: Uuid, value: &str, max: &str) -> sqlx::Result<(String, bool)> {
+    let mut tx = pool.begin().await?;
+    // One reservation at a time per scope, across replicas, so two sends can't both fit the limit
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('policy_spends:' || $1))")
+        .bind(scope)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM policy_spends WHERE scope = $1 AND spent_at <= now() - interval '24 hours'")
+        .bind(scope)
+        .execute(&mut *tx)
+        .await?;
+    let (spent, fits): (String, bool) = sqlx::query_as(
+        "SELECT COALESCE(SUM(value), 0)::TEXT, COALESCE(SUM(value), 0) + $2::NUMERIC <= $3::NUMERIC \
+         FROM policy_spends WHERE scope = $1",
+    )
+    .bind(scope)
+    .bind(value)
+    .bind(max)
+    .fetch_one(&mut *tx)
+    .await?;
+    if fits {
+        sqlx::query("INSERT INTO policy_spends (id, scope, value) VALUES ($1, $2, $3::NUMERIC)")
+            .bind(id)
+            .bind(scope)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok((spent, fits))
+}
+
+/// Give back a reserved value whose transaction was never sent
+pub async fn release_policy_spend(pool: &Pool<Postgres>, id: Uuid) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM policy_spends WHERE id = $1").bind(id).execute(pool).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ContactRecord {
+    pub label: String,
+    pub address: String,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Add an address book entry for `owner` (None for the CLI and Discord), or point an existing
+/// label at a new address; the address it pointed at before, if any
+pub async fn save_contact(pool: &Pool<Postgres>, owner: Option<&str>, label: &str, address: &str, note: Option<&str>) -> sqlx::Result<Option<String>> {
+    let previous = find_contact(pool, owner, label).await?.map(|contact| contact.address);
+    sqlx::query(
+        "INSERT INTO address_book (owner_address, label, address, note) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (COALESCE(owner_address, ''), lower(label)) \
+         DO UPDATE SET label = EXCLUDED.label, address = EXCLUDED.address, note = EXCLUDED.note",
+    )
+    .bind(owner)
+    .bind(label)
+    .bind(address.to_lowercase())
+    .bind(note)
+    .execute(pool)
+    .await?;
+    Ok(previous.filter(|previous| !previous.eq_ignore_ascii_case(address)))
+}
+
+/// The entry of `owner` with `label`, whatever its case
+pub async fn find_contact(pool: &Pool<Postgres>, owner: Option<&str>, label: &str) -> sqlx::Result<Option<ContactRecord>> {
+    sqlx::query_as(
+        "SELECT label, address, note, created_at FROM address_book \
+         WHERE owner_address IS NOT DISTINCT FROM $1 AND lower(label) = lower($2)",
+    )
+    .bind(owner)
+    .bind(label)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The first entry of `owner` for `address`
+pub async fn contact_for_address(pool: &Pool<Postgres>, owner: Option<&str>, address: &str) -> sqlx::Result<Option<ContactRecord>> {
+    sqlx::query_as(
+        "SELECT label, address, note, created_at FROM address_book \
+         WHERE owner_address IS NOT DISTINCT FROM $1 AND address = $2 ORDER BY id LIMIT 1",
+    )
+    .bind(owner)
+    .bind(address.to_lowercase())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_contacts(pool: &Pool<Postgres>, owner: Option<&str>) -> sqlx::Result<Vec<ContactRecord>> {
+    sqlx::query_as(
+        "SELECT label, address, note, created_at FROM address_book \
+         WHERE owner_address IS NOT DISTINCT FROM $1 ORDER BY lower(label)",
+    )
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+}
+
+/// Remove the entry of `owner` with `label`; false if there was none
+pub async fn delete_contact(pool: &Pool<Postgres>, owner: Option<&str>, label: &str) -> sqlx::Result<bool> {
+    let result = sqlx::query("DELETE FROM address_book WHERE owner_address IS NOT DISTINCT FROM $1 AND lower(label) = lower($2)")
+        .bind(owner)
+        .bind(label)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NoteRecord {
+    pub id: i64,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Keep a note for `owner` (None for the CLI and Discord)
+pub async fn save_note(pool: &Pool<Postgres>, owner: Option<&str>, content: &str) -> sqlx::Result<i64> {
+    let (id,): (i64,) = sqlx::query_as("INSERT INTO notes (owner_address, content) VALUES ($1, $2) RETURNING id")
+        .bind(owner)
+        .bind(content)
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+/// The notes of `owner`, newest first, optionally only those containing `query`
+pub async fn list_notes(pool: &Pool<Postgres>, owner: Option<&str>, query: Option<&str>) -> sqlx::Result<Vec<NoteRecord>> {
+    sqlx::query_as(
+        "SELECT id, content, created_at FROM notes \
+         WHERE owner_address IS NOT DISTINCT FROM $1 AND ($2::text IS NULL OR content ILIKE '%' || $2 || '%') \
+         ORDER BY id DESC",
+    )
+    .bind(owner)
+    .bind(query)
+    .fetch_all(pool)
+    .await
+}
+
+/// Remove a note of `owner`; false if there was none
+pub async fn delete_note(pool: &Pool<Postgres>, owner: Option<&str>, id: i64) -> sqlx::Result<bool> {
+    let result = sqlx::query("DELETE FROM notes WHERE id = $1 AND owner_address IS NOT DISTINCT FROM $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReminderRecord {
+    pub id: i64,
+    pub session_id: Option<Uuid>,
+    pub message: String,
+    /// UTC
+    pub due_at: NaiveDateTime,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub fired_at: Option<NaiveDateTime>,
+}
+
+const REMINDER_COLUMNS: &str = "id, session_id, message, due_at, status, created_at, fired_at";
+
+/// Record a new active reminder of `owner`, to be shown in the session it was set in
+pub async fn save_reminder(pool: &Pool<Postgres>, session_id: Option<Uuid>, owner: Option<&str>, message: &str, due_at: NaiveDateTime) -> sqlx::Result<i64> {
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO reminders (session_id, owner_address, message, due_at) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(session_id)
+    .bind(owner)
+    .bind(message)
+    .bind(due_at)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// The reminders of `owner`, soonest first
+pub async fn list_reminders(pool: &Pool<Postgres>, owner: Option<&str>, include_inactive: bool) -> sqlx::Result<Vec<ReminderRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM reminders WHERE owner_address IS NOT DISTINCT FROM $1 AND ($2 OR status = 'active') \
+         ORDER BY due_at, id",
+        REMINDER_COLUMNS
+    ))
+    .bind(owner)
+    .bind(include_inactive)
+    .fetch_all(pool)
+    .await
+}
+
+/// Cancel an active reminder of `owner`; false if there is no such reminder
+pub async fn cancel_reminder(pool: &Pool<Postgres>, owner: Option<&str>, id: i64) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE reminders SET status = 'cancelled' \
+         WHERE id = $1 AND status = 'active' AND owner_address IS NOT DISTINCT FROM $2",
+    )
+    .bind(id)
+    .bind(owner)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Active reminders due by `now` (UTC)
+pub async fn due_reminders(pool: &Pool<Postgres>, now: NaiveDateTime) -> sqlx::Result<Vec<ReminderRecord>> {
+    sqlx::query_as(&format!("SELECT {} FROM reminders WHERE status = 'active' AND due_at <= $1 ORDER BY due_at, id", REMINDER_COLUMNS))
+        .bind(now)
+        .fetch_all(pool)
+        .await
+}
+
+/// Mark a reminder as fired; false if it was cancelled or fired in the meantime
+pub async fn fire_reminder(pool: &Pool<Postgres>, id: i64, fired_at: NaiveDateTime) -> sqlx::Result<bool> {
+    let result = sqlx::query("UPDATE reminders SET status = 'fired', fired_at = $2 WHERE id = $1 AND status = 'active'")
+        .bind(id)
+        .bind(fired_at)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// A session's reminders that fired since they were last reported, marking them as reported
+pub async fn take_fired_reminders(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<ReminderRecord>> {
+    sqlx::query_as(&format!(
+        "UPDATE reminders SET reported = true \
+         WHERE session_id = $1 AND status = 'fired' AND NOT reported \
+         RETURNING {}",
+        REMINDER_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// A buy, sell, or transfer of one of the user's accounts
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PositionEntry {
+    pub chain_id: i64,
+    /// Lowercase hex
+    pub account: String,
+    pub token: String,
+    /// Lowercase hex of the ERC-20 contract; None for ETH
+    pub token_address: Option<String>,
+    /// `buy`, `sell`, `transfer_in`, or `transfer_out`
+    pub kind: String,
+    /// In the token's smallest unit, as a decimal string
+    pub amount: String,
+    pub decimals: i32,
+    /// USD per whole token when it happened, as a decimal string
+    pub price_usd: Option<String>,
+    pub tx_hash: Option<String>,
+    /// `swap`, `send`, or `import`
+    pub source: String,
+    /// UTC
+    pub occurred_at: NaiveDateTime,
+}
+
+/// Record an entry of `owner`; false if its transaction was already recorded
+pub async fn save_position_entry(pool: &Pool<Postgres>, owner: Option<&str>, entry: &PositionEntry) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "INSERT INTO positions (owner_address, chain_id, account, token, token_address, kind, amount, decimals, price_usd, tx_hash, source, occurred_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) ON CONFLICT DO NOTHING",
+    )
+    .bind(owner)
+    .bind(entry.chain_id)
+    .bind(&entry.account)
+    .bind(&entry.token)
+    .bind(&entry.token_address)
+    .bind(&entry.kind)
+    .bind(&entry.amount)
+    .bind(entry.decimals)
+    .bind(&entry.price_usd)
+    .bind(&entry.tx_hash)
+    .bind(&entry.source)
+    .bind(entry.occurred_at)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The entries of `owner` on a chain, optionally of one account, oldest first
+pub async fn list_position_entries(pool: &Pool<Postgres>, owner: Option<&str>, chain_id: i64, account: Option<&str>) -> sqlx::Result<Vec<PositionEntry>> {
+    sqlx::query_as(
+        "SELECT chain_id, account, token, token_address, kind, amount, decimals, price_usd, tx_hash, source, occurred_at FROM positions \
+         WHERE owner_address IS NOT DISTINCT FROM $1 AND chain_id = $2 AND ($3::text IS NULL OR account = $3) \
+         ORDER BY occurred_at, id",
+    )
+    .bind(owner)
+    .bind(chain_id)
+    .bind(account)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub owner_address: Option<String>,
+    pub summary: Option<String>,
+    pub summarized_messages: i64,
+}
+
+pub async fn find_session(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Option<SessionInfo>> {
+    sqlx::query_as("SELECT id, created_at, owner_address, summary, summarized_messages FROM sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Every tool call of a session, oldest first
+pub async fn session_tool_calls(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<ToolCallRecord>> {
+    sqlx::query_as(
+        "SELECT id, session_id, message_id, tool_name, args, result, duration_ms, success, created_at \
+         FROM tool_calls WHERE session_id = $1 ORDER BY id",
+    )
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Every transaction sent in a session, oldest first
+pub async fn session_transactions(pool: &Pool<Postgres>, session_id: Uuid) -> sqlx::Result<Vec<TransactionRecord>> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM transactions WHERE session_id = $1 ORDER BY submitted_at",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Store a session exported from another deployment under `session_id`, all or nothing.
+/// Transactions already known here (by hash) are left as they are.
+pub async fn import_session(
+    pool: &Pool<Postgres>,
+    session_id: Uuid,
+    session: &SessionInfo,
+    messages: &[MessageRecord],
+    tool_calls: &[ToolCallRecord],
+    transactions: &[TransactionRecord],
+) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO sessions (id, created_at, owner_address, summary, summarized_messages) \
+         VALUES ($1, COALESCE($2, now()), $3, $4, $5)",
+    )
+    .bind(session_id)
+    .bind(session.created_at)
+    .bind(&session.owner_address)
+    .bind(&session.summary)
+    .bind(session.summarized_messages)
+    .execute(&mut *tx)
+    .await?;
+    // Messages get new ids here, which the tool calls are linked to instead of their old ones
+    let mut message_ids = std::collections::HashMap::new();
+    for message in messages {
+        let (id,): (i32,) = sqlx::query_as(
+            "INSERT INTO messages (session_id, role, content, model, input_tokens, output_tokens, latency_ms, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, now())) RETURNING id",
+        )
+        .bind(session_id)
+        .bind(&message.role)
+        .bind(redact(&message.content).as_ref())
+        .bind(&message.model)
+        .bind(message.input_tokens)
+        .bind(message.output_tokens)
+        .bind(message.latency_ms)
+        .bind(message.created_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        message_ids.insert(message.id, id);
+    }
+    for call in tool_calls {
+        sqlx::query(
+            "INSERT INTO tool_calls (session_id, message_id, tool_name, args, result, duration_ms, success, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(session_id)
+        .bind(call.message_id.and_then(|id| message_ids.get(&id)))
+        .bind(&call.tool_name)
+        .bind(redact_json(&call.args))
+        .bind(redact(&call.result).as_ref())
+        .bind(call.duration_ms)
+        .bind(call.success)
+        .bind(call.created_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    // Their outcomes were reported where they were sent
+    for transaction in transactions {
+        sqlx::query(
+            "INSERT INTO transactions (tx_hash, session_id, chain_id, from_address, to_address, value, nonce, data, \
+             description, status, block_number, reported, submitted_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, true, $12) ON CONFLICT (tx_hash) DO NOTHING",
+        )
+        .bind(&transaction.tx_hash)
+        .bind(session_id)
+        .bind(transaction.chain_id)
+        .bind(&transaction.from_address)
+        .bind(&transaction.to_address)
+        .bind(&transaction.value)
+        .bind(transaction.nonce)
+        .bind(&transaction.data)
+        .bind(&transaction.description)
+        .bind(&transaction.status)
+        .bind(transaction.block_number)
+        .bind(transaction.submitted_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::database;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn searches_conversation_history() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        // A word no other test stores, so that only these messages match
+        let word = format!("zebra{}", Uuid::new_v4().simple());
+        let owner = format!("{:?}", ethers::types::Address::random());
+        let (mine, theirs) = (Uuid::new_v4(), Uuid::new_v4());
+        create_owned_session(&pool, mine, &owner).await.unwrap();
+        create_session(&pool, theirs).await.unwrap();
+        save_message(&pool, mine, "user", &format!("Bridge the {} tokens to Base when fees are low", word), "claude-test", None).await.unwrap();
+        save_message(&pool, theirs, "assistant", &format!("Bridged {} tokens yesterday", word), "claude-test", None).await.unwrap();
+
+        let hits = search_messages(&pool, &word, None, 10).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().any(|hit| hit.snippet.contains(&format!("**{}**", word))), "{:?}", hits);
+        let hits = search_messages(&pool, &format!("{} -yesterday", word), Some(&owner), 10).await.unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.session_id).collect::<Vec<_>>(), [mine]);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::config::DexConfig;
+use ethers::prelude::*;
+use ethers::utils::{format_units, parse_units};
+use std::str::FromStr;
+use std::sync::Arc;
+
+abigen!(
+    QuoterV2,
+    r#"[
+        struct QuoteExactInputSingleParams { address tokenIn; address tokenOut; uint256 amountIn; uint24 fee; uint160 sqrtPriceLimitX96; }
+        function quoteExactInputSingle(QuoteExactInputSingleParams params) external returns (uint256 amountOut, uint160 sqrtPriceX96After, uint32 initializedTicksCrossed, uint256 gasEstimate)
+    ]"#
+);
+
+abigen!(
+    UniswapV3Factory,
+    r#"[
+        function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+    ]"#
+);
+
+abigen!(
+    UniswapV3Pool,
+    r#"[
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)
+    ]"#
+);
+
+abigen!(
+    SwapRouter02,
+    r#"[
+        struct ExactInputSingleParams { address tokenIn; address tokenOut; uint24 fee; address recipient; uint256 amountIn; uint256 amountOutMinimum; uint160 sqrtPriceLimitX96; }
+        function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut)
+        function unwrapWETH9(uint256 amountMinimum, address recipient) external payable
+        function multicall(bytes[] data) external payable returns (bytes[] results)
+    ]"#
+);
+
+abigen!(
+    Erc20,
+    r#"[
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function approve(address spender, uint256 amount) external returns (bool)
+    ]"#
+);
+
+// SwapRouter02 keeps output sent to this address in the router, e.g. to unwrap WETH afterwards
+const ROUTER_ADDRESS_THIS: u64 = 2;
+
+/// A token taking part in a swap. Native ETH is swapped as WETH and (un)wrapped by the router.
+#[derive(Debug, Clone)]
+pub struct SwapToken {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    pub native: bool,
+}
+
+/// An exact-input quote from the Uniswap V3 quoter
+#[derive(Debug, Clone)]
+pub struct SwapQuote {
+    pub token_in: SwapToken,
+    pub token_out: SwapToken,
+    pub fee: u32,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Lowest output accepted at the requested slippage tolerance
+    pub min_amount_out: U256,
+    pub slippage_bps: u32,
+    /// How far the execution price is below the pool's current price, in percent (fee excluded)
+    pub price_impact: Option<f64>,
+    pub gas_estimate: U256,
+}
+
+fn parse_address(value: &str, what: &str) -> anyhow::Result<Address> {
+    Address::from_str(value).map_err(|_| anyhow::anyhow!("Invalid {} address in the [dex] config: {}", what, value))
+}
+
+/// Resolve a token given as `ETH`, a symbol from `[dex.tokens]`, or an ERC-20 address
+pub async fn resolve_token<M: Middleware + 'static>(client: Arc<M>, config: &DexConfig, input: &str) -> anyhow::Result<SwapToken> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("ETH") {
+        return Ok(SwapToken { address: parse_address(&config.weth, "WETH")?, symbol: "ETH".to_string(), decimals: 18, native: true });
+    }
+    let address = match config.tokens.iter().find(|(symbol, _)| symbol.eq_ignore_ascii_case(input)) {
+        Some((_, address)) => parse_address(address, input)?,
+        None => Address::from_str(input)
+            .map_err(|_| anyhow::anyhow!("Unknown token {}; use ETH, a contract address, or a symbol from [dex.tokens]", input))?,
+    };
+    let token = Erc20::new(address, client);
+    let decimals = token.decimals().call().await
+        .map_err(|e| anyhow::anyhow!("{:?} does not look like an ERC-20 token: {}", address, e))?;
+    let symbol = token.symbol().call().await.unwrap_or_else(|_| format!("{:?}", address));
+    Ok(SwapToken { address, symbol, decimals, native: false })
+}
+
+/// Parse a human amount such as `"1.5"` in the token's own decimals
+pub fn parse_token_amount(amount: &str, token: &SwapToken) -> anyhow::Result<U256> {
+    let amount = amount.trim();
+    if amount.is_empty() {
+        return Err(anyhow::anyhow!("Amount is required"));
+    }
+    let value: U256 = parse_units(amount, token.decimals as u32)
+        .map_err(|e| anyhow::anyhow!("Invalid amount {}: {}", amount, e))?
+        .into();
+    if value.is_zero() {
+        return Err(anyhow::anyhow!("Amount must be greater than zero"));
+    }
+    Ok(value)
+}
+
+pub fn format_token_amount(amount: U256, token: &SwapToken) -> String {
+    let value = format_units(amount, token.decimals as u32).unwrap_or_else(|_| amount.to_string());
+    let value = if value.contains('.') { value.trim_end_matches('0').trim_end_matches('.').to_string() } else { value };
+    format!("{} {}", value, token.symbol)
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+// Price impact from the pool's current sqrt price, in raw units of token_out per token_in
+async fn price_impact<M: Middleware + 'static>(client: Arc<M>, config: &DexConfig, token_in: Address, token_out: Address, fee: u32, amount_in: U256, amount_out: U256) -> anyhow::Result<f64> {
+    let factory = UniswapV3Factory::new(parse_address(&config.factory, "factory")?, client.clone());
+    let pool = factory.get_pool(token_in, token_out, fee).call().await?;
+    if pool.is_zero() {
+        return Err(anyhow::anyhow!("No pool for this pair at the {} fee tier", fee));
+    }
+    let (sqrt_price_x96, ..) = UniswapV3Pool::new(pool, client).slot_0().call().await?;
+    let sqrt_price = u256_to_f64(sqrt_price_x96) / 2f64.powi(96);
+    // The pool price is token1 per token0, and token0 is the lower address
+    let pool_price = if token_in < token_out { sqrt_price * sqrt_price } else { 1.0 / (sqrt_price * sqrt_price) };
+    let fee_factor = 1.0 - fee as f64 / 1_000_000.0;
+    let execution_price = u256_to_f64(amount_out) / u256_to_f64(amount_in);
+    Ok(((1.0 - execution_price / (pool_price * fee_factor)) * 100.0).max(0.0))
+}
+
+/// Quote swapping exactly `amount_in` of `token_in` for `token_out` in the pool at `fee`
+pub async fn quote<M: Middleware + 'static>(
+    client: Arc<M>,
+    config: &DexConfig,
+    token_in: SwapToken,
+    token_out: SwapToken,
+    amount_in: U256,
+    fee: u32,
+    slippage_bps: u32,
+) -> anyhow::Result<SwapQuote> {
+    if token_in.address == token_out.address {
+        return Err(anyhow::anyhow!("Cannot swap {} for itself", token_in.symbol));
+    }
+    if slippage_bps >= 10_000 {
+        return Err(anyhow::anyhow!("Slippage tolerance must be below 100%"));
+    }
+    let quoter = QuoterV2::new(parse_address(&config.quoter, "quoter")?, client.clone());
+    let (amount_out, _sqrt_price_after, _ticks_crossed, gas_estimate) = quoter
+        .quote_exact_input_single(QuoteExactInputSingleParams {
+            token_in: token_in.address,
+            token_out: token_out.address,
+            amount_in,
+            fee,
+            sqrt_price_limit_x96: U256::zero(),
+        })
+        .call()
+        .await
+        .map_err(|e| anyhow::anyhow!("Quoter call failed (is there a {} / {} pool at the {} fee tier?): {}", token_in.symbol, token_out.symbol, fee, e))?;
+
+    let impact = match price_impact(client, config, token_in.address, token_out.address, fee, amount_in, amount_out).await {
+        Ok(impact) => Some(impact),
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not compute price impact");
+            None
+        },
+    };
+    let min_amount_out = amount_out * U256::from(10_000 - slippage_bps) / U256::from(10_000);
+    Ok(SwapQuote { token_in, token_out, fee, amount_in, amount_out, min_amount_out, slippage_bps, price_impact: impact, gas_estimate })
+}
+
+pub fn describe_quote(quote: &SwapQuote) -> String {
+    let impact = quote.price_impact
+        .map(|impact| format!("{:.2}%", impact))
+        .unwrap_or_else(|| "unknown".to_string());
+    format!("Swap {} for {} (Uniswap V3, {}% fee tier)\n\
+            Expected Output: {}\n\
+            Minimum Output: {} (slippage tolerance {}%)\n\
+            Price Impact: {}\n\
+            Quoter Gas Estimate: {}",
+            format_token_amount(quote.amount_in, &quote.token_in),
+            quote.token_out.symbol,
+            quote.fee as f64 / 10_000.0,
+            format_token_amount(quote.amount_out, &quote.token_out),
+            format_token_amount(quote.min_amount_out, &quote.token_out),
+            quote.slippage_bps as f64 / 100.0,
+            impact,
+            quote.gas_estimate)
+}
+
+/// Router calldata and ETH value for executing a quote, paying out to `recipient`.
+/// ETH input is sent as value and wrapped by the router; ETH output is unwrapped in the same call.
+pub fn swap_call<M: Middleware + 'static>(client: Arc<M>, config: &DexConfig, quote: &SwapQuote, recipient: Address) -> anyhow::Result<(Address, Bytes, U256)> {
+    let router_address = parse_address(&config.router, "router")?;
+    let router = SwapRouter02::new(router_address, client);
+    let swap_recipient = if quote.token_out.native { Address::from_low_u64_be(ROUTER_ADDRESS_THIS) } else { recipient };
+    let swap = router.exact_input_single(ExactInputSingleParams {
+        token_in: quote.token_in.address,
+        token_out: quote.token_out.address,
+        fee: quote.fee,
+        recipient: swap_recipient,
+        amount_in: quote.amount_in,
+        amount_out_minimum: quote.min_amount_out,
+        sqrt_price_limit_x96: U256::zero(),
+    });
+
+    let data = if quote.token_out.native {
+        let swap_data = swap.calldata().unwrap_or_default();
+        let unwrap_data = router.unwrap_weth9(quote.min_amount_out, recipient).calldata().unwrap_or_default();
+        router.multicall(vec![swap_data, unwrap_data]).calldata().unwrap_or_default()
+    } else {
+        swap.calldata().unwrap_or_default()
+    };
+    let value = if quote.token_in.native { quote.amount_in } else { U256::zero() };
+    Ok((router_address, data, value))
+}
+
+/// Calldata approving the router to spend `amount` of an ERC-20, or None when the
+/// current allowance already covers it (or the input is native ETH)
+pub async fn approval_call<M: Middleware + 'static>(client: Arc<M>, config: &DexConfig, token: &SwapToken, owner: Address, amount: U256) -> anyhow::Result<Option<Bytes>> {
+    if token.native {
+        return Ok(None);
+    }
+    let router = parse_address(&config.router, "router")?;
+    let erc20 = Erc20::new(token.address, client);
+    let allowance = erc20.allowance(owner, router).call().await?;
+    if allowance >= amount {
+        return Ok(None);
+    }
+    Ok(erc20.approve(router, amount).calldata())
+}
@@ -0,0 +1,188 @@
+use crate::alerts;
+use crate::anthropic::{call_anthropic_with_tools, model_for};
+use crate::config::Config;
+use crate::memory;
+use crate::conversation::Conversation;
+use crate::db::{get_or_create_channel_session, save_message};
+use crate::gas;
+use crate::personality::LivePersonality;
+use crate::policy::PolicyEngine;
+use crate::reminders;
+use crate::tools::ToolContext;
+use crate::tracker;
+use crate::usage::TurnMeter;
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::model::gateway::Ready;
+use serenity::prelude::*;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+// Number of stored messages replayed as context for each reply
+const HISTORY_LIMIT: i64 = 20;
+// Discord rejects messages longer than 2000 characters
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+struct Handler {
+    config: Arc<Config>,
+    policy: Arc<PolicyEngine>,
+    pool: Option<Pool<Postgres>>,
+    personality: LivePersonality,
+    channel_id: Option<u64>,
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    #[tracing::instrument(name = "discord_message", skip_all, fields(channel_id = %msg.channel_id))]
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.bot {
+            return;
+        }
+        
+        // Respond to mentions anywhere, or to every message in the dedicated channel
+        let in_dedicated_channel = self.channel_id == Some(msg.channel_id.get());
+        let mentioned = msg.mentions_me(&ctx.http).await.unwrap_or(false);
+        if !in_dedicated_channel && !mentioned {
+            return;
+        }
+        
+        let prompt = strip_mentions(&msg.content);
+        if prompt.is_empty() {
+            return;
+        }
+        
+        let channel_key = msg.channel_id.get().to_string();
+        let personality = self.personality.current();
+        let model = model_for(&self.config.anthropic, Some(&personality));
+        let _typing = msg.channel_id.start_typing(&ctx.http);
+        
+        // Each channel maps to its own session; replay its history so the conversation has context
+        let mut conversation = Conversation::default();
+        let mut session_id = None;
+        let mut message_id = None;
+        if let Some(pool) = &self.pool {
+            match get_or_create_channel_session(pool, &channel_key).await {
+                Ok(id) => session_id = Some(id),
+                Err(e) => tracing::error!(error = %e, "Failed to load channel session"),
+            }
+        }
+        if let (Some(pool), Some(session_id)) = (&self.pool, session_id) {
+            match Conversation::load(pool, session_id, HISTORY_LIMIT).await {
+                Ok(loaded) => conversation = loaded,
+                Err(e) => tracing::error!(error = %e, "Failed to load channel history"),
+            }
+            
+            match save_message(pool, session_id, "user", &prompt, &model, None).await {
+                Ok(id) => message_id = Some(id),
+                Err(e) => tracing::error!(error = %e, "Failed to save user message"),
+            }
+            if let Err(e) = memory::remember(pool, &self.config.memory, session_id, "user", &prompt).await {
+                tracing::warn!(error = %e, "Failed to store user message in memory");
+            }
+        }
+        
+        let mut tool_context = ToolContext::new(self.config.clone(), self.policy.clone(), self.pool.clone(), session_id);
+        tool_context.message_id = message_id;
+        // Anyone in the channel can mention the bot, so tool calls are limited to the author's role
+        tool_context.role = Some(self.config.discord.role_of(msg.author.id.get()));
+        if let Err(e) = conversation.compact(&tool_context).await {
+            tracing::warn!(error = %e, "Failed to compact channel history");
+        }
+        let meter = TurnMeter::start(&tool_context);
+        let reply = match call_anthropic_with_tools(&self.config.anthropic, &prompt, &[], Some(&personality), &conversation, &tool_context).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                tracing::error!(error = %e, "Error generating Discord reply");
+                format!("Sorry, something went wrong: {}", e)
+            }
+        };
+        
+        if let (Some(pool), Some(session_id)) = (&self.pool, session_id) {
+            if let Err(e) = save_message(pool, session_id, "assistant", &reply, &model, Some(meter.stats(&tool_context))).await {
+                tracing::error!(error = %e, "Failed to save assistant message");
+            }
+            if let Err(e) = memory::remember(pool, &self.config.memory, session_id, "assistant", &reply).await {
+                tracing::warn!(error = %e, "Failed to store assistant message in memory");
+            }
+        }
+        
+        // Report the outcome of earlier transactions, alerts, and reminders from this channel ahead of the reply
+        let mut reply = reply;
+        if let Some(session_id) = session_id {
+            let mut updates = tracker::take_updates(self.pool.as_ref(), session_id).await;
+            updates.extend(gas::take_updates(session_id));
+            updates.extend(alerts::take_updates(self.pool.as_ref(), session_id).await);
+            updates.extend(reminders::take_updates(self.pool.as_ref(), session_id).await);
+            if !updates.is_empty() {
+                reply = format!("{}\n\n{}", updates.join("\n"), reply);
+            }
+        }
+        
+        for chunk in split_message(&reply, DISCORD_MESSAGE_LIMIT) {
+            if let Err(e) = msg.channel_id.say(&ctx.http, chunk).await {
+                tracing::error!(error = %e, "Failed to send Discord message");
+                break;
+            }
+        }
+    }
+    
+    async fn ready(&self, _ctx: Context, ready: Ready) {
+        tracing::info!(persona = %self.personality.current().name, user = %ready.user.name, "Connected to Discord");
+    }
+}
+
+// Remove user/role mention tokens such as <@123> or <@!123> from the message text
+fn strip_mentions(content: &str) -> String {
+    let mention_pattern = regex::Regex::new(r"<@[!&]?\d+>").unwrap();
+    mention_pattern.replace_all(content, "").trim().to_string()
+}
+
+// Split a reply into chunks that fit Discord's message size limit, preferring line breaks
+fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    
+    for line in text.split_inclusive('\n') {
+        if !current.is_empty() && current.chars().count() + line.chars().count() > limit {
+            chunks.push(std::mem::take(&mut current));
+        }
+        // Hard-wrap lines that are longer than the limit on their own
+        for ch in line.chars() {
+            if current.chars().count() == limit {
+                chunks.push(std::mem::take(&mut current));
+            }
+            current.push(ch);
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Connect to the Discord gateway and answer messages until the process is stopped.
+///
+/// Requires `discord.token` (`DISCORD_TOKEN`); `discord.channel_id` (`DISCORD_CHANNEL_ID`)
+/// optionally names a channel where the bot answers every message instead of only mentions.
+pub async fn run(
+    config: Arc<Config>,
+    policy: Arc<PolicyEngine>,
+    pool: Option<Pool<Postgres>>,
+    personality: LivePersonality,
+) -> anyhow::Result<()> {
+    let token = config.discord.token.clone()
+        .ok_or_else(|| anyhow::anyhow!("DISCORD_TOKEN must be set to run the Discord bot"))?;
+    let channel_id = config.discord.channel_id;
+    
+    let intents = GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT;
+    
+    let handler = Handler { config, policy, pool, personality, channel_id };
+    let mut client = Client::builder(&token, intents)
+        .event_handler(handler)
+        .await?;
+    
+    client.start().await?;
+    Ok(())
+}
@@ -0,0 +1,216 @@
+// Saved Dune Analytics queries (`dune_query` tool), run through the Dune API so the agent can
+// answer analytics questions (volumes, holders, flows) without indexing anything itself. A
+// query is executed with its parameters, polled until it finishes, and its result table is
+// summarized; queries still running when `wait_secs` is up can be picked up later by
+// execution ID.
+
+use crate::config::DuneConfig;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+
+// Longest cell shown in a summary
+const MAX_CELL_LEN: usize = 48;
+
+/// Rows of a finished execution
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<serde_json::Map<String, serde_json::Value>>,
+    /// Rows in the whole result, of which `rows` are the first
+    pub total_rows: u64,
+    pub ended_at: Option<String>,
+}
+
+/// How waiting for an execution ended
+pub enum Outcome {
+    Finished(QueryResult),
+    /// Still running after `wait_secs`
+    Running(String),
+}
+
+fn api_key(config: &DuneConfig) -> anyhow::Result<&str> {
+    config.api_key.as_deref()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No Dune API key; set api_key under [dune] or DUNE_API_KEY"))
+}
+
+async fn request(config: &DuneConfig, builder: reqwest::RequestBuilder) -> anyhow::Result<serde_json::Value> {
+    let response = builder.header("X-Dune-API-Key", api_key(config)?).send().await?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    if !status.is_success() {
+        let message = body["error"].as_str().map(str::to_string).unwrap_or_else(|| body.to_string());
+        return Err(anyhow::anyhow!("Dune API returned {}: {}", status, message));
+    }
+    Ok(body)
+}
+
+fn url(config: &DuneConfig, path: &str) -> String {
+    format!("{}{}", config.api_url.trim_end_matches('/'), path)
+}
+
+/// A query ID, or the name it is saved under in `[dune.queries]`
+pub fn query_id(config: &DuneConfig, query: &str) -> anyhow::Result<u64> {
+    let query = query.trim();
+    if let Ok(id) = query.parse() {
+        return Ok(id);
+    }
+    config.queries.get(query).copied().ok_or_else(|| {
+        let known: Vec<&str> = config.queries.keys().map(String::as_str).collect();
+        match known.is_empty() {
+            true => anyhow::anyhow!("'{}' is not a query ID, and no queries are named under [dune.queries]", query),
+            false => anyhow::anyhow!("Unknown query '{}' (named queries: {})", query, known.join(", ")),
+        }
+    })
+}
+
+/// Start an execution of a saved query and return its execution ID
+pub async fn execute(config: &DuneConfig, query_id: u64, parameters: &serde_json::Map<String, serde_json::Value>) -> anyhow::Result<String> {
+    let body = serde_json::json!({ "query_parameters": parameters, "performance": config.performance });
+    let response = request(config, Client::new().post(url(config, &format!("/query/{}/execute", query_id))).json(&body)).await?;
+    response["execution_id"].as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Dune returned no execution ID"))
+}
+
+fn parse_result(body: &serde_json::Value) -> QueryResult {
+    let result = &body["result"];
+    let rows: Vec<_> = result["rows"].as_array().into_iter().flatten()
+        .filter_map(|row| row.as_object().cloned())
+        .collect();
+    let columns = match result["metadata"]["column_names"].as_array() {
+        Some(names) => names.iter().filter_map(|name| name.as_str().map(str::to_string)).collect(),
+        None => rows.first().map(|row| row.keys().cloned().collect()).unwrap_or_default(),
+    };
+    QueryResult {
+        total_rows: result["metadata"]["total_row_count"].as_u64().unwrap_or(rows.len() as u64),
+        columns,
+        rows,
+        ended_at: body["execution_ended_at"].as_str().map(str::to_string),
+    }
+}
+
+/// Wait for an execution to finish, then fetch its first `max_rows` rows
+pub async fn results(config: &DuneConfig, execution_id: &str) -> anyhow::Result<Outcome> {
+    let deadline = Instant::now() + Duration::from_secs(config.wait_secs);
+    loop {
+        let status = request(config, Client::new().get(url(config, &format!("/execution/{}/status", execution_id)))).await?;
+        match status["state"].as_str().unwrap_or_default() {
+            "QUERY_STATE_COMPLETED" => break,
+            "QUERY_STATE_FAILED" | "QUERY_STATE_CANCELLED" | "QUERY_STATE_EXPIRED" => {
+                let reason = status["error"]["message"].as_str().unwrap_or("no reason given");
+                return Err(anyhow::anyhow!("Execution {} ended in {}: {}", execution_id, status["state"], reason));
+            },
+            _ if Instant::now() >= deadline => return Ok(Outcome::Running(execution_id.to_string())),
+            _ => tokio::time::sleep(Duration::from_secs(config.poll_interval_secs)).await,
+        }
+    }
+    let request_url = url(config, &format!("/execution/{}/results", execution_id));
+    let body = request(config, Client::new().get(request_url).query(&[("limit", config.max_rows)])).await?;
+    Ok(Outcome::Finished(parse_result(&body)))
+}
+
+/// The last stored result of a query, without running it again
+pub async fn latest(config: &DuneConfig, query_id: u64) -> anyhow::Result<QueryResult> {
+    let request_url = url(config, &format!("/query/{}/results", query_id));
+    let body = request(config, Client::new().get(request_url).query(&[("limit", config.max_rows)])).await?;
+    Ok(parse_result(&body))
+}
+
+fn cell(value: Option<&serde_json::Value>) -> String {
+    let text = match value {
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    };
+    let text = text.replace('|', "\\|").replace('\n', " ");
+    match text.chars().count() > MAX_CELL_LEN {
+        true => format!("{}…", text.chars().take(MAX_CELL_LEN - 1).collect::<String>()),
+        false => text,
+    }
+}
+
+/// The result as a Markdown table, with how many rows it has in all
+pub fn summarize(query_id: u64, result: &QueryResult) -> String {
+    let mut lines = vec![format!(
+        "Dune query {}: {} row{}, {} column{}{}",
+        query_id,
+        result.total_rows, if result.total_rows == 1 { "" } else { "s" },
+        result.columns.len(), if result.columns.len() == 1 { "" } else { "s" },
+        result.ended_at.as_ref().map(|ended| format!(" (executed {})", ended)).unwrap_or_default(),
+    )];
+    if result.rows.is_empty() {
+        return lines.join("\n");
+    }
+    lines.push(String::new());
+    lines.push(format!("| {} |", result.columns.join(" | ")));
+    lines.push(format!("|{}|", vec!["---"; result.columns.len()].join("|")));
+    for row in &result.rows {
+        let cells: Vec<String> = result.columns.iter().map(|column| cell(row.get(column))).collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+    }
+    if result.total_rows > result.rows.len() as u64 {
+        lines.push(format!("\n{} more rows not shown", result.total_rows - result.rows.len() as u64));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn runs_saved_dune_queries_and_summarizes_their_results() {
+        use axum::extract::{Json as Body, Path};
+        use axum::http::HeaderMap;
+        use axum::routing::{get, post};
+
+        // A Dune API whose execution is pending at the first status check
+        let checks = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = checks.clone();
+        let rows = json!({
+            "execution_ended_at": "2025-01-01T00:00:00Z",
+            "result": {
+                "rows": [{ "day": "2025-01-01", "volume": 1250.5 }, { "day": "2025-01-02", "volume": null }],
+                "metadata": { "column_names": ["day", "volume"], "total_row_count": 30 }
+            }
+        });
+        let stored = rows.clone();
+        let api = axum::Router::new()
+            .route("/query/{id}/execute", post(|Path(id): Path<u64>, headers: HeaderMap, Body(body): Body<serde_json::Value>| async move {
+                assert_eq!(headers["x-dune-api-key"], "dune-key");
+                axum::Json(json!({ "execution_id": format!("exec-{}-{}", id, body["query_parameters"]["days"]) }))
+            }))
+            .route("/execution/{id}/status", get(move |Path(id): Path<String>| {
+                let state = if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 { "QUERY_STATE_PENDING" } else { "QUERY_STATE_COMPLETED" };
+                async move { axum::Json(json!({ "execution_id": id, "state": state })) }
+            }))
+            .route("/execution/{id}/results", get(move || async move { axum::Json(rows) }))
+            .route("/query/{id}/results", get(move || async move { axum::Json(stored) }));
+        let url = serve(api).await;
+
+        let mut config = Config::default();
+        let unconfigured = execute_tool(&context(config.clone()), "dune_query", &json!({ "query": 1 })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+
+        config.dune.api_url = url;
+        config.dune.api_key = Some("dune-key".to_string());
+        config.dune.poll_interval_secs = 0;
+        config.dune.queries.insert("dex_volume".to_string(), 1234);
+        let ctx = context(config);
+        let summary = execute_tool(&ctx, "dune_query", &json!({ "query": "dex_volume", "parameters": { "days": 30 } })).await.unwrap();
+        assert!(summary.starts_with("Dune query 1234: 30 rows, 2 columns (executed 2025-01-01T00:00:00Z)"), "{}", summary);
+        assert!(summary.contains("| day | volume |\n|---|---|\n| 2025-01-01 | 1250.5 |\n| 2025-01-02 |  |"), "{}", summary);
+        assert!(summary.ends_with("28 more rows not shown"), "{}", summary);
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let latest = execute_tool(&ctx, "dune_query", &json!({ "query": 1234, "latest": true })).await.unwrap();
+        assert!(latest.starts_with("Dune query 1234: 30 rows"), "{}", latest);
+        let unknown = execute_tool(&ctx, "dune_query", &json!({ "query": "tvl" })).await.unwrap_err();
+        assert!(unknown.message().contains("named queries: dex_volume"), "{:?}", unknown);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::config::{Config, EventFilterConfig, NotifyConfig};
+use crate::contracts::format_params;
+use crate::notify::{self, Kind, Notification};
+use crate::provider;
+use ethers::abi::token::{LenientTokenizer, Tokenizer};
+use ethers::abi::{Event, HumanReadableParser, ParamType, RawLog};
+use ethers::prelude::*;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Wait before resubscribing after the connection is lost for good
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A log matched by one of the configured filters
+#[derive(Debug, Clone)]
+pub struct ChainEvent {
+    /// Name of the filter that matched
+    pub filter: String,
+    pub description: String,
+}
+
+impl ChainEvent {
+    /// Message handed to the agent so it can react to the event. It is marked as coming from
+    /// the subscription, since it arrives in the user's turn.
+    pub fn prompt(&self) -> String {
+        format!("[On-chain event from the '{}' subscription] {}\n\
+                 This message was generated by an event subscription, not typed by the user. \
+                 Tell the user what happened and whether it needs their attention. \
+                 Do not send any transaction in response unless the user asks for it.",
+                self.filter, self.description)
+    }
+}
+
+struct Subscription {
+    name: String,
+    event: Event,
+    filter: Filter,
+}
+
+// Topic for an indexed argument. Only value types can be matched; indexed strings, bytes, and
+// arrays are stored as hashes.
+fn topic(kind: &ParamType, value: &str) -> anyhow::Result<H256> {
+    match kind {
+        ParamType::Address | ParamType::Uint(_) | ParamType::Int(_) | ParamType::Bool | ParamType::FixedBytes(_) => {
+            let token = LenientTokenizer::tokenize(kind, value)?;
+            Ok(H256::from_slice(&ethers::abi::encode(&[token])))
+        },
+        _ => Err(anyhow::anyhow!("indexed {} arguments can't be matched", kind)),
+    }
+}
+
+fn subscription(config: &EventFilterConfig) -> anyhow::Result<Subscription> {
+    let signature = config.event.trim();
+    let signature = if signature.starts_with("event ") { signature.to_string() } else { format!("event {}", signature) };
+    let event = HumanReadableParser::parse_event(&signature)
+        .map_err(|e| anyhow::anyhow!("Invalid event '{}': {}", config.event, e))?;
+
+    let mut filter = Filter::new().topic0(event.signature());
+    if let Some(address) = &config.address {
+        filter = filter.address(Address::from_str(address).map_err(|_| anyhow::anyhow!("Invalid address {}", address))?);
+    }
+    // Indexed arguments fill topics 1 to 3 in order
+    let indexed: Vec<_> = event.inputs.iter().filter(|input| input.indexed).collect();
+    for (name, value) in &config.args {
+        let position = indexed.iter()
+            .position(|input| &input.name == name)
+            .ok_or_else(|| anyhow::anyhow!("{} has no indexed argument '{}'", event.name, name))?;
+        let topic = topic(&indexed[position].kind, value)
+            .map_err(|e| anyhow::anyhow!("Invalid value for '{}': {}", name, e))?;
+        filter = match position {
+            0 => filter.topic1(topic),
+            1 => filter.topic2(topic),
+            _ => filter.topic3(topic),
+        };
+    }
+    Ok(Subscription { name: config.name.clone(), event, filter })
+}
+
+fn describe(subscription: &Subscription, log: &Log) -> String {
+    let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+    let args = match subscription.event.parse_log(raw) {
+        Ok(parsed) => format_params(&parsed.params),
+        Err(_) => "(arguments could not be decoded)".to_string(),
+    };
+    format!("{} on {:?}: {} (block {}, transaction {:?})",
+            subscription.event.name, log.address, args,
+            log.block_number.unwrap_or_default(), log.transaction_hash.unwrap_or_default())
+}
+
+// Follow one subscription until the connection can't be re-established
+async fn follow(ws_url: &str, notify_config: &NotifyConfig, subscription: &Subscription, on_event: &(dyn Fn(ChainEvent) + Send + Sync)) -> anyhow::Result<()> {
+    let provider = provider::pubsub(ws_url).await?;
+    let mut stream = provider.subscribe_logs(&subscription.filter).await?;
+    tracing::info!(filter = %subscription.name, "Subscribed to on-chain events");
+    while let Some(log) = stream.next().await {
+        // Logs removed by a reorg are resent with `removed` set
+        if log.removed == Some(true) {
+            continue;
+        }
+        let event = ChainEvent {
+            filter: subscription.name.clone(),
+            description: describe(subscription, &log),
+        };
+        let title = format!("On-chain event: {}", event.filter);
+        notify::send(notify_config, &Notification::new(Kind::Event, title, event.description.clone())).await;
+        on_event(event);
+    }
+    Ok(())
+}
+
+/// Subscribe to the logs matching `[events]` filters in the background, calling `on_event` for
+/// each one after sending it to the `[notify]` channels. A dropped WebSocket is reconnected with its subscriptions by the provider; if it
+/// can't be, the connection is opened again.
+pub fn spawn(config: Arc<Config>, on_event: impl Fn(ChainEvent) + Send + Sync + 'static) {
+    if !config.events.enabled || config.events.filters.is_empty() {
+        return;
+    }
+    // The chain's RPC endpoints are used when one of them is a WebSocket
+    let chain_ws_url = config.chain.rpc_url.clone()
+        .filter(|urls| urls.split(',').any(|url| url.trim().starts_with("ws")));
+    let Some(ws_url) = config.events.ws_url.clone().or(chain_ws_url) else {
+        tracing::warn!("Event subscriptions are enabled but no WebSocket URL is set (events.ws_url or ETH_WS_URL)");
+        return;
+    };
+    let on_event: Arc<dyn Fn(ChainEvent) + Send + Sync> = Arc::new(on_event);
+    for filter_config in &config.events.filters {
+        let subscription = match subscription(filter_config) {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                tracing::error!(filter = %filter_config.name, error = %e, "Skipping invalid event filter");
+                continue;
+            },
+        };
+        let ws_url = ws_url.clone();
+        let notify_config = config.notify.clone();
+        let on_event = on_event.clone();
+        tokio::spawn(async move {
+            loop {
+                match follow(&ws_url, &notify_config, &subscription, on_event.as_ref()).await {
+                    Ok(()) => tracing::warn!(filter = %subscription.name, "Event subscription closed; resubscribing"),
+                    Err(e) => tracing::warn!(filter = %subscription.name, error = %e, "Event subscription failed; retrying"),
+                }
+                provider::forget(&ws_url).await;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,183 @@
+// Sessions written out whole, with their messages, tool calls, and transactions: as JSON to
+// move a conversation to another deployment (`agent import`), or as Markdown to attach to an
+// incident report. Stored rows are already redacted, and imported ones are redacted again.
+
+use crate::db::{
+    all_session_messages, find_session, import_session, session_exists, session_tool_calls, session_transactions,
+    MessageRecord, SessionInfo, ToolCallRecord, TransactionRecord,
+};
+use crate::units::format_eth;
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Version of the JSON format, raised when a change would break older importers
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    Md,
+    Json,
+}
+
+/// A session with everything recorded in it
+#[derive(Serialize, Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    pub session: SessionInfo,
+    pub messages: Vec<MessageRecord>,
+    pub tool_calls: Vec<ToolCallRecord>,
+    pub transactions: Vec<TransactionRecord>,
+}
+
+pub async fn load(pool: &Pool<Postgres>, session_id: Uuid) -> anyhow::Result<SessionExport> {
+    let session = find_session(pool, session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session {} not found", session_id))?;
+    Ok(SessionExport {
+        version: EXPORT_VERSION,
+        session,
+        messages: all_session_messages(pool, session_id).await?,
+        tool_calls: session_tool_calls(pool, session_id).await?,
+        transactions: session_transactions(pool, session_id).await?,
+    })
+}
+
+/// A session in `format`
+pub async fn export(pool: &Pool<Postgres>, session_id: Uuid, format: ExportFormat) -> anyhow::Result<String> {
+    let export = load(pool, session_id).await?;
+    Ok(match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&export)?,
+        ExportFormat::Md => to_markdown(&export),
+    })
+}
+
+/// Store a session exported as JSON, under its own id unless that is taken here, and return
+/// the id it was stored under
+pub async fn import(pool: &Pool<Postgres>, json: &str) -> anyhow::Result<Uuid> {
+    let export: SessionExport = serde_json::from_str(json)
+        .map_err(|e| anyhow::anyhow!("Not a session exported as JSON: {}", e))?;
+    if export.version > EXPORT_VERSION {
+        return Err(anyhow::anyhow!("The export is format version {}; this build reads up to {}", export.version, EXPORT_VERSION));
+    }
+    let id = match session_exists(pool, export.session.id).await? {
+        true => Uuid::new_v4(),
+        false => export.session.id,
+    };
+    import_session(pool, id, &export.session, &export.messages, &export.tool_calls, &export.transactions).await?;
+    Ok(id)
+}
+
+fn to_markdown(export: &SessionExport) -> String {
+    let session = &export.session;
+    let mut out = format!("# Session {}\n\n", session.id);
+    if let Some(created_at) = session.created_at {
+        let _ = writeln!(out, "- Started: {}", created_at.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if let Some(owner) = &session.owner_address {
+        let _ = writeln!(out, "- Owner: {}", owner);
+    }
+    let _ = writeln!(out, "- {} messages, {} tool calls, {} transactions\n",
+                     export.messages.len(), export.tool_calls.len(), export.transactions.len());
+
+    if let Some(summary) = &session.summary {
+        let _ = writeln!(out, "## Summary of the first {} messages\n\n{}\n", session.summarized_messages, summary.trim());
+    }
+
+    out.push_str("## Conversation\n\n");
+    for message in &export.messages {
+        let mut heading = match message.role.as_str() {
+            "user" => "User".to_string(),
+            "assistant" => "Assistant".to_string(),
+            role => role.to_string(),
+        };
+        if message.role == "assistant" && let Some(model) = &message.model {
+            let _ = write!(heading, " ({})", model);
+        }
+        if let Some(created_at) = message.created_at {
+            let _ = write!(heading, " · {}", created_at.format("%Y-%m-%d %H:%M:%S"));
+        }
+        // Answers record what their turn took
+        if let (Some(input), Some(output), Some(latency_ms)) = (message.input_tokens, message.output_tokens, message.latency_ms) {
+            let _ = write!(heading, " · {} in / {} out tokens, {} ms", input, output, latency_ms);
+        }
+        let _ = writeln!(out, "### {}\n\n{}\n", heading, message.content.trim());
+    }
+
+    if !export.tool_calls.is_empty() {
+        out.push_str("## Tool Calls\n\n");
+        for (n, call) in export.tool_calls.iter().enumerate() {
+            let name = match call.args.get("operation").and_then(|v| v.as_str()) {
+                Some(operation) => format!("{}.{}", call.tool_name, operation),
+                None => call.tool_name.clone(),
+            };
+            let outcome = if call.success { "ok" } else { "failed" };
+            let _ = writeln!(out, "### {}. {}: {} ({} ms) · {}\n", n + 1, name, outcome, call.duration_ms,
+                             call.created_at.format("%Y-%m-%d %H:%M:%S"));
+            let args = serde_json::to_string_pretty(&call.args).unwrap_or_default();
+            let _ = writeln!(out, "```json\n{}\n```\n\n```\n{}\n```\n", args, call.result.trim());
+        }
+    }
+
+    if !export.transactions.is_empty() {
+        out.push_str("## Transactions\n\n| Hash | Chain | From | To | Value (ETH) | Status | Block | Description |\n");
+        out.push_str("|---|---|---|---|---|---|---|---|\n");
+        for tx in &export.transactions {
+            let value = U256::from_dec_str(&tx.value).map(format_eth).unwrap_or_else(|_| tx.value.clone());
+            let _ = writeln!(out, "| {} | {} | {} | {} | {} | {} | {} | {} |",
+                             tx.tx_hash, tx.chain_id, tx.from_address, tx.to_address.as_deref().unwrap_or("(deploy)"),
+                             value, tx.status, tx.block_number.map(|block| block.to_string()).unwrap_or_default(),
+                             tx.description.replace('|', "\\|"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::db::{create_session, save_message, save_tool_call, save_transaction, MessageStats};
+    use crate::test_support::database;
+    use serde_json::json;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn exports_sessions_and_imports_them_again() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let session_id = Uuid::new_v4();
+        create_session(&pool, session_id).await.unwrap();
+        let asked = save_message(&pool, session_id, "user", "Send 0.01 ETH to the treasury", "claude-test", None).await.unwrap();
+        let stats = MessageStats { input_tokens: 1200, output_tokens: 80, latency_ms: 2300 };
+        save_message(&pool, session_id, "assistant", "Sent.", "claude-test", Some(stats)).await.unwrap();
+        let args = json!({ "operation": "send", "to_address": "0x000000000000000000000000000000000000dEaD", "amount": "0.01" });
+        save_tool_call(&pool, Some(session_id), Some(asked), "eth_wallet", &args, "Sent 0.01 ETH", 412, true).await.unwrap();
+        let tx_hash = format!("{:?}", ethers::types::H256::random());
+        save_transaction(&pool, Some(session_id), 1, &tx_hash, "0x000000000000000000000000000000000000bEEF",
+                         Some("0x000000000000000000000000000000000000dEaD"), "10000000000000000", 7, None, "Send 0.01 ETH").await.unwrap();
+
+        let markdown = export(&pool, session_id, ExportFormat::Md).await.unwrap();
+        assert!(markdown.contains("### User"), "{}", markdown);
+        assert!(markdown.contains("eth_wallet.send: ok (412 ms)"));
+        assert!(markdown.contains("1200 in / 80 out tokens, 2300 ms"), "{}", markdown);
+        assert!(markdown.contains(&tx_hash) && markdown.contains("| 0.01 |"));
+
+        let json = export(&pool, session_id, ExportFormat::Json).await.unwrap();
+        // The id is taken here, so the copy gets a new one
+        let imported = import(&pool, &json).await.unwrap();
+        assert_ne!(imported, session_id);
+        let messages = all_session_messages(&pool, imported).await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["Send 0.01 ETH to the treasury", "Sent."]);
+        assert_eq!(messages[1].model.as_deref(), Some("claude-test"));
+        assert_eq!((messages[1].input_tokens, messages[1].output_tokens, messages[1].latency_ms), (Some(1200), Some(80), Some(2300)));
+        let calls = session_tool_calls(&pool, imported).await.unwrap();
+        assert_eq!(calls[0].args, args);
+        // Still linked to its message, under the copy's id
+        assert_eq!(calls[0].message_id, Some(messages[0].id));
+        // A transaction is kept once, in the session it was sent from
+        assert!(session_transactions(&pool, imported).await.unwrap().is_empty());
+        assert!(import(&pool, "{\"messages\": []}").await.is_err());
+    }
+}
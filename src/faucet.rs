@@ -0,0 +1,121 @@
+// Test ETH for wallets, so that ones the agent just generated can send right away in demos.
+// Faucet APIs under `[faucet]` serving the configured chain are asked first; when none does, or
+// they all fail, a funded keystore account sends `amount` like any other send, through the
+// transaction policy. Only testnets of the chain registry are funded, and each address at most
+// once per cooldown.
+
+use crate::chains;
+use crate::config::{FaucetApiConfig, FaucetConfig};
+use ethers::types::Address;
+use lazy_static::lazy_static;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    // When each address was last funded, by chain
+    static ref FUNDED: Mutex<HashMap<(u64, Address), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Whether the faucet funds addresses on a chain: the testnets of the chain registry
+pub fn is_testnet(chain_id: u64) -> bool {
+    chains::find(chain_id).is_some_and(|chain| chain.testnet)
+}
+
+/// Names of the chains the faucet funds addresses on
+pub fn testnets() -> Vec<&'static str> {
+    chains::CHAINS.iter().filter(|chain| chain.testnet).map(|chain| chain.name).collect()
+}
+
+/// Refuse an address funded within the cooldown, with how long is left
+pub fn check_cooldown(config: &FaucetConfig, chain_id: u64, address: Address) -> Result<(), String> {
+    let funded = FUNDED.lock().unwrap();
+    let Some(at) = funded.get(&(chain_id, address)) else { return Ok(()) };
+    let left = Duration::from_secs(config.cooldown_secs).saturating_sub(at.elapsed());
+    if left.is_zero() {
+        return Ok(());
+    }
+    Err(format!("{:?} was funded recently; try again in {} minutes", address, left.as_secs().div_ceil(60)))
+}
+
+pub fn record(chain_id: u64, address: Address) {
+    FUNDED.lock().unwrap().insert((chain_id, address), Instant::now());
+}
+
+/// Faucet APIs configured for a chain, in order
+pub fn apis(config: &FaucetConfig, chain_id: u64) -> impl Iterator<Item = &FaucetApiConfig> {
+    config.apis.iter().filter(move |api| api.chain_id == chain_id)
+}
+
+/// Host of a faucet API, for messages
+pub fn host(url: &str) -> String {
+    reqwest::Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Ask a faucet API for test ETH; what it answered, e.g. the hash of its transaction
+pub async fn request(api: &FaucetApiConfig, api_key: Option<&str>, address: Address) -> anyhow::Result<String> {
+    let mut request = Client::new().post(&api.url)
+        .timeout(REQUEST_TIMEOUT)
+        .json(&serde_json::json!({ "address": format!("{:?}", address), "chain_id": api.chain_id }));
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("{} ({})", status, body.trim()));
+    }
+    // Faucets answer in their own shapes; show the hash or message when there is one
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+    let answer = ["txHash", "tx_hash", "transactionHash", "hash", "message"].iter()
+        .find_map(|key| json[key].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| body.trim().to_string());
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn funds_testnet_wallets_from_a_faucet_api_once_per_cooldown() {
+        use crate::config::FaucetApiConfig;
+
+        // A faucet API answering with the hash of the transaction it sent
+        let api = axum::Router::new().route("/claim", axum::routing::post(|axum::Json(body): axum::Json<serde_json::Value>| async move {
+            axum::Json(json!({ "txHash": format!("0xfeed for {}", body["address"].as_str().unwrap_or_default()) }))
+        }));
+        let url = format!("{}/claim", serve(api).await);
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        config.faucet.apis = vec![FaucetApiConfig { chain_id: config.chain.chain_id, url }];
+        config.faucet.fund_new_wallets = true;
+        let dir = std::env::temp_dir().join(format!("faucet-wallets-{}", Uuid::new_v4()));
+        config.signer.keystore_dir = dir.to_string_lossy().into_owned();
+        let ctx = context(config.clone());
+
+        let created = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "generate" })).await.unwrap();
+        let address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().to_string();
+        assert!(created.contains(&format!("0xfeed for {}", address)), "{}", created);
+        let again = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "faucet", "address": address })).await.unwrap_err();
+        assert!(again.message().contains("funded recently"), "{:?}", again);
+
+        config.chain.chain_id = 1;
+        config.chain.name = "Ethereum".to_string();
+        let mainnet = execute_tool(&context(config), "eth_wallet", &json!({ "operation": "faucet", "address": format!("{:?}", ethers::types::Address::random()) })).await.unwrap_err();
+        assert!(mainnet.message().contains("only funds testnets"), "{:?}", mainnet);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
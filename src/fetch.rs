@@ -0,0 +1,228 @@
+// Web pages as readable text (`fetch_url` tool), for "summarize this governance proposal"
+// requests. HTML has its scripts, styles, navigation, headers, and footers dropped (keeping the
+// `<article>` or `<main>` element when there is one) and is turned into plain text, cut to a
+// token budget. Only public http(s) addresses are fetched: every host is resolved and refused
+// if it points at a private, loopback, or link-local address, the connection goes to the
+// checked address, and each redirect is checked again.
+
+use crate::config::FetchConfig;
+use crate::conversation::estimate_tokens;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{Client, Url};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_REDIRECTS: usize = 5;
+
+lazy_static! {
+    static ref TITLE: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    // Elements that are never part of the text
+    static ref HIDDEN: Regex = Regex::new(r"(?is)<!--.*?-->|<(script|style|noscript|svg|template|iframe)\b[^>]*>.*?</(script|style|noscript|svg|template|iframe)>").unwrap();
+    // Page furniture around the content
+    static ref BOILERPLATE: Regex = Regex::new(r"(?is)<(head|nav|header|footer|aside|form)\b[^>]*>.*?</(head|nav|header|footer|aside|form)>").unwrap();
+    static ref MAIN: Regex = Regex::new(r"(?is)<(article|main)\b[^>]*>(.*)</(article|main)>").unwrap();
+    static ref LIST_ITEM: Regex = Regex::new(r"(?i)<li\b[^>]*>").unwrap();
+    static ref BLOCK: Regex = Regex::new(r"(?i)</?(p|div|section|br|h[1-6]|ul|ol|li|tr|table|blockquote|pre|hr)\b[^>]*>").unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref ENTITY: Regex = Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|[a-zA-Z]+);").unwrap();
+}
+
+/// A fetched page
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// Where it ended up after redirects
+    pub url: String,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+// Addresses that belong to the local machine or network rather than the internet
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation()
+                // Carrier-grade NAT and "this network"
+                || (a == 100 && (64..128).contains(&b)) || a == 0)
+        },
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+                    // Unique local (fc00::/7) and link-local (fe80::/10)
+                    || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+            },
+        },
+    }
+}
+
+// The address to connect to for a URL, refused when it isn't a public one
+async fn checked_address(config: &FetchConfig, url: &Url) -> anyhow::Result<SocketAddr> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("Only http and https URLs can be fetched"));
+    }
+    let host = url.host_str().ok_or_else(|| anyhow::anyhow!("The URL has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port)).await
+        .map_err(|e| anyhow::anyhow!("Could not resolve {}: {}", host, e))?
+        .collect();
+    let address = addresses.first().copied().ok_or_else(|| anyhow::anyhow!("{} has no address", host))?;
+    if !config.allow_private_hosts && addresses.iter().any(|address| !is_public(address.ip())) {
+        return Err(anyhow::anyhow!("{} points at a private or local address, which can't be fetched", host));
+    }
+    Ok(address)
+}
+
+// The body, cut at `max_bytes`
+async fn read_body(mut response: reqwest::Response, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= max_bytes {
+            body.truncate(max_bytes);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+fn decode_entities(text: &str) -> String {
+    ENTITY.replace_all(text, |captures: &regex::Captures| {
+        let entity = &captures[1];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "rsquo" | "lsquo" => Some('\''),
+            "rdquo" | "ldquo" => Some('"'),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity.strip_prefix('#').and_then(|number| number.parse().ok()).and_then(char::from_u32),
+            },
+        };
+        decoded.map(String::from).unwrap_or_else(|| captures[0].to_string())
+    }).into_owned()
+}
+
+/// The readable text of an HTML page, one block per line
+pub fn readable_text(html: &str) -> String {
+    let html = HIDDEN.replace_all(html, " ");
+    let html = BOILERPLATE.replace_all(&html, " ");
+    let content = MAIN.captures(&html).map(|captures| captures[2].to_string()).unwrap_or_else(|| html.to_string());
+    let content = LIST_ITEM.replace_all(&content, "\n- ");
+    let content = BLOCK.replace_all(&content, "\n");
+    let text = decode_entities(&TAG.replace_all(&content, " "));
+    let lines: Vec<String> = text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty() && line != "-")
+        .collect();
+    lines.join("\n")
+}
+
+/// Fetch a page as text; HTML is reduced to its readable text
+pub async fn fetch(config: &FetchConfig, url: &str) -> anyhow::Result<Page> {
+    let mut url = Url::parse(url.trim()).map_err(|e| anyhow::anyhow!("Invalid URL {}: {}", url, e))?;
+    for _ in 0..=MAX_REDIRECTS {
+        let address = checked_address(config, &url).await?;
+        // Connecting to the checked address keeps a second DNS answer from pointing elsewhere
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .resolve(url.host_str().unwrap_or_default(), address)
+            .user_agent(concat!("onchain-agent/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let response = client.get(url.clone()).send().await?;
+        if response.status().is_redirection() {
+            let location = response.headers().get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| anyhow::anyhow!("{} redirected without a location", url))?;
+            url = url.join(location)?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("{} returned {}", url, response.status()));
+        }
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+        let is_html = content_type.contains("html");
+        if !is_html && !content_type.starts_with("text/") && !content_type.contains("json") && !content_type.contains("xml") {
+            return Err(anyhow::anyhow!("{} is {}, not a page that can be read as text", url, content_type));
+        }
+        let body = read_body(response, config.max_bytes).await?;
+        let body = String::from_utf8_lossy(&body);
+        let title = TITLE.captures(&body)
+            .map(|captures| decode_entities(captures[1].split_whitespace().collect::<Vec<_>>().join(" ").as_str()))
+            .filter(|title| is_html && !title.is_empty());
+        let text = if is_html { readable_text(&body) } else { body.trim().to_string() };
+        return Ok(Page { url: url.to_string(), title, text });
+    }
+    Err(anyhow::anyhow!("Too many redirects fetching {}", url))
+}
+
+/// The page as a tool result, cut to about `max_tokens` tokens
+pub fn format_page(page: &Page, max_tokens: usize) -> String {
+    let total = estimate_tokens(&page.text);
+    let text = match total > max_tokens {
+        true => {
+            let shown: String = page.text.chars().take(max_tokens * 4).collect();
+            format!("{}\n\n[Truncated: about {} of {} tokens shown]", shown, max_tokens, total)
+        },
+        false => page.text.clone(),
+    };
+    let title = page.title.as_ref().map(|title| format!("Title: {}\n", title)).unwrap_or_default();
+    format!("{}URL: {}\n\n{}", title, page.url, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn fetches_readable_page_text_but_not_private_addresses() {
+        use axum::response::{Html, Redirect};
+        use axum::routing::get;
+
+        let page = r#"<html><head><title>Proposal &amp; vote</title><style>body { color: red }</style></head>
+            <body><nav><a href="/">Home</a> | <a href="/forum">Forum</a></nav>
+            <article><h1>Raise the fee switch</h1><script>track()</script>
+            <p>This proposal turns on the   protocol fee.</p><ul><li>Fee: 10%</li><li>Start: next epoch</li></ul></article>
+            <footer>Copyright</footer></body></html>"#;
+        let api = axum::Router::new()
+            .route("/proposal", get(move || async move { Html(page) }))
+            .route("/short", get(|| async { Redirect::temporary("/proposal") }))
+            .route("/long", get(|| async { "word ".repeat(1000) }))
+            .route("/image", get(|| async { ([(axum::http::header::CONTENT_TYPE, "image/png")], vec![0u8; 16]) }));
+        let url = serve(api).await;
+
+        let mut config = Config::default();
+        for refused in [format!("{}/proposal", url), "http://localhost/".to_string(), "http://[::1]/".to_string(), "file:///etc/passwd".to_string()] {
+            let error = execute_tool(&context(config.clone()), "fetch_url", &json!({ "url": refused })).await.unwrap_err();
+            assert_eq!(error.kind(), "invalid_args", "{}: {:?}", refused, error);
+        }
+
+        config.fetch.allow_private_hosts = true;
+        let ctx = context(config);
+        let fetched = execute_tool(&ctx, "fetch_url", &json!({ "url": format!("{}/short", url) })).await.unwrap();
+        assert_eq!(fetched, format!("Title: Proposal & vote\nURL: {}/proposal\n\nRaise the fee switch\nThis proposal turns on the protocol fee.\n- Fee: 10%\n- Start: next epoch", url));
+
+        let long = execute_tool(&ctx, "fetch_url", &json!({ "url": format!("{}/long", url), "max_tokens": 100 })).await.unwrap();
+        assert!(long.ends_with("[Truncated: about 100 of 1250 tokens shown]"), "{}", long);
+        let image = execute_tool(&ctx, "fetch_url", &json!({ "url": format!("{}/image", url) })).await.unwrap_err();
+        assert!(image.message().contains("image/png"), "{:?}", image);
+    }
+}
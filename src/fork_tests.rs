@@ -0,0 +1,217 @@
+// End-to-end tests of the wallet and contract tools against a local Anvil fork. They run with
+// `cargo test --features anvil`, and need `anvil` (from Foundry) on the PATH and FORK_URL set
+// to an RPC endpoint of the chain to fork.
+
+use crate::config::Config;
+use crate::keystore;
+use crate::policy::{Policy, PolicyEngine};
+use crate::provider::EthProvider;
+use crate::tool_error::ToolError;
+use crate::tools::{execute_tool, get_provider, ToolContext};
+use ethers::prelude::*;
+use ethers::utils::{parse_ether, Anvil, AnvilInstance};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Every fork gets its own chain id, so that nonces tracked on one don't carry over to the next
+static NEXT_CHAIN_ID: AtomicU64 = AtomicU64::new(31_337);
+
+// Forking has to fetch state from FORK_URL before the node answers
+const STARTUP_TIMEOUT_MS: u64 = 30_000;
+
+// Wrapped ether, an ERC-20 that mints to whoever sends it ETH, on the chains usually forked
+fn weth(chain_id: u64) -> Option<Address> {
+    let address = match chain_id {
+        1 => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        11155111 => "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14",
+        _ => return None,
+    };
+    Address::from_str(address).ok()
+}
+
+struct Fork {
+    anvil: AnvilInstance,
+    ctx: ToolContext,
+    provider: EthProvider,
+    // Chain id of the forked network, which the fork itself does not keep
+    forked_chain_id: u64,
+}
+
+impl Fork {
+    // Fork FORK_URL and point the tools at the fork, with no transaction policy
+    async fn start() -> Fork {
+        let url = std::env::var("FORK_URL").expect("Set FORK_URL to an RPC endpoint of the chain to fork");
+        let forked_chain_id = Provider::<Http>::try_from(url.as_str()).expect("FORK_URL is not a valid URL")
+            .get_chainid().await
+            .expect("FORK_URL did not answer")
+            .as_u64();
+        let chain_id = NEXT_CHAIN_ID.fetch_add(1, Ordering::Relaxed);
+        let anvil = Anvil::new().fork(url).chain_id(chain_id).timeout(STARTUP_TIMEOUT_MS).spawn();
+
+        let mut config = Config::default();
+        config.chain.name = "Anvil fork".to_string();
+        config.chain.chain_id = chain_id;
+        config.chain.rpc_url = Some(anvil.endpoint());
+        config.signer.keystore_dir = std::env::temp_dir().join(format!("fork-keystore-{}", chain_id)).to_string_lossy().into_owned();
+        let provider = get_provider(&config.chain).await.expect("Could not connect to Anvil");
+        let ctx = ToolContext::new(Arc::new(config), Arc::new(PolicyEngine::new(Policy::default())), None, None);
+        Fork { anvil, ctx, provider, forked_chain_id }
+    }
+
+    // One of the accounts Anvil funds, with its key imported into the keystore
+    fn funded_account(&self) -> Address {
+        let key = hex::encode(self.anvil.keys()[0].to_bytes());
+        let name = format!("funded-{}", self.ctx.config.chain.chain_id);
+        if keystore::unlock(&self.ctx.config.signer.keystore_dir, &name).is_err() {
+            keystore::import(&self.ctx.config.signer.keystore_dir, &name, &key).expect("Could not import the funded account");
+        }
+        self.anvil.addresses()[0]
+    }
+
+    // Run a tool, failing the test if it reports an error
+    async fn tool(&self, name: &str, args: serde_json::Value) -> String {
+        execute_tool(&self.ctx, name, &args).await
+            .unwrap_or_else(|e| panic!("{} {} failed: {}", name, args, e))
+    }
+
+    // A new wallet from the tool, whose key it keeps for later sends
+    async fn generate_wallet(&self) -> Address {
+        let output = self.tool("eth_wallet", serde_json::json!({ "operation": "generate" })).await;
+        let address = output.lines()
+            .find_map(|line| line.strip_prefix("Address: "))
+            .expect("No address in the generate output");
+        Address::from_str(address).expect("Generated address doesn't parse")
+    }
+
+    async fn send_eth(&self, from: Address, to: Address, amount: &str) -> String {
+        self.tool("eth_wallet", serde_json::json!({
+            "operation": "send",
+            "from_address": format!("{:?}", from),
+            "to_address": format!("{:?}", to),
+            "amount": amount,
+        })).await
+    }
+
+    async fn balance(&self, address: Address) -> U256 {
+        self.provider.get_balance(address, None).await.expect("Could not read balance")
+    }
+
+    async fn token_balance(&self, token: Address, owner: Address) -> String {
+        self.tool("contract_call", serde_json::json!({
+            "abi": "ERC20",
+            "address": format!("{:?}", token),
+            "function": "balanceOf",
+            "args": [format!("{:?}", owner)],
+        })).await
+    }
+}
+
+#[tokio::test]
+async fn generated_wallet_starts_empty() {
+    let fork = Fork::start().await;
+    let wallet = fork.generate_wallet().await;
+
+    let output = fork.tool("eth_wallet", serde_json::json!({ "operation": "balance", "address": format!("{:?}", wallet) })).await;
+    assert!(output.contains(": 0 ETH on Anvil fork"), "{}", output);
+}
+
+#[tokio::test]
+async fn sends_eth_to_a_generated_wallet_and_back() {
+    let fork = Fork::start().await;
+    let funded = fork.funded_account();
+    let wallet = fork.generate_wallet().await;
+
+    let output = fork.send_eth(funded, wallet, "1.5").await;
+    assert!(output.contains("Transaction successfully sent 1.5 ETH"), "{}", output);
+    assert_eq!(fork.balance(wallet).await, parse_ether("1.5").unwrap());
+
+    // Signed with the key the generate operation kept
+    let output = fork.send_eth(wallet, funded, "1").await;
+    assert!(output.contains("Transaction successfully sent 1 ETH"), "{}", output);
+    let left = fork.balance(wallet).await;
+    assert!(left < parse_ether("0.5").unwrap() && left > parse_ether("0.49").unwrap(), "{} wei left", left);
+}
+
+#[tokio::test]
+async fn sends_back_to_back_without_nonce_conflicts() {
+    let fork = Fork::start().await;
+    let funded = fork.funded_account();
+    let wallet = fork.generate_wallet().await;
+
+    tokio::join!(
+        fork.send_eth(funded, wallet, "0.1"),
+        fork.send_eth(funded, wallet, "0.1"),
+        fork.send_eth(funded, wallet, "0.1"),
+    );
+    assert_eq!(fork.balance(wallet).await, parse_ether("0.3").unwrap());
+}
+
+#[tokio::test]
+async fn transfers_an_erc20() {
+    let fork = Fork::start().await;
+    let Some(weth) = weth(fork.forked_chain_id) else {
+        eprintln!("No WETH address known for chain {}; skipping", fork.forked_chain_id);
+        return;
+    };
+    let funded = fork.funded_account();
+    let wallet = fork.generate_wallet().await;
+
+    // WETH mints to whoever sends it ETH
+    fork.send_eth(funded, weth, "1").await;
+    let output = fork.token_balance(weth, funded).await;
+    assert!(output.contains("\"1000000000000000000\""), "{}", output);
+
+    let output = fork.tool("contract_send", serde_json::json!({
+        "abi": "ERC20",
+        "address": format!("{:?}", weth),
+        "function": "transfer",
+        "args": [format!("{:?}", wallet), "250000000000000000"],
+        "from_address": format!("{:?}", funded),
+    })).await;
+    assert!(output.starts_with("Transaction succeeded"), "{}", output);
+    let output = fork.token_balance(weth, wallet).await;
+    assert!(output.contains("\"250000000000000000\""), "{}", output);
+}
+
+#[tokio::test]
+async fn reverting_contract_send_is_not_broadcast() {
+    let fork = Fork::start().await;
+    let Some(weth) = weth(fork.forked_chain_id) else {
+        eprintln!("No WETH address known for chain {}; skipping", fork.forked_chain_id);
+        return;
+    };
+    let funded = fork.funded_account();
+    let nonce = fork.provider.get_transaction_count(funded, None).await.unwrap();
+
+    // The funded account holds no WETH on a fresh fork, so the transfer reverts
+    let args = serde_json::json!({
+        "abi": "ERC20",
+        "address": format!("{:?}", weth),
+        "function": "transfer",
+        "args": [format!("{:?}", Address::repeat_byte(0x11)), "1"],
+        "from_address": format!("{:?}", funded),
+    });
+    let error = execute_tool(&fork.ctx, "contract_send", &args).await.unwrap_err();
+    assert!(matches!(error, ToolError::SimulationFailed(_)), "{:?}", error);
+    assert!(error.message().contains("The transaction was NOT sent"), "{}", error);
+    assert_eq!(fork.provider.get_transaction_count(funded, None).await.unwrap(), nonce);
+}
+
+#[tokio::test]
+async fn dry_run_only_simulates() {
+    let fork = Fork::start().await;
+    let funded = fork.funded_account();
+    let wallet = fork.generate_wallet().await;
+
+    let output = fork.tool("eth_wallet", serde_json::json!({
+        "operation": "send",
+        "from_address": format!("{:?}", funded),
+        "to_address": format!("{:?}", wallet),
+        "amount": "1",
+        "dry_run": true,
+    })).await;
+    assert!(output.starts_with("Dry run; nothing was sent."), "{}", output);
+    assert!(output.contains("Simulation: succeeds"), "{}", output);
+    assert!(fork.balance(wallet).await.is_zero());
+}
@@ -0,0 +1,293 @@
+use crate::notify::{self, Kind, Notification};
+use crate::scheduler;
+use crate::tools::{execute_tool, get_provider, ToolContext};
+use crate::units;
+use chrono::{DateTime, Duration, Local};
+use ethers::prelude::*;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// Priority fee percentiles asked of eth_feeHistory, for the cheap, standard, and fast levels
+const PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+// Gas used by a plain ETH transfer, for cost estimates
+const TRANSFER_GAS: u64 = 21_000;
+
+/// Fees to offer for a transaction to be included at some speed
+#[derive(Debug, Clone)]
+pub struct FeeLevel {
+    pub name: &'static str,
+    pub max_fee: U256,
+    pub priority_fee: U256,
+}
+
+/// Current fees on an EIP-1559 chain, from recent blocks
+#[derive(Debug, Clone)]
+pub struct GasReport {
+    pub block: u64,
+    pub base_fee: U256,
+    /// Base fee of the next block, which follows from how full the latest one was
+    pub next_base_fee: U256,
+    /// Median over the recent blocks of each percentile of the priority fees paid
+    pub priority_fees: Vec<(f64, U256)>,
+    pub levels: Vec<FeeLevel>,
+    pub blocks: u64,
+}
+
+fn median(mut values: Vec<U256>) -> U256 {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied().unwrap_or_default()
+}
+
+/// Base fee of the latest block, or None on chains without EIP-1559
+pub async fn base_fee<M: Middleware>(client: &M) -> anyhow::Result<Option<U256>> {
+    let block = client.get_block(BlockNumber::Latest).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(block.and_then(|block| block.base_fee_per_gas))
+}
+
+/// Fee report over the last `blocks` blocks, or None on chains without EIP-1559
+pub async fn report<M: Middleware>(client: &M, blocks: u64) -> anyhow::Result<Option<GasReport>> {
+    let history = client.fee_history(blocks.max(1), BlockNumber::Latest, &PERCENTILES).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(report_from_history(&history))
+}
+
+// The report for a fee history asked for at PERCENTILES
+fn report_from_history(history: &FeeHistory) -> Option<GasReport> {
+    // One base fee per block plus the next block's
+    let [.., base_fee, next_base_fee] = history.base_fee_per_gas.as_slice() else { return None };
+    if next_base_fee.is_zero() {
+        return None;
+    }
+    let block_count = history.gas_used_ratio.len() as u64;
+
+    // Empty blocks report zero tips, which says nothing about what inclusion costs
+    let priority_fees: Vec<(f64, U256)> = PERCENTILES.iter().enumerate()
+        .map(|(column, percentile)| {
+            let tips: Vec<U256> = history.reward.iter()
+                .zip(&history.gas_used_ratio)
+                .filter(|(_, used)| **used > 0.0)
+                .filter_map(|(rewards, _)| rewards.get(column).copied())
+                .collect();
+            (*percentile, median(tips))
+        })
+        .collect();
+
+    // Headroom over the next base fee for it to rise while the transaction waits
+    let levels = [("cheap", 100), ("standard", 125), ("fast", 200)].iter()
+        .zip(&priority_fees)
+        .map(|((name, headroom_percent), (_, tip))| FeeLevel {
+            name,
+            max_fee: *next_base_fee * *headroom_percent / 100 + *tip,
+            priority_fee: *tip,
+        })
+        .collect();
+
+    Some(GasReport {
+        block: (history.oldest_block + block_count).as_u64().saturating_sub(1),
+        base_fee: *base_fee,
+        next_base_fee: *next_base_fee,
+        priority_fees,
+        levels,
+        blocks: block_count,
+    })
+}
+
+/// Report as text, with the cost of a plain transfer at each level (in USD too, given the
+/// native currency's price)
+pub fn format_report(chain: &str, report: &GasReport, native_usd: Option<f64>) -> String {
+    let percentiles: Vec<String> = report.priority_fees.iter()
+        .map(|(percentile, fee)| format!("p{}: {} gwei", percentile, units::format_gwei(*fee)))
+        .collect();
+    let mut lines = vec![
+        format!("Gas on {} at block {}", chain, report.block),
+        format!("Base fee: {} gwei (next block: {} gwei)", units::format_gwei(report.base_fee), units::format_gwei(report.next_base_fee)),
+        format!("Priority fees over the last {} blocks: {}", report.blocks, percentiles.join(", ")),
+        String::new(),
+        "| Level | Priority fee | Max fee | Transfer cost |".to_string(),
+        "|---|---|---|---|".to_string(),
+    ];
+    for level in &report.levels {
+        // A transaction pays the base fee plus its tip, up to the max fee
+        let cost = (report.next_base_fee + level.priority_fee).min(level.max_fee) * TRANSFER_GAS;
+        let eth = units::format_eth(cost);
+        let cost = match native_usd.and_then(|usd| eth.parse::<f64>().ok().map(|eth| eth * usd)) {
+            Some(usd) => format!("{} ETH (${:.2})", eth, usd),
+            None => format!("{} ETH", eth),
+        };
+        lines.push(format!("| {} | {} gwei | {} gwei | {} |", level.name,
+                           units::format_gwei(level.priority_fee), units::format_gwei(level.max_fee), cost));
+    }
+    lines.join("\n")
+}
+
+/// A send held back until the base fee drops below a limit
+#[derive(Debug, Clone)]
+pub struct WaitingSend {
+    pub id: u64,
+    pub session_id: Option<Uuid>,
+    pub description: String,
+    pub max_base_fee: U256,
+    pub expires_at: DateTime<Local>,
+}
+
+impl WaitingSend {
+    /// Whether the send can be made at this base fee; at the limit itself it still waits
+    pub fn can_send(&self, base_fee: U256) -> bool {
+        base_fee < self.max_base_fee
+    }
+
+    pub fn expired(&self, now: DateTime<Local>) -> bool {
+        now > self.expires_at
+    }
+}
+
+lazy_static! {
+    // Sends waiting for cheaper gas in this process
+    static ref WAITING: Mutex<Vec<WaitingSend>> = Mutex::new(Vec::new());
+    // Outcomes of waits, by the session they were started in
+    static ref UPDATES: Mutex<Vec<(Option<Uuid>, String)>> = Mutex::new(Vec::new());
+}
+
+static NEXT_WAIT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn finish(wait: &WaitingSend, outcome: String) {
+    WAITING.lock().unwrap().retain(|waiting| waiting.id != wait.id);
+    UPDATES.lock().unwrap().push((wait.session_id, outcome));
+}
+
+/// Hold a tool call back until the base fee is below `max_base_fee`, checking on the `[gas]`
+/// schedule. The call is made with `ctx` as it is now, so it goes through the policy and
+/// simulation like any other. Waits live in this process and end when it exits.
+pub fn defer(ctx: &ToolContext, tool: &str, args: serde_json::Value, max_base_fee: U256, description: &str) -> anyhow::Result<WaitingSend> {
+    let schedule = scheduler::parse_schedule(&ctx.config.gas.check_schedule)?;
+    let wait = WaitingSend {
+        id: NEXT_WAIT_ID.fetch_add(1, Ordering::Relaxed),
+        session_id: ctx.session_id,
+        description: description.to_string(),
+        max_base_fee,
+        expires_at: Local::now() + Duration::hours(ctx.config.gas.max_wait_hours as i64),
+    };
+    WAITING.lock().unwrap().push(wait.clone());
+
+    let (ctx, tool, waiting) = (ctx.clone(), tool.to_string(), wait.clone());
+    tokio::spawn(async move {
+        let wait = waiting;
+        while scheduler::sleep_until_next(&schedule).await {
+            if !WAITING.lock().unwrap().iter().any(|waiting| waiting.id == wait.id) {
+                return; // cancelled
+            }
+            if wait.expired(Local::now()) {
+                let outcome = format!("Gave up waiting to {}: the base fee stayed above {} gwei", wait.description, units::format_gwei(wait.max_base_fee));
+                notify::send(&ctx.config.notify, &Notification::new(Kind::Transaction, "Gas wait expired", outcome.clone())).await;
+                finish(&wait, outcome);
+                return;
+            }
+            let current = match get_provider(&ctx.config.chain).await {
+                Ok(provider) => base_fee(&provider).await,
+                Err(e) => Err(e),
+            };
+            match current {
+                Ok(Some(fee)) if wait.can_send(fee) => {
+                    tracing::info!(wait_id = wait.id, base_fee = %fee, "Base fee dropped; sending");
+                    let result = execute_tool(&ctx, &tool, &args).await.unwrap_or_else(|e| format!("Error: {}", e));
+                    let outcome = format!("The base fee dropped to {} gwei, so I went ahead to {}:\n{}", units::format_gwei(fee), wait.description, result);
+                    notify::send(&ctx.config.notify, &Notification::new(Kind::Transaction, "Gas-waiting send made", outcome.clone())).await;
+                    finish(&wait, outcome);
+                    return;
+                },
+                Ok(_) => {},
+                Err(e) => tracing::warn!(wait_id = wait.id, error = %e, "Failed to read the base fee"),
+            }
+        }
+    });
+    Ok(wait)
+}
+
+/// Sends from this session still waiting for cheaper gas
+pub fn waiting(session_id: Option<Uuid>) -> Vec<WaitingSend> {
+    WAITING.lock().unwrap().iter()
+        .filter(|wait| wait.session_id == session_id)
+        .cloned()
+        .collect()
+}
+
+/// Stop waiting to make one of this session's sends; false if there is no such wait
+pub fn cancel(session_id: Option<Uuid>, id: u64) -> bool {
+    let mut waiting = WAITING.lock().unwrap();
+    let before = waiting.len();
+    waiting.retain(|wait| !(wait.id == id && wait.session_id == session_id));
+    waiting.len() < before
+}
+
+/// Outcomes of this session's waits since they were last reported
+pub fn take_updates(session_id: Uuid) -> Vec<String> {
+    let mut updates = UPDATES.lock().unwrap();
+    let (taken, kept) = updates.drain(..).partition(|(session, _)| *session == Some(session_id));
+    *updates = kept;
+    taken.into_iter().map(|(_, update)| update).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gwei(amount: u64) -> U256 {
+        U256::from(amount) * 1_000_000_000u64
+    }
+
+    // Four blocks from 100, the second of them empty, with their tips at the 10th, 50th, and
+    // 90th percentiles
+    fn fee_history() -> FeeHistory {
+        FeeHistory {
+            base_fee_per_gas: [20, 22, 21, 24, 26].map(gwei).to_vec(),
+            gas_used_ratio: vec![0.5, 0.0, 0.9, 0.3],
+            oldest_block: 100.into(),
+            reward: vec![[1, 2, 5], [0, 0, 0], [2, 3, 9], [1, 1, 4]].into_iter().map(|tips| tips.map(gwei).to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn recommends_fees_from_the_fee_history() {
+        let report = report_from_history(&fee_history()).unwrap();
+        assert_eq!((report.block, report.blocks), (103, 4));
+        assert_eq!((report.base_fee, report.next_base_fee), (gwei(24), gwei(26)));
+        // Medians of the non-empty blocks
+        assert_eq!(report.priority_fees, [(10.0, gwei(1)), (50.0, gwei(2)), (90.0, gwei(5))]);
+        let levels: Vec<(&str, U256, U256)> = report.levels.iter().map(|level| (level.name, level.priority_fee, level.max_fee)).collect();
+        assert_eq!(levels, [
+            ("cheap", gwei(1), gwei(26 + 1)),
+            ("standard", gwei(2), gwei(26) * 125 / 100 + gwei(2)),
+            ("fast", gwei(5), gwei(26 * 2 + 5)),
+        ]);
+
+        let table = format_report("Sepolia", &report, Some(2000.0));
+        assert!(table.starts_with("Gas on Sepolia at block 103\nBase fee: 24 gwei (next block: 26 gwei)"), "{}", table);
+        // A transfer pays the next base fee and the tip: 21000 * 27 gwei
+        assert!(table.contains("| cheap | 1 gwei | 27 gwei | 0.000567 ETH ($1.13) |"), "{}", table);
+    }
+
+    #[test]
+    fn finds_no_recommendation_without_a_base_fee() {
+        let legacy = FeeHistory { base_fee_per_gas: vec![U256::zero(); 5], ..fee_history() };
+        assert!(report_from_history(&legacy).is_none());
+        let empty = FeeHistory { base_fee_per_gas: Vec::new(), gas_used_ratio: Vec::new(), reward: Vec::new(), ..fee_history() };
+        assert!(report_from_history(&empty).is_none());
+        // Only empty blocks leave the tips at zero
+        let idle = FeeHistory { gas_used_ratio: vec![0.0; 4], ..fee_history() };
+        assert!(report_from_history(&idle).unwrap().levels.iter().all(|level| level.priority_fee.is_zero()));
+    }
+
+    #[test]
+    fn sends_once_the_base_fee_is_below_the_limit() {
+        let now = Local::now();
+        let wait = WaitingSend { id: 1, session_id: None, description: "send 1 ETH".to_string(), max_base_fee: gwei(20), expires_at: now + Duration::hours(24) };
+        assert!(wait.can_send(gwei(19)));
+        assert!(!wait.can_send(gwei(20)));
+        assert!(!wait.can_send(gwei(35)));
+        assert!(!wait.expired(now));
+        assert!(wait.expired(now + Duration::hours(25)));
+    }
+}
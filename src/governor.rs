@@ -0,0 +1,435 @@
+// On-chain governance through OpenZeppelin Governor contracts: proposals come from their
+// ProposalCreated events, state and tallies from the contract, and voting power from
+// `getVotes` at the proposal's snapshot. Votes are `castVote` transactions, which the tools
+// send like any other contract call.
+
+use crate::config::GovernorConfig;
+use crate::reminders::describe_wait;
+use crate::units;
+use chrono::{DateTime, Duration, Utc};
+use ethers::prelude::*;
+use std::str::FromStr;
+use std::sync::Arc;
+
+abigen!(
+    Governor,
+    r#"[
+        function name() external view returns (string)
+        function token() external view returns (address)
+        function clock() external view returns (uint48)
+        function CLOCK_MODE() external view returns (string)
+        function state(uint256 proposalId) external view returns (uint8)
+        function proposalSnapshot(uint256 proposalId) external view returns (uint256)
+        function proposalDeadline(uint256 proposalId) external view returns (uint256)
+        function proposalVotes(uint256 proposalId) external view returns (uint256 againstVotes, uint256 forVotes, uint256 abstainVotes)
+        function quorum(uint256 timepoint) external view returns (uint256)
+        function getVotes(address account, uint256 timepoint) external view returns (uint256)
+        function hasVoted(uint256 proposalId, address account) external view returns (bool)
+        function castVote(uint256 proposalId, uint8 support) external returns (uint256)
+        function castVoteWithReason(uint256 proposalId, uint8 support, string reason) external returns (uint256)
+        event ProposalCreated(uint256 proposalId, address proposer, address[] targets, uint256[] values, string[] signatures, bytes[] calldatas, uint256 voteStart, uint256 voteEnd, string description)
+    ]"#
+);
+
+abigen!(
+    VotesToken,
+    r#"[
+        function decimals() external view returns (uint8)
+    ]"#
+);
+
+// Proposals listed at once
+const MAX_PROPOSALS: usize = 20;
+// Characters of a proposal's description returned for summarizing
+const MAX_DESCRIPTION_CHARS: usize = 6000;
+// For turning block timepoints into rough times
+const SECONDS_PER_BLOCK: i64 = 12;
+
+// Governor's ProposalState, by its index
+const STATES: [&str; 8] = ["pending", "active", "canceled", "defeated", "succeeded", "queued", "expired", "executed"];
+/// `state` of a proposal open for votes
+pub const ACTIVE: u8 = 1;
+
+/// A Governor contract and how it keeps time
+#[derive(Debug, Clone)]
+pub struct GovernorInfo {
+    pub address: Address,
+    pub name: String,
+    /// Timepoints are timestamps (ERC-6372 `mode=timestamp`) rather than block numbers
+    pub timestamps: bool,
+    /// The current timepoint
+    pub clock: u64,
+    /// Decimals of the voting token, for vote counts
+    pub decimals: u32,
+}
+
+/// A proposal as created, from its ProposalCreated event
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub id: U256,
+    pub proposer: Address,
+    pub targets: Vec<Address>,
+    pub values: Vec<U256>,
+    pub calldatas: Vec<Bytes>,
+    pub description: String,
+    pub block: u64,
+}
+
+/// Where voting on a proposal stands, read from the contract
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub state: u8,
+    pub snapshot: U256,
+    pub deadline: U256,
+    /// Against, for, and abstain votes, when the Governor counts them that way
+    pub votes: Option<(U256, U256, U256)>,
+    /// Votes needed, once the snapshot is past
+    pub quorum: Option<U256>,
+}
+
+/// The Governor named `input` under `[governor]`, or given by address. Without one, the only
+/// configured Governor is used.
+pub fn resolve(config: &GovernorConfig, input: Option<&str>) -> anyhow::Result<Address> {
+    let address = match input.map(str::trim).filter(|input| !input.is_empty()) {
+        Some(input) => config.contracts.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(input))
+            .map(|(_, address)| address.as_str())
+            .unwrap_or(input),
+        None => match config.contracts.values().collect::<Vec<_>>().as_slice() {
+            [] => return Err(anyhow::anyhow!("No Governor given and none configured under [governor]")),
+            [address] => address.as_str(),
+            _ => return Err(anyhow::anyhow!("Name one of the configured Governors: {}",
+                                            config.contracts.keys().cloned().collect::<Vec<_>>().join(", "))),
+        },
+    };
+    Address::from_str(address).map_err(|_| anyhow::anyhow!("{} is neither a configured Governor nor an address", address))
+}
+
+/// A proposal id, in decimal as Governors emit them or in hex
+pub fn parse_proposal_id(input: &str) -> anyhow::Result<U256> {
+    let input = input.trim().trim_start_matches('#');
+    let id = match input.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(input).ok(),
+    };
+    id.ok_or_else(|| anyhow::anyhow!("Invalid proposal id {}", input))
+}
+
+/// A vote's `support`: 0 against, 1 for, 2 abstain
+pub fn parse_support(input: &str) -> anyhow::Result<u8> {
+    match input.trim().to_lowercase().as_str() {
+        "against" | "no" | "0" => Ok(0),
+        "for" | "yes" | "1" => Ok(1),
+        "abstain" | "2" => Ok(2),
+        _ => Err(anyhow::anyhow!("Support must be for, against, or abstain, not '{}'", input)),
+    }
+}
+
+pub fn support_name(support: u8) -> &'static str {
+    match support {
+        0 => "Against",
+        1 => "For",
+        _ => "Abstain",
+    }
+}
+
+pub fn state_name(state: u8) -> &'static str {
+    STATES.get(state as usize).copied().unwrap_or("unknown")
+}
+
+/// The first line of a description, without Markdown heading marks
+pub fn title(description: &str) -> String {
+    let line = description.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("Untitled");
+    line.trim_start_matches('#').trim().to_string()
+}
+
+pub async fn info<M: Middleware + 'static>(client: Arc<M>, address: Address) -> anyhow::Result<GovernorInfo> {
+    let governor = Governor::new(address, client.clone());
+    let name = governor.name().call().await
+        .map_err(|e| anyhow::anyhow!("{:?} does not look like a Governor: {}", address, e))?;
+    // Governors from before ERC-6372 count in blocks
+    let timestamps = governor.clock_mode().call().await.is_ok_and(|mode| mode.contains("mode=timestamp"));
+    let clock = match governor.clock().call().await {
+        Ok(clock) => clock,
+        Err(_) => client.get_block_number().await.map_err(|e| anyhow::anyhow!("{}", e))?.as_u64(),
+    };
+    let decimals = match governor.token().call().await {
+        Ok(token) => VotesToken::new(token, client).decimals().call().await.unwrap_or(0) as u32,
+        Err(_) => 18,
+    };
+    Ok(GovernorInfo { address, name, timestamps, clock, decimals })
+}
+
+// ProposalCreated events from the newest block back, in chunks, until `enough` says stop
+async fn scan<M: Middleware + 'static>(client: Arc<M>, config: &GovernorConfig, address: Address, enough: impl Fn(&[Proposal]) -> bool) -> anyhow::Result<Vec<Proposal>> {
+    let governor = Governor::new(address, client.clone());
+    let latest = client.get_block_number().await.map_err(|e| anyhow::anyhow!("{}", e))?.as_u64();
+    let oldest = latest.saturating_sub(config.lookback_blocks);
+    let chunk = config.log_chunk_blocks.max(1);
+    let mut proposals = Vec::new();
+    let mut to = latest;
+    while to >= oldest && !enough(&proposals) {
+        let from = to.saturating_sub(chunk - 1).max(oldest);
+        let mut found: Vec<Proposal> = governor.proposal_created_filter().from_block(from).to_block(to)
+            .query_with_meta().await
+            .map_err(|e| anyhow::anyhow!("Could not read proposals from blocks {} to {}: {}", from, to, e))?
+            .into_iter()
+            .map(|(event, meta)| Proposal {
+                id: event.proposal_id,
+                proposer: event.proposer,
+                targets: event.targets,
+                values: event.values,
+                calldatas: event.calldatas,
+                description: event.description,
+                block: meta.block_number.as_u64(),
+            })
+            .collect();
+        found.reverse();
+        proposals.extend(found);
+        if from == 0 {
+            break;
+        }
+        to = from - 1;
+    }
+    Ok(proposals)
+}
+
+/// Proposals created in the last `lookback_blocks` blocks, newest first
+pub async fn proposals<M: Middleware + 'static>(client: Arc<M>, config: &GovernorConfig, address: Address) -> anyhow::Result<Vec<Proposal>> {
+    let mut proposals = scan(client, config, address, |found| found.len() >= MAX_PROPOSALS).await?;
+    proposals.truncate(MAX_PROPOSALS);
+    Ok(proposals)
+}
+
+/// The ProposalCreated event of `id`, if it is in the last `lookback_blocks` blocks
+pub async fn find<M: Middleware + 'static>(client: Arc<M>, config: &GovernorConfig, address: Address, id: U256) -> anyhow::Result<Option<Proposal>> {
+    let proposals = scan(client, config, address, |found| found.iter().any(|proposal| proposal.id == id)).await?;
+    Ok(proposals.into_iter().find(|proposal| proposal.id == id))
+}
+
+pub async fn status<M: Middleware + 'static>(client: Arc<M>, address: Address, info: &GovernorInfo, id: U256) -> anyhow::Result<Status> {
+    let governor = Governor::new(address, client);
+    let state = governor.state(id).call().await
+        .map_err(|_| anyhow::anyhow!("{} has no proposal {}", info.name, id))?;
+    let snapshot = governor.proposal_snapshot(id).call().await?;
+    let deadline = governor.proposal_deadline(id).call().await?;
+    let votes = governor.proposal_votes(id).call().await.ok();
+    // Quorum is only known for timepoints in the past
+    let quorum = match snapshot < U256::from(info.clock) {
+        true => governor.quorum(snapshot).call().await.ok(),
+        false => None,
+    };
+    Ok(Status { state, snapshot, deadline, votes, quorum })
+}
+
+/// Votes `voter` has at `timepoint`, or now (at the last timepoint, since `getVotes` only
+/// looks back)
+pub async fn voting_power<M: Middleware + 'static>(client: Arc<M>, info: &GovernorInfo, voter: Address, timepoint: Option<U256>) -> anyhow::Result<U256> {
+    let timepoint = timepoint.unwrap_or_else(|| U256::from(info.clock.saturating_sub(1)));
+    Ok(Governor::new(info.address, client).get_votes(voter, timepoint).call().await?)
+}
+
+pub async fn has_voted<M: Middleware + 'static>(client: Arc<M>, address: Address, id: U256, voter: Address) -> anyhow::Result<bool> {
+    Ok(Governor::new(address, client).has_voted(id, voter).call().await?)
+}
+
+/// Calldata of a vote, with the reason when one is given
+pub fn vote_call<M: Middleware + 'static>(client: Arc<M>, address: Address, id: U256, support: u8, reason: &str) -> Bytes {
+    let governor = Governor::new(address, client);
+    let call = match reason.trim() {
+        "" => governor.cast_vote(id, support),
+        reason => governor.cast_vote_with_reason(id, support, reason.to_string()),
+    };
+    call.calldata().unwrap_or_default()
+}
+
+/// Votes in the voting token's units, e.g. "1250.5"
+pub fn format_votes(info: &GovernorInfo, votes: U256) -> String {
+    units::format_token(votes, info.decimals)
+}
+
+// A timepoint as a time or a block number
+fn timepoint(info: &GovernorInfo, timepoint: U256) -> String {
+    if info.timestamps {
+        return DateTime::from_timestamp(timepoint.low_u64() as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| timepoint.to_string());
+    }
+    format!("block {}", timepoint)
+}
+
+// How long until a future timepoint, roughly for blocks
+fn wait(info: &GovernorInfo, timepoint: U256, now: DateTime<Utc>) -> String {
+    let ahead = timepoint.low_u64().saturating_sub(info.clock) as i64;
+    match info.timestamps {
+        true => describe_wait(now + Duration::seconds(ahead), now),
+        false => format!("about {}", describe_wait(now + Duration::seconds(ahead * SECONDS_PER_BLOCK), now)),
+    }
+}
+
+// When voting opens or closes, e.g. "ends block 21000000 (in about 2d 3h)"
+fn window(info: &GovernorInfo, status: &Status, now: DateTime<Utc>) -> String {
+    match status.state {
+        0 => format!("voting starts {} (in {})", timepoint(info, status.snapshot), wait(info, status.snapshot, now)),
+        ACTIVE => format!("ends {} (in {})", timepoint(info, status.deadline), wait(info, status.deadline, now)),
+        _ => format!("ended {}", timepoint(info, status.deadline)),
+    }
+}
+
+// The tally, e.g. "For 1200, Against 300, Abstain 0; quorum 1000 reached"
+fn tally(info: &GovernorInfo, status: &Status) -> String {
+    let Some((against, for_votes, abstain)) = status.votes else {
+        return "tally not readable".to_string();
+    };
+    let counts = format!("For {}, Against {}, Abstain {}", format_votes(info, for_votes), format_votes(info, against), format_votes(info, abstain));
+    match status.quorum {
+        // Governor's simple counting meets quorum with for and abstain votes
+        Some(quorum) => {
+            let reached = if for_votes + abstain >= quorum { "reached" } else { "not reached" };
+            format!("{}; quorum {} {}", counts, format_votes(info, quorum), reached)
+        },
+        None => counts,
+    }
+}
+
+/// Proposals with their state, one per line
+pub fn list(info: &GovernorInfo, proposals: &[(Proposal, Status)], now: DateTime<Utc>) -> String {
+    let mut lines = vec![format!("Proposals on {} ({:?}), newest first ({}):", info.name, info.address, proposals.len())];
+    for (proposal, status) in proposals {
+        lines.push(format!("- {} ({}): {}, {}; {}", title(&proposal.description), proposal.id, state_name(status.state),
+                           window(info, status, now), tally(info, status)));
+    }
+    lines.join("\n")
+}
+
+/// One proposal in full: its state, tally, actions, and description
+pub fn details(info: &GovernorInfo, id: U256, proposal: Option<&Proposal>, status: &Status, now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        format!("{} ({})", proposal.map(|proposal| title(&proposal.description)).unwrap_or_else(|| format!("Proposal {}", id)), info.name),
+        format!("Id: {}", id),
+        format!("State: {}, {}", state_name(status.state), window(info, status, now)),
+        format!("Votes: {}", tally(info, status)),
+        format!("Voting power at {}", timepoint(info, status.snapshot)),
+    ];
+    let Some(proposal) = proposal else {
+        lines.push("Its ProposalCreated event is older than the blocks searched, so its description and actions are unknown.".to_string());
+        return lines.join("\n");
+    };
+    lines.push(format!("Proposer: {:?}, in block {}", proposal.proposer, proposal.block));
+    lines.push(format!("Actions ({}):", proposal.targets.len()));
+    for (index, target) in proposal.targets.iter().enumerate() {
+        let value = proposal.values.get(index).copied().unwrap_or_default();
+        let calldata = proposal.calldatas.get(index).cloned().unwrap_or_default();
+        let selector = match calldata.len() >= 4 {
+            true => format!("0x{}", hex::encode(&calldata[..4])),
+            false => "no call".to_string(),
+        };
+        lines.push(format!("{}. {:?}: {} ({} bytes), {} ETH", index + 1, target, selector, calldata.len(), units::format_eth(value)));
+    }
+    let mut description: String = proposal.description.chars().take(MAX_DESCRIPTION_CHARS).collect();
+    if description.len() < proposal.description.len() {
+        description.push_str("\n[...description cut]");
+    }
+    format!("{}\n\n{}", lines.join("\n"), description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context, returns, rpc_node, EthCall};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn reads_governor_proposals_and_checks_votes_before_casting() {
+        use ethers::abi::{encode, Token};
+
+        let (governor, token, target) = (Address::repeat_byte(0x60), Address::repeat_byte(0x70), Address::repeat_byte(0x80));
+        let voter = Address::repeat_byte(0x11);
+        let proposal_id = U256::from_dec_str("98765432109876543210987654321098765432109876543210").unwrap();
+        let votes = |amount: u64| Token::Uint(U256::from(amount) * U256::exp10(18));
+        let topic = ethers::types::H256(ethers::utils::keccak256("ProposalCreated(uint256,address,address[],uint256[],string[],bytes[],uint256,uint256,string)"));
+        // A node serving one Governor counting in blocks, with one active proposal created at block 20989000
+        let url = rpc_node(move |method, params| {
+            let word = |value: u64| Token::Uint(U256::from(value));
+            match method {
+                "eth_blockNumber" => Some(json!("0x1406f40")),
+                "eth_getLogs" => {
+                    let block = |key: &str| u64::from_str_radix(params[0][key].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+                    match (block("fromBlock")..=block("toBlock")).contains(&20_989_000) {
+                        true => Some(json!([{
+                            "address": governor, "topics": [topic],
+                            "data": format!("0x{}", hex::encode(encode(&[
+                                Token::Uint(proposal_id), Token::Address(Address::repeat_byte(0x22)), Token::Array(vec![Token::Address(target)]),
+                                Token::Array(vec![word(0)]), Token::Array(vec![Token::String(String::new())]), Token::Array(vec![Token::Bytes(vec![0xa9, 0x05, 0x9c, 0xbb, 1, 2])]),
+                                word(20_990_000), word(21_014_400), Token::String("# Raise the quorum\n\nRaise quorum to 2% of supply.".to_string()),
+                            ]))),
+                            "blockNumber": "0x1404c48", "blockHash": format!("0x{}", "01".repeat(32)), "transactionHash": format!("0x{}", "02".repeat(32)),
+                            "transactionIndex": "0x0", "logIndex": "0x0", "removed": false,
+                        }])),
+                        false => Some(json!([])),
+                    }
+                },
+                "eth_call" => {
+                    let call = EthCall::parse(params);
+                    let tokens = if call.to == token && call.is("decimals()") {
+                        vec![word(18)]
+                    } else if call.is("name()") {
+                        vec![Token::String("ENS Governor".to_string())]
+                    } else if call.is("CLOCK_MODE()") {
+                        vec![Token::String("mode=blocknumber&from=default".to_string())]
+                    } else if call.is("clock()") {
+                        vec![word(21_000_000)]
+                    } else if call.is("token()") {
+                        vec![Token::Address(token)]
+                    } else if call.is("state(uint256)") {
+                        vec![word(1)]
+                    } else if call.is("proposalSnapshot(uint256)") {
+                        vec![word(20_990_000)]
+                    } else if call.is("proposalDeadline(uint256)") {
+                        vec![word(21_014_400)]
+                    } else if call.is("proposalVotes(uint256)") {
+                        vec![votes(300), votes(1200), votes(0)]
+                    } else if call.is("quorum(uint256)") {
+                        vec![votes(1000)]
+                    } else if call.is("getVotes(address,uint256)") {
+                        // 42.5 votes for the voter, none for anyone else
+                        let amount = if call.address(0) == voter { U256::from(425) * U256::exp10(17) } else { U256::zero() };
+                        vec![Token::Uint(amount)]
+                    } else if call.is("hasVoted(uint256,address)") {
+                        vec![Token::Bool(false)]
+                    } else {
+                        return None;
+                    };
+                    returns(&tokens)
+                },
+                method => panic!("unexpected RPC call {}", method),
+            }
+        }).await;
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(url);
+        let unconfigured = execute_tool(&context(config.clone()), "governor", &json!({ "operation": "proposals" })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+        config.governor.contracts.insert("ens".to_string(), format!("{:?}", governor));
+        let ctx = context(config);
+
+        let listing = execute_tool(&ctx, "governor", &json!({ "operation": "proposals" })).await.unwrap();
+        assert!(listing.starts_with(&format!("Proposals on ENS Governor ({:?}), newest first (1):\n\
+                                              - Raise the quorum ({}): active, ends block 21014400 (in about 2d 0h); \
+                                              For 1200, Against 300, Abstain 0; quorum 1000 reached", governor, proposal_id)), "{}", listing);
+        let details = execute_tool(&ctx, "governor", &json!({ "operation": "proposal", "governor": "ENS", "proposal_id": proposal_id.to_string() })).await.unwrap();
+        assert!(details.contains(&format!("Actions (1):\n1. {:?}: 0xa9059cbb (6 bytes), 0 ETH\n\n# Raise the quorum", target)), "{}", details);
+        let power = execute_tool(&ctx, "governor", &json!({ "operation": "voting_power", "account": format!("{:?}", voter), "proposal_id": proposal_id.to_string() })).await.unwrap();
+        assert!(power.starts_with(&format!("{:?} has 42.5 votes on ENS Governor on proposal {}", voter, proposal_id)), "{}", power);
+
+        let vote = |account: Address, support: &str| json!({ "operation": "vote", "proposal_id": proposal_id.to_string(), "support": support, "account": format!("{:?}", account) });
+        assert_eq!(execute_tool(&ctx, "governor", &vote(voter, "maybe")).await.unwrap_err().kind(), "invalid_args");
+        let unconfirmed = execute_tool(&ctx, "governor", &vote(voter, "for")).await.unwrap_err();
+        assert!(unconfirmed.message().contains(&format!("Vote For on proposal {} \"Raise the quorum\" on ENS Governor as {:?}\nVoting power: 42.5", proposal_id, voter)),
+                "{}", unconfirmed.message());
+        let powerless = execute_tool(&ctx, "governor", &vote(Address::repeat_byte(0x33), "against")).await.unwrap_err();
+        assert_eq!(powerless.kind(), "failed");
+    }
+}
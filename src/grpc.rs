@@ -0,0 +1,212 @@
+// The agent as the gRPC service of proto/agent.proto (`grpc-serve`, built with the `grpc`
+// feature), so that other languages get typed clients generated from the same definition.
+// Chat sessions are shared with the JSON-RPC mode's handling, and tool and wallet calls go
+// through the same allowlist, policy, dry-run mode, and audit log as the agent's own.
+
+use crate::personality::Personality;
+use crate::tools::ToolContext;
+
+#[cfg(feature = "grpc")]
+use crate::redact::redact;
+#[cfg(feature = "grpc")]
+use crate::rpc::{check_message, ChatError, RpcServer};
+#[cfg(feature = "grpc")]
+use crate::tool_error::ToolError;
+#[cfg(feature = "grpc")]
+use crate::tools::{execute_tool_as, tools_for};
+#[cfg(feature = "grpc")]
+use std::pin::Pin;
+#[cfg(feature = "grpc")]
+use std::sync::Arc;
+#[cfg(feature = "grpc")]
+use tonic::{Request, Response, Status};
+
+#[cfg(feature = "grpc")]
+#[allow(clippy::all)]
+pub mod pb {
+    tonic::include_proto!("agent.v1");
+}
+
+#[cfg(feature = "grpc")]
+use pb::agent_server::{Agent, AgentServer};
+#[cfg(feature = "grpc")]
+use pb::*;
+
+#[cfg(not(feature = "grpc"))]
+pub async fn serve(_ctx: ToolContext, _personality: Option<Personality>, _port: u16) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("The gRPC service is not enabled; rebuild with `--features grpc`"))
+}
+
+/// Serve the gRPC service on 127.0.0.1:`port`
+#[cfg(feature = "grpc")]
+pub async fn serve(ctx: ToolContext, personality: Option<Personality>, port: u16) -> anyhow::Result<()> {
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    tracing::info!(%addr, "Serving the agent over gRPC");
+    tonic::transport::Server::builder()
+        .add_service(AgentServer::new(GrpcAgent::new(ctx, personality)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(feature = "grpc")]
+pub struct GrpcAgent {
+    chat: Arc<RpcServer>,
+    ctx: ToolContext,
+    personality: Option<Personality>,
+}
+
+#[cfg(feature = "grpc")]
+impl GrpcAgent {
+    pub fn new(ctx: ToolContext, personality: Option<Personality>) -> Self {
+        GrpcAgent { chat: Arc::new(RpcServer::new(ctx.clone(), personality.clone())), ctx, personality }
+    }
+
+    async fn tool(&self, name: &str, args: serde_json::Value) -> Result<Response<ToolOutput>, Status> {
+        tracing::info!(tool = name, "gRPC client called a tool");
+        let output = execute_tool_as(&self.ctx, self.personality.as_ref(), name, &args).await.map_err(tool_status)?;
+        Ok(Response::new(ToolOutput { text: redact(&output).into_owned() }))
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn tool_status(e: ToolError) -> Status {
+    let message = redact(e.message()).into_owned();
+    match e {
+        ToolError::InvalidArgs(_) => Status::invalid_argument(message),
+        ToolError::RpcError(_) => Status::unavailable(message),
+        ToolError::PolicyViolation(_) => Status::permission_denied(message),
+        ToolError::SimulationFailed(_) | ToolError::NotConfigured(_) => Status::failed_precondition(message),
+        ToolError::Timeout(_) => Status::deadline_exceeded(message),
+        ToolError::Failed(_) => Status::internal(message),
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn chat_status(e: ChatError) -> Status {
+    match e {
+        ChatError::NotFound(_) => Status::not_found(e.to_string()),
+        ChatError::Invalid(_) => Status::invalid_argument(e.to_string()),
+        ChatError::Failed(_) => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn session_id(request: &ChatRequest) -> Result<Option<uuid::Uuid>, ChatError> {
+    request.session.as_deref()
+        .map(|session| uuid::Uuid::parse_str(session).map_err(|_| ChatError::Invalid(format!("Invalid session id: {}", session))))
+        .transpose()
+}
+
+#[cfg(feature = "grpc")]
+type ChatEvents = Pin<Box<dyn futures::Stream<Item = Result<ChatEvent, Status>> + Send>>;
+
+#[cfg(feature = "grpc")]
+#[tonic::async_trait]
+impl Agent for GrpcAgent {
+    async fn chat(&self, request: Request<ChatRequest>) -> Result<Response<ChatReply>, Status> {
+        let request = request.into_inner();
+        check_message(&request.message).map_err(chat_status)?;
+        let session = self.chat.open(session_id(&request).map_err(chat_status)?).await.map_err(chat_status)?;
+        let answer = self.chat.chat(session, &request.message, |_| {}).await.map_err(chat_status)?;
+        Ok(Response::new(ChatReply { session: answer.session.to_string(), reply: answer.reply, model: answer.model }))
+    }
+
+    type ChatStreamStream = ChatEvents;
+
+    async fn chat_stream(&self, request: Request<ChatRequest>) -> Result<Response<ChatEvents>, Status> {
+        let request = request.into_inner();
+        check_message(&request.message).map_err(chat_status)?;
+        let session = self.chat.open(session_id(&request).map_err(chat_status)?).await.map_err(chat_status)?;
+        let (sender, events) = tokio::sync::mpsc::unbounded_channel();
+        let chat = self.chat.clone();
+        tokio::spawn(async move {
+            let answer = chat.chat(session, &request.message, |line| {
+                let activity = ToolActivity { session: session.to_string(), line };
+                let _ = sender.send(Ok(ChatEvent { event: Some(chat_event::Event::Activity(activity)) }));
+            }).await;
+            let last = answer.map_err(chat_status).map(|answer| {
+                let reply = ChatReply { session: answer.session.to_string(), reply: answer.reply, model: answer.model };
+                ChatEvent { event: Some(chat_event::Event::Reply(reply)) }
+            });
+            let _ = sender.send(last);
+        });
+        let stream = futures::stream::unfold(events, |mut events| async move {
+            events.recv().await.map(|event| (event, events))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn list_tools(&self, _request: Request<ListToolsRequest>) -> Result<Response<ListToolsReply>, Status> {
+        let tools = tools_for(self.personality.as_ref()).into_iter()
+            .map(|tool| Tool { name: tool.name, description: tool.description, input_schema_json: tool.input_schema.to_string() })
+            .collect();
+        Ok(Response::new(ListToolsReply { tools }))
+    }
+
+    async fn call_tool(&self, request: Request<CallToolRequest>) -> Result<Response<ToolOutput>, Status> {
+        let request = request.into_inner();
+        let args = match request.arguments_json.trim() {
+            "" => serde_json::json!({}),
+            json => serde_json::from_str(json).map_err(|e| Status::invalid_argument(format!("Invalid arguments JSON: {}", e)))?,
+        };
+        self.tool(&request.name, args).await
+    }
+
+    async fn get_balance(&self, request: Request<GetBalanceRequest>) -> Result<Response<ToolOutput>, Status> {
+        let address = request.into_inner().address;
+        self.tool("eth_wallet", serde_json::json!({ "operation": "balance", "address": address })).await
+    }
+
+    async fn list_accounts(&self, _request: Request<ListAccountsRequest>) -> Result<Response<ToolOutput>, Status> {
+        self.tool("eth_wallet", serde_json::json!({ "operation": "accounts" })).await
+    }
+
+    async fn create_account(&self, request: Request<CreateAccountRequest>) -> Result<Response<ToolOutput>, Status> {
+        let name = request.into_inner().name;
+        self.tool("eth_wallet", serde_json::json!({ "operation": "generate", "label": name })).await
+    }
+
+    async fn send_eth(&self, request: Request<SendEthRequest>) -> Result<Response<ToolOutput>, Status> {
+        let request = request.into_inner();
+        self.tool("eth_wallet", serde_json::json!({
+            "operation": "send",
+            "account": request.account,
+            "to_address": request.to,
+            "amount": request.amount,
+            "dry_run": request.dry_run,
+        })).await
+    }
+}
+
+#[cfg(all(test, feature = "grpc"))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::context;
+    use futures::StreamExt;
+    use pb::chat_event::Event;
+
+    #[tokio::test]
+    async fn streams_tool_activity_over_grpc() {
+        let mut config = Config::default();
+        config.anthropic.mock_fixture = Some("tests/fixtures/llm/grpc_chat.json".to_string());
+        let agent = GrpcAgent::new(context(config), None);
+
+        let request = tonic::Request::new(ChatRequest { message: "What time is it?".to_string(), session: None });
+        let events: Vec<Event> = agent.chat_stream(request).await.unwrap().into_inner()
+            .map(|event| event.unwrap().event.unwrap()).collect().await;
+        let [Event::Activity(started), Event::Activity(finished), Event::Reply(reply)] = events.as_slice() else {
+            panic!("unexpected events: {:?}", events);
+        };
+        assert_eq!(started.line, "→ get_time");
+        assert!(finished.line.starts_with("ok get_time"));
+        assert_eq!(reply.reply, "It is noon.");
+        assert_eq!(started.session, reply.session);
+
+        let tools = agent.list_tools(tonic::Request::new(ListToolsRequest {})).await.unwrap().into_inner().tools;
+        assert!(tools.iter().any(|tool| tool.name == "eth_wallet" && tool.input_schema_json.contains("\"operation\"")));
+        let empty = ChatRequest { message: " ".to_string(), session: None };
+        assert_eq!(agent.chat(tonic::Request::new(empty)).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+}
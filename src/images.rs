@@ -0,0 +1,56 @@
+// Images attached to a prompt (screenshots of transactions, charts), sent to the model as
+// image content blocks
+
+use crate::anthropic::ImageSource;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::path::Path;
+
+// Formats the model reads, by file extension
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+];
+
+// Largest image the API accepts inline
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// An image from a local file, or from an `http(s)://` URL that the API fetches itself
+pub fn load(reference: &str) -> anyhow::Result<ImageSource> {
+    if reference.starts_with("http://") || reference.starts_with("https://") {
+        return Ok(ImageSource::Url { url: reference.to_string() });
+    }
+    let path = Path::new(reference);
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let media_type = MEDIA_TYPES.iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, media_type)| media_type.to_string())
+        .ok_or_else(|| anyhow::anyhow!("{} is not a PNG, JPEG, GIF, or WebP image", reference))?;
+    let bytes = std::fs::read(path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", reference, e))?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(anyhow::anyhow!("{} is larger than the 5 MB the model accepts", reference));
+    }
+    Ok(ImageSource::Base64 { media_type, data: BASE64.encode(bytes) })
+}
+
+/// Check an image sent by an API client: a supported format within the size limit, or an
+/// `http(s)://` URL
+pub fn check(image: &ImageSource) -> anyhow::Result<()> {
+    match image {
+        ImageSource::Url { url } if url.starts_with("http://") || url.starts_with("https://") => Ok(()),
+        ImageSource::Url { url } => Err(anyhow::anyhow!("Image URL {} is not http(s)", url)),
+        ImageSource::Base64 { media_type, data } => {
+            if !MEDIA_TYPES.iter().any(|(_, known)| known == media_type) {
+                return Err(anyhow::anyhow!("Unsupported image type {}; use PNG, JPEG, GIF, or WebP", media_type));
+            }
+            let bytes = BASE64.decode(data).map_err(|e| anyhow::anyhow!("Image data is not valid base64: {}", e))?;
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err(anyhow::anyhow!("Image is larger than the 5 MB the model accepts"));
+            }
+            Ok(())
+        },
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::IpfsConfig;
+use crate::nft;
+use crate::workspace;
+use reqwest::multipart::{Form, Part};
+use reqwest::Client;
+use std::path::Path;
+
+const PINATA_API_URL: &str = "https://api.pinata.cloud";
+const WEB3_STORAGE_API_URL: &str = "https://api.web3.storage";
+const LOCAL_NODE_API_URL: &str = "http://127.0.0.1:5001";
+
+// Largest upload accepted from the uploads directory
+const MAX_UPLOAD_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Content fetched by CID
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub bytes: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+fn api_url<'a>(config: &'a IpfsConfig, default: &'a str) -> &'a str {
+    config.api_url.as_deref()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(default)
+        .trim_end_matches('/')
+}
+
+fn api_token(config: &IpfsConfig) -> anyhow::Result<&str> {
+    config.api_token.as_deref()
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No API token for {}; set api_token under [ipfs] or IPFS_API_TOKEN", config.provider))
+}
+
+async fn check(response: reqwest::Response) -> anyhow::Result<serde_json::Value> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("IPFS API returned {}: {}", status, body));
+    }
+    Ok(response.json().await?)
+}
+
+fn cid_field(response: &serde_json::Value, field: &str) -> anyhow::Result<String> {
+    response[field].as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("IPFS API returned no CID"))
+}
+
+/// Pin a JSON document (such as NFT metadata) and return its CID
+pub async fn pin_json(config: &IpfsConfig, name: &str, content: &serde_json::Value) -> anyhow::Result<String> {
+    if config.provider == "pinata" {
+        // Pinata stores JSON through its own endpoint, keeping the name as pin metadata
+        let body = serde_json::json!({
+            "pinataContent": content,
+            "pinataMetadata": { "name": name },
+        });
+        let response = Client::new().post(format!("{}/pinning/pinJSONToIPFS", api_url(config, PINATA_API_URL)))
+            .bearer_auth(api_token(config)?)
+            .json(&body)
+            .send()
+            .await?;
+        return cid_field(&check(response).await?, "IpfsHash");
+    }
+    pin_bytes(config, name, serde_json::to_vec_pretty(content)?).await
+}
+
+/// Pin raw content under a file name and return its CID
+pub async fn pin_bytes(config: &IpfsConfig, name: &str, bytes: Vec<u8>) -> anyhow::Result<String> {
+    let client = Client::new();
+    match config.provider.as_str() {
+        "pinata" => {
+            let form = Form::new()
+                .part("file", Part::bytes(bytes).file_name(name.to_string()))
+                .text("pinataMetadata", serde_json::json!({ "name": name }).to_string());
+            let response = client.post(format!("{}/pinning/pinFileToIPFS", api_url(config, PINATA_API_URL)))
+                .bearer_auth(api_token(config)?)
+                .multipart(form)
+                .send()
+                .await?;
+            cid_field(&check(response).await?, "IpfsHash")
+        },
+        "web3storage" => {
+            let response = client.post(format!("{}/upload", api_url(config, WEB3_STORAGE_API_URL)))
+                .bearer_auth(api_token(config)?)
+                .header("X-Name", name)
+                .body(bytes)
+                .send()
+                .await?;
+            cid_field(&check(response).await?, "cid")
+        },
+        "node" => {
+            // Kubo RPC API; CIDv1 matches what the pinning services return
+            let form = Form::new().part("file", Part::bytes(bytes).file_name(name.to_string()));
+            let request = client.post(format!("{}/api/v0/add", api_url(config, LOCAL_NODE_API_URL)))
+                .query(&[("pin", "true"), ("cid-version", "1")])
+                .multipart(form);
+            let request = match config.api_token.as_deref().filter(|token| !token.is_empty()) {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            };
+            cid_field(&check(request.send().await?).await?, "Hash")
+        },
+        other => Err(anyhow::anyhow!("Unknown IPFS provider '{}'; use 'pinata', 'web3storage', or 'node'", other)),
+    }
+}
+
+/// Read a file from an uploads directory, the user's own in server mode. Paths are relative to
+/// it and may not leave it, also through symlinks, as for the workspace.
+pub fn read_upload(dir: &str, path: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let full = workspace::resolve(dir, path)
+        .map_err(|_| anyhow::anyhow!("Upload paths must be relative to the uploads directory and stay inside it, not {}", path))?;
+    let size = std::fs::metadata(&full)
+        .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", full.display(), e))?
+        .len();
+    if size > MAX_UPLOAD_BYTES {
+        return Err(anyhow::anyhow!("{} is {} bytes, over the {} byte upload limit", full.display(), size, MAX_UPLOAD_BYTES));
+    }
+    let name = Path::new(path).file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+    Ok((name, std::fs::read(&full)?))
+}
+
+/// CID (with an optional path) from a bare CID, an `ipfs://` URI, or a gateway URL
+pub fn parse_cid(input: &str) -> String {
+    let input = input.trim();
+    let path = input.strip_prefix("ipfs://")
+        .or_else(|| input.split_once("/ipfs/").map(|(_, path)| path))
+        .unwrap_or(input);
+    path.trim_start_matches('/').to_string()
+}
+
+/// Fetch content by CID from the local node, or through the gateway for the pinning services
+pub async fn fetch(config: &IpfsConfig, gateway: &str, cid: &str) -> anyhow::Result<Fetched> {
+    let client = Client::new();
+    let request = if config.provider == "node" {
+        client.post(format!("{}/api/v0/cat", api_url(config, LOCAL_NODE_API_URL)))
+            .query(&[("arg", cid)])
+    } else {
+        client.get(nft::gateway_url(&format!("ipfs://{}", cid), gateway))
+    };
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Fetching {} returned {}", cid, status));
+    }
+    let content_type = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok(Fetched { bytes: response.bytes().await?.to_vec(), content_type })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::context;
+    use crate::tools::ToolContext;
+    use uuid::Uuid;
+
+    #[test]
+    fn reads_uploads_only_from_the_users_own_directory() {
+        let dir = std::env::temp_dir().join(format!("uploads-{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.ipfs.uploads_dir = dir.to_string_lossy().into_owned();
+        let base = context(config);
+        let alice = ToolContext { user: Some("0x00000000000000000000000000000000000a11ce".to_string()), ..base.clone() };
+        let bob = ToolContext { user: Some("0x0000000000000000000000000000000000000b0b".to_string()), ..base };
+        std::fs::create_dir_all(alice.uploads_dir()).unwrap();
+        std::fs::write(Path::new(&alice.uploads_dir()).join("art.json"), "{}").unwrap();
+
+        assert_eq!(read_upload(&alice.uploads_dir(), "art.json").unwrap(), ("art.json".to_string(), b"{}".to_vec()));
+        assert!(read_upload(&bob.uploads_dir(), "art.json").is_err());
+        let bob_dir = Path::new(&bob.uploads_dir()).to_path_buf();
+        std::os::unix::fs::symlink(alice.uploads_dir(), bob_dir.join("alice")).unwrap();
+        std::os::unix::fs::symlink("/etc", bob_dir.join("etc")).unwrap();
+        for path in ["alice/art.json", "../0x00000000000000000000000000000000000a11ce/art.json", "etc/hostname", "/etc/hostname"] {
+            let refused = read_upload(&bob.uploads_dir(), path).unwrap_err();
+            assert!(refused.to_string().starts_with("Upload paths must be relative"), "{}: {}", path, refused);
+        }
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
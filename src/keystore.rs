@@ -0,0 +1,189 @@
+// Named local accounts, so that tools refer to a key by name ("trading-1") and the key itself
+// never passes through a prompt or a completion. Keys are kept in `[signer] keystore_dir` as
+// encrypted keystore files (Web3 Secret Storage, as geth and Foundry write them), one
+// `<name>.json` per account, under the password in KEYSTORE_PASSWORD. Without a password,
+// new accounts are kept in memory for the session only. Each user of a server deployment has
+// a directory of their own (see `tools::keystore_dir`), and sees only the accounts in it. The
+// HD wallet's mnemonic is kept next to the keys, encrypted the same way.
+
+use ethers::prelude::*;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Not a `.json` file, so it is never listed as an account
+const MNEMONIC_FILE: &str = "mnemonic.keystore";
+
+lazy_static! {
+    // Accounts unlocked (or created in memory) in this session, by keystore directory and name
+    static ref UNLOCKED: Mutex<BTreeMap<(String, String), LocalWallet>> = Mutex::new(BTreeMap::new());
+}
+
+fn password() -> Option<String> {
+    std::env::var("KEYSTORE_PASSWORD").ok().filter(|password| !password.is_empty())
+}
+
+fn key_file(dir: &str, name: &str) -> PathBuf {
+    Path::new(dir).join(format!("{}.json", name))
+}
+
+// Names are file stems, and must not be mistaken for an address, an HD account index, or
+// another signer backend's selector
+fn check_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty() && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow::anyhow!("Account names use letters, digits, '-' and '_' (up to 64), not '{}'", name));
+    }
+    if name.starts_with("0x") || name.parse::<u32>().is_ok() || name == "kms" || name == "ledger" {
+        return Err(anyhow::anyhow!("'{}' can't be an account name; it would be read as an address, an HD account, or another signer", name));
+    }
+    Ok(())
+}
+
+/// Names of the accounts in the keystore directory and in memory, in order
+pub fn names(dir: &str) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).into_iter().flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .collect();
+    names.extend(UNLOCKED.lock().unwrap().keys()
+        .filter(|(in_dir, name)| in_dir == dir && !name.starts_with("0x"))
+        .map(|(_, name)| name.clone()));
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Accounts of the keystore directory unlocked in this session, with their addresses
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub fn unlocked(dir: &str) -> Vec<(String, Address)> {
+    UNLOCKED.lock().unwrap().iter()
+        .filter(|((in_dir, name), _)| in_dir == dir && !name.starts_with("0x"))
+        .map(|((_, name), wallet)| (name.clone(), wallet.address()))
+        .collect()
+}
+
+/// The address of a named account without decrypting it: unlocked in this session, or
+/// recorded in its file (as geth and this keystore write it)
+pub fn address(dir: &str, name: &str) -> Option<Address> {
+    if let Some(wallet) = UNLOCKED.lock().unwrap().get(&(dir.to_string(), name.to_string())) {
+        return Some(wallet.address());
+    }
+    check_name(name).ok()?;
+    let contents: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(key_file(dir, name)).ok()?).ok()?;
+    contents["address"].as_str()?.trim_start_matches("0x").parse().ok()
+}
+
+/// The first free `wallet-N` name
+pub fn next_name(dir: &str) -> String {
+    let names = names(dir);
+    (1..).map(|n| format!("wallet-{}", n)).find(|name| !names.contains(name)).unwrap()
+}
+
+// Add a key under a new name, writing it to the keystore when a password is set. True when
+// it was written, false when it is kept in memory only.
+fn store(dir: &str, name: &str, key: &[u8]) -> anyhow::Result<(Address, bool)> {
+    check_name(name)?;
+    if names(dir).iter().any(|existing| existing == name) {
+        return Err(anyhow::anyhow!("There is already an account named '{}'", name));
+    }
+    let wallet = match password() {
+        Some(password) => {
+            std::fs::create_dir_all(dir)?;
+            let file = format!("{}.json", name);
+            let wallet = LocalWallet::encrypt_keystore(dir, &mut rand::thread_rng(), key, password, Some(&file))
+                .map_err(|e| anyhow::anyhow!("Could not write the keystore file: {}", e))?.0;
+            // The address in the clear, as geth writes it, so that listings don't need the password
+            let path = key_file(dir, name);
+            let mut contents: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            contents["address"] = serde_json::Value::String(hex::encode(wallet.address()));
+            std::fs::write(&path, contents.to_string())?;
+            wallet
+        },
+        None => LocalWallet::from_bytes(key).map_err(|_| anyhow::anyhow!("Invalid private key"))?,
+    };
+    let address = wallet.address();
+    UNLOCKED.lock().unwrap().insert((dir.to_string(), name.to_string()), wallet);
+    Ok((address, password().is_some()))
+}
+
+/// Create an account with a new random key
+pub fn create(dir: &str, name: &str) -> anyhow::Result<(Address, bool)> {
+    let key = LocalWallet::new(&mut rand::thread_rng()).signer().to_bytes();
+    store(dir, name, &key)
+}
+
+/// Add an existing private key (hex, with or without 0x) under a name
+pub fn import(dir: &str, name: &str, private_key: &str) -> anyhow::Result<(Address, bool)> {
+    let key = hex::decode(private_key.trim().trim_start_matches("0x"))
+        .map_err(|_| anyhow::anyhow!("Invalid private key format"))?;
+    store(dir, name, &key)
+}
+
+/// The signing key of a named account, decrypting its file the first time it is used
+pub fn unlock(dir: &str, name: &str) -> anyhow::Result<LocalWallet> {
+    if let Some(wallet) = UNLOCKED.lock().unwrap().get(&(dir.to_string(), name.to_string())) {
+        return Ok(wallet.clone());
+    }
+    let file = key_file(dir, name);
+    if check_name(name).is_err() || !file.exists() {
+        return Err(anyhow::anyhow!("No account named '{}'", name));
+    }
+    let password = password()
+        .ok_or_else(|| anyhow::anyhow!("Set KEYSTORE_PASSWORD to unlock the account '{}'", name))?;
+    let wallet = LocalWallet::decrypt_keystore(&file, password)
+        .map_err(|e| anyhow::anyhow!("Could not unlock the account '{}': {}", name, e))?;
+    UNLOCKED.lock().unwrap().insert((dir.to_string(), name.to_string()), wallet.clone());
+    Ok(wallet)
+}
+
+/// The signing key of the account holding `address`, unlocking keystore files until one does
+pub fn signer_for_address(dir: &str, address: Address) -> Option<LocalWallet> {
+    let unlocked = UNLOCKED.lock().unwrap().iter()
+        .find(|((in_dir, _), wallet)| in_dir == dir && wallet.address() == address)
+        .map(|(_, wallet)| wallet.clone());
+    if unlocked.is_some() {
+        return unlocked;
+    }
+    password()?;
+    names(dir).iter()
+        .filter_map(|name| unlock(dir, name).ok())
+        .find(|wallet| wallet.address() == address)
+}
+
+/// Store the HD wallet's mnemonic in the keystore directory, replacing the one stored there
+pub fn save_mnemonic(dir: &str, phrase: &str) -> anyhow::Result<()> {
+    let password = password()
+        .ok_or_else(|| anyhow::anyhow!("Set KEYSTORE_PASSWORD to the password the keystore is encrypted with"))?;
+    std::fs::create_dir_all(dir)?;
+    eth_keystore::encrypt_key(dir, &mut rand::thread_rng(), phrase.as_bytes(), password, Some(MNEMONIC_FILE))
+        .map_err(|e| anyhow::anyhow!("Could not write the keystore file: {}", e))?;
+    Ok(())
+}
+
+/// The mnemonic stored in the keystore directory, if there is one
+pub fn mnemonic(dir: &str) -> anyhow::Result<Option<String>> {
+    let file = Path::new(dir).join(MNEMONIC_FILE);
+    if !file.exists() {
+        return Ok(None);
+    }
+    let password = password()
+        .ok_or_else(|| anyhow::anyhow!("Set KEYSTORE_PASSWORD to unlock the HD wallet's mnemonic"))?;
+    let phrase = eth_keystore::decrypt_key(&file, password)
+        .map_err(|e| anyhow::anyhow!("Could not unlock the HD wallet's mnemonic: {}", e))?;
+    Ok(Some(String::from_utf8(phrase)?))
+}
+
+/// Keep a private key passed as a tool argument in memory for the session, under its address.
+/// Only for `[signer] allow_private_key_args`.
+pub fn add_session_key(dir: &str, private_key: &str) -> anyhow::Result<Address> {
+    let key = hex::decode(private_key.trim().trim_start_matches("0x"))
+        .map_err(|_| anyhow::anyhow!("Invalid private key format"))?;
+    let wallet = LocalWallet::from_bytes(&key).map_err(|_| anyhow::anyhow!("Failed to create wallet from private key"))?;
+    let address = wallet.address();
+    UNLOCKED.lock().unwrap().insert((dir.to_string(), format!("{:?}", address)), wallet);
+    Ok(address)
+}
@@ -0,0 +1,165 @@
+use crate::config::KmsConfig;
+use crate::wallet::AgentSigner;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ethers::prelude::*;
+use ethers::utils::keccak256;
+use reqwest::Client;
+
+const GCP_KMS_URL: &str = "https://cloudkms.googleapis.com/v1";
+const GCP_METADATA_TOKEN_URL: &str = "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+// Order of the secp256k1 group; Ethereum only accepts signatures with s in the lower half
+const SECP256K1_ORDER: &str = "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141";
+
+/// A secp256k1 key version in Google Cloud KMS (`EC_SIGN_SECP256K1_SHA256`). Digests are sent to
+/// Cloud KMS to be signed, so the key never leaves it.
+#[derive(Debug, Clone)]
+pub struct GcpKmsSigner {
+    key_name: String,
+    address: Address,
+    chain_id: u64,
+}
+
+/// Connect to the key configured under `[kms]`
+pub async fn signer(config: &KmsConfig, chain_id: u64) -> anyhow::Result<AgentSigner> {
+    let key_id = config.key_id.as_deref()
+        .filter(|key_id| !key_id.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No KMS key configured; set key_id under [kms] or KMS_KEY_ID"))?;
+    match config.provider.as_str() {
+        "aws" => aws_signer(key_id, chain_id).await,
+        "gcp" => Ok(AgentSigner::Gcp(GcpKmsSigner::new(key_id, chain_id).await?)),
+        other => Err(anyhow::anyhow!("Unknown KMS provider '{}'; use 'aws' or 'gcp'", other)),
+    }
+}
+
+// Region and credentials come from the usual AWS environment (AWS_REGION, AWS_ACCESS_KEY_ID, an
+// instance role, ...)
+#[cfg(feature = "aws")]
+async fn aws_signer(key_id: &str, chain_id: u64) -> anyhow::Result<AgentSigner> {
+    let client = rusoto_kms::KmsClient::new(rusoto_core::Region::default());
+    let signer = AwsSigner::new(client, key_id, chain_id).await
+        .map_err(|e| anyhow::anyhow!("Could not load AWS KMS key {}: {}", key_id, e))?;
+    Ok(AgentSigner::Aws(signer))
+}
+
+#[cfg(not(feature = "aws"))]
+async fn aws_signer(_key_id: &str, _chain_id: u64) -> anyhow::Result<AgentSigner> {
+    Err(anyhow::anyhow!("AWS KMS support is not enabled; rebuild with `--features aws`"))
+}
+
+// OAuth access token from GCP_ACCESS_TOKEN, or else for the attached service account from the
+// metadata server (on GCE, Cloud Run, GKE, ...)
+async fn gcp_access_token(client: &Client) -> anyhow::Result<String> {
+    if let Ok(token) = std::env::var("GCP_ACCESS_TOKEN")
+        && !token.is_empty()
+    {
+        return Ok(token);
+    }
+    let response: serde_json::Value = client.get(GCP_METADATA_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("No GCP_ACCESS_TOKEN set and the metadata server is unreachable: {}", e))?
+        .error_for_status()?
+        .json()
+        .await?;
+    response["access_token"].as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Metadata server returned no access token"))
+}
+
+async fn gcp_request(client: &Client, request: reqwest::RequestBuilder) -> anyhow::Result<serde_json::Value> {
+    let response = request.bearer_auth(gcp_access_token(client).await?).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("Cloud KMS returned {}: {}", status, body));
+    }
+    Ok(response.json().await?)
+}
+
+// Address of a PEM-encoded secp256k1 public key. Its DER ends with the 65-byte uncompressed point.
+fn address_from_pem(pem: &str) -> anyhow::Result<Address> {
+    let body: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+    let der = BASE64.decode(body.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid public key PEM: {}", e))?;
+    let point = der.len().checked_sub(65).map(|start| &der[start..])
+        .filter(|point| point[0] == 0x04)
+        .ok_or_else(|| anyhow::anyhow!("Public key is not an uncompressed secp256k1 key"))?;
+    Ok(Address::from_slice(&keccak256(&point[1..])[12..]))
+}
+
+// r and s of a DER-encoded ECDSA signature: SEQUENCE { INTEGER r, INTEGER s }
+fn parse_der_signature(der: &[u8]) -> Option<(U256, U256)> {
+    fn integer(bytes: &[u8]) -> Option<(U256, &[u8])> {
+        let (&tag, rest) = bytes.split_first()?;
+        let (&len, rest) = rest.split_first()?;
+        if tag != 0x02 || rest.len() < len as usize {
+            return None;
+        }
+        let (value, rest) = rest.split_at(len as usize);
+        // A leading zero byte keeps values with the high bit set positive
+        let value = value.strip_prefix(&[0]).unwrap_or(value);
+        (value.len() <= 32).then(|| (U256::from_big_endian(value), rest))
+    }
+    match der {
+        [0x30, _, body @ ..] => integer(body).and_then(|(r, rest)| integer(rest).map(|(s, _)| (r, s))),
+        _ => None,
+    }
+}
+
+// Ethereum signature from a DER one, with s normalized to the lower half. KMS doesn't return the
+// recovery id, so it is the one that recovers the key's address.
+fn recoverable_signature(der: &[u8], digest: H256, address: Address) -> Option<Signature> {
+    let (r, mut s) = parse_der_signature(der)?;
+    let order = U256::from_str_radix(SECP256K1_ORDER, 16).ok()?;
+    if s > order / 2 {
+        s = order - s;
+    }
+    (0..2).map(|recovery_id| Signature { r, s, v: recovery_id })
+        .find(|signature| Signature { v: signature.v + 27, ..*signature }
+            .recover(RecoveryMessage::Hash(digest))
+            .is_ok_and(|recovered| recovered == address))
+}
+
+impl GcpKmsSigner {
+    /// Load a key version given by its full resource name,
+    /// `projects/<p>/locations/<l>/keyRings/<r>/cryptoKeys/<k>/cryptoKeyVersions/<n>`
+    pub async fn new(key_name: &str, chain_id: u64) -> anyhow::Result<Self> {
+        let client = Client::new();
+        let key = gcp_request(&client, client.get(format!("{}/{}/publicKey", GCP_KMS_URL, key_name))).await?;
+        let algorithm = key["algorithm"].as_str().unwrap_or("");
+        if algorithm != "EC_SIGN_SECP256K1_SHA256" {
+            return Err(anyhow::anyhow!("Cloud KMS key {} uses {}; Ethereum needs EC_SIGN_SECP256K1_SHA256", key_name, algorithm));
+        }
+        let pem = key["pem"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Cloud KMS returned no public key"))?;
+        Ok(GcpKmsSigner { key_name: key_name.to_string(), address: address_from_pem(pem)?, chain_id })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn with_chain_id(self, chain_id: u64) -> Self {
+        GcpKmsSigner { chain_id, ..self }
+    }
+
+    /// Sign a 32-byte digest. The returned `v` is the bare recovery id (0 or 1).
+    pub async fn sign_digest(&self, digest: H256) -> anyhow::Result<Signature> {
+        let client = Client::new();
+        let body = serde_json::json!({ "digest": { "sha256": BASE64.encode(digest.as_bytes()) } });
+        let response = gcp_request(&client, client.post(format!("{}/{}:asymmetricSign", GCP_KMS_URL, self.key_name)).json(&body)).await?;
+        let der = response["signature"].as_str()
+            .and_then(|signature| BASE64.decode(signature).ok())
+            .ok_or_else(|| anyhow::anyhow!("Cloud KMS returned no signature"))?;
+        recoverable_signature(&der, digest, self.address)
+            .ok_or_else(|| anyhow::anyhow!("Cloud KMS signature does not match key {}", self.key_name))
+    }
+}
+
@@ -0,0 +1,576 @@
+// Lending on Aave V3 and Compound V3 money markets. An Aave pool holds many reserves: supplied
+// assets earn interest and back borrows of any other reserve. A Compound Comet lends out a single
+// base asset (such as USDC) against collateral assets that earn nothing; borrowing withdraws more
+// of the base asset than was supplied, and repaying supplies it back. Either way an account can be
+// liquidated once its health factor (collateral weighted by the liquidation thresholds, over the
+// debt) falls below 1.
+
+use crate::config::{LendingConfig, LendingMarketConfig};
+use crate::dex::{self, SwapToken};
+use ethers::prelude::*;
+use ethers::utils::format_units;
+use std::str::FromStr;
+use std::sync::Arc;
+
+mod aave {
+    use ethers::prelude::*;
+
+    abigen!(
+        Pool,
+        r#"[
+            function ADDRESSES_PROVIDER() external view returns (address)
+            function getUserAccountData(address user) external view returns (uint256 totalCollateralBase, uint256 totalDebtBase, uint256 availableBorrowsBase, uint256 currentLiquidationThreshold, uint256 ltv, uint256 healthFactor)
+            function supply(address asset, uint256 amount, address onBehalfOf, uint16 referralCode) external
+            function withdraw(address asset, uint256 amount, address to) external returns (uint256)
+            function borrow(address asset, uint256 amount, uint256 interestRateMode, uint16 referralCode, address onBehalfOf) external
+            function repay(address asset, uint256 amount, uint256 interestRateMode, address onBehalfOf) external returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        PoolAddressesProvider,
+        r#"[
+            function getPriceOracle() external view returns (address)
+            function getPoolDataProvider() external view returns (address)
+        ]"#
+    );
+
+    abigen!(
+        PriceOracle,
+        r#"[
+            function BASE_CURRENCY_UNIT() external view returns (uint256)
+            function getAssetPrice(address asset) external view returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        PoolDataProvider,
+        r#"[
+            function getReserveConfigurationData(address asset) external view returns (uint256 decimals, uint256 ltv, uint256 liquidationThreshold, uint256 liquidationBonus, uint256 reserveFactor, bool usageAsCollateralEnabled, bool borrowingEnabled, bool stableBorrowRateEnabled, bool isActive, bool isFrozen)
+            function getUserReserveData(address asset, address user) external view returns (uint256 currentATokenBalance, uint256 currentStableDebt, uint256 currentVariableDebt, uint256 principalStableDebt, uint256 scaledVariableDebt, uint256 stableBorrowRate, uint256 liquidityRate, uint40 stableRateLastUpdated, bool usageAsCollateralEnabled)
+        ]"#
+    );
+}
+
+mod compound {
+    use ethers::prelude::*;
+
+    abigen!(
+        Comet,
+        r#"[
+            struct AssetInfo { uint8 offset; address asset; address priceFeed; uint64 scale; uint64 borrowCollateralFactor; uint64 liquidateCollateralFactor; uint64 liquidationFactor; uint128 supplyCap; }
+            function baseToken() external view returns (address)
+            function baseTokenPriceFeed() external view returns (address)
+            function numAssets() external view returns (uint8)
+            function getAssetInfo(uint8 i) external view returns (AssetInfo)
+            function getAssetInfoByAddress(address asset) external view returns (AssetInfo)
+            function getPrice(address priceFeed) external view returns (uint256)
+            function balanceOf(address account) external view returns (uint256)
+            function borrowBalanceOf(address account) external view returns (uint256)
+            function collateralBalanceOf(address account, address asset) external view returns (uint128)
+            function supply(address asset, uint256 amount) external
+            function withdraw(address asset, uint256 amount) external
+        ]"#
+    );
+}
+
+use aave::{Pool, PoolAddressesProvider, PoolDataProvider, PriceOracle};
+use compound::Comet;
+
+// Aave's variable interest rate mode; the stable rate is deprecated
+const AAVE_VARIABLE_RATE: u64 = 2;
+// Aave reports liquidation thresholds in basis points
+const AAVE_THRESHOLD_DECIMALS: u32 = 4;
+// Comet prices have 8 decimals and its collateral factors 18
+const COMET_PRICE_DECIMALS: u32 = 8;
+const COMET_FACTOR_DECIMALS: u32 = 18;
+
+/// A market's contracts on the configured chain
+#[derive(Debug, Clone)]
+pub enum Contracts {
+    AaveV3 { pool: Address, oracle: Address, data_provider: Address },
+    CompoundV3 { comet: Address, base: Address },
+}
+
+#[derive(Debug, Clone)]
+pub struct Market {
+    pub name: String,
+    pub contracts: Contracts,
+}
+
+impl Market {
+    pub fn protocol(&self) -> &'static str {
+        match self.contracts {
+            Contracts::AaveV3 { .. } => "Aave V3",
+            Contracts::CompoundV3 { .. } => "Compound V3",
+        }
+    }
+
+    /// The contract supplies and repayments are approved for
+    pub fn spender(&self) -> Address {
+        match self.contracts {
+            Contracts::AaveV3 { pool, .. } => pool,
+            Contracts::CompoundV3 { comet, .. } => comet,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Supply,
+    Withdraw,
+    Borrow,
+    Repay,
+}
+
+impl Action {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Action::Supply => "Supply",
+            Action::Withdraw => "Withdraw",
+            Action::Borrow => "Borrow",
+            Action::Repay => "Repay",
+        }
+    }
+}
+
+/// An account's standing in a market, in USD
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    pub collateral_usd: f64,
+    /// Collateral weighted by each asset's liquidation threshold
+    pub liquidation_usd: f64,
+    pub debt_usd: f64,
+    /// How much more can be borrowed now
+    pub available_usd: f64,
+}
+
+impl Health {
+    /// None while there is no debt, as nothing can be liquidated
+    pub fn factor(&self) -> Option<f64> {
+        (self.debt_usd > 0.0).then(|| self.liquidation_usd / self.debt_usd)
+    }
+}
+
+/// A market's terms for one asset, and an account's amounts of it
+#[derive(Debug, Clone)]
+pub struct Reserve {
+    pub token: SwapToken,
+    pub price_usd: f64,
+    /// Share of the asset's value counted toward the health factor, 0 if it isn't collateral
+    pub liquidation_threshold: f64,
+    /// Whether the account's supply of it counts as collateral
+    pub collateral: bool,
+    pub borrowable: bool,
+    /// Compound's base asset, which supply and withdraw also repay and borrow
+    pub base: bool,
+    pub supplied: U256,
+    pub borrowed: U256,
+}
+
+// A Comet collateral asset: its token, price feed, scale, and the borrow and liquidation
+// collateral factors
+struct CometAsset {
+    asset: Address,
+    price_feed: Address,
+    scale: u64,
+    borrow_factor: f64,
+    liquidation_factor: f64,
+}
+
+// From Comet's (offset, asset, priceFeed, scale, borrowCollateralFactor,
+// liquidateCollateralFactor, liquidationFactor, supplyCap) asset info
+fn comet_asset(info: (u8, Address, Address, u64, u64, u64, u64, u128)) -> CometAsset {
+    CometAsset {
+        asset: info.1,
+        price_feed: info.2,
+        scale: info.3,
+        borrow_factor: to_f64(info.4.into(), COMET_FACTOR_DECIMALS),
+        liquidation_factor: to_f64(info.5.into(), COMET_FACTOR_DECIMALS),
+    }
+}
+
+fn parse_address(value: &str, name: &str) -> anyhow::Result<Address> {
+    Address::from_str(value).map_err(|_| anyhow::anyhow!("Invalid contract address {} for {} under [lending]", value, name))
+}
+
+fn to_f64(value: U256, decimals: u32) -> f64 {
+    format_units(value, decimals).ok().and_then(|value| value.parse().ok()).unwrap_or(0.0)
+}
+
+/// USD value of an amount of the reserve's asset
+pub fn usd_value(reserve: &Reserve, amount: U256) -> f64 {
+    to_f64(amount, reserve.token.decimals as u32) * reserve.price_usd
+}
+
+/// The markets deployed on `chain_id`: the one named, or all of them
+pub fn deployments<'a>(config: &'a LendingConfig, chain_id: u64, name: Option<&str>) -> anyhow::Result<Vec<&'a LendingMarketConfig>> {
+    let on_chain: Vec<&LendingMarketConfig> = config.markets.iter().filter(|market| market.chain_id == chain_id).collect();
+    let Some(name) = name else { return Ok(on_chain) };
+    let wanted = name.trim().to_lowercase().replace([' ', '-'], "_");
+    match on_chain.iter().find(|market| market.name.eq_ignore_ascii_case(&wanted)) {
+        Some(market) => Ok(vec![*market]),
+        None => Err(anyhow::anyhow!("No lending market '{}' on this chain; there is {}", name,
+                                    match on_chain.is_empty() {
+                                        true => "none".to_string(),
+                                        false => on_chain.iter().map(|market| market.name.as_str()).collect::<Vec<_>>().join(", "),
+                                    })),
+    }
+}
+
+/// Find a deployment's contracts
+pub async fn connect<M: Middleware + 'static>(client: Arc<M>, config: &LendingMarketConfig) -> anyhow::Result<Market> {
+    let contract = parse_address(&config.contract, &config.name)?;
+    let contracts = match config.kind.as_str() {
+        "aave_v3" => {
+            let provider = Pool::new(contract, client.clone()).addresses_provider().call().await
+                .map_err(|e| anyhow::anyhow!("Could not read the Aave pool at {:?}: {}", contract, e))?;
+            let provider = PoolAddressesProvider::new(provider, client);
+            Contracts::AaveV3 {
+                pool: contract,
+                oracle: provider.get_price_oracle().call().await?,
+                data_provider: provider.get_pool_data_provider().call().await?,
+            }
+        },
+        "compound_v3" => Contracts::CompoundV3 {
+            comet: contract,
+            base: Comet::new(contract, client).base_token().call().await
+                .map_err(|e| anyhow::anyhow!("Could not read the Comet at {:?}: {}", contract, e))?,
+        },
+        kind => return Err(anyhow::anyhow!("Unknown lending market kind '{}' for {}; use aave_v3 or compound_v3", kind, config.name)),
+    };
+    Ok(Market { name: config.name.clone(), contracts })
+}
+
+pub async fn health<M: Middleware + 'static>(client: Arc<M>, market: &Market, account: Address) -> anyhow::Result<Health> {
+    match market.contracts {
+        Contracts::AaveV3 { pool, oracle, .. } => {
+            let (collateral, debt, available, threshold, _, _) = Pool::new(pool, client.clone()).get_user_account_data(account).call().await?;
+            // Amounts are in the oracle's base currency, USD with 8 decimals on current deployments
+            let unit = PriceOracle::new(oracle, client).base_currency_unit().call().await?;
+            let usd = |amount: U256| to_f64(amount, 0) / to_f64(unit, 0);
+            Ok(Health {
+                collateral_usd: usd(collateral),
+                liquidation_usd: usd(collateral) * to_f64(threshold, AAVE_THRESHOLD_DECIMALS),
+                debt_usd: usd(debt),
+                available_usd: usd(available),
+            })
+        },
+        Contracts::CompoundV3 { comet, base } => {
+            let comet = Comet::new(comet, client.clone());
+            let mut health = Health::default();
+            let mut borrow_capacity = 0.0;
+            for i in 0..comet.num_assets().call().await? {
+                let info = comet_asset(comet.get_asset_info(i).call().await?);
+                let balance = comet.collateral_balance_of(account, info.asset).call().await?;
+                if balance == 0 {
+                    continue;
+                }
+                let price = to_f64(comet.get_price(info.price_feed).call().await?, COMET_PRICE_DECIMALS);
+                let value = balance as f64 / info.scale as f64 * price;
+                health.collateral_usd += value;
+                health.liquidation_usd += value * info.liquidation_factor;
+                borrow_capacity += value * info.borrow_factor;
+            }
+            let borrowed = comet.borrow_balance_of(account).call().await?;
+            if !borrowed.is_zero() {
+                let decimals = dex::Erc20::new(base, client).decimals().call().await?;
+                let price = to_f64(comet.get_price(comet.base_token_price_feed().call().await?).call().await?, COMET_PRICE_DECIMALS);
+                health.debt_usd = to_f64(borrowed, decimals as u32) * price;
+            }
+            health.available_usd = (borrow_capacity - health.debt_usd).max(0.0);
+            Ok(health)
+        },
+    }
+}
+
+/// The market's terms for `token` and the account's amounts of it, refused when the market
+/// doesn't list it
+pub async fn reserve<M: Middleware + 'static>(client: Arc<M>, market: &Market, token: SwapToken, account: Address) -> anyhow::Result<Reserve> {
+    match market.contracts {
+        Contracts::AaveV3 { oracle, data_provider, .. } => {
+            let data = PoolDataProvider::new(data_provider, client.clone());
+            let (_, _, threshold, _, _, collateral_enabled, borrowing_enabled, _, active, frozen) = data.get_reserve_configuration_data(token.address).call().await?;
+            if !active {
+                return Err(anyhow::anyhow!("{} is not a reserve of {}", token.symbol, market.name));
+            }
+            if frozen {
+                return Err(anyhow::anyhow!("The {} reserve of {} is frozen", token.symbol, market.name));
+            }
+            let oracle = PriceOracle::new(oracle, client);
+            let unit = oracle.base_currency_unit().call().await?;
+            let price = oracle.get_asset_price(token.address).call().await?;
+            let (supplied, stable_debt, variable_debt, _, _, _, _, _, used_as_collateral) = data.get_user_reserve_data(token.address, account).call().await?;
+            let liquidation_threshold = match collateral_enabled {
+                true => to_f64(threshold, AAVE_THRESHOLD_DECIMALS),
+                false => 0.0,
+            };
+            Ok(Reserve {
+                token,
+                price_usd: to_f64(price, 0) / to_f64(unit, 0),
+                liquidation_threshold,
+                // A first supply of a collateral asset is used as collateral
+                collateral: liquidation_threshold > 0.0 && (used_as_collateral || supplied.is_zero()),
+                borrowable: borrowing_enabled,
+                base: false,
+                supplied,
+                borrowed: stable_debt + variable_debt,
+            })
+        },
+        Contracts::CompoundV3 { comet, base } => {
+            let comet = Comet::new(comet, client);
+            if token.address == base {
+                let price = comet.get_price(comet.base_token_price_feed().call().await?).call().await?;
+                return Ok(Reserve {
+                    token,
+                    price_usd: to_f64(price, COMET_PRICE_DECIMALS),
+                    liquidation_threshold: 0.0,
+                    collateral: false,
+                    borrowable: true,
+                    base: true,
+                    supplied: comet.balance_of(account).call().await?,
+                    borrowed: comet.borrow_balance_of(account).call().await?,
+                });
+            }
+            let info = comet.get_asset_info_by_address(token.address).call().await
+                .map(comet_asset)
+                .map_err(|_| anyhow::anyhow!("{} is not a collateral asset of {}", token.symbol, market.name))?;
+            let price = comet.get_price(info.price_feed).call().await?;
+            let supplied = comet.collateral_balance_of(account, token.address).call().await?;
+            Ok(Reserve {
+                token,
+                price_usd: to_f64(price, COMET_PRICE_DECIMALS),
+                liquidation_threshold: info.liquidation_factor,
+                collateral: true,
+                borrowable: false,
+                base: false,
+                supplied: supplied.into(),
+                borrowed: U256::zero(),
+            })
+        },
+    }
+}
+
+/// The health factor after `action` with `amount` of the reserve's asset; None without debt
+pub fn projected_factor(health: &Health, reserve: &Reserve, action: Action, amount: U256) -> Option<f64> {
+    let mut liquidation = health.liquidation_usd;
+    let mut debt = health.debt_usd;
+    match (action, reserve.base) {
+        // Compound's base asset repays debt before it is supplied, and is borrowed once the
+        // supply is withdrawn
+        (Action::Supply | Action::Repay, true) | (Action::Repay, false) => debt -= usd_value(reserve, amount.min(reserve.borrowed)),
+        (Action::Withdraw | Action::Borrow, true) => debt += usd_value(reserve, amount.saturating_sub(reserve.supplied)),
+        (Action::Borrow, false) => debt += usd_value(reserve, amount),
+        (Action::Supply, false) if reserve.collateral => liquidation += usd_value(reserve, amount) * reserve.liquidation_threshold,
+        (Action::Withdraw, false) if reserve.collateral => liquidation -= usd_value(reserve, amount) * reserve.liquidation_threshold,
+        (Action::Supply | Action::Withdraw, false) => {},
+    }
+    Health { liquidation_usd: liquidation.max(0.0), debt_usd: debt.max(0.0), ..health.clone() }.factor()
+}
+
+/// Contract and calldata of an action. `amount` may be U256::MAX to withdraw or repay everything.
+pub fn action_call<M: Middleware + 'static>(client: Arc<M>, market: &Market, action: Action, token: Address, amount: U256, account: Address) -> (Address, Bytes) {
+    match market.contracts {
+        Contracts::AaveV3 { pool, .. } => {
+            let contract = Pool::new(pool, client);
+            let rate = U256::from(AAVE_VARIABLE_RATE);
+            let call = match action {
+                Action::Supply => contract.supply(token, amount, account, 0).calldata(),
+                Action::Withdraw => contract.withdraw(token, amount, account).calldata(),
+                Action::Borrow => contract.borrow(token, amount, rate, 0, account).calldata(),
+                Action::Repay => contract.repay(token, amount, rate, account).calldata(),
+            };
+            (pool, call.unwrap_or_default())
+        },
+        Contracts::CompoundV3 { comet, .. } => {
+            let contract = Comet::new(comet, client);
+            let call = match action {
+                Action::Supply | Action::Repay => contract.supply(token, amount).calldata(),
+                Action::Withdraw | Action::Borrow => contract.withdraw(token, amount).calldata(),
+            };
+            (comet, call.unwrap_or_default())
+        },
+    }
+}
+
+/// The amount a call passes. 'all' passes the largest amount, which Aave and the base asset of a
+/// Comet take as the whole supply or debt; a Comet withdraws collateral through a checked 128-bit
+/// cast, so it is passed the supplied balance instead.
+pub fn call_amount(market: &Market, reserve: &Reserve, action: Action, amount: U256, everything: bool) -> U256 {
+    match (&market.contracts, action) {
+        _ if !everything => amount,
+        (Contracts::CompoundV3 { .. }, Action::Withdraw) if !reserve.base => reserve.supplied,
+        _ => U256::MAX,
+    }
+}
+
+/// An approval a supply or repayment of `amount` needs first
+pub async fn approval_call<M: Middleware + 'static>(client: Arc<M>, market: &Market, token: Address, owner: Address, amount: U256) -> anyhow::Result<Option<(Address, Bytes)>> {
+    let erc20 = dex::Erc20::new(token, client);
+    if erc20.allowance(owner, market.spender()).call().await? >= amount {
+        return Ok(None);
+    }
+    Ok(Some((token, erc20.approve(market.spender(), amount).calldata().unwrap_or_default())))
+}
+
+pub fn format_factor(factor: Option<f64>) -> String {
+    match factor {
+        Some(factor) => format!("{:.2}", factor),
+        None => "no debt".to_string(),
+    }
+}
+
+pub fn describe_health(market: &Market, health: &Health, min_health_factor: f64) -> String {
+    let mut lines = vec![
+        format!("{} ({}): collateral ${:.2}, debt ${:.2}, ${:.2} more can be borrowed",
+                market.name, market.protocol(), health.collateral_usd, health.debt_usd, health.available_usd),
+        format!("Health factor: {}", format_factor(health.factor())),
+    ];
+    match health.factor() {
+        Some(factor) if factor < 1.0 => lines.push("Warning: below 1, so the position can be liquidated now".to_string()),
+        Some(factor) if factor < min_health_factor => lines.push(format!("Warning: below the {} minimum; supply collateral or repay debt to raise it", min_health_factor)),
+        _ => {},
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context, returns, rpc_node, EthCall};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn checks_health_factors_before_lending_actions() {
+        use ethers::abi::Token;
+
+        let [pool, addresses_provider, oracle, data_provider, comet, weth_feed, usdc_feed] = [0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67].map(Address::repeat_byte);
+        let [weth, usdc] = [0x71, 0x72].map(Address::repeat_byte);
+        let owner = Address::repeat_byte(0x11);
+        let units = |amount: u64, decimals: usize| U256::from(amount) * U256::exp10(decimals);
+        // An Aave pool where the account supplies 5 WETH ($10000) and owes 4000 USDC, and a Comet
+        // lending USDC where it supplies 2 WETH ($4000) and owes 3000 USDC
+        let url = rpc_node(move |method, params| {
+            if method != "eth_call" {
+                panic!("unexpected RPC call {}", method);
+            }
+            let call = EthCall::parse(params);
+            let to = call.to;
+            let asset = || call.address(0);
+            let uint = |value: U256| Token::Uint(value);
+            let tokens = match to {
+                _ if call.is("decimals()") && (to == weth || to == usdc) => vec![uint(if to == weth { 18 } else { 6 }.into())],
+                _ if call.is("symbol()") && (to == weth || to == usdc) => vec![Token::String(if to == weth { "WETH" } else { "USDC" }.to_string())],
+                _ if to == pool && call.is("ADDRESSES_PROVIDER()") => vec![Token::Address(addresses_provider)],
+                _ if to == pool && call.is("getUserAccountData(address)") => [10_000, 4_000, 3_500, 0, 0, 0].iter()
+                    .map(|usd| uint(units(*usd, 8))).enumerate()
+                    .map(|(i, value)| if i == 3 { uint(8250.into()) } else { value })
+                    .collect(),
+                _ if to == addresses_provider && call.is("getPriceOracle()") => vec![Token::Address(oracle)],
+                _ if to == addresses_provider && call.is("getPoolDataProvider()") => vec![Token::Address(data_provider)],
+                _ if to == oracle && call.is("BASE_CURRENCY_UNIT()") => vec![uint(units(1, 8))],
+                _ if to == oracle && call.is("getAssetPrice(address)") => vec![uint(units(if asset() == weth { 2000 } else { 1 }, 8))],
+                _ if to == data_provider && call.is("getReserveConfigurationData(address)") => {
+                    let [decimals, threshold] = if asset() == weth { [18, 8250] } else { [6, 7800] };
+                    [decimals, 8000, threshold, 10500, 1000].map(|value| uint(value.into())).into_iter()
+                        .chain([true, true, false, true, false].map(Token::Bool))
+                        .collect()
+                },
+                _ if to == data_provider && call.is("getUserReserveData(address,address)") => {
+                    let (supplied, debt) = if asset() == weth { (units(5, 18), U256::zero()) } else { (U256::zero(), units(4_000, 6)) };
+                    [supplied, U256::zero(), debt, U256::zero(), debt, U256::zero(), U256::zero(), U256::zero()].map(uint).into_iter()
+                        .chain([Token::Bool(true)])
+                        .collect()
+                },
+                _ if to == comet && call.is("baseToken()") => vec![Token::Address(usdc)],
+                _ if to == comet && call.is("baseTokenPriceFeed()") => vec![Token::Address(usdc_feed)],
+                _ if to == comet && call.is("numAssets()") => vec![uint(1.into())],
+                _ if to == comet && (call.is("getAssetInfo(uint8)") || (call.is("getAssetInfoByAddress(address)") && asset() == weth)) => vec![Token::Tuple(vec![
+                    uint(0.into()), Token::Address(weth), Token::Address(weth_feed), uint(units(1, 18)),
+                    uint(units(825, 15)), uint(units(900, 15)), uint(units(950, 15)), uint(units(1, 24)),
+                ])],
+                _ if to == comet && call.is("getPrice(address)") => vec![uint(units(if asset() == weth_feed { 2000 } else { 1 }, 8))],
+                _ if to == comet && call.is("collateralBalanceOf(address,address)") => vec![uint(units(2, 18))],
+                _ if to == comet && call.is("balanceOf(address)") => vec![uint(U256::zero())],
+                _ if to == comet && call.is("borrowBalanceOf(address)") => vec![uint(units(3_000, 6))],
+                _ => return None,
+            };
+            returns(&tokens)
+        }).await;
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(url);
+        config.chain.chain_id = 5;
+        let unconfigured = execute_tool(&context(config.clone()), "lending", &json!({ "operation": "health_factor", "account": format!("{:?}", owner) })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+        config.chain.chain_id = 1;
+        config.chain.name = "Ethereum".to_string();
+        config.lending.markets = vec![
+            LendingMarketConfig { name: "aave".to_string(), kind: "aave_v3".to_string(), chain_id: 1, contract: format!("{:?}", pool) },
+            LendingMarketConfig { name: "compound_usdc".to_string(), kind: "compound_v3".to_string(), chain_id: 1, contract: format!("{:?}", comet) },
+        ];
+        let ctx = context(config);
+
+        let account = format!("{:?}", owner);
+        let health = execute_tool(&ctx, "lending", &json!({ "operation": "health_factor", "account": account })).await.unwrap();
+        assert!(health.starts_with(&format!("Lending of {:?} on Ethereum:\n\n\
+                                             aave (Aave V3): collateral $10000.00, debt $4000.00, $3500.00 more can be borrowed\nHealth factor: 2.06\n\n\
+                                             compound_usdc (Compound V3): collateral $4000.00, debt $3000.00, $300.00 more can be borrowed\nHealth factor: 1.20\n\
+                                             Warning: below the 1.5 minimum; supply collateral or repay debt to raise it", owner)), "{}", health);
+
+        let call = |operation: &str, market: &str, asset: Option<Address>, amount: &str| {
+            json!({ "operation": operation, "market": market, "asset": asset.map(|asset| format!("{:?}", asset)), "amount": amount, "account": account })
+        };
+        let quote = execute_tool(&ctx, "lending", &call("withdraw", "aave", Some(weth), "1")).await.unwrap_err();
+        assert!(quote.message().contains(&format!("Withdraw 1 WETH ($2000.00) from aave as {:?}\nHealth factor: 2.06 -> 1.65", owner)), "{}", quote.message());
+        assert!(!quote.message().contains("Warning"), "{}", quote.message());
+        let risky = execute_tool(&ctx, "lending", &call("withdraw", "aave", Some(weth), "2")).await.unwrap_err();
+        assert!(risky.message().contains("Health factor: 2.06 -> 1.24\nWarning: this leaves a health factor of 1.24, below the 1.5 minimum"), "{}", risky.message());
+        let mut confirmed = call("withdraw", "aave", Some(weth), "2");
+        confirmed["confirm"] = json!(true);
+        let unaccepted = execute_tool(&ctx, "lending", &confirmed).await.unwrap_err();
+        assert!(unaccepted.message().contains("It was NOT sent; repeat it with accept_risk=true"), "{}", unaccepted.message());
+        let liquidatable = execute_tool(&ctx, "lending", &call("withdraw", "aave", Some(weth), "all")).await.unwrap_err();
+        assert!(liquidatable.message().contains("Withdraw 5 WETH would leave a health factor of 0.00, below 1"), "{}", liquidatable.message());
+
+        let too_much = execute_tool(&ctx, "lending", &call("borrow", "aave", Some(usdc), "4000")).await.unwrap_err();
+        assert!(too_much.message().contains("can borrow only $3500.00 more from aave, less than the $4000.00 of 4000 USDC"), "{}", too_much.message());
+        let borrow = execute_tool(&ctx, "lending", &call("borrow", "aave", Some(usdc), "1000")).await.unwrap_err();
+        assert!(borrow.message().contains("Borrow 1000 USDC ($1000.00) from aave as") && borrow.message().contains("Health factor: 2.06 -> 1.65"), "{}", borrow.message());
+        let repay = execute_tool(&ctx, "lending", &call("repay", "aave", Some(usdc), "all")).await.unwrap_err();
+        assert!(repay.message().contains("Repay 4000 USDC ($4000.00) to aave as") && repay.message().contains("Health factor: 2.06 -> no debt"), "{}", repay.message());
+
+        let base_borrow = execute_tool(&ctx, "lending", &call("borrow", "compound_usdc", None, "100")).await.unwrap_err();
+        assert!(base_borrow.message().contains("Borrow 100 USDC ($100.00) from compound_usdc") && base_borrow.message().contains("Health factor: 1.20 -> 1.16\nWarning"), "{}", base_borrow.message());
+        let collateral_borrow = execute_tool(&ctx, "lending", &call("borrow", "compound_usdc", Some(weth), "1")).await.unwrap_err();
+        assert!(collateral_borrow.message().contains("Only the base asset of compound_usdc can be borrowed"), "{}", collateral_borrow.message());
+        let native = execute_tool(&ctx, "lending", &json!({ "operation": "supply", "market": "aave", "asset": "ETH", "amount": "1", "account": account })).await.unwrap_err();
+        assert!(native.message().contains("lend WETH rather than native ETH"), "{}", native.message());
+    }
+
+    #[test]
+    fn withdraws_all_compound_collateral_by_its_balance() {
+        let client = Arc::new(Provider::<Http>::try_from("http://127.0.0.1:9").unwrap());
+        let (comet, weth, usdc, owner) = (Address::repeat_byte(0x65), Address::repeat_byte(0x71), Address::repeat_byte(0x72), Address::repeat_byte(0x11));
+        let compound = Market { name: "compound_usdc".to_string(), contracts: Contracts::CompoundV3 { comet, base: usdc } };
+        let aave = Market { name: "aave".to_string(), contracts: Contracts::AaveV3 { pool: comet, oracle: comet, data_provider: comet } };
+        let reserve = |token: Address, base: bool| Reserve {
+            token: SwapToken { address: token, symbol: String::new(), decimals: 18, native: false },
+            price_usd: 1.0, liquidation_threshold: 0.0, collateral: !base, borrowable: base, base,
+            supplied: U256::exp10(18) * 2, borrowed: U256::zero(),
+        };
+        // withdraw(address,uint256) on both markets
+        let withdrawn = |market: &Market, reserve: &Reserve, everything: bool| {
+            let amount = call_amount(market, reserve, Action::Withdraw, U256::exp10(18), everything);
+            let (_, calldata) = action_call(client.clone(), market, Action::Withdraw, reserve.token.address, amount, owner);
+            U256::from_big_endian(&calldata[36..68])
+        };
+
+        assert_eq!(withdrawn(&compound, &reserve(weth, false), true), U256::exp10(18) * 2);
+        assert_eq!(withdrawn(&compound, &reserve(weth, false), false), U256::exp10(18));
+        assert_eq!(withdrawn(&compound, &reserve(usdc, true), true), U256::MAX);
+        assert_eq!(withdrawn(&aave, &reserve(weth, false), true), U256::MAX);
+    }
+}
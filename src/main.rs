@@ -1,58 +1,544 @@
+mod address_book;
+mod agent;
+mod alerts;
 mod anthropic;
+mod approvals;
+mod bridge;
+mod calc;
+mod chain_query;
+mod chains;
+mod cli;
+mod cluster;
+mod config;
+mod contracts;
+mod conversation;
 mod db;
+mod dex;
+#[cfg(feature = "discord")]
+mod discord;
+mod dune;
+mod events;
+mod export;
+mod faucet;
+mod fetch;
+#[cfg(all(test, feature = "anvil"))]
+mod fork_tests;
+mod gas;
+mod governor;
+mod grpc;
+mod images;
+mod injection_guard;
+mod ipfs;
+mod keystore;
+mod kms;
+mod lending;
+mod markdown;
+mod mcp;
+mod mcp_server;
+mod memory;
+mod mock_llm;
+mod models;
+mod multicall;
+mod nft;
+mod nonce;
+mod notify;
 mod personality;
+mod plugins;
+mod pnl;
+mod policy;
+mod prices;
+mod private_tx;
+mod provider;
+mod redact;
+mod reminders;
+mod repl;
+mod report;
+mod retention;
+mod rpc;
+mod safe;
+mod scheduler;
+mod search;
+mod server;
+mod signatures;
+mod signing;
+mod siwe;
+mod simulation;
+mod smart_account;
+mod snapshot;
+mod staking;
+mod store;
+#[cfg(test)]
+mod test_support;
+mod tool_cache;
+mod tool_error;
 mod tools;
+mod tracker;
+mod tui;
+mod units;
+mod usage;
+mod users;
+mod wallet;
+mod workspace;
 
-use db::{get_db_pool, save_message};
-use anthropic::call_anthropic_with_personality;
-use personality::load_personality;
-use tools::get_tools_as_json;
+use agent::Agent;
+use db::{create_session, get_db_pool, list_tool_calls, load_session_usage, search_messages, session_exists, MessageStats};
+use anthropic::{call_anthropic_with_personality, model_for, DEFAULT_THINKING_BUDGET};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{load_config, Config};
+use conversation::Conversation;
+use events::ChainEvent;
+use mcp_server::McpServer;
+use personality::{load_personality, persona_path, LivePersonality};
+use policy::{load_policy_engine, PolicyEngine};
+use scheduler::ScheduledRun;
+use tools::{get_available_tools, get_tools_as_json, ToolContext};
 use std::io::{self, Write};
-use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use usage::{TurnMeter, UsageTotals};
+use uuid::Uuid;
+
+// Number of stored messages preloaded when resuming a session
+const RESUME_HISTORY_LIMIT: i64 = 50;
+
+// Log to stderr, filtered by RUST_LOG (default: info, or warn for `ask`), as JSON when LOG_FORMAT=json
+fn init_tracing(cli: &Cli) {
+    // `ask` output is read by scripts, and its stderr often mailed by cron, so only problems are logged
+    let default = match cli.command {
+        Some(Command::Ask { .. }) => "warn",
+        _ => "info,sqlx=warn",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if cli.tui {
+        builder.with_ansi(false).with_writer(|| tui::LogPane).init();
+        return;
+    }
+    let builder = builder.with_writer(|| redact::RedactedStderr);
+    
+    if std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json")) {
+        builder.json().init();
+    } else {
+        builder.init();
+    }
+}
+
+// Number of tool calls shown by the /audit command
+const AUDIT_LIMIT: i64 = 20;
+
+// `/audit` lists this session's tool calls; `/audit all` lists them across sessions
+async fn print_audit(pool: Option<&sqlx::PgPool>, session_id: Uuid, scope: &str) {
+    let Some(pool) = pool else {
+        println!("The audit log requires a database (DATABASE_URL)");
+        return;
+    };
+    let session_filter = if scope == "all" { None } else { Some(session_id) };
+    match list_tool_calls(pool, session_filter, None, AUDIT_LIMIT).await {
+        Ok(records) if records.is_empty() => println!("No tool calls recorded yet."),
+        Ok(records) => {
+            for record in records.iter().rev() {
+                println!("[{}] {} {} ({} ms) args={}",
+                         record.created_at.format("%Y-%m-%d %H:%M:%S"),
+                         if record.success { "ok  " } else { "FAIL" },
+                         record.tool_name,
+                         record.duration_ms,
+                         record.args);
+            }
+        },
+        Err(e) => tracing::error!(error = %e, "Failed to load audit log"),
+    }
+}
+
+// Number of messages shown by the /search command
+const SEARCH_LIMIT: i64 = 10;
+
+// `/search <query>` finds messages across sessions, best match first
+async fn print_search(pool: Option<&sqlx::PgPool>, query: &str) {
+    let Some(pool) = pool else {
+        println!("Searching history requires a database (DATABASE_URL)");
+        return;
+    };
+    if query.is_empty() {
+        println!("Usage: /search <words, \"a phrase\", or -excluded>");
+        return;
+    }
+    match search_messages(pool, query, None, SEARCH_LIMIT).await {
+        Ok(hits) if hits.is_empty() => println!("No messages match '{}'.", query),
+        Ok(hits) => {
+            for hit in hits {
+                let at = hit.created_at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+                println!("[{}] {} {}: {}", at, hit.session_id, hit.role, hit.snippet.replace('\n', " "));
+            }
+        },
+        Err(e) => tracing::error!(error = %e, "Failed to search history"),
+    }
+}
+
+// Token usage of the current session: its stored totals (across runs) when persisted, otherwise this run's
+async fn session_usage(ctx: &ToolContext) -> UsageTotals {
+    if let (Some(pool), Some(session_id)) = (&ctx.pool, ctx.session_id) {
+        match load_session_usage(pool, session_id).await {
+            Ok(totals) => return totals,
+            Err(e) => tracing::error!(error = %e, "Failed to load session usage"),
+        }
+    }
+    *ctx.usage.lock().unwrap()
+}
+
+// Connect to the database and prepare the subsystems that depend on it
+async fn agent_db_pool(config: &Config) -> anyhow::Result<Option<sqlx::PgPool>> {
+    let pool = get_db_pool(&config.database).await;
+    if config.memory.enabled {
+        match &pool {
+            Some(pool) => memory::init(pool, &config.memory).await?,
+            None => tracing::warn!("Long-term memory is enabled but no database is available"),
+        }
+    }
+    Ok(pool)
+}
+
+// Load the configured persona and keep watching its file for edits
+fn load_agent_personality(config: &Config) -> anyhow::Result<LivePersonality> {
+    let path = persona_path(&config.personalities_dir, &config.persona)?;
+    match LivePersonality::watch(&path) {
+        Ok(live) => {
+            let p = live.current();
+            tracing::info!(name = %p.name, role = %p.role, path = %path.display(), "Loaded personality");
+            Ok(live)
+        },
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load personality");
+            Err(anyhow::anyhow!("Failed to load personality"))
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
-    let pool = get_db_pool().await;
+    redact::register_env_secrets();
+    let cli = Cli::parse();
+    init_tracing(&cli);
+    
+    // Load configuration (default config.toml) with env overrides
+    let mut config = load_config(&cli.config)?;
+    if let Some(persona) = cli.persona {
+        config.persona = persona;
+    }
+    if cli.dry_run {
+        config.dry_run = true;
+    }
+    let config = Arc::new(config);
+    let policy = Arc::new(load_policy_engine(&config.policy_path));
+    plugins::load(&config.plugins, &tools::builtin_tools()).await;
+    mcp::connect(&config.mcp, &tools::get_available_tools()).await;
+    cluster::connect(&config.redis).await;
+    wallet::load_stored_mnemonic(&config.signer.keystore_dir);
     
-    // Load personality
-    let personality_path = Path::new("assets/personality.json");
-    let personality = match load_personality(personality_path.to_str().unwrap()) {
-        Ok(p) => {
-            println!("Loaded personality: {} - {}", p.name, p.role);
-            p
+    match cli.command.unwrap_or(Command::Chat { session: None }) {
+        Command::Chat { session } => run_chat(config, policy, session, cli.verbose, cli.tui, cli.plain).await,
+        Command::Ask { prompt } => run_ask(config, policy, &prompt, cli.plain).await,
+        // Expose the HTTP API instead of the interactive CLI
+        Command::Serve { port } => {
+            let pool = get_db_pool(&config.database).await;
+            let personality = load_agent_personality(&config)?;
+            if let Some(pool) = &pool {
+                tracker::spawn(config.clone(), pool.clone());
+                alerts::spawn(config.clone(), policy.clone(), pool.clone());
+                reminders::spawn(config.clone(), pool.clone());
+                retention::spawn(config.clone(), pool.clone());
+            }
+            let port = port.unwrap_or(config.server.port);
+            server::serve(config, policy, pool, personality, port).await
         },
+        // Run the agent as a Discord bot instead of the interactive CLI
+        #[cfg(feature = "discord")]
+        Command::Discord => {
+            let pool = agent_db_pool(&config).await?;
+            let personality = load_agent_personality(&config)?;
+            if let Some(pool) = &pool {
+                tracker::spawn(config.clone(), pool.clone());
+                alerts::spawn(config.clone(), policy.clone(), pool.clone());
+                reminders::spawn(config.clone(), pool.clone());
+                retention::spawn(config.clone(), pool.clone());
+            }
+            // Results are stored and posted to each job's webhook
+            scheduler::spawn(config.clone(), policy.clone(), pool.clone(), |_| {});
+            discord::run(config, policy, pool, personality).await
+        },
+        #[cfg(not(feature = "discord"))]
+        Command::Discord => Err(anyhow::anyhow!("Discord support is not enabled; rebuild with `--features discord`")),
+        // Let other agents and MCP clients use the tools without the chat
+        Command::McpServe { port } => {
+            let pool = get_db_pool(&config.database).await;
+            let path = persona_path(&config.personalities_dir, &config.persona)?;
+            let personality = load_personality(&path.to_string_lossy())?;
+            let server = McpServer::new(ToolContext::new(config, policy, pool, None), Some(personality));
+            match port {
+                Some(port) => mcp_server::serve_http(server, port).await,
+                None => mcp_server::serve_stdio(server).await,
+            }
+        },
+        // Typed clients in other languages, generated from proto/agent.proto
+        Command::GrpcServe { port } => {
+            let pool = agent_db_pool(&config).await?;
+            let path = persona_path(&config.personalities_dir, &config.persona)?;
+            let personality = load_personality(&path.to_string_lossy())?;
+            if let Some(pool) = &pool {
+                tracker::spawn(config.clone(), pool.clone());
+                retention::spawn(config.clone(), pool.clone());
+            }
+            grpc::serve(ToolContext::new(config, policy, pool, None), Some(personality), port).await
+        },
+        // Let editors and other host programs drive the chat without a terminal
+        Command::Rpc => {
+            let pool = agent_db_pool(&config).await?;
+            let path = persona_path(&config.personalities_dir, &config.persona)?;
+            let personality = load_personality(&path.to_string_lossy())?;
+            if let Some(pool) = &pool {
+                tracker::spawn(config.clone(), pool.clone());
+                retention::spawn(config.clone(), pool.clone());
+            }
+            let server = rpc::RpcServer::new(ToolContext::new(config, policy, pool, None), Some(personality));
+            rpc::serve_stdio(server).await
+        },
+        Command::Plan { request } => {
+            let pool = get_db_pool(&config.database).await;
+            let path = persona_path(&config.personalities_dir, &config.persona)?;
+            let personality = load_personality(&path.to_string_lossy())?;
+            let agent = Agent::new(ToolContext::new(config, policy, pool, None), Some(personality))?;
+            println!("{}", serde_json::to_string_pretty(&agent.plan(&request).await?)?);
+            Ok(())
+        },
+        Command::Wallet(command) => {
+            let pool = get_db_pool(&config.database).await;
+            let tool_context = ToolContext::new(config, policy, pool, None);
+            cli::run_wallet(&tool_context, command).await
+        },
+        Command::Tools(command) => cli::run_tools(command),
+        Command::Export { session, format, output } => {
+            cli::run_export(get_db_pool(&config.database).await.as_ref(), session, format, output.as_deref()).await
+        },
+        Command::Import { file } => cli::run_import(get_db_pool(&config.database).await.as_ref(), &file).await,
+        Command::Db(command) => cli::run_db(&config, get_db_pool(&config.database).await.as_ref(), command).await,
+        Command::Users(command) => cli::run_users(get_db_pool(&config.database).await.as_ref(), command).await,
+    }
+}
+
+// Budget `/think` turns thinking on with: the configured one, or a default when it is off
+fn thinking_budget(config: &Config) -> u32 {
+    match config.anthropic.thinking_budget_tokens {
+        0 => DEFAULT_THINKING_BUDGET,
+        tokens => tokens,
+    }
+}
+
+/// Store a chat message in the context's history store, with what its turn took for answers,
+/// and remember it for long-term memory when that is on. The id it was stored under, if it was.
+async fn record_message(ctx: &ToolContext, session_id: Uuid, role: &str, content: &str, model: &str, stats: Option<MessageStats>) -> Option<i32> {
+    let id = match ctx.store.save_message(session_id, role, content, model, stats).await {
+        Ok(id) => Some(id),
         Err(e) => {
-            println!("Failed to load personality: {}", e);
-            return Err(anyhow::anyhow!("Failed to load personality"));
+            tracing::error!(error = %e, role, "Failed to save message");
+            None
+        },
+    };
+    if let Some(pool) = &ctx.pool
+        && let Err(e) = memory::remember(pool, &ctx.config.memory, session_id, role, content).await
+    {
+        tracing::warn!(error = %e, role, "Failed to store message in memory");
+    }
+    id
+}
+
+// Exit statuses of `ask` besides 0 (answered) and 1 (the turn failed)
+const EXIT_EMPTY_PROMPT: i32 = 2;
+const EXIT_TOOL_FAILED: i32 = 3;
+
+/// Answer one prompt (`-` reads it from stdin) in a new session, for scripts and cron jobs:
+/// only the answer goes to stdout
+async fn run_ask(config: Arc<Config>, policy: Arc<PolicyEngine>, prompt: &str, plain: bool) -> anyhow::Result<()> {
+    let prompt = match prompt {
+        "-" => io::read_to_string(io::stdin())?,
+        prompt => prompt.to_string(),
+    };
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        eprintln!("Error: the prompt is empty");
+        std::process::exit(EXIT_EMPTY_PROMPT);
+    }
+
+    let pool = agent_db_pool(&config).await?;
+    let path = persona_path(&config.personalities_dir, &config.persona)?;
+    let personality = load_personality(&path.to_string_lossy())?;
+    let session_id = Uuid::new_v4();
+    if let Some(pool) = &pool
+        && let Err(e) = create_session(pool, session_id).await
+    {
+        tracing::error!(error = %e, "Failed to create session");
+    }
+    let mut ctx = ToolContext::new(config.clone(), policy, pool.clone(), Some(session_id));
+    // Outcomes of the turn's tool calls, to exit with EXIT_TOOL_FAILED if one failed
+    let activity = Arc::new(std::sync::Mutex::new(Vec::new()));
+    ctx.activity = Some(activity.clone());
+
+    let model = model_for(&config.anthropic, Some(&personality));
+    ctx.message_id = record_message(&ctx, session_id, "user", prompt, &model, None).await;
+    let meter = TurnMeter::start(&ctx);
+    let reply = call_anthropic_with_personality(&config.anthropic, prompt, &[], Some(&personality), &Conversation::default(), &ctx).await?;
+    record_message(&ctx, session_id, "assistant", &reply, &model, Some(meter.stats(&ctx))).await;
+
+    if markdown::enabled(plain) {
+        print!("{}", markdown::render(&reply));
+    } else {
+        println!("{}", reply);
+    }
+    let tool_failed = activity.lock().unwrap().iter()
+        .any(|line| !line.starts_with("→ ") && !line.starts_with("ok "));
+    if tool_failed {
+        io::stdout().flush()?;
+        std::process::exit(EXIT_TOOL_FAILED);
+    }
+    Ok(())
+}
+
+async fn run_chat(config: Arc<Config>, policy: Arc<PolicyEngine>, session: Option<Uuid>, verbose: bool, tui: bool, plain: bool) -> anyhow::Result<()> {
+    let pool = agent_db_pool(&config).await?;
+    let live_personality = load_agent_personality(&config)?;
+    
+    // Start a new session, or resume an earlier one with its history preloaded
+    let mut conversation = Conversation::default();
+    let session_id = match session {
+        Some(id) => {
+            let pool = pool.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Resuming a session requires DATABASE_URL"))?;
+            if !session_exists(pool, id).await? {
+                return Err(anyhow::anyhow!("Session {} not found", id));
+            }
+            conversation = Conversation::load(pool, id, RESUME_HISTORY_LIMIT).await?;
+            println!("Resumed session {} ({} messages{})", id, conversation.messages.len(),
+                     if conversation.summary.is_some() { " plus a summary of earlier turns" } else { "" });
+            id
+        },
+        None => {
+            let id = Uuid::new_v4();
+            if let Some(pool) = &pool
+                && let Err(e) = create_session(pool, id).await
+            {
+                tracing::error!(error = %e, "Failed to create session");
+            }
+            println!("Started session {} (resume with --session {})", id, id);
+            id
         }
     };
     
+    let mut tool_context = ToolContext::new(config.clone(), policy.clone(), pool.clone(), Some(session_id));
+    if verbose {
+        tool_context.thinking = Some(Arc::default());
+    }
+    // `/think` changes the thinking budget for the rest of the session
+    let mut anthropic_config = config.anthropic.clone();
+    // Model picked with `/model`, for the rest of the session
+    let mut session_model: Option<String> = None;
+    // Images attached with `/image`, sent with the next message
+    let mut pending_images = Vec::new();
+    
+    // Follow submitted transactions and check price alerts and reminders in the background; outcomes are shown at the prompt
+    if let Some(pool) = &pool {
+        tracker::spawn(config.clone(), pool.clone());
+        alerts::spawn(config.clone(), policy.clone(), pool.clone());
+        reminders::spawn(config.clone(), pool.clone());
+    }
+    
+    // Logs matching the configured event filters arrive alongside user input
+    let (event_sender, mut chain_events) = mpsc::unbounded_channel::<ChainEvent>();
+    events::spawn(config.clone(), move |event| {
+        let _ = event_sender.send(event);
+    });
+    
+    // Results of scheduled jobs are shown as they finish
+    let (run_sender, mut scheduled_runs) = mpsc::unbounded_channel::<ScheduledRun>();
+    scheduler::spawn(config.clone(), policy, pool.clone(), move |run| {
+        let _ = run_sender.send(run);
+    });
+    
+    if tui {
+        return tui::run(tui::ChatSession {
+            config, anthropic: anthropic_config, pool, session_id, personality: live_personality,
+            conversation, ctx: tool_context, chain_events, scheduled_runs,
+        }).await;
+    }
+    let tool_names = get_available_tools().into_iter().map(|tool| tool.name).collect();
+    let model_names = config.models.models.keys().cloned().collect();
+    let mut input = repl::LineReader::spawn(&config.history_path, tool_names, model_names)?;
+    let render_markdown = markdown::enabled(plain);
+    
     // Load available tools
     match get_tools_as_json() {
         Ok(tools_json) => {
-            println!("Loaded tools: {}", tools_json);
+            tracing::debug!(tools = %tools_json, "Loaded tools");
         },
         Err(e) => {
-            println!("Failed to load tools: {}", e);
+            tracing::error!(error = %e, "Failed to load tools");
         }
     };
     
+    let personality = live_personality.current();
     println!("Welcome to Agent Friend! I'm {}, your {}.", personality.name, personality.role);
-    println!("Type 'exit' to quit.");
+    println!("Type 'exit' to quit, '/audit' to see the tools used in this session, '/cost' for its token usage, '/search <words>' to find earlier messages, '/schedule' for scheduled jobs, '/wallets' for your accounts and balances, '/pnl [account]' for cost basis and profit and loss, '/image <path or URL>' to attach an image, '/model' to switch models, or '/think' to toggle extended thinking.");
+    if config.dry_run {
+        println!("Dry-run mode: transactions are simulated and reported, never sent.");
+    }
     
     loop {
-        // Prompt for user input
-        print!("You: ");
-        io::stdout().flush()?;
+        for update in tracker::take_updates(pool.as_ref(), session_id).await {
+            println!("Update: {}", update);
+        }
+        for update in gas::take_updates(session_id) {
+            println!("Update: {}", update);
+        }
+        for alert in alerts::take_updates(pool.as_ref(), session_id).await {
+            println!("Alert: {}", alert);
+        }
+        for reminder in reminders::take_updates(pool.as_ref(), session_id).await {
+            println!("{}", reminder);
+        }
+        
+        // Prompt for user input (still pending if an event interrupted the wait)
+        input.prompt("You: ");
         
-        // Read user input
-        let mut user_input = String::new();
-        io::stdin().read_line(&mut user_input)?;
-        let user_input = user_input.trim();
+        // Wait for user input, an on-chain event to show or have the agent react to, or a scheduled job's result
+        let (line, from_event) = tokio::select! {
+            line = input.recv() => match line {
+                repl::Input::Line(line) => (line, false),
+                // Ctrl-C at the prompt quits, as Ctrl-D does
+                repl::Input::Interrupted | repl::Input::Eof => break,
+            },
+            Some(event) = chain_events.recv() => {
+                println!("\rEvent: {}", event.description);
+                if !config.events.trigger_agent {
+                    continue;
+                }
+                (event.prompt(), true)
+            },
+            Some(run) = scheduled_runs.recv() => {
+                println!("\rScheduled ({}{}): {}", run.job, if run.success { "" } else { ", failed" }, run.output);
+                continue;
+            },
+            // Ctrl-C only stops a running turn, so at the prompt it quits as usual (the line
+            // editor reports it itself when stdin is a terminal)
+            _ = tokio::signal::ctrl_c() => {
+                println!();
+                break;
+            },
+        };
+        let user_input = line.trim();
         
         // Check if user wants to exit
-        if user_input.to_lowercase() == "exit" {
+        if !from_event && user_input.to_lowercase() == "exit" {
+            println!("Session usage: {}", session_usage(&tool_context).await.summary());
             println!("Goodbye!");
             break;
         }
@@ -62,28 +548,155 @@ async fn main() -> anyhow::Result<()> {
             continue;
         }
         
-        // Save user message to database if pool is available
-        if let Some(pool) = &pool {
-            if let Err(e) = save_message(pool, "user", user_input).await {
-                eprintln!("Failed to save user message: {}", e);
+        // Slash commands are handled locally instead of going to the model
+        if !from_event
+            && let Some(command) = user_input.strip_prefix('/')
+        {
+            let mut parts = command.split_whitespace();
+            match parts.next().unwrap_or("") {
+                "audit" => print_audit(pool.as_ref(), session_id, parts.next().unwrap_or("")).await,
+                "cost" => println!("Session usage: {}", session_usage(&tool_context).await.summary()),
+                "search" => print_search(pool.as_ref(), &parts.collect::<Vec<_>>().join(" ")).await,
+                "wallets" => match tools::wallets_report(&tool_context).await {
+                    Ok(report) => println!("{}", report),
+                    Err(e) => eprintln!("Error: {}", e.message()),
+                },
+                "pnl" => match tools::pnl_positions(&tool_context, parts.next(), None).await {
+                    Ok(report) => println!("{}", report),
+                    Err(e) => eprintln!("Error: {}", e.message()),
+                },
+                "schedule" => {
+                    let jobs = scheduler::status(&config, pool.as_ref()).await;
+                    if jobs.is_empty() {
+                        println!("No scheduled jobs are configured");
+                    }
+                    for job in jobs {
+                        println!("{}", job);
+                    }
+                },
+                "image" => {
+                    let reference = parts.collect::<Vec<_>>().join(" ");
+                    if reference.is_empty() {
+                        println!("Usage: /image <path or URL>");
+                        continue;
+                    }
+                    match images::load(&reference) {
+                        Ok(image) => {
+                            pending_images.push(image);
+                            println!("Attached {} ({} image(s) will go with your next message)", reference, pending_images.len());
+                        },
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                },
+                "model" => match parts.next() {
+                    None => {
+                        let active = session_model.clone().unwrap_or_else(|| model_for(&anthropic_config, Some(&live_personality.current())));
+                        println!("Active model: {}", active);
+                        if config.models.models.is_empty() {
+                            println!("No other models are registered in {}", config.models_path);
+                        }
+                        for line in config.models.listing(&active) {
+                            println!("{}", line);
+                        }
+                    },
+                    Some(name) => match config.models.check(name) {
+                        Ok(model) => {
+                            println!("Switched to {} for the rest of this session", name);
+                            if let Some(context_tokens) = model.context_tokens
+                                && context_tokens < config.context.max_history_tokens
+                            {
+                                println!("Its context window ({} tokens) is smaller than [context] max_history_tokens, so long histories may not fit", context_tokens);
+                            }
+                            session_model = Some(name.to_string());
+                        },
+                        Err(e) => eprintln!("Error: {}", e),
+                    },
+                },
+                "think" => {
+                    let budget = match parts.next() {
+                        Some("off") => 0,
+                        Some("on") => thinking_budget(&config),
+                        Some(tokens) => match tokens.parse() {
+                            Ok(tokens) => tokens,
+                            Err(_) => {
+                                println!("Usage: /think [on|off|<budget tokens>]");
+                                continue;
+                            },
+                        },
+                        None if anthropic_config.thinking_budget_tokens > 0 => 0,
+                        None => thinking_budget(&config),
+                    };
+                    anthropic_config.thinking_budget_tokens = budget;
+                    if budget > 0 {
+                        println!("Extended thinking is on, with up to {} tokens per response", budget);
+                    } else {
+                        println!("Extended thinking is off");
+                    }
+                },
+                other => println!("Unknown command: /{}", other),
             }
+            continue;
         }
         
-        // Get response from Claude with personality
+        // Answer with the personality as of this turn (its file may have been edited), and the
+        // model picked with `/model` over the persona's own
+        let mut personality = live_personality.current();
+        if let Some(model) = &session_model {
+            personality.model = Some(model.clone());
+        }
+        let model = model_for(&anthropic_config, Some(&personality));
+        
+        // Save user message to database if pool is available; the turn's tool calls link to it
+        tool_context.message_id = record_message(&tool_context, session_id, "user", user_input, &model, None).await;
+        
+        // Get response from Claude
         print!("{} is thinking...", personality.name);
         io::stdout().flush()?;
-        let reply = call_anthropic_with_personality(user_input, Some(&personality)).await?;
+        // Summarize older turns if the history has grown too large for the context window
+        if let Err(e) = conversation.compact(&tool_context).await {
+            tracing::warn!(error = %e, "Failed to compact conversation history");
+        }
+        if let Some(thinking) = &tool_context.thinking {
+            thinking.lock().unwrap().clear();
+        }
+        // Attachments go with the user's next message, not with an event the agent reacts to
+        let images = if from_event { Vec::new() } else { std::mem::take(&mut pending_images) };
+        let meter = TurnMeter::start(&tool_context);
+        // Ctrl-C abandons the turn, along with the tool call it is waiting on
+        let turn = tokio::select! {
+            reply = call_anthropic_with_personality(&anthropic_config, user_input, &images, Some(&personality), &conversation, &tool_context) => reply,
+            _ = tokio::signal::ctrl_c() => {
+                println!("\rStopped. Transactions already sent this turn are not undone; /audit lists its tool calls.");
+                continue;
+            },
+        };
+        let reply = match turn {
+            Ok(reply) => reply,
+            Err(e) => {
+                println!("\r");
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
         println!("\r"); // Clear the "thinking" message
         
         // Save assistant message to database if pool is available
-        if let Some(pool) = &pool {
-            if let Err(e) = save_message(pool, "assistant", &reply).await {
-                eprintln!("Failed to save assistant message: {}", e);
+        record_message(&tool_context, session_id, "assistant", &reply, &model, Some(meter.stats(&tool_context))).await;
+        
+        conversation.push("user", user_input);
+        conversation.push("assistant", &reply);
+        
+        // Display the response, after the model's thinking when it is shown
+        if let Some(thinking) = &tool_context.thinking {
+            for thought in thinking.lock().unwrap().drain(..) {
+                println!("(thinking) {}\n", thought);
             }
         }
-        
-        // Display the response
-        println!("{}: {}", personality.name, reply);
+        if render_markdown {
+            print!("{}:\n{}", personality.name, markdown::render(&reply));
+        } else {
+            println!("{}: {}", personality.name, reply);
+        }
     }
     
     Ok(())
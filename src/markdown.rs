@@ -0,0 +1,116 @@
+// Replies rendered for the terminal: headers, emphasis, lists, and tables through termimad,
+// and fenced code blocks highlighted for their language with syntect. With `--plain`, or when
+// stdout isn't a terminal, replies are printed as the model wrote them.
+
+use lazy_static::lazy_static;
+use std::io::IsTerminal;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use termimad::MadSkin;
+
+// Theme of highlighted code, from syntect's defaults
+const CODE_THEME: &str = "base16-ocean.dark";
+
+lazy_static! {
+    static ref SKIN: MadSkin = MadSkin::default();
+    static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME: Theme = ThemeSet::load_defaults().themes.remove(CODE_THEME).unwrap_or_default();
+}
+
+/// Whether replies are rendered rather than printed as written
+pub fn enabled(plain: bool) -> bool {
+    !plain && std::io::stdout().is_terminal()
+}
+
+/// `text` rendered for the terminal, wrapped to its width
+pub fn render(text: &str) -> String {
+    render_to(text, termimad::terminal_size().0 as usize)
+}
+
+fn render_to(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut prose = String::new();
+    // Language and lines of the code block being read, between its fences
+    let mut code: Option<(String, String)> = None;
+    for line in LinesWithEndings::from(text) {
+        let fence = line.trim_start().strip_prefix("```").map(str::trim);
+        match (&mut code, fence) {
+            (None, Some(language)) => {
+                out.push_str(&render_prose(&std::mem::take(&mut prose), width));
+                code = Some((language.to_string(), String::new()));
+            },
+            (None, None) => prose.push_str(line),
+            (Some((language, lines)), Some("")) => {
+                out.push_str(&highlight(language, lines));
+                code = None;
+            },
+            (Some((_, lines)), _) => lines.push_str(line),
+        }
+    }
+    out.push_str(&render_prose(&prose, width));
+    // A block the model didn't close runs to the end of the reply
+    if let Some((language, lines)) = code {
+        out.push_str(&highlight(&language, &lines));
+    }
+    out
+}
+
+fn render_prose(prose: &str, width: usize) -> String {
+    if prose.trim().is_empty() {
+        return String::new();
+    }
+    SKIN.text(prose, Some(width)).to_string()
+}
+
+// Code in the colours of its language, or as plain text when the language isn't known
+fn highlight(language: &str, code: &str) -> String {
+    let syntax = SYNTAXES.find_syntax_by_token(language).unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+    let mut out = String::new();
+    for line in LinesWithEndings::from(code) {
+        match highlighter.highlight_line(line, &SYNTAXES) {
+            Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+            Err(_) => out.push_str(line),
+        }
+    }
+    out.push_str("\x1b[0m");
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_escapes(text: &str) -> String {
+        regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap().replace_all(text, "").into_owned()
+    }
+
+    #[test]
+    fn highlights_fenced_code_and_keeps_its_text() {
+        let reply = "Deploy it with:\n\n```rust\nlet wallet = LocalWallet::new(&mut rng);\n```\n\nThen fund it.";
+        let rendered = render_to(reply, 80);
+        assert!(rendered.contains("\x1b[38;2;"), "code is coloured: {:?}", rendered);
+        let text = strip_escapes(&rendered);
+        assert!(text.contains("let wallet = LocalWallet::new(&mut rng);"));
+        assert!(text.contains("Deploy it with:") && text.contains("Then fund it."));
+        assert!(!text.contains("```"));
+    }
+
+    #[test]
+    fn renders_markdown_markup() {
+        let text = strip_escapes(&render_to("# Balances\n\n- **ETH**: 1.5\n- **USDC**: 200", 80));
+        assert!(text.contains("Balances"));
+        assert!(text.contains("ETH") && !text.contains("**"));
+    }
+
+    #[test]
+    fn unclosed_code_blocks_run_to_the_end() {
+        let text = strip_escapes(&render_to("```\ncast balance vitalik.eth", 80));
+        assert!(text.contains("cast balance vitalik.eth"));
+    }
+}
@@ -0,0 +1,432 @@
+// Client for Model Context Protocol servers declared under `[mcp.servers]`. Servers are
+// started (stdio) or reached (streamable HTTP) at startup, and their tools are offered to the
+// model as `<server>__<tool>`. Resources are read through the `mcp_resources` tool.
+
+use crate::config::{McpConfig, McpServerConfig};
+use crate::tool_error::ToolError;
+use crate::tools::Tool;
+use lazy_static::lazy_static;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin};
+use tokio::sync::oneshot;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+// Time a server gets to start, initialize, and list what it offers
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Tool that lists and reads the resources of the connected servers
+const RESOURCES_TOOL: &str = "mcp_resources";
+
+lazy_static! {
+    static ref SERVERS: RwLock<Vec<Arc<Server>>> = RwLock::new(Vec::new());
+}
+
+/// A connected server and the tools it offers
+struct Server {
+    name: String,
+    transport: Transport,
+    // Tools by the name the model sees, with the server's own name for each
+    tools: Vec<(Tool, String)>,
+    resources: bool,
+}
+
+enum Transport {
+    Stdio(StdioTransport),
+    Http(HttpTransport),
+}
+
+// Requests awaiting a response, by JSON-RPC id
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<serde_json::Value>>>>;
+
+/// A server run as a child process, exchanging newline-delimited JSON-RPC on stdin/stdout
+struct StdioTransport {
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Pending,
+    next_id: AtomicU64,
+    // Killed when the transport is dropped
+    _child: Child,
+}
+
+/// A server reached over streamable HTTP, answering each POST with JSON or an event stream
+struct HttpTransport {
+    client: Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    session_id: Mutex<Option<String>>,
+    next_id: AtomicU64,
+}
+
+impl Transport {
+    async fn request(&self, method: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let mut response = match self {
+            Transport::Stdio(stdio) => stdio.request(method, params).await?,
+            Transport::Http(http) => http.request(method, params).await?,
+        };
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+            return Err(anyhow::anyhow!("{} failed: {}", method, message));
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn notify(&self, method: &str) -> anyhow::Result<()> {
+        let message = serde_json::json!({ "jsonrpc": "2.0", "method": method });
+        match self {
+            Transport::Stdio(stdio) => write_line(&stdio.stdin, &message).await,
+            Transport::Http(http) => http.post(&message).await.map(|_| ()),
+        }
+    }
+}
+
+async fn write_line(stdin: &tokio::sync::Mutex<ChildStdin>, message: &serde_json::Value) -> anyhow::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+impl StdioTransport {
+    fn spawn(name: &str, command: &str, config: &McpServerConfig) -> anyhow::Result<Self> {
+        let mut child = tokio::process::Command::new(command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Could not start {}: {}", command, e))?;
+        let stdin = Arc::new(tokio::sync::Mutex::new(child.stdin.take().expect("stdin is piped")));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let pending: Pending = Arc::default();
+
+        // Responses go to whoever is waiting on their id. Servers may ping us; we don't offer
+        // anything else they could ask for.
+        let (server, replies, waiting) = (name.to_string(), stdin.clone(), pending.clone());
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+                    tracing::debug!(server, line, "Ignoring non-JSON output of MCP server");
+                    continue;
+                };
+                match (message.get("id"), message.get("method").and_then(|m| m.as_str())) {
+                    (Some(id), Some(method)) => {
+                        let reply = match method {
+                            "ping" => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+                            _ => serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "Method not found" } }),
+                        };
+                        if let Err(e) = write_line(&replies, &reply).await {
+                            tracing::warn!(server, error = %e, "Could not answer MCP server request");
+                        }
+                    },
+                    (Some(id), None) => {
+                        let sender = id.as_u64().and_then(|id| waiting.lock().unwrap().remove(&id));
+                        if let Some(sender) = sender {
+                            sender.send(message).ok();
+                        }
+                    },
+                    _ => {},
+                }
+            }
+            // Dropping the senders fails the requests still waiting
+            waiting.lock().unwrap().clear();
+            tracing::warn!(server, "MCP server exited");
+        });
+        let server = name.to_string();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                tracing::debug!(server, line, "MCP server log");
+            }
+        });
+
+        Ok(StdioTransport { stdin, pending, next_id: AtomicU64::new(1), _child: child })
+    }
+
+    async fn request(&self, method: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let message = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = write_line(&self.stdin, &message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+        receiver.await.map_err(|_| anyhow::anyhow!("The MCP server exited before answering {}", method))
+    }
+}
+
+impl HttpTransport {
+    fn new(url: &str, config: &McpServerConfig) -> Self {
+        HttpTransport {
+            client: Client::new(),
+            url: url.to_string(),
+            headers: config.headers.iter().map(|(name, value)| (name.clone(), value.clone())).collect(),
+            session_id: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    async fn post(&self, message: &serde_json::Value) -> anyhow::Result<reqwest::Response> {
+        let mut request = self.client.post(&self.url)
+            .header("Accept", "application/json, text/event-stream")
+            .header("MCP-Protocol-Version", PROTOCOL_VERSION)
+            .json(message);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let session_id = self.session_id.lock().unwrap().clone();
+        if let Some(session_id) = session_id {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+        let response = request.send().await?.error_for_status()?;
+        // The server names the session in its answer to initialize
+        if let Some(session_id) = response.headers().get("Mcp-Session-Id").and_then(|v| v.to_str().ok()) {
+            *self.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+        Ok(response)
+    }
+
+    async fn request(&self, method: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response = self.post(&message).await?;
+        let is_stream = response.headers().get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+        if !is_stream {
+            return Ok(response.json().await?);
+        }
+        // The stream may carry notifications before the response; the server ends it after
+        let body = response.text().await?;
+        body.split("\n\n")
+            .map(|event| event.lines().filter_map(|line| line.strip_prefix("data:")).map(str::trim_start).collect::<Vec<_>>().join("\n"))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+            .find(|message| message.get("id").and_then(|v| v.as_u64()) == Some(id) && message.get("method").is_none())
+            .ok_or_else(|| anyhow::anyhow!("The MCP server did not answer {}", method))
+    }
+}
+
+// A tool name the model accepts: letters, digits, `_` and `-`, at most 64 characters
+fn tool_name(server: &str, tool: &str) -> String {
+    format!("{}__{}", server, tool).chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+// Every page of a list method, concatenated
+async fn list_all(transport: &Transport, method: &str, field: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut items = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let params = match &cursor {
+            Some(cursor) => serde_json::json!({ "cursor": cursor }),
+            None => serde_json::json!({}),
+        };
+        let result = transport.request(method, params).await?;
+        items.extend(result[field].as_array().cloned().unwrap_or_default());
+        match result.get("nextCursor").and_then(|v| v.as_str()) {
+            Some(next) => cursor = Some(next.to_string()),
+            None => return Ok(items),
+        }
+    }
+}
+
+async fn connect_server(name: &str, config: &McpServerConfig, taken: &[String]) -> anyhow::Result<Server> {
+    let transport = match (&config.command, &config.url) {
+        (Some(command), None) => Transport::Stdio(StdioTransport::spawn(name, command, config)?),
+        (None, Some(url)) => Transport::Http(HttpTransport::new(url, config)),
+        _ => return Err(anyhow::anyhow!("Set either command or url")),
+    };
+    let result = transport.request("initialize", serde_json::json!({
+        "protocolVersion": PROTOCOL_VERSION,
+        "capabilities": {},
+        "clientInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+    })).await?;
+    transport.notify("notifications/initialized").await?;
+    let capabilities = &result["capabilities"];
+
+    let mut tools = Vec::new();
+    if capabilities.get("tools").is_some() {
+        for tool in list_all(&transport, "tools/list", "tools").await? {
+            let Some(original) = tool["name"].as_str() else { continue };
+            let exposed = tool_name(name, original);
+            if taken.contains(&exposed) {
+                tracing::warn!(server = name, tool = original, "Skipping MCP tool whose name is already taken");
+                continue;
+            }
+            let description = match tool["description"].as_str() {
+                Some(description) => format!("{} (from the {} MCP server)", description, name),
+                None => format!("{} tool of the {} MCP server", original, name),
+            };
+            let input_schema = tool.get("inputSchema").cloned().unwrap_or_else(|| serde_json::json!({ "type": "object" }));
+            tools.push((Tool { name: exposed, description, input_schema }, original.to_string()));
+        }
+    }
+    Ok(Server { name: name.to_string(), transport, tools, resources: capabilities.get("resources").is_some() })
+}
+
+/// Connect to the servers under `[mcp.servers]`, skipping (and logging) any that fail to
+/// start or answer. Tools whose name is in `taken` are left out.
+pub async fn connect(config: &McpConfig, taken: &[Tool]) {
+    let taken: Vec<String> = taken.iter().map(|tool| tool.name.clone()).collect();
+    let connecting = config.servers.iter().map(|(name, server)| {
+        let taken = &taken;
+        async move {
+            let result = tokio::time::timeout(CONNECT_TIMEOUT, connect_server(name, server, taken)).await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("No answer within {} seconds", CONNECT_TIMEOUT.as_secs())));
+            match result {
+                Ok(server) => {
+                    tracing::info!(server = name, tools = server.tools.len(), resources = server.resources, "Connected to MCP server");
+                    Some(Arc::new(server))
+                },
+                Err(e) => {
+                    tracing::warn!(server = name, error = %e, "Could not connect to MCP server");
+                    None
+                },
+            }
+        }
+    });
+    let servers = futures::future::join_all(connecting).await.into_iter().flatten().collect();
+    *SERVERS.write().unwrap() = servers;
+}
+
+/// Tools of the connected servers, plus `mcp_resources` when any of them has resources
+pub fn tools() -> Vec<Tool> {
+    let servers = SERVERS.read().unwrap();
+    let mut tools: Vec<Tool> = servers.iter()
+        .flat_map(|server| server.tools.iter().map(|(tool, _)| tool.clone()))
+        .collect();
+    let with_resources: Vec<&str> = servers.iter().filter(|server| server.resources).map(|server| server.name.as_str()).collect();
+    if !with_resources.is_empty() {
+        tools.push(Tool {
+            name: RESOURCES_TOOL.to_string(),
+            description: "List or read the resources (files, documents, records) of connected MCP servers".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["list", "read"],
+                        "description": "list resources, or read one by URI"
+                    },
+                    "server": {
+                        "type": "string",
+                        "enum": with_resources,
+                        "description": "Server to ask (list: optional, defaults to all)"
+                    },
+                    "uri": {
+                        "type": "string",
+                        "description": "URI of the resource to read (for read)"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        });
+    }
+    tools
+}
+
+/// Run an MCP tool, or `mcp_resources`; None when `name` is neither
+pub async fn execute(name: &str, args: &serde_json::Value) -> Option<Result<String, ToolError>> {
+    if name == RESOURCES_TOOL {
+        return Some(resources(args).await);
+    }
+    let (server, original) = SERVERS.read().unwrap().iter().find_map(|server| {
+        server.tools.iter().find(|(tool, _)| tool.name == name).map(|(_, original)| (server.clone(), original.clone()))
+    })?;
+    let result = server.transport.request("tools/call", serde_json::json!({ "name": original, "arguments": args })).await
+        .map_err(|e| ToolError::RpcError(format!("{} MCP server: {}", server.name, e)));
+    Some(result.and_then(|result| {
+        let output = content_text(&result);
+        if result["isError"].as_bool() == Some(true) {
+            Err(ToolError::Failed(output))
+        } else {
+            Ok(output)
+        }
+    }))
+}
+
+// What a tool result or resource says, as text. Content the model can't be given as text is
+// described instead.
+fn content_text(result: &serde_json::Value) -> String {
+    let parts: Vec<String> = result["content"].as_array().into_iter().flatten()
+        .map(|block| match block["type"].as_str() {
+            Some("text") => block["text"].as_str().unwrap_or_default().to_string(),
+            Some("resource") => match block["resource"]["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("[resource {}]", block["resource"]["uri"].as_str().unwrap_or_default()),
+            },
+            Some("resource_link") => format!("[resource {}]", block["uri"].as_str().unwrap_or_default()),
+            Some(kind) => format!("[{} content, {}]", kind, block["mimeType"].as_str().unwrap_or("unknown type")),
+            None => block.to_string(),
+        })
+        .collect();
+    match result.get("structuredContent") {
+        Some(structured) if parts.is_empty() => structured.to_string(),
+        _ => parts.join("\n"),
+    }
+}
+
+async fn resources(args: &serde_json::Value) -> Result<String, ToolError> {
+    let operation = args.get("operation").and_then(|v| v.as_str()).unwrap_or("list");
+    let name = args.get("server").and_then(|v| v.as_str());
+    let servers: Vec<Arc<Server>> = SERVERS.read().unwrap().iter()
+        .filter(|server| server.resources && name.is_none_or(|name| server.name == name))
+        .cloned()
+        .collect();
+    if servers.is_empty() {
+        return Err(ToolError::InvalidArgs(match name {
+            Some(name) => format!("No connected MCP server named {} has resources", name),
+            None => "No connected MCP server has resources".to_string(),
+        }));
+    }
+    match operation {
+        "list" => {
+            let mut output = String::new();
+            for server in servers {
+                let listed = list_all(&server.transport, "resources/list", "resources").await
+                    .map_err(|e| ToolError::RpcError(format!("{} MCP server: {}", server.name, e)))?;
+                output.push_str(&format!("{} ({} resources)\n", server.name, listed.len()));
+                for resource in listed {
+                    let uri = resource["uri"].as_str().unwrap_or_default();
+                    let title = resource["title"].as_str().or(resource["name"].as_str()).unwrap_or(uri);
+                    output.push_str(&format!("- {}: {}", title, uri));
+                    if let Some(description) = resource["description"].as_str() {
+                        output.push_str(&format!(" ({})", description));
+                    }
+                    output.push('\n');
+                }
+            }
+            Ok(output.trim_end().to_string())
+        },
+        "read" => {
+            let uri = args.get("uri").and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArgs("The uri of the resource to read is required".to_string()))?;
+            let [server] = servers.as_slice() else {
+                return Err(ToolError::InvalidArgs("Several MCP servers have resources; say which one to read from".to_string()));
+            };
+            let result = server.transport.request("resources/read", serde_json::json!({ "uri": uri })).await
+                .map_err(|e| ToolError::RpcError(format!("{} MCP server: {}", server.name, e)))?;
+            let contents: Vec<String> = result["contents"].as_array().into_iter().flatten()
+                .map(|content| match content["text"].as_str() {
+                    Some(text) => text.to_string(),
+                    None => format!("[binary content, {}]", content["mimeType"].as_str().unwrap_or("unknown type")),
+                })
+                .collect();
+            Ok(contents.join("\n"))
+        },
+        _ => Err(ToolError::InvalidArgs(format!("Unknown MCP resources operation: {}", operation))),
+    }
+}
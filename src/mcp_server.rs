@@ -0,0 +1,138 @@
+// The agent's tools served to Model Context Protocol clients (Claude Desktop, IDEs, other
+// agents), over stdio or streamable HTTP. Calls go through the same allowlist, transaction
+// policy, dry-run mode, and audit log as the agent's own.
+
+use crate::personality::Personality;
+use crate::redact::redact;
+use crate::tools::{execute_tool_as, tools_for, ToolContext};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use uuid::Uuid;
+
+// Protocol versions we speak, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// What tool calls run with, and the persona whose allowlist limits them
+pub struct McpServer {
+    ctx: ToolContext,
+    personality: Option<Personality>,
+}
+
+pub fn reply(id: &serde_json::Value, result: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_reply(id: &serde_json::Value, code: i64, message: &str) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+impl McpServer {
+    pub fn new(ctx: ToolContext, personality: Option<Personality>) -> Self {
+        McpServer { ctx, personality }
+    }
+
+    /// Answer one JSON-RPC message; None for notifications, which get no answer
+    async fn handle(&self, message: serde_json::Value) -> Option<serde_json::Value> {
+        let id = message.get("id")?.clone();
+        let method = message.get("method").and_then(|v| v.as_str()).unwrap_or_default();
+        let params = &message["params"];
+        Some(match method {
+            "initialize" => {
+                let requested = params["protocolVersion"].as_str().unwrap_or_default();
+                let version = PROTOCOL_VERSIONS.iter().find(|version| **version == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+                reply(&id, serde_json::json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": { "listChanged": false } },
+                    "serverInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                }))
+            },
+            "ping" => reply(&id, serde_json::json!({})),
+            "tools/list" => {
+                let tools: Vec<serde_json::Value> = tools_for(self.personality.as_ref()).into_iter()
+                    .map(|tool| serde_json::json!({ "name": tool.name, "description": tool.description, "inputSchema": tool.input_schema }))
+                    .collect();
+                reply(&id, serde_json::json!({ "tools": tools }))
+            },
+            "tools/call" => {
+                let Some(name) = params["name"].as_str() else {
+                    return Some(error_reply(&id, -32602, "The name of the tool to call is required"));
+                };
+                let args = params.get("arguments").cloned().unwrap_or_else(|| serde_json::json!({}));
+                tracing::info!(tool = name, "MCP client called a tool");
+                // Failures carry the same JSON the agent's model gets, and secrets are redacted the same way
+                let (text, is_error) = match execute_tool_as(&self.ctx, self.personality.as_ref(), name, &args).await {
+                    Ok(output) => (output, false),
+                    Err(e) => (e.to_json(), true),
+                };
+                reply(&id, serde_json::json!({ "content": [{ "type": "text", "text": redact(&text) }], "isError": is_error }))
+            },
+            _ => error_reply(&id, -32601, &format!("Method not found: {}", method)),
+        })
+    }
+}
+
+/// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes. Calls run
+/// concurrently, so answers may come back out of order.
+pub async fn serve_stdio(server: McpServer) -> anyhow::Result<()> {
+    let server = Arc::new(server);
+    let stdout = Arc::new(tokio::sync::Mutex::new(tokio::io::stdout()));
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    tracing::info!("Serving tools to MCP clients on stdio");
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (server, stdout) = (server.clone(), stdout.clone());
+        tokio::spawn(async move {
+            let answer = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(message) => server.handle(message).await,
+                Err(e) => Some(error_reply(&serde_json::Value::Null, -32700, &format!("Parse error: {}", e))),
+            };
+            let Some(answer) = answer else { return };
+            let mut line = answer.to_string();
+            line.push('\n');
+            let mut stdout = stdout.lock().await;
+            if let Err(e) = stdout.write_all(line.as_bytes()).await.and(stdout.flush().await) {
+                tracing::error!(error = %e, "Could not write to the MCP client");
+            }
+        });
+    }
+    Ok(())
+}
+
+// Browsers send an Origin; only pages served from this machine may call the tools, so that a
+// website can't reach them through DNS rebinding
+fn local_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else { return true };
+    let host = origin.split("://").nth(1).unwrap_or(origin);
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+async fn post_message(State(server): State<Arc<McpServer>>, headers: HeaderMap, Json(message): Json<serde_json::Value>) -> Response {
+    if !local_origin(&headers) {
+        return (StatusCode::FORBIDDEN, "Origin not allowed").into_response();
+    }
+    let initialize = message.get("method").and_then(|v| v.as_str()) == Some("initialize");
+    match server.handle(message).await {
+        Some(answer) if initialize => ([("Mcp-Session-Id", Uuid::new_v4().to_string())], Json(answer)).into_response(),
+        Some(answer) => Json(answer).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// Serve streamable HTTP at `POST /mcp` on 127.0.0.1, answering each request with JSON
+pub async fn serve_http(server: McpServer, port: u16) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/mcp", post(post_message))
+        .with_state(Arc::new(server));
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    tracing::info!(addr = %listener.local_addr()?, "Serving tools to MCP clients");
+    axum::serve(listener, router).await?;
+    Ok(())
+}
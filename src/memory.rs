@@ -0,0 +1,139 @@
+use crate::config::MemoryConfig;
+use crate::redact::redact;
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+/// A stored message recalled because it is semantically close to the current prompt
+#[derive(Debug, sqlx::FromRow)]
+pub struct Recollection {
+    pub role: String,
+    pub content: String,
+    pub similarity: f64,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// Create the pgvector extension and the `memories` table.
+///
+/// This runs at startup instead of as a migration because pgvector is only required when
+/// memory is enabled, and the vector column is sized by the configured embedding dimensions.
+pub async fn init(pool: &Pool<Postgres>, config: &MemoryConfig) -> anyhow::Result<()> {
+    sqlx::query("CREATE EXTENSION IF NOT EXISTS vector")
+        .execute(pool)
+        .await
+        .map_err(|e| anyhow::anyhow!("Long-term memory requires the pgvector extension: {}", e))?;
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS memories (\
+             id BIGSERIAL PRIMARY KEY, \
+             session_id UUID REFERENCES sessions (id), \
+             role TEXT NOT NULL, \
+             content TEXT NOT NULL, \
+             embedding vector({}) NOT NULL, \
+             created_at TIMESTAMP NOT NULL DEFAULT now())",
+        config.dimensions
+    ))
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS memories_embedding_idx ON memories USING hnsw (embedding vector_cosine_ops)")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Embed a text with the configured OpenAI-compatible embeddings endpoint (e.g. Voyage AI)
+async fn embed(config: &MemoryConfig, text: &str) -> anyhow::Result<Vec<f32>> {
+    let api_key = config.api_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No embedding API key configured; set EMBEDDING_API_KEY"))?;
+    let response = Client::new()
+        .post(&config.embedding_url)
+        .bearer_auth(api_key)
+        .json(&serde_json::json!({
+            "model": config.embedding_model,
+            "input": [text],
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    let body: EmbeddingResponse = response.json().await?;
+    let embedding = body.data.into_iter().next()
+        .map(|data| data.embedding)
+        .ok_or_else(|| anyhow::anyhow!("Embedding response contained no vectors"))?;
+    if embedding.len() != config.dimensions {
+        return Err(anyhow::anyhow!("Expected {} embedding dimensions, got {}", config.dimensions, embedding.len()));
+    }
+    Ok(embedding)
+}
+
+// pgvector's text input format, e.g. "[0.1,0.2,0.3]"
+fn vector_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Embed and store a message so later sessions can recall it
+pub async fn remember(pool: &Pool<Postgres>, config: &MemoryConfig, session_id: Uuid, role: &str, content: &str) -> anyhow::Result<()> {
+    if !config.enabled || content.trim().is_empty() {
+        return Ok(());
+    }
+    // Secrets are neither embedded nor stored
+    let content = redact(content);
+    let embedding = embed(config, &content).await?;
+    sqlx::query("INSERT INTO memories (session_id, role, content, embedding) VALUES ($1, $2, $3, $4::vector)")
+        .bind(session_id)
+        .bind(role)
+        .bind(content.as_ref())
+        .bind(vector_literal(&embedding))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The stored messages from other sessions most similar to `prompt`, best match first.
+/// The current session is skipped because its recent turns are already in the context window.
+/// Only sessions of the same owner are searched: a server user's own, or those without an
+/// owner for the CLI and Discord.
+pub async fn recall(pool: &Pool<Postgres>, config: &MemoryConfig, session_id: Option<Uuid>, owner: Option<&str>, prompt: &str) -> anyhow::Result<Vec<Recollection>> {
+    if !config.enabled || prompt.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let embedding = vector_literal(&embed(config, prompt).await?);
+    let recollections = sqlx::query_as(
+        "SELECT role, content, 1 - (embedding <=> $1::vector) AS similarity FROM memories \
+         WHERE session_id IS DISTINCT FROM $2 \
+         AND session_id IN (SELECT id FROM sessions WHERE owner_address IS NOT DISTINCT FROM $4) \
+         ORDER BY embedding <=> $1::vector LIMIT $3",
+    )
+    .bind(&embedding)
+    .bind(session_id)
+    .bind(config.top_k as i64)
+    .bind(owner)
+    .fetch_all(pool)
+    .await?;
+    Ok(recollections.into_iter()
+        .filter(|r: &Recollection| r.similarity >= config.min_similarity)
+        .collect())
+}
+
+/// Render recollections as a system prompt section
+pub fn format_recollections(recollections: &[Recollection]) -> Option<String> {
+    if recollections.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = recollections.iter()
+        .map(|r| format!("- ({}) {}", r.role, r.content))
+        .collect();
+    Some(format!(
+        "Relevant excerpts from earlier conversations (use them only if they help with the current request):\n{}",
+        lines.join("\n")
+    ))
+}
@@ -0,0 +1,177 @@
+use crate::anthropic::{AnthropicRequest, AnthropicResponse, LlmProvider};
+use crate::config::AnthropicConfig;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    // One script per fixture file, so that it carries on from turn to turn
+    static ref SHARED: Mutex<HashMap<String, Arc<MockLlm>>> = Mutex::new(HashMap::new());
+}
+
+/// Model provider that answers from a script rather than the API. Each request gets the next
+/// response, in the Messages API's format; a plain string stands for a text-only answer.
+pub struct MockLlm {
+    responses: Mutex<VecDeque<serde_json::Value>>,
+    requests: Mutex<Vec<serde_json::Value>>,
+}
+
+impl MockLlm {
+    pub fn new(responses: Vec<serde_json::Value>) -> Self {
+        MockLlm { responses: Mutex::new(responses.into()), requests: Mutex::new(Vec::new()) }
+    }
+
+    /// Script from a JSON file holding an array of responses
+    pub fn from_fixture(path: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Could not read mock responses {}: {}", path, e))?;
+        let responses: Vec<serde_json::Value> = serde_json::from_str(&data)
+            .map_err(|e| anyhow::anyhow!("Mock responses in {} are not a JSON array: {}", path, e))?;
+        Ok(MockLlm::new(responses))
+    }
+
+    /// Requests made so far, as they would have been sent to the API
+    #[cfg(test)]
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockLlm {
+    async fn send(&self, _config: &AnthropicConfig, req: &AnthropicRequest) -> anyhow::Result<AnthropicResponse> {
+        self.requests.lock().unwrap().push(serde_json::to_value(req)?);
+        let response = self.responses.lock().unwrap().pop_front()
+            .ok_or_else(|| anyhow::anyhow!("The mock model has no scripted responses left"))?;
+        let response = match response {
+            serde_json::Value::String(text) => serde_json::json!({ "content": [{ "type": "text", "text": text }] }),
+            response => response,
+        };
+        serde_json::from_value(response).map_err(|e| anyhow::anyhow!("Invalid scripted response: {}", e))
+    }
+}
+
+/// The script loaded from `path`, shared by every request configured with it
+pub fn shared(path: &str) -> anyhow::Result<Arc<MockLlm>> {
+    let mut shared = SHARED.lock().unwrap();
+    if let Some(mock) = shared.get(path) {
+        return Ok(mock.clone());
+    }
+    let mock = Arc::new(MockLlm::from_fixture(path)?);
+    shared.insert(path.to_string(), mock.clone());
+    Ok(mock)
+}
+
+/// A scripted response asking for one tool call
+#[cfg(test)]
+pub fn tool_use(id: &str, name: &str, input: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "content": [{ "type": "tool_use", "id": id, "name": name, "input": input }] })
+}
+
+/// Content of the tool_result blocks in the last message of a request, without the injection
+/// guard's delimiters
+#[cfg(test)]
+pub fn tool_results(request: &serde_json::Value) -> Vec<String> {
+    request["messages"].as_array().and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_array()).into_iter().flatten()
+        .filter(|block| block["type"] == "tool_result")
+        .map(|block| {
+            let content = block["content"].as_str().unwrap_or_default();
+            let lines: Vec<&str> = content.lines().collect();
+            match lines.as_slice() {
+                [first, inner @ .., last] if first.starts_with("<<<TOOL OUTPUT") && last.starts_with("<<<END TOOL OUTPUT") => inner.join("\n"),
+                _ => content.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::{call_anthropic_with_tools, run_turn};
+    use crate::config::Config;
+    use crate::conversation::Conversation;
+    use crate::personality::Personality;
+    use crate::test_support::context;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn runs_the_requested_tools_and_returns_the_answer() {
+        let ctx = context(Config::default());
+        let mock = MockLlm::new(vec![tool_use("t1", "get_time", json!({})), json!("It's late.")]);
+
+        let answer = run_turn(&mock, &ctx.config.anthropic, "What time is it?", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        assert_eq!(answer, "It's late.");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        let results = tool_results(&requests[1]);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].starts_with("Current local time"), "{:?}", results);
+        assert!(requests[1]["messages"].as_array().unwrap().last().unwrap()["content"][0].get("is_error").is_none());
+    }
+
+    #[tokio::test]
+    async fn returns_results_of_several_tools_in_request_order() {
+        let ctx = context(Config::default());
+        let mock = MockLlm::new(vec![
+            json!({ "content": [
+                { "type": "tool_use", "id": "t1", "name": "get_time", "input": { "timezone": "UTC" } },
+                { "type": "tool_use", "id": "t2", "name": "no_such_tool", "input": {} },
+                { "type": "tool_use", "id": "t3", "name": "get_time", "input": {} },
+            ] }),
+            json!("Done."),
+        ]);
+
+        run_turn(&mock, &ctx.config.anthropic, "Several things", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        let requests = mock.requests();
+        let blocks = requests[1]["messages"].as_array().unwrap().last().unwrap()["content"].as_array().unwrap().clone();
+        let ids: Vec<&str> = blocks.iter().filter_map(|block| block["tool_use_id"].as_str()).collect();
+        assert_eq!(ids, ["t1", "t2", "t3"]);
+        assert_eq!(blocks[1]["is_error"], true);
+    }
+
+    #[tokio::test]
+    async fn refuses_tools_outside_the_persona_allowlist() {
+        let ctx = context(Config::default());
+        let personality: Personality = serde_json::from_value(json!({
+            "name": "Reader",
+            "role": "read-only assistant",
+            "style": { "tone": "plain", "formality": "neutral", "domain_focus": [] },
+            "rules": [],
+            "allowed_tools": ["get_time"],
+        })).unwrap();
+        let mock = MockLlm::new(vec![tool_use("t1", "eth_wallet", json!({ "operation": "generate" })), json!("I can't do that.")]);
+
+        run_turn(&mock, &ctx.config.anthropic, "Make me a wallet", &[], Some(&personality), &Conversation::default(), &ctx).await.unwrap();
+        let requests = mock.requests();
+        let result: serde_json::Value = serde_json::from_str(&tool_results(&requests[1])[0]).unwrap();
+        assert_eq!(result, json!({ "error": "policy_violation", "message": "Reader is not allowed to use eth_wallet.generate" }));
+        assert_eq!(requests[1]["messages"].as_array().unwrap().last().unwrap()["content"][0]["is_error"], true);
+        let offered: Vec<&str> = requests[0]["tools"].as_array().unwrap().iter().filter_map(|tool| tool["name"].as_str()).collect();
+        assert_eq!(offered, ["get_time"]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_iteration_cap() {
+        let mut config = Config::default();
+        config.anthropic.max_tool_iterations = 2;
+        let ctx = context(config);
+        let mock = MockLlm::new(vec![tool_use("t1", "get_time", json!({})), tool_use("t2", "get_time", json!({})), json!("Never reached")]);
+
+        let error = run_turn(&mock, &ctx.config.anthropic, "Loop", &[], None, &Conversation::default(), &ctx).await.unwrap_err();
+        assert!(error.to_string().contains("2 tool iterations"), "{}", error);
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn answers_from_a_fixture_file() {
+        let mut config = Config::default();
+        config.anthropic.mock_fixture = Some("tests/fixtures/llm/time.json".to_string());
+        let ctx = context(config);
+
+        let answer = call_anthropic_with_tools(&ctx.config.anthropic, "What time is it?", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        assert_eq!(answer, "Here is the time.");
+    }
+}
@@ -0,0 +1,97 @@
+// The model registry: models a chat can switch to with `/model`, from models.toml, with their
+// provider, context window, and prices
+
+use crate::config::ModelPrice;
+use figment::providers::{Format, Toml};
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Only Anthropic models are served today
+const SUPPORTED_PROVIDERS: &[&str] = &["anthropic"];
+
+/// A model in the registry, by its API name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// Service that serves the model
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    /// Size of the context window in tokens
+    pub context_tokens: Option<usize>,
+    /// USD per million input tokens
+    pub input_per_mtok: Option<f64>,
+    /// USD per million output tokens
+    pub output_per_mtok: Option<f64>,
+}
+
+fn default_provider() -> String {
+    "anthropic".to_string()
+}
+
+impl ModelInfo {
+    pub fn price(&self) -> Option<ModelPrice> {
+        Some(ModelPrice { input_per_mtok: self.input_per_mtok?, output_per_mtok: self.output_per_mtok? })
+    }
+
+    // e.g. "anthropic, 200000-token context, $3/$15 per Mtok"
+    fn summary(&self) -> String {
+        let mut parts = vec![self.provider.clone()];
+        if let Some(tokens) = self.context_tokens {
+            parts.push(format!("{}-token context", tokens));
+        }
+        if let Some(price) = self.price() {
+            parts.push(format!("${}/${} per Mtok", price.input_per_mtok, price.output_per_mtok));
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelRegistry {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelInfo>,
+}
+
+impl ModelRegistry {
+    pub fn get(&self, name: &str) -> Option<&ModelInfo> {
+        self.models.get(name)
+    }
+
+    /// Check that `name` can be switched to
+    pub fn check(&self, name: &str) -> anyhow::Result<&ModelInfo> {
+        if self.models.is_empty() {
+            return Err(anyhow::anyhow!("No models are registered; list them in models.toml"));
+        }
+        let model = self.get(name).ok_or_else(|| anyhow::anyhow!(
+            "{} is not in the model registry; known models: {}", name,
+            self.models.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))?;
+        if !SUPPORTED_PROVIDERS.contains(&model.provider.as_str()) {
+            return Err(anyhow::anyhow!("{} is served by {}, which is not supported", name, model.provider));
+        }
+        Ok(model)
+    }
+
+    /// One line per model, with `active` marked
+    pub fn listing(&self, active: &str) -> Vec<String> {
+        self.models.iter()
+            .map(|(name, model)| format!("{} {} ({})", if name == active { "*" } else { " " }, name, model.summary()))
+            .collect()
+    }
+}
+
+/// Load the registry; a missing or invalid file leaves it empty
+pub fn load_models(path: &str) -> ModelRegistry {
+    if !Path::new(path).exists() {
+        tracing::debug!(path, "No model registry found");
+        return ModelRegistry::default();
+    }
+    match Figment::from(Toml::file(path)).extract::<ModelRegistry>() {
+        Ok(registry) => registry,
+        Err(e) => {
+            tracing::error!(path, error = %e, "Failed to load the model registry");
+            ModelRegistry::default()
+        }
+    }
+}
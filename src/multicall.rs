@@ -0,0 +1,214 @@
+use ethers::abi::{self, ParamType, Token};
+use ethers::prelude::*;
+use std::sync::Arc;
+
+abigen!(
+    Multicall3,
+    r#"[
+        struct Call3 { address target; bool allowFailure; bytes callData; }
+        struct Call3Result { bool success; bytes returnData; }
+        function aggregate3(Call3[] calls) external payable returns (Call3Result[] returnData)
+        function getEthBalance(address addr) external view returns (uint256 balance)
+    ]"#
+);
+
+abigen!(
+    Erc20,
+    r#"[
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function balanceOf(address owner) external view returns (uint256)
+    ]"#
+);
+
+// Largest number of calls sent in one aggregate3, to stay under node gas limits for eth_call
+const MAX_CALLS_PER_BATCH: usize = 500;
+
+/// Token metadata read in a portfolio batch
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Balances of every owner in every token, read together
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    /// The native currency first, then the tokens in the order asked for
+    pub tokens: Vec<TokenInfo>,
+    /// One row per owner, one balance per token (None where the read failed)
+    pub balances: Vec<(Address, Vec<Option<U256>>)>,
+    /// Number of eth_call requests made
+    pub requests: usize,
+}
+
+/// Make many read-only calls through Multicall3 in as few `eth_call`s as possible. Each result
+/// is the call's return data, or None if it reverted.
+pub async fn aggregate<M: Middleware + 'static>(client: Arc<M>, multicall: Address, calls: Vec<(Address, Bytes)>) -> anyhow::Result<(Vec<Option<Bytes>>, usize)> {
+    let contract = Multicall3::new(multicall, client);
+    let mut results = Vec::with_capacity(calls.len());
+    let mut requests = 0;
+    for batch in calls.chunks(MAX_CALLS_PER_BATCH) {
+        let batch = batch.iter()
+            .map(|(target, data)| Call3 { target: *target, allow_failure: true, call_data: data.clone() })
+            .collect();
+        let returned = contract.aggregate_3(batch).call().await
+            .map_err(|e| anyhow::anyhow!("Multicall3 at {:?} failed: {}", multicall, e))?;
+        requests += 1;
+        results.extend(returned.into_iter().map(|(success, data)| success.then_some(data)));
+    }
+    Ok((results, requests))
+}
+
+fn decode_uint(data: &Option<Bytes>) -> Option<U256> {
+    match abi::decode(&[ParamType::Uint(256)], data.as_ref()?).ok()?.as_slice() {
+        [Token::Uint(value)] => Some(*value),
+        _ => None,
+    }
+}
+
+// Symbols are strings, except for a few older tokens (such as MKR) that return bytes32
+fn decode_symbol(data: &Option<Bytes>) -> Option<String> {
+    let data = data.as_ref()?;
+    if let Ok(tokens) = abi::decode(&[ParamType::String], data)
+        && let [Token::String(symbol)] = tokens.as_slice()
+    {
+        return Some(symbol.clone());
+    }
+    match abi::decode(&[ParamType::FixedBytes(32)], data).ok()?.as_slice() {
+        [Token::FixedBytes(bytes)] => {
+            let symbol = String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string();
+            (!symbol.is_empty()).then_some(symbol)
+        },
+        _ => None,
+    }
+}
+
+/// Native and ERC-20 balances of `owners` in `tokens`, with each token's symbol and decimals,
+/// all read through Multicall3
+pub async fn portfolio<M: Middleware + 'static>(client: Arc<M>, multicall: Address, native_symbol: &str, owners: &[Address], tokens: &[Address]) -> anyhow::Result<Portfolio> {
+    let multicall_contract = Multicall3::new(multicall, client.clone());
+    let erc20 = |address: Address| Erc20::new(address, client.clone());
+
+    // Metadata of each token, then each owner's ETH balance and token balances
+    let mut calls = Vec::new();
+    for &address in tokens {
+        calls.push((address, erc20(address).symbol().calldata().unwrap_or_default()));
+        calls.push((address, erc20(address).decimals().calldata().unwrap_or_default()));
+    }
+    for &owner in owners {
+        calls.push((multicall, multicall_contract.get_eth_balance(owner).calldata().unwrap_or_default()));
+        for &address in tokens {
+            calls.push((address, erc20(address).balance_of(owner).calldata().unwrap_or_default()));
+        }
+    }
+    let (results, requests) = aggregate(client.clone(), multicall, calls).await?;
+
+    let mut results = results.into_iter();
+    let mut infos = vec![TokenInfo { symbol: native_symbol.to_string(), decimals: 18 }];
+    for &address in tokens {
+        let symbol = decode_symbol(&results.next().flatten());
+        let decimals = decode_uint(&results.next().flatten());
+        infos.push(TokenInfo {
+            symbol: symbol.unwrap_or_else(|| format!("{:?}", address)),
+            // Contracts without decimals() are probably not ERC-20s; their raw balances are shown
+            decimals: decimals.map_or(0, |decimals| decimals.low_u32().min(77) as u8),
+        });
+    }
+    let balances = owners.iter()
+        .map(|&owner| (owner, results.by_ref().take(tokens.len() + 1).map(|data| decode_uint(&data)).collect()))
+        .collect();
+    Ok(Portfolio { tokens: infos, balances, requests })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider;
+    use crate::test_support::{rpc_node, EthCall};
+    use serde_json::json;
+
+    // A node with Multicall3 at `multicall`, answering each call in a batch with `answer(target,
+    // calldata)`, None being a revert. Every call must allow failure.
+    async fn node<F>(multicall: Address, answer: F) -> Arc<provider::EthProvider>
+    where
+        F: Fn(Address, &[u8]) -> Option<Token> + Clone + Send + Sync + 'static,
+    {
+        let url = rpc_node(move |method, params| match method {
+            "eth_chainId" => Some(json!("0x1")),
+            "eth_blockNumber" => Some(json!("0x10")),
+            "eth_call" => {
+                let call = EthCall::parse(params);
+                assert!(call.to == multicall && call.is("aggregate3((address,bool,bytes)[])"));
+                let call_type = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+                let decoded = abi::decode(&[ParamType::Array(Box::new(call_type))], &call.data[4..]).unwrap();
+                let results = decoded[0].clone().into_array().unwrap().into_iter()
+                    .map(|call| match call.into_tuple().unwrap().as_slice() {
+                        [Token::Address(target), Token::Bool(true), Token::Bytes(data)] => match answer(*target, data) {
+                            Some(token) => Token::Tuple(vec![Token::Bool(true), Token::Bytes(abi::encode(&[token]))]),
+                            None => Token::Tuple(vec![Token::Bool(false), Token::Bytes(Vec::new())]),
+                        },
+                        call => panic!("unexpected call {:?}", call),
+                    })
+                    .collect();
+                Some(json!(Bytes::from(abi::encode(&[Token::Array(results)]))))
+            },
+            _ => None,
+        }).await;
+        Arc::new(provider::connect(&url).await.unwrap())
+    }
+
+    fn selector(signature: &str) -> [u8; 4] {
+        ethers::utils::id(signature)
+    }
+
+    #[tokio::test]
+    async fn keeps_the_results_of_calls_that_succeed_when_others_revert() {
+        let multicall = Address::repeat_byte(0xca);
+        let client = node(multicall, |target, data| (target.0[0] % 2 == 0).then(|| Token::Bytes(data.to_vec()))).await;
+        let calls = (0..3u8).map(|i| (Address::repeat_byte(i), Bytes::from(vec![i; 4]))).collect();
+        let (results, requests) = aggregate(client.clone(), multicall, calls).await.unwrap();
+        let echoed = |i: u8| Some(Bytes::from(abi::encode(&[Token::Bytes(vec![i; 4])])));
+        assert_eq!(results, [echoed(0), None, echoed(2)]);
+        assert_eq!(requests, 1);
+
+        // Larger lists are split into batches, keeping the order of the results
+        let calls = (0..MAX_CALLS_PER_BATCH + 2).map(|i| (Address::repeat_byte(i as u8), Bytes::from(vec![i as u8; 4]))).collect();
+        let (results, requests) = aggregate(client, multicall, calls).await.unwrap();
+        assert_eq!((results.len(), requests), (MAX_CALLS_PER_BATCH + 2, 2));
+        // The last four calls are to 0xf2f2.. through 0xf5f5..
+        let last = results[MAX_CALLS_PER_BATCH - 2..].to_vec();
+        assert_eq!(last, [echoed(242), None, echoed(244), None]);
+    }
+
+    #[tokio::test]
+    async fn reads_a_portfolio_with_failed_reads_left_out() {
+        let multicall = Address::repeat_byte(0xca);
+        let (usdc, mkr, not_a_token) = (Address::repeat_byte(0x0c), Address::repeat_byte(0x3e), Address::repeat_byte(0xbd));
+        let (alice, bob) = (Address::repeat_byte(0xa1), Address::repeat_byte(0xb0));
+        let client = node(multicall, move |target, data| {
+            let owner = || Address::from_slice(&data[16..36]);
+            match &data[..4] {
+                s if s == selector("symbol()") && target == usdc => Some(Token::String("USDC".to_string())),
+                // An older token returning its symbol as bytes32
+                s if s == selector("symbol()") && target == mkr => Some(Token::FixedBytes(b"MKR".iter().copied().chain([0; 29]).collect())),
+                s if s == selector("decimals()") && target == usdc => Some(Token::Uint(6.into())),
+                s if s == selector("decimals()") && target == mkr => Some(Token::Uint(18.into())),
+                s if s == selector("getEthBalance(address)") && target == multicall => Some(Token::Uint(if owner() == alice { 7 } else { 9 }.into())),
+                // Bob's USDC balance can't be read
+                s if s == selector("balanceOf(address)") && target != not_a_token && !(target == usdc && owner() == bob) => Some(Token::Uint(target.0[0].into())),
+                _ => None,
+            }
+        }).await;
+
+        let portfolio = portfolio(client, multicall, "ETH", &[alice, bob], &[usdc, mkr, not_a_token]).await.unwrap();
+        let tokens: Vec<(&str, u8)> = portfolio.tokens.iter().map(|token| (token.symbol.as_str(), token.decimals)).collect();
+        let unknown = format!("{:?}", not_a_token);
+        assert_eq!(tokens, [("ETH", 18), ("USDC", 6), ("MKR", 18), (unknown.as_str(), 0)]);
+        assert_eq!(portfolio.balances, [
+            (alice, vec![Some(7.into()), Some(0x0c.into()), Some(0x3e.into()), None]),
+            (bob, vec![Some(9.into()), None, Some(0x3e.into()), None]),
+        ]);
+        assert_eq!(portfolio.requests, 1);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::config::NftConfig;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ethers::abi::{Function, HumanReadableParser, ParamType, Token};
+use ethers::prelude::*;
+use reqwest::Client;
+use std::sync::Arc;
+
+abigen!(
+    Erc721,
+    r#"[
+        function name() external view returns (string)
+        function ownerOf(uint256 tokenId) external view returns (address)
+        function balanceOf(address owner) external view returns (uint256)
+        function tokenURI(uint256 tokenId) external view returns (string)
+        function tokenOfOwnerByIndex(address owner, uint256 index) external view returns (uint256)
+        function supportsInterface(bytes4 interfaceId) external view returns (bool)
+        function safeTransferFrom(address from, address to, uint256 tokenId) external
+    ]"#
+);
+
+// ERC-1155 shares function names with ERC-721, so its bindings get their own module
+mod multi_token {
+    use ethers::prelude::*;
+
+    abigen!(
+        Erc1155,
+        r#"[
+            function balanceOf(address account, uint256 id) external view returns (uint256)
+            function uri(uint256 id) external view returns (string)
+            function safeTransferFrom(address from, address to, uint256 id, uint256 amount, bytes data) external
+        ]"#
+    );
+}
+
+use multi_token::Erc1155;
+
+// ERC-165 interface ids
+const ERC721_INTERFACE: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+const ERC1155_INTERFACE: [u8; 4] = [0xd9, 0xb6, 0x7a, 0x26];
+
+// Most tokens listed for one owner
+const MAX_LISTED: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Standard {
+    Erc721,
+    Erc1155,
+}
+
+impl std::fmt::Display for Standard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Standard::Erc721 => "ERC-721",
+            Standard::Erc1155 => "ERC-1155",
+        })
+    }
+}
+
+/// A token held by an owner
+#[derive(Debug, Clone)]
+pub struct OwnedNft {
+    pub contract: Address,
+    pub collection: Option<String>,
+    pub token_id: U256,
+    pub standard: Option<String>,
+    pub balance: U256,
+    pub name: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Token metadata, with IPFS and Arweave links turned into gateway URLs
+#[derive(Debug, Clone)]
+pub struct NftMetadata {
+    pub token_uri: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub animation_url: Option<String>,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Which standard a contract implements, from ERC-165
+pub async fn standard<M: Middleware + 'static>(client: Arc<M>, contract: Address) -> anyhow::Result<Standard> {
+    let erc165 = Erc721::new(contract, client);
+    if erc165.supports_interface(ERC721_INTERFACE).call().await.unwrap_or(false) {
+        return Ok(Standard::Erc721);
+    }
+    if erc165.supports_interface(ERC1155_INTERFACE).call().await.unwrap_or(false) {
+        return Ok(Standard::Erc1155);
+    }
+    Err(anyhow::anyhow!("{:?} is not an ERC-721 or ERC-1155 contract", contract))
+}
+
+/// Tokens held by `owner`: from the Alchemy NFT API when a key is configured, otherwise by
+/// enumerating an ERC-721 contract (ERC721Enumerable) on-chain
+pub async fn owned<M: Middleware + 'static>(client: Arc<M>, config: &NftConfig, owner: Address, contract: Option<Address>) -> anyhow::Result<Vec<OwnedNft>> {
+    if let Some(key) = &config.alchemy_api_key {
+        return alchemy_owned(config, key, owner, contract).await;
+    }
+    let contract = contract.ok_or_else(|| anyhow::anyhow!(
+        "Listing every NFT of an owner needs an Alchemy API key (ALCHEMY_API_KEY); otherwise name the contract"))?;
+    if standard(client.clone(), contract).await? == Standard::Erc1155 {
+        return Err(anyhow::anyhow!("ERC-1155 balances can't be enumerated on-chain; give a token_id to check its balance"));
+    }
+
+    let nft = Erc721::new(contract, client);
+    let collection = nft.name().call().await.ok();
+    let balance = nft.balance_of(owner).call().await?;
+    let mut tokens = Vec::new();
+    for index in 0..balance.min(U256::from(MAX_LISTED)).as_usize() {
+        let token_id = nft.token_of_owner_by_index(owner, U256::from(index)).call().await
+            .map_err(|_| anyhow::anyhow!("{:?} is not enumerable; set ALCHEMY_API_KEY to list its tokens", contract))?;
+        tokens.push(OwnedNft {
+            contract,
+            collection: collection.clone(),
+            token_id,
+            standard: Some(Standard::Erc721.to_string()),
+            balance: U256::one(),
+            name: None,
+            image: None,
+        });
+    }
+    Ok(tokens)
+}
+
+async fn alchemy_owned(config: &NftConfig, key: &str, owner: Address, contract: Option<Address>) -> anyhow::Result<Vec<OwnedNft>> {
+    let url = format!("https://{}.g.alchemy.com/nft/v3/{}/getNFTsForOwner", config.alchemy_network, key);
+    let mut query = vec![
+        ("owner", format!("{:?}", owner)),
+        ("withMetadata", "true".to_string()),
+        ("pageSize", MAX_LISTED.to_string()),
+    ];
+    if let Some(contract) = contract {
+        query.push(("contractAddresses[]", format!("{:?}", contract)));
+    }
+    let response = Client::new().get(url).query(&query).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Alchemy NFT API returned {}", status));
+    }
+    let body: serde_json::Value = response.json().await?;
+    let text = |value: &serde_json::Value| value.as_str().filter(|text| !text.is_empty()).map(str::to_string);
+
+    Ok(body["ownedNfts"].as_array().cloned().unwrap_or_default().iter()
+        .filter_map(|nft| {
+            Some(OwnedNft {
+                contract: nft["contract"]["address"].as_str()?.parse().ok()?,
+                collection: text(&nft["contract"]["name"]),
+                token_id: U256::from_dec_str(nft["tokenId"].as_str()?).ok()?,
+                standard: text(&nft["tokenType"]),
+                balance: nft["balance"].as_str().and_then(|balance| U256::from_dec_str(balance).ok()).unwrap_or(U256::one()),
+                name: text(&nft["name"]),
+                image: text(&nft["image"]["cachedUrl"]).or_else(|| text(&nft["image"]["originalUrl"]))
+                    .map(|url| gateway_url(&url, &config.ipfs_gateway)),
+            })
+        })
+        .collect())
+}
+
+/// The owner of an ERC-721 token
+pub async fn owner_of<M: Middleware + 'static>(client: Arc<M>, contract: Address, token_id: U256) -> anyhow::Result<Address> {
+    Ok(Erc721::new(contract, client).owner_of(token_id).call().await?)
+}
+
+/// `owner`'s balance of an ERC-1155 token
+pub async fn balance_of<M: Middleware + 'static>(client: Arc<M>, contract: Address, owner: Address, token_id: U256) -> anyhow::Result<U256> {
+    Ok(Erc1155::new(contract, client).balance_of(owner, token_id).call().await?)
+}
+
+/// HTTP URL for an `ipfs://` or `ar://` link; other URLs are returned unchanged
+pub fn gateway_url(uri: &str, ipfs_gateway: &str) -> String {
+    if let Some(path) = uri.strip_prefix("ipfs://") {
+        let path = path.strip_prefix("ipfs/").unwrap_or(path);
+        return format!("{}/ipfs/{}", ipfs_gateway.trim_end_matches('/'), path);
+    }
+    if let Some(path) = uri.strip_prefix("ar://") {
+        return format!("https://arweave.net/{}", path);
+    }
+    uri.to_string()
+}
+
+// JSON of a metadata URI: fetched over HTTP, or decoded from a `data:` URI
+async fn fetch_metadata_json(uri: &str, ipfs_gateway: &str) -> anyhow::Result<serde_json::Value> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, payload) = data.split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("Malformed data URI"))?;
+        let bytes = if header.ends_with(";base64") {
+            BASE64.decode(payload)?
+        } else {
+            payload.as_bytes().to_vec()
+        };
+        return Ok(serde_json::from_slice(&bytes)?);
+    }
+    let response = Client::new().get(gateway_url(uri, ipfs_gateway)).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Fetching {} returned {}", uri, status));
+    }
+    Ok(response.json().await?)
+}
+
+/// Token URI and metadata of a token. ERC-1155 `{id}` placeholders are substituted.
+pub async fn metadata<M: Middleware + 'static>(client: Arc<M>, config: &NftConfig, contract: Address, token_id: U256) -> anyhow::Result<NftMetadata> {
+    let token_uri = match standard(client.clone(), contract).await? {
+        Standard::Erc721 => Erc721::new(contract, client).token_uri(token_id).call().await?,
+        Standard::Erc1155 => {
+            let mut id = [0u8; 32];
+            token_id.to_big_endian(&mut id);
+            Erc1155::new(contract, client).uri(token_id).call().await?
+                .replace("{id}", &hex::encode(id))
+        },
+    };
+    let json = fetch_metadata_json(&token_uri, &config.ipfs_gateway).await?;
+    let text = |key: &str| json[key].as_str().filter(|text| !text.is_empty()).map(str::to_string);
+    let attributes = json["attributes"].as_array().cloned().unwrap_or_default().iter()
+        .filter_map(|attribute| {
+            let trait_type = attribute["trait_type"].as_str()?.to_string();
+            let value = match &attribute["value"] {
+                serde_json::Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+            Some((trait_type, value))
+        })
+        .collect();
+    Ok(NftMetadata {
+        name: text("name"),
+        description: text("description"),
+        image: text("image").or_else(|| text("image_url")).map(|url| gateway_url(&url, &config.ipfs_gateway)),
+        animation_url: text("animation_url").map(|url| gateway_url(&url, &config.ipfs_gateway)),
+        attributes,
+        token_uri,
+    })
+}
+
+/// Calldata transferring a token with `safeTransferFrom`
+pub fn transfer_call<M: Middleware + 'static>(client: Arc<M>, standard: Standard, contract: Address, from: Address, to: Address, token_id: U256, amount: U256) -> Bytes {
+    match standard {
+        Standard::Erc721 => Erc721::new(contract, client).safe_transfer_from(from, to, token_id).calldata(),
+        Standard::Erc1155 => Erc1155::new(contract, client).safe_transfer_from(from, to, token_id, amount, Bytes::default()).calldata(),
+    }
+    .unwrap_or_default()
+}
+
+/// The configured mint function, e.g. `function safeMint(address to, string uri)`
+pub fn mint_function(config: &NftConfig) -> anyhow::Result<Function> {
+    HumanReadableParser::parse_function(&config.mint_function)
+        .map_err(|e| anyhow::anyhow!("Invalid mint_function under [nft]: {}", e))
+}
+
+/// Calldata for the mint function. Each parameter gets the recipient (address), the metadata URI
+/// (string), or the quantity (uint).
+pub fn mint_call(function: &Function, to: Address, token_uri: Option<&str>, quantity: U256) -> anyhow::Result<Bytes> {
+    let tokens = function.inputs.iter()
+        .map(|param| match &param.kind {
+            ParamType::Address => Ok(Token::Address(to)),
+            ParamType::String => token_uri
+                .map(|uri| Token::String(uri.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("{} takes a metadata URI; give token_uri", function.name)),
+            ParamType::Uint(_) => Ok(Token::Uint(quantity)),
+            other => Err(anyhow::anyhow!("Unsupported mint parameter {} of type {}", param.name, other)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Bytes::from(function.encode_input(&tokens)?))
+}
+
+/// Ids of ERC-721 tokens minted to `to` in a receipt (Transfer events from the zero address)
+pub fn minted_token_ids(receipt: &TransactionReceipt, contract: Address, to: Address) -> Vec<U256> {
+    let transfer = H256::from(ethers::utils::keccak256("Transfer(address,address,uint256)"));
+    receipt.logs.iter()
+        .filter(|log| log.address == contract && log.topics.len() == 4 && log.topics[0] == transfer)
+        .filter(|log| log.topics[1] == H256::zero() && log.topics[2] == H256::from(to))
+        .map(|log| U256::from_big_endian(log.topics[3].as_bytes()))
+        .collect()
+}
@@ -0,0 +1,189 @@
+use crate::cluster;
+use crate::private_tx;
+use crate::provider::EthProvider;
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Retries after a nonce conflict before giving up on a submission
+const MAX_NONCE_RETRIES: usize = 3;
+
+lazy_static::lazy_static! {
+    /// Shared by every session, since they may sign for the same accounts
+    pub static ref NONCE_MANAGER: NonceManager = NonceManager::default();
+}
+
+// Next nonce to use for one account, None until it is first synced from the node
+type AccountNonce = Arc<tokio::sync::Mutex<Option<U256>>>;
+
+/// Assigns nonces to outgoing transactions, keyed by sender and chain.
+///
+/// Submissions from one account are serialized, and the next nonce is the higher of the
+/// locally tracked one and the node's pending count, so transactions sent in quick succession
+/// don't reuse a nonce. A "nonce too low" rejection resyncs with the node and retries.
+#[derive(Default)]
+pub struct NonceManager {
+    accounts: Mutex<HashMap<(Address, u64), AccountNonce>>,
+}
+
+// Node errors meaning the nonce was already used, by a mined or a pending transaction
+fn is_nonce_conflict(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["nonce too low", "already known", "nonce has already been used", "replacement transaction underpriced", "known transaction"]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+impl NonceManager {
+    fn account(&self, from: Address, chain_id: u64) -> AccountNonce {
+        self.accounts.lock().unwrap().entry((from, chain_id)).or_default().clone()
+    }
+
+    /// Set the nonce of `tx` and submit it, to `relay` if given, returning once it was
+    /// accepted. The nonce that was used stays set on `tx`.
+    pub async fn send<'a, M: Middleware>(&self, client: &'a M, chain_id: u64, from: Address, tx: &mut TypedTransaction, relay: Option<&EthProvider>) -> anyhow::Result<PendingTransaction<'a, M::Provider>> {
+        let account = self.account(from, chain_id);
+        let mut next_nonce = account.lock().await;
+        // Other replicas sign for the same accounts; the node's pending count covers their sends
+        let lock = cluster::lock_account(from, chain_id).await?;
+        let sent = send_with_nonce(client, chain_id, from, tx, relay, &mut next_nonce).await;
+        if let Some(lock) = lock {
+            lock.release().await;
+        }
+        sent
+    }
+}
+
+// Submit `tx` with the next nonce of its account, tracked in `next_nonce`, once the account is locked
+async fn send_with_nonce<'a, M: Middleware>(client: &'a M, chain_id: u64, from: Address, tx: &mut TypedTransaction, relay: Option<&EthProvider>, next_nonce: &mut Option<U256>) -> anyhow::Result<PendingTransaction<'a, M::Provider>> {
+    let mut retries = 0;
+    loop {
+        let pending_count = client.get_transaction_count(from, Some(BlockNumber::Pending.into())).await
+            .map_err(|e| anyhow::anyhow!("Could not fetch nonce: {}", e))?;
+        let nonce = match *next_nonce {
+            Some(tracked) => tracked.max(pending_count),
+            None => pending_count,
+        };
+        tx.set_nonce(nonce);
+
+        let submitted = match relay {
+            Some(relay) => private_tx::send(client, relay, from, tx.clone()).await,
+            None => client.send_transaction(tx.clone(), None).await.map_err(|e| anyhow::anyhow!("{}", e)),
+        };
+        match submitted {
+            Ok(pending_tx) => {
+                *next_nonce = Some(nonce + 1);
+                tracing::debug!(from = ?from, chain_id, nonce = %nonce, "Submitted transaction");
+                return Ok(pending_tx);
+            },
+            Err(e) if is_nonce_conflict(&e.to_string()) && retries < MAX_NONCE_RETRIES => {
+                tracing::warn!(from = ?from, nonce = %nonce, error = %e, "Nonce conflict; retrying with the next nonce");
+                *next_nonce = Some(nonce + 1);
+                retries += 1;
+            },
+            Err(e) => {
+                // Forget the tracked nonce so the next send starts from the node's view again
+                *next_nonce = None;
+                return Err(e);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider;
+    use crate::test_support::rpc_node_with_errors;
+    use serde_json::json;
+
+    // A node whose pending count for every account is `pending`, answering eth_sendTransaction
+    // with `sent(nonce)` and recording the nonces of every submission
+    async fn node<F>(pending: Arc<Mutex<u64>>, sent: F) -> (EthProvider, Arc<Mutex<Vec<u64>>>)
+    where
+        F: Fn(u64) -> Result<(), String> + Clone + Send + Sync + 'static,
+    {
+        let submitted = Arc::new(Mutex::new(Vec::new()));
+        let nonces = submitted.clone();
+        let url = rpc_node_with_errors(move |method, params| match method {
+            "eth_chainId" => Ok(json!("0x1")),
+            "eth_blockNumber" => Ok(json!("0x10")),
+            "eth_getTransactionCount" => Ok(json!(format!("{:#x}", *pending.lock().unwrap()))),
+            "eth_sendTransaction" => {
+                let nonce = u64::from_str_radix(params[0]["nonce"].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+                nonces.lock().unwrap().push(nonce);
+                sent(nonce).map(|()| json!(format!("{:?}", H256::from_low_u64_be(nonce))))
+            },
+            _ => Err(format!("unexpected call {}", method)),
+        }).await;
+        (provider::connect(&url).await.unwrap(), submitted)
+    }
+
+    fn transaction(from: Address) -> TypedTransaction {
+        TransactionRequest::new().from(from).to(Address::random()).value(1).gas(21_000).gas_price(1_000_000_000).into()
+    }
+
+    async fn send(manager: &NonceManager, client: &EthProvider, from: Address) -> anyhow::Result<U256> {
+        let mut tx = transaction(from);
+        manager.send(client, 1, from, &mut tx, None).await?;
+        Ok(*tx.nonce().unwrap())
+    }
+
+    #[tokio::test]
+    async fn reserves_consecutive_nonces_ahead_of_the_node() {
+        let (client, submitted) = node(Arc::new(Mutex::new(5)), |_| Ok(())).await;
+        let manager = NonceManager::default();
+        let from = Address::random();
+        // The node still counts 5 pending, but 5 and 6 are already taken
+        for expected in [5u64, 6, 7] {
+            assert_eq!(send(&manager, &client, from).await.unwrap(), expected.into());
+        }
+        // Each account keeps its own nonces
+        assert_eq!(send(&manager, &client, Address::random()).await.unwrap(), 5.into());
+        assert_eq!(*submitted.lock().unwrap(), [5, 6, 7, 5]);
+    }
+
+    #[tokio::test]
+    async fn retries_with_the_next_nonce_after_a_nonce_too_low() {
+        let (client, submitted) = node(Arc::new(Mutex::new(5)), |nonce| match nonce {
+            5 => Err("nonce too low".to_string()),
+            6 => Err("already known".to_string()),
+            _ => Ok(()),
+        }).await;
+        let manager = NonceManager::default();
+        let from = Address::random();
+        assert_eq!(send(&manager, &client, from).await.unwrap(), 7.into());
+        assert_eq!(send(&manager, &client, from).await.unwrap(), 8.into());
+        assert_eq!(*submitted.lock().unwrap(), [5, 6, 7, 8]);
+
+        // Conflicts on every nonce give up after the retries
+        let (client, submitted) = node(Arc::new(Mutex::new(5)), |_| Err("nonce too low".to_string())).await;
+        let error = send(&manager, &client, Address::random()).await.unwrap_err();
+        assert!(error.to_string().contains("nonce too low"), "{}", error);
+        assert_eq!(*submitted.lock().unwrap(), [5, 6, 7, 8]);
+    }
+
+    #[tokio::test]
+    async fn resyncs_with_the_node() {
+        let pending = Arc::new(Mutex::new(5));
+        let (client, submitted) = node(pending.clone(), |nonce| match nonce {
+            10 => Err("insufficient funds for gas * price + value".to_string()),
+            _ => Ok(()),
+        }).await;
+        let manager = NonceManager::default();
+        let from = Address::random();
+        assert_eq!(send(&manager, &client, from).await.unwrap(), 5.into());
+
+        // Another replica sent from the account, so the node is ahead of the tracked nonce
+        *pending.lock().unwrap() = 9;
+        assert_eq!(send(&manager, &client, from).await.unwrap(), 9.into());
+
+        // After a failed send the node's count is used again, even where it is behind, as when
+        // sent transactions were dropped
+        assert!(send(&manager, &client, from).await.is_err());
+        *pending.lock().unwrap() = 4;
+        assert_eq!(send(&manager, &client, from).await.unwrap(), 4.into());
+        assert_eq!(*submitted.lock().unwrap(), [5, 9, 10, 4]);
+    }
+}
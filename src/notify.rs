@@ -0,0 +1,227 @@
+use crate::config::{EmailNotifyConfig, NotifyConfig};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+
+// Longest messages the chat services accept
+const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// What a notification is about, so each channel can be limited to some kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Transaction,
+    PriceAlert,
+    Reminder,
+    Scheduler,
+    Event,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Transaction => "transaction",
+            Kind::PriceAlert => "price_alert",
+            Kind::Reminder => "reminder",
+            Kind::Scheduler => "scheduler",
+            Kind::Event => "event",
+        }
+    }
+}
+
+/// Something that happened in the background and should reach the user
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub kind: Kind,
+    pub title: String,
+    pub body: String,
+}
+
+impl Notification {
+    pub fn new(kind: Kind, title: impl Into<String>, body: impl Into<String>) -> Self {
+        Notification { kind, title: title.into(), body: body.into() }
+    }
+
+    fn text(&self, limit: usize) -> String {
+        let text = format!("{}\n{}", self.title, self.body);
+        if text.chars().count() <= limit {
+            return text;
+        }
+        let mut shown: String = text.chars().take(limit.saturating_sub(1)).collect();
+        shown.push('…');
+        shown
+    }
+}
+
+/// A channel notifications are delivered through
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+struct Telegram {
+    bot_token: String,
+    chat_id: String,
+}
+
+#[async_trait]
+impl Sink for Telegram {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let body = serde_json::json!({
+            "chat_id": self.chat_id,
+            "text": notification.text(TELEGRAM_MESSAGE_LIMIT),
+        });
+        Client::new().post(format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()
+            // The bot token is part of the URL, so it is left out of the error
+            .map_err(|e| anyhow::anyhow!("Telegram returned {}", e.status().unwrap_or_default()))?;
+        Ok(())
+    }
+}
+
+struct DiscordWebhook {
+    url: String,
+}
+
+#[async_trait]
+impl Sink for DiscordWebhook {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let body = serde_json::json!({ "content": notification.text(DISCORD_MESSAGE_LIMIT) });
+        Client::new().post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow::anyhow!("Discord webhook returned {}", e.status().unwrap_or_default()))?;
+        Ok(())
+    }
+}
+
+struct Email {
+    config: EmailNotifyConfig,
+    host: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+#[async_trait]
+impl Sink for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(&notification.title);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message.body(notification.body.clone())?;
+
+        let transport = if self.config.smtp_port == 465 {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?
+        };
+        let mut transport = transport.port(self.config.smtp_port);
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        transport.build().send(message).await?;
+        Ok(())
+    }
+}
+
+struct Desktop;
+
+#[async_trait]
+impl Sink for Desktop {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let mut command = if cfg!(target_os = "macos") {
+            // The text is passed as arguments rather than spliced into the script
+            let mut command = tokio::process::Command::new("osascript");
+            command.args(["-e", "on run argv", "-e", "display notification (item 2 of argv) with title (item 1 of argv)", "-e", "end run"]);
+            command
+        } else {
+            tokio::process::Command::new("notify-send")
+        };
+        let status = command.arg(&notification.title).arg(&notification.body).status().await
+            .map_err(|e| anyhow::anyhow!("Could not show a desktop notification: {}", e))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("Desktop notification command exited with {}", status));
+        }
+        Ok(())
+    }
+}
+
+fn wants(kinds: &[String], kind: Kind) -> bool {
+    kinds.is_empty() || kinds.iter().any(|wanted| wanted == kind.as_str())
+}
+
+fn email_sink(config: &EmailNotifyConfig) -> anyhow::Result<Option<Email>> {
+    let (Some(host), Some(from)) = (&config.smtp_host, &config.from) else { return Ok(None) };
+    if config.to.is_empty() {
+        return Ok(None);
+    }
+    let from = from.parse().map_err(|e| anyhow::anyhow!("Invalid sender {}: {}", from, e))?;
+    let to = config.to.iter()
+        .map(|to| to.parse().map_err(|e| anyhow::anyhow!("Invalid recipient {}: {}", to, e)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Some(Email { config: config.clone(), host: host.clone(), from, to }))
+}
+
+/// The channels set up under `[notify]` for a kind of notification
+pub fn sinks(config: &NotifyConfig, kind: Kind) -> Vec<Box<dyn Sink>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    let telegram = &config.telegram;
+    if let (Some(bot_token), Some(chat_id)) = (&telegram.bot_token, &telegram.chat_id)
+        && wants(&telegram.kinds, kind)
+    {
+        sinks.push(Box::new(Telegram { bot_token: bot_token.clone(), chat_id: chat_id.clone() }));
+    }
+    if let Some(url) = &config.discord.webhook_url
+        && wants(&config.discord.kinds, kind)
+    {
+        sinks.push(Box::new(DiscordWebhook { url: url.clone() }));
+    }
+    if wants(&config.email.kinds, kind) {
+        match email_sink(&config.email) {
+            Ok(Some(email)) => sinks.push(Box::new(email)),
+            Ok(None) => {},
+            Err(e) => tracing::warn!(error = %e, "Email notifications are misconfigured"),
+        }
+    }
+    if config.desktop.enabled && wants(&config.desktop.kinds, kind) {
+        sinks.push(Box::new(Desktop));
+    }
+    sinks
+}
+
+/// Deliver a notification through every channel that takes its kind. Failures are logged, and
+/// don't keep it from the other channels.
+pub async fn send(config: &NotifyConfig, notification: &Notification) {
+    for sink in sinks(config, notification.kind) {
+        if let Err(e) = sink.send(notification).await {
+            tracing::warn!(channel = sink.name(), kind = notification.kind.as_str(), error = %e, "Failed to deliver notification");
+        }
+    }
+}
@@ -1,23 +1,162 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::Deserialize;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Personality {
     pub name: String,
     pub role: String,
     pub style: Style,
     pub rules: Vec<String>,
+    /// Tools the persona may use, as tool names (`eth_wallet`) or single operations of a
+    /// tool (`eth_wallet.balance`). When absent, every tool is available.
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+    /// Generation settings for this persona; unset ones fall back to `[anthropic]` in the config
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Example exchanges demonstrating the persona's style, sent ahead of the conversation
+    #[serde(default)]
+    pub examples: Vec<Example>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
+pub struct Example {
+    pub user: String,
+    pub assistant: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct Style {
     pub tone: String,
     pub formality: String,
     pub domain_focus: Vec<String>,
 }
 
+impl Personality {
+    /// Whether the persona may call a tool, and the given `operation` of it if the tool has one
+    pub fn allows_tool(&self, name: &str, operation: Option<&str>) -> bool {
+        let Some(allowed) = &self.allowed_tools else { return true };
+        allowed.iter().any(|entry| {
+            entry == name || operation.is_some_and(|op| *entry == format!("{}.{}", name, op))
+        })
+    }
+
+    /// Which operations of a tool the persona may use: `None` for all of them, otherwise the
+    /// operations listed as `tool.operation` (empty when the tool isn't allowed at all)
+    pub fn allowed_operations(&self, name: &str) -> Option<Vec<String>> {
+        let allowed = self.allowed_tools.as_ref()?;
+        if allowed.iter().any(|entry| entry == name) {
+            return None;
+        }
+        let prefix = format!("{}.", name);
+        Some(allowed.iter()
+            .filter_map(|entry| entry.strip_prefix(&prefix).map(str::to_string))
+            .collect())
+    }
+}
+
 pub fn load_personality(path: &str) -> anyhow::Result<Personality> {
     let data = fs::read_to_string(path)?;
     let persona: Personality = serde_json::from_str(&data)?;
+    // Catch out-of-range settings here rather than as an API error on the first turn
+    for (field, value) in [("temperature", persona.temperature), ("top_p", persona.top_p)] {
+        if let Some(value) = value
+            && !(0.0..=1.0).contains(&value)
+        {
+            return Err(anyhow::anyhow!("{} must be between 0 and 1, got {}", field, value));
+        }
+    }
+    if persona.max_tokens == Some(0) {
+        return Err(anyhow::anyhow!("max_tokens must be greater than 0"));
+    }
+    // The API rejects empty messages
+    if persona.examples.iter().any(|e| e.user.trim().is_empty() || e.assistant.trim().is_empty()) {
+        return Err(anyhow::anyhow!("examples need both a user and an assistant message"));
+    }
     Ok(persona)
 }
+
+/// Names of the personas in a personalities directory (file stems, sorted)
+pub fn list_personas(dir: &str) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("json")
+            && let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+        {
+            names.push(stem.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Path of a persona file, `<dir>/<name>.json`
+pub fn persona_path(dir: &str, name: &str) -> anyhow::Result<PathBuf> {
+    // Only allow bare names so the lookup can't escape the personalities directory
+    if name.is_empty() || Path::new(name).components().count() != 1 || name.contains("..") {
+        return Err(anyhow::anyhow!("Invalid persona name: {}", name));
+    }
+    let path = Path::new(dir).join(format!("{}.json", name));
+    if !path.exists() {
+        let available = list_personas(dir).map(|names| names.join(", ")).unwrap_or_default();
+        return Err(anyhow::anyhow!("Persona {} not found in {} (available: {})", name, dir, available));
+    }
+    Ok(path)
+}
+
+/// A personality that is reloaded whenever its file changes, so edits apply on the next turn
+pub struct LivePersonality {
+    current: Arc<RwLock<Personality>>,
+    // Dropping the watcher stops the reloads
+    _watcher: RecommendedWatcher,
+}
+
+impl LivePersonality {
+    pub fn watch(path: &Path) -> anyhow::Result<Self> {
+        let path_str = path.to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid personality path: {}", path.display()))?
+            .to_string();
+        let current = Arc::new(RwLock::new(load_personality(&path_str)?));
+        let file_name = path.file_name().map(|name| name.to_os_string());
+
+        let reload_target = current.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            if event.kind.is_access() || !event.paths.iter().any(|p| p.file_name() == file_name.as_deref()) {
+                return;
+            }
+            // Keep the previous personality if the edited file doesn't parse (e.g. mid-save)
+            match load_personality(&path_str) {
+                Ok(personality) => {
+                    tracing::info!(path = %path_str, name = %personality.name, "Reloaded personality");
+                    *reload_target.write().unwrap() = personality;
+                },
+                Err(e) => tracing::warn!(path = %path_str, error = %e, "Ignoring invalid personality change"),
+            }
+        })?;
+
+        // Watch the directory rather than the file, since editors often save by replacing the file
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(LivePersonality { current, _watcher: watcher })
+    }
+
+    /// The personality as of its latest successful (re)load
+    pub fn current(&self) -> Personality {
+        self.current.read().unwrap().clone()
+    }
+}
@@ -0,0 +1,197 @@
+// Third-party tools compiled to WebAssembly components that implement wit/plugin.wit. Each
+// `.wasm` file in `[plugins] dir` is one tool. Plugins run in a WASI sandbox with nothing from
+// the host (no files, network, or environment) beyond what `[plugins.grants.<file stem>]`
+// gives them, and each call gets a fresh instance.
+
+use crate::config::PluginsConfig;
+use crate::tool_error::ToolError;
+use crate::tools::Tool;
+use std::path::PathBuf;
+
+#[cfg(feature = "plugins")]
+use crate::config::PluginGrants;
+#[cfg(feature = "plugins")]
+use lazy_static::lazy_static;
+#[cfg(feature = "plugins")]
+use std::sync::RwLock;
+#[cfg(feature = "plugins")]
+use std::time::Duration;
+#[cfg(feature = "plugins")]
+use wasmtime::component::{Component, Linker, ResourceTable};
+#[cfg(feature = "plugins")]
+use wasmtime::{Engine, Store};
+#[cfg(feature = "plugins")]
+use wasmtime_wasi::{FsPerms, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+#[cfg(feature = "plugins")]
+mod bindings {
+    wasmtime::component::bindgen!({
+        path: "wit/plugin.wit",
+        world: "plugin",
+        exports: { default: async },
+    });
+}
+
+// How often running plugins yield, so that a tool timeout can stop one stuck in a loop
+#[cfg(feature = "plugins")]
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+
+// Time a plugin gets to report its name, description, and schema at startup
+#[cfg(feature = "plugins")]
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[cfg(feature = "plugins")]
+lazy_static! {
+    static ref ENGINE: Engine = {
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("Could not create the WebAssembly engine");
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    };
+    static ref PLUGINS: RwLock<Vec<Plugin>> = RwLock::new(Vec::new());
+}
+
+/// A loaded plugin: the tool it provides and what it may reach outside the sandbox
+#[cfg(feature = "plugins")]
+#[derive(Clone)]
+struct Plugin {
+    tool: Tool,
+    grants: PluginGrants,
+    pre: bindings::PluginPre<Sandbox>,
+}
+
+// What a plugin instance sees of the host
+#[cfg(feature = "plugins")]
+struct Sandbox {
+    wasi: WasiCtx,
+    table: ResourceTable,
+}
+
+#[cfg(feature = "plugins")]
+impl WasiView for Sandbox {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView { ctx: &mut self.wasi, table: &mut self.table }
+    }
+}
+
+// `.wasm` files in the plugin directory, in name order; none when it doesn't exist
+fn plugin_files(dir: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "wasm"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Load the plugins in `[plugins] dir`, skipping (and logging) any that fail to load or
+/// whose name is already taken
+#[cfg(feature = "plugins")]
+pub async fn load(config: &PluginsConfig, builtin: &[Tool]) {
+    let mut linker = Linker::new(&ENGINE);
+    if let Err(e) = wasmtime_wasi::p2::add_to_linker_async(&mut linker) {
+        tracing::error!(error = %e, "Could not set up the plugin sandbox");
+        return;
+    }
+
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for path in plugin_files(&config.dir) {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let grants = config.grants.get(&stem).cloned().unwrap_or_default();
+        match load_plugin(&path, &linker, grants).await {
+            Ok(plugin) if builtin.iter().chain(plugins.iter().map(|plugin| &plugin.tool)).any(|tool| tool.name == plugin.tool.name) => {
+                tracing::warn!(path = %path.display(), name = %plugin.tool.name, "Skipping plugin whose tool name is already taken");
+            },
+            Ok(plugin) => {
+                tracing::info!(path = %path.display(), name = %plugin.tool.name, "Loaded plugin");
+                plugins.push(plugin);
+            },
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Could not load plugin"),
+        }
+    }
+    *PLUGINS.write().unwrap() = plugins;
+}
+
+#[cfg(not(feature = "plugins"))]
+pub async fn load(config: &PluginsConfig, _builtin: &[Tool]) {
+    let files = plugin_files(&config.dir);
+    if !files.is_empty() {
+        tracing::warn!(dir = %config.dir, count = files.len(), "Plugins found but not loaded; rebuild with `--features plugins`");
+    }
+}
+
+// Compile a plugin and ask it, with no grants, which tool it provides
+#[cfg(feature = "plugins")]
+async fn load_plugin(path: &std::path::Path, linker: &Linker<Sandbox>, grants: PluginGrants) -> anyhow::Result<Plugin> {
+    let component = Component::from_file(&ENGINE, path)?;
+    let pre = bindings::PluginPre::new(linker.instantiate_pre(&component)?)?;
+    let describe = async {
+        let mut store = sandbox(&PluginGrants::default())?;
+        let instance = pre.instantiate_async(&mut store).await?;
+        let name = instance.call_name(&mut store).await?;
+        let description = instance.call_description(&mut store).await?;
+        let schema = instance.call_schema(&mut store).await?;
+        anyhow::Ok((name, description, schema))
+    };
+    let (name, description, schema) = tokio::time::timeout(DESCRIBE_TIMEOUT, describe).await
+        .map_err(|_| anyhow::anyhow!("The plugin did not describe its tool within {} seconds", DESCRIBE_TIMEOUT.as_secs()))??;
+    let input_schema = serde_json::from_str(&schema)
+        .map_err(|e| anyhow::anyhow!("The schema of {} is not valid JSON: {}", name, e))?;
+    Ok(Plugin { tool: Tool { name, description, input_schema }, grants, pre })
+}
+
+// A store for one plugin instance, holding only the granted capabilities
+#[cfg(feature = "plugins")]
+fn sandbox(grants: &PluginGrants) -> anyhow::Result<Store<Sandbox>> {
+    let mut wasi = WasiCtxBuilder::new();
+    for dir in &grants.dirs {
+        wasi.preopened_dir(dir, dir, FsPerms::ReadOnly)
+            .map_err(|e| anyhow::anyhow!("Could not open granted directory {}: {}", dir, e))?;
+    }
+    if grants.network {
+        wasi.inherit_network().allow_ip_name_lookup(true).allow_tcp(true).allow_udp(true);
+    } else {
+        wasi.allow_tcp(false).allow_udp(false);
+    }
+    let mut store = Store::new(&ENGINE, Sandbox { wasi: wasi.build(), table: ResourceTable::new() });
+    store.epoch_deadline_async_yield_and_update(1);
+    Ok(store)
+}
+
+/// Tools provided by the loaded plugins
+#[cfg(feature = "plugins")]
+pub fn tools() -> Vec<Tool> {
+    PLUGINS.read().unwrap().iter().map(|plugin| plugin.tool.clone()).collect()
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn tools() -> Vec<Tool> {
+    Vec::new()
+}
+
+/// Run the plugin providing `name`; None when no plugin does
+#[cfg(feature = "plugins")]
+pub async fn execute(name: &str, args: &serde_json::Value) -> Option<Result<String, ToolError>> {
+    let plugin = PLUGINS.read().unwrap().iter().find(|plugin| plugin.tool.name == name).cloned()?;
+    Some(run(&plugin, args).await)
+}
+
+#[cfg(not(feature = "plugins"))]
+pub async fn execute(_name: &str, _args: &serde_json::Value) -> Option<Result<String, ToolError>> {
+    None
+}
+
+#[cfg(feature = "plugins")]
+async fn run(plugin: &Plugin, args: &serde_json::Value) -> Result<String, ToolError> {
+    let mut store = sandbox(&plugin.grants).map_err(|e| ToolError::NotConfigured(e.to_string()))?;
+    let instance = plugin.pre.instantiate_async(&mut store).await
+        .map_err(|e| ToolError::Failed(format!("Could not start the {} plugin: {}", plugin.tool.name, e)))?;
+    instance.call_execute(&mut store, &args.to_string()).await
+        .map_err(|e| ToolError::Failed(format!("The {} plugin crashed: {}", plugin.tool.name, e)))?
+        .map_err(ToolError::Failed)
+}
@@ -0,0 +1,516 @@
+// Cost basis and profit and loss (`pnl` tool and `/pnl` command). The buys, sells, and
+// transfers of the user's accounts go into the `positions` table: swaps and sends are recorded
+// as the agent makes them, and earlier history can be imported from the explorer API, priced at
+// CoinGecko's daily prices. Replaying a position's entries oldest first gives its average cost
+// basis and realized PnL; unrealized PnL values what is held at the current CoinGecko price.
+// Transfers in are acquired at their market price, transfers out leave at the average cost and
+// realize nothing, and gas is not counted.
+
+use crate::config::Config;
+use crate::db::{list_position_entries, save_position_entry, PositionEntry};
+use crate::dex::{SwapQuote, SwapToken};
+use crate::prices;
+use crate::report::{self, Direction};
+use crate::units;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use ethers::types::{Address, TransactionReceipt, H256, U256};
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+
+/// One token held by one account, from replaying its entries
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub account: String,
+    pub token: String,
+    pub token_address: Option<String>,
+    /// Held, in the token's smallest unit
+    pub quantity: U256,
+    pub decimals: u32,
+    /// USD paid for what is held
+    pub cost: f64,
+    pub realized: f64,
+    /// Entries without a price: their cost counted as zero, or their sale realized nothing
+    pub unpriced: usize,
+    /// Whether more left than the entries say was held, so part of it had no cost basis
+    pub oversold: bool,
+}
+
+impl Position {
+    pub fn is_open(&self) -> bool {
+        !self.quantity.is_zero()
+    }
+
+    /// Whole tokens held
+    pub fn held(&self) -> f64 {
+        whole(self.quantity, self.decimals)
+    }
+
+    /// USD paid per token held
+    pub fn average_cost(&self) -> Option<f64> {
+        self.is_open().then(|| self.cost / self.held())
+    }
+}
+
+/// The positions built by entries (oldest first), at average cost: buys and transfers in add
+/// their value to the cost, sells realize their price over the average cost, and transfers out
+/// take their share of the cost with them
+pub fn positions(entries: &[PositionEntry]) -> Vec<Position> {
+    let mut by_token: BTreeMap<(String, String), Position> = BTreeMap::new();
+    for entry in entries {
+        let key = (entry.account.clone(), entry.token_address.clone().unwrap_or_default());
+        let position = by_token.entry(key).or_insert_with(|| Position {
+            account: entry.account.clone(),
+            token: entry.token.clone(),
+            token_address: entry.token_address.clone(),
+            decimals: entry.decimals as u32,
+            ..Position::default()
+        });
+        let amount = U256::from_dec_str(&entry.amount).unwrap_or_default();
+        let price = entry.price_usd.as_deref().and_then(|price| price.parse::<f64>().ok());
+        if price.is_none() {
+            position.unpriced += 1;
+        }
+        match entry.kind.as_str() {
+            "buy" | "transfer_in" => {
+                position.quantity += amount;
+                position.cost += whole(amount, position.decimals) * price.unwrap_or(0.0);
+            },
+            kind => {
+                let covered = amount.min(position.quantity);
+                position.oversold |= amount > covered;
+                let basis = match position.is_open() {
+                    true => position.cost * whole(covered, position.decimals) / position.held(),
+                    false => 0.0,
+                };
+                if kind == "sell"
+                    && let Some(price) = price
+                {
+                    position.realized += whole(covered, position.decimals) * price - basis;
+                }
+                position.quantity -= covered;
+                position.cost -= basis;
+            },
+        }
+    }
+    by_token.into_values().collect()
+}
+
+/// Prices per token of both sides of a swap, valued at what was sold (or at what was bought,
+/// when only its price is known), so the purchase costs what the sale brought in
+pub fn swap_prices(sold: f64, sold_price: Option<f64>, bought: f64, bought_price: Option<f64>) -> (Option<f64>, Option<f64>) {
+    let value = sold_price.map(|price| price * sold).or(bought_price.map(|price| price * bought));
+    match value {
+        Some(value) if sold > 0.0 && bought > 0.0 => (Some(value / sold), Some(value / bought)),
+        _ => (sold_price, bought_price),
+    }
+}
+
+// A swap token as (symbol, contract); native ETH has no contract
+fn token_id(token: &SwapToken) -> (String, Option<String>) {
+    match token.native {
+        true => ("ETH".to_string(), None),
+        false => (token.symbol.clone(), Some(format!("{:?}", token.address))),
+    }
+}
+
+// An amount in a token's smallest unit as whole tokens, for valuing it
+fn whole(value: U256, decimals: u32) -> f64 {
+    units::format_token(value, decimals).parse().unwrap_or(0.0)
+}
+
+// USD price of a token now: ETH, or an ERC-20 by contract address
+async fn price_now(config: &Config, token_address: Option<&str>) -> Option<f64> {
+    match prices::token_price(&config.prices, token_address.unwrap_or("ETH")).await {
+        Ok(price) => price.usd,
+        Err(e) => {
+            tracing::debug!(token = ?token_address, error = %e, "No current price for position");
+            None
+        },
+    }
+}
+
+// USD price of a token on a past day
+async fn price_on(config: &Config, token_address: Option<&str>, date: NaiveDate) -> Option<f64> {
+    match prices::historical_usd_price(&config.prices, token_address.unwrap_or("ETH"), date).await {
+        Ok(price) => Some(price),
+        Err(e) => {
+            tracing::debug!(token = ?token_address, %date, error = %e, "No historical price for position");
+            None
+        },
+    }
+}
+
+async fn save(pool: &Pool<Postgres>, owner: Option<&str>, entry: &PositionEntry) -> bool {
+    match save_position_entry(pool, owner, entry).await {
+        Ok(saved) => saved,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to record position entry");
+            false
+        },
+    }
+}
+
+// What arrived of an ERC-20 at `to` in a transaction, from its Transfer logs
+fn received(receipt: &TransactionReceipt, token: Address, to: Address) -> Option<U256> {
+    let transfer = H256::from(ethers::utils::keccak256("Transfer(address,address,uint256)"));
+    let amounts: Vec<U256> = receipt.logs.iter()
+        .filter(|log| log.address == token && log.topics.len() == 3 && log.topics[0] == transfer && log.topics[2] == H256::from(to))
+        .map(|log| U256::from_big_endian(&log.data))
+        .collect();
+    (!amounts.is_empty()).then(|| amounts.iter().fold(U256::zero(), |total, amount| total + amount))
+}
+
+/// Record a swap the agent made: a sale of what went in and a purchase of what came out (as
+/// the receipt's Transfer logs say, or as quoted for ETH), at today's prices
+pub async fn record_swap(config: &Config, pool: Option<&Pool<Postgres>>, owner: Option<&str>, account: Address, quote: &SwapQuote, receipt: &TransactionReceipt) {
+    let Some(pool) = pool else { return };
+    let bought_amount = match quote.token_out.native {
+        true => quote.amount_out,
+        false => received(receipt, quote.token_out.address, account).unwrap_or(quote.amount_out),
+    };
+    let (sold_token, sold_address) = token_id(&quote.token_in);
+    let (bought_token, bought_address) = token_id(&quote.token_out);
+    let sold = whole(quote.amount_in, quote.token_in.decimals as u32);
+    let bought = whole(bought_amount, quote.token_out.decimals as u32);
+    let (sold_price, bought_price) = swap_prices(
+        sold, price_now(config, sold_address.as_deref()).await,
+        bought, price_now(config, bought_address.as_deref()).await,
+    );
+    let now = Utc::now().naive_utc();
+    let tx_hash = Some(format!("{:?}", receipt.transaction_hash));
+    for (token, token_address, kind, amount, decimals, price_usd) in [
+        (sold_token, sold_address, "sell", quote.amount_in, quote.token_in.decimals, sold_price),
+        (bought_token, bought_address, "buy", bought_amount, quote.token_out.decimals, bought_price),
+    ] {
+        let entry = PositionEntry {
+            chain_id: config.chain.chain_id as i64,
+            account: format!("{:?}", account),
+            token,
+            token_address,
+            kind: kind.to_string(),
+            amount: amount.to_string(),
+            decimals: decimals as i32,
+            price_usd: price_usd.map(|price| price.to_string()),
+            tx_hash: tx_hash.clone(),
+            source: "swap".to_string(),
+            occurred_at: now,
+        };
+        save(pool, owner, &entry).await;
+    }
+}
+
+/// Record ETH the agent sent from an account, leaving at its average cost
+pub async fn record_send(config: &Config, pool: Option<&Pool<Postgres>>, owner: Option<&str>, account: Address, value: U256, receipt: &TransactionReceipt) {
+    let Some(pool) = pool else { return };
+    if value.is_zero() {
+        return;
+    }
+    let entry = PositionEntry {
+        chain_id: config.chain.chain_id as i64,
+        account: format!("{:?}", account),
+        token: "ETH".to_string(),
+        token_address: None,
+        kind: "transfer_out".to_string(),
+        amount: value.to_string(),
+        decimals: 18,
+        price_usd: price_now(config, None).await.map(|price| price.to_string()),
+        tx_hash: Some(format!("{:?}", receipt.transaction_hash)),
+        source: "send".to_string(),
+        occurred_at: Utc::now().naive_utc(),
+    };
+    save(pool, owner, &entry).await;
+}
+
+/// What an import recorded
+#[derive(Debug, Clone, Default)]
+pub struct Imported {
+    pub recorded: usize,
+    /// Entries whose transaction was already recorded
+    pub known: usize,
+    pub unpriced: usize,
+    /// Whether the explorer cut a list short, so the end of the range may be missing
+    pub truncated: bool,
+}
+
+// What one transaction moved of one asset
+struct Leg {
+    token: String,
+    token_address: Option<String>,
+    incoming: bool,
+    value: U256,
+    decimals: u32,
+    time: NaiveDateTime,
+}
+
+/// Record the buys, sells, and transfers in an account's explorer history from the start of
+/// `from` to the end of `to` (UTC). A transaction that both took assets from the account and
+/// gave it others is a swap: what left was sold and what arrived was bought.
+pub async fn import(config: &Config, pool: &Pool<Postgres>, owner: Option<&str>, account: Address, from: NaiveDate, to: NaiveDate) -> anyhow::Result<Imported> {
+    let account = format!("{:?}", account);
+    let history = report::history(&config.etherscan, config.chain.chain_id, &account, from, to).await?;
+    // Legs per transaction, oldest transaction first
+    let mut transactions: Vec<(String, Vec<Leg>)> = Vec::new();
+    for transfer in history.transfers.iter().filter(|transfer| !transfer.failed && !transfer.value.is_zero() && transfer.direction != Direction::Own) {
+        let incoming = transfer.direction == Direction::In;
+        if transactions.last().is_none_or(|(hash, _)| *hash != transfer.hash) {
+            transactions.push((transfer.hash.clone(), Vec::new()));
+        }
+        let legs = &mut transactions.last_mut().unwrap().1;
+        match legs.iter_mut().find(|leg| leg.token_address == transfer.contract && leg.incoming == incoming) {
+            Some(leg) => leg.value += transfer.value,
+            None => legs.push(Leg {
+                token: transfer.asset.clone(),
+                token_address: transfer.contract.clone(),
+                incoming,
+                value: transfer.value,
+                decimals: transfer.decimals,
+                time: transfer.time.naive_utc(),
+            }),
+        }
+    }
+
+    let mut imported = Imported { truncated: history.truncated, ..Imported::default() };
+    for (hash, legs) in transactions {
+        let swap = legs.iter().any(|leg| leg.incoming) && legs.iter().any(|leg| !leg.incoming);
+        let amounts: Vec<f64> = legs.iter().map(|leg| whole(leg.value, leg.decimals)).collect();
+        let mut prices = Vec::new();
+        for leg in &legs {
+            prices.push(price_on(config, leg.token_address.as_deref(), leg.time.date()).await);
+        }
+        // A plain one-for-one swap values the purchase at the sale
+        if swap && legs.len() == 2 {
+            let (sold, bought) = if legs[0].incoming { (1, 0) } else { (0, 1) };
+            (prices[sold], prices[bought]) = swap_prices(amounts[sold], prices[sold], amounts[bought], prices[bought]);
+        }
+        for (leg, price_usd) in legs.iter().zip(prices) {
+            let kind = match (swap, leg.incoming) {
+                (true, true) => "buy",
+                (true, false) => "sell",
+                (false, true) => "transfer_in",
+                (false, false) => "transfer_out",
+            };
+            let entry = PositionEntry {
+                chain_id: config.chain.chain_id as i64,
+                account: account.clone(),
+                token: leg.token.clone(),
+                token_address: leg.token_address.clone(),
+                kind: kind.to_string(),
+                amount: leg.value.to_string(),
+                decimals: leg.decimals as i32,
+                price_usd: price_usd.map(|price| price.to_string()),
+                tx_hash: Some(hash.clone()),
+                source: "import".to_string(),
+                occurred_at: leg.time,
+            };
+            if save_position_entry(pool, owner, &entry).await? {
+                imported.recorded += 1;
+                imported.unpriced += usize::from(price_usd.is_none());
+            } else {
+                imported.known += 1;
+            }
+        }
+    }
+    Ok(imported)
+}
+
+// A signed USD amount, e.g. "+$12.50" or "-$3.00"
+fn signed_usd(value: f64) -> String {
+    format!("{}${:.2}", if value < 0.0 { "-" } else { "+" }, value.abs())
+}
+
+fn describe(position: &Position, price: Option<f64>) -> String {
+    let realized = signed_usd(position.realized);
+    if !position.is_open() {
+        return format!("- {}: closed, realized {}", position.token, realized);
+    }
+    let held = format!("{} at ${:.2} average (basis ${:.2})", units::format_token(position.quantity, position.decimals), position.average_cost().unwrap_or(0.0), position.cost);
+    match price {
+        Some(price) => {
+            let value = position.held() * price;
+            let unrealized = value - position.cost;
+            let percent = if position.cost > 0.0 { format!(" ({:+.2}%)", unrealized / position.cost * 100.0) } else { String::new() };
+            format!("- {}: {}; now ${:.2}, worth ${:.2}, unrealized {}{}; realized {}",
+                    position.token, held, price, value, signed_usd(unrealized), percent, realized)
+        },
+        None => format!("- {}: {}; no price now, so unrealized is unknown; realized {}", position.token, held, realized),
+    }
+}
+
+/// The positions of `owner` on the configured chain, optionally of one account or token, with
+/// their cost basis and PnL at current prices
+pub async fn summary(config: &Config, pool: &Pool<Postgres>, owner: Option<&str>, account: Option<Address>, token: Option<&str>) -> anyhow::Result<String> {
+    let account = account.map(|account| format!("{:?}", account));
+    let entries = list_position_entries(pool, owner, config.chain.chain_id as i64, account.as_deref()).await?;
+    let token = token.map(|token| token.trim().to_lowercase());
+    let positions: Vec<Position> = positions(&entries).into_iter()
+        .filter(|position| token.as_ref().is_none_or(|token| {
+            position.token.to_lowercase() == *token || position.token_address.as_deref() == Some(token.as_str())
+        }))
+        .collect();
+    if positions.is_empty() {
+        return Ok(format!("No positions recorded on {}. Swaps and sends are recorded as the agent makes them; import earlier history with the pnl tool's 'import' operation.", config.chain.name));
+    }
+
+    let mut sections: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    let (mut unrealized, mut basis, mut realized, mut unpriced_now) = (0.0, 0.0, 0.0, 0);
+    let mut notes = Vec::new();
+    for position in &positions {
+        let price = match position.is_open() {
+            true => price_now(config, position.token_address.as_deref()).await,
+            false => None,
+        };
+        if position.is_open() {
+            match price {
+                Some(price) => {
+                    unrealized += position.held() * price - position.cost;
+                    basis += position.cost;
+                },
+                None => unpriced_now += 1,
+            }
+        }
+        realized += position.realized;
+        if position.oversold {
+            notes.push(format!("More {} left {} than its entries say it held, so part of it had no cost basis; import its earlier history.", position.token, position.account));
+        }
+        sections.entry(position.account.as_str()).or_default().push(describe(position, price));
+    }
+
+    let mut lines = vec![format!("Positions on {} (average cost, USD):", config.chain.name)];
+    for (account, rows) in sections {
+        lines.push(format!("{}:", account));
+        lines.extend(rows);
+    }
+    let mut total = format!("Total: unrealized {} on ${:.2} basis, realized {}", signed_usd(unrealized), basis, signed_usd(realized));
+    if unpriced_now > 0 {
+        total.push_str(&format!(" ({} without a price now not counted)", unpriced_now));
+    }
+    lines.push(total);
+    let unpriced: usize = positions.iter().map(|position| position.unpriced).sum();
+    if unpriced > 0 {
+        notes.push(format!("{} entr{} had no price, so {} cost counted as zero or {} sale realized nothing.",
+                           unpriced, if unpriced == 1 { "y" } else { "ies" }, if unpriced == 1 { "its" } else { "their" }, if unpriced == 1 { "its" } else { "their" }));
+    }
+    notes.push("Gas is not counted.".to_string());
+    lines.extend(notes);
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::policy::{Policy, PolicyEngine};
+    use crate::test_support::{context, database, serve};
+    use crate::tools::{execute_tool, ToolContext};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn closes_positions_exactly() {
+        let entry = |kind: &str, amount: &str, price_usd: &str| PositionEntry {
+            chain_id: 1,
+            account: "0x00000000000000000000000000000000000000aa".to_string(),
+            token: "ETH".to_string(),
+            token_address: None,
+            kind: kind.to_string(),
+            amount: amount.to_string(),
+            decimals: 18,
+            price_usd: Some(price_usd.to_string()),
+            tx_hash: None,
+            source: "import".to_string(),
+            occurred_at: Utc::now().naive_utc(),
+        };
+        // 0.1 + 0.2 ETH in, 0.3 sold: not exact as floats, exact in wei
+        let entries = [
+            entry("buy", "100000000000000000", "1000"),
+            entry("transfer_in", "200000000000000000", "2500"),
+            entry("sell", "300000000000000000", "3000"),
+        ];
+        let position = &positions(&entries)[0];
+        assert!(!position.is_open() && !position.oversold);
+        assert_eq!(position.cost, 0.0);
+        assert!((position.realized - 300.0).abs() < 1e-9, "{}", position.realized);
+    }
+
+    #[tokio::test]
+    async fn needs_a_database_for_positions() {
+        let unconfigured = execute_tool(&context(Config::default()), "pnl", &json!({ "operation": "positions" })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn imports_history_into_positions_with_cost_basis_and_pnl() {
+        use axum::extract::{Path, Query};
+        use axum::routing::get;
+
+        let me = format!("{:?}", ethers::types::Address::random());
+        let (other, router) = ("0x00000000000000000000000000000000000000bb", "0x00000000000000000000000000000000000000cc");
+        let usdc = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        // 2025-02-01 and 2025-02-02
+        let (first, second) = ("1738368000", "1738454400");
+        let account = me.clone();
+        let explorer = axum::Router::new().route("/", get(move |Query(query): Query<HashMap<String, String>>| {
+            let me = account.clone();
+            async move {
+                let result = match query["action"].as_str() {
+                    "getblocknobytime" => json!("100"),
+                    "txlist" => json!([
+                        { "hash": "0x01", "timeStamp": first, "from": other, "to": me, "value": "2000000000000000000", "gasUsed": "21000", "gasPrice": "1", "isError": "0" },
+                        // Swapping 1 ETH for 3000 USDC
+                        { "hash": "0x02", "timeStamp": second, "from": me, "to": router, "value": "1000000000000000000", "gasUsed": "150000", "gasPrice": "1", "isError": "0" },
+                        { "hash": "0x03", "timeStamp": second, "from": me, "to": usdc, "value": "0", "gasUsed": "50000", "gasPrice": "1", "isError": "0" },
+                    ]),
+                    "txlistinternal" => json!([]),
+                    _ => json!([
+                        { "hash": "0x02", "timeStamp": second, "from": router, "to": me, "value": "3000000000", "tokenSymbol": "USDC", "tokenDecimal": "6", "contractAddress": usdc },
+                        { "hash": "0x03", "timeStamp": second, "from": me, "to": other, "value": "1000000000", "tokenSymbol": "USDC", "tokenDecimal": "6", "contractAddress": usdc },
+                    ]),
+                };
+                axum::Json(json!({ "status": "1", "message": "OK", "result": result }))
+            }
+        }));
+        let coingecko = axum::Router::new()
+            .route("/coins/ethereum/contract/{address}", get(move |Path(address): Path<String>| async move {
+                assert_eq!(address, usdc);
+                axum::Json(json!({ "id": "usd-coin" }))
+            }))
+            .route("/coins/{id}/history", get(|Path(id): Path<String>, Query(query): Query<HashMap<String, String>>| async move {
+                let usd = match (id.as_str(), query["date"].as_str()) {
+                    ("ethereum", "01-02-2025") => 2000.0,
+                    ("ethereum", _) => 3000.0,
+                    _ => 0.99,
+                };
+                axum::Json(json!({ "market_data": { "current_price": { "usd": usd } } }))
+            }))
+            .route("/simple/price", get(|| async { axum::Json(json!({ "ethereum": { "usd": 2500.0 } })) }))
+            .route("/simple/token_price/ethereum", get(move || async move { axum::Json(json!({ usdc: { "usd": 1.0 } })) }));
+
+        let mut config = Config::default();
+        config.etherscan.api_url = format!("{}/", serve(explorer).await);
+        config.etherscan.api_key = Some("explorer-key".to_string());
+        config.prices.coingecko_url = format!("{}/", serve(coingecko).await);
+        config.prices.cache_ttl_secs = 0;
+        let pool = database(&mut config).await;
+        let base = ToolContext::new(Arc::new(config), Arc::new(PolicyEngine::new(Policy::default())), Some(pool), None);
+        let ctx = ToolContext { user: Some(me.clone()), ..base.clone() };
+        let import = json!({ "operation": "import", "account": me, "from_date": "2025-02-01", "to_date": "2025-02-28" });
+        let imported = execute_tool(&ctx, "pnl", &import).await.unwrap();
+        assert!(imported.starts_with(&format!("Imported 4 entries for {} from 2025-02-01 to 2025-02-28", me)), "{}", imported);
+        let again = execute_tool(&ctx, "pnl", &import).await.unwrap();
+        assert!(again.contains("Imported 0 entries") && again.contains("(4 already recorded)"), "{}", again);
+
+        // 2 ETH in at $2000, 1 sold at $3000 for 3000 USDC at $1, then 1000 USDC sent away
+        let positions = execute_tool(&ctx, "pnl", &json!({ "operation": "positions" })).await.unwrap();
+        assert!(positions.starts_with(&format!("Positions on Sepolia (average cost, USD):\n{}:\n\
+                                                - ETH: 1 at $2000.00 average (basis $2000.00); now $2500.00, worth $2500.00, unrealized +$500.00 (+25.00%); realized +$1000.00\n\
+                                                - USDC: 2000 at $1.00 average (basis $2000.00); now $1.00, worth $2000.00, unrealized +$0.00 (+0.00%); realized +$0.00\n\
+                                                Total: unrealized +$500.00 on $4000.00 basis, realized +$1000.00\n\
+                                                Gas is not counted.", me)), "{}", positions);
+        let usdc_only = execute_tool(&ctx, "pnl", &json!({ "operation": "positions", "token": "usdc" })).await.unwrap();
+        assert!(usdc_only.contains("- USDC: 2000") && !usdc_only.contains("- ETH"), "{}", usdc_only);
+        let stranger = ToolContext { user: Some(format!("{:?}", ethers::types::Address::random())), ..base };
+        assert!(execute_tool(&stranger, "pnl", &json!({ "operation": "positions" })).await.unwrap().starts_with("No positions recorded on Sepolia"));
+    }
+}
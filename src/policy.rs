@@ -0,0 +1,429 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::db;
+use crate::units::format_eth;
+use ethers::abi::{self, ParamType, Token};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Deserializer};
+use sqlx::{Pool, Postgres};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Guardrails applied to every signing operation, loaded from `policy.json`.
+/// Every field is optional; an absent or empty field places no restriction.
+#[derive(Deserialize, Debug, Default)]
+pub struct Policy {
+    #[serde(default, deserialize_with = "deserialize_eth_amount")]
+    pub max_eth_per_tx: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_eth_amount")]
+    pub max_eth_per_day: Option<U256>,
+    #[serde(default)]
+    pub allowed_recipients: Vec<Address>,
+    #[serde(default)]
+    pub denied_recipients: Vec<Address>,
+    #[serde(default)]
+    pub allowed_chains: Vec<u64>,
+}
+
+// Accept ETH amounts either as JSON numbers (0.5) or strings ("0.5", "500 gwei") and store them in wei
+fn deserialize_eth_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+    let value: Option<serde_json::Value> = Option::deserialize(deserializer)?;
+    let text = match value {
+        None | Some(serde_json::Value::Null) => return Ok(None),
+        Some(serde_json::Value::String(text)) => text,
+        Some(serde_json::Value::Number(number)) => number.to_string(),
+        Some(other) => return Err(serde::de::Error::custom(format!("invalid ETH amount: {}", other))),
+    };
+    crate::units::parse_amount(&text)
+        .map(Some)
+        .map_err(|e| serde::de::Error::custom(format!("invalid ETH amount {}: {}", text, e)))
+}
+
+/// A value-moving operation about to be signed
+#[derive(Debug, Clone, Copy)]
+pub struct TxIntent {
+    pub chain_id: u64,
+    pub to: Address,
+    pub value: U256,
+    /// The token recipient when the call is an ERC-20/721/1155 transfer, decoded from its calldata
+    pub recipient: Option<Address>,
+}
+
+impl TxIntent {
+    /// A Safe's `execTransaction` moves the Safe's own funds, so it is checked as the call the
+    /// Safe makes rather than as a call to the Safe
+    pub fn new(chain_id: u64, to: Address, value: U256, data: &[u8]) -> Self {
+        match safe_exec_call(data) {
+            Some((to, value, data)) => TxIntent { chain_id, to, value, recipient: transfer_recipient(&data) },
+            None => TxIntent { chain_id, to, value, recipient: transfer_recipient(data) },
+        }
+    }
+}
+
+// The destination, value, and calldata of a Safe execTransaction call
+fn safe_exec_call(data: &[u8]) -> Option<(Address, U256, Vec<u8>)> {
+    // execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)
+    let args = data.strip_prefix(&[0x6a, 0x76, 0x12, 0x02])?;
+    let params = [ParamType::Address, ParamType::Uint(256), ParamType::Bytes, ParamType::Uint(8), ParamType::Uint(256),
+                  ParamType::Uint(256), ParamType::Uint(256), ParamType::Address, ParamType::Address, ParamType::Bytes];
+    match abi::decode(&params, args).ok()?.as_slice() {
+        [Token::Address(to), Token::Uint(value), Token::Bytes(data), ..] => Some((*to, *value, data.clone())),
+        _ => None,
+    }
+}
+
+/// Decode who receives the tokens moved by a `transfer`, `transferFrom` or `safeTransferFrom` call.
+/// The amount of tokens is not decoded: the ETH limits count native value only.
+pub fn transfer_recipient(data: &[u8]) -> Option<Address> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, args) = data.split_at(4);
+    let (params, index) = match selector {
+        // transfer(address,uint256)
+        [0xa9, 0x05, 0x9c, 0xbb] => (vec![ParamType::Address, ParamType::Uint(256)], 0),
+        // transferFrom(address,address,uint256) and safeTransferFrom(address,address,uint256)
+        [0x23, 0xb8, 0x72, 0xdd] | [0x42, 0x84, 0x2e, 0x0e] => (vec![ParamType::Address, ParamType::Address, ParamType::Uint(256)], 1),
+        // safeTransferFrom(address,address,uint256,bytes)
+        [0xb8, 0x8d, 0x4f, 0xde] => (vec![ParamType::Address, ParamType::Address, ParamType::Uint(256), ParamType::Bytes], 1),
+        // ERC-1155 safeTransferFrom(address,address,uint256,uint256,bytes)
+        [0xf2, 0x42, 0x43, 0x2a] => (vec![ParamType::Address, ParamType::Address, ParamType::Uint(256), ParamType::Uint(256), ParamType::Bytes], 1),
+        _ => return None,
+    };
+    match abi::decode(&params, args).ok()?.into_iter().nth(index)? {
+        Token::Address(recipient) => Some(recipient),
+        _ => None,
+    }
+}
+
+// Values sent or reserved, by reservation, with when they were
+type SpendLog = Arc<Mutex<Vec<(Uuid, DateTime<Utc>, U256)>>>;
+
+pub struct PolicyEngine {
+    policy: Policy,
+    // Set when the policy file exists but is invalid; every check then fails closed
+    load_error: Option<String>,
+    // Whose policy this is, e.g. a user's address; keys the spending persisted to the database
+    scope: String,
+    // Values sent or reserved in the last 24 hours, used for the daily limit without a database
+    spent: SpendLog,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: Policy) -> Self {
+        PolicyEngine {
+            policy,
+            load_error: None,
+            scope: "deployment".to_string(),
+            spent: Arc::default(),
+        }
+    }
+    
+    // Engine that refuses every transaction because the policy could not be loaded
+    fn invalid(error: String) -> Self {
+        PolicyEngine {
+            load_error: Some(error),
+            ..PolicyEngine::new(Policy::default())
+        }
+    }
+    
+    /// Check a transaction against the policy. Returns the refusal reason on violation.
+    ///
+    /// A transaction that passes has its value reserved towards the daily limit in the same step,
+    /// so concurrent sends can't both fit under it. The spending is kept in `pool` when there is
+    /// one, so the limit holds across restarts and replicas. The reservation is released when
+    /// dropped: call [`SpendReservation::commit`] once the transaction is sent.
+    pub async fn check(&self, intent: &TxIntent, pool: Option<&Pool<Postgres>>) -> Result<SpendReservation, String> {
+        if let Some(error) = &self.load_error {
+            return Err(format!("the transaction policy could not be loaded ({})", error));
+        }
+        let policy = &self.policy;
+        
+        if !policy.allowed_chains.is_empty() && !policy.allowed_chains.contains(&intent.chain_id) {
+            return Err(format!("chain {} is not in the allowed chains {:?}", intent.chain_id, policy.allowed_chains));
+        }
+        
+        // Both the called address and the recipient of a token transfer must pass the lists
+        for recipient in std::iter::once(intent.to).chain(intent.recipient) {
+            if policy.denied_recipients.contains(&recipient) {
+                return Err(format!("recipient {:?} is on the deny list", recipient));
+            }
+            
+            if !policy.allowed_recipients.is_empty() && !policy.allowed_recipients.contains(&recipient) {
+                return Err(format!("recipient {:?} is not on the allow list", recipient));
+            }
+        }
+        
+        if let Some(max_per_tx) = policy.max_eth_per_tx
+            && intent.value > max_per_tx
+        {
+            return Err(format!(
+                "{} ETH exceeds the per-transaction limit of {} ETH",
+                format_eth(intent.value),
+                format_eth(max_per_tx)
+            ));
+        }
+        
+        let Some(max_per_day) = policy.max_eth_per_day.filter(|_| !intent.value.is_zero()) else {
+            return Ok(SpendReservation { id: None, store: SpendStore::Memory(self.spent.clone()) });
+        };
+        let id = Uuid::new_v4();
+        let (store, spent_today, reserved) = match pool {
+            Some(pool) => {
+                let (spent, reserved) = db::reserve_policy_spend(pool, &self.scope, id, &intent.value.to_string(), &max_per_day.to_string())
+                    .await
+                    .map_err(|e| format!("the daily limit could not be checked ({})", e))?;
+                let spent = U256::from_dec_str(&spent).map_err(|e| format!("the daily limit could not be checked ({})", e))?;
+                (SpendStore::Database(pool.clone()), spent, reserved)
+            },
+            None => {
+                let (spent, reserved) = self.reserve_in_memory(id, intent.value, max_per_day);
+                (SpendStore::Memory(self.spent.clone()), spent, reserved)
+            },
+        };
+        if !reserved {
+            return Err(format!(
+                "{} ETH would exceed the daily limit of {} ETH ({} ETH already sent in the last 24 hours)",
+                format_eth(intent.value),
+                format_eth(max_per_day),
+                format_eth(spent_today)
+            ));
+        }
+        Ok(SpendReservation { id: Some(id), store })
+    }
+    
+    // Reserve a value unless it would take the last 24 hours' spending over `max`; the spending
+    // before it and whether it was reserved
+    fn reserve_in_memory(&self, id: Uuid, value: U256, max: U256) -> (U256, bool) {
+        let mut spent = self.spent.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(24);
+        spent.retain(|(_, at, _)| *at > cutoff);
+        let spent_today = spent.iter().fold(U256::zero(), |total, (_, _, value)| total.saturating_add(*value));
+        let reserved = spent_today.saturating_add(value) <= max;
+        if reserved {
+            spent.push((id, Utc::now(), value));
+        }
+        (spent_today, reserved)
+    }
+}
+
+#[derive(Debug)]
+enum SpendStore {
+    Memory(SpendLog),
+    Database(Pool<Postgres>),
+}
+
+/// A transaction's value, held towards the daily limit from the policy check until it is sent.
+/// Dropping it without [`commit`](Self::commit), e.g. when sending fails, releases the value.
+#[derive(Debug)]
+#[must_use = "the value is released as soon as the reservation is dropped"]
+pub struct SpendReservation {
+    // None once committed, or when nothing needed reserving
+    id: Option<Uuid>,
+    store: SpendStore,
+}
+
+impl SpendReservation {
+    /// Keep the value counted towards the daily limit, since the transaction was sent
+    pub fn commit(mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for SpendReservation {
+    fn drop(&mut self) {
+        let Some(id) = self.id.take() else { return };
+        match &self.store {
+            SpendStore::Memory(spent) => spent.lock().unwrap().retain(|(reserved, _, _)| *reserved != id),
+            SpendStore::Database(pool) => {
+                let pool = pool.clone();
+                match tokio::runtime::Handle::try_current() {
+                    Ok(runtime) => {
+                        runtime.spawn(async move {
+                            if let Err(e) = db::release_policy_spend(&pool, id).await {
+                                tracing::warn!(error = %e, "Failed to release reserved policy spending");
+                            }
+                        });
+                    },
+                    // Without a runtime the reservation stays, counting until it is 24 hours old
+                    Err(_) => tracing::warn!("No runtime to release reserved policy spending"),
+                }
+            },
+        }
+    }
+}
+
+pub fn load_policy(path: &str) -> anyhow::Result<Policy> {
+    let data = fs::read_to_string(path)?;
+    let policy: Policy = serde_json::from_str(&data)?;
+    Ok(policy)
+}
+
+/// Build a policy engine from a policy stored as JSON, as a user's is; an invalid one refuses
+/// everything
+pub fn policy_engine_from_json(name: &str, policy: &serde_json::Value) -> PolicyEngine {
+    match Policy::deserialize(policy) {
+        Ok(policy) => PolicyEngine { scope: name.to_string(), ..PolicyEngine::new(policy) },
+        Err(e) => {
+            tracing::error!(name, error = %e, "Invalid transaction policy; refusing all transactions");
+            PolicyEngine::invalid(format!("{}: {}", name, e))
+        }
+    }
+}
+
+/// Build the policy engine from the configured policy file.
+/// A missing file means no restrictions; an invalid file refuses everything.
+pub fn load_policy_engine(path: &str) -> PolicyEngine {
+    if !Path::new(path).exists() {
+        tracing::warn!(path, "No transaction policy found; signing is unrestricted");
+        return PolicyEngine::new(Policy::default());
+    }
+    match load_policy(path) {
+        Ok(policy) => PolicyEngine::new(policy),
+        Err(e) => {
+            tracing::error!(path, error = %e, "Failed to load transaction policy; refusing all transactions");
+            PolicyEngine::invalid(format!("{}: {}", path, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::database;
+    use ethers::utils::parse_ether;
+    use serde_json::json;
+
+    fn engine(policy: serde_json::Value) -> PolicyEngine {
+        PolicyEngine::new(Policy::deserialize(&policy).unwrap())
+    }
+
+    fn send(to: Address, eth: &str) -> TxIntent {
+        TxIntent::new(1, to, parse_ether(eth).unwrap(), &[])
+    }
+
+    #[tokio::test]
+    async fn refuses_values_over_the_per_transaction_limit() {
+        let engine = engine(json!({ "max_eth_per_tx": "0.5" }));
+        assert!(engine.check(&send(Address::random(), "0.5"), None).await.is_ok());
+        let reason = engine.check(&send(Address::random(), "0.51"), None).await.unwrap_err();
+        assert!(reason.contains("per-transaction limit"), "{}", reason);
+    }
+
+    #[tokio::test]
+    async fn daily_limit_rolls_over_after_24_hours() {
+        let engine = engine(json!({ "max_eth_per_day": 1 }));
+        engine.check(&send(Address::random(), "0.8"), None).await.unwrap().commit();
+        assert!(engine.check(&send(Address::random(), "0.3"), None).await.unwrap_err().contains("daily limit"));
+
+        // Spending from more than a day ago no longer counts
+        engine.spent.lock().unwrap()[0].1 = Utc::now() - Duration::hours(25);
+        assert!(engine.check(&send(Address::random(), "0.3"), None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn holds_values_towards_the_daily_limit_until_they_are_released() {
+        let engine = engine(json!({ "max_eth_per_day": 1 }));
+        let first = engine.check(&send(Address::random(), "0.6"), None).await.unwrap();
+        // A second send checked before the first is sent can't use the same allowance
+        let reason = engine.check(&send(Address::random(), "0.6"), None).await.unwrap_err();
+        assert!(reason.contains("0.6 ETH already sent"), "{}", reason);
+
+        // The first was never sent
+        drop(first);
+        engine.check(&send(Address::random(), "0.6"), None).await.unwrap().commit();
+        assert!(engine.check(&send(Address::random(), "0.6"), None).await.is_err());
+        assert!(engine.check(&send(Address::random(), "0.4"), None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn lets_only_as_many_concurrent_sends_through_as_the_daily_limit_allows() {
+        let engine = Arc::new(engine(json!({ "max_eth_per_day": 1 })));
+        let checks = (0..10).map(|_| {
+            let engine = engine.clone();
+            tokio::spawn(async move { engine.check(&send(Address::random(), "0.3"), None).await.map(SpendReservation::commit) })
+        });
+        let passed = futures::future::join_all(checks).await.into_iter().filter(|result| matches!(result, Ok(Ok(())))).count();
+        assert_eq!(passed, 3);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn keeps_daily_spending_in_the_database() {
+        let mut config = crate::config::Config::default();
+        let pool = database(&mut config).await;
+        // Engines for the same user share their spending, as replicas or a restarted agent do
+        let name = format!("{:?}", Address::random());
+        let (engine, restarted) = (policy_engine_from_json(&name, &json!({ "max_eth_per_day": 1 })),
+                                   policy_engine_from_json(&name, &json!({ "max_eth_per_day": 1 })));
+        engine.check(&send(Address::random(), "0.6"), Some(&pool)).await.unwrap().commit();
+        let reason = restarted.check(&send(Address::random(), "0.6"), Some(&pool)).await.unwrap_err();
+        assert!(reason.contains("0.6 ETH already sent"), "{}", reason);
+
+        let unsent = restarted.check(&send(Address::random(), "0.4"), Some(&pool)).await.unwrap();
+        assert!(engine.check(&send(Address::random(), "0.1"), Some(&pool)).await.is_err());
+        drop(unsent);
+        // The release is made in the background
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(engine.check(&send(Address::random(), "0.1"), Some(&pool)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn applies_the_recipient_lists_to_token_transfers() {
+        let (allowed, denied, token) = (Address::random(), Address::random(), Address::random());
+        let transfer = |to: Address| {
+            let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+            data.extend(abi::encode(&[Token::Address(to), Token::Uint(U256::from(100))]));
+            TxIntent::new(1, token, U256::zero(), &data)
+        };
+
+        let deny = engine(json!({ "denied_recipients": [denied] }));
+        assert!(deny.check(&send(denied, "0.1"), None).await.unwrap_err().contains("deny list"));
+        assert!(deny.check(&transfer(denied), None).await.unwrap_err().contains("deny list"));
+        assert!(deny.check(&transfer(allowed), None).await.is_ok());
+
+        let allow = engine(json!({ "allowed_recipients": [allowed, token] }));
+        assert!(allow.check(&send(allowed, "0.1"), None).await.is_ok());
+        assert!(allow.check(&send(denied, "0.1"), None).await.unwrap_err().contains("allow list"));
+        assert!(allow.check(&transfer(allowed), None).await.is_ok());
+        assert!(allow.check(&transfer(denied), None).await.unwrap_err().contains("allow list"));
+    }
+
+    #[test]
+    fn decodes_the_recipient_of_transfer_from() {
+        let (owner, recipient) = (Address::random(), Address::random());
+        let mut data = vec![0x23, 0xb8, 0x72, 0xdd];
+        data.extend(abi::encode(&[Token::Address(owner), Token::Address(recipient), Token::Uint(U256::one())]));
+        assert_eq!(transfer_recipient(&data), Some(recipient));
+        assert_eq!(transfer_recipient(&[0x12, 0x34, 0x56, 0x78]), None);
+        assert_eq!(transfer_recipient(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn checks_safe_executions_as_the_call_the_safe_makes() {
+        let (safe, token, recipient) = (Address::random(), Address::random(), Address::random());
+        let mut transfer = vec![0xa9, 0x05, 0x9c, 0xbb];
+        transfer.extend(abi::encode(&[Token::Address(recipient), Token::Uint(U256::from(100))]));
+        let mut data = ethers::utils::id("execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)").to_vec();
+        data.extend(abi::encode(&[Token::Address(token), Token::Uint(parse_ether("2").unwrap()), Token::Bytes(transfer), Token::Uint(U256::zero()),
+                                  Token::Uint(U256::zero()), Token::Uint(U256::zero()), Token::Uint(U256::zero()),
+                                  Token::Address(Address::zero()), Token::Address(Address::zero()), Token::Bytes(vec![1; 65])]));
+
+        let intent = TxIntent::new(1, safe, U256::zero(), &data);
+        assert_eq!((intent.to, intent.value, intent.recipient), (token, parse_ether("2").unwrap(), Some(recipient)));
+        let deny = engine(json!({ "denied_recipients": [recipient] }));
+        assert!(deny.check(&intent, None).await.unwrap_err().contains("deny list"));
+    }
+
+    #[tokio::test]
+    async fn refuses_everything_when_the_policy_file_is_invalid() {
+        let path = std::env::temp_dir().join(format!("policy-{}.json", std::process::id()));
+        fs::write(&path, "{ \"max_eth_per_tx\": \"lots\" }").unwrap();
+        let engine = load_policy_engine(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let reason = engine.check(&send(Address::random(), "0"), None).await.unwrap_err();
+        assert!(reason.contains("could not be loaded"), "{}", reason);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::config::PricesConfig;
+use crate::units;
+use chrono::NaiveDate;
+use ethers::prelude::*;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// CoinGecko ids for common symbols, so these don't need a search request
+const KNOWN_SYMBOLS: &[(&str, &str)] = &[
+    ("ETH", "ethereum"),
+    ("WETH", "weth"),
+    ("BTC", "bitcoin"),
+    ("WBTC", "wrapped-bitcoin"),
+    ("USDC", "usd-coin"),
+    ("USDT", "tether"),
+    ("DAI", "dai"),
+    ("LINK", "chainlink"),
+    ("UNI", "uniswap"),
+    ("AAVE", "aave"),
+    ("MKR", "maker"),
+    ("ARB", "arbitrum"),
+    ("OP", "optimism"),
+    ("MATIC", "matic-network"),
+    ("POL", "polygon-ecosystem-token"),
+];
+
+/// Current price of a token in USD and EUR
+#[derive(Debug, Clone)]
+pub struct TokenPrice {
+    pub usd: Option<f64>,
+    pub eur: Option<f64>,
+    /// Price change over the last 24 hours in percent (USD)
+    pub usd_24h_change: Option<f64>,
+}
+
+lazy_static::lazy_static! {
+    // Recent quotes by CoinGecko id or contract address
+    static ref PRICE_CACHE: Mutex<HashMap<String, (Instant, TokenPrice)>> = Mutex::new(HashMap::new());
+    // Past daily USD prices by CoinGecko id and date, which don't change
+    static ref HISTORY_CACHE: Mutex<HashMap<(String, NaiveDate), f64>> = Mutex::new(HashMap::new());
+    // CoinGecko ids of contract addresses
+    static ref CONTRACT_IDS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+fn cached(key: &str, ttl: Duration) -> Option<TokenPrice> {
+    let cache = PRICE_CACHE.lock().unwrap();
+    cache.get(key)
+        .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+        .map(|(_, price)| price.clone())
+}
+
+fn get(client: &Client, config: &PricesConfig, path: &str) -> reqwest::RequestBuilder {
+    let request = client.get(format!("{}{}", config.coingecko_url.trim_end_matches('/'), path));
+    match &config.coingecko_api_key {
+        Some(key) => request.header("x-cg-demo-api-key", key),
+        None => request,
+    }
+}
+
+async fn fetch_json(request: reqwest::RequestBuilder) -> anyhow::Result<serde_json::Value> {
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(anyhow::anyhow!("CoinGecko rate limit reached; try again in a minute"));
+    }
+    Ok(response.error_for_status()?.json().await?)
+}
+
+fn parse_price(entry: &serde_json::Value) -> TokenPrice {
+    TokenPrice {
+        usd: entry.get("usd").and_then(|v| v.as_f64()),
+        eur: entry.get("eur").and_then(|v| v.as_f64()),
+        usd_24h_change: entry.get("usd_24h_change").and_then(|v| v.as_f64()),
+    }
+}
+
+// Map a symbol to a CoinGecko id, searching when it isn't a well-known one. Among coins
+// sharing the symbol, the search returns the highest market cap first.
+async fn coin_id(client: &Client, config: &PricesConfig, symbol: &str) -> anyhow::Result<String> {
+    let upper = symbol.to_uppercase();
+    if let Some((_, id)) = KNOWN_SYMBOLS.iter().find(|(known, _)| *known == upper) {
+        return Ok(id.to_string());
+    }
+    let body = fetch_json(get(client, config, "/search").query(&[("query", symbol)])).await?;
+    body.get("coins")
+        .and_then(|coins| coins.as_array())
+        .and_then(|coins| coins.iter().find(|coin| {
+            coin.get("symbol").and_then(|s| s.as_str()).is_some_and(|s| s.eq_ignore_ascii_case(symbol))
+        }))
+        .and_then(|coin| coin.get("id").and_then(|id| id.as_str()))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("No token with symbol {} found on CoinGecko", symbol))
+}
+
+/// Price of a token given by symbol (`ETH`, `USDC`) or by ERC-20 contract address on the
+/// configured CoinGecko platform. Quotes are cached for `cache_ttl_secs`.
+pub async fn token_price(config: &PricesConfig, token: &str) -> anyhow::Result<TokenPrice> {
+    let token = token.trim();
+    let ttl = Duration::from_secs(config.cache_ttl_secs);
+    let client = Client::new();
+    let is_address = token.starts_with("0x") && token.len() == 42;
+
+    let key = if is_address { token.to_lowercase() } else { coin_id(&client, config, token).await? };
+    if let Some(price) = cached(&key, ttl) {
+        tracing::debug!(token = %key, "Using cached token price");
+        return Ok(price);
+    }
+
+    let body = if is_address {
+        let path = format!("/simple/token_price/{}", config.coingecko_platform);
+        fetch_json(get(&client, config, &path).query(&[
+            ("contract_addresses", key.as_str()),
+            ("vs_currencies", "usd,eur"),
+            ("include_24hr_change", "true"),
+        ])).await?
+    } else {
+        fetch_json(get(&client, config, "/simple/price").query(&[
+            ("ids", key.as_str()),
+            ("vs_currencies", "usd,eur"),
+            ("include_24hr_change", "true"),
+        ])).await?
+    };
+    let price = body.get(&key)
+        .map(parse_price)
+        .ok_or_else(|| anyhow::anyhow!("CoinGecko has no price for {}", token))?;
+
+    PRICE_CACHE.lock().unwrap().insert(key, (Instant::now(), price.clone()));
+    Ok(price)
+}
+
+/// USD prices of several tokens by symbol, fetching the ones not cached in one request.
+/// Symbols CoinGecko doesn't know are left out.
+pub async fn usd_prices(config: &PricesConfig, symbols: &[String]) -> anyhow::Result<HashMap<String, f64>> {
+    let ttl = Duration::from_secs(config.cache_ttl_secs);
+    let client = Client::new();
+    let mut ids = Vec::new();
+    for symbol in symbols {
+        match coin_id(&client, config, symbol).await {
+            Ok(id) => ids.push((symbol.clone(), id)),
+            Err(e) => tracing::debug!(symbol = %symbol, error = %e, "No CoinGecko id for token"),
+        }
+    }
+
+    let mut missing: Vec<&str> = ids.iter()
+        .filter(|(_, id)| cached(id, ttl).is_none())
+        .map(|(_, id)| id.as_str())
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    if !missing.is_empty() {
+        let body = fetch_json(get(&client, config, "/simple/price").query(&[
+            ("ids", missing.join(",").as_str()),
+            ("vs_currencies", "usd,eur"),
+            ("include_24hr_change", "true"),
+        ])).await?;
+        let mut cache = PRICE_CACHE.lock().unwrap();
+        for id in missing {
+            if let Some(entry) = body.get(id) {
+                cache.insert(id.to_string(), (Instant::now(), parse_price(entry)));
+            }
+        }
+    }
+    Ok(ids.into_iter()
+        .filter_map(|(symbol, id)| cached(&id, Duration::MAX)?.usd.map(|usd| (symbol, usd)))
+        .collect())
+}
+
+/// USD price of a token on a past day (CoinGecko's daily price, at 00:00 UTC), given by symbol
+/// or by ERC-20 contract address on the configured CoinGecko platform
+pub async fn historical_usd_price(config: &PricesConfig, token: &str, date: NaiveDate) -> anyhow::Result<f64> {
+    let token = token.trim();
+    let client = Client::new();
+    let id = if token.starts_with("0x") && token.len() == 42 {
+        let address = token.to_lowercase();
+        let known = CONTRACT_IDS.lock().unwrap().get(&address).cloned();
+        match known {
+            Some(id) => id,
+            None => {
+                let path = format!("/coins/{}/contract/{}", config.coingecko_platform, address);
+                let body = fetch_json(get(&client, config, &path)).await
+                    .map_err(|e| anyhow::anyhow!("CoinGecko doesn't know the token {}: {}", token, e))?;
+                let id = body["id"].as_str().ok_or_else(|| anyhow::anyhow!("CoinGecko doesn't know the token {}", token))?;
+                CONTRACT_IDS.lock().unwrap().insert(address, id.to_string());
+                id.to_string()
+            },
+        }
+    } else {
+        coin_id(&client, config, token).await?
+    };
+    let key = (id, date);
+    if let Some(price) = HISTORY_CACHE.lock().unwrap().get(&key) {
+        return Ok(*price);
+    }
+    let path = format!("/coins/{}/history", key.0);
+    let day = date.format("%d-%m-%Y").to_string();
+    let body = fetch_json(get(&client, config, &path).query(&[("date", day.as_str()), ("localization", "false")])).await?;
+    let price = body.pointer("/market_data/current_price/usd")
+        .and_then(|usd| usd.as_f64())
+        .ok_or_else(|| anyhow::anyhow!("CoinGecko has no price for {} on {}", token, date))?;
+    HISTORY_CACHE.lock().unwrap().insert(key, price);
+    Ok(price)
+}
+
+pub fn format_price(token: &str, price: &TokenPrice) -> String {
+    let mut parts = Vec::new();
+    if let Some(usd) = price.usd {
+        parts.push(format!("${:.4}", usd));
+    }
+    if let Some(eur) = price.eur {
+        parts.push(format!("€{:.4}", eur));
+    }
+    let mut line = format!("{}: {}", token, if parts.is_empty() { "no price available".to_string() } else { parts.join(" / ") });
+    if let Some(change) = price.usd_24h_change {
+        line.push_str(&format!(" ({:+.2}% in 24h)", change));
+    }
+    line
+}
+
+ethers::contract::abigen!(
+    AggregatorV3,
+    r#"[
+        function decimals() external view returns (uint8)
+        function description() external view returns (string)
+        function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+    ]"#
+);
+
+/// Latest answer of a Chainlink price feed
+#[derive(Debug, Clone)]
+pub struct FeedPrice {
+    pub description: String,
+    pub price: String,
+    pub round_id: u128,
+    pub updated_at: u64,
+}
+
+/// Read `latestRoundData` from a Chainlink aggregator (or its proxy) and scale the answer
+/// by the feed's decimals
+pub async fn chainlink_price<M: Middleware + 'static>(client: Arc<M>, feed: Address) -> anyhow::Result<FeedPrice> {
+    let aggregator = AggregatorV3::new(feed, client);
+    let decimals = aggregator.decimals().call().await
+        .map_err(|e| anyhow::anyhow!("{:?} does not look like a Chainlink feed: {}", feed, e))?;
+    let description = aggregator.description().call().await.unwrap_or_default();
+    let (round_id, answer, _started_at, updated_at, _answered_in_round) = aggregator.latest_round_data().call().await?;
+    if updated_at.is_zero() {
+        return Err(anyhow::anyhow!("Feed {:?} has no completed round", feed));
+    }
+    Ok(FeedPrice {
+        description,
+        price: units::format_fixed(answer, decimals as u32),
+        round_id,
+        updated_at: updated_at.as_u64(),
+    })
+}
+
+pub fn format_feed_price(pair: &str, network: &str, feed: &FeedPrice) -> String {
+    let updated = chrono::DateTime::from_timestamp(feed.updated_at as i64, 0)
+        .map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| feed.updated_at.to_string());
+    let age_secs = chrono::Utc::now().timestamp() - feed.updated_at as i64;
+    let name = if feed.description.is_empty() { pair } else { feed.description.as_str() };
+    format!("{}: {} (Chainlink on {}, round {}, updated {}, {} min ago)",
+            name, feed.price, network, feed.round_id, updated, age_secs.max(0) / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+    use axum::extract::{Path, Query};
+    use axum::routing::get;
+    use serde_json::json;
+
+    // A CoinGecko stand-in quoting every coin and contract at $2, recording the requests it gets
+    async fn coingecko(requests: Arc<Mutex<Vec<String>>>) -> PricesConfig {
+        let (searches, prices, token_prices) = (requests.clone(), requests.clone(), requests);
+        let app = axum::Router::new()
+            .route("/search", get(move |Query(query): Query<HashMap<String, String>>| async move {
+                searches.lock().unwrap().push(format!("search {}", query["query"]));
+                if query["query"] == "NOSUCH" {
+                    return axum::Json(json!({ "coins": [{ "id": "nosuchcoin", "symbol": "NOSUCHCOIN" }] }));
+                }
+                // Coins sharing a symbol come highest market cap first
+                axum::Json(json!({ "coins": [
+                    { "id": "pepe-classic", "symbol": "PEPEC" },
+                    { "id": format!("{}-coin", query["query"].to_lowercase()), "symbol": query["query"].to_uppercase() },
+                    { "id": format!("{}-fork", query["query"].to_lowercase()), "symbol": query["query"].to_lowercase() },
+                ] }))
+            }))
+            .route("/simple/price", get(move |Query(query): Query<HashMap<String, String>>| async move {
+                prices.lock().unwrap().push(format!("price {}", query["ids"]));
+                axum::Json(json!({ query["ids"].clone(): { "usd": 2.0, "eur": 1.8, "usd_24h_change": -1.5 } }))
+            }))
+            .route("/simple/token_price/{platform}", get(move |Path(platform): Path<String>, Query(query): Query<HashMap<String, String>>| async move {
+                token_prices.lock().unwrap().push(format!("token price {} {}", platform, query["contract_addresses"]));
+                axum::Json(json!({ query["contract_addresses"].clone(): { "usd": 2.0 } }))
+            }));
+        PricesConfig { coingecko_url: serve(app).await, ..PricesConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn resolves_tokens_by_symbol_or_contract_address() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let config = coingecko(requests.clone()).await;
+        // Well-known symbols, in any case, need no search
+        assert_eq!(coin_id(&Client::new(), &config, "usdc").await.unwrap(), "usd-coin");
+        assert!(requests.lock().unwrap().is_empty());
+
+        let symbol = format!("T{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+        let price = token_price(&config, &format!(" {} ", symbol)).await.unwrap();
+        assert_eq!(format_price(&symbol, &price), format!("{}: $2.0000 / €1.8000 (-1.50% in 24h)", symbol));
+        let id = format!("{}-coin", symbol.to_lowercase());
+        assert_eq!(*requests.lock().unwrap(), [format!("search {}", symbol), format!("price {}", id)]);
+
+        // Contract addresses are quoted on the configured platform, whatever their case
+        let address = format!("{:?}", Address::random());
+        token_price(&config, &address.to_uppercase().replacen("0X", "0x", 1)).await.unwrap();
+        assert_eq!(requests.lock().unwrap().last().unwrap(), &format!("token price ethereum {}", address));
+
+        // Only an exact symbol match counts
+        let error = token_price(&config, "NOSUCH").await.unwrap_err();
+        assert_eq!(error.to_string(), "No token with symbol NOSUCH found on CoinGecko");
+    }
+
+    #[tokio::test]
+    async fn reuses_quotes_within_the_cache_ttl() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let config = coingecko(requests.clone()).await;
+        let address = format!("{:?}", Address::random());
+        token_price(&config, &address).await.unwrap();
+        token_price(&config, &address).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Once the quote is older than the TTL, it is fetched again
+        let expired = PricesConfig { cache_ttl_secs: 0, ..config };
+        token_price(&expired, &address).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
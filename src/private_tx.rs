@@ -0,0 +1,32 @@
+use crate::config::ChainConfig;
+use crate::provider::{self, EthProvider};
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+
+// Polls of the public node before a privately sent transaction is taken as dropped. The node
+// only learns of it once it is mined, which can take a few blocks.
+const PRIVATE_TX_RETRIES: usize = 30;
+
+/// Private RPC to send a transaction through, or None for the public mempool. `private`
+/// overrides the chain's `private_transactions` for one transaction.
+pub async fn relay(chain: &ChainConfig, private: Option<bool>) -> anyhow::Result<Option<EthProvider>> {
+    if !private.unwrap_or(chain.private_transactions) {
+        return Ok(None);
+    }
+    let url = chain.private_rpc_url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Private submission needs chain.private_rpc_url in config.toml"))?;
+    provider::connect(url).await.map(Some)
+}
+
+/// Sign `tx` with `client` and submit it through `relay` rather than the client's node. The
+/// receipt is still looked for on the client's node.
+pub async fn send<'a, M: Middleware>(client: &'a M, relay: &EthProvider, from: Address, mut tx: TypedTransaction) -> anyhow::Result<PendingTransaction<'a, M::Provider>> {
+    client.fill_transaction(&mut tx, None).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let signature = client.sign_transaction(&tx, from).await
+        .map_err(|e| anyhow::anyhow!("Could not sign transaction: {}", e))?;
+    let tx_hash = relay.send_raw_transaction(tx.rlp_signed(&signature)).await
+        .map_err(|e| anyhow::anyhow!("Private RPC rejected the transaction: {}", e))?
+        .tx_hash();
+    Ok(PendingTransaction::new(tx_hash, client.provider()).retries(PRIVATE_TX_RETRIES))
+}
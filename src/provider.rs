@@ -0,0 +1,348 @@
+use async_trait::async_trait;
+use ethers::prelude::*;
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Attempts to re-establish a dropped WebSocket before requests on it fail. Subscriptions are
+// renewed on the new connection.
+const WS_RECONNECTS: usize = 10;
+
+// With several endpoints, a request that takes longer than this moves on to the next one
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+// A failed endpoint is tried after the healthy ones for this long
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// JSON-RPC transport picked from the endpoint's URL scheme
+#[derive(Debug, Clone)]
+pub enum RpcClient {
+    Http(Http),
+    Ws(Ws),
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcClient {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            RpcClient::Http(http) => JsonRpcClient::request(http, method, params).await.map_err(Into::into),
+            RpcClient::Ws(ws) => JsonRpcClient::request(ws, method, params).await.map_err(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    // Moving average of response times
+    latency: Option<Duration>,
+    down_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Endpoint<C = RpcClient> {
+    url: String,
+    client: C,
+    health: std::sync::Mutex<Health>,
+}
+
+// Host of an endpoint for logs, leaving out API keys in the path
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown host".to_string())
+}
+
+impl<C: JsonRpcClient> Endpoint<C> {
+    fn succeeded(&self, elapsed: Duration) {
+        let mut health = self.health.lock().unwrap();
+        health.latency = Some(match health.latency {
+            Some(latency) => (latency * 7 + elapsed * 3) / 10,
+            None => elapsed,
+        });
+        if health.down_until.take().is_some() {
+            tracing::info!(endpoint = %host(&self.url), "RPC endpoint recovered");
+        }
+    }
+
+    fn failed(&self, error: &str) {
+        tracing::warn!(endpoint = %host(&self.url), error = %error, "RPC endpoint failed");
+        self.health.lock().unwrap().down_until = Some(Instant::now() + FAILURE_COOLDOWN);
+    }
+
+    // Healthy endpoints first, then the fastest
+    fn rank(&self, now: Instant) -> (bool, Duration) {
+        let health = self.health.lock().unwrap();
+        (health.down_until.is_some_and(|until| until > now), health.latency.unwrap_or(Duration::MAX))
+    }
+
+    async fn probe(&self) {
+        let started = Instant::now();
+        match tokio::time::timeout(REQUEST_TIMEOUT, JsonRpcClient::request::<_, U64>(&self.client, "eth_blockNumber", ())).await {
+            Ok(Ok(_)) => self.succeeded(started.elapsed()),
+            Ok(Err(e)) => self.failed(&e.to_string()),
+            Err(_) => self.failed("health check timed out"),
+        }
+    }
+}
+
+/// Transport over one or more endpoints of the same chain. Requests go to the healthiest,
+/// fastest endpoint and fail over to the others on connection errors and timeouts; error
+/// responses from a node (such as reverts) are returned as they are. Generic over the transport
+/// of each endpoint so tests can stand in for the nodes.
+#[derive(Debug, Clone)]
+pub struct FailoverClient<C = RpcClient> {
+    endpoints: Arc<Vec<Endpoint<C>>>,
+}
+
+impl<C: JsonRpcClient> FailoverClient<C> {
+    fn ranked(&self) -> Vec<&Endpoint<C>> {
+        let now = Instant::now();
+        let mut endpoints: Vec<&Endpoint<C>> = self.endpoints.iter().collect();
+        // Stable, so equally ranked endpoints keep their configured order
+        endpoints.sort_by_key(|endpoint| endpoint.rank(now));
+        endpoints
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for FailoverClient<C> {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if let [endpoint] = self.endpoints.as_slice() {
+            return JsonRpcClient::request(&endpoint.client, method, params).await.map_err(Into::into);
+        }
+        let mut last_error = None;
+        for endpoint in self.ranked() {
+            let started = Instant::now();
+            let request = JsonRpcClient::request(&endpoint.client, method, &params);
+            match tokio::time::timeout(REQUEST_TIMEOUT, async { request.await.map_err(Into::into) }).await {
+                Ok(Ok(result)) => {
+                    endpoint.succeeded(started.elapsed());
+                    return Ok(result);
+                },
+                // The node answered, so another one would answer the same
+                Ok(Err(e)) if RpcError::as_error_response(&e).is_some() => {
+                    endpoint.succeeded(started.elapsed());
+                    return Err(e);
+                },
+                Ok(Err(e)) => {
+                    endpoint.failed(&e.to_string());
+                    last_error = Some(e);
+                },
+                Err(_) => {
+                    endpoint.failed(&format!("{} timed out", method));
+                    last_error = Some(ProviderError::CustomError(format!("{} timed out on {}", method, host(&endpoint.url))));
+                },
+            }
+        }
+        Err(last_error.unwrap_or_else(|| ProviderError::CustomError("No RPC endpoints".to_string())))
+    }
+}
+
+pub type EthProvider = Provider<FailoverClient>;
+
+lazy_static! {
+    // One provider per endpoint list, so HTTP connections are pooled and a WebSocket is opened once
+    static ref PROVIDERS: Mutex<HashMap<String, EthProvider>> = Mutex::new(HashMap::new());
+}
+
+async fn connect_endpoint(url: &str) -> anyhow::Result<RpcClient> {
+    match url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase()).as_deref() {
+        Some("http" | "https") => Ok(RpcClient::Http(Http::from_str(url)?)),
+        Some("ws" | "wss") => Ok(RpcClient::Ws(Ws::connect_with_reconnects(url, WS_RECONNECTS).await
+            .map_err(|e| anyhow::anyhow!("Could not connect to {}: {}", host(url), e))?)),
+        _ => Err(anyhow::anyhow!("Unsupported RPC URL {}; use http(s):// or ws(s)://", url)),
+    }
+}
+
+// Probe every endpoint in the background, so one that failed is ranked again once it is back
+// and latencies stay current. Stops when the provider is dropped.
+fn spawn_health_checks(endpoints: Weak<Vec<Endpoint>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(endpoints) = endpoints.upgrade() else { break };
+            for endpoint in endpoints.iter() {
+                endpoint.probe().await;
+            }
+        }
+    });
+}
+
+/// Provider for a comma-separated list of `http(s)://` or `ws(s)://` endpoints, reused across
+/// calls. Endpoints that can't be reached at first are left out.
+pub async fn connect(urls: &str) -> anyhow::Result<EthProvider> {
+    let mut providers = PROVIDERS.lock().await;
+    if let Some(provider) = providers.get(urls) {
+        return Ok(provider.clone());
+    }
+    let mut endpoints = Vec::new();
+    let mut last_error = None;
+    for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+        match connect_endpoint(url).await {
+            Ok(client) => endpoints.push(Endpoint { url: url.to_string(), client, health: Default::default() }),
+            Err(e) => {
+                tracing::warn!(endpoint = %host(url), error = %e, "Skipping RPC endpoint");
+                last_error = Some(e);
+            },
+        }
+    }
+    if endpoints.is_empty() {
+        return Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No RPC URL given")));
+    }
+
+    let endpoints = Arc::new(endpoints);
+    if endpoints.len() > 1 {
+        spawn_health_checks(Arc::downgrade(&endpoints));
+    }
+    let provider = Provider::new(FailoverClient { endpoints });
+    providers.insert(urls.to_string(), provider.clone());
+    Ok(provider)
+}
+
+/// Provider for log and block subscriptions, sharing the first WebSocket connection among the
+/// endpoints
+pub async fn pubsub(urls: &str) -> anyhow::Result<Provider<Ws>> {
+    let provider = connect(urls).await?;
+    provider.as_ref().endpoints.iter()
+        .find_map(|endpoint| match &endpoint.client {
+            RpcClient::Ws(ws) => Some(Provider::new(ws.clone())),
+            RpcClient::Http(_) => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("Subscriptions need a ws(s):// endpoint"))
+}
+
+/// Drop the cached provider for endpoints whose connection is gone for good, so the next call
+/// reconnects
+pub async fn forget(urls: &str) {
+    PROVIDERS.lock().await.remove(urls);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone, Copy)]
+    enum Reply {
+        Answer(u64),
+        // A transport failure, such as a refused connection
+        Unreachable,
+        // An error response from the node itself
+        Revert,
+        Stall,
+    }
+
+    // A node answering every request with `reply`, counting the requests
+    #[derive(Debug, Clone)]
+    struct MockNode {
+        reply: Reply,
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockNode {
+        type Error = ProviderError;
+
+        async fn request<T, R>(&self, _method: &str, _params: T) -> Result<R, ProviderError>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            match self.reply {
+                Reply::Answer(block) => Ok(serde_json::from_value(serde_json::json!(format!("{:#x}", block)))?),
+                Reply::Unreachable => Err(ProviderError::CustomError("connection refused".to_string())),
+                Reply::Revert => Err(HttpClientError::JsonRpcError(JsonRpcError { code: 3, message: "execution reverted".to_string(), data: None }).into()),
+                Reply::Stall => std::future::pending().await,
+            }
+        }
+    }
+
+    fn failover(replies: &[Reply]) -> (FailoverClient<MockNode>, Vec<Arc<AtomicUsize>>) {
+        let nodes: Vec<MockNode> = replies.iter().map(|&reply| MockNode { reply, requests: Arc::default() }).collect();
+        let requests = nodes.iter().map(|node| node.requests.clone()).collect();
+        let endpoints = nodes.into_iter().enumerate()
+            .map(|(i, client)| Endpoint { url: format!("https://node-{}.example", i), client, health: Default::default() })
+            .collect();
+        (FailoverClient { endpoints: Arc::new(endpoints) }, requests)
+    }
+
+    fn counts(requests: &[Arc<AtomicUsize>]) -> Vec<usize> {
+        requests.iter().map(|count| count.load(Ordering::SeqCst)).collect()
+    }
+
+    fn ranked_urls(client: &FailoverClient<MockNode>) -> Vec<&str> {
+        client.ranked().iter().map(|endpoint| endpoint.url.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_healthy_endpoints_first_then_the_fastest() {
+        let (client, _) = failover(&[Reply::Answer(1); 4]);
+        // Endpoints without a latency yet keep their configured order
+        assert_eq!(ranked_urls(&client), ["https://node-0.example", "https://node-1.example", "https://node-2.example", "https://node-3.example"]);
+
+        client.endpoints[0].failed("connection refused");
+        client.endpoints[1].succeeded(Duration::from_millis(80));
+        client.endpoints[2].succeeded(Duration::from_millis(20));
+        assert_eq!(ranked_urls(&client), ["https://node-2.example", "https://node-1.example", "https://node-3.example", "https://node-0.example"]);
+
+        // The latency is a moving average, and an endpoint that answers again is no longer down
+        client.endpoints[2].succeeded(Duration::from_millis(120));
+        client.endpoints[0].succeeded(Duration::from_millis(10));
+        assert_eq!(client.endpoints[2].health.lock().unwrap().latency, Some(Duration::from_millis(50)));
+        assert_eq!(ranked_urls(&client), ["https://node-0.example", "https://node-2.example", "https://node-1.example", "https://node-3.example"]);
+
+        // Once the cooldown is over, a failed endpoint is ranked by its latency again
+        client.endpoints[1].health.lock().unwrap().down_until = Some(Instant::now() - Duration::from_secs(1));
+        assert_eq!(ranked_urls(&client)[..2], ["https://node-0.example", "https://node-2.example"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_over_on_transport_errors_and_timeouts() {
+        let (client, requests) = failover(&[Reply::Unreachable, Reply::Stall, Reply::Answer(7)]);
+        let block: U64 = JsonRpcClient::request(&client, "eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(7));
+        assert_eq!(counts(&requests), [1, 1, 1]);
+        assert!(client.endpoints[..2].iter().all(|endpoint| endpoint.health.lock().unwrap().down_until.is_some()));
+
+        // The endpoints that failed are tried last from then on
+        let block: U64 = JsonRpcClient::request(&client, "eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(7));
+        assert_eq!(counts(&requests), [1, 1, 2]);
+
+        let (client, requests) = failover(&[Reply::Unreachable, Reply::Stall]);
+        let error = JsonRpcClient::request::<_, U64>(&client, "eth_blockNumber", ()).await.unwrap_err();
+        assert!(error.to_string().contains("eth_blockNumber timed out on node-1.example"), "{}", error);
+        assert_eq!(counts(&requests), [1, 1]);
+    }
+
+    #[tokio::test]
+    async fn returns_node_errors_without_trying_other_endpoints() {
+        let (client, requests) = failover(&[Reply::Revert, Reply::Answer(7)]);
+        let error = JsonRpcClient::request::<_, U64>(&client, "eth_call", ()).await.unwrap_err();
+        assert_eq!(RpcError::as_error_response(&error).map(|response| response.message.as_str()), Some("execution reverted"));
+        assert_eq!(counts(&requests), [1, 0]);
+        // The node answered, so it stays ranked first
+        assert!(client.endpoints[0].health.lock().unwrap().down_until.is_none());
+        assert_eq!(ranked_urls(&client)[0], "https://node-0.example");
+    }
+}
@@ -0,0 +1,301 @@
+// Redaction of secrets (private keys, mnemonics, API keys, tokens, passwords) from everything
+// that leaves the process: log lines, stored messages and audit rows, and tool results sent
+// to the model. Secrets are found by pattern, plus the values of secret-looking environment
+// variables.
+
+use ethers::signers::coins_bip39::{English, Wordlist};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::RwLock;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+// Shortest phrase of BIP-39 words treated as a mnemonic
+const MIN_MNEMONIC_WORDS: usize = 12;
+
+// Bytes of streamed text held back until more arrives, at least the length of the longest
+// secret, so a secret split across pieces is whole when it is redacted. A 24-word mnemonic is
+// under 220 bytes.
+const HELD_BACK: usize = 256;
+
+// Environment variables whose values are secrets, by a part of their name
+const SECRET_VARIABLES: &[&str] = &["KEY", "SECRET", "TOKEN", "PASSWORD", "MNEMONIC", "JWT", "WEBHOOK", "DATABASE_URL"];
+
+lazy_static! {
+    // Secret shapes, with the text kept in front of the secret as the first group
+    static ref PATTERNS: Vec<Regex> = [
+        // Labelled values: "private_key": "...", Private Key: ..., api_key=...
+        r#"(?i)((?:private[_ ]?key|secret(?:[_ ]?key)?|api[_-]?key|access[_-]?token|auth[_-]?token|password|passphrase)"?\s*[:=]\s*"?)[^\s",}']{8,}"#,
+        // Bare 64-digit hex, the form keys are exported in (0x-prefixed hashes are left alone)
+        r"(^|[^0-9a-fA-Fx])[0-9a-fA-F]{64}\b",
+        // 0x-prefixed ones where they are labelled as a key: "key": "0x...", signingKey=0x...
+        r#"(?i)(\b\w*(?:key|secret|seed)"?\s*[:=]\s*"?)0x[0-9a-fA-F]{64}\b"#,
+        // Provider API keys and tokens
+        r"()\bsk-[A-Za-z0-9_-]{20,}",
+        r"()\bAKIA[0-9A-Z]{16}\b",
+        r"()\bgh[pousr]_[A-Za-z0-9]{30,}",
+        r"()\bxox[abprs]-[A-Za-z0-9-]{10,}",
+        r"()\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+        r"()\b\d{8,10}:[A-Za-z0-9_-]{35}\b",
+        r"(?i)(bearer\s+)[A-Za-z0-9._~+/-]{16,}=*",
+        // Keys in URLs: query parameters, RPC provider paths, and database passwords
+        r#"(?i)([?&](?:api[_-]?key|apikey|key|token|access_token)=)[^&\s"]+"#,
+        r"(?i)((?:infura\.io/v3|alchemy\.com/v2|quiknode\.pro)/)[A-Za-z0-9_-]{16,}",
+        r"(?i)(postgres(?:ql)?://[^:/\s@]+:)[^@\s]+",
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect();
+
+    // Values of secret environment variables, longest first so overlapping ones go whole
+    static ref KNOWN: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+/// Remember the values of secret-looking environment variables (`ANTHROPIC_API_KEY`,
+/// `MNEMONIC`, `DATABASE_URL`, ...) so they are redacted wherever they appear
+pub fn register_env_secrets() {
+    let mut known: Vec<String> = std::env::vars()
+        .filter(|(name, value)| {
+            let name = name.to_uppercase();
+            value.len() >= 8 && !name.ends_with("_ID") && SECRET_VARIABLES.iter().any(|part| name.contains(part))
+        })
+        .map(|(_, value)| value)
+        .collect();
+    known.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    known.dedup();
+    *KNOWN.write().unwrap() = known;
+}
+
+/// `text` with every secret it contains replaced by `[REDACTED]`
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut redacted = Cow::Borrowed(text);
+    for value in KNOWN.read().unwrap().iter() {
+        if redacted.contains(value.as_str()) {
+            redacted = Cow::Owned(redacted.replace(value.as_str(), REDACTED));
+        }
+    }
+    for pattern in PATTERNS.iter() {
+        if pattern.is_match(&redacted) {
+            redacted = Cow::Owned(pattern.replace_all(&redacted, format!("${{1}}{}", REDACTED)).into_owned());
+        }
+    }
+    match redact_mnemonics(&redacted) {
+        Some(text) => Cow::Owned(text),
+        None => redacted,
+    }
+}
+
+/// `value` with secrets redacted from every string in it
+pub fn redact_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(redact(text).into_owned()),
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.iter().map(redact_json).collect()),
+        serde_json::Value::Object(fields) => serde_json::Value::Object(fields.iter()
+            .map(|(name, value)| {
+                // Arguments named for a secret are dropped whatever they hold
+                let secret = matches!(name.as_str(), "private_key" | "mnemonic" | "password" | "api_key")
+                    || (key_like(name) && value.as_str().is_some_and(is_hex_key));
+                let value = if secret { serde_json::Value::String(REDACTED.to_string()) } else { redact_json(value) };
+                (name.clone(), value)
+            })
+            .collect()),
+        other => other.clone(),
+    }
+}
+
+// Field names such as "key", "signing_key", or "secret", whose 0x-prefixed hex is a key
+// rather than a hash
+fn key_like(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["key", "secret", "seed"].iter().any(|part| name.contains(part))
+}
+
+fn is_hex_key(value: &str) -> bool {
+    value.strip_prefix("0x").is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// Runs of at least twelve BIP-39 words replaced as a whole; None when there are none
+fn redact_mnemonics(text: &str) -> Option<String> {
+    let spans = mnemonic_spans(text);
+    if spans.is_empty() {
+        return None;
+    }
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (from, to) in spans {
+        redacted.push_str(&text[copied..from]);
+        redacted.push_str(REDACTED);
+        copied = to;
+    }
+    redacted.push_str(&text[copied..]);
+    Some(redacted)
+}
+
+// Byte ranges of the runs of at least twelve BIP-39 words
+fn mnemonic_spans(text: &str) -> Vec<(usize, usize)> {
+    let words: Vec<(usize, &str)> = text.split_whitespace()
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+        .collect();
+    let is_bip39 = |word: &str| {
+        let word = word.trim_matches(|c: char| !c.is_ascii_alphabetic());
+        !word.is_empty() && English::get_all().binary_search(&word.to_lowercase().as_str()).is_ok()
+    };
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        let mut end = start;
+        while end < words.len() && is_bip39(words[end].1) {
+            end += 1;
+        }
+        if end - start >= MIN_MNEMONIC_WORDS {
+            let (last_offset, last_word) = words[end - 1];
+            spans.push((words[start].0, last_offset + last_word.len()));
+        }
+        start = end.max(start + 1);
+    }
+    spans
+}
+
+// Byte ranges of everything `redact` would replace, with the text matched in front of a secret
+// (such as its label), possibly overlapping
+fn secret_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = KNOWN.read().unwrap().iter()
+        .flat_map(|value| text.match_indices(value.as_str()).map(|(start, value)| (start, start + value.len())))
+        .collect();
+    for pattern in PATTERNS.iter() {
+        spans.extend(pattern.find_iter(text).map(|found| (found.start(), found.end())));
+    }
+    spans.extend(mnemonic_spans(text));
+    spans
+}
+
+/// Redaction of text that arrives in pieces. Only the last `HELD_BACK` bytes or so are held
+/// back; once more arrives, what lies before them is redacted and passed on, cut where no
+/// secret is split, and the rest comes out with the next pieces or `finish`.
+#[derive(Default)]
+pub struct RedactedStream {
+    // Text received but not passed on yet
+    held: String,
+}
+
+impl RedactedStream {
+    /// Add a piece, returning the redacted text that is now safe to pass on, if any
+    pub fn push(&mut self, piece: &str) -> Option<String> {
+        self.held.push_str(piece);
+        let mut cut = self.held.len().checked_sub(HELD_BACK)?;
+        while !self.held.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        // Moved back to the start of any secret it would split
+        let spans = secret_spans(&self.held);
+        while let Some(start) = spans.iter().filter(|(start, end)| *start < cut && cut < *end).map(|(start, _)| *start).min() {
+            cut = start;
+        }
+        if cut == 0 {
+            return None;
+        }
+        let ready = redact(&self.held[..cut]).into_owned();
+        self.held.drain(..cut);
+        Some(ready)
+    }
+
+    /// The redacted text not passed on yet
+    pub fn finish(self) -> String {
+        redact(&self.held).into_owned()
+    }
+}
+
+/// Log writer that redacts each formatted event before it reaches stderr
+pub struct RedactedStderr;
+
+impl Write for RedactedStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stderr().write_all(redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::run_turn;
+    use crate::config::Config;
+    use crate::conversation::Conversation;
+    use crate::mock_llm::{tool_results, tool_use, MockLlm};
+    use crate::test_support::context;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_prefixed_hex_labelled_as_a_key() {
+        let key = format!("0x{}", "cd".repeat(32));
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        for text in [format!("\"key\": \"{}\"", key), format!("signingKey={}", key), format!("Secret: {}", key)] {
+            let redacted = redact(&text);
+            assert!(!redacted.contains(&key) && redacted.contains(REDACTED), "{}", redacted);
+        }
+        assert_eq!(redact(&format!("Tx hash: {}", tx_hash)), format!("Tx hash: {}", tx_hash));
+
+        let fields = json!({ "key": key, "secret": key, "tx_hash": tx_hash, "key_index": 3 });
+        assert_eq!(redact_json(&fields), json!({ "key": REDACTED, "secret": REDACTED, "tx_hash": tx_hash, "key_index": 3 }));
+        // Serialized, as they are logged
+        assert!(!redact(&fields.to_string()).contains(&key));
+    }
+
+    #[test]
+    fn redacts_secrets_split_across_streamed_pieces() {
+        let key = "ab".repeat(32);
+        let text = format!("{} Your key is {} — keep it safe. {}", "Done. ".repeat(50), key, "Bye. ".repeat(60));
+        let mut stream = RedactedStream::default();
+        let mut passed = String::new();
+        // Pieces of seven characters, several of them splitting the key
+        let chars: Vec<char> = text.chars().collect();
+        for piece in chars.chunks(7) {
+            passed.extend(stream.push(&piece.iter().collect::<String>()));
+        }
+        passed.push_str(&stream.finish());
+        assert!(!passed.contains(&key[..16]), "{}", passed);
+        assert_eq!(passed, redact(&text));
+    }
+
+    #[test]
+    fn redacts_streamed_pieces_within_a_bounded_window() {
+        let password = "hunter2-correct-horse-battery-staple";
+        let text = format!("{}password: {}\n{}", "Working on it. ".repeat(40), password, "Still going. ".repeat(400));
+        let mut stream = RedactedStream::default();
+        let mut passed = String::new();
+        for piece in text.as_bytes().chunks(3) {
+            passed.extend(stream.push(std::str::from_utf8(piece).unwrap()));
+            // Only a tail is held, however long the answer grows
+            assert!(stream.held.len() < 2 * HELD_BACK, "{}", stream.held.len());
+        }
+        passed.push_str(&stream.finish());
+        assert!(!passed.contains("hunter2") && !passed.contains("battery-staple"), "{}", passed);
+        assert_eq!(passed, redact(&text));
+    }
+
+    #[tokio::test]
+    async fn redacts_secrets_from_tool_results() {
+        let ctx = context(Config::default());
+        let mock = MockLlm::new(vec![tool_use("t1", "eth_wallet", json!({ "operation": "mnemonic_generate" })), json!("Done.")]);
+
+        run_turn(&mock, &ctx.config.anthropic, "Make me an HD wallet", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        let output = &tool_results(&mock.requests()[1])[0];
+        assert!(output.contains("Mnemonic: [REDACTED]") && output.contains("(m/44'/60'/0'/0/0)"), "{}", output);
+
+        let mnemonic = "test test test test test test test test test test test junk";
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        let text = format!("Mnemonic: {}\nKey: {}\nTx: {}\nRPC: https://mainnet.infura.io/v3/0123456789abcdef0123", mnemonic, "cd".repeat(32), tx_hash);
+        let redacted = redact(&text);
+        assert!(!redacted.contains(mnemonic) && !redacted.contains(&"cd".repeat(32)) && !redacted.contains("0123456789abcdef0123"), "{}", redacted);
+        assert!(redacted.contains(&tx_hash), "{}", redacted);
+        assert_eq!(redact_json(&json!({ "operation": "send", "private_key": "0xabc" })), json!({ "operation": "send", "private_key": "[REDACTED]" }));
+    }
+}
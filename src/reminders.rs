@@ -0,0 +1,201 @@
+// Timed reminders set with the `remind_me` tool, e.g. "remind me to claim my rewards in 3
+// days". They are stored in the database and checked on the `[reminders]` schedule; each one
+// fires once, when it is due, into the session it was set in (shown with the next reply, like
+// price alerts) and on the notification channels.
+
+use crate::config::Config;
+use crate::db::{due_reminders, fire_reminder, take_fired_reminders, ReminderRecord};
+use crate::notify::{self, Kind, Notification};
+use crate::scheduler;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Longest reminder message kept
+pub const MAX_MESSAGE_LEN: usize = 1000;
+
+// Seconds in each unit of a relative time
+fn unit_seconds(unit: &str) -> Option<i64> {
+    match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3600),
+        "d" | "day" | "days" => Some(86400),
+        "w" | "week" | "weeks" => Some(7 * 86400),
+        _ => None,
+    }
+}
+
+// A relative time such as "in 2 hours", "90m", or "1h 30m"
+fn relative(when: &str) -> Option<Duration> {
+    let text = when.strip_prefix("in ").unwrap_or(when).replace(" and ", " ").replace(',', " ");
+    let mut seconds: i64 = 0;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().ok()?;
+        let after = rest[digits..].trim_start();
+        let letters = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+        seconds = seconds.checked_add(amount.checked_mul(unit_seconds(&after[..letters])?)?)?;
+        rest = after[letters..].trim_start();
+    }
+    Duration::try_seconds(seconds)
+}
+
+/// When a reminder given as `when` is due: a relative time ("in 30 minutes", "2h", "1d 6h"),
+/// an RFC 3339 time ("2025-11-02T09:00:00Z"), or a local date and time ("2025-11-02 09:00",
+/// or a date alone for 09:00). Refused unless it is after `now`.
+pub fn due_at(when: &str, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    let when = when.trim().to_lowercase();
+    let due = if let Some(delay) = relative(&when) {
+        now + delay
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(&when.to_uppercase()) {
+        time.with_timezone(&Utc)
+    } else {
+        let local = ["%Y-%m-%d %H:%M", "%Y-%m-%dt%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dt%H:%M:%S"].iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(&when, format).ok())
+            .or_else(|| NaiveDate::parse_from_str(&when, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(9, 0, 0)))
+            .ok_or_else(|| anyhow::anyhow!(
+                "Can't tell when '{}' is; give a delay such as 'in 2 hours' or a time such as '2025-11-02 09:00'", when))?;
+        Local.from_local_datetime(&local).earliest()
+            .ok_or_else(|| anyhow::anyhow!("{} doesn't exist in local time", when))?
+            .with_timezone(&Utc)
+    };
+    if due <= now {
+        return Err(anyhow::anyhow!("{} is in the past", due.format("%Y-%m-%d %H:%M UTC")));
+    }
+    Ok(due)
+}
+
+/// How long until `due`, e.g. "2d 3h" or "45m"
+pub fn describe_wait(due: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let minutes = (due - now).num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes.max(1)),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+/// A reminder in words, e.g. "#3 claim my rewards (due 2025-11-02 09:00 UTC)"
+pub fn describe(reminder: &ReminderRecord) -> String {
+    format!("#{} {} (due {} UTC)", reminder.id, reminder.message, reminder.due_at.format("%Y-%m-%d %H:%M"))
+}
+
+/// Fire every reminder that is due
+pub async fn check(config: &Arc<Config>, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    let now = Utc::now().naive_utc();
+    for reminder in due_reminders(pool, now).await? {
+        // Another replica, or a cancellation, may have got there first
+        if !fire_reminder(pool, reminder.id, now).await? {
+            continue;
+        }
+        tracing::info!(reminder_id = reminder.id, "Reminder fired");
+        let title = format!("Reminder #{}", reminder.id);
+        notify::send(&config.notify, &Notification::new(Kind::Reminder, title, reminder.message.clone())).await;
+    }
+    Ok(())
+}
+
+/// Fire due reminders in the background on the `[reminders]` schedule
+pub fn spawn(config: Arc<Config>, pool: Pool<Postgres>) {
+    if !config.reminders.enabled {
+        return;
+    }
+    let schedule = match scheduler::parse_schedule(&config.reminders.schedule) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            tracing::error!(error = %e, "Reminders are not checked");
+            return;
+        },
+    };
+    tokio::spawn(async move {
+        while scheduler::sleep_until_next(&schedule).await {
+            if let Err(e) = check(&config, &pool).await {
+                tracing::warn!(error = %e, "Failed to check reminders");
+            }
+        }
+    });
+}
+
+/// Reminders set in this session that fired since they were last reported
+pub async fn take_updates(pool: Option<&Pool<Postgres>>, session_id: Uuid) -> Vec<String> {
+    let Some(pool) = pool else { return Vec::new() };
+    match take_fired_reminders(pool, session_id).await {
+        Ok(reminders) => reminders.iter()
+            .map(|reminder| format!("Reminder #{}: {}", reminder.id, reminder.message))
+            .collect(),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load fired reminders");
+            Vec::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_owned_session;
+    use crate::policy::{Policy, PolicyEngine};
+    use crate::test_support::database;
+    use crate::tools::{execute_tool, ToolContext};
+    use serde_json::json;
+
+    #[test]
+    fn reads_relative_due_times() {
+        let now = Utc::now();
+        assert_eq!(due_at("in 1h 30m", now).unwrap(), now + Duration::minutes(90));
+        assert_eq!(due_at("2 days", now).unwrap(), now + Duration::days(2));
+        assert!(due_at("2020-01-01 09:00", now).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn keeps_notes_and_fires_reminders_into_their_session() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let config = Arc::new(config);
+        let address = format!("{:?}", ethers::types::Address::random());
+        let session_id = Uuid::new_v4();
+        create_owned_session(&pool, session_id, &address).await.unwrap();
+        let base = ToolContext::new(config.clone(), Arc::new(PolicyEngine::new(Policy::default())), Some(pool.clone()), None);
+        let alice = ToolContext { user: Some(address), session_id: Some(session_id), ..base.clone() };
+        let bob = ToolContext { user: Some(format!("{:?}", ethers::types::Address::random())), ..base };
+
+        let saved = execute_tool(&alice, "remember", &json!({ "operation": "save", "content": "Cold wallet is the Ledger one" })).await.unwrap();
+        assert!(saved.starts_with("Saved note #"), "{}", saved);
+        let notes = |ctx: ToolContext, query: &'static str| async move {
+            execute_tool(&ctx, "remember", &json!({ "operation": "list", "query": query })).await.unwrap()
+        };
+        assert!(notes(alice.clone(), "ledger").await.contains("Cold wallet is the Ledger one"));
+        assert_eq!(notes(alice.clone(), "trezor").await, "No notes mention \"trezor\"");
+        assert_eq!(notes(bob.clone(), "").await, "No notes saved");
+
+        let remind = |when: &'static str| json!({ "operation": "create", "message": "Claim staking rewards", "when": when });
+        let later = execute_tool(&alice, "remind_me", &remind("in 2 hours")).await.unwrap();
+        assert!(later.contains("(in 2h 0m): Claim staking rewards") || later.contains("(in 1h 59m)"), "{}", later);
+        let refused = execute_tool(&alice, "remind_me", &remind("whenever")).await.unwrap_err();
+        assert_eq!(refused.kind(), "invalid_args");
+        let soon = execute_tool(&alice, "remind_me", &remind("5m")).await.unwrap();
+        let id_of = |reply: &str| reply.trim_start_matches("Reminder #").split(' ').next().unwrap().parse::<i64>().unwrap();
+        let (later, soon) = (id_of(&later), id_of(&soon));
+        let listed = execute_tool(&alice, "remind_me", &json!({ "operation": "list" })).await.unwrap();
+        assert!(listed.contains(&format!("#{} Claim staking rewards", later)) && listed.contains(&format!("#{} Claim", soon)), "{}", listed);
+        assert_eq!(execute_tool(&bob, "remind_me", &json!({ "operation": "list" })).await.unwrap(), "No active reminders");
+
+        // Once due, it fires once and is reported in the session it was set in
+        sqlx::query("UPDATE reminders SET due_at = due_at - interval '10 minutes' WHERE id = $1").bind(soon).execute(&pool).await.unwrap();
+        check(&config, &pool).await.unwrap();
+        assert_eq!(take_updates(Some(&pool), session_id).await, vec![format!("Reminder #{}: Claim staking rewards", soon)]);
+        assert!(take_updates(Some(&pool), session_id).await.is_empty());
+
+        assert!(execute_tool(&bob, "remind_me", &json!({ "operation": "cancel", "reminder_id": later })).await.is_err());
+        execute_tool(&alice, "remind_me", &json!({ "operation": "cancel", "reminder_id": later })).await.unwrap();
+        assert_eq!(execute_tool(&alice, "remind_me", &json!({ "operation": "list" })).await.unwrap(), "No active reminders");
+    }
+}
@@ -0,0 +1,152 @@
+// Line editing for the chat prompt: arrow keys, Ctrl-R history search, tab completion of
+// slash commands, tool and model names, and image paths, and history kept across sessions.
+// A line ending in a backslash continues on the next one.
+
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use tokio::sync::mpsc;
+
+/// Slash commands of the chat, completed at the start of a line
+pub const COMMANDS: &[&str] = &["/audit", "/cost", "/search", "/schedule", "/wallets", "/pnl", "/image", "/model", "/think"];
+
+/// What the user did at the prompt
+pub enum Input {
+    Line(String),
+    /// Ctrl-C
+    Interrupted,
+    /// Ctrl-D, or stdin closed
+    Eof,
+}
+
+struct ChatHelper {
+    tools: Vec<String>,
+    models: Vec<String>,
+    files: FilenameCompleter,
+}
+
+fn candidates<'a>(prefix: &str, words: impl IntoIterator<Item = &'a str>) -> Vec<Pair> {
+    words.into_iter()
+        .filter(|word| word.starts_with(prefix))
+        .map(|word| Pair { display: word.to_string(), replacement: word.to_string() })
+        .collect()
+}
+
+impl Completer for ChatHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        if before.starts_with("/image ") {
+            return self.files.complete(line, pos, ctx);
+        }
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let pairs = if start == 0 && word.starts_with('/') {
+            candidates(word, COMMANDS.iter().copied())
+        } else if before.starts_with("/model ") {
+            candidates(word, self.models.iter().map(String::as_str))
+        } else if !word.is_empty() {
+            candidates(word, self.tools.iter().map(String::as_str))
+        } else {
+            Vec::new()
+        };
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ChatHelper {}
+
+impl Validator for ChatHelper {}
+
+impl Helper for ChatHelper {}
+
+/// The prompt, read on a blocking thread so that the chat loop can wait for on-chain events at
+/// the same time. A line is read each time `prompt` is called.
+pub struct LineReader {
+    requests: std::sync::mpsc::Sender<String>,
+    lines: mpsc::UnboundedReceiver<Input>,
+    pending: bool,
+}
+
+impl LineReader {
+    /// Start the reader, loading history from `history_path` (empty for none) and saving each
+    /// line there
+    pub fn spawn(history_path: &str, tools: Vec<String>, models: Vec<String>) -> anyhow::Result<Self> {
+        let mut editor: Editor<ChatHelper, DefaultHistory> = Editor::new()?;
+        editor.set_helper(Some(ChatHelper { tools, models, files: FilenameCompleter::new() }));
+        let history_path = (!history_path.is_empty()).then(|| history_path.to_string());
+        if let Some(path) = &history_path {
+            // A missing file is the first session
+            let _ = editor.load_history(path);
+        }
+
+        let (requests, prompts) = std::sync::mpsc::channel::<String>();
+        let (sender, lines) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for prompt in prompts {
+                let input = read(&mut editor, &prompt);
+                if let (Input::Line(line), Some(path)) = (&input, &history_path) {
+                    let _ = editor.add_history_entry(line.as_str());
+                    if let Err(e) = editor.append_history(path) {
+                        tracing::debug!(error = %e, "Could not save chat history");
+                    }
+                }
+                let eof = matches!(input, Input::Eof);
+                if sender.send(input).is_err() || eof {
+                    break;
+                }
+            }
+        });
+        Ok(LineReader { requests, lines, pending: false })
+    }
+
+    /// Show `prompt` and start reading a line, unless one is already being read
+    pub fn prompt(&mut self, prompt: &str) {
+        if !self.pending {
+            self.pending = self.requests.send(prompt.to_string()).is_ok();
+        }
+    }
+
+    /// The next input; Eof once the reader has stopped
+    pub async fn recv(&mut self) -> Input {
+        let input = self.lines.recv().await.unwrap_or(Input::Eof);
+        self.pending = false;
+        input
+    }
+}
+
+// One entry, joining lines that end in a backslash
+fn read(editor: &mut Editor<ChatHelper, DefaultHistory>, prompt: &str) -> Input {
+    let mut lines = Vec::new();
+    let mut prompt = prompt;
+    loop {
+        match editor.readline(prompt) {
+            Ok(line) => match line.strip_suffix('\\') {
+                Some(continued) => {
+                    lines.push(continued.to_string());
+                    prompt = "... ";
+                },
+                None => {
+                    lines.push(line);
+                    return Input::Line(lines.join("\n"));
+                },
+            },
+            Err(ReadlineError::Interrupted) => return Input::Interrupted,
+            Err(ReadlineError::Eof) if lines.is_empty() => return Input::Eof,
+            Err(ReadlineError::Eof) => return Input::Line(lines.join("\n")),
+            Err(e) => {
+                tracing::error!(error = %e, "Could not read input");
+                return Input::Eof;
+            },
+        }
+    }
+}
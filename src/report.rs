@@ -0,0 +1,422 @@
+// Transaction history reports (`generate_report` tool): an address's transactions, internal
+// ETH transfers, and ERC-20 transfers over a date range, from an Etherscan-compatible explorer
+// API, with totals per asset and the gas the address paid. Reports are written to the
+// workspace as CSV or Markdown, a first step toward tax exports; they carry no prices.
+
+use crate::config::EtherscanConfig;
+use crate::units;
+use chrono::{DateTime, NaiveDate, Utc};
+use ethers::types::U256;
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Assets listed in a report's summary
+const MAX_SUMMARY_ASSETS: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+    /// From the address to itself
+    Own,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+            Direction::Own => "self",
+        }
+    }
+}
+
+/// One movement of ETH or a token to or from the address
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub hash: String,
+    pub time: DateTime<Utc>,
+    /// `transaction`, `internal`, or `erc20`
+    pub kind: &'static str,
+    pub direction: Direction,
+    /// `ETH` or the token's symbol
+    pub asset: String,
+    /// The token contract; None for ETH
+    pub contract: Option<String>,
+    pub decimals: u32,
+    pub value: U256,
+    pub from: String,
+    pub to: String,
+    /// Gas the address paid, for the transactions it sent
+    pub fee: Option<U256>,
+    pub failed: bool,
+}
+
+impl Transfer {
+    fn amount(&self) -> String {
+        units::format_token(self.value, self.decimals)
+    }
+
+    fn counterparty(&self) -> &str {
+        if self.direction == Direction::In { &self.from } else { &self.to }
+    }
+}
+
+/// What went in and out of one asset
+#[derive(Debug, Clone, Default)]
+pub struct AssetTotals {
+    pub asset: String,
+    pub decimals: u32,
+    pub received: U256,
+    pub sent: U256,
+    pub transfers: usize,
+}
+
+impl AssetTotals {
+    /// Received minus sent, with its sign
+    pub fn net(&self) -> String {
+        match self.received >= self.sent {
+            true => units::format_token(self.received - self.sent, self.decimals),
+            false => format!("-{}", units::format_token(self.sent - self.received, self.decimals)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub address: String,
+    pub chain_id: u64,
+    pub from: NaiveDate,
+    /// Last day included
+    pub to: NaiveDate,
+    pub transfers: Vec<Transfer>,
+    /// Whether a list reached `max_transactions`, so the end of the range may be missing
+    pub truncated: bool,
+}
+
+impl Report {
+    /// Totals per asset, ETH first; failed transfers moved nothing and are left out, as are
+    /// transactions that only called a contract
+    pub fn totals(&self) -> Vec<AssetTotals> {
+        let mut by_asset: BTreeMap<Option<String>, AssetTotals> = BTreeMap::new();
+        for transfer in self.transfers.iter().filter(|transfer| !transfer.failed && !transfer.value.is_zero()) {
+            let totals = by_asset.entry(transfer.contract.clone()).or_insert_with(|| AssetTotals {
+                asset: transfer.asset.clone(),
+                decimals: transfer.decimals,
+                ..AssetTotals::default()
+            });
+            match transfer.direction {
+                Direction::In => totals.received += transfer.value,
+                Direction::Out => totals.sent += transfer.value,
+                Direction::Own => {},
+            }
+            totals.transfers += 1;
+        }
+        by_asset.into_values().collect()
+    }
+
+    /// Gas paid over the range, and how many transactions it was paid for
+    pub fn gas(&self) -> (U256, usize) {
+        let fees: Vec<U256> = self.transfers.iter().filter_map(|transfer| transfer.fee).collect();
+        (fees.iter().fold(U256::zero(), |total, fee| total + fee), fees.len())
+    }
+}
+
+fn api_key(config: &EtherscanConfig) -> anyhow::Result<&str> {
+    config.api_key.as_deref()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No explorer API key; set api_key under [etherscan] or ETHERSCAN_API_KEY"))
+}
+
+/// One call to the explorer API for `chain_id`
+pub async fn request(config: &EtherscanConfig, chain_id: u64, query: &[(&str, &str)]) -> anyhow::Result<serde_json::Value> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let response = client.get(&config.api_url)
+        .query(&[("chainid", chain_id.to_string().as_str()), ("apikey", api_key(config)?)])
+        .query(query)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("The explorer API returned {}", status));
+    }
+    Ok(response.json().await?)
+}
+
+/// Why the explorer API refused a request
+pub fn refusal(body: &serde_json::Value) -> &str {
+    body["result"].as_str().or(body["message"].as_str()).unwrap_or("no reason given")
+}
+
+// The first block after (or last before) a time
+async fn block_at(config: &EtherscanConfig, chain_id: u64, timestamp: i64, closest: &str) -> anyhow::Result<u64> {
+    let timestamp = timestamp.to_string();
+    let query = [("module", "block"), ("action", "getblocknobytime"), ("timestamp", timestamp.as_str()), ("closest", closest)];
+    let body = request(config, chain_id, &query).await?;
+    body["result"].as_str()
+        .and_then(|block| block.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("The explorer API has no block for {}: {}", timestamp, refusal(&body)))
+}
+
+// One of the account lists (`txlist`, `txlistinternal`, `tokentx`) over a block range, oldest
+// first, and whether it was cut at `max_transactions`
+async fn list(config: &EtherscanConfig, chain_id: u64, action: &str, address: &str, blocks: (u64, u64)) -> anyhow::Result<(Vec<serde_json::Value>, bool)> {
+    let (start_block, end_block, offset) = (blocks.0.to_string(), blocks.1.to_string(), config.max_transactions.to_string());
+    let query = [
+        ("module", "account"),
+        ("action", action),
+        ("address", address),
+        ("startblock", start_block.as_str()),
+        ("endblock", end_block.as_str()),
+        ("page", "1"),
+        ("offset", offset.as_str()),
+        ("sort", "asc"),
+    ];
+    let body = request(config, chain_id, &query).await?;
+    match (body["status"].as_str(), body["result"].as_array()) {
+        (Some("1"), Some(items)) => Ok((items.clone(), items.len() as u64 >= config.max_transactions)),
+        // An empty list comes as an error status
+        (_, Some(items)) if items.is_empty() => Ok((Vec::new(), false)),
+        _ => Err(anyhow::anyhow!("The explorer API refused {}: {}", action, refusal(&body))),
+    }
+}
+
+fn text<'a>(item: &'a serde_json::Value, field: &str) -> &'a str {
+    item[field].as_str().unwrap_or_default()
+}
+
+fn number(item: &serde_json::Value, field: &str) -> U256 {
+    U256::from_dec_str(text(item, field)).unwrap_or_default()
+}
+
+// The entry as a transfer, if it is in the range `[start, end)`
+fn transfer(item: &serde_json::Value, kind: &'static str, address: &str, start: i64, end: i64) -> Option<Transfer> {
+    let timestamp: i64 = text(item, "timeStamp").parse().ok()?;
+    if timestamp < start || timestamp >= end {
+        return None;
+    }
+    let (from, to) = (text(item, "from").to_lowercase(), text(item, "to").to_lowercase());
+    // Contract creations have no `to` but say what was created
+    let to = if to.is_empty() { text(item, "contractAddress").to_lowercase() } else { to };
+    let direction = match (from == address, to == address) {
+        (true, true) => Direction::Own,
+        (true, false) => Direction::Out,
+        _ => Direction::In,
+    };
+    let (asset, contract, decimals) = match kind {
+        "erc20" => {
+            let symbol = text(item, "tokenSymbol").trim();
+            (if symbol.is_empty() { "?".to_string() } else { symbol.to_string() },
+             Some(text(item, "contractAddress").to_lowercase()),
+             text(item, "tokenDecimal").parse().unwrap_or(0))
+        },
+        _ => ("ETH".to_string(), None, 18),
+    };
+    // Gas is paid once, by the sender of the transaction itself
+    let fee = (kind == "transaction" && from == address).then(|| number(item, "gasUsed") * number(item, "gasPrice"));
+    Some(Transfer {
+        hash: text(item, "hash").to_string(),
+        time: DateTime::from_timestamp(timestamp, 0)?,
+        kind,
+        direction,
+        asset,
+        contract,
+        decimals,
+        value: number(item, "value"),
+        from,
+        to,
+        fee,
+        failed: text(item, "isError") == "1",
+    })
+}
+
+/// The address's history on `chain_id` from the start of `from` to the end of `to` (UTC)
+pub async fn history(config: &EtherscanConfig, chain_id: u64, address: &str, from: NaiveDate, to: NaiveDate) -> anyhow::Result<Report> {
+    let address = address.to_lowercase();
+    let start = from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    let end = to.succ_opt().unwrap_or(to).and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    // Blocks bound the lists, so `max_transactions` applies to the range only
+    let blocks = (
+        block_at(config, chain_id, start, "after").await?,
+        block_at(config, chain_id, end.min(Utc::now().timestamp()), "before").await?,
+    );
+    let mut transfers = Vec::new();
+    let mut truncated = false;
+    for (action, kind) in [("txlist", "transaction"), ("txlistinternal", "internal"), ("tokentx", "erc20")] {
+        let (items, cut) = list(config, chain_id, action, &address, blocks).await?;
+        truncated |= cut;
+        transfers.extend(items.iter().filter_map(|item| transfer(item, kind, &address, start, end)));
+    }
+    transfers.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.hash.cmp(&b.hash)));
+    Ok(Report { address, chain_id, from, to, transfers, truncated })
+}
+
+// A CSV field, quoted when it needs to be
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// One row per transfer
+pub fn csv(report: &Report) -> String {
+    let mut lines = vec!["date,hash,type,direction,asset,token_contract,amount,from,to,gas_fee_eth,status".to_string()];
+    for transfer in &report.transfers {
+        let fields = [
+            transfer.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            transfer.hash.clone(),
+            transfer.kind.to_string(),
+            transfer.direction.as_str().to_string(),
+            transfer.asset.clone(),
+            transfer.contract.clone().unwrap_or_default(),
+            transfer.amount(),
+            transfer.from.clone(),
+            transfer.to.clone(),
+            transfer.fee.map(units::format_eth).unwrap_or_default(),
+            if transfer.failed { "failed" } else { "success" }.to_string(),
+        ];
+        lines.push(fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn cell(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+/// Totals, gas, and a table of the transfers
+pub fn markdown(report: &Report) -> String {
+    let (gas, paid_for) = report.gas();
+    let mut lines = vec![
+        format!("# Transaction report for {}", report.address),
+        String::new(),
+        format!("Chain {}, {} to {} (UTC)", report.chain_id, report.from, report.to),
+        String::new(),
+        "## Totals".to_string(),
+        String::new(),
+        "| Asset | Received | Sent | Net | Transfers |".to_string(),
+        "|---|---|---|---|---|".to_string(),
+    ];
+    for totals in report.totals() {
+        lines.push(format!("| {} | {} | {} | {} | {} |", cell(&totals.asset), units::format_token(totals.received, totals.decimals),
+                           units::format_token(totals.sent, totals.decimals), totals.net(), totals.transfers));
+    }
+    lines.push(String::new());
+    lines.push(format!("Gas paid: {} ETH over {} transaction{}", units::format_eth(gas), paid_for, if paid_for == 1 { "" } else { "s" }));
+    lines.push(String::new());
+    lines.push("## Transfers".to_string());
+    lines.push(String::new());
+    lines.push("| Date | Type | Direction | Asset | Amount | Counterparty | Gas fee (ETH) | Status | Transaction |".to_string());
+    lines.push("|---|---|---|---|---|---|---|---|---|".to_string());
+    for transfer in &report.transfers {
+        lines.push(format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+            transfer.time.format("%Y-%m-%d %H:%M"), transfer.kind, transfer.direction.as_str(), cell(&transfer.asset),
+            transfer.amount(), transfer.counterparty(), transfer.fee.map(units::format_eth).unwrap_or_default(),
+            if transfer.failed { "failed" } else { "success" }, transfer.hash,
+        ));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// What the report found, for the tool result
+pub fn summary(report: &Report) -> String {
+    let mut lines = vec![format!(
+        "Report for {} on chain {}, {} to {}: {} transfer{}",
+        report.address, report.chain_id, report.from, report.to,
+        report.transfers.len(), if report.transfers.len() == 1 { "" } else { "s" },
+    )];
+    let totals = report.totals();
+    for totals in totals.iter().take(MAX_SUMMARY_ASSETS) {
+        let decimals = totals.decimals;
+        lines.push(format!("- {}: received {}, sent {}, net {} ({} transfers)", totals.asset,
+                           units::format_token(totals.received, decimals), units::format_token(totals.sent, decimals),
+                           totals.net(), totals.transfers));
+    }
+    if totals.len() > MAX_SUMMARY_ASSETS {
+        lines.push(format!("- and {} more assets", totals.len() - MAX_SUMMARY_ASSETS));
+    }
+    let (gas, paid_for) = report.gas();
+    lines.push(format!("Gas paid: {} ETH over {} transaction{}", units::format_eth(gas), paid_for, if paid_for == 1 { "" } else { "s" }));
+    if report.truncated {
+        lines.push("Note: the explorer returned as many entries as max_transactions allows, so the end of the range may be missing; try a shorter range.".to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn reports_transfer_history_with_totals_and_gas() {
+        use axum::routing::get;
+
+        let me = "0x00000000000000000000000000000000000000aa";
+        let other = "0x00000000000000000000000000000000000000bb";
+        let usdc = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        // 2025-02-01, 2025-02-02, and 2024-12-01 (before the range)
+        let (first, second, before) = ("1738368000", "1738454400", "1733011200");
+        let api = axum::Router::new().route("/", get(move |axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>| async move {
+            assert_eq!((query["chainid"].as_str(), query["apikey"].as_str()), ("11155111", "explorer-key"));
+            let result = match query["action"].as_str() {
+                "getblocknobytime" => return axum::Json(json!({ "status": "1", "message": "OK", "result": if query["closest"] == "after" { "100" } else { "200" } })),
+                "txlist" => {
+                    assert_eq!((query["startblock"].as_str(), query["endblock"].as_str()), ("100", "200"));
+                    json!([
+                        { "hash": "0x01", "timeStamp": first, "from": me, "to": other, "value": "1000000000000000000", "gasUsed": "21000", "gasPrice": "10000000000", "isError": "0" },
+                        { "hash": "0x02", "timeStamp": first, "from": other, "to": me, "value": "500000000000000000", "gasUsed": "21000", "gasPrice": "10000000000", "isError": "0" },
+                        { "hash": "0x03", "timeStamp": second, "from": me, "to": usdc, "value": "0", "gasUsed": "50000", "gasPrice": "10000000000", "isError": "1" },
+                        { "hash": "0x04", "timeStamp": before, "from": other, "to": me, "value": "9000000000000000000", "gasUsed": "21000", "gasPrice": "1", "isError": "0" },
+                    ])
+                },
+                "txlistinternal" => json!([{ "hash": "0x05", "timeStamp": second, "from": other, "to": me, "value": "250000000000000000", "isError": "0" }]),
+                _ => json!([
+                    { "hash": "0x06", "timeStamp": first, "from": other, "to": me, "value": "100000000", "tokenSymbol": "USDC", "tokenDecimal": "6", "contractAddress": usdc },
+                    { "hash": "0x07", "timeStamp": second, "from": me, "to": other, "value": "40000000", "tokenSymbol": "USDC", "tokenDecimal": "6", "contractAddress": usdc },
+                ]),
+            };
+            axum::Json(json!({ "status": "1", "message": "OK", "result": result }))
+        }));
+        let url = format!("{}/", serve(api).await);
+
+        let dir = std::env::temp_dir().join(format!("report-{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.workspace.dir = dir.to_string_lossy().into_owned();
+        let args = json!({ "address": me, "from_date": "2025-01-01", "to_date": "2025-03-31" });
+        let unconfigured = execute_tool(&context(config.clone()), "generate_report", &args).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+
+        config.etherscan.api_url = url;
+        config.etherscan.api_key = Some("explorer-key".to_string());
+        let ctx = context(config);
+        let summary = execute_tool(&ctx, "generate_report", &args).await.unwrap();
+        assert!(summary.starts_with(&format!("Report for {} on chain 11155111, 2025-01-01 to 2025-03-31: 6 transfers\n\
+                                              - ETH: received 0.75, sent 1, net -0.25 (3 transfers)\n\
+                                              - USDC: received 100, sent 40, net 60 (2 transfers)\n\
+                                              Gas paid: 0.00071 ETH over 2 transactions\n", me)), "{}", summary);
+        let path = "reports/0x00000000_2025-01-01_2025-03-31.csv";
+        assert!(summary.contains(&format!("\nCreated {} ({} bytes written)\n", path, std::fs::metadata(dir.join(path)).unwrap().len())), "{}", summary);
+        let csv = std::fs::read_to_string(dir.join(path)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "date,hash,type,direction,asset,token_contract,amount,from,to,gas_fee_eth,status");
+        assert_eq!(lines[1], format!("2025-02-01 00:00:00,0x01,transaction,out,ETH,,1,{},{},0.00021,success", me, other));
+        assert!(lines.contains(&format!("2025-02-02 00:00:00,0x03,transaction,out,ETH,,0,{},{},0.0005,failed", me, usdc).as_str()), "{}", csv);
+        assert!(lines.contains(&format!("2025-02-02 00:00:00,0x07,erc20,out,USDC,{},40,{},{},,success", usdc, me, other).as_str()), "{}", csv);
+        assert_eq!(lines.len(), 7);
+
+        let markdown = execute_tool(&ctx, "generate_report", &json!({ "address": me, "from_date": "2025-01-01", "to_date": "2025-03-31", "format": "markdown", "path": "tax/2025-q1.md" })).await.unwrap();
+        assert!(markdown.contains("\nCreated tax/2025-q1.md"), "{}", markdown);
+        let written = std::fs::read_to_string(dir.join("tax/2025-q1.md")).unwrap();
+        assert!(written.contains("| USDC | 100 | 40 | 60 | 2 |"), "{}", written);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,96 @@
+// Retention of stored messages (`[retention]`): those older than `max_age_days`, and those
+// beyond the latest `max_messages_per_session` of a session, are deleted, in the background in
+// long-running modes and on demand with `agent db prune`. Tool calls they asked for stay in the
+// audit log, unlinked from them; sessions, their summaries, and memories are kept.
+
+use crate::cluster;
+use crate::config::{Config, RetentionConfig};
+use crate::db::{prune_messages_beyond, prune_messages_older_than};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+/// Messages deleted by one pruning
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pruned {
+    pub expired: i64,
+    pub beyond_limit: i64,
+}
+
+impl Pruned {
+    pub fn total(&self) -> i64 {
+        self.expired + self.beyond_limit
+    }
+}
+
+/// Delete the messages `config` doesn't keep
+pub async fn prune(config: &RetentionConfig, pool: &Pool<Postgres>) -> sqlx::Result<Pruned> {
+    let mut pruned = Pruned::default();
+    if config.max_age_days > 0 {
+        pruned.expired = prune_messages_older_than(pool, config.max_age_days as i32).await?;
+    }
+    if config.max_messages_per_session > 0 {
+        pruned.beyond_limit = prune_messages_beyond(pool, config.max_messages_per_session as i32).await?;
+    }
+    // Cached history would still have the deleted messages, and their old positions
+    if pruned.total() > 0 {
+        cluster::forget_sessions().await;
+    }
+    Ok(pruned)
+}
+
+/// Prune in the background until the process exits, when a limit is set
+pub fn spawn(config: Arc<Config>, pool: Pool<Postgres>) {
+    let retention = &config.retention;
+    if retention.max_age_days == 0 && retention.max_messages_per_session == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(config.retention.prune_interval_secs.max(60)));
+        loop {
+            interval.tick().await;
+            match prune(&config.retention, &pool).await {
+                Ok(pruned) if pruned.total() > 0 => {
+                    tracing::info!(expired = pruned.expired, beyond_limit = pruned.beyond_limit, "Pruned stored messages");
+                },
+                Ok(_) => {},
+                Err(e) => tracing::warn!(error = %e, "Failed to prune stored messages"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{all_session_messages, create_session, load_session_summary, save_message, save_session_summary};
+    use crate::test_support::database;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn prunes_messages_past_the_retention_limits() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let session_id = Uuid::new_v4();
+        create_session(&pool, session_id).await.unwrap();
+        let mut ids = Vec::new();
+        for n in 0..53 {
+            ids.push(save_message(&pool, session_id, "user", &format!("message {}", n), "claude-test", None).await.unwrap());
+        }
+        save_session_summary(&pool, session_id, "The first ten messages", 10).await.unwrap();
+        sqlx::query("UPDATE messages SET created_at = now() - interval '100 days' WHERE id = ANY($1)")
+            .bind(&ids[..2])
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let retention = RetentionConfig { max_age_days: 90, max_messages_per_session: 50, ..Default::default() };
+        let pruned = prune(&retention, &pool).await.unwrap();
+        assert!(pruned.expired >= 2 && pruned.beyond_limit >= 1, "{:?}", pruned);
+        let messages = all_session_messages(&pool, session_id).await.unwrap();
+        assert_eq!(messages.len(), 50);
+        assert_eq!(messages[0].content, "message 3");
+        // The summary still covers the same messages
+        assert_eq!(load_session_summary(&pool, session_id).await.unwrap().1, 7);
+    }
+}
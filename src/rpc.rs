@@ -0,0 +1,300 @@
+// The chat driven by another program (an editor, a desktop app, a bot host) over stdio:
+// newline-delimited JSON-RPC requests on stdin, answers and progress notifications on stdout.
+//
+//   {"jsonrpc":"2.0","id":1,"method":"chat","params":{"message":"What's my balance?"}}
+//   {"jsonrpc":"2.0","method":"progress","params":{"id":1,"session":"…","activity":"→ eth_wallet.balance"}}
+//   {"jsonrpc":"2.0","id":1,"result":{"session":"…","reply":"…","model":"…"}}
+//
+// `chat` takes an optional `session` to continue; `cancel` stops the request with the given
+// `id`. Sessions are kept in memory, and resumed from the context's history store (the
+// database when one is configured).
+
+use crate::anthropic::{call_anthropic_with_personality, model_for};
+use crate::conversation::Conversation;
+use crate::mcp_server::{error_reply, reply};
+use crate::personality::Personality;
+use crate::tools::ToolContext;
+use crate::usage::TurnMeter;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+// How often tool activity of a running turn is sent as progress notifications
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Chat sessions of a host program, and what their turns run with
+pub struct RpcServer {
+    ctx: ToolContext,
+    personality: Option<Personality>,
+    // A session's turns run one at a time, in the order they were asked
+    sessions: tokio::sync::Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<Conversation>>>>,
+    // Requests being answered, by id, for `cancel`
+    running: Mutex<HashMap<String, AbortHandle>>,
+}
+
+/// A turn's answer
+pub struct ChatReply {
+    pub session: Uuid,
+    pub reply: String,
+    pub model: String,
+}
+
+/// Why a turn got no answer
+#[derive(Debug)]
+pub enum ChatError {
+    NotFound(Uuid),
+    Invalid(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::NotFound(id) => write!(f, "Session {} not found", id),
+            ChatError::Invalid(message) | ChatError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl ChatError {
+    // JSON-RPC error code
+    fn code(&self) -> i64 {
+        match self {
+            ChatError::NotFound(_) | ChatError::Invalid(_) => -32602,
+            ChatError::Failed(_) => -32000,
+        }
+    }
+}
+
+/// Refuse an empty message, before a session is opened for it
+pub fn check_message(message: &str) -> Result<(), ChatError> {
+    match message.trim().is_empty() {
+        true => Err(ChatError::Invalid("The message to send is required".to_string())),
+        false => Ok(()),
+    }
+}
+
+fn notification(method: &str, params: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+impl RpcServer {
+    pub fn new(ctx: ToolContext, personality: Option<Personality>) -> Self {
+        RpcServer { ctx, personality, sessions: Default::default(), running: Default::default() }
+    }
+
+    /// Answer one JSON-RPC message, sending progress notifications to `out` along the way;
+    /// None for notifications, which get no answer
+    pub async fn handle(&self, message: serde_json::Value, out: &mpsc::UnboundedSender<serde_json::Value>) -> Option<serde_json::Value> {
+        let id = message.get("id")?.clone();
+        let method = message.get("method").and_then(|v| v.as_str()).unwrap_or_default();
+        let params = &message["params"];
+        Some(match method {
+            "chat" => self.chat_request(&id, params, out).await,
+            "cancel" => {
+                let target = params["id"].to_string();
+                match self.running.lock().unwrap().remove(&target) {
+                    Some(task) => {
+                        task.abort();
+                        reply(&id, serde_json::json!({ "cancelled": true }))
+                    },
+                    None => reply(&id, serde_json::json!({ "cancelled": false })),
+                }
+            },
+            "ping" => reply(&id, serde_json::json!({})),
+            _ => error_reply(&id, -32601, &format!("Method not found: {}", method)),
+        })
+    }
+
+    /// The id of `session` once its history is loaded, or of a new session when it is None
+    pub async fn open(&self, session: Option<Uuid>) -> Result<Uuid, ChatError> {
+        self.session(session).await.map(|(id, _)| id)
+    }
+
+    // The conversation of `session`, a new one when it is None
+    async fn session(&self, session: Option<Uuid>) -> Result<(Uuid, Arc<tokio::sync::Mutex<Conversation>>), ChatError> {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(id) = session
+            && let Some(conversation) = sessions.get(&id)
+        {
+            return Ok((id, conversation.clone()));
+        }
+        let store = &self.ctx.store;
+        let (id, conversation) = match session {
+            Some(id) => {
+                if !store.session_exists(id).await.map_err(|e| ChatError::Failed(e.to_string()))? {
+                    return Err(ChatError::NotFound(id));
+                }
+                let conversation = Conversation::load(store.as_ref(), id, crate::RESUME_HISTORY_LIMIT).await
+                    .map_err(|e| ChatError::Failed(e.to_string()))?;
+                (id, conversation)
+            },
+            None => {
+                let id = Uuid::new_v4();
+                if let Err(e) = store.create_session(id).await {
+                    tracing::error!(error = %e, "Failed to create session");
+                }
+                (id, Conversation::default())
+            },
+        };
+        let conversation = Arc::new(tokio::sync::Mutex::new(conversation));
+        sessions.insert(id, conversation.clone());
+        Ok((id, conversation))
+    }
+
+    /// Answer `message` in a session from `open`, calling `progress` with each tool call as it
+    /// starts and finishes
+    pub async fn chat(&self, session: Uuid, message: &str, progress: impl Fn(String)) -> Result<ChatReply, ChatError> {
+        let (session_id, conversation) = self.session(Some(session)).await?;
+        let mut conversation = conversation.lock().await;
+
+        let activity = Arc::new(Mutex::new(Vec::new()));
+        let config = &self.ctx.config;
+        let model = model_for(&config.anthropic, self.personality.as_ref());
+        let message_id = crate::record_message(&self.ctx, session_id, "user", message, &model, None).await;
+        let ctx = ToolContext { session_id: Some(session_id), message_id, activity: Some(activity.clone()), ..self.ctx.clone() };
+        if let Err(e) = conversation.compact(&ctx).await {
+            tracing::warn!(error = %e, "Failed to compact conversation history");
+        }
+
+        let report = || activity.lock().unwrap().drain(..).for_each(&progress);
+        let meter = TurnMeter::start(&ctx);
+        let result = {
+            let turn = call_anthropic_with_personality(&config.anthropic, message, &[], self.personality.as_ref(), &conversation, &ctx);
+            tokio::pin!(turn);
+            let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+            loop {
+                tokio::select! {
+                    result = &mut turn => break result,
+                    _ = ticker.tick() => report(),
+                }
+            }
+        };
+        report();
+        let reply = result.map_err(|e| ChatError::Failed(e.to_string()))?;
+
+        crate::record_message(&ctx, session_id, "assistant", &reply, &model, Some(meter.stats(&ctx))).await;
+        conversation.push("user", message);
+        conversation.push("assistant", &reply);
+        Ok(ChatReply { session: session_id, reply, model })
+    }
+
+    // `chat` with JSON-RPC params, reporting progress as notifications tagged with the request id
+    async fn chat_request(&self, id: &serde_json::Value, params: &serde_json::Value, out: &mpsc::UnboundedSender<serde_json::Value>) -> serde_json::Value {
+        let session = match &params["session"] {
+            serde_json::Value::Null => None,
+            value => match value.as_str().and_then(|session| Uuid::parse_str(session).ok()) {
+                Some(session) => Some(session),
+                None => return error_reply(id, -32602, &format!("Invalid session id: {}", value)),
+            },
+        };
+        let message = params["message"].as_str().unwrap_or_default();
+        let result = async {
+            check_message(message)?;
+            let session = self.open(session).await?;
+            self.chat(session, message, |line| {
+                let _ = out.send(notification("progress", serde_json::json!({ "id": id, "session": session, "activity": line })));
+            }).await
+        }.await;
+        match result {
+            Ok(ChatReply { session, reply: text, model }) => reply(id, serde_json::json!({ "session": session, "reply": text, "model": model })),
+            Err(e) => error_reply(id, e.code(), &e.to_string()),
+        }
+    }
+}
+
+/// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes. Requests run
+/// concurrently, so answers may come back out of order; match them by id.
+pub async fn serve_stdio(server: RpcServer) -> anyhow::Result<()> {
+    let server = Arc::new(server);
+    let (out, mut lines_out) = mpsc::unbounded_channel::<serde_json::Value>();
+    // One writer, so that lines from concurrent requests don't interleave
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = lines_out.recv().await {
+            let mut line = message.to_string();
+            line.push('\n');
+            if let Err(e) = stdout.write_all(line.as_bytes()).await.and(stdout.flush().await) {
+                tracing::error!(error = %e, "Could not write to the host program");
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    tracing::info!("Serving the chat over JSON-RPC on stdio");
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                let _ = out.send(error_reply(&serde_json::Value::Null, -32700, &format!("Parse error: {}", e)));
+                continue;
+            },
+        };
+        let id = message.get("id").cloned();
+        let task = tokio::spawn({
+            let (server, out) = (server.clone(), out.clone());
+            async move { server.handle(message, &out).await }
+        });
+        if let Some(id) = &id {
+            server.running.lock().unwrap().insert(id.to_string(), task.abort_handle());
+        }
+        let (server, out) = (server.clone(), out.clone());
+        tokio::spawn(async move {
+            let answer = match task.await {
+                Ok(answer) => answer,
+                Err(e) if e.is_cancelled() => id.as_ref().map(|id| error_reply(id, -32800, "Request cancelled")),
+                Err(_) => id.as_ref().map(|id| error_reply(id, -32603, "Internal error")),
+            };
+            if let Some(id) = &id {
+                server.running.lock().unwrap().remove(&id.to_string());
+            }
+            if let Some(answer) = answer {
+                let _ = out.send(answer);
+            }
+        });
+    }
+    drop(out);
+    let _ = writer.await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::context;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn chats_over_json_rpc_with_progress_notifications() {
+        let mut config = Config::default();
+        config.anthropic.mock_fixture = Some("tests/fixtures/llm/rpc_chat.json".to_string());
+        let server = RpcServer::new(context(config), None);
+        let (out, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        let request = json!({ "jsonrpc": "2.0", "id": 7, "method": "chat", "params": { "message": "What time is it?" } });
+        let answer = server.handle(request, &out).await.unwrap();
+        assert_eq!(answer["id"], 7);
+        assert_eq!(answer["result"]["reply"], "It is noon.");
+        let session = answer["result"]["session"].as_str().unwrap();
+        let mut activity = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            assert_eq!(notification["method"], "progress");
+            assert_eq!(notification["params"]["session"], session);
+            activity.push(notification["params"]["activity"].as_str().unwrap().to_string());
+        }
+        assert_eq!(activity.first().map(String::as_str), Some("→ get_time"));
+        assert!(activity.last().unwrap().starts_with("ok get_time"));
+
+        // Without a database, only sessions started by this server can be continued
+        let unknown = json!({ "jsonrpc": "2.0", "id": 8, "method": "chat", "params": { "message": "Hi", "session": Uuid::new_v4() } });
+        assert_eq!(server.handle(unknown, &out).await.unwrap()["error"]["code"], -32602);
+        assert!(server.handle(json!({ "jsonrpc": "2.0", "method": "chat" }), &out).await.is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::config::SafeConfig;
+use crate::units;
+use crate::wallet::AgentSigner;
+use ethers::prelude::*;
+use ethers::utils::to_checksum;
+use reqwest::Client;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+abigen!(
+    GnosisSafe,
+    r#"[
+        function nonce() external view returns (uint256)
+        function getThreshold() external view returns (uint256)
+        function getOwners() external view returns (address[])
+        function getTransactionHash(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, uint256 _nonce) external view returns (bytes32)
+        function execTransaction(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, bytes signatures) external payable returns (bool success)
+    ]"#
+);
+
+/// Owners, threshold, and next nonce of a Safe, read from the contract
+#[derive(Debug, Clone)]
+pub struct SafeInfo {
+    pub address: Address,
+    pub owners: Vec<Address>,
+    pub threshold: U256,
+    pub nonce: U256,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Confirmation {
+    pub owner: Address,
+    pub signature: Bytes,
+}
+
+// The service returns some integers as JSON numbers and others as strings
+fn deserialize_u256<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(number) => Ok(U256::from(number.as_u64().unwrap_or_default())),
+        serde_json::Value::String(text) => U256::from_dec_str(&text).map_err(serde::de::Error::custom),
+        _ => Ok(U256::zero()),
+    }
+}
+
+/// A multisig transaction as stored by the Safe Transaction Service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeTransaction {
+    pub safe_tx_hash: H256,
+    pub to: Address,
+    #[serde(deserialize_with = "deserialize_u256")]
+    pub value: U256,
+    pub data: Option<Bytes>,
+    pub operation: u8,
+    #[serde(deserialize_with = "deserialize_u256")]
+    pub safe_tx_gas: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
+    pub base_gas: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
+    pub gas_price: U256,
+    pub gas_token: Address,
+    pub refund_receiver: Address,
+    #[serde(deserialize_with = "deserialize_u256")]
+    pub nonce: U256,
+    #[serde(default)]
+    pub confirmations: Vec<Confirmation>,
+    pub confirmations_required: Option<u64>,
+    #[serde(default)]
+    pub is_executed: bool,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    results: Vec<T>,
+}
+
+pub fn safe_address(config: &SafeConfig, input: Option<&str>) -> anyhow::Result<Address> {
+    let address = input.filter(|input| !input.is_empty())
+        .or(config.address.as_deref())
+        .ok_or_else(|| anyhow::anyhow!("No Safe address given and none configured under [safe]"))?;
+    Address::from_str(address).map_err(|_| anyhow::anyhow!("Invalid Safe address {}", address))
+}
+
+pub async fn info<M: Middleware + 'static>(client: Arc<M>, address: Address) -> anyhow::Result<SafeInfo> {
+    let safe = GnosisSafe::new(address, client);
+    let threshold = safe.get_threshold().call().await
+        .map_err(|e| anyhow::anyhow!("{:?} does not look like a Safe: {}", address, e))?;
+    Ok(SafeInfo {
+        address,
+        owners: safe.get_owners().call().await?,
+        threshold,
+        nonce: safe.nonce().call().await?,
+    })
+}
+
+/// Hash the owners sign for a plain call from the Safe, without gas refunds
+pub async fn transaction_hash<M: Middleware + 'static>(client: Arc<M>, address: Address, to: Address, value: U256, data: Bytes, nonce: U256) -> anyhow::Result<H256> {
+    let safe = GnosisSafe::new(address, client);
+    let hash = safe.get_transaction_hash(to, value, data, 0, U256::zero(), U256::zero(), U256::zero(), Address::zero(), Address::zero(), nonce)
+        .call()
+        .await?;
+    Ok(H256::from(hash))
+}
+
+/// Sign a Safe transaction hash as an owner. Safe accepts a plain ECDSA signature of the hash
+/// (v = 27 or 28), or an `eth_sign` signature of it marked by adding 4 to v, for signers such as
+/// hardware wallets that only sign prefixed messages.
+pub async fn sign_transaction_hash(signer: &AgentSigner, hash: H256) -> anyhow::Result<Bytes> {
+    let signature = match signer.local_wallet() {
+        Some(wallet) => wallet.sign_hash(hash)?,
+        None => {
+            let mut signature = signer.sign_message(hash.as_bytes()).await?;
+            signature.v += 4;
+            signature
+        },
+    };
+    Ok(Bytes::from(signature.to_vec()))
+}
+
+fn request(client: &Client, config: &SafeConfig, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+    let request = client.request(method, format!("{}/api/v1{}", config.transaction_service_url.trim_end_matches('/'), path));
+    match &config.api_key {
+        Some(key) => request.bearer_auth(key),
+        None => request,
+    }
+}
+
+async fn check(response: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow::anyhow!("Safe Transaction Service returned {}: {}", status, body))
+}
+
+/// Unexecuted transactions at or above the Safe's current nonce, oldest first
+pub async fn pending_transactions(config: &SafeConfig, safe: Address, current_nonce: U256) -> anyhow::Result<Vec<SafeTransaction>> {
+    let path = format!("/safes/{}/multisig-transactions/", to_checksum(&safe, None));
+    let response = request(&Client::new(), config, reqwest::Method::GET, &path)
+        .query(&[("executed", "false"), ("nonce__gte", current_nonce.to_string().as_str()), ("ordering", "nonce")])
+        .send()
+        .await?;
+    let page: Page<SafeTransaction> = check(response).await?.json().await?;
+    Ok(page.results)
+}
+
+pub async fn get_transaction(config: &SafeConfig, safe_tx_hash: H256) -> anyhow::Result<SafeTransaction> {
+    let path = format!("/multisig-transactions/{:?}/", safe_tx_hash);
+    let response = request(&Client::new(), config, reqwest::Method::GET, &path).send().await?;
+    Ok(check(response).await?.json().await?)
+}
+
+/// Next nonce for a proposal: after the current on-chain nonce and every queued proposal
+pub fn next_nonce(info: &SafeInfo, pending: &[SafeTransaction]) -> U256 {
+    pending.iter()
+        .map(|tx| tx.nonce + 1)
+        .fold(info.nonce, U256::max)
+}
+
+/// Propose a call to the Safe's owners, signed by the proposing owner
+#[allow(clippy::too_many_arguments)]
+pub async fn propose(config: &SafeConfig, safe: Address, to: Address, value: U256, data: &Bytes, nonce: U256, safe_tx_hash: H256, sender: Address, signature: &Bytes) -> anyhow::Result<()> {
+    let body = serde_json::json!({
+        "to": to_checksum(&to, None),
+        "value": value.to_string(),
+        "data": if data.is_empty() { serde_json::Value::Null } else { serde_json::json!(data) },
+        "operation": 0,
+        "safeTxGas": "0",
+        "baseGas": "0",
+        "gasPrice": "0",
+        "gasToken": to_checksum(&Address::zero(), None),
+        "refundReceiver": to_checksum(&Address::zero(), None),
+        "nonce": nonce.to_string(),
+        "contractTransactionHash": format!("{:?}", safe_tx_hash),
+        "sender": to_checksum(&sender, None),
+        "signature": signature,
+        "origin": "onchain-agent-template",
+    });
+    let path = format!("/safes/{}/multisig-transactions/", to_checksum(&safe, None));
+    let response = request(&Client::new(), config, reqwest::Method::POST, &path).json(&body).send().await?;
+    check(response).await?;
+    Ok(())
+}
+
+/// Add an owner's signature to a proposed transaction
+pub async fn confirm(config: &SafeConfig, safe_tx_hash: H256, signature: &Bytes) -> anyhow::Result<()> {
+    let path = format!("/multisig-transactions/{:?}/confirmations/", safe_tx_hash);
+    let response = request(&Client::new(), config, reqwest::Method::POST, &path)
+        .json(&serde_json::json!({ "signature": signature }))
+        .send()
+        .await?;
+    check(response).await?;
+    Ok(())
+}
+
+/// Calldata executing a confirmed transaction. Safe expects the signatures concatenated in
+/// ascending order of the owner addresses.
+pub fn exec_call<M: Middleware + 'static>(client: Arc<M>, safe: Address, tx: &SafeTransaction) -> Bytes {
+    let mut confirmations = tx.confirmations.clone();
+    confirmations.sort_by_key(|confirmation| confirmation.owner);
+    let signatures: Vec<u8> = confirmations.iter()
+        .flat_map(|confirmation| confirmation.signature.to_vec())
+        .collect();
+    GnosisSafe::new(safe, client)
+        .exec_transaction(tx.to, tx.value, tx.data.clone().unwrap_or_default(), tx.operation, tx.safe_tx_gas,
+                          tx.base_gas, tx.gas_price, tx.gas_token, tx.refund_receiver, signatures.into())
+        .calldata()
+        .unwrap_or_default()
+}
+
+pub fn describe_transaction(tx: &SafeTransaction, threshold: U256) -> String {
+    let required = tx.confirmations_required.map(U256::from).unwrap_or(threshold);
+    let data = match &tx.data {
+        Some(data) if !data.is_empty() => format!(", {} bytes of calldata", data.len()),
+        _ => String::new(),
+    };
+    let signers = tx.confirmations.iter()
+        .map(|confirmation| format!("{:?}", confirmation.owner))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("#{} {:?}: send {} ETH to {:?}{} ({} of {} confirmations{}{})",
+            tx.nonce, tx.safe_tx_hash, units::format_eth(tx.value), tx.to, data,
+            tx.confirmations.len(), required,
+            if signers.is_empty() { String::new() } else { format!(" by {}", signers) },
+            if U256::from(tx.confirmations.len()) >= required { ", ready to execute" } else { "" })
+}
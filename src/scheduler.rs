@@ -0,0 +1,232 @@
+use crate::anthropic::call_anthropic_with_tools;
+use crate::config::{Config, ScheduledJobConfig};
+use crate::conversation::Conversation;
+use crate::db::{latest_scheduled_runs, save_scheduled_run};
+use crate::notify::{self, Kind, Notification};
+use crate::personality::{load_personality, persona_path};
+use crate::policy::PolicyEngine;
+use crate::tools::{execute_tool, ToolContext};
+use chrono::{Local, Utc};
+use cron::Schedule;
+use sqlx::{Pool, Postgres};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Outcome of one run of a scheduled job
+#[derive(Debug, Clone)]
+pub struct ScheduledRun {
+    pub job: String,
+    pub output: String,
+    pub success: bool,
+}
+
+enum Action {
+    Prompt(String),
+    Tool(String, serde_json::Value),
+}
+
+struct Job {
+    config: ScheduledJobConfig,
+    schedule: Schedule,
+    action: Action,
+}
+
+/// Parse a cron expression. The cron crate wants a seconds field; standard five-field
+/// expressions fire at second 0.
+pub fn parse_schedule(expression: &str) -> anyhow::Result<Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 { format!("0 {}", expression) } else { expression.to_string() };
+    Schedule::from_str(&expression).map_err(|e| anyhow::anyhow!("Invalid schedule '{}': {}", expression, e))
+}
+
+fn parse_job(config: &ScheduledJobConfig) -> anyhow::Result<Job> {
+    let schedule = parse_schedule(&config.schedule)?;
+    let action = match (&config.prompt, &config.tool) {
+        (Some(prompt), None) => Action::Prompt(prompt.clone()),
+        (None, Some(tool)) => {
+            let args = if config.args.is_null() { serde_json::json!({}) } else { config.args.clone() };
+            Action::Tool(tool.clone(), args)
+        },
+        _ => return Err(anyhow::anyhow!("Set either prompt or tool")),
+    };
+    Ok(Job { config: config.clone(), schedule, action })
+}
+
+// Message handed to the agent. It is marked as coming from the scheduler, since nobody is
+// there to confirm anything it does.
+fn scheduled_prompt(name: &str, prompt: &str) -> String {
+    format!("[Scheduled job '{}'] {}\n\
+             This message was sent by a scheduled job, not typed by the user, who may not be watching. \
+             Do not send any transaction unless this message explicitly asks for it.",
+            name, prompt)
+}
+
+async fn run_job(config: &Arc<Config>, policy: &Arc<PolicyEngine>, pool: Option<&Pool<Postgres>>, job: &Job) -> (String, ScheduledRun) {
+    let ctx = ToolContext::new(config.clone(), policy.clone(), pool.cloned(), None);
+    let (input, result) = match &job.action {
+        Action::Prompt(prompt) => {
+            // The persona is loaded for every run, so edits to its file apply
+            let result = match persona_path(&config.personalities_dir, &config.persona).and_then(|path| load_personality(&path.to_string_lossy())) {
+                Ok(personality) => call_anthropic_with_tools(&config.anthropic, &scheduled_prompt(&job.config.name, prompt), &[],
+                                                             Some(&personality), &Conversation::default(), &ctx).await,
+                Err(e) => Err(e),
+            };
+            (prompt.clone(), result)
+        },
+        Action::Tool(tool, args) => (format!("{} {}", tool, args), execute_tool(&ctx, tool, args).await.map_err(|e| anyhow::anyhow!(e))),
+    };
+    let run = match result {
+        Ok(output) => ScheduledRun { job: job.config.name.clone(), output, success: true },
+        Err(e) => ScheduledRun { job: job.config.name.clone(), output: format!("Error: {}", e), success: false },
+    };
+    (input, run)
+}
+
+async fn post_webhook(url: &str, run: &ScheduledRun) -> anyhow::Result<()> {
+    let body = serde_json::json!({
+        "job": run.job,
+        "success": run.success,
+        "output": run.output,
+    });
+    reqwest::Client::new().post(url).json(&body).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Wait until the schedule is next due in local time; false if it never is again
+pub async fn sleep_until_next(schedule: &Schedule) -> bool {
+    let Some(next) = schedule.upcoming(Local).next() else { return false };
+    tokio::time::sleep((next - Local::now()).to_std().unwrap_or_default()).await;
+    true
+}
+
+/// Run the `[scheduler]` jobs in the background, storing each result, posting it to the job's
+/// webhook and the `[notify]` channels, and handing it to `on_run`. A run that is still going when the job is next due
+/// skips that time.
+pub fn spawn(config: Arc<Config>, policy: Arc<PolicyEngine>, pool: Option<Pool<Postgres>>, on_run: impl Fn(ScheduledRun) + Send + Sync + 'static) {
+    if !config.scheduler.enabled || config.scheduler.jobs.is_empty() {
+        return;
+    }
+    let on_run: Arc<dyn Fn(ScheduledRun) + Send + Sync> = Arc::new(on_run);
+    for job_config in &config.scheduler.jobs {
+        let job = match parse_job(job_config) {
+            Ok(job) => job,
+            Err(e) => {
+                tracing::error!(job = %job_config.name, error = %e, "Skipping invalid scheduled job");
+                continue;
+            },
+        };
+        let (config, policy, pool, on_run) = (config.clone(), policy.clone(), pool.clone(), on_run.clone());
+        tokio::spawn(async move {
+            while sleep_until_next(&job.schedule).await {
+                let started_at = Utc::now().naive_utc();
+                let (input, run) = run_job(&config, &policy, pool.as_ref(), &job).await;
+                tracing::info!(job = %run.job, success = run.success, "Scheduled job ran");
+                if let Some(pool) = &pool
+                    && let Err(e) = save_scheduled_run(pool, &run.job, &input, &run.output, run.success, started_at).await
+                {
+                    tracing::error!(job = %run.job, error = %e, "Failed to save scheduled run");
+                }
+                if let Some(url) = &job.config.notify_url
+                    && let Err(e) = post_webhook(url, &run).await
+                {
+                    tracing::warn!(job = %run.job, error = %e, "Failed to post scheduled run to its webhook");
+                }
+                let title = format!("Scheduled job '{}' {}", run.job, if run.success { "finished" } else { "failed" });
+                notify::send(&config.notify, &Notification::new(Kind::Scheduler, title, run.output.clone())).await;
+                on_run(run);
+            }
+        });
+    }
+}
+
+/// One line per configured job with its next run and the outcome of its last one
+pub async fn status(config: &Config, pool: Option<&Pool<Postgres>>) -> Vec<String> {
+    let latest = match pool {
+        Some(pool) => latest_scheduled_runs(pool).await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to load scheduled runs");
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    config.scheduler.jobs.iter()
+        .map(|job_config| {
+            let next = match parse_job(job_config) {
+                Ok(_) if !config.scheduler.enabled => "scheduler disabled".to_string(),
+                Ok(job) => job.schedule.upcoming(Local).next()
+                    .map_or("never runs again".to_string(), |next| format!("next run {}", next.format("%Y-%m-%d %H:%M"))),
+                Err(e) => format!("invalid: {}", e),
+            };
+            let last = latest.iter()
+                .find(|run| run.job_name == job_config.name)
+                .map_or("never ran".to_string(), |run| format!("last run {} UTC {}", run.started_at.format("%Y-%m-%d %H:%M"),
+                                                               if run.success { "succeeded" } else { "failed" }));
+            format!("{} ({}): {}; {}", job_config.name, job_config.schedule, next, last)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone};
+
+    fn job(name: &str, schedule: &str, prompt: Option<&str>, tool: Option<&str>) -> ScheduledJobConfig {
+        ScheduledJobConfig {
+            name: name.to_string(),
+            schedule: schedule.to_string(),
+            prompt: prompt.map(str::to_string),
+            tool: tool.map(str::to_string),
+            args: serde_json::Value::Null,
+            notify_url: None,
+        }
+    }
+
+    // The next times the schedule fires after `after`
+    fn fires(expression: &str, after: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        parse_schedule(expression).unwrap().after(&after).take(count).collect()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn computes_the_next_runs() {
+        // Wednesday 14 October 2026, 12:07:30
+        let now = at(2026, 10, 14, 12, 7, 30);
+        assert_eq!(fires("*/15 * * * *", now, 2), [at(2026, 10, 14, 12, 15, 0), at(2026, 10, 14, 12, 30, 0)]);
+        assert_eq!(fires(" 0 9 * * * ", now, 1), [at(2026, 10, 15, 9, 0, 0)]);
+        // Weekdays only, so Friday's run is followed by Monday's
+        assert_eq!(fires("0 9 * * Mon-Fri", at(2026, 10, 16, 10, 0, 0), 1), [at(2026, 10, 19, 9, 0, 0)]);
+        // With a leading seconds field the expression is taken as it is
+        assert_eq!(fires("30 0 9 * * *", now, 1), [at(2026, 10, 15, 9, 0, 30)]);
+        // A year field ends the schedule after its last run
+        assert_eq!(fires("0 0 0 1 1 * 2027", now, 2), [at(2027, 1, 1, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn rejects_invalid_jobs() {
+        for expression in ["every day", "* * *", "0 61 * * *", "0 9 * * Funday"] {
+            let error = parse_schedule(expression).unwrap_err().to_string();
+            assert!(error.starts_with("Invalid schedule '"), "{}", error);
+        }
+        let error = parse_job(&job("hourly", "0 * * * *", Some("Check my balance"), Some("gas_report"))).err().unwrap();
+        assert_eq!(error.to_string(), "Set either prompt or tool");
+        assert!(parse_job(&job("hourly", "0 * * * *", None, None)).is_err());
+        assert!(matches!(parse_job(&job("hourly", "0 * * * *", None, Some("gas_report"))).unwrap().action,
+                         Action::Tool(ref tool, ref args) if tool == "gas_report" && *args == serde_json::json!({})));
+    }
+
+    #[tokio::test]
+    async fn shows_invalid_jobs_in_the_status() {
+        let mut config = Config::default();
+        config.scheduler.enabled = true;
+        config.scheduler.jobs = vec![
+            job("report", "0 9 * * *", Some("Summarize my portfolio"), None),
+            job("typo", "0 25 * * *", Some("Summarize my portfolio"), None),
+        ];
+        let lines = status(&config, None).await;
+        assert!(lines[0].starts_with("report (0 9 * * *): next run ") && lines[0].ends_with(" 09:00; never ran"), "{}", lines[0]);
+        assert!(lines[1].starts_with("typo (0 25 * * *): invalid: Invalid schedule '0 0 25 * * *'"), "{}", lines[1]);
+    }
+}
@@ -0,0 +1,179 @@
+// Web search for the `web_search` tool, through Brave Search, SerpAPI (Google results), or
+// Tavily, picked with `[search] provider`. Each backend's response is reduced to titles, URLs,
+// and snippets, so the agent can look up protocols, exploits, or news it wasn't trained on.
+
+use crate::config::SearchConfig;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Client;
+use std::time::Duration;
+
+const BRAVE_API_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+const SERPAPI_API_URL: &str = "https://serpapi.com/search.json";
+const TAVILY_API_URL: &str = "https://api.tavily.com/search";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Most results asked for in one search
+pub const MAX_RESULTS: usize = 10;
+
+lazy_static! {
+    // Highlighting markup in snippets, e.g. <strong>
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+fn api_url<'a>(config: &'a SearchConfig, default: &'a str) -> &'a str {
+    config.api_url.as_deref().filter(|url| !url.is_empty()).unwrap_or(default)
+}
+
+fn api_key(config: &SearchConfig) -> anyhow::Result<&str> {
+    config.api_key.as_deref()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("No API key for {}; set api_key under [search] or SEARCH_API_KEY", config.provider))
+}
+
+// Snippet text without markup or entities, on one line
+fn plain(text: &str) -> String {
+    TAG.replace_all(text, "")
+        .replace("&amp;", "&").replace("&quot;", "\"").replace("&#39;", "'").replace("&lt;", "<").replace("&gt;", ">")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Results listed under `list`, with their fields under the given names
+fn results(body: &serde_json::Value, list: &serde_json::Value, (title, url, snippet): (&str, &str, &str)) -> anyhow::Result<Vec<SearchResult>> {
+    let Some(items) = list.as_array() else {
+        // An empty result set comes without the list
+        if let Some(error) = body["error"].as_str() {
+            return Err(anyhow::anyhow!("{}", error));
+        }
+        return Ok(Vec::new());
+    };
+    Ok(items.iter()
+        .filter_map(|item| Some(SearchResult {
+            title: plain(item[title].as_str()?),
+            url: item[url].as_str()?.to_string(),
+            snippet: plain(item[snippet].as_str().unwrap_or_default()),
+        }))
+        .collect())
+}
+
+async fn check(response: reqwest::Response, provider: &str) -> anyhow::Result<serde_json::Value> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("{} returned {}: {}", provider, status, body));
+    }
+    Ok(response.json().await?)
+}
+
+/// Search the web, returning up to `count` results
+pub async fn search(config: &SearchConfig, query: &str, count: usize) -> anyhow::Result<Vec<SearchResult>> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let count = count.clamp(1, MAX_RESULTS);
+    let mut found = match config.provider.as_str() {
+        "brave" => {
+            let response = client.get(api_url(config, BRAVE_API_URL))
+                .header("X-Subscription-Token", api_key(config)?)
+                .query(&[("q", query), ("count", &count.to_string())])
+                .send()
+                .await?;
+            let body = check(response, "Brave Search").await?;
+            results(&body, &body["web"]["results"], ("title", "url", "description"))?
+        },
+        "serpapi" => {
+            let response = client.get(api_url(config, SERPAPI_API_URL))
+                .query(&[("engine", "google"), ("q", query), ("num", &count.to_string()), ("api_key", api_key(config)?)])
+                .send()
+                .await?;
+            let body = check(response, "SerpAPI").await?;
+            results(&body, &body["organic_results"], ("title", "link", "snippet"))?
+        },
+        "tavily" => {
+            let response = client.post(api_url(config, TAVILY_API_URL))
+                .bearer_auth(api_key(config)?)
+                .json(&serde_json::json!({ "query": query, "max_results": count }))
+                .send()
+                .await?;
+            let body = check(response, "Tavily").await?;
+            results(&body, &body["results"], ("title", "url", "content"))?
+        },
+        other => return Err(anyhow::anyhow!("Unknown search provider '{}' (expected brave, serpapi, or tavily)", other)),
+    };
+    found.truncate(count);
+    Ok(found)
+}
+
+pub fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No web results for \"{}\"", query);
+    }
+    let entries: Vec<String> = results.iter().enumerate()
+        .map(|(i, result)| match result.snippet.is_empty() {
+            true => format!("{}. {}\n   {}", i + 1, result.title, result.url),
+            false => format!("{}. {}\n   {}\n   {}", i + 1, result.title, result.url, result.snippet),
+        })
+        .collect();
+    format!("Web results for \"{}\":\n{}", query, entries.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn searches_the_web_through_each_backend() {
+        use axum::http::HeaderMap;
+        use axum::routing::{get, post};
+
+        let api = axum::Router::new()
+            .route("/brave", get(|headers: HeaderMap, axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>| async move {
+                assert_eq!(headers["x-subscription-token"], "search-key");
+                axum::Json(json!({ "web": { "results": [
+                    { "title": format!("Results for {}", query["q"]), "url": "https://example.com/a", "description": "The <strong>Euler</strong> exploit &amp; its\n recovery" },
+                    { "title": "Second", "url": "https://example.com/b", "description": "More" },
+                ] } }))
+            }))
+            .route("/serpapi", get(|axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>| async move {
+                assert_eq!(query["api_key"], "search-key");
+                axum::Json(json!({ "organic_results": [{ "title": "Google result", "link": "https://example.com/g", "snippet": "From Google" }] }))
+            }))
+            .route("/tavily", post(|headers: HeaderMap, axum::Json(body): axum::Json<serde_json::Value>| async move {
+                assert_eq!(headers["authorization"], "Bearer search-key");
+                axum::Json(json!({ "results": [{ "title": format!("Tavily: {}", body["query"].as_str().unwrap()), "url": "https://example.com/t", "content": "" }] }))
+            }));
+        let url = serve(api).await;
+
+        let mut config = Config::default();
+        let unconfigured = execute_tool(&context(config.clone()), "web_search", &json!({ "query": "euler" })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+
+        config.search.api_key = Some("search-key".to_string());
+        config.search.api_url = Some(format!("{}/brave", url));
+        let brave = execute_tool(&context(config.clone()), "web_search", &json!({ "query": "euler exploit", "count": 1 })).await.unwrap();
+        assert!(brave.contains("1. Results for euler exploit\n   https://example.com/a\n   The Euler exploit & its recovery"), "{}", brave);
+        assert!(!brave.contains("Second"), "{}", brave);
+
+        config.search.provider = "serpapi".to_string();
+        config.search.api_url = Some(format!("{}/serpapi", url));
+        let serpapi = execute_tool(&context(config.clone()), "web_search", &json!({ "query": "euler" })).await.unwrap();
+        assert!(serpapi.contains("1. Google result\n   https://example.com/g\n   From Google"), "{}", serpapi);
+
+        config.search.provider = "tavily".to_string();
+        config.search.api_url = Some(format!("{}/tavily", url));
+        let tavily = execute_tool(&context(config), "web_search", &json!({ "query": "euler" })).await.unwrap();
+        assert!(tavily.ends_with("1. Tavily: euler\n   https://example.com/t"), "{}", tavily);
+    }
+}
@@ -0,0 +1,677 @@
+use crate::alerts;
+use crate::anthropic::{call_anthropic_with_tools, model_for, ImageSource};
+use crate::config::Config;
+use crate::conversation::Conversation;
+use crate::db::{
+    api_key_user, auth_token_user, create_owned_session, find_user, list_owned_sessions, list_owned_wallets,
+    list_tool_calls, load_session_messages, save_auth_nonce, save_auth_token, save_user, search_messages,
+    session_owned_by, set_user_policy, take_auth_nonce, SearchHit, SessionRecord, ToolCallRecord, WalletRecord,
+};
+use crate::gas;
+use crate::images;
+use crate::memory;
+use crate::personality::LivePersonality;
+use crate::policy::PolicyEngine;
+use crate::reminders;
+use crate::signing::{self, SignRequest};
+use crate::siwe::{self, SiweMessage};
+use crate::tools::ToolContext;
+use crate::tracker;
+use crate::usage::TurnMeter;
+use crate::policy::Policy;
+use crate::users::{key_hash, user_address, Role, UserPolicies, API_KEY_PREFIX};
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use ethers::types::Address;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use uuid::Uuid;
+
+const DEFAULT_AUDIT_LIMIT: i64 = 50;
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+// Number of stored messages replayed as context for each reply
+const CHAT_HISTORY_LIMIT: i64 = 50;
+// How often text and tool activity of a reply being written are sent over a WebSocket
+const STREAM_INTERVAL: Duration = Duration::from_millis(50);
+// How long an issued sign-in nonce can be used
+const NONCE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Clone)]
+struct AppState {
+    pool: Option<Pool<Postgres>>,
+    config: Arc<Config>,
+    policy: Arc<PolicyEngine>,
+    personality: Arc<LivePersonality>,
+    auth: Arc<AuthState>,
+    policies: Arc<UserPolicies>,
+}
+
+// Sign-in state of a deployment without a database; with one, it is kept in the auth_nonces
+// and auth_tokens tables instead, so sign-ins survive restarts and hold on every replica
+#[derive(Default)]
+struct AuthState {
+    // Nonces issued for sign-in messages, each usable once
+    nonces: Mutex<HashMap<String, Instant>>,
+    // Bearer tokens of signed-in addresses, with their expiry
+    tokens: Mutex<HashMap<String, (Address, Instant)>>,
+}
+
+type ApiError = (StatusCode, String);
+
+fn database(state: &AppState) -> Result<&Pool<Postgres>, ApiError> {
+    state.pool.as_ref()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Database is not configured".to_string()))
+}
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn unauthorized(reason: &str) -> ApiError {
+    (StatusCode::UNAUTHORIZED, reason.to_string())
+}
+
+// The user of the request's `Authorization: Bearer <token or API key>` header, if any
+async fn authenticated(state: &AppState, headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(header::AUTHORIZATION) else { return Ok(None) };
+    let token = value.to_str().ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| unauthorized("Expected a bearer token"))?;
+    token_owner(state, token).await.map(Some)
+}
+
+// The address of the user a sign-in token or API key was issued to
+async fn token_owner(state: &AppState, token: &str) -> Result<String, ApiError> {
+    let token = token.trim();
+    if token.starts_with(API_KEY_PREFIX) {
+        return api_key_user(database(state)?, &key_hash(token)).await
+            .map_err(internal_error)?
+            .ok_or_else(|| unauthorized("Unknown or revoked API key"));
+    }
+    let address = match &state.pool {
+        Some(pool) => auth_token_user(pool, &key_hash(token)).await.map_err(internal_error)?,
+        None => {
+            let mut tokens = state.auth.tokens.lock().unwrap();
+            tokens.retain(|_, (_, expires_at)| *expires_at > Instant::now());
+            tokens.get(token).map(|(address, _)| format!("{:?}", address))
+        },
+    }
+    .ok_or_else(|| unauthorized("Sign-in expired or unknown; sign in again"))?;
+    // Without sign-ups, a user removed since signing in loses access straight away
+    if !state.config.server.allow_signup
+        && let Some(pool) = &state.pool
+        && find_user(pool, &address).await.map_err(internal_error)?.is_none()
+    {
+        return Err(not_a_user());
+    }
+    Ok(address)
+}
+
+fn not_a_user() -> ApiError {
+    (StatusCode::FORBIDDEN, "This address is not a user of this deployment".to_string())
+}
+
+async fn signed_in(state: &AppState, headers: &HeaderMap) -> Result<String, ApiError> {
+    authenticated(state, headers).await?
+        .ok_or_else(|| unauthorized("Sign in with Ethereum first"))
+}
+
+// A user's role; that of sign-ups for one who signed in but has no row (removed since)
+async fn user_role(state: &AppState, pool: &Pool<Postgres>, owner: &str) -> Result<(Role, Option<serde_json::Value>), ApiError> {
+    let user = find_user(pool, owner).await.map_err(internal_error)?;
+    let role = user.as_ref().and_then(|user| Role::parse(&user.role)).unwrap_or(state.config.server.signup_role);
+    Ok((role, user.and_then(|user| user.policy)))
+}
+
+// Tools acting for a user in one of their sessions, within their role, and under their own
+// policy if they have one
+async fn user_context(state: &AppState, pool: &Pool<Postgres>, session: Uuid, owner: &str) -> Result<ToolContext, ApiError> {
+    let (role, policy) = user_role(state, pool, owner).await?;
+    let policy = state.policies.for_user(owner, policy, &state.policy);
+    Ok(ToolContext {
+        user: Some(owner.to_string()),
+        role: Some(role),
+        ..ToolContext::new(state.config.clone(), policy, Some(pool.clone()), Some(session))
+    })
+}
+
+#[derive(Serialize)]
+struct NonceResponse {
+    nonce: String,
+}
+
+// GET /auth/nonce - a fresh nonce to put in a Sign-In with Ethereum message
+async fn auth_nonce(State(state): State<AppState>) -> Result<Json<NonceResponse>, ApiError> {
+    let nonce = siwe::generate_nonce();
+    match &state.pool {
+        Some(pool) => save_auth_nonce(pool, &nonce, NONCE_TTL.as_secs()).await.map_err(internal_error)?,
+        None => {
+            let mut nonces = state.auth.nonces.lock().unwrap();
+            nonces.retain(|_, issued_at| issued_at.elapsed() < NONCE_TTL);
+            nonces.insert(nonce.clone(), Instant::now());
+        },
+    }
+    Ok(Json(NonceResponse { nonce }))
+}
+
+// Use up a nonce issued by /auth/nonce; false if it is unknown, used, or expired
+async fn take_nonce(state: &AppState, nonce: &str) -> Result<bool, ApiError> {
+    if let Some(pool) = &state.pool {
+        return take_auth_nonce(pool, nonce).await.map_err(internal_error);
+    }
+    let issued_at = state.auth.nonces.lock().unwrap().remove(nonce);
+    Ok(issued_at.is_some_and(|issued_at| issued_at.elapsed() < NONCE_TTL))
+}
+
+// A new bearer token for a signed-in address, valid for `auth_ttl_secs`
+async fn issue_token(state: &AppState, address: Address) -> Result<String, ApiError> {
+    let token = hex::encode(rand::thread_rng().r#gen::<[u8; 32]>());
+    let ttl = state.config.server.auth_ttl_secs;
+    match &state.pool {
+        Some(pool) => save_auth_token(pool, &key_hash(&token), &format!("{:?}", address), ttl).await.map_err(internal_error)?,
+        None => {
+            state.auth.tokens.lock().unwrap().insert(token.clone(), (address, Instant::now() + Duration::from_secs(ttl)));
+        },
+    }
+    Ok(token)
+}
+
+#[derive(Deserialize)]
+struct VerifyRequest {
+    message: String,
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct VerifyResponse {
+    token: String,
+    address: String,
+    expires_in_secs: u64,
+}
+
+// POST /auth/verify - check a signed EIP-4361 message and issue a bearer token for its address
+async fn auth_verify(State(state): State<AppState>, Json(request): Json<VerifyRequest>) -> Result<Json<VerifyResponse>, ApiError> {
+    let message = SiweMessage::parse(&request.message)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    message.validate(&state.config.server.domain, state.config.chain.chain_id, chrono::Utc::now())
+        .map_err(|e| unauthorized(&e.to_string()))?;
+
+    // The nonce is consumed even if the signature turns out invalid
+    if !take_nonce(&state, &message.nonce).await? {
+        return Err(unauthorized("Unknown or expired nonce; request a new one from /auth/nonce"));
+    }
+
+    let signature = signing::parse_signature(&request.signature)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let signer = signing::recover(&SignRequest::Message(request.message.into_bytes()), &signature)
+        .map_err(|e| unauthorized(&e.to_string()))?;
+    if signer != message.address {
+        return Err(unauthorized("Signature was not made by the address in the message"));
+    }
+    if let Some(pool) = &state.pool {
+        let address = format!("{:?}", signer);
+        if state.config.server.allow_signup {
+            save_user(pool, &address, None, state.config.server.signup_role.as_str()).await.map_err(internal_error)?;
+        } else if find_user(pool, &address).await.map_err(internal_error)?.is_none() {
+            return Err(not_a_user());
+        }
+    }
+
+    let token = issue_token(&state, signer).await?;
+    tracing::info!(address = ?signer, chain_id = message.chain_id, uri = %message.uri, "Signed in with Ethereum");
+    Ok(Json(VerifyResponse { token, address: format!("{:?}", signer), expires_in_secs: state.config.server.auth_ttl_secs }))
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    session: Option<Uuid>,
+    limit: Option<i64>,
+}
+
+// Whose sessions a signed-in caller may look through: their own, or every session (those of
+// the CLI and Discord, which have no owner, included) for an admin
+async fn visible_owner(state: &AppState, headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let owner = signed_in(state, headers).await?;
+    match user_role(state, database(state)?, &owner).await?.0 {
+        Role::Admin => Ok(None),
+        _ => Ok(Some(owner)),
+    }
+}
+
+// GET /audit?session=<uuid>&limit=<n> - recent tool calls of the caller's sessions, newest first
+async fn audit(State(state): State<AppState>, headers: HeaderMap, Query(query): Query<AuditQuery>) -> Result<Json<Vec<ToolCallRecord>>, ApiError> {
+    let owner = visible_owner(&state, &headers).await?;
+    let pool = database(&state)?;
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT).clamp(1, 1000);
+
+    list_tool_calls(pool, query.session, owner.as_deref(), limit).await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<i64>,
+}
+
+// GET /history/search?q=<query>&limit=<n> - messages of the caller's sessions matching a
+// full-text search, best match first, with a snippet of each
+async fn search_history(State(state): State<AppState>, headers: HeaderMap, Query(query): Query<SearchQuery>) -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let owner = visible_owner(&state, &headers).await?;
+    if query.q.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "The search query `q` is empty".to_string()));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, 100);
+    search_messages(database(&state)?, &query.q, owner.as_deref(), limit).await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// GET /sessions - the signed-in user's sessions, newest first
+async fn sessions(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<Vec<SessionRecord>>, ApiError> {
+    let owner = signed_in(&state, &headers).await?;
+    list_owned_sessions(database(&state)?, &owner).await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+#[derive(Serialize)]
+struct CreatedSession {
+    id: Uuid,
+}
+
+// POST /sessions - start a session owned by the signed-in user
+async fn create_session(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<CreatedSession>, ApiError> {
+    let owner = signed_in(&state, &headers).await?;
+    let id = Uuid::new_v4();
+    create_owned_session(database(&state)?, id, &owner).await.map_err(internal_error)?;
+    Ok(Json(CreatedSession { id }))
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct HistoryMessage {
+    position: i64,
+    role: String,
+    content: String,
+}
+
+// GET /sessions/{id}/messages?limit=<n> - latest messages of a session the caller owns
+async fn session_messages(State(state): State<AppState>, headers: HeaderMap, Path(id): Path<Uuid>, Query(query): Query<HistoryQuery>) -> Result<Json<Vec<HistoryMessage>>, ApiError> {
+    let owner = signed_in(&state, &headers).await?;
+    let pool = database(&state)?;
+    // Other owners' sessions are reported as missing rather than forbidden
+    if !session_owned_by(pool, id, &owner).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, format!("Session {} not found", id)));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, 1000);
+    let messages = load_session_messages(pool, id, 0, limit).await.map_err(internal_error)?;
+    Ok(Json(messages.into_iter()
+        .map(|(position, role, content)| HistoryMessage { position, role, content })
+        .collect()))
+}
+
+#[derive(Deserialize)]
+struct ChatRequest {
+    content: String,
+    /// Images sent with the message, as Anthropic image sources
+    #[serde(default)]
+    images: Vec<ImageSource>,
+}
+
+#[derive(Serialize)]
+struct ChatResponse {
+    reply: String,
+    /// Outcomes of the session's earlier transactions, alerts, and reminders
+    updates: Vec<String>,
+}
+
+// POST /sessions/{id}/messages - send a message, with any images, to a session the caller owns
+// and wait for the agent's reply
+async fn send_message(State(state): State<AppState>, headers: HeaderMap, Path(id): Path<Uuid>, Json(request): Json<ChatRequest>) -> Result<Json<ChatResponse>, ApiError> {
+    let owner = signed_in(&state, &headers).await?;
+    let pool = database(&state)?;
+    if !session_owned_by(pool, id, &owner).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, format!("Session {} not found", id)));
+    }
+    let ctx = user_context(&state, pool, id, &owner).await?;
+    answer(&state, pool, id, &request, &ctx).await.map(Json)
+}
+
+// Answer a message in a session, storing both, with the updates that came in meanwhile
+async fn answer(state: &AppState, pool: &Pool<Postgres>, id: Uuid, request: &ChatRequest, ctx: &ToolContext) -> Result<ChatResponse, ApiError> {
+    if request.content.trim().is_empty() && request.images.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "The message has no content or images".to_string()));
+    }
+    for image in &request.images {
+        images::check(image).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    let personality = state.personality.current();
+    let model = model_for(&state.config.anthropic, Some(&personality));
+    let mut conversation = Conversation::load(ctx.store.as_ref(), id, CHAT_HISTORY_LIMIT).await.map_err(internal_error)?;
+    let message_id = ctx.store.save_message(id, "user", &request.content, &model, None).await.map_err(internal_error)?;
+    let ctx = &ToolContext { message_id: Some(message_id), ..ctx.clone() };
+    if let Err(e) = memory::remember(pool, &state.config.memory, id, "user", &request.content).await {
+        tracing::warn!(error = %e, "Failed to store user message in memory");
+    }
+
+    if let Err(e) = conversation.compact(ctx).await {
+        tracing::warn!(error = %e, "Failed to compact session history");
+    }
+    let meter = TurnMeter::start(ctx);
+    let reply = call_anthropic_with_tools(&state.config.anthropic, &request.content, &request.images, Some(&personality), &conversation, ctx).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    ctx.store.save_message(id, "assistant", &reply, &model, Some(meter.stats(ctx))).await.map_err(internal_error)?;
+    if let Err(e) = memory::remember(pool, &state.config.memory, id, "assistant", &reply).await {
+        tracing::warn!(error = %e, "Failed to store assistant message in memory");
+    }
+    let mut updates = tracker::take_updates(Some(pool), id).await;
+    updates.extend(gas::take_updates(id));
+    updates.extend(alerts::take_updates(Some(pool), id).await);
+    updates.extend(reminders::take_updates(Some(pool), id).await);
+    Ok(ChatResponse { reply, updates })
+}
+
+#[derive(Deserialize)]
+struct SocketQuery {
+    session: Uuid,
+    /// Bearer token, for browsers, which can't set headers on a WebSocket
+    token: Option<String>,
+}
+
+/// What a WebSocket client sends
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    /// A message to answer, after the ones before it
+    Message {
+        content: String,
+        #[serde(default)]
+        images: Vec<ImageSource>,
+    },
+    /// Stop the reply being written
+    Cancel,
+}
+
+// GET /ws?session=<uuid>&token=<token> - chat in a session the caller owns over a WebSocket,
+// with the answer's text and tool calls streamed as they happen
+async fn chat_socket(State(state): State<AppState>, headers: HeaderMap, Query(query): Query<SocketQuery>, upgrade: WebSocketUpgrade) -> Result<Response, ApiError> {
+    let owner = match &query.token {
+        Some(token) => token_owner(&state, token).await?,
+        None => signed_in(&state, &headers).await?,
+    };
+    let pool = database(&state)?.clone();
+    if !session_owned_by(&pool, query.session, &owner).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, format!("Session {} not found", query.session)));
+    }
+    Ok(upgrade.on_upgrade(move |socket| run_socket(state, pool, query.session, owner, socket)))
+}
+
+// Answer the socket's messages one at a time, in the order they arrive. Messages sent while a
+// reply is being written wait their turn.
+async fn run_socket(state: AppState, pool: Pool<Postgres>, session: Uuid, owner: String, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    let (frames, mut outgoing) = mpsc::unbounded_channel::<serde_json::Value>();
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = outgoing.recv().await {
+            if sink.send(WsMessage::Text(frame.to_string().into())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let (queue, mut messages) = mpsc::unbounded_channel::<ChatRequest>();
+    let cancel = Arc::new(Notify::new());
+    let reader = tokio::spawn({
+        let (frames, cancel) = (frames.clone(), cancel.clone());
+        async move {
+            while let Some(Ok(message)) = stream.next().await {
+                let WsMessage::Text(text) = message else { continue };
+                match serde_json::from_str::<ClientFrame>(&text) {
+                    Ok(ClientFrame::Message { content, images }) => {
+                        let _ = queue.send(ChatRequest { content, images });
+                    },
+                    Ok(ClientFrame::Cancel) => cancel.notify_waiters(),
+                    Err(e) => {
+                        let _ = frames.send(serde_json::json!({ "type": "error", "message": format!("Invalid frame: {}", e) }));
+                    },
+                }
+            }
+        }
+    });
+
+    let deltas = Arc::new(Mutex::new(Vec::new()));
+    let activity = Arc::new(Mutex::new(Vec::new()));
+    let flush = || {
+        let text: String = deltas.lock().unwrap().drain(..).collect();
+        if !text.is_empty() {
+            let _ = frames.send(serde_json::json!({ "type": "delta", "text": text }));
+        }
+        for line in activity.lock().unwrap().drain(..) {
+            let _ = frames.send(serde_json::json!({ "type": "tool", "activity": line }));
+        }
+    };
+    while let Some(request) = messages.recv().await {
+        let ctx = match user_context(&state, &pool, session, &owner).await {
+            Ok(ctx) => ToolContext { activity: Some(activity.clone()), deltas: Some(deltas.clone()), ..ctx },
+            Err((_, message)) => {
+                let _ = frames.send(serde_json::json!({ "type": "error", "message": message }));
+                continue;
+            },
+        };
+        let turn = answer(&state, &pool, session, &request, &ctx);
+        tokio::pin!(turn);
+        let mut ticker = tokio::time::interval(STREAM_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                result = &mut turn => break Some(result),
+                _ = cancel.notified() => break None,
+                _ = ticker.tick() => flush(),
+            }
+        };
+        flush();
+        let _ = frames.send(match result {
+            Some(Ok(response)) => serde_json::json!({ "type": "done", "reply": response.reply, "updates": response.updates }),
+            Some(Err((_, message))) => serde_json::json!({ "type": "error", "message": message }),
+            None => serde_json::json!({ "type": "cancelled" }),
+        });
+    }
+    drop(frames);
+    let _ = reader.await;
+    let _ = writer.await;
+}
+
+// GET /wallets - wallets created in the signed-in user's sessions
+async fn wallets(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<Vec<WalletRecord>>, ApiError> {
+    let owner = signed_in(&state, &headers).await?;
+    list_owned_wallets(database(&state)?, &owner).await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// PUT /users/{address}/policy - give a user a transaction policy of their own, in the
+// policy.json format, or put them back on the deployment's with `null`. Admins only.
+async fn set_policy(State(state): State<AppState>, headers: HeaderMap, Path(address): Path<String>, Json(policy): Json<serde_json::Value>) -> Result<StatusCode, ApiError> {
+    let admin = signed_in(&state, &headers).await?;
+    let pool = database(&state)?;
+    if user_role(&state, pool, &admin).await?.0 != Role::Admin {
+        return Err((StatusCode::FORBIDDEN, "Only admins can change users' policies".to_string()));
+    }
+    let address = user_address(&address).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let policy = match policy {
+        serde_json::Value::Null => None,
+        policy => {
+            Policy::deserialize(&policy).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid policy: {}", e)))?;
+            Some(policy)
+        },
+    };
+    if !set_user_policy(pool, &address, policy.as_ref()).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, format!("{} is not a user", address)));
+    }
+    tracing::info!(admin, user = address, own_policy = policy.is_some(), "Changed a user's transaction policy");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/auth/nonce", get(auth_nonce))
+        .route("/auth/verify", post(auth_verify))
+        .route("/audit", get(audit))
+        .route("/history/search", get(search_history))
+        .route("/sessions", get(sessions).post(create_session))
+        .route("/sessions/{id}/messages", get(session_messages).post(send_message))
+        .route("/users/{address}/policy", put(set_policy))
+        .route("/wallets", get(wallets))
+        .route("/ws", get(chat_socket))
+        .with_state(state)
+}
+
+/// Serve the HTTP API on the given port until the process is stopped. Chat replies come from
+/// `personality`.
+pub async fn serve(config: Arc<Config>, policy: Arc<PolicyEngine>, pool: Option<Pool<Postgres>>, personality: LivePersonality, port: u16) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    tracing::info!(addr = %listener.local_addr()?, "HTTP API listening");
+    let state = AppState { pool, config, policy, personality: Arc::new(personality), auth: Arc::default(), policies: Arc::default() };
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{create_owned_session, save_message};
+    use crate::test_support::database;
+
+    fn state(config: Config, pool: Option<Pool<Postgres>>) -> AppState {
+        let personality = LivePersonality::watch(std::path::Path::new("assets/personalities/aero.json")).unwrap();
+        AppState {
+            pool,
+            config: Arc::new(config),
+            policy: Arc::new(PolicyEngine::new(Policy::default())),
+            personality: Arc::new(personality),
+            auth: Arc::default(),
+            policies: Arc::default(),
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    async fn sign_in(state: &AppState, address: Address) -> HeaderMap {
+        bearer(&issue_token(state, address).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn shows_the_audit_log_only_to_signed_in_callers() {
+        let state = state(Config::default(), None);
+        let audit_query = || Query(AuditQuery { session: None, limit: None });
+        let (status, _) = audit(State(state.clone()), HeaderMap::new(), audit_query()).await.unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = audit(State(state.clone()), bearer("unknown"), audit_query()).await.unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        // Signed in, the caller gets as far as the database
+        let (status, _) = audit(State(state.clone()), sign_in(&state, Address::random()).await, audit_query()).await.unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn searches_history_only_for_signed_in_callers() {
+        let search_query = Query(SearchQuery { q: "swap".to_string(), limit: None });
+        let (status, _) = search_history(State(state(Config::default(), None)), HeaderMap::new(), search_query).await.unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // A nonce signs in once; a replayed message, or one for another chain, is turned away
+    #[tokio::test]
+    async fn signs_in_once_per_nonce() {
+        use ethers::signers::{LocalWallet, Signer};
+        let state = state(Config::default(), None);
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let signed = |chain_id: u64, nonce: String| {
+            let message = format!("localhost:3000 wants you to sign in with your Ethereum account:\n{}\n\n\
+                                   URI: http://localhost:3000\nVersion: 1\nChain ID: {}\nNonce: {}\nIssued At: {}",
+                                  ethers::utils::to_checksum(&wallet.address(), None), chain_id, nonce, chrono::Utc::now().to_rfc3339());
+            let wallet = wallet.clone();
+            async move {
+                let signature = wallet.sign_message(&message).await.unwrap();
+                Json(VerifyRequest { message, signature: format!("0x{}", signature) })
+            }
+        };
+
+        let Json(NonceResponse { nonce }) = auth_nonce(State(state.clone())).await.unwrap();
+        let Json(signed_in) = auth_verify(State(state.clone()), signed(11155111, nonce.clone()).await).await.unwrap();
+        assert_eq!(signed_in.address, format!("{:?}", wallet.address()));
+        assert_eq!(token_owner(&state, &signed_in.token).await.unwrap(), signed_in.address);
+        let (status, error) = auth_verify(State(state.clone()), signed(11155111, nonce).await).await.err().unwrap();
+        assert_eq!((status, error.as_str()), (StatusCode::UNAUTHORIZED, "Unknown or expired nonce; request a new one from /auth/nonce"));
+
+        let Json(NonceResponse { nonce }) = auth_nonce(State(state.clone())).await.unwrap();
+        let (status, error) = auth_verify(State(state.clone()), signed(1, nonce).await).await.err().unwrap();
+        assert_eq!((status, error.as_str()), (StatusCode::UNAUTHORIZED, "Message is for chain 1, not 11155111"));
+    }
+
+    // The nonces and tokens are in the database, so the server still accepts them after a
+    // restart, as does every other replica
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn keeps_sign_ins_across_restarts() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let address = Address::random();
+        save_user(&pool, &format!("{:?}", address), None, "viewer").await.unwrap();
+        let before = state(config.clone(), Some(pool.clone()));
+        let Json(NonceResponse { nonce }) = auth_nonce(State(before.clone())).await.unwrap();
+        let token = issue_token(&before, address).await.unwrap();
+
+        let after = state(config.clone(), Some(pool.clone()));
+        assert!(take_nonce(&after, &nonce).await.unwrap());
+        assert!(!take_nonce(&after, &nonce).await.unwrap(), "a nonce is used once");
+        assert_eq!(token_owner(&after, &token).await.unwrap(), format!("{:?}", address));
+        assert!(token_owner(&after, "unknown").await.is_err());
+
+        config.server.auth_ttl_secs = 0;
+        let expiring = state(config, Some(pool));
+        let expired = issue_token(&expiring, address).await.unwrap();
+        assert!(token_owner(&expiring, &expired).await.is_err());
+    }
+
+    // With history stored, an unauthenticated search still finds nothing
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn finds_no_history_for_unauthenticated_searches() {
+        let search_query = || Query(SearchQuery { q: "swap".to_string(), limit: None });
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let session = Uuid::new_v4();
+        create_owned_session(&pool, session, &format!("{:?}", Address::random())).await.unwrap();
+        save_message(&pool, session, "user", "Swap 1 ETH for USDC", "claude-test", None).await.unwrap();
+        let state = state(config, Some(pool));
+        let (status, _) = search_history(State(state.clone()), HeaderMap::new(), search_query()).await.unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let viewer = Address::random();
+        save_user(state.pool.as_ref().unwrap(), &format!("{:?}", viewer), None, "viewer").await.unwrap();
+        let Json(hits) = search_history(State(state.clone()), sign_in(&state, viewer).await, search_query()).await.unwrap();
+        assert!(hits.iter().all(|hit| hit.session_id != session));
+    }
+}
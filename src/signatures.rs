@@ -0,0 +1,236 @@
+// Function and event signatures from the Sourcify and 4byte.directory databases, for calldata
+// and logs of contracts whose ABI isn't in `abi_dir`. A selector or topic may match several
+// signatures; the ones whose decoding re-encodes to exactly the same bytes are kept. Signatures
+// found are saved to `cache_path`, since a hash always stands for the same text.
+
+use crate::config::SignaturesConfig;
+use ethers::abi::{Event, EventParam, Function, HumanReadableParser, ParamType};
+use lazy_static::lazy_static;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    // Signatures looked up this run by hash, misses included, so each database is asked about a
+    // hash at most once
+    static ref FOUND: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+}
+
+fn load(path: &str) -> HashMap<String, Vec<String>> {
+    std::fs::read_to_string(path).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &str, hash: &str, signatures: &[String]) -> anyhow::Result<()> {
+    let mut cached = load(path);
+    cached.insert(hash.to_string(), signatures.to_vec());
+    std::fs::write(path, serde_json::to_string_pretty(&cached)?)?;
+    Ok(())
+}
+
+fn client() -> anyhow::Result<Client> {
+    Ok(Client::builder().timeout(REQUEST_TIMEOUT).build()?)
+}
+
+// Selectors are 4 bytes, event topics 32
+fn is_event(hash: &str) -> bool {
+    hash.len() == 66
+}
+
+async fn sourcify(config: &SignaturesConfig, hash: &str) -> anyhow::Result<Vec<String>> {
+    let kind = if is_event(hash) { "event" } else { "function" };
+    let body: serde_json::Value = client()?.get(&config.sourcify_url)
+        .query(&[(kind, hash), ("filter", "false")])
+        .send().await?
+        .error_for_status()?
+        .json().await?;
+    let matches = body["result"][kind][hash].as_array().cloned().unwrap_or_default();
+    // Signatures flagged as spam come last
+    let (mut found, flagged): (Vec<_>, Vec<_>) = matches.iter()
+        .filter_map(|entry| Some((entry["name"].as_str()?.to_string(), entry["filtered"].as_bool() == Some(true))))
+        .partition(|(_, filtered)| !filtered);
+    found.extend(flagged);
+    Ok(found.into_iter().map(|(name, _)| name).collect())
+}
+
+async fn fourbyte(config: &SignaturesConfig, hash: &str) -> anyhow::Result<Vec<String>> {
+    let path = if is_event(hash) { "event-signatures" } else { "signatures" };
+    let body: serde_json::Value = client()?.get(format!("{}/{}/", config.fourbyte_url.trim_end_matches('/'), path))
+        .query(&[("hex_signature", hash)])
+        .send().await?
+        .error_for_status()?
+        .json().await?;
+    // The signature registered first is the most likely to be the real one
+    let mut results = body["results"].as_array().cloned().unwrap_or_default();
+    results.sort_by_key(|result| result["id"].as_u64().unwrap_or(u64::MAX));
+    Ok(results.iter().filter_map(|result| result["text_signature"].as_str().map(str::to_string)).collect())
+}
+
+/// Text signatures for a selector (`0xa9059cbb`) or event topic, such as
+/// `transfer(address,uint256)`, most likely first
+pub async fn lookup(config: &SignaturesConfig, hash: &str) -> Vec<String> {
+    let hash = hash.to_lowercase();
+    if let Some(found) = FOUND.lock().unwrap().get(&hash) {
+        return found.clone();
+    }
+    if let Some(found) = load(&config.cache_path).remove(&hash) {
+        FOUND.lock().unwrap().insert(hash, found.clone());
+        return found;
+    }
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    let from_sourcify = sourcify(config, &hash).await;
+    let found = match &from_sourcify {
+        Ok(found) if !found.is_empty() => Ok(found.clone()),
+        _ => fourbyte(config, &hash).await,
+    };
+    match (found, from_sourcify) {
+        (Ok(found), _) if !found.is_empty() => {
+            if let Err(e) = save(&config.cache_path, &hash, &found) {
+                tracing::warn!(error = %e, path = %config.cache_path, "Could not save looked-up signatures");
+            }
+            FOUND.lock().unwrap().insert(hash, found.clone());
+            found
+        },
+        // Neither database knows it; remembered for this run only, as it may be added later
+        (Ok(_), Ok(_)) => {
+            FOUND.lock().unwrap().insert(hash, Vec::new());
+            Vec::new()
+        },
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::warn!(error = %e, hash = %hash, "Could not look up signature");
+            Vec::new()
+        },
+    }
+}
+
+/// Functions a selector may stand for, by their signatures in the databases
+pub async fn functions(config: &SignaturesConfig, selector: [u8; 4]) -> Vec<Function> {
+    lookup(config, &format!("0x{}", hex::encode(selector))).await.iter()
+        .filter_map(|signature| HumanReadableParser::parse_function(&format!("function {}", signature)).ok())
+        .filter(|function| function.short_signature() == selector)
+        .collect()
+}
+
+// Whether an argument of this type can be indexed and still be decoded from its topic
+fn value_type(kind: &ParamType) -> bool {
+    matches!(kind, ParamType::Address | ParamType::Uint(_) | ParamType::Int(_) | ParamType::Bool | ParamType::FixedBytes(_))
+}
+
+// Positions of `count` of the indexed-capable inputs, earlier ones first, which is how events
+// are usually declared
+fn indexed_choices(candidates: &[usize], count: usize) -> Vec<Vec<usize>> {
+    if count == 0 {
+        return vec![Vec::new()];
+    }
+    let mut choices = Vec::new();
+    for (i, first) in candidates.iter().enumerate() {
+        for mut rest in indexed_choices(&candidates[i + 1..], count - 1) {
+            rest.insert(0, *first);
+            choices.push(rest);
+        }
+    }
+    choices
+}
+
+/// Events a topic may stand for. Text signatures don't say which arguments are indexed, so
+/// there is one event for each way up to three of them can be; logs pick the one their topics
+/// and data fit. Arguments are named by position (`arg0`, `arg1`, ...).
+pub async fn events(config: &SignaturesConfig, topic: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    for signature in lookup(config, topic).await {
+        let Ok(event) = HumanReadableParser::parse_event(&format!("event {}", signature)) else { continue };
+        let candidates: Vec<usize> = (0..event.inputs.len()).filter(|i| value_type(&event.inputs[*i].kind)).collect();
+        for count in 0..=candidates.len().min(3) {
+            for indexed in indexed_choices(&candidates, count) {
+                events.push(Event {
+                    inputs: event.inputs.iter().enumerate()
+                        .map(|(i, input)| EventParam { name: format!("arg{}", i), kind: input.kind.clone(), indexed: indexed.contains(&i) })
+                        .collect(),
+                    ..event.clone()
+                });
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chain_query::{format_transaction, Decoders};
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn decodes_unknown_selectors_and_topics_from_signature_databases() {
+        use ethers::abi::Token;
+        use ethers::types::{Address, Log, Transaction, TransactionReceipt, H256, U256};
+
+        // A Sourcify-style database that knows one function and one event, and counts lookups
+        let lookups = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = lookups.clone();
+        let database = axum::Router::new().route("/lookup", axum::routing::get(move |axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                let known = HashMap::from([
+                    ("function", ("0x".to_string() + &hex::encode(ethers::utils::id("stake(uint256,address)")), "stake(uint256,address)")),
+                    ("event", (format!("{:?}", H256::from(ethers::utils::keccak256("Staked(address,uint256)"))), "Staked(address,uint256)")),
+                ]);
+                let mut result = json!({ "function": {}, "event": {} });
+                for (kind, (hash, name)) in known {
+                    if query.get(kind) == Some(&hash) {
+                        result[kind][hash] = json!([{ "name": name, "filtered": false }]);
+                    }
+                }
+                axum::Json(json!({ "ok": true, "result": result }))
+            }
+        }));
+        let url = format!("{}/lookup", serve(database).await);
+
+        let cache_path = std::env::temp_dir().join(format!("signatures-{}.json", std::process::id()));
+        let mut config = Config::default();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        config.signatures.sourcify_url = url;
+        config.signatures.fourbyte_url = "http://127.0.0.1:9".to_string();
+        config.signatures.cache_path = cache_path.to_string_lossy().into_owned();
+        let ctx = context(config.clone());
+
+        let staker = Address::random();
+        let mut input = ethers::utils::id("stake(uint256,address)").to_vec();
+        input.extend(ethers::abi::encode(&[Token::Uint(U256::from(5)), Token::Address(staker)]));
+        let decoded = execute_tool(&ctx, "chain_query", &json!({ "operation": "decode", "data": format!("0x{}", hex::encode(&input)) })).await.unwrap();
+        assert!(decoded.contains(&format!("Call: stake(uint256,address)\nArguments: 5, {:?}", staker)), "{}", decoded);
+        let selector = execute_tool(&ctx, "chain_query", &json!({ "operation": "decode", "data": "0xa9059cbb" })).await.unwrap();
+        assert!(selector.contains("transfer(address,uint256)"), "{}", selector);
+
+        // Which argument of a looked-up event is indexed is worked out from the log
+        let log = Log {
+            topics: vec![ethers::utils::keccak256("Staked(address,uint256)").into(), H256::from(staker)],
+            data: ethers::abi::encode(&[Token::Uint(U256::from(5))]).into(),
+            ..Default::default()
+        };
+        let receipt = TransactionReceipt { logs: vec![log.clone()], ..Default::default() };
+        let tx = Transaction { to: Some(Address::random()), input: input.clone().into(), ..Default::default() };
+        let decoders = Decoders::for_transaction(&config, Some(&tx.input), &receipt.logs).await;
+        let breakdown = format_transaction(&tx, Some(&receipt), &decoders);
+        assert!(breakdown.contains(&format!("1. Staked on {:?}: arg0={:?}, arg1=5", log.address, staker)), "{}", breakdown);
+
+        // Signatures found are saved to the cache file and not looked up again
+        let asked = lookups.load(std::sync::atomic::Ordering::SeqCst);
+        let cached = std::fs::read_to_string(&cache_path).unwrap();
+        assert!(cached.contains("stake(uint256,address)") && cached.contains("Staked(address,uint256)"), "{}", cached);
+        Decoders::for_transaction(&config, Some(&tx.input), &receipt.logs).await;
+        assert_eq!(lookups.load(std::sync::atomic::Ordering::SeqCst), asked);
+        std::fs::remove_file(cache_path).ok();
+    }
+}
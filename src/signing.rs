@@ -0,0 +1,113 @@
+use crate::wallet::AgentSigner;
+use ethers::prelude::*;
+use ethers::types::transaction::eip712::{Eip712, TypedData};
+use std::str::FromStr;
+
+/// A payload to sign or verify: an EIP-191 personal message or EIP-712 typed data
+pub enum SignRequest {
+    Message(Vec<u8>),
+    TypedData(Box<TypedData>),
+}
+
+/// Bytes of a personal message. `0x`-prefixed hex is signed as raw bytes, like wallets do;
+/// anything else is signed as UTF-8 text.
+pub fn message_bytes(message: &str) -> Vec<u8> {
+    match message.strip_prefix("0x").map(hex::decode) {
+        Some(Ok(bytes)) => bytes,
+        _ => message.as_bytes().to_vec(),
+    }
+}
+
+/// Parse EIP-712 typed data given as a JSON object or as a string holding one, in the
+/// `eth_signTypedData_v4` format (`types`, `primaryType`, `domain`, `message`)
+pub fn parse_typed_data(value: &serde_json::Value) -> anyhow::Result<TypedData> {
+    let value = match value {
+        serde_json::Value::String(text) => serde_json::from_str(text)
+            .map_err(|e| anyhow::anyhow!("Typed data is not valid JSON: {}", e))?,
+        other => other.clone(),
+    };
+    let typed_data: TypedData = serde_json::from_value(value)
+        .map_err(|e| anyhow::anyhow!("Invalid EIP-712 typed data: {}", e))?;
+    // Encoding checks that the message matches the declared types
+    typed_data.encode_eip712()
+        .map_err(|e| anyhow::anyhow!("Could not encode typed data: {}", e))?;
+    Ok(typed_data)
+}
+
+/// What the user is asked to sign, so it can be confirmed before signing
+pub fn describe_typed_data(typed_data: &TypedData) -> String {
+    let domain = &typed_data.domain;
+    let mut parts = Vec::new();
+    if let Some(name) = &domain.name {
+        parts.push(name.clone());
+    }
+    if let Some(version) = &domain.version {
+        parts.push(format!("version {}", version));
+    }
+    if let Some(chain_id) = domain.chain_id {
+        parts.push(format!("chain {}", chain_id));
+    }
+    if let Some(contract) = domain.verifying_contract {
+        parts.push(format!("contract {:?}", contract));
+    }
+    let mut lines = vec![
+        format!("Domain: {}", if parts.is_empty() { "(empty)".to_string() } else { parts.join(", ") }),
+        format!("Primary Type: {}", typed_data.primary_type),
+        format!("Message: {}", serde_json::to_string(&typed_data.message).unwrap_or_default()),
+    ];
+    // Permits let the spender move tokens later without any transaction from the owner
+    if typed_data.primary_type.contains("Permit") {
+        lines.push("Warning: this is a token permit; the signature authorizes the spender to move tokens".to_string());
+    }
+    lines.join("\n")
+}
+
+/// The verifying contract of typed data and every spender its message names, at any depth (as
+/// in Permit2's `PermitSingle`); a permit or order signature hands these addresses power over
+/// the signer's tokens
+pub fn typed_data_parties(typed_data: &TypedData) -> Vec<Address> {
+    fn spenders(value: &serde_json::Value, found: &mut Vec<Address>) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                for (name, field) in fields {
+                    match field.as_str().and_then(|text| Address::from_str(text).ok()) {
+                        Some(address) if name == "spender" => found.push(address),
+                        _ => spenders(field, found),
+                    }
+                }
+            },
+            serde_json::Value::Array(items) => items.iter().for_each(|item| spenders(item, found)),
+            _ => {},
+        }
+    }
+
+    let mut parties: Vec<Address> = typed_data.domain.verifying_contract.into_iter().collect();
+    spenders(&serde_json::to_value(&typed_data.message).unwrap_or_default(), &mut parties);
+    parties
+}
+
+pub fn parse_signature(signature: &str) -> anyhow::Result<Signature> {
+    Signature::from_str(signature.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))
+}
+
+/// Sign with a local key, an HD wallet account, or a Ledger
+pub async fn sign(wallet: &AgentSigner, request: &SignRequest) -> anyhow::Result<Signature> {
+    match request {
+        SignRequest::Message(bytes) => Ok(wallet.sign_message(bytes).await?),
+        SignRequest::TypedData(typed_data) => Ok(wallet.sign_typed_data(typed_data.as_ref()).await?),
+    }
+}
+
+/// Recover the address that produced `signature` over the payload
+pub fn recover(request: &SignRequest, signature: &Signature) -> anyhow::Result<Address> {
+    let address = match request {
+        SignRequest::Message(bytes) => signature.recover(bytes.as_slice())?,
+        SignRequest::TypedData(typed_data) => {
+            let digest = typed_data.encode_eip712()
+                .map_err(|e| anyhow::anyhow!("Could not encode typed data: {}", e))?;
+            signature.recover(H256::from(digest))?
+        },
+    };
+    Ok(address)
+}
@@ -0,0 +1,108 @@
+use crate::contracts;
+use crate::provider::EthProvider;
+use crate::units;
+use ethers::abi::Abi;
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::DiffMode;
+
+// Gas limit for transactions broadcast despite a failed simulation, when gas can't be estimated
+pub const FORCED_GAS_LIMIT: u64 = 500_000;
+
+/// Change of an account's ETH balance caused by a simulated transaction (gas included)
+#[derive(Debug, Clone)]
+pub struct BalanceChange {
+    pub address: Address,
+    pub before: U256,
+    pub after: U256,
+}
+
+/// Outcome of running a transaction against the latest block without broadcasting it
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Return data of the call
+    pub output: Bytes,
+    /// Why the call or gas estimation failed, decoded when possible
+    pub revert: Option<String>,
+    pub gas_estimate: Option<U256>,
+    /// ETH balance changes from `debug_traceCall`, or None when the node doesn't support it
+    pub balance_changes: Option<Vec<BalanceChange>>,
+}
+
+fn revert_data(error: &ProviderError) -> Option<Bytes> {
+    RpcError::as_error_response(error).and_then(|response| response.as_revert_data())
+}
+
+/// Describe why a call failed, decoding the revert reason when the node returned one
+pub fn describe_revert(abi: Option<&Abi>, error: &ProviderError) -> String {
+    match revert_data(error) {
+        Some(data) => contracts::decode_revert(abi, &data),
+        None => error.to_string(),
+    }
+}
+
+// Balance changes from a prestateTracer diff; `post` only lists the fields that changed
+async fn trace_balance_changes(provider: &EthProvider, tx: &TypedTransaction) -> anyhow::Result<Vec<BalanceChange>> {
+    let options = serde_json::json!({ "tracer": "prestateTracer", "tracerConfig": { "diffMode": true } });
+    let trace: serde_json::Value = provider.request("debug_traceCall", (tx, "latest", options)).await?;
+    let diff: DiffMode = serde_json::from_value(trace)?;
+    Ok(diff.post.iter()
+        .filter_map(|(address, state)| {
+            let after = state.balance?;
+            let before = diff.pre.get(address).and_then(|pre| pre.balance).unwrap_or_default();
+            (before != after).then_some(BalanceChange { address: *address, before, after })
+        })
+        .collect())
+}
+
+/// Run the exact transaction through `eth_call` and `eth_estimateGas`, and through
+/// `debug_traceCall` for balance changes when the node supports it
+pub async fn simulate(provider: &EthProvider, tx: &TypedTransaction, abi: Option<&Abi>) -> Simulation {
+    let (output, mut revert) = match provider.call(tx, None).await {
+        Ok(output) => (output, None),
+        Err(e) => (Bytes::default(), Some(describe_revert(abi, &e))),
+    };
+    let gas_estimate = match provider.estimate_gas(tx, None).await {
+        Ok(estimate) => Some(estimate),
+        Err(e) => {
+            revert.get_or_insert_with(|| describe_revert(abi, &e));
+            None
+        },
+    };
+    let balance_changes = match trace_balance_changes(provider, tx).await {
+        Ok(changes) => Some(changes),
+        Err(e) => {
+            tracing::debug!(error = %e, "debug_traceCall unavailable; skipping balance changes");
+            None
+        },
+    };
+    Simulation { output, revert, gas_estimate, balance_changes }
+}
+
+impl Simulation {
+    /// Human-readable summary; `labels` names known addresses such as the sender
+    pub fn summary(&self, labels: &[(Address, &str)]) -> String {
+        let mut lines = vec![match &self.revert {
+            Some(reason) => format!("Simulation: reverts ({})", reason),
+            None => format!("Simulation: succeeds (gas estimate {})", self.gas_estimate.unwrap_or_default()),
+        }];
+        if let Some(changes) = &self.balance_changes
+            && !changes.is_empty()
+        {
+            lines.push("Balance Changes:".to_string());
+            for change in changes {
+                let delta = if change.after >= change.before {
+                    format!("+{}", units::format_eth(change.after - change.before))
+                } else {
+                    format!("-{}", units::format_eth(change.before - change.after))
+                };
+                let label = labels.iter()
+                    .find(|(address, _)| *address == change.address)
+                    .map(|(_, label)| format!(" ({})", label))
+                    .unwrap_or_default();
+                lines.push(format!("- {:?}{}: {} ETH", change.address, label, delta));
+            }
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use ethers::types::Address;
+use ethers::utils::to_checksum;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::str::FromStr;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+
+/// The fields of a Sign-In with Ethereum (EIP-4361) message this server checks. The optional
+/// statement, request ID, and resources are not kept.
+#[derive(Debug, Clone)]
+pub struct SiweMessage {
+    pub domain: String,
+    pub address: Address,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub expiration_time: Option<DateTime<FixedOffset>>,
+    pub not_before: Option<DateTime<FixedOffset>>,
+}
+
+/// Random alphanumeric nonce for a sign-in (EIP-4361 requires at least 8 characters)
+pub fn generate_nonce() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(17).map(char::from).collect()
+}
+
+fn parse_time(field: &str, value: &str) -> anyhow::Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).map_err(|e| anyhow::anyhow!("Invalid {}: {}", field, e))
+}
+
+impl SiweMessage {
+    /// Parse the plain-text message a wallet signed
+    pub fn parse(message: &str) -> anyhow::Result<Self> {
+        let mut lines = message.lines();
+        let domain = lines.next()
+            .and_then(|line| line.strip_suffix(HEADER_SUFFIX))
+            .filter(|domain| !domain.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Not a Sign-In with Ethereum message"))?
+            .to_string();
+        let address_text = lines.next().unwrap_or("").trim();
+        let address = Address::from_str(address_text)
+            .map_err(|_| anyhow::anyhow!("Invalid address {}", address_text))?;
+        if to_checksum(&address, None) != address_text {
+            return Err(anyhow::anyhow!("Address must be EIP-55 checksummed"));
+        }
+
+        // `Key: value` fields start at the URI, after the optional statement, and end at the resources
+        let mut fields = Vec::new();
+        for line in lines {
+            if line == "Resources:" {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(": ")
+                && (key == "URI" || !fields.is_empty())
+            {
+                fields.push((key, value.trim()));
+            }
+        }
+        let field = |name: &str| fields.iter().find(|(key, _)| *key == name).map(|(_, value)| *value);
+        let required = |name: &str| field(name).ok_or_else(|| anyhow::anyhow!("Missing {} in the message", name));
+
+        // Required by the format, though only the nonce and expiry bound the message's validity
+        parse_time("Issued At", required("Issued At")?)?;
+        Ok(SiweMessage {
+            domain,
+            address,
+            uri: required("URI")?.to_string(),
+            version: required("Version")?.to_string(),
+            chain_id: required("Chain ID")?.parse().map_err(|_| anyhow::anyhow!("Invalid Chain ID"))?,
+            nonce: required("Nonce")?.to_string(),
+            expiration_time: field("Expiration Time").map(|value| parse_time("Expiration Time", value)).transpose()?,
+            not_before: field("Not Before").map(|value| parse_time("Not Before", value)).transpose()?,
+        })
+    }
+
+    /// Check the fields that don't depend on server state: the domain the user signed in to,
+    /// the chain, the message version, and its validity window
+    pub fn validate(&self, domain: &str, chain_id: u64, now: DateTime<Utc>) -> anyhow::Result<()> {
+        if self.domain != domain {
+            return Err(anyhow::anyhow!("Message is for {}, not {}", self.domain, domain));
+        }
+        if self.chain_id != chain_id {
+            return Err(anyhow::anyhow!("Message is for chain {}, not {}", self.chain_id, chain_id));
+        }
+        if self.version != "1" {
+            return Err(anyhow::anyhow!("Unsupported message version {}", self.version));
+        }
+        if let Some(expiration_time) = self.expiration_time
+            && expiration_time <= now
+        {
+            return Err(anyhow::anyhow!("Message expired at {}", expiration_time));
+        }
+        if let Some(not_before) = self.not_before
+            && not_before > now
+        {
+            return Err(anyhow::anyhow!("Message is not valid before {}", not_before));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    // A message for agent.example on chain 1, with the optional fields given as `extra` lines
+    fn message(extra: &str) -> String {
+        format!("agent.example wants you to sign in with your Ethereum account:\n{}\n\nSign in to the agent.\n\n\
+                 URI: https://agent.example/login\nVersion: 1\nChain ID: 1\nNonce: k2Lp9fGx7Qa3zT0wE\n\
+                 Issued At: 2026-10-14T12:00:00Z{}\nResources:\n- https://agent.example/terms", ADDRESS, extra)
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_and_accepts_a_valid_message() {
+        let parsed = SiweMessage::parse(&message("\nExpiration Time: 2026-10-14T12:10:00Z")).unwrap();
+        assert_eq!((parsed.domain.as_str(), parsed.uri.as_str(), parsed.chain_id), ("agent.example", "https://agent.example/login", 1));
+        assert_eq!((to_checksum(&parsed.address, None).as_str(), parsed.nonce.as_str()), (ADDRESS, "k2Lp9fGx7Qa3zT0wE"));
+        assert!(parsed.validate("agent.example", 1, at("2026-10-14T12:05:00Z")).is_ok());
+        assert!(generate_nonce().len() >= 8);
+    }
+
+    #[test]
+    fn refuses_messages_for_another_domain_or_chain() {
+        let parsed = SiweMessage::parse(&message("")).unwrap();
+        let now = at("2026-10-14T12:05:00Z");
+        assert_eq!(parsed.validate("evil.example", 1, now).unwrap_err().to_string(), "Message is for agent.example, not evil.example");
+        assert_eq!(parsed.validate("agent.example", 11155111, now).unwrap_err().to_string(), "Message is for chain 1, not 11155111");
+    }
+
+    #[test]
+    fn refuses_messages_outside_their_validity_window() {
+        let parsed = SiweMessage::parse(&message("\nExpiration Time: 2026-10-14T12:10:00Z\nNot Before: 2026-10-14T12:01:00Z")).unwrap();
+        let expired = parsed.validate("agent.example", 1, at("2026-10-14T12:10:00Z")).unwrap_err();
+        assert_eq!(expired.to_string(), "Message expired at 2026-10-14 12:10:00 +00:00");
+        let early = parsed.validate("agent.example", 1, at("2026-10-14T12:00:30Z")).unwrap_err();
+        assert_eq!(early.to_string(), "Message is not valid before 2026-10-14 12:01:00 +00:00");
+        assert!(parsed.validate("agent.example", 1, at("2026-10-14T12:05:00Z")).is_ok());
+    }
+
+    #[test]
+    fn refuses_malformed_messages() {
+        let valid = message("");
+        let errors = [
+            (valid.replacen(" wants you to sign in", " would like you to sign in", 1), "Not a Sign-In with Ethereum message"),
+            (valid.replacen(ADDRESS, &ADDRESS.to_lowercase(), 1), "Address must be EIP-55 checksummed"),
+            (valid.replacen(ADDRESS, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1B", 1), "Invalid address 0x5aAeb6053F3E94C9b9A09f33669435E7Ef1B"),
+            (valid.replacen("Nonce: k2Lp9fGx7Qa3zT0wE\n", "", 1), "Missing Nonce in the message"),
+            (valid.replacen("Chain ID: 1", "Chain ID: mainnet", 1), "Invalid Chain ID"),
+            (valid.replacen("2026-10-14T12:00:00Z", "yesterday", 1), "Invalid Issued At: input contains invalid characters"),
+            (message("\nExpiration Time: soon"), "Invalid Expiration Time: input contains invalid characters"),
+        ];
+        for (text, error) in errors {
+            assert_eq!(SiweMessage::parse(&text).unwrap_err().to_string(), error, "{}", text);
+        }
+        // Fields are only read from the URI on, so a statement can't stand in for them
+        let statement = valid.replacen("Sign in to the agent.", "Nonce: chosen-by-the-statement", 1);
+        assert_eq!(SiweMessage::parse(&statement).unwrap().nonce, "k2Lp9fGx7Qa3zT0wE");
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config::AccountAbstractionConfig;
+use crate::wallet::AgentSigner;
+use ethers::prelude::*;
+use reqwest::Client;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+abigen!(
+    EntryPoint,
+    r#"[
+        struct UserOperation { address sender; uint256 nonce; bytes initCode; bytes callData; uint256 callGasLimit; uint256 verificationGasLimit; uint256 preVerificationGas; uint256 maxFeePerGas; uint256 maxPriorityFeePerGas; bytes paymasterAndData; bytes signature; }
+        function getNonce(address sender, uint192 key) external view returns (uint256 nonce)
+        function getUserOpHash(UserOperation userOp) external view returns (bytes32)
+    ]"#
+);
+
+abigen!(
+    SimpleAccountFactory,
+    r#"[
+        function getAddress(address owner, uint256 salt) external view returns (address)
+        function createAccount(address owner, uint256 salt) external returns (address)
+    ]"#
+);
+
+abigen!(
+    SimpleAccount,
+    r#"[
+        function execute(address dest, uint256 value, bytes func) external
+    ]"#
+);
+
+// Signature with the shape of a real one, so bundlers can estimate signature verification
+const DUMMY_SIGNATURE: &str = "0xfffffffffffffffffffffffffffffff0000000000000000000000000000000007aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa1c";
+
+// How often and how long to poll the bundler for a user operation receipt
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A SimpleAccount owned by a local key, deployed or still counterfactual
+#[derive(Debug, Clone)]
+pub struct SmartAccount {
+    pub address: Address,
+    pub owner: Address,
+    pub salt: U256,
+    pub deployed: bool,
+}
+
+/// Outcome of an included user operation
+#[derive(Debug, Clone)]
+pub struct UserOperationReceipt {
+    pub success: bool,
+    pub transaction_hash: Option<H256>,
+    pub block_number: Option<U64>,
+    pub actual_gas_cost: U256,
+}
+
+fn parse_address(value: &str, what: &str) -> anyhow::Result<Address> {
+    Address::from_str(value).map_err(|_| anyhow::anyhow!("Invalid {} address in the [account_abstraction] config: {}", what, value))
+}
+
+/// The owner's account for `salt`, whose address is known before it is deployed
+pub async fn account<M: Middleware + 'static>(client: Arc<M>, config: &AccountAbstractionConfig, owner: Address, salt: U256) -> anyhow::Result<SmartAccount> {
+    let factory = SimpleAccountFactory::new(parse_address(&config.factory, "factory")?, client.clone());
+    let address = factory.get_address(owner, salt).call().await
+        .map_err(|e| anyhow::anyhow!("Account factory call failed: {}", e))?;
+    let code = client.get_code(address, None).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(SmartAccount { address, owner, salt, deployed: !code.is_empty() })
+}
+
+/// Build an unsigned user operation making `call` (to, value, data) from the account, or only
+/// deploying it when there is no call. Undeployed accounts are created by the same operation
+/// through the factory.
+pub async fn build_operation<M: Middleware + 'static>(client: Arc<M>, config: &AccountAbstractionConfig, account: &SmartAccount, call: Option<(Address, U256, Bytes)>) -> anyhow::Result<UserOperation> {
+    let entry_point = EntryPoint::new(parse_address(&config.entry_point, "entry point")?, client.clone());
+    let nonce = entry_point.get_nonce(account.address, U256::zero()).call().await
+        .map_err(|e| anyhow::anyhow!("EntryPoint nonce call failed: {}", e))?;
+
+    let factory_address = parse_address(&config.factory, "factory")?;
+    let init_code = if account.deployed {
+        Bytes::default()
+    } else {
+        let factory = SimpleAccountFactory::new(factory_address, client.clone());
+        let create = factory.create_account(account.owner, account.salt).calldata().unwrap_or_default();
+        Bytes::from([factory_address.as_bytes(), create.as_ref()].concat())
+    };
+    // The EntryPoint skips calling the account when the call data is empty
+    let call_data = match call {
+        Some((to, value, data)) => SimpleAccount::new(account.address, client.clone())
+            .execute(to, value, data)
+            .calldata()
+            .unwrap_or_default(),
+        None => Bytes::default(),
+    };
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) = client.estimate_eip1559_fees(None).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(UserOperation {
+        sender: account.address,
+        nonce,
+        init_code,
+        call_data,
+        call_gas_limit: U256::zero(),
+        verification_gas_limit: U256::zero(),
+        pre_verification_gas: U256::zero(),
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        paymaster_and_data: Bytes::default(),
+        signature: Bytes::from_str(DUMMY_SIGNATURE)?,
+    })
+}
+
+// User operation as bundlers and paymasters expect it over JSON-RPC (hex quantities)
+fn operation_json(op: &UserOperation) -> serde_json::Value {
+    serde_json::json!({
+        "sender": op.sender,
+        "nonce": format!("{:#x}", op.nonce),
+        "initCode": op.init_code,
+        "callData": op.call_data,
+        "callGasLimit": format!("{:#x}", op.call_gas_limit),
+        "verificationGasLimit": format!("{:#x}", op.verification_gas_limit),
+        "preVerificationGas": format!("{:#x}", op.pre_verification_gas),
+        "maxFeePerGas": format!("{:#x}", op.max_fee_per_gas),
+        "maxPriorityFeePerGas": format!("{:#x}", op.max_priority_fee_per_gas),
+        "paymasterAndData": op.paymaster_and_data,
+        "signature": op.signature,
+    })
+}
+
+async fn rpc(url: &str, method: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let mut response: serde_json::Value = Client::new().post(url).json(&body).send().await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("{} failed: {}", method, message));
+    }
+    Ok(response["result"].take())
+}
+
+// Quantities come back as hex strings, or as numbers from some bundlers
+fn quantity(value: &serde_json::Value, field: &str) -> anyhow::Result<U256> {
+    match &value[field] {
+        serde_json::Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(text).ok(),
+        }
+        .ok_or_else(|| anyhow::anyhow!("Invalid {} in bundler response: {}", field, text)),
+        serde_json::Value::Number(number) => Ok(U256::from(number.as_u64().unwrap_or_default())),
+        _ => Err(anyhow::anyhow!("Missing {} in bundler response", field)),
+    }
+}
+
+fn bundler_url(config: &AccountAbstractionConfig) -> anyhow::Result<&str> {
+    config.bundler_url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No bundler configured; set bundler_url under [account_abstraction] or BUNDLER_URL"))
+}
+
+fn paymaster_params(config: &AccountAbstractionConfig, op: &UserOperation, chain_id: u64) -> serde_json::Value {
+    serde_json::json!([operation_json(op), config.entry_point, format!("{:#x}", chain_id), {}])
+}
+
+/// Fill in paymaster data with ERC-7677 `pm_getPaymasterStubData` (for estimation) or
+/// `pm_getPaymasterData` (final, after the gas limits are set)
+pub async fn sponsor(config: &AccountAbstractionConfig, op: &mut UserOperation, chain_id: u64, stub: bool) -> anyhow::Result<()> {
+    let url = config.paymaster_url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No paymaster configured; set paymaster_url under [account_abstraction]"))?;
+    let method = if stub { "pm_getPaymasterStubData" } else { "pm_getPaymasterData" };
+    let result = rpc(url, method, paymaster_params(config, op, chain_id)).await?;
+    let data = result["paymasterAndData"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("{} returned no paymasterAndData", method))?;
+    op.paymaster_and_data = Bytes::from_str(data)?;
+    Ok(())
+}
+
+/// Set the gas limits from the bundler's `eth_estimateUserOperationGas`, which also simulates
+/// the operation and fails if it would revert
+pub async fn estimate_gas(config: &AccountAbstractionConfig, op: &mut UserOperation) -> anyhow::Result<()> {
+    let estimate = rpc(bundler_url(config)?, "eth_estimateUserOperationGas", serde_json::json!([operation_json(op), config.entry_point])).await?;
+    op.pre_verification_gas = quantity(&estimate, "preVerificationGas")?;
+    op.verification_gas_limit = quantity(&estimate, "verificationGasLimit")?;
+    op.call_gas_limit = quantity(&estimate, "callGasLimit")?;
+    Ok(())
+}
+
+/// Sign the operation as the account owner. SimpleAccount checks an EIP-191 signature of the
+/// EntryPoint's user operation hash.
+pub async fn sign<M: Middleware + 'static>(client: Arc<M>, config: &AccountAbstractionConfig, op: &mut UserOperation, owner: &AgentSigner) -> anyhow::Result<H256> {
+    let entry_point = EntryPoint::new(parse_address(&config.entry_point, "entry point")?, client);
+    let hash = H256::from(entry_point.get_user_op_hash(op.clone()).call().await?);
+    op.signature = Bytes::from(owner.sign_message(hash.as_bytes()).await?.to_vec());
+    Ok(hash)
+}
+
+/// Submit to the bundler, returning the user operation hash
+pub async fn send(config: &AccountAbstractionConfig, op: &UserOperation) -> anyhow::Result<H256> {
+    let result = rpc(bundler_url(config)?, "eth_sendUserOperation", serde_json::json!([operation_json(op), config.entry_point])).await?;
+    let hash = result.as_str().ok_or_else(|| anyhow::anyhow!("Bundler returned no user operation hash"))?;
+    Ok(H256::from_str(hash)?)
+}
+
+/// Wait until the bundler reports the operation included, or None after a minute
+pub async fn wait_for_receipt(config: &AccountAbstractionConfig, hash: H256) -> anyhow::Result<Option<UserOperationReceipt>> {
+    let started = std::time::Instant::now();
+    while started.elapsed() < RECEIPT_TIMEOUT {
+        let result = rpc(bundler_url(config)?, "eth_getUserOperationReceipt", serde_json::json!([hash])).await?;
+        if !result.is_null() {
+            return Ok(Some(UserOperationReceipt {
+                success: result["success"].as_bool().unwrap_or(false),
+                transaction_hash: serde_json::from_value(result["receipt"]["transactionHash"].clone()).ok(),
+                block_number: serde_json::from_value(result["receipt"]["blockNumber"].clone()).ok(),
+                actual_gas_cost: quantity(&result, "actualGasCost").unwrap_or_default(),
+            }));
+        }
+        tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+    }
+    Ok(None)
+}
+
+/// Most the operation can cost in gas at its fee cap (paid by the account, or the paymaster)
+pub fn max_cost(op: &UserOperation) -> U256 {
+    (op.call_gas_limit + op.verification_gas_limit + op.pre_verification_gas) * op.max_fee_per_gas
+}
@@ -0,0 +1,411 @@
+// Snapshot governance (`snapshot` tool): active proposals of the configured spaces from the
+// Snapshot hub's GraphQL API, single proposals in full for the agent to summarize, and votes
+// signed as EIP-712 typed data and sent to the Snapshot sequencer. Votes are off-chain and cost
+// no gas; each one weighs the voter's voting power at the proposal's snapshot block.
+
+use crate::config::SnapshotConfig;
+use crate::reminders::describe_wait;
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, Signature};
+use ethers::utils::to_checksum;
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Proposals listed at once
+const MAX_PROPOSALS: usize = 20;
+// Characters of a proposal's body returned for summarizing
+const MAX_BODY_CHARS: usize = 6000;
+
+const PROPOSAL_FIELDS: &str = "id title body choices start end snapshot state type scores scores_total quorum author link discussion space { id name }";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Space {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Proposal {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub choices: Vec<String>,
+    /// Voting window, as Unix timestamps
+    pub start: i64,
+    pub end: i64,
+    /// Block voting power is read at
+    pub snapshot: Option<String>,
+    /// `pending`, `active`, or `closed`
+    pub state: String,
+    /// `single-choice`, `basic`, `approval`, `ranked-choice`, `weighted`, or `quadratic`
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub scores: Vec<f64>,
+    pub scores_total: Option<f64>,
+    pub quorum: Option<f64>,
+    pub author: String,
+    pub link: Option<String>,
+    pub discussion: Option<String>,
+    pub space: Space,
+}
+
+async fn query(config: &SnapshotConfig, query: &str, variables: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut request = client.post(&config.hub_url).json(&serde_json::json!({ "query": query, "variables": variables }));
+    if let Some(key) = config.api_key.as_deref().filter(|key| !key.is_empty()) {
+        request = request.header("x-api-key", key);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await
+        .map_err(|_| anyhow::anyhow!("The Snapshot hub returned {}", status))?;
+    if let Some(error) = body["errors"].as_array().and_then(|errors| errors.first()) {
+        return Err(anyhow::anyhow!("The Snapshot hub refused the query: {}", error["message"].as_str().unwrap_or("no reason given")));
+    }
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("The Snapshot hub returned {}", status));
+    }
+    Ok(body["data"].clone())
+}
+
+/// Active proposals of the spaces, ending soonest first
+pub async fn active_proposals(config: &SnapshotConfig, spaces: &[String]) -> anyhow::Result<Vec<Proposal>> {
+    let text = format!(
+        "query Proposals($spaces: [String], $first: Int) {{ proposals(first: $first, where: {{ space_in: $spaces, state: \"active\" }}, orderBy: \"end\", orderDirection: asc) {{ {} }} }}",
+        PROPOSAL_FIELDS
+    );
+    let data = query(config, &text, serde_json::json!({ "spaces": spaces, "first": MAX_PROPOSALS })).await?;
+    Ok(serde_json::from_value(data["proposals"].clone())?)
+}
+
+/// A proposal by id, if there is one
+pub async fn proposal(config: &SnapshotConfig, id: &str) -> anyhow::Result<Option<Proposal>> {
+    let text = format!("query Proposal($id: String) {{ proposal(id: $id) {{ {} }} }}", PROPOSAL_FIELDS);
+    let data = query(config, &text, serde_json::json!({ "id": id.trim() })).await?;
+    Ok(serde_json::from_value(data["proposal"].clone())?)
+}
+
+/// Voting power of `voter` on a proposal
+pub async fn voting_power(config: &SnapshotConfig, voter: Address, proposal: &Proposal) -> anyhow::Result<f64> {
+    let text = "query VotingPower($voter: String!, $space: String!, $proposal: String) { vp(voter: $voter, space: $space, proposal: $proposal) { vp } }";
+    let variables = serde_json::json!({ "voter": to_checksum(&voter, None), "space": proposal.space.id, "proposal": proposal.id });
+    let data = query(config, text, variables).await?;
+    data.pointer("/vp/vp").and_then(|vp| vp.as_f64()).ok_or_else(|| anyhow::anyhow!("The Snapshot hub returned no voting power"))
+}
+
+// The 1-based number of a choice given by number or by its text
+fn choice_number(proposal: &Proposal, input: &serde_json::Value) -> anyhow::Result<u32> {
+    let number = match input {
+        serde_json::Value::Number(number) => number.as_u64().map(|number| number as usize),
+        serde_json::Value::String(text) => text.trim().parse().ok().or_else(|| {
+            proposal.choices.iter().position(|choice| choice.trim().eq_ignore_ascii_case(text.trim())).map(|index| index + 1)
+        }),
+        _ => None,
+    };
+    match number {
+        Some(number) if (1..=proposal.choices.len()).contains(&number) => Ok(number as u32),
+        _ => Err(anyhow::anyhow!("{} is not one of the choices: {}", input, numbered_choices(proposal))),
+    }
+}
+
+fn numbered_choices(proposal: &Proposal) -> String {
+    proposal.choices.iter().enumerate().map(|(index, choice)| format!("{}. {}", index + 1, choice)).collect::<Vec<_>>().join(", ")
+}
+
+/// A choice as the proposal's voting type encodes it: one number for single-choice and basic
+/// votes, a list of numbers for approval and ranked-choice votes (ranked ones ranking every
+/// choice), and a JSON object of weights for weighted and quadratic votes. Choices are given
+/// by number (from 1) or by their text.
+pub fn encode_choice(proposal: &Proposal, input: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    match proposal.kind.as_str() {
+        "approval" | "ranked-choice" => {
+            let inputs = match input {
+                serde_json::Value::Array(inputs) => inputs.clone(),
+                single => vec![single.clone()],
+            };
+            let mut numbers = Vec::new();
+            for input in &inputs {
+                let number = choice_number(proposal, input)?;
+                if numbers.contains(&number) {
+                    return Err(anyhow::anyhow!("Choice {} is given twice", number));
+                }
+                numbers.push(number);
+            }
+            if numbers.is_empty() {
+                return Err(anyhow::anyhow!("Pick at least one of the choices: {}", numbered_choices(proposal)));
+            }
+            if proposal.kind == "ranked-choice" && numbers.len() != proposal.choices.len() {
+                return Err(anyhow::anyhow!("A ranked-choice vote ranks every choice, best first: {}", numbered_choices(proposal)));
+            }
+            Ok(serde_json::json!(numbers))
+        },
+        "weighted" | "quadratic" => {
+            let weights = match input {
+                serde_json::Value::Object(weights) => weights.iter().map(|(choice, weight)| (serde_json::json!(choice), weight.as_f64())).collect(),
+                single => vec![(single.clone(), Some(1.0))],
+            };
+            let mut encoded = serde_json::Map::new();
+            for (choice, weight) in weights {
+                let number = choice_number(proposal, &choice)?;
+                match weight {
+                    Some(weight) if weight > 0.0 => encoded.insert(number.to_string(), serde_json::json!(weight)),
+                    _ => return Err(anyhow::anyhow!("The weight of choice {} must be a positive number", number)),
+                };
+            }
+            Ok(serde_json::Value::String(serde_json::Value::Object(encoded).to_string()))
+        },
+        _ => match input {
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => Err(anyhow::anyhow!("This is a {} vote; pick one choice: {}", proposal.kind, numbered_choices(proposal))),
+            single => Ok(serde_json::json!(choice_number(proposal, single)?)),
+        },
+    }
+}
+
+/// An encoded choice in words, e.g. "For", "#1 Option A, #2 Option B", or "A 75%, B 25%"
+pub fn describe_choice(proposal: &Proposal, choice: &serde_json::Value) -> String {
+    let name = |number: u64| {
+        (number as usize).checked_sub(1).and_then(|index| proposal.choices.get(index)).cloned().unwrap_or_else(|| format!("choice {}", number))
+    };
+    match choice {
+        serde_json::Value::Number(number) => name(number.as_u64().unwrap_or(1)),
+        serde_json::Value::Array(numbers) => {
+            let names: Vec<String> = numbers.iter().filter_map(|number| number.as_u64()).map(name).collect();
+            match proposal.kind.as_str() {
+                "ranked-choice" => names.iter().enumerate().map(|(rank, name)| format!("#{} {}", rank + 1, name)).collect::<Vec<_>>().join(", "),
+                _ => names.join(", "),
+            }
+        },
+        serde_json::Value::String(weights) => {
+            let weights: serde_json::Map<String, serde_json::Value> = serde_json::from_str(weights).unwrap_or_default();
+            let total: f64 = weights.values().filter_map(|weight| weight.as_f64()).sum();
+            weights.iter()
+                .map(|(number, weight)| format!("{} {:.0}%", name(number.parse().unwrap_or(1)), weight.as_f64().unwrap_or(0.0) / total * 100.0))
+                .collect::<Vec<_>>()
+                .join(", ")
+        },
+        other => other.to_string(),
+    }
+}
+
+/// The vote as EIP-712 typed data in the `eth_signTypedData_v4` format
+pub fn vote_typed_data(config: &SnapshotConfig, voter: Address, proposal: &Proposal, choice: &serde_json::Value, reason: &str, timestamp: i64) -> serde_json::Value {
+    // Proposals have bytes32 ids, except some old ones that have IPFS hashes
+    let proposal_type = if proposal.id.starts_with("0x") && proposal.id.len() == 66 { "bytes32" } else { "string" };
+    let choice_type = match choice {
+        serde_json::Value::Array(_) => "uint32[]",
+        serde_json::Value::String(_) => "string",
+        _ => "uint32",
+    };
+    serde_json::json!({
+        "domain": { "name": "snapshot", "version": "0.1.4" },
+        "types": {
+            "Vote": [
+                { "name": "from", "type": "address" },
+                { "name": "space", "type": "string" },
+                { "name": "timestamp", "type": "uint64" },
+                { "name": "proposal", "type": proposal_type },
+                { "name": "choice", "type": choice_type },
+                { "name": "reason", "type": "string" },
+                { "name": "app", "type": "string" },
+                { "name": "metadata", "type": "string" },
+            ],
+        },
+        "primaryType": "Vote",
+        "message": {
+            "from": to_checksum(&voter, None),
+            "space": proposal.space.id,
+            "timestamp": timestamp,
+            "proposal": proposal.id,
+            "choice": choice,
+            "reason": reason,
+            "app": config.app,
+            "metadata": "{}",
+        },
+    })
+}
+
+/// Send a signed vote to the sequencer; the id of the stored vote
+pub async fn send_vote(config: &SnapshotConfig, voter: Address, typed_data: &serde_json::Value, signature: &Signature) -> anyhow::Result<String> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let envelope = serde_json::json!({
+        "address": to_checksum(&voter, None),
+        "sig": format!("0x{}", signature),
+        "data": {
+            "domain": typed_data["domain"],
+            "types": typed_data["types"],
+            "message": typed_data["message"],
+        },
+    });
+    let response = client.post(&config.sequencer_url).json(&envelope).send().await?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    if !status.is_success() {
+        let reason = body["error_description"].as_str().or(body["error"].as_str()).unwrap_or("no reason given");
+        return Err(anyhow::anyhow!("Snapshot refused the vote: {}", reason));
+    }
+    Ok(body["id"].as_str().unwrap_or_default().to_string())
+}
+
+// Where the proposal can be read and voted on
+fn url(proposal: &Proposal) -> String {
+    proposal.link.clone().unwrap_or_else(|| format!("https://snapshot.org/#/{}/proposal/{}", proposal.space.id, proposal.id))
+}
+
+fn time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0).map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string()).unwrap_or_default()
+}
+
+// When voting starts, ends, or ended, e.g. "ends 2025-11-02 09:00 UTC (in 2d 3h)"
+fn ending(proposal: &Proposal, now: DateTime<Utc>) -> String {
+    let at = |timestamp| DateTime::from_timestamp(timestamp, 0).filter(|time| *time > now);
+    match (at(proposal.start), at(proposal.end)) {
+        (Some(start), _) => format!("starts {} (in {})", time(proposal.start), describe_wait(start, now)),
+        (None, Some(end)) => format!("ends {} (in {})", time(proposal.end), describe_wait(end, now)),
+        _ => format!("ended {}", time(proposal.end)),
+    }
+}
+
+// The share of the votes each choice has, e.g. "For 62.5%, Against 37.5%"
+fn standings(proposal: &Proposal) -> String {
+    let total = proposal.scores_total.unwrap_or_else(|| proposal.scores.iter().sum());
+    if total <= 0.0 {
+        return "no votes yet".to_string();
+    }
+    let shares: Vec<String> = proposal.choices.iter().zip(&proposal.scores)
+        .map(|(choice, score)| format!("{} {:.1}%", choice, score / total * 100.0))
+        .collect();
+    let quorum = match proposal.quorum.filter(|quorum| *quorum > 0.0) {
+        Some(quorum) if total >= quorum => ", quorum reached".to_string(),
+        Some(quorum) => format!(", {:.0} of the {:.0} quorum", total, quorum),
+        None => String::new(),
+    };
+    format!("{} of {:.0} votes{}", shares.join(", "), total, quorum)
+}
+
+/// One line per proposal, for listing
+pub fn list(proposals: &[Proposal], now: DateTime<Utc>) -> String {
+    let lines: Vec<String> = proposals.iter()
+        .map(|proposal| format!("- [{}] {} ({}): {} vote, {}; {}", proposal.space.id, proposal.title, proposal.id, proposal.kind, ending(proposal, now), standings(proposal)))
+        .collect();
+    format!("Active Snapshot proposals ({}):\n{}", proposals.len(), lines.join("\n"))
+}
+
+/// A proposal in full, its body cut to a few thousand characters
+pub fn details(proposal: &Proposal, now: DateTime<Utc>) -> String {
+    let space = proposal.space.name.as_deref().unwrap_or(&proposal.space.id);
+    let mut lines = vec![
+        format!("{} ({})", proposal.title, space),
+        format!("Id: {}", proposal.id),
+        format!("State: {}, {} vote, {}", proposal.state, proposal.kind, ending(proposal, now)),
+        format!("Choices: {}", numbered_choices(proposal)),
+        format!("Votes: {}", standings(proposal)),
+        format!("Author: {}", proposal.author),
+    ];
+    if let Some(block) = &proposal.snapshot {
+        lines.push(format!("Voting power at block {}", block));
+    }
+    lines.push(format!("Link: {}", url(proposal)));
+    if let Some(discussion) = proposal.discussion.as_deref().filter(|discussion| !discussion.is_empty()) {
+        lines.push(format!("Discussion: {}", discussion));
+    }
+    let body = proposal.body.as_deref().unwrap_or_default().trim();
+    let length = body.chars().count();
+    let body = match length > MAX_BODY_CHARS {
+        true => format!("{}\n\n[Truncated: {} of {} characters shown]", body.chars().take(MAX_BODY_CHARS).collect::<String>(), MAX_BODY_CHARS, length),
+        false => body.to_string(),
+    };
+    format!("{}\n\n{}", lines.join("\n"), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::{context, serve};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn lists_snapshot_proposals_and_casts_signed_votes() {
+        use crate::signing::{self, SignRequest};
+        use axum::routing::post;
+
+        let id = format!("0x{}", "ab".repeat(32));
+        let end = chrono::Utc::now().timestamp() + 2 * 86400 + 600;
+        let proposal = json!({
+            "id": id, "title": "Raise the reserve factor", "body": "Raise the WETH reserve factor to 20%.", "choices": ["For", "Against", "Abstain"],
+            "start": end - 5 * 86400, "end": end, "snapshot": "21000000", "state": "active", "type": "single-choice",
+            "scores": [750.0, 250.0, 0.0], "scores_total": 1000.0, "quorum": 500.0, "author": "0x00000000000000000000000000000000000000aa",
+            "link": null, "discussion": "https://governance.aave.com/t/1", "space": { "id": "aave.eth", "name": "Aave" },
+        });
+        let votes = Arc::new(Mutex::new(Vec::new()));
+        let sent = votes.clone();
+        let api = axum::Router::new()
+            .route("/graphql", post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let proposal = proposal.clone();
+                async move {
+                    let query = body["query"].as_str().unwrap_or_default();
+                    let data = if query.contains("proposals(") {
+                        assert_eq!(body["variables"]["spaces"], json!(["aave.eth"]));
+                        json!({ "proposals": [proposal] })
+                    } else if query.contains("vp(") {
+                        json!({ "vp": { "vp": 42.5 } })
+                    } else {
+                        json!({ "proposal": proposal })
+                    };
+                    axum::Json(json!({ "data": data }))
+                }
+            }))
+            .route("/seq", post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                sent.lock().unwrap().push(body);
+                axum::Json(json!({ "id": "0xvote" }))
+            }));
+        let url = serve(api).await;
+
+        let mut config = Config::default();
+        config.snapshot.hub_url = format!("{}/graphql", url);
+        config.snapshot.sequencer_url = format!("{}/seq", url);
+        config.signer.keystore_dir = std::env::temp_dir().join(format!("snapshot-{}", Uuid::new_v4())).to_string_lossy().into_owned();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        let unconfigured = execute_tool(&context(config.clone()), "snapshot", &json!({ "operation": "proposals" })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+        config.snapshot.spaces = vec!["aave.eth".to_string()];
+        let ctx = context(config);
+
+        let listing = execute_tool(&ctx, "snapshot", &json!({ "operation": "proposals" })).await.unwrap();
+        assert!(listing.starts_with(&format!("Active Snapshot proposals (1):\n- [aave.eth] Raise the reserve factor ({}): single-choice vote, ends ", id)), "{}", listing);
+        assert!(listing.contains("(in 2d 0h); For 75.0%, Against 25.0%, Abstain 0.0% of 1000 votes, quorum reached"), "{}", listing);
+        let details = execute_tool(&ctx, "snapshot", &json!({ "operation": "proposal", "proposal_id": id })).await.unwrap();
+        assert!(details.contains("Choices: 1. For, 2. Against, 3. Abstain\n") && details.contains("\n\nRaise the WETH reserve factor to 20%."), "{}", details);
+
+        let created = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "generate", "label": "voter" })).await.unwrap();
+        let address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().to_string();
+        let vote = json!({ "operation": "vote", "proposal_id": id, "choice": "against", "reason": "Too fast", "account": "voter" });
+        let unconfirmed = execute_tool(&ctx, "snapshot", &vote).await.unwrap_err();
+        assert!(unconfirmed.message().contains(&format!("Vote Against on \"Raise the reserve factor\" (aave.eth) as {}", address.to_lowercase())), "{}", unconfirmed.message());
+        assert!(votes.lock().unwrap().is_empty());
+        let invalid = json!({ "operation": "vote", "proposal_id": id, "choice": 4, "account": "voter", "confirm": true });
+        assert!(execute_tool(&ctx, "snapshot", &invalid).await.unwrap_err().message().contains("4 is not one of the choices: 1. For, 2. Against, 3. Abstain"));
+
+        let mut confirmed = vote.clone();
+        confirmed["confirm"] = json!(true);
+        let voted = execute_tool(&ctx, "snapshot", &confirmed).await.unwrap();
+        assert!(voted.contains("Voted Against on \"Raise the reserve factor\" (aave.eth)") && voted.contains("Voting power: 42.5\nVote id: 0xvote"), "{}", voted);
+        let envelope = votes.lock().unwrap().pop().unwrap();
+        let message = &envelope["data"]["message"];
+        assert_eq!((message["space"].as_str(), message["proposal"].as_str(), message["choice"].as_u64(), message["reason"].as_str()),
+                   (Some("aave.eth"), Some(id.as_str()), Some(2), Some("Too fast")));
+        // The signature is the voter's over the typed data as sent
+        let mut typed = envelope["data"].clone();
+        typed["primaryType"] = json!("Vote");
+        let request = SignRequest::TypedData(Box::new(signing::parse_typed_data(&typed).unwrap()));
+        let signature = signing::parse_signature(envelope["sig"].as_str().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", signing::recover(&request, &signature).unwrap()), address.to_lowercase());
+        assert_eq!(envelope["address"].as_str().unwrap().to_lowercase(), address.to_lowercase());
+    }
+}
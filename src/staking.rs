@@ -0,0 +1,517 @@
+// Liquid staking with Lido and Rocket Pool. Lido mints rebasing stETH 1:1 for ETH and unstakes
+// through its withdrawal queue: a request is finalized by the protocol, then claimed. Rocket Pool
+// mints rETH at its exchange rate from the deposit pool and burns it for ETH while the pool has
+// the liquidity. Rocket Pool's contracts are found through RocketStorage, as they move on upgrades.
+
+use crate::config::{StakingConfig, StakingProtocolConfig};
+use crate::units;
+use ethers::prelude::*;
+use std::str::FromStr;
+use std::sync::Arc;
+
+mod lido {
+    use ethers::prelude::*;
+
+    abigen!(
+        StEth,
+        r#"[
+            function submit(address referral) external payable returns (uint256)
+            function balanceOf(address account) external view returns (uint256)
+            function isStakingPaused() external view returns (bool)
+            function getCurrentStakeLimit() external view returns (uint256)
+            function allowance(address owner, address spender) external view returns (uint256)
+            function approve(address spender, uint256 amount) external returns (bool)
+        ]"#
+    );
+
+    abigen!(
+        WithdrawalQueue,
+        r#"[
+            struct WithdrawalRequestStatus { uint256 amountOfStETH; uint256 amountOfShares; address owner; uint256 timestamp; bool isFinalized; bool isClaimed; }
+            function requestWithdrawals(uint256[] amounts, address owner) external returns (uint256[] requestIds)
+            function getWithdrawalRequests(address owner) external view returns (uint256[] requestsIds)
+            function getWithdrawalStatus(uint256[] requestIds) external view returns (WithdrawalRequestStatus[] statuses)
+            function claimWithdrawal(uint256 requestId) external
+        ]"#
+    );
+}
+
+mod rocket_pool {
+    use ethers::prelude::*;
+
+    abigen!(
+        RocketStorage,
+        r#"[
+            function getAddress(bytes32 key) external view returns (address)
+        ]"#
+    );
+
+    abigen!(
+        RocketDepositPool,
+        r#"[
+            function deposit() external payable
+            function getMaximumDepositAmount() external view returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        RocketDepositSettings,
+        r#"[
+            function getDepositFee() external view returns (uint256)
+            function getMinimumDeposit() external view returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        RocketTokenReth,
+        r#"[
+            function balanceOf(address account) external view returns (uint256)
+            function getEthValue(uint256 rethAmount) external view returns (uint256)
+            function getRethValue(uint256 ethAmount) external view returns (uint256)
+            function getTotalCollateral() external view returns (uint256)
+            function burn(uint256 rethAmount) external
+        ]"#
+    );
+}
+
+use lido::{StEth, WithdrawalQueue};
+use rocket_pool::{RocketDepositPool, RocketDepositSettings, RocketStorage, RocketTokenReth};
+
+// Lido's limits on one withdrawal request, in stETH wei
+const LIDO_MIN_WITHDRAWAL: u64 = 100;
+const LIDO_MAX_WITHDRAWAL_ETH: u64 = 1000;
+// Deposit limits of 10^12 ETH and more are no limit
+const UNLIMITED_ETH_DIGITS: usize = 12;
+// Lido withdrawal requests listed for one owner
+const MAX_REQUESTS: usize = 20;
+
+/// A protocol's contracts on the configured chain
+#[derive(Debug, Clone)]
+pub enum Contracts {
+    Lido { steth: Address, withdrawal_queue: Option<Address> },
+    RocketPool { deposit_pool: Address, reth: Address, settings: Address },
+}
+
+#[derive(Debug, Clone)]
+pub struct Protocol {
+    pub name: String,
+    pub contracts: Contracts,
+}
+
+impl Protocol {
+    /// Symbol of the liquid staking token
+    pub fn token(&self) -> &'static str {
+        match self.contracts {
+            Contracts::Lido { .. } => "stETH",
+            Contracts::RocketPool { .. } => "rETH",
+        }
+    }
+}
+
+/// Rates and limits of a protocol, read from its contracts. Amounts are in wei.
+#[derive(Debug, Clone)]
+pub struct Rates {
+    /// ETH one token (10^18 wei of it) is worth
+    pub eth_per_token: U256,
+    /// Share of a deposit kept as a fee, where 10^18 is all of it
+    pub deposit_fee: U256,
+    pub minimum_deposit: U256,
+    /// Most that can be deposited now
+    pub deposit_limit: U256,
+    pub paused: bool,
+    /// ETH available to pay out burned tokens right away (Rocket Pool)
+    pub withdrawal_liquidity: Option<U256>,
+}
+
+/// A Lido withdrawal request
+#[derive(Debug, Clone)]
+pub struct WithdrawalRequest {
+    pub id: U256,
+    pub owner: Address,
+    pub steth: U256,
+    pub finalized: bool,
+    pub claimed: bool,
+}
+
+/// An account's holdings in a protocol
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub balance: U256,
+    pub eth_value: U256,
+    pub withdrawals: Vec<WithdrawalRequest>,
+}
+
+// A request from the queue's (amountOfStETH, amountOfShares, owner, timestamp, isFinalized,
+// isClaimed) status
+fn withdrawal_request(id: U256, status: &(U256, U256, Address, U256, bool, bool)) -> WithdrawalRequest {
+    WithdrawalRequest { id, owner: status.2, steth: status.0, finalized: status.4, claimed: status.5 }
+}
+
+fn parse_address(value: &str, what: &str) -> anyhow::Result<Address> {
+    Address::from_str(value).map_err(|_| anyhow::anyhow!("Invalid {} address {} under [staking]", what, value))
+}
+
+/// The protocols deployed on `chain_id`: the one named, or all of them
+pub fn deployments<'a>(config: &'a StakingConfig, chain_id: u64, name: Option<&str>) -> anyhow::Result<Vec<&'a StakingProtocolConfig>> {
+    let on_chain: Vec<&StakingProtocolConfig> = config.protocols.iter().filter(|protocol| protocol.chain_id == chain_id).collect();
+    let Some(name) = name else { return Ok(on_chain) };
+    let wanted = name.trim().to_lowercase().replace([' ', '-'], "_");
+    match on_chain.iter().find(|protocol| protocol.name.eq_ignore_ascii_case(&wanted)) {
+        Some(protocol) => Ok(vec![*protocol]),
+        None => Err(anyhow::anyhow!("No staking protocol '{}' on this chain; there is {}", name,
+                                    match on_chain.is_empty() {
+                                        true => "none".to_string(),
+                                        false => on_chain.iter().map(|protocol| protocol.name.as_str()).collect::<Vec<_>>().join(", "),
+                                    })),
+    }
+}
+
+// Key of a contract's address in RocketStorage
+fn rocket_key(contract: &str) -> [u8; 32] {
+    ethers::utils::keccak256(format!("contract.address{}", contract))
+}
+
+/// Find a deployment's contracts
+pub async fn connect<M: Middleware + 'static>(client: Arc<M>, config: &StakingProtocolConfig) -> anyhow::Result<Protocol> {
+    let contract = parse_address(&config.contract, &config.name)?;
+    let contracts = match config.kind.as_str() {
+        "lido" => Contracts::Lido {
+            steth: contract,
+            withdrawal_queue: config.withdrawal_queue.as_deref().map(|queue| parse_address(queue, "withdrawal_queue")).transpose()?,
+        },
+        "rocket_pool" => {
+            let storage = RocketStorage::new(contract, client);
+            let find = |name: &'static str| {
+                let storage = storage.clone();
+                async move {
+                    match storage.get_address(rocket_key(name)).call().await {
+                        Ok(address) if !address.is_zero() => Ok(address),
+                        Ok(_) => Err(anyhow::anyhow!("RocketStorage at {:?} has no {}", contract, name)),
+                        Err(e) => Err(anyhow::anyhow!("Could not read RocketStorage at {:?}: {}", contract, e)),
+                    }
+                }
+            };
+            Contracts::RocketPool {
+                deposit_pool: find("rocketDepositPool").await?,
+                reth: find("rocketTokenRETH").await?,
+                settings: find("rocketDAOProtocolSettingsDeposit").await?,
+            }
+        },
+        kind => return Err(anyhow::anyhow!("Unknown staking protocol kind '{}' for {}; use lido or rocket_pool", kind, config.name)),
+    };
+    Ok(Protocol { name: config.name.clone(), contracts })
+}
+
+pub async fn rates<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol) -> anyhow::Result<Rates> {
+    let one = U256::exp10(18);
+    match protocol.contracts {
+        Contracts::Lido { steth, .. } => {
+            let steth = StEth::new(steth, client);
+            Ok(Rates {
+                // stETH rebases, so a token stays worth one ETH; the share rate grows instead
+                eth_per_token: one,
+                deposit_fee: U256::zero(),
+                minimum_deposit: U256::one(),
+                deposit_limit: steth.get_current_stake_limit().call().await?,
+                paused: steth.is_staking_paused().call().await?,
+                withdrawal_liquidity: None,
+            })
+        },
+        Contracts::RocketPool { deposit_pool, reth, settings } => {
+            let reth = RocketTokenReth::new(reth, client.clone());
+            let settings = RocketDepositSettings::new(settings, client.clone());
+            let deposit_limit = RocketDepositPool::new(deposit_pool, client).get_maximum_deposit_amount().call().await?;
+            Ok(Rates {
+                eth_per_token: reth.get_eth_value(one).call().await?,
+                deposit_fee: settings.get_deposit_fee().call().await?,
+                minimum_deposit: settings.get_minimum_deposit().call().await?,
+                deposit_limit,
+                paused: deposit_limit.is_zero(),
+                withdrawal_liquidity: Some(reth.get_total_collateral().call().await?),
+            })
+        },
+    }
+}
+
+/// A percentage of 10^18, e.g. "0.05%"
+fn percent(share: U256) -> String {
+    format!("{}%", units::format_token(share * 100, 18))
+}
+
+pub fn describe_rates(protocol: &Protocol, rates: &Rates) -> String {
+    let mut lines = vec![format!("{}: 1 {} = {} ETH", protocol.name, protocol.token(), units::format_eth(rates.eth_per_token))];
+    if let Contracts::Lido { .. } = protocol.contracts {
+        lines.push("stETH rebases: balances grow daily with the staking rewards".to_string());
+    }
+    if rates.paused {
+        lines.push("Deposits: not accepted now".to_string());
+    } else {
+        // Lido reports an unlimited stake limit as the largest uint256
+        let limit = match rates.deposit_limit >= U256::exp10(18 + UNLIMITED_ETH_DIGITS) {
+            true => "no limit now".to_string(),
+            false => format!("up to {} ETH now", units::format_eth(rates.deposit_limit)),
+        };
+        lines.push(format!("Deposits: {} fee, {} ETH minimum, {}", percent(rates.deposit_fee), units::format_eth(rates.minimum_deposit), limit));
+    }
+    lines.push(match (&protocol.contracts, rates.withdrawal_liquidity) {
+        (Contracts::Lido { withdrawal_queue: Some(_), .. }, _) => "Withdrawals: through the withdrawal queue, claimable once Lido finalizes them (usually 1 to 5 days)".to_string(),
+        (Contracts::Lido { withdrawal_queue: None, .. }, _) => "Withdrawals: no withdrawal queue configured".to_string(),
+        (Contracts::RocketPool { .. }, Some(liquidity)) => format!("Withdrawals: burned rETH is paid out right away, up to {} ETH now", units::format_eth(liquidity)),
+        (Contracts::RocketPool { .. }, None) => "Withdrawals: burned rETH is paid out right away".to_string(),
+    });
+    lines.join("\n")
+}
+
+/// Tokens a deposit of `amount` ETH mints
+pub async fn deposit_output<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, rates: &Rates, amount: U256) -> anyhow::Result<U256> {
+    match protocol.contracts {
+        Contracts::Lido { .. } => Ok(amount),
+        Contracts::RocketPool { reth, .. } => {
+            let fee = amount * rates.deposit_fee / U256::exp10(18);
+            Ok(RocketTokenReth::new(reth, client).get_reth_value(amount - fee).call().await?)
+        },
+    }
+}
+
+/// Contract and calldata of a deposit; it is sent with the ETH as value
+pub fn deposit_call<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol) -> (Address, Bytes) {
+    match protocol.contracts {
+        Contracts::Lido { steth, .. } => (steth, StEth::new(steth, client).submit(Address::zero()).calldata().unwrap_or_default()),
+        Contracts::RocketPool { deposit_pool, .. } => (deposit_pool, RocketDepositPool::new(deposit_pool, client).deposit().calldata().unwrap_or_default()),
+    }
+}
+
+/// ETH a withdrawal of `amount` tokens pays out
+pub async fn withdrawal_output<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, amount: U256) -> anyhow::Result<U256> {
+    match protocol.contracts {
+        Contracts::Lido { .. } => Ok(amount),
+        Contracts::RocketPool { reth, .. } => Ok(RocketTokenReth::new(reth, client).get_eth_value(amount).call().await?),
+    }
+}
+
+// Lido takes at most 1000 stETH per request, so larger withdrawals are split
+fn lido_request_amounts(amount: U256) -> Vec<U256> {
+    let max = U256::from(LIDO_MAX_WITHDRAWAL_ETH) * U256::exp10(18);
+    let mut amounts = Vec::new();
+    let mut left = amount;
+    while left > max {
+        amounts.push(max);
+        left -= max;
+    }
+    amounts.push(left);
+    amounts
+}
+
+/// An approval the withdrawal needs first: Lido's queue takes the stETH from the owner
+pub async fn withdrawal_approval<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, owner: Address, amount: U256) -> anyhow::Result<Option<(Address, Bytes)>> {
+    let Contracts::Lido { steth, withdrawal_queue: Some(queue) } = protocol.contracts else { return Ok(None) };
+    let steth_contract = StEth::new(steth, client);
+    if steth_contract.allowance(owner, queue).call().await? >= amount {
+        return Ok(None);
+    }
+    Ok(Some((steth, steth_contract.approve(queue, amount).calldata().unwrap_or_default())))
+}
+
+/// Contract and calldata of a withdrawal of `amount` tokens, refused when the protocol can't
+/// take it
+pub async fn withdrawal_call<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, owner: Address, amount: U256) -> anyhow::Result<(Address, Bytes)> {
+    match protocol.contracts {
+        Contracts::Lido { withdrawal_queue: None, .. } => Err(anyhow::anyhow!("No Lido withdrawal queue is configured for this chain")),
+        Contracts::Lido { withdrawal_queue: Some(queue), .. } => {
+            let amounts = lido_request_amounts(amount);
+            if amounts.iter().any(|amount| *amount < U256::from(LIDO_MIN_WITHDRAWAL)) {
+                return Err(anyhow::anyhow!("Lido withdrawals are at least {} wei of stETH", LIDO_MIN_WITHDRAWAL));
+            }
+            Ok((queue, WithdrawalQueue::new(queue, client).request_withdrawals(amounts, owner).calldata().unwrap_or_default()))
+        },
+        Contracts::RocketPool { reth, .. } => {
+            let contract = RocketTokenReth::new(reth, client);
+            let payout = contract.get_eth_value(amount).call().await?;
+            let liquidity = contract.get_total_collateral().call().await?;
+            if payout > liquidity {
+                return Err(anyhow::anyhow!("Rocket Pool can pay out only {} ETH now, less than the {} ETH this burn is worth; \
+                                            swap the rETH on a DEX instead, or burn less", units::format_eth(liquidity), units::format_eth(payout)));
+            }
+            Ok((reth, contract.burn(amount).calldata().unwrap_or_default()))
+        },
+    }
+}
+
+/// Contract and calldata claiming a finalized Lido withdrawal request of `owner`
+pub async fn claim_call<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, owner: Address, request_id: U256) -> anyhow::Result<(Address, Bytes, U256)> {
+    let Contracts::Lido { withdrawal_queue: Some(queue), .. } = protocol.contracts else {
+        return Err(anyhow::anyhow!("Only Lido withdrawals are claimed; Rocket Pool pays out when rETH is burned"));
+    };
+    let contract = WithdrawalQueue::new(queue, client);
+    let statuses = contract.get_withdrawal_status(vec![request_id]).call().await
+        .map_err(|_| anyhow::anyhow!("No Lido withdrawal request #{}", request_id))?;
+    let Some(request) = statuses.first().map(|status| withdrawal_request(request_id, status)) else {
+        return Err(anyhow::anyhow!("No Lido withdrawal request #{}", request_id));
+    };
+    if request.owner != owner {
+        return Err(anyhow::anyhow!("Withdrawal request #{} belongs to {:?}, not {:?}", request_id, request.owner, owner));
+    }
+    if request.claimed {
+        return Err(anyhow::anyhow!("Withdrawal request #{} was already claimed", request_id));
+    }
+    if !request.finalized {
+        return Err(anyhow::anyhow!("Withdrawal request #{} is not finalized yet; Lido usually finalizes requests within 1 to 5 days", request_id));
+    }
+    Ok((queue, contract.claim_withdrawal(request_id).calldata().unwrap_or_default(), request.steth))
+}
+
+/// Ids of the Lido withdrawal requests a transaction made
+pub fn requested_ids(receipt: &TransactionReceipt, protocol: &Protocol) -> Vec<U256> {
+    let Contracts::Lido { withdrawal_queue: Some(queue), .. } = protocol.contracts else { return Vec::new() };
+    let requested = H256::from(ethers::utils::keccak256("WithdrawalRequested(uint256,address,address,uint256,uint256)"));
+    receipt.logs.iter()
+        .filter(|log| log.address == queue && log.topics.len() == 4 && log.topics[0] == requested)
+        .map(|log| U256::from_big_endian(log.topics[1].as_bytes()))
+        .collect()
+}
+
+pub async fn position<M: Middleware + 'static>(client: Arc<M>, protocol: &Protocol, owner: Address) -> anyhow::Result<Position> {
+    match protocol.contracts {
+        Contracts::Lido { steth, withdrawal_queue } => {
+            let balance = StEth::new(steth, client.clone()).balance_of(owner).call().await?;
+            let mut withdrawals = Vec::new();
+            if let Some(queue) = withdrawal_queue {
+                let queue = WithdrawalQueue::new(queue, client);
+                let mut ids = queue.get_withdrawal_requests(owner).call().await?;
+                // The newest requests are the ones still waiting
+                ids.reverse();
+                ids.truncate(MAX_REQUESTS);
+                if !ids.is_empty() {
+                    let statuses = queue.get_withdrawal_status(ids.clone()).call().await?;
+                    withdrawals = ids.into_iter().zip(statuses)
+                        .map(|(id, status)| withdrawal_request(id, &status))
+                        .collect();
+                }
+            }
+            Ok(Position { balance, eth_value: balance, withdrawals })
+        },
+        Contracts::RocketPool { reth, .. } => {
+            let reth = RocketTokenReth::new(reth, client);
+            let balance = reth.balance_of(owner).call().await?;
+            let eth_value = match balance.is_zero() {
+                true => U256::zero(),
+                false => reth.get_eth_value(balance).call().await?,
+            };
+            Ok(Position { balance, eth_value, withdrawals: Vec::new() })
+        },
+    }
+}
+
+pub fn describe_position(protocol: &Protocol, position: &Position) -> String {
+    let mut lines = vec![format!("{}: {} {} (worth {} ETH)", protocol.name, units::format_eth(position.balance), protocol.token(), units::format_eth(position.eth_value))];
+    for request in position.withdrawals.iter().filter(|request| !request.claimed) {
+        let state = if request.finalized { "ready to claim" } else { "waiting for finalization" };
+        lines.push(format!("- Withdrawal request #{}: {} stETH, {}", request.id, units::format_eth(request.steth), state));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context, returns, rpc_node, EthCall};
+    use crate::tools::execute_tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn quotes_liquid_staking_and_checks_limits_before_sending() {
+        use ethers::abi::{decode, ParamType, Token};
+        use ethers::utils::keccak256;
+
+        let [steth, queue, storage, deposit_pool, reth, settings] = [0x51, 0x52, 0x53, 0x54, 0x55, 0x56].map(Address::repeat_byte);
+        let owner = Address::repeat_byte(0x11);
+        let eth = |tenths: u64| U256::from(tenths) * U256::exp10(17);
+        // A node with Lido and Rocket Pool deployments; rETH is worth 1.1 ETH
+        let url = rpc_node(move |method, params| {
+            if method != "eth_call" {
+                panic!("unexpected RPC call {}", method);
+            }
+            let call = EthCall::parse(params);
+            let (to, data) = (call.to, &call.data);
+            let argument = || U256::from_big_endian(&data[4..36]);
+            let uint = |value: U256| vec![Token::Uint(value)];
+            let tokens = match to {
+                _ if to == steth && call.is("isStakingPaused()") => vec![Token::Bool(false)],
+                _ if to == steth && call.is("getCurrentStakeLimit()") => uint(eth(1_500_000)),
+                _ if to == steth && call.is("balanceOf(address)") => uint(eth(20)),
+                _ if to == queue && call.is("getWithdrawalRequests(address)") => vec![Token::Array(vec![Token::Uint(7.into()), Token::Uint(8.into())])],
+                _ if to == queue && call.is("getWithdrawalStatus(uint256[])") => {
+                    let ids = decode(&[ParamType::Array(Box::new(ParamType::Uint(256)))], &data[4..]).unwrap().remove(0).into_array().unwrap();
+                    vec![Token::Array(ids.into_iter().map(|id| {
+                        let finalized = id.clone().into_uint().unwrap() == 7.into();
+                        let amount = if finalized { eth(10) } else { eth(5) };
+                        Token::Tuple(vec![Token::Uint(amount), Token::Uint(amount), Token::Address(owner), Token::Uint(0.into()), Token::Bool(finalized), Token::Bool(false)])
+                    }).collect())]
+                },
+                _ if to == storage => {
+                    let found = [("rocketDepositPool", deposit_pool), ("rocketTokenRETH", reth), ("rocketDAOProtocolSettingsDeposit", settings)].into_iter()
+                        .find(|(name, _)| data[4..36] == keccak256(format!("contract.address{}", name)))
+                        .map(|(_, address)| address).unwrap_or_default();
+                    vec![Token::Address(found)]
+                },
+                _ if to == deposit_pool && call.is("getMaximumDepositAmount()") => uint(eth(1000)),
+                _ if to == settings && call.is("getDepositFee()") => uint(U256::from(5) * U256::exp10(14)),
+                _ if to == settings && call.is("getMinimumDeposit()") => uint(U256::exp10(16)),
+                _ if to == reth && call.is("getEthValue(uint256)") => uint(argument() * 11 / 10),
+                _ if to == reth && call.is("getRethValue(uint256)") => uint(argument() * 10 / 11),
+                _ if to == reth && call.is("getTotalCollateral()") => uint(eth(30)),
+                _ if to == reth && call.is("balanceOf(address)") => uint(eth(50)),
+                _ => return None,
+            };
+            returns(&tokens)
+        }).await;
+
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(url);
+        config.chain.chain_id = 5;
+        let unconfigured = execute_tool(&context(config.clone()), "stake", &json!({ "operation": "rates" })).await.unwrap_err();
+        assert_eq!(unconfigured.kind(), "not_configured");
+        config.chain.chain_id = 1;
+        config.chain.name = "Ethereum".to_string();
+        config.staking.protocols = vec![
+            StakingProtocolConfig { name: "lido".to_string(), kind: "lido".to_string(), chain_id: 1, contract: format!("{:?}", steth), withdrawal_queue: Some(format!("{:?}", queue)) },
+            StakingProtocolConfig { name: "rocket_pool".to_string(), kind: "rocket_pool".to_string(), chain_id: 1, contract: format!("{:?}", storage), withdrawal_queue: None },
+        ];
+        let ctx = context(config);
+
+        let rates = execute_tool(&ctx, "stake", &json!({ "operation": "rates" })).await.unwrap();
+        assert!(rates.starts_with("lido: 1 stETH = 1 ETH\nstETH rebases: balances grow daily with the staking rewards\n\
+                                   Deposits: 0% fee, 0.000000000000000001 ETH minimum, up to 150000 ETH now\n"), "{}", rates);
+        assert!(rates.ends_with("rocket_pool: 1 rETH = 1.1 ETH\nDeposits: 0.05% fee, 0.01 ETH minimum, up to 100 ETH now\n\
+                                 Withdrawals: burned rETH is paid out right away, up to 3 ETH now"), "{}", rates);
+        let account = format!("{:?}", owner);
+        let position = execute_tool(&ctx, "stake", &json!({ "operation": "position", "account": account })).await.unwrap();
+        assert!(position.starts_with(&format!("Staking of {:?} on Ethereum:\nlido: 2 stETH (worth 2 ETH)\n\
+                                               - Withdrawal request #8: 0.5 stETH, waiting for finalization\n\
+                                               - Withdrawal request #7: 1 stETH, ready to claim\n\
+                                               rocket_pool: 5 rETH (worth 5.5 ETH)", owner)), "{}", position);
+
+        let call = |operation: &str, protocol: Option<&str>, amount: &str| {
+            json!({ "operation": operation, "protocol": protocol, "amount": amount, "account": account, "request_id": amount })
+        };
+        let unnamed = execute_tool(&ctx, "stake", &call("deposit", None, "1")).await.unwrap_err();
+        assert!(unnamed.message().contains("Name the protocol: lido or rocket_pool"), "{}", unnamed.message());
+        let quote = execute_tool(&ctx, "stake", &call("deposit", Some("rocket pool"), "1")).await.unwrap_err();
+        let minted = (U256::exp10(18) - U256::from(5) * U256::exp10(14)) * 10 / 11;
+        assert!(quote.message().contains(&format!("Stake 1 ETH with rocket_pool for about {} rETH as {:?}", crate::units::format_eth(minted), owner)), "{}", quote.message());
+        let too_much = execute_tool(&ctx, "stake", &call("deposit", Some("rocket_pool"), "200")).await.unwrap_err();
+        assert!(too_much.message().contains("rocket_pool takes deposits of 0.01 to 100 ETH now"), "{}", too_much.message());
+        let illiquid = execute_tool(&ctx, "stake", &call("withdraw", Some("rocket_pool"), "4")).await.unwrap_err();
+        assert!(illiquid.message().contains("Rocket Pool can pay out only 3 ETH now, less than the 4.4 ETH this burn is worth"), "{}", illiquid.message());
+        let request = execute_tool(&ctx, "stake", &call("withdraw", Some("lido"), "1")).await.unwrap_err();
+        assert!(request.message().contains("Request the withdrawal of 1 stETH from Lido"), "{}", request.message());
+        let overdrawn = execute_tool(&ctx, "stake", &call("withdraw", Some("lido"), "3")).await.unwrap_err();
+        assert!(overdrawn.message().contains("holds 2 stETH, less than 3"), "{}", overdrawn.message());
+
+        let pending = execute_tool(&ctx, "stake", &call("claim", None, "8")).await.unwrap_err();
+        assert!(pending.message().contains("#8 is not finalized yet"), "{}", pending.message());
+        let claim = execute_tool(&ctx, "stake", &call("claim", None, "7")).await.unwrap_err();
+        assert!(claim.message().contains(&format!("Claim Lido withdrawal request #7 (1 stETH) for {:?}", owner)), "{}", claim.message());
+    }
+}
@@ -0,0 +1,167 @@
+// Where chat history is kept: sessions, their messages, and the running summary of compacted
+// turns. The chat, `ask`, the terminal interface, and the JSON-RPC and gRPC modes go through
+// `MessageStore` (`ToolContext::store`), so that a program embedding the agent can keep history
+// in its own storage, and tests can run without a database. Postgres is the store of every mode with `DATABASE_URL` set;
+// without it, `MemoryStore` keeps history for the life of the process.
+//
+// The audit log, transactions, users, memory, and the HTTP API's session ownership stay in
+// Postgres (see db.rs).
+
+use crate::db::{
+    create_session, load_session_messages, load_session_summary, save_message, save_session_summary, session_exists, MessageStats,
+};
+use crate::redact::redact;
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[async_trait]
+pub trait MessageStore: Send + Sync {
+    async fn create_session(&self, session_id: Uuid) -> anyhow::Result<()>;
+
+    async fn session_exists(&self, session_id: Uuid) -> anyhow::Result<bool>;
+
+    /// Store a message, redacted, with what its turn took for answers; the id it was stored under
+    async fn save_message(&self, session_id: Uuid, role: &str, content: &str, model: &str, stats: Option<MessageStats>) -> anyhow::Result<i32>;
+
+    /// Up to `limit` of the latest messages after the first `offset`, oldest first, as
+    /// (position from 1, role, content)
+    async fn load_messages(&self, session_id: Uuid, offset: i64, limit: i64) -> anyhow::Result<Vec<(i64, String, String)>>;
+
+    /// The running summary of the session's compacted history and how many messages it covers
+    async fn load_summary(&self, session_id: Uuid) -> anyhow::Result<(Option<String>, i64)>;
+
+    async fn save_summary(&self, session_id: Uuid, summary: &str, summarized_messages: i64) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl MessageStore for Pool<Postgres> {
+    async fn create_session(&self, session_id: Uuid) -> anyhow::Result<()> {
+        Ok(create_session(self, session_id).await?)
+    }
+
+    async fn session_exists(&self, session_id: Uuid) -> anyhow::Result<bool> {
+        Ok(session_exists(self, session_id).await?)
+    }
+
+    async fn save_message(&self, session_id: Uuid, role: &str, content: &str, model: &str, stats: Option<MessageStats>) -> anyhow::Result<i32> {
+        Ok(save_message(self, session_id, role, content, model, stats).await?)
+    }
+
+    async fn load_messages(&self, session_id: Uuid, offset: i64, limit: i64) -> anyhow::Result<Vec<(i64, String, String)>> {
+        Ok(load_session_messages(self, session_id, offset, limit).await?)
+    }
+
+    async fn load_summary(&self, session_id: Uuid) -> anyhow::Result<(Option<String>, i64)> {
+        Ok(load_session_summary(self, session_id).await?)
+    }
+
+    async fn save_summary(&self, session_id: Uuid, summary: &str, summarized_messages: i64) -> anyhow::Result<()> {
+        Ok(save_session_summary(self, session_id, summary, summarized_messages).await?)
+    }
+}
+
+#[derive(Default)]
+struct StoredSession {
+    // Role and content, oldest first
+    messages: Vec<(String, String)>,
+    summary: Option<String>,
+    summarized_messages: i64,
+}
+
+/// History kept in memory, lost when the process exits
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<Uuid, StoredSession>>,
+    // Ids are unique across sessions, like the database's
+    next_id: Mutex<i32>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl MessageStore for MemoryStore {
+    async fn create_session(&self, session_id: Uuid) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&session_id) {
+            return Err(anyhow::anyhow!("Session {} already exists", session_id));
+        }
+        sessions.insert(session_id, StoredSession::default());
+        Ok(())
+    }
+
+    async fn session_exists(&self, session_id: Uuid) -> anyhow::Result<bool> {
+        Ok(self.sessions.lock().unwrap().contains_key(&session_id))
+    }
+
+    // The model and stats are only kept in the database
+    async fn save_message(&self, session_id: Uuid, role: &str, content: &str, _model: &str, _stats: Option<MessageStats>) -> anyhow::Result<i32> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id).ok_or_else(|| anyhow::anyhow!("Session {} not found", session_id))?;
+        session.messages.push((role.to_string(), redact(content).into_owned()));
+        let mut next_id = self.next_id.lock().unwrap();
+        *next_id += 1;
+        Ok(*next_id)
+    }
+
+    async fn load_messages(&self, session_id: Uuid, offset: i64, limit: i64) -> anyhow::Result<Vec<(i64, String, String)>> {
+        let sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get(&session_id) else { return Ok(Vec::new()) };
+        let after: Vec<(i64, String, String)> = session.messages.iter().enumerate()
+            .map(|(n, (role, content))| (n as i64 + 1, role.clone(), content.clone()))
+            .filter(|(position, _, _)| *position > offset)
+            .collect();
+        let skip = after.len().saturating_sub(limit.max(0) as usize);
+        Ok(after.into_iter().skip(skip).collect())
+    }
+
+    async fn load_summary(&self, session_id: Uuid) -> anyhow::Result<(Option<String>, i64)> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(&session_id).map(|session| (session.summary.clone(), session.summarized_messages)).unwrap_or_default())
+    }
+
+    async fn save_summary(&self, session_id: Uuid, summary: &str, summarized_messages: i64) -> anyhow::Result<()> {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&session_id) {
+            session.summary = Some(redact(summary).into_owned());
+            session.summarized_messages = summarized_messages;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::conversation::Conversation;
+    use crate::rpc::RpcServer;
+    use crate::test_support::context;
+    use crate::tools::ToolContext;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn resumes_sessions_from_a_store_of_its_own() {
+        let mut config = Config::default();
+        config.anthropic.mock_fixture = Some("tests/fixtures/llm/resumed_chat.json".to_string());
+        let store = Arc::new(MemoryStore::new());
+        let ctx = ToolContext { store: store.clone(), ..context(config) };
+
+        let first = RpcServer::new(ctx.clone(), None);
+        let session = first.open(None).await.unwrap();
+        first.chat(session, "What time is it?", |_| {}).await.unwrap();
+        // Another server over the same store picks the session up where the first left it
+        let second = RpcServer::new(ctx, None);
+        second.chat(session, "And now?", |_| {}).await.unwrap();
+
+        let conversation = Conversation::load(store.as_ref(), session, 10).await.unwrap();
+        let texts: Vec<String> = conversation.messages.iter().map(|m| m.plain_text()).collect();
+        assert_eq!(texts, ["What time is it?", "It is noon.", "And now?", "Still noon."]);
+        assert!(second.open(Some(Uuid::new_v4())).await.is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Helpers shared by the module tests: a context to run tools in, local servers standing in for
+// the APIs and nodes the tools call, and the database of the tests that need one. The tests
+// that need a database are marked #[ignore]; run them with `cargo test -- --ignored` and
+// DATABASE_URL pointing at a Postgres database.
+
+use crate::config::Config;
+use crate::db::get_db_pool;
+use crate::policy::{Policy, PolicyEngine};
+use crate::tools::ToolContext;
+use ethers::abi::{encode, Token};
+use ethers::types::Address;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+pub fn context(config: Config) -> ToolContext {
+    ToolContext::new(Arc::new(config), Arc::new(PolicyEngine::new(Policy::default())), None, None)
+}
+
+// Serve an API on a free local port, returning its base URL (without a trailing slash)
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
+
+// A JSON-RPC node answering each call with `answer(method, params)`; None is answered as a
+// reverted call
+pub async fn rpc_node<F>(answer: F) -> String
+where
+    F: Fn(&str, &serde_json::Value) -> Option<serde_json::Value> + Clone + Send + Sync + 'static,
+{
+    rpc_node_with_errors(move |method, params| answer(method, params).ok_or_else(|| "execution reverted".to_string())).await
+}
+
+// Like `rpc_node`, but an Err is answered as a node error with that message, e.g. "nonce too low"
+pub async fn rpc_node_with_errors<F>(answer: F) -> String
+where
+    F: Fn(&str, &serde_json::Value) -> Result<serde_json::Value, String> + Clone + Send + Sync + 'static,
+{
+    let app = axum::Router::new().route("/", axum::routing::post(move |axum::Json(request): axum::Json<serde_json::Value>| async move {
+        let method = request["method"].as_str().unwrap_or_default();
+        let reply = match answer(method, &request["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(message) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": 3, "message": message } }),
+        };
+        axum::Json(reply)
+    }));
+    serve(app).await
+}
+
+// The contract and calldata of an eth_call
+pub struct EthCall {
+    pub to: Address,
+    pub data: Vec<u8>,
+}
+
+impl EthCall {
+    pub fn parse(params: &serde_json::Value) -> EthCall {
+        let call = &params[0];
+        let data = call["data"].as_str().or(call["input"].as_str()).unwrap_or_default();
+        EthCall {
+            to: serde_json::from_value(call["to"].clone()).unwrap_or_default(),
+            data: hex::decode(data.trim_start_matches("0x")).unwrap(),
+        }
+    }
+
+    // Whether the call is to the function with this signature, e.g. "balanceOf(address)"
+    pub fn is(&self, signature: &str) -> bool {
+        self.data.len() >= 4 && self.data[..4] == ethers::utils::id(signature)[..4]
+    }
+
+    // The address passed as the argument at `index`
+    pub fn address(&self, index: usize) -> Address {
+        Address::from_slice(&self.data[16 + 32 * index..36 + 32 * index])
+    }
+}
+
+// The result of an eth_call returning these values
+pub fn returns(tokens: &[Token]) -> Option<serde_json::Value> {
+    Some(json!(format!("0x{}", hex::encode(encode(tokens)))))
+}
+
+// The database DATABASE_URL points at, migrated
+pub async fn database(config: &mut Config) -> Pool<Postgres> {
+    config.database.url = Some(std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a Postgres database"));
+    config.database.auto_migrate = true;
+    get_db_pool(&config.database).await.expect("the database at DATABASE_URL is unreachable")
+}
@@ -0,0 +1,79 @@
+use crate::cluster;
+use crate::users::{required_role, Role};
+use ethers::types::H256;
+use ethers::utils::keccak256;
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    // Outputs of recent tool calls by cache key, with when they expire
+    static ref CACHE: Mutex<HashMap<H256, (Instant, String)>> = Mutex::new(HashMap::new());
+}
+
+/// How long the output of a call is reused, from the `[tools.cache_ttl_secs]` entry for the
+/// tool's operation (e.g. `eth_wallet.balance`) or else for the whole tool; None when it is
+/// not cached. Only read-only calls are, so an entry for a whole tool never replays a send.
+pub fn ttl(ttls: &BTreeMap<String, u64>, tool: &str, args: &serde_json::Value) -> Option<Duration> {
+    let operation = args.get("operation").and_then(|v| v.as_str());
+    if required_role(tool, operation) != Role::Viewer {
+        return None;
+    }
+    operation.and_then(|operation| ttls.get(&format!("{}.{}", tool, operation)))
+        .or_else(|| ttls.get(tool))
+        .filter(|secs| **secs > 0)
+        .map(|secs| Duration::from_secs(*secs))
+}
+
+/// Key of a call: the tool, all of its arguments, the chain it reads from, the user it is made
+/// for, and whether it runs in dry-run mode. Replicas share the keys through Redis, so they are
+/// the keccak256 of the call as JSON (whose objects serialize with sorted keys), which doesn't
+/// change between builds the way `std`'s hasher may.
+pub fn key(chain_id: u64, user: Option<&str>, dry_run: bool, tool: &str, args: &serde_json::Value) -> H256 {
+    let call = serde_json::json!([chain_id, user, dry_run, tool, args]);
+    H256::from(keccak256(call.to_string()))
+}
+
+/// A fresh output of the call, from this process or, with Redis, from another replica
+pub async fn get(key: H256) -> Option<String> {
+    let local = CACHE.lock().unwrap().get(&key)
+        .filter(|(expires_at, _)| *expires_at > Instant::now())
+        .map(|(_, output)| output.clone());
+    match local {
+        Some(output) => Some(output),
+        None => cluster::cached_output(key).await,
+    }
+}
+
+pub async fn put(key: H256, ttl: Duration, output: &str) {
+    {
+        let mut cache = CACHE.lock().unwrap();
+        let now = Instant::now();
+        cache.retain(|_, (expires_at, _)| *expires_at > now);
+        cache.insert(key, (now + ttl, output.to_string()));
+    }
+    cluster::cache_output(key, ttl, output).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn caches_only_read_only_calls() {
+        let ttls = BTreeMap::from([("eth_wallet".to_string(), 30), ("get_token_price".to_string(), 60)]);
+        assert_eq!(ttl(&ttls, "eth_wallet", &json!({ "operation": "balance" })), Some(Duration::from_secs(30)));
+        assert_eq!(ttl(&ttls, "eth_wallet", &json!({ "operation": "send", "to_address": "0xdead", "amount": "1" })), None);
+        assert_eq!(ttl(&ttls, "get_token_price", &json!({ "token": "ETH" })), Some(Duration::from_secs(60)));
+
+        let args = json!({ "operation": "balance", "address": "0xdead" });
+        assert_ne!(key(1, None, false, "eth_wallet", &args), key(1, None, true, "eth_wallet", &args));
+        assert_ne!(key(1, None, false, "eth_wallet", &args), key(1, None, false, "eth_wallet", &json!({ "operation": "balance", "address": "0xbeef" })));
+        // The same on every replica and build, whatever the order the arguments came in
+        assert_eq!(key(1, None, false, "eth_wallet", &args), key(1, None, false, "eth_wallet", &json!({ "address": "0xdead", "operation": "balance" })));
+        assert_eq!(format!("{:?}", key(1, Some("0xa11ce"), false, "get_token_price", &json!({ "token": "ETH" }))),
+                   format!("{:?}", H256::from(keccak256(r#"[1,"0xa11ce",false,"get_token_price",{"token":"ETH"}]"#))));
+    }
+}
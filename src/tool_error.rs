@@ -0,0 +1,64 @@
+/// Why a tool call failed, so callers and the model can tell a bad argument from a node that
+/// is down or a transaction the policy refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolError {
+    /// Arguments are missing or malformed, or name something that doesn't exist
+    InvalidArgs(String),
+    /// The node, or a service the tool relies on, failed or could not be reached
+    RpcError(String),
+    /// The transaction policy or the persona's allowlist refused the call
+    PolicyViolation(String),
+    /// The transaction would revert, so it was not sent
+    SimulationFailed(String),
+    /// Nothing came back in time
+    Timeout(String),
+    /// The tool needs configuration that isn't set
+    NotConfigured(String),
+    /// Anything else, such as a signer or the node rejecting a transaction
+    Failed(String),
+}
+
+impl ToolError {
+    /// Short name of the variant, as the model and the audit log see it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ToolError::InvalidArgs(_) => "invalid_args",
+            ToolError::RpcError(_) => "rpc_error",
+            ToolError::PolicyViolation(_) => "policy_violation",
+            ToolError::SimulationFailed(_) => "simulation_failed",
+            ToolError::Timeout(_) => "timeout",
+            ToolError::NotConfigured(_) => "not_configured",
+            ToolError::Failed(_) => "failed",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ToolError::InvalidArgs(message)
+            | ToolError::RpcError(message)
+            | ToolError::PolicyViolation(message)
+            | ToolError::SimulationFailed(message)
+            | ToolError::Timeout(message)
+            | ToolError::NotConfigured(message)
+            | ToolError::Failed(message) => message,
+        }
+    }
+
+    /// Content of the tool_result block the model gets for this error
+    pub fn to_json(&self) -> String {
+        serde_json::json!({ "error": self.kind(), "message": self.message() }).to_string()
+    }
+}
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+// Failures a tool passes on with `?` are not classified any further
+impl<E: Into<anyhow::Error>> From<E> for ToolError {
+    fn from(e: E) -> Self {
+        ToolError::Failed(e.into().to_string())
+    }
+}
@@ -1,56 +1,1477 @@
 use serde::{Deserialize, Serialize};
-use chrono::Local;
+use crate::address_book::{self, Recipient};
+use crate::alerts;
+use crate::approvals;
+use crate::bridge;
+use crate::calc;
+use crate::chain_query::{self, Decoders};
+use crate::chains;
+use crate::cluster;
+use crate::contracts;
+use crate::db::{
+    cancel_price_alert, cancel_reminder, contact_for_address, delete_contact, delete_note, find_bridge_transfer, find_contact, find_transaction,
+    list_contacts, list_notes, list_price_alerts, list_reminders, save_contact, save_note, save_price_alert, save_reminder,
+    save_tool_call, save_wallet,
+};
+use crate::mcp;
+use crate::multicall;
+use crate::nft;
+use crate::nonce::NONCE_MANAGER;
+use crate::dex;
+use crate::dune;
+use crate::faucet;
+use crate::fetch;
+use crate::gas;
+use crate::governor;
+use crate::ipfs;
+use crate::keystore;
+use crate::kms;
+use crate::lending;
+use crate::personality::Personality;
+use crate::plugins;
+use crate::pnl;
+use crate::units;
+use crate::wallet::{self, AgentSigner};
+use crate::workspace;
+use crate::config::{ChainConfig, Config};
+use crate::policy::{PolicyEngine, TxIntent};
+use crate::prices;
+use crate::reminders;
+use crate::report;
+use crate::private_tx;
+use crate::provider::{self, EthProvider};
+use crate::safe;
+use crate::snapshot;
+use crate::staking;
+use crate::search;
+use crate::signatures;
+use crate::signing::{self, SignRequest};
+use crate::simulation::{self, Simulation, FORCED_GAS_LIMIT};
+use crate::smart_account;
+use crate::store::{MemoryStore, MessageStore};
+use crate::tool_cache;
+use crate::tool_error::ToolError;
+use crate::tracker;
+use crate::usage::UsageTotals;
+use crate::users::{check_role, Role};
+use ethers::abi::{EventExt, FunctionExt};
+use chrono::{Datelike, Local};
 use ethers::prelude::*;
 use ethers::types::transaction::eip2718::TypedTransaction;
-use rand::Rng;
 use std::str::FromStr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Mutex;
 use std::sync::Arc;
-use std::env;
+use std::path::Path;
+use std::time::Instant;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tool {
     pub name: String,
     pub description: String,
+    pub input_schema: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ToolCall {
-    pub action: String, // "call_tool" or "answer"
-    pub tool: Option<String>,
-    pub args: Option<serde_json::Value>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ToolResponse {
-    pub tool: String,
-    pub content: String,
+/// Every tool the agent offers: the built-in ones followed by those of loaded plugins and
+/// connected MCP servers
+pub fn get_available_tools() -> Vec<Tool> {
+    let mut tools = builtin_tools();
+    tools.extend(plugins::tools());
+    tools.extend(mcp::tools());
+    tools
 }
 
-pub fn get_available_tools() -> Vec<Tool> {
+pub fn builtin_tools() -> Vec<Tool> {
     vec![
         Tool {
             name: "get_weather".to_string(),
             description: "Get the current weather for a given city".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "city": {
+                        "type": "string",
+                        "description": "The city to get weather for"
+                    }
+                },
+                "required": ["city"]
+            }),
         },
         Tool {
             name: "get_time".to_string(),
             description: "Get the current time in a specific timezone or local time".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "timezone": {
+                        "type": "string",
+                        "description": "Optional timezone (e.g., 'UTC', 'America/New_York'). If not provided, local time is returned."
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_token_price".to_string(),
+            description: "Get the current USD and EUR price and 24h change of ETH or an ERC-20 token from CoinGecko".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "token": {
+                        "type": "string",
+                        "description": "Token symbol such as 'ETH' or 'USDC', or an ERC-20 contract address on Ethereum mainnet"
+                    }
+                },
+                "required": ["token"]
+            }),
+        },
+        Tool {
+            name: "chainlink_price".to_string(),
+            description: "Read the latest price from a Chainlink on-chain price feed on the configured chain, without relying on a centralized API. Omit 'pair' to list the configured feeds".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pair": {
+                        "type": "string",
+                        "description": "Configured feed pair such as 'ETH/USD', or the address of a Chainlink aggregator"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "gas_tracker".to_string(),
+            description: "Current gas fees on the configured chain: base fee, priority fee percentiles over recent blocks, and cheap/standard/fast fee recommendations with the cost of a transfer. Also lists or cancels sends waiting for the base fee to drop (see max_base_fee on eth_wallet and contract_send)".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["report", "waiting", "cancel"],
+                        "description": "'report' (current fees, the default), 'waiting' (sends waiting for cheaper gas), or 'cancel' (stop waiting to make the send 'wait_id')"
+                    },
+                    "wait_id": {
+                        "type": "integer",
+                        "description": "Waiting send to cancel"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "price_alert".to_string(),
+            description: "Price alerts on CoinGecko USD prices, e.g. 'alert me when ETH drops below $2000'. An alert is checked in the background and fires once when the price crosses its threshold; the user then gets a short explanation in the chat and on the configured notification channels. Create, list, or cancel alerts".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["create", "list", "cancel"],
+                        "description": "'create' (a new alert), 'list' (the user's alerts), or 'cancel' (an active alert by 'alert_id')"
+                    },
+                    "token": {
+                        "type": "string",
+                        "description": "Token symbol such as 'ETH', or an ERC-20 contract address on Ethereum mainnet"
+                    },
+                    "condition": {
+                        "type": "string",
+                        "enum": ["above", "below"],
+                        "description": "Fire when the price rises to or above the threshold, or falls to or below it"
+                    },
+                    "price": {
+                        "type": "number",
+                        "description": "Threshold in USD"
+                    },
+                    "alert_id": {
+                        "type": "integer",
+                        "description": "Alert to cancel"
+                    },
+                    "include_inactive": {
+                        "type": "boolean",
+                        "description": "For 'list', also show alerts that fired or were cancelled (default false)"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "remember".to_string(),
+            description: "Notes kept for the user across sessions, e.g. 'remember that my cold wallet is the Ledger one' or 'note that I bought ETH at $1820'. Save a note only when the user asks you to remember something; list them when a question may depend on something the user told you before".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["save", "list", "forget"],
+                        "description": "'save' (a new note), 'list' (the user's notes, optionally matching 'query'), or 'forget' (a note by 'note_id')"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "The note to save, written so it makes sense on its own later"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "For 'list', only notes containing these words"
+                    },
+                    "note_id": {
+                        "type": "integer",
+                        "description": "Note to forget"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "remind_me".to_string(),
+            description: "Timed reminders, e.g. 'remind me to claim my staking rewards in 3 days'. A reminder fires once when it is due; the user sees it in this chat and on the configured notification channels. Create, list, or cancel reminders".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["create", "list", "cancel"],
+                        "description": "'create' (a new reminder), 'list' (the user's reminders), or 'cancel' (an active reminder by 'reminder_id')"
+                    },
+                    "message": {
+                        "type": "string",
+                        "description": "What to remind the user of"
+                    },
+                    "when": {
+                        "type": "string",
+                        "description": "A delay such as 'in 30 minutes', '2h', or '3 days', or a time: RFC 3339 ('2025-11-02T09:00:00Z') or local ('2025-11-02 09:00')"
+                    },
+                    "reminder_id": {
+                        "type": "integer",
+                        "description": "Reminder to cancel"
+                    },
+                    "include_inactive": {
+                        "type": "boolean",
+                        "description": "For 'list', also show reminders that fired or were cancelled (default false)"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "address_book".to_string(),
+            description: "The user's address book: labels such as 'mom' or 'cold storage' for addresses they send to. Recipients of sends and transfers may then be given by label, e.g. to_address 'mom'. Only add entries with addresses the user gave; never guess one for a label".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["add", "list", "remove"],
+                        "description": "'add' (save or update a label), 'list' (every entry), or 'remove' (an entry by label)"
+                    },
+                    "label": {
+                        "type": "string",
+                        "description": "Label for 'add' and 'remove', e.g. 'mom' or 'uniswap router'; no dots"
+                    },
+                    "address": {
+                        "type": "string",
+                        "description": "Address or ENS name for 'add'; ENS names are resolved and the address saved"
+                    },
+                    "note": {
+                        "type": "string",
+                        "description": "Optional note for 'add'"
+                    }
+                },
+                "required": ["operation"]
+            }),
         },
         Tool {
             name: "eth_wallet".to_string(),
-            description: "Ethereum wallet operations: generate new wallet, check balance, or send ETH".to_string(),
+            description: "Ethereum wallet operations: generate new wallet, check balance, send ETH, speed up or cancel a pending transaction, manage an HD wallet (BIP-39 mnemonic with derived accounts), or resolve/look up ENS names. Address parameters accept ENS names such as vitalik.eth".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "description": "The operation to perform: 'generate' (a new keystore account), 'balance', 'send', 'speed_up' (re-send a pending transaction with higher fees), 'cancel' (replace a pending transaction with a zero-value self-send), 'resolve' (ENS name to address), 'lookup' (address to primary ENS name), 'mnemonic_generate', 'derive' (derive an HD account), 'accounts' (list keystore and derived HD accounts), 'wallets' (those accounts by name with their ETH balances), 'faucet' (test ETH for an address or account on a testnet), or 'connect_ledger' (read a Ledger account's address so it can sign)"
+                    },
+                    "tx_hash": {
+                        "type": "string",
+                        "description": "Hash of the pending transaction for 'speed_up' or 'cancel'. Only use these when the user asks to unstick that transaction"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "index": {
+                        "type": "integer",
+                        "description": "Account index N in m/44'/60'/0'/0/N for 'derive', or the Ledger account index for 'connect_ledger'"
+                    },
+                    "label": {
+                        "type": "string",
+                        "description": "Name of the keystore account for 'generate' (default wallet-N), or an optional label for the account being derived. Tool calls can then refer to the account by it. Ask the user for one when they don't say"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name (e.g. 'trading-1'), HD wallet account index or label, 'ledger'/'ledger:N' for a connected Ledger account, or 'kms' for the KMS key, to send from instead of from_address"
+                    },
+                    "address": {
+                        "type": "string",
+                        "description": "Ethereum address, ENS name, or account name (e.g. 'trading-1') for 'balance' and 'faucet', raw address for 'lookup'"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "ENS name for 'resolve' operation, e.g. vitalik.eth"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Sender's Ethereum address, ENS name, or account name for 'send' operation"
+                    },
+                    "to_address": {
+                        "type": "string",
+                        "description": "Recipient's Ethereum address, ENS name, or address book label (e.g. 'mom') for 'send' operation"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "Amount to send for 'send' operation, in ETH by default or with a unit, e.g. '0.1', '0.1 ETH', '500 gwei', '1000 wei'"
+                    },
+                    "max_fee_per_gas": {
+                        "type": "string",
+                        "description": "Optional max fee per gas in gwei for 'send', 'speed_up' or 'cancel' (gas price on legacy chains). Estimated from fee history if omitted"
+                    },
+                    "max_priority_fee_per_gas": {
+                        "type": "string",
+                        "description": "Optional max priority fee (tip) per gas in gwei for 'send', 'speed_up' or 'cancel'. Estimated from fee history if omitted"
+                    },
+                    "max_base_fee": {
+                        "type": "number",
+                        "description": "Optional for 'send': Only broadcast once the base fee is below this many gwei, checked in the background for up to a day. Use when the user asks to send when gas is cheap"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "contract_call".to_string(),
+            description: "Read-only call to a smart contract function using an ABI from assets/abis, with decoded return values. Omit 'abi' to list available ABIs, or omit 'function' to list an ABI's functions".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "abi": {
+                        "type": "string",
+                        "description": "Name of the ABI file in assets/abis without extension, e.g. 'ERC20'"
+                    },
+                    "address": {
+                        "type": "string",
+                        "description": "Contract address or ENS name"
+                    },
+                    "function": {
+                        "type": "string",
+                        "description": "Function name, or full signature such as 'balanceOf(address)' for overloaded functions"
+                    },
+                    "args": {
+                        "type": "array",
+                        "description": "Function arguments in ABI order. Use strings for addresses and large integers, nested arrays for tuples and arrays",
+                        "items": {}
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "contract_send".to_string(),
+            description: "Send a state-changing transaction to a smart contract function using an ABI from assets/abis. The call is simulated first and not broadcast if it would revert".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "abi": {
+                        "type": "string",
+                        "description": "Name of the ABI file in assets/abis without extension, e.g. 'ERC20'"
+                    },
+                    "address": {
+                        "type": "string",
+                        "description": "Contract address or ENS name"
+                    },
+                    "function": {
+                        "type": "string",
+                        "description": "Function name, or full signature such as 'transfer(address,uint256)' for overloaded functions"
+                    },
+                    "args": {
+                        "type": "array",
+                        "description": "Function arguments in ABI order. Use strings for addresses and large integers, nested arrays for tuples and arrays",
+                        "items": {}
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name (e.g. 'trading-1'), HD wallet account index or label, 'ledger'/'ledger:N' for a connected Ledger account, or 'kms' for the KMS key, to send from instead of from_address"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Sender's Ethereum address or ENS name"
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "Optional amount to attach for payable functions, in ETH by default or with a unit (wei, gwei, ETH)"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "max_fee_per_gas": {
+                        "type": "string",
+                        "description": "Optional max fee per gas in gwei (gas price on legacy chains)"
+                    },
+                    "max_priority_fee_per_gas": {
+                        "type": "string",
+                        "description": "Optional max priority fee (tip) per gas in gwei"
+                    },
+                    "max_base_fee": {
+                        "type": "number",
+                        "description": "Optional: Only broadcast once the base fee is below this many gwei, checked in the background for up to a day. Use when the user asks to send when gas is cheap"
+                    }
+                },
+                "required": ["abi", "address", "function"]
+            }),
+        },
+        Tool {
+            name: "chain_query".to_string(),
+            description: "Look up a block, or a transaction and its receipt, on the configured chain, with the call and logs decoded (into events such as token transfers and swaps) from known ABIs and signature databases. Use it when the user asks what happened in a transaction or block, or what some calldata or selector means".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["block", "transaction", "receipt", "decode"],
+                        "description": "'block' (a block by number or hash), 'transaction' (a transaction with its call, receipt, and logs decoded), 'receipt' (only the outcome and logs of a transaction), or 'decode' (what calldata, a function selector, or an event topic stands for)"
+                    },
+                    "block": {
+                        "type": "string",
+                        "description": "Block number, block hash, or 'latest' (the default), 'finalized', 'safe', or 'pending' for 'block'"
+                    },
+                    "hash": {
+                        "type": "string",
+                        "description": "Transaction hash for 'transaction' and 'receipt'"
+                    },
+                    "data": {
+                        "type": "string",
+                        "description": "Hex calldata, 4-byte selector, or 32-byte event topic for 'decode'"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "dune_query".to_string(),
+            description: "Run a saved Dune Analytics query by ID (or by a name configured under [dune.queries]) with optional parameters, and get its result table. Use it for analytics that need indexed history, such as volumes, holders, or flows. Queries can take a while and cost Dune credits; use 'latest' when a recent stored result will do".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": ["string", "integer"],
+                        "description": "Dune query ID, e.g. 1234567, or a configured query name"
+                    },
+                    "parameters": {
+                        "type": "object",
+                        "description": "Query parameters by name, e.g. {\"token\": \"0x...\", \"days\": 30}"
+                    },
+                    "latest": {
+                        "type": "boolean",
+                        "description": "Return the query's last stored result instead of running it again"
+                    },
+                    "execution_id": {
+                        "type": "string",
+                        "description": "Fetch the result of an earlier execution that was still running, instead of starting a new one"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "web_search".to_string(),
+            description: "Search the web and get result titles, URLs, and snippets. Use it for protocols, exploits, announcements, or news that may be newer than your training data, and cite the URLs you rely on".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Search query, e.g. 'Euler Finance exploit March 2023'"
+                    },
+                    "count": {
+                        "type": "integer",
+                        "description": "Number of results, at most 10 (default 5)"
+                    }
+                },
+                "required": ["query"]
+            }),
+        },
+        Tool {
+            name: "fetch_url".to_string(),
+            description: "Download a web page and get its readable text, without navigation and other boilerplate, cut to a token budget. Use it to read or summarize a link the user gives, such as a governance proposal, or a page found with web_search. Only public http(s) URLs can be fetched".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "http or https URL of the page"
+                    },
+                    "max_tokens": {
+                        "type": "integer",
+                        "description": "Most tokens of text to return (default and limit 4000)"
+                    }
+                },
+                "required": ["url"]
+            }),
+        },
+        Tool {
+            name: "read_file".to_string(),
+            description: "Read a text file from the workspace directory, such as a report or export saved earlier".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path relative to the workspace, e.g. 'reports/balances.csv'"
+                    }
+                },
+                "required": ["path"]
+            }),
+        },
+        Tool {
+            name: "write_file".to_string(),
+            description: "Save a text file in the workspace directory, such as a report, a CSV export, or a generated ABI, when the user asks for one. Directories are created as needed, and an existing file is overwritten unless 'append' is set".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path relative to the workspace, e.g. 'reports/balances.csv'"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Text to write"
+                    },
+                    "append": {
+                        "type": "boolean",
+                        "description": "Add to the end of the file instead of replacing it"
+                    }
+                },
+                "required": ["path", "content"]
+            }),
+        },
+        Tool {
+            name: "list_dir".to_string(),
+            description: "List the files and directories in the workspace directory, or in one of its subdirectories".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory relative to the workspace (default: the workspace itself)"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "calc".to_string(),
+            description: "Evaluate arithmetic exactly, with no floating-point rounding. Use it for any math on token amounts, wei conversions, fees, percentages, or PnL instead of computing in your head. Supports + - * / ^, parentheses, abs/min/max, units wei/gwei/eth, % and bps, 'of' (0.3% of 2 eth), and converting with 'in' (1.5 eth in gwei)".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "expression": {
+                        "type": "string",
+                        "description": "The expression, e.g. '(2.5 eth - 21000 * 30 gwei) in eth' or '1234.5 * 0.997 ^ 3'"
+                    }
+                },
+                "required": ["expression"]
+            }),
+        },
+        Tool {
+            name: "generate_report".to_string(),
+            description: "Build a transaction history report for an address on the configured chain: its ETH transactions, internal transfers, and ERC-20 transfers over a date range, with totals received and sent per asset and the gas it paid. The report is written to the workspace as CSV (for spreadsheets and tax tools) or Markdown, and a summary is returned. It has no prices; use it for amounts, not USD values".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "address": {
+                        "type": "string",
+                        "description": "Address, ENS name, or the name of one of the user's accounts"
+                    },
+                    "from_date": {
+                        "type": "string",
+                        "description": "First day included, YYYY-MM-DD in UTC (default: January 1st of this year)"
+                    },
+                    "to_date": {
+                        "type": "string",
+                        "description": "Last day included, YYYY-MM-DD in UTC (default: today)"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["csv", "markdown"],
+                        "description": "File format (default csv)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Where to write it in the workspace (default: reports/<address>_<from>_<to>.csv or .md)"
+                    }
+                },
+                "required": ["address"]
+            }),
+        },
+        Tool {
+            name: "pnl".to_string(),
+            description: "Cost basis and profit and loss of the user's accounts on the configured chain. 'positions' lists each token held with its average cost, current value, unrealized PnL, and the PnL realized by sales, at current CoinGecko prices. Swaps and ETH sends the agent makes are recorded automatically; 'import' records an account's earlier buys, sells, and transfers from the explorer API at daily prices. Transfers in count as bought at their market price, transfers out leave at average cost; gas is not counted".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["positions", "import"],
+                        "description": "'positions' to show cost basis and PnL, or 'import' to record an account's history from the explorer"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Address, ENS name, or the name of one of the user's accounts; required for 'import', and limits 'positions' to it"
+                    },
+                    "token": {
+                        "type": "string",
+                        "description": "Optional symbol or contract address to limit 'positions' to"
+                    },
+                    "from_date": {
+                        "type": "string",
+                        "description": "For 'import': first day included, YYYY-MM-DD in UTC (default: January 1st of this year)"
+                    },
+                    "to_date": {
+                        "type": "string",
+                        "description": "For 'import': last day included, YYYY-MM-DD in UTC (default: today)"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "snapshot".to_string(),
+            description: "Snapshot off-chain governance: list the active proposals of the configured spaces (or of a given one), read a proposal in full to summarize it for the user, and vote. Votes are signed as EIP-712 typed data and cost no gas. Before voting, show the user the proposal, the choice, and the voting account, and only call 'vote' with confirm=true after they explicitly confirmed".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["proposals", "proposal", "vote"],
+                        "description": "'proposals' (active ones), 'proposal' (one in full, with its text), or 'vote'"
+                    },
+                    "space": {
+                        "type": "string",
+                        "description": "For 'proposals': a space such as 'aave.eth' instead of the configured ones"
+                    },
+                    "proposal_id": {
+                        "type": "string",
+                        "description": "Proposal id for 'proposal' and 'vote'"
+                    },
+                    "choice": {
+                        "description": "For 'vote': the choice by number (from 1) or text; a list of them for approval and ranked-choice proposals (ranking every choice, best first); an object of weights by choice for weighted and quadratic ones"
+                    },
+                    "reason": {
+                        "type": "string",
+                        "description": "Optional public reason recorded with the vote"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "For 'vote': the voting address, ENS name, or name of one of the user's accounts"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true for 'vote'; set it only after the user confirmed the vote"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "governor".to_string(),
+            description: "On-chain governance through OpenZeppelin Governor contracts on the configured chain: list recent proposals with their state and tally, read a proposal in full to summarize it for the user, quote an account's voting power, and vote with a castVote transaction. Before voting, show the user the proposal, the support, and the voting account, and only call 'vote' with confirm=true after they explicitly confirmed".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["proposals", "proposal", "voting_power", "vote"],
+                        "description": "'proposals' (recent ones, newest first), 'proposal' (one in full, with its description and actions), 'voting_power', or 'vote'"
+                    },
+                    "governor": {
+                        "type": "string",
+                        "description": "Name of a Governor configured under [governor], or its address; may be left out when only one is configured"
+                    },
+                    "proposal_id": {
+                        "type": "string",
+                        "description": "Proposal id for 'proposal' and 'vote', and optionally for 'voting_power' (power at its snapshot instead of now)"
+                    },
+                    "support": {
+                        "type": "string",
+                        "enum": ["for", "against", "abstain"],
+                        "description": "For 'vote': how to vote"
+                    },
+                    "reason": {
+                        "type": "string",
+                        "description": "Optional public reason recorded with the vote"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "For 'voting_power' and 'vote': the voting address, ENS name, or name of one of the user's accounts"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true for 'vote'; set it only after the user confirmed the vote"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "stake".to_string(),
+            description: "Liquid staking of ETH with Lido (stETH) or Rocket Pool (rETH) on the configured chain: show exchange rates and deposit limits, an account's staked balance and pending Lido withdrawals, deposit ETH, withdraw (Lido: request through the withdrawal queue, then claim once finalized; Rocket Pool: burn rETH for ETH), and claim finalized Lido withdrawals. Transactions go through the transaction policy. Show the user the quote from a first call without confirm, and only repeat it with confirm=true after they explicitly confirmed".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["rates", "position", "deposit", "withdraw", "claim"],
+                        "description": "'rates' (exchange rates, fees, limits), 'position' (staked balance and Lido withdrawal requests), 'deposit', 'withdraw', or 'claim'"
+                    },
+                    "protocol": {
+                        "type": "string",
+                        "description": "'lido' or 'rocket_pool' (as configured under [staking]); left out, 'rates' and 'position' cover every protocol on the chain"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "ETH to deposit, or stETH/rETH to withdraw, e.g. '0.5'"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "The staking address, ENS name, or name of one of the user's accounts"
+                    },
+                    "request_id": {
+                        "type": "string",
+                        "description": "For 'claim': the Lido withdrawal request id, as shown by 'position'"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true to send a deposit, withdrawal, or claim; set it only after the user confirmed the quote"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "lending".to_string(),
+            description: "Lend and borrow on the Aave V3 and Compound V3 money markets configured for this chain: check an account's health factor, supply or withdraw an asset, and borrow or repay one (on Compound only the market's base asset, such as USDC, is borrowed). Transactions go through the transaction policy. Show the user the quote from a first call without confirm, with its health factor change and any warning, and only repeat it with confirm=true after they explicitly confirmed. A withdrawal or borrow that would leave the health factor below the configured minimum also needs accept_risk=true, set only after the user has seen that warning and accepted the liquidation risk".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["health_factor", "supply", "withdraw", "borrow", "repay"],
+                        "description": "'health_factor' (collateral, debt, and health factor), 'supply', 'withdraw', 'borrow', or 'repay'"
+                    },
+                    "market": {
+                        "type": "string",
+                        "description": "The market as configured under [lending], e.g. 'aave' or 'compound_usdc'; left out, 'health_factor' covers every market on the chain"
+                    },
+                    "asset": {
+                        "type": "string",
+                        "description": "Token symbol from [dex.tokens] or contract address; lend WETH rather than ETH. On Compound, borrow and repay default to the base asset"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "Amount of the asset, e.g. '0.5', or 'all' to withdraw the whole supply or repay the whole debt"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "The lending address, ENS name, or name of one of the user's accounts"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true to send a supply, withdrawal, borrow, or repayment; set it only after the user confirmed the quote"
+                    },
+                    "accept_risk": {
+                        "type": "boolean",
+                        "description": "Must also be true for a withdrawal or borrow that would leave the health factor below the minimum; set it only after the user explicitly accepted that warning"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "bridge".to_string(),
+            description: "Move ETH or an ERC-20 token from the configured chain to another chain through the LI.FI bridge aggregator: 'quote' a route (bridge, amount received, fees, time), 'execute' it, or check the 'status' of a transfer until it arrives. Always 'quote' first and show the user the route; only 'execute' with confirm=true after they explicitly confirmed it. Sent transfers are tracked until they arrive on the destination chain".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["quote", "execute", "status"],
+                        "description": "'quote' to preview a transfer, 'execute' to sign and send it, 'status' to see where a sent one is"
+                    },
+                    "token": {
+                        "type": "string",
+                        "description": "Token to send: ETH, a symbol from [dex.tokens], or a contract address on the configured chain"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "Amount of the token to send, e.g. '0.5'"
+                    },
+                    "to_chain": {
+                        "type": "string",
+                        "description": "Destination chain, by name (e.g. 'Base', 'Arbitrum') or chain id"
+                    },
+                    "to_token": {
+                        "type": "string",
+                        "description": "Token to receive, as a symbol or an address on the destination chain; left out, the same token as sent"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "The sending address, ENS name, or name of one of the user's accounts"
+                    },
+                    "recipient": {
+                        "type": "string",
+                        "description": "Address receiving on the destination chain; left out, the sender's own address"
+                    },
+                    "tx_hash": {
+                        "type": "string",
+                        "description": "For 'status': hash of the transaction that sent the transfer"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true to execute; set it only after the user confirmed the quote"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "approvals".to_string(),
+            description: "Audit the token approvals an account has granted on the configured chain: ERC-20 allowances and NFT approvals still in force, with unlimited approvals to unverified contracts (or to accounts that aren't contracts) flagged. 'revoke' removes one. Show the user the revocation from a first call without confirm and only repeat it with confirm=true after they explicitly confirmed".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["list", "revoke"],
+                        "description": "'list' the outstanding approvals, or 'revoke' one"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "The approving address, ENS name, or name of one of the user's accounts"
+                    },
+                    "token": {
+                        "type": "string",
+                        "description": "For 'revoke': the token or NFT contract address, or a symbol from [dex.tokens]"
+                    },
+                    "spender": {
+                        "type": "string",
+                        "description": "For 'revoke': the approved spender or operator address"
+                    },
+                    "token_id": {
+                        "type": "string",
+                        "description": "For 'revoke': the NFT id, for an approval of a single ERC-721 token"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true to send a revocation; set it only after the user confirmed it"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    }
+                },
+                "required": ["operation", "account"]
+            }),
+        },
+        Tool {
+            name: "dex_swap".to_string(),
+            description: "Quote or execute an exact-input token swap through Uniswap V3 on the configured chain. Always 'quote' first and show the user the expected output, minimum output, and price impact; only call 'execute' with confirm=true after the user has explicitly confirmed that quote".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["quote", "execute"],
+                        "description": "'quote' to preview the swap, 'execute' to sign and send it"
+                    },
+                    "token_in": {
+                        "type": "string",
+                        "description": "Token to sell: 'ETH', a symbol from the configured token list (e.g. 'USDC'), or an ERC-20 address"
+                    },
+                    "token_out": {
+                        "type": "string",
+                        "description": "Token to buy, in the same forms as token_in"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "Exact amount of token_in to sell, in whole tokens (e.g. '0.1')"
+                    },
+                    "fee": {
+                        "type": "integer",
+                        "description": "Optional pool fee tier: 100, 500, 3000, or 10000 (defaults to the configured tier)"
+                    },
+                    "slippage_bps": {
+                        "type": "integer",
+                        "description": "Optional slippage tolerance in basis points (50 = 0.5%)"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true for 'execute'; set it only after the user confirmed the quote"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name, HD wallet account index or label (or 'ledger'/'ledger:N', or 'kms') to swap from, instead of from_address"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Address (or ENS name) that sells token_in and receives token_out, for 'execute'"
+                    }
+                },
+                "required": ["operation", "token_in", "token_out", "amount"]
+            }),
+        },
+        Tool {
+            name: "sign_message".to_string(),
+            description: "Sign a personal message (EIP-191 personal_sign) or EIP-712 typed data with a wallet, e.g. for off-chain orders, Snapshot votes, or Sign-In with Ethereum, or verify a signature by recovering its signer. Signatures can authorize actions such as token permits, so only sign what the user explicitly asked to sign".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["personal_sign", "sign_typed_data", "verify_signature"],
+                        "description": "'personal_sign' to sign 'message', 'sign_typed_data' to sign 'typed_data', or 'verify_signature' to recover the signer of 'signature' over either"
+                    },
+                    "message": {
+                        "type": "string",
+                        "description": "Message text for 'personal_sign' or verification; 0x-prefixed hex is signed as raw bytes"
+                    },
+                    "typed_data": {
+                        "type": "object",
+                        "description": "EIP-712 typed data as used by eth_signTypedData_v4, with 'types', 'primaryType', 'domain' and 'message'"
+                    },
+                    "signature": {
+                        "type": "string",
+                        "description": "0x-prefixed 65-byte signature for 'verify_signature'"
+                    },
+                    "address": {
+                        "type": "string",
+                        "description": "Signing address (or ENS name), or for 'verify_signature' the expected signer to compare against"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name, HD wallet account index or label (or 'ledger'/'ledger:N', or 'kms') to sign with, instead of address"
+                    },
+                    "confirm": {
+                        "type": "boolean",
+                        "description": "Must be true for 'sign_typed_data'; set it only after the user confirmed the domain and message shown to them"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "portfolio".to_string(),
+            description: "Portfolio snapshot: the native and ERC-20 token balances of one or more addresses across the configured chains, valued in USD with totals. Balances are read in one batch per chain (Multicall3), so use this instead of checking balances one by one".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "addresses": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Addresses, ENS names, or HD wallet account indexes/labels. Defaults to every derived HD wallet account"
+                    },
+                    "tokens": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "ERC-20 contract addresses or configured symbols (e.g. 'USDC') to check on the main chain. Defaults to the configured tokens; other chains always use their configured tokens"
+                    },
+                    "include_zero": {
+                        "type": "boolean",
+                        "description": "Also list zero balances (default false)"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "nft".to_string(),
+            description: "NFTs (ERC-721 and ERC-1155): list the NFTs an address owns, show a token's metadata and image, transfer a token, or mint on the configured contract. Confirm the details with the user before transferring or minting".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["owned", "metadata", "transfer", "mint"],
+                        "description": "'owned' (NFTs held by 'owner'), 'metadata' (token URI, name, image, and attributes), 'transfer', or 'mint'"
+                    },
+                    "owner": {
+                        "type": "string",
+                        "description": "Address or ENS name whose NFTs to list for 'owned'"
+                    },
+                    "contract": {
+                        "type": "string",
+                        "description": "NFT contract address. Optional for 'owned' (filters the list) and 'mint' (defaults to the configured contract)"
+                    },
+                    "token_id": {
+                        "type": "string",
+                        "description": "Token ID (decimal or 0x hex) for 'metadata' and 'transfer', or with 'owned' and a contract to check a single token"
+                    },
+                    "to": {
+                        "type": "string",
+                        "description": "Recipient for 'transfer' and 'mint': address, ENS name, or address book label"
+                    },
+                    "amount": {
+                        "type": "string",
+                        "description": "Number of ERC-1155 tokens to transfer, or the quantity for mint functions that take one (default 1)"
+                    },
+                    "token_uri": {
+                        "type": "string",
+                        "description": "Metadata URI for 'mint', e.g. ipfs://<cid>"
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "ETH paid to the contract for 'mint', e.g. '0.01' (default 0)"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Broadcast even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name, HD wallet account index or label (or 'ledger'/'ledger:N', or 'kms') to send from, instead of from_address"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Sender for 'transfer' and 'mint'"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "ipfs".to_string(),
+            description: "Publish content to IPFS and read it back: pin a JSON document (such as NFT metadata) or a file and get its CID, or fetch content by CID".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["pin_json", "pin_file", "fetch"],
+                        "description": "'pin_json' (pin 'content' as JSON), 'pin_file' (pin a file from the uploads directory, or 'content' as a text file), or 'fetch' (read content by 'cid')"
+                    },
+                    "content": {
+                        "description": "For 'pin_json', the JSON document (an object, or a string of JSON). For 'pin_file', text to store as the file's content instead of reading 'path'"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File to pin for 'pin_file', relative to the uploads directory"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Name for the pinned content, e.g. 'metadata.json' or 'report.md'"
+                    },
+                    "cid": {
+                        "type": "string",
+                        "description": "CID to fetch, optionally with a path, or an ipfs:// URI"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "safe_multisig".to_string(),
+            description: "Operate a Safe (Gnosis Safe) multisig through the Safe Transaction Service: show its owners and threshold, list pending proposals, propose a transaction from the Safe, confirm a proposal as an owner, or execute it once enough owners confirmed. Confirm the details with the user before proposing, confirming, or executing".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["info", "pending", "propose", "confirm", "execute"],
+                        "description": "'info' (owners, threshold, nonce), 'pending' (unexecuted proposals), 'propose', 'confirm', or 'execute'"
+                    },
+                    "safe": {
+                        "type": "string",
+                        "description": "Safe address; defaults to the configured Safe"
+                    },
+                    "to": {
+                        "type": "string",
+                        "description": "Recipient or contract the Safe calls, for 'propose'"
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "ETH the Safe sends with the call for 'propose', e.g. '0.1' or '500 gwei' (default 0)"
+                    },
+                    "data": {
+                        "type": "string",
+                        "description": "Optional 0x-prefixed calldata for 'propose'; alternatively give abi, function, and args"
+                    },
+                    "abi": {
+                        "type": "string",
+                        "description": "ABI name in assets/abis to encode the call for 'propose'"
+                    },
+                    "function": {
+                        "type": "string",
+                        "description": "Function to call on 'to' for 'propose', with 'abi'"
+                    },
+                    "args": {
+                        "type": "array",
+                        "description": "Function arguments in ABI order, with 'abi' and 'function'",
+                        "items": {}
+                    },
+                    "safe_tx_hash": {
+                        "type": "string",
+                        "description": "Safe transaction hash of the proposal for 'confirm' and 'execute'"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Execute even if the simulation reverts. Only set this when the user explicitly insists after seeing the failed simulation"
+                    },
+                    "private": {
+                        "type": "boolean",
+                        "description": "Send through the chain's private RPC (e.g. Flashbots Protect) instead of the public mempool, so it can't be frontrun and isn't included if it would revert. Leave unset for the configured default"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name, HD wallet account index or label (or 'ledger'/'ledger:N', or 'kms') of the owner (or executor), instead of from_address"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Owner address that proposes or confirms, or any address that pays gas to execute"
+                    }
+                },
+                "required": ["operation"]
+            }),
+        },
+        Tool {
+            name: "smart_account".to_string(),
+            description: "ERC-4337 smart account (SimpleAccount) owned by a wallet: show its address, deploy it, or send a call from it as a UserOperation through the configured bundler. With a paymaster configured, gas is sponsored. Confirm the details with the user before sending".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["address", "deploy", "send"],
+                        "description": "'address' (counterfactual address, deployment status, balance), 'deploy', or 'send'"
+                    },
+                    "to": {
+                        "type": "string",
+                        "description": "Recipient or contract the account calls, for 'send'"
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "ETH sent from the account for 'send', e.g. '0.01' or '500 gwei' (default 0)"
+                    },
+                    "data": {
+                        "type": "string",
+                        "description": "Optional 0x-prefixed calldata for 'send'; alternatively give abi, function, and args"
+                    },
+                    "abi": {
+                        "type": "string",
+                        "description": "ABI name in assets/abis to encode the call for 'send'"
+                    },
+                    "function": {
+                        "type": "string",
+                        "description": "Function to call on 'to' for 'send', with 'abi'"
+                    },
+                    "args": {
+                        "type": "array",
+                        "description": "Function arguments in ABI order, with 'abi' and 'function'",
+                        "items": {}
+                    },
+                    "sponsored": {
+                        "type": "boolean",
+                        "description": "Use the configured paymaster to pay for gas (default true when one is configured)"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only simulate and report what would happen, without sending anything. Use it when the user wants to preview an operation"
+                    },
+                    "salt": {
+                        "type": "integer",
+                        "description": "Salt selecting one of the owner's accounts (default 0)"
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Keystore account name, HD wallet account index or label (or 'ledger'/'ledger:N', or 'kms') of the owner, instead of from_address"
+                    },
+                    "from_address": {
+                        "type": "string",
+                        "description": "Owner address of the smart account"
+                    }
+                },
+                "required": ["operation"]
+            }),
         },
     ]
 }
 
+/// The tools a persona may use. Tools restricted to some operations keep only those
+/// operations in the `operation` enum of their schema.
+pub fn tools_for(personality: Option<&Personality>) -> Vec<Tool> {
+    let Some(personality) = personality else { return get_available_tools() };
+    get_available_tools().into_iter()
+        .filter_map(|mut tool| match personality.allowed_operations(&tool.name) {
+            None => Some(tool),
+            Some(operations) if operations.is_empty() => None,
+            Some(operations) => {
+                if let Some(operation) = tool.input_schema.pointer_mut("/properties/operation") {
+                    operation["enum"] = serde_json::json!(operations);
+                }
+                Some(tool)
+            },
+        })
+        .collect()
+}
+
 pub fn get_tools_as_json() -> anyhow::Result<String> {
     let tools = get_available_tools();
     Ok(serde_json::to_string_pretty(&tools)?)
 }
 
-pub async fn execute_tool(name: &str, args: &serde_json::Value) -> anyhow::Result<String> {
+/// What tools run with: the configuration and transaction policy, plus where invocations
+/// are recorded (the database pool and the session they belong to) and where the session's
+/// history is kept
+#[derive(Clone)]
+pub struct ToolContext {
+    pub config: Arc<Config>,
+    pub policy: Arc<PolicyEngine>,
+    pub pool: Option<Pool<Postgres>>,
+    /// Where chat history is kept: the database, memory without one, or a store of the
+    /// embedding program's own
+    pub store: Arc<dyn MessageStore>,
+    pub session_id: Option<Uuid>,
+    /// The stored user message whose turn the tools run in, which their audit records link to
+    pub message_id: Option<i32>,
+    /// Token usage of the model calls made with this context
+    pub usage: Arc<Mutex<UsageTotals>>,
+    /// Simulate value-moving operations instead of sending them
+    pub dry_run: bool,
+    /// Collects the model's extended thinking, for frontends that show it (`--verbose`)
+    pub thinking: Option<Arc<Mutex<Vec<String>>>>,
+    /// Collects a line as each tool call starts and finishes, for frontends that show live
+    /// activity (`--tui`)
+    pub activity: Option<Arc<Mutex<Vec<String>>>>,
+    /// Collects the answer's text as it streams in from the model, for frontends that show
+    /// it as it is written (`/ws`)
+    pub deltas: Option<Arc<Mutex<Vec<String>>>>,
+    /// Address of the signed-in user the tools act for, in server mode; their keystore
+    /// accounts are kept apart from the deployment's and other users'
+    pub user: Option<String>,
+    /// That user's role, which limits the calls they may have the tools make; None allows all
+    pub role: Option<Role>,
+}
+
+impl ToolContext {
+    pub fn new(config: Arc<Config>, policy: Arc<PolicyEngine>, pool: Option<Pool<Postgres>>, session_id: Option<Uuid>) -> Self {
+        ToolContext {
+            dry_run: config.dry_run,
+            config,
+            policy,
+            store: match &pool {
+                Some(pool) => cluster::cached_store(Arc::new(pool.clone())),
+                None => Arc::new(MemoryStore::new()),
+            },
+            pool,
+            session_id,
+            message_id: None,
+            usage: Arc::default(),
+            thinking: None,
+            activity: None,
+            deltas: None,
+            user: None,
+            role: None,
+        }
+    }
+
+    /// Directory of the workspace tools: the user's own under `[workspace] dir`, or the
+    /// directory itself outside server mode
+    pub fn workspace_dir(&self) -> String {
+        match &self.user {
+            Some(user) => Path::new(&self.config.workspace.dir).join(user).to_string_lossy().into_owned(),
+            None => self.config.workspace.dir.clone(),
+        }
+    }
+
+    /// Directory the user's files are pinned to IPFS from: the user's own under `[ipfs]
+    /// uploads_dir`, or the directory itself outside server mode
+    pub fn uploads_dir(&self) -> String {
+        match &self.user {
+            Some(user) => Path::new(&self.config.ipfs.uploads_dir).join(user).to_string_lossy().into_owned(),
+            None => self.config.ipfs.uploads_dir.clone(),
+        }
+    }
+
+    /// Keystore directory of the accounts the tools may use: the user's own under
+    /// `[signer] keystore_dir`, or the directory itself outside server mode
+    pub fn keystore_dir(&self) -> String {
+        match &self.user {
+            Some(user) => Path::new(&self.config.signer.keystore_dir).join(user).to_string_lossy().into_owned(),
+            None => self.config.signer.keystore_dir.clone(),
+        }
+    }
+}
+
+/// Execute a tool, stopping it once it runs past its `[tools]` timeout, and record the
+/// invocation in the `tool_calls` audit table. Tools with a cache TTL reuse the output of an
+/// identical recent call.
+#[tracing::instrument(name = "tool_call", skip(ctx, args), fields(session_id = ?ctx.session_id))]
+pub async fn execute_tool(ctx: &ToolContext, name: &str, args: &serde_json::Value) -> Result<String, ToolError> {
+    let started = Instant::now();
+    let call = match args.get("operation").and_then(|v| v.as_str()) {
+        Some(operation) => format!("{}.{}", name, operation),
+        None => name.to_string(),
+    };
+    if let Some(activity) = &ctx.activity {
+        activity.lock().unwrap().push(format!("→ {}", call));
+    }
+    // Refused calls are recorded like failed ones
+    let result = match check_role(ctx.role, name, args) {
+        Ok(()) => run_tool(ctx, name, args).await,
+        Err(e) => Err(e),
+    };
+    let duration_ms = started.elapsed().as_millis() as i64;
+    
+    // Failures are stored as the JSON the model gets, so the audit log keeps their kind
+    let (output, success) = match &result {
+        Ok(output) => (output.clone(), true),
+        Err(e) => (e.to_json(), false),
+    };
+    tracing::info!(duration_ms, success, error = result.as_ref().err().map(ToolError::kind), "Tool call finished");
+    if let Some(activity) = &ctx.activity {
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.kind().to_string(),
+        };
+        activity.lock().unwrap().push(format!("{} {} ({} ms)", outcome, call, duration_ms));
+    }
+    
+    if let Some(pool) = &ctx.pool
+        && let Err(e) = save_tool_call(pool, ctx.session_id, ctx.message_id, name, args, &output, duration_ms, success).await
+    {
+        tracing::error!(error = %e, "Failed to record tool call");
+    }
+    
+    // Links aren't part of what the tool said, so the audit log and the cache keep it as it was
+    result.map(|output| chains::with_links(&ctx.config.chain, output))
+}
+
+// A call's output, from the cache or else from the tool, within its timeout
+async fn run_tool(ctx: &ToolContext, name: &str, args: &serde_json::Value) -> Result<String, ToolError> {
+    let cache_ttl = tool_cache::ttl(&ctx.config.tools.cache_ttl_secs, name, args);
+    let cache_key = tool_cache::key(ctx.config.chain.chain_id, ctx.user.as_deref(), ctx.dry_run, name, args);
+    if cache_ttl.is_some()
+        && let Some(output) = tool_cache::get(cache_key).await
+    {
+        tracing::debug!("Using cached tool output");
+        return Ok(output);
+    }
+    let timeout_secs = ctx.config.tools.timeouts.get(name).copied().unwrap_or(ctx.config.tools.timeout_secs);
+    let result = match tokio::time::timeout(std::time::Duration::from_secs(timeout_secs), dispatch_tool(ctx, name, args)).await {
+        Ok(result) => result,
+        // Stopping a send can't take back a transaction already broadcast
+        Err(_) => Err(ToolError::Timeout(format!("{} did not finish within {} seconds and was stopped. \
+                                                  If it was sending a transaction, that may have gone out anyway; check before trying again.",
+                                                 name, timeout_secs))),
+    };
+    if let (Some(ttl), Ok(output)) = (cache_ttl, &result) {
+        tool_cache::put(cache_key, ttl, output).await;
+    }
+    result
+}
+
+/// Execute a tool on behalf of a persona, refusing calls outside its allowlist
+pub async fn execute_tool_as(ctx: &ToolContext, personality: Option<&Personality>, name: &str, args: &serde_json::Value) -> Result<String, ToolError> {
+    let operation = args.get("operation").and_then(|v| v.as_str());
+    match personality {
+        Some(persona) if !persona.allows_tool(name, operation) => {
+            tracing::warn!(tool = %name, operation, persona = %persona.name, "Refused tool call outside the persona's allowlist");
+            Err(ToolError::PolicyViolation(format!("{} is not allowed to use {}{}", persona.name, name,
+                                                   operation.map(|op| format!(".{}", op)).unwrap_or_default())))
+        },
+        _ => execute_tool(ctx, name, args).await,
+    }
+}
+
+async fn dispatch_tool(ctx: &ToolContext, name: &str, args: &serde_json::Value) -> Result<String, ToolError> {
+    // A call can ask for a dry run, but not send while dry-run mode is on
+    let dry_run_ctx;
+    let ctx = if !ctx.dry_run && args.get("dry_run").and_then(|v| v.as_bool()) == Some(true) {
+        dry_run_ctx = ToolContext { dry_run: true, ..ctx.clone() };
+        &dry_run_ctx
+    } else {
+        ctx
+    };
+    let legacy_args = legacy_private_key(ctx, name, args)?;
+    let args = legacy_args.as_ref().unwrap_or(args);
+    
     match name {
         "get_weather" => {
             let city = args.get("city")
@@ -65,6 +1486,125 @@ pub async fn execute_tool(name: &str, args: &serde_json::Value) -> anyhow::Resul
             
             get_time(timezone)
         },
+        "get_token_price" => {
+            let token = args.get("token")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            
+            get_token_price(ctx, token).await
+        },
+        "chainlink_price" => {
+            let pair = args.get("pair")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            
+            chainlink_price(ctx, pair).await
+        },
+        "gas_tracker" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("report");
+            
+            match operation {
+                "report" => gas_report(ctx).await,
+                "waiting" => Ok(gas_waiting(ctx)),
+                "cancel" => match args.get("wait_id").and_then(|v| v.as_u64()) {
+                    Some(id) if gas::cancel(ctx.session_id, id) => Ok(format!("Stopped waiting to make send #{}", id)),
+                    Some(id) => Err(ToolError::InvalidArgs(format!("No send #{} is waiting for gas", id))),
+                    None => Err(ToolError::InvalidArgs("Please provide the wait_id to cancel".to_string())),
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown gas tracker operation: {}", operation))),
+            }
+        },
+        "price_alert" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            
+            match operation {
+                "create" => {
+                    let token = args.get("token").and_then(|v| v.as_str()).unwrap_or("");
+                    let condition = args.get("condition").and_then(|v| v.as_str()).unwrap_or("");
+                    // Accept "2000" or "$2,000" as well as a number
+                    let price = args.get("price").and_then(|v| match v {
+                        serde_json::Value::String(text) => text.trim().trim_start_matches('$').replace(',', "").parse().ok(),
+                        other => other.as_f64(),
+                    });
+                    create_price_alert(ctx, token, condition, price).await
+                },
+                "list" => {
+                    let include_inactive = args.get("include_inactive").and_then(|v| v.as_bool()).unwrap_or(false);
+                    list_alerts(ctx, include_inactive).await
+                },
+                "cancel" => match args.get("alert_id").and_then(|v| v.as_i64()) {
+                    Some(id) => cancel_alert(ctx, id).await,
+                    None => Err(ToolError::InvalidArgs("Please provide the alert_id to cancel".to_string())),
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown price alert operation: {}", operation))),
+            }
+        },
+        "remember" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            
+            match operation {
+                "save" => {
+                    let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
+                    save_user_note(ctx, content).await
+                },
+                "list" => {
+                    let query = args.get("query").and_then(|v| v.as_str()).map(str::trim).filter(|query| !query.is_empty());
+                    list_user_notes(ctx, query).await
+                },
+                "forget" => match args.get("note_id").and_then(|v| v.as_i64()) {
+                    Some(id) => forget_note(ctx, id).await,
+                    None => Err(ToolError::InvalidArgs("Please provide the note_id to forget".to_string())),
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown remember operation: {}", operation))),
+            }
+        },
+        "remind_me" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            
+            match operation {
+                "create" => {
+                    let message = args.get("message").and_then(|v| v.as_str()).unwrap_or("");
+                    let when = args.get("when").and_then(|v| v.as_str()).unwrap_or("");
+                    create_reminder(ctx, message, when).await
+                },
+                "list" => {
+                    let include_inactive = args.get("include_inactive").and_then(|v| v.as_bool()).unwrap_or(false);
+                    list_user_reminders(ctx, include_inactive).await
+                },
+                "cancel" => match args.get("reminder_id").and_then(|v| v.as_i64()) {
+                    Some(id) => cancel_user_reminder(ctx, id).await,
+                    None => Err(ToolError::InvalidArgs("Please provide the reminder_id to cancel".to_string())),
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown reminder operation: {}", operation))),
+            }
+        },
+        "address_book" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let label = args.get("label")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            match operation {
+                "add" => {
+                    let address = args.get("address").and_then(|v| v.as_str()).unwrap_or("");
+                    let note = args.get("note").and_then(|v| v.as_str()).filter(|note| !note.trim().is_empty());
+                    add_contact(ctx, label, address, note).await
+                },
+                "list" => list_address_book(ctx).await,
+                "remove" => remove_contact(ctx, label).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown address book operation: {}", operation))),
+            }
+        },
         "eth_wallet" => {
             let operation = args.get("operation")
                 .and_then(|v| v.as_str())
@@ -72,345 +1612,4729 @@ pub async fn execute_tool(name: &str, args: &serde_json::Value) -> anyhow::Resul
             
             match operation {
                 "generate" => {
-                    eth_generate_wallet().await
+                    let name = args.get("label")
+                        .and_then(|v| v.as_str());
+                    
+                    eth_generate_wallet(ctx, name).await
                 },
                 "balance" => {
                     let address = args.get("address")
                         .and_then(|v| v.as_str())
                         .unwrap_or("");
                     
-                    eth_check_balance(address).await
+                    eth_check_balance(ctx, address).await
+                },
+                "mnemonic_generate" => {
+                    eth_generate_mnemonic(ctx).await
+                },
+                // The phrase would pass through the prompt and the audit log
+                "mnemonic_import" => {
+                    Err(ToolError::InvalidArgs("Mnemonics are not accepted as tool arguments. Import one with \
+                                               `wallet import-mnemonic`, then derive accounts from it".to_string()))
+                },
+                "derive" => {
+                    let index = args.get("index")
+                        .and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+                        .unwrap_or(0);
+                    let label = args.get("label")
+                        .and_then(|v| v.as_str());
+                    
+                    eth_derive_account(ctx, index, label).await
+                },
+                "accounts" => {
+                    eth_list_accounts(ctx)
+                },
+                "wallets" => {
+                    wallets_report(ctx).await
+                },
+                "connect_ledger" => {
+                    let index = args.get("index")
+                        .and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+                        .unwrap_or(0);
+                    
+                    eth_connect_ledger(ctx, index).await
+                },
+                "faucet" => {
+                    let address = match account_address(ctx, args).await {
+                        Ok(Some(address)) => address,
+                        Ok(None) => args.get("address")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+                    };
+                    
+                    eth_faucet(ctx, &address).await
+                },
+                "resolve" => {
+                    let name = args.get("name")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    
+                    eth_resolve_name(ctx, name).await
+                },
+                "lookup" => {
+                    let address = args.get("address")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    
+                    eth_lookup_address(ctx, address).await
                 },
                 "send" => {
                     // Check if we have a raw command string in the args
                     if let Some(raw_command) = args.get("raw_command").and_then(|v| v.as_str()) {
                         // Try to parse the natural language command
-                        return parse_and_execute_eth_send_command(raw_command).await;
+                        return parse_and_execute_eth_send_command(ctx, raw_command).await;
                     }
                     
                     // Otherwise use the structured parameters
-                    let from_address = args.get("from_address")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
+                    let from_address = match account_address(ctx, args).await {
+                        Ok(Some(address)) => address,
+                        Ok(None) => args.get("from_address")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+                    };
+                    let from_address = from_address.as_str();
                     let to_address = args.get("to_address")
                         .and_then(|v| v.as_str())
                         .unwrap_or("");
                     let amount = args.get("amount")
                         .and_then(|v| v.as_str())
                         .unwrap_or("0");
-                    let private_key = args.get("private_key")
-                        .and_then(|v| v.as_str());
+                    let fee_overrides = FeeOverrides {
+                        max_fee_per_gas: args.get("max_fee_per_gas").and_then(|v| v.as_str()),
+                        max_priority_fee_per_gas: args.get("max_priority_fee_per_gas").and_then(|v| v.as_str()),
+                    };
                     
-                    eth_send_eth(from_address, to_address, amount, private_key).await
+                    let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+                    
+                    // Incomplete sends fail now rather than after waiting
+                    if !from_address.is_empty() && !to_address.is_empty()
+                        && let Some(reply) = wait_for_gas(ctx, "eth_wallet", args, &format!("send {} to {}", amount, to_address)).await
+                    {
+                        return reply;
+                    }
+                    
+                    let private = args.get("private").and_then(|v| v.as_bool());
+                    
+                    eth_send_eth(ctx, from_address, to_address, amount, fee_overrides, force, private).await
+                },
+                "speed_up" | "cancel" => {
+                    let tx_hash = args.get("tx_hash")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    let fee_overrides = FeeOverrides {
+                        max_fee_per_gas: args.get("max_fee_per_gas").and_then(|v| v.as_str()),
+                        max_priority_fee_per_gas: args.get("max_priority_fee_per_gas").and_then(|v| v.as_str()),
+                    };
+                    let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+                    
+                    eth_replace_transaction(ctx, tx_hash, operation == "cancel", fee_overrides, force).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown Ethereum wallet operation: {}", operation))),
+            }
+        },
+        "contract_call" => {
+            let abi = args.get("abi")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let address = args.get("address")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let function = args.get("function")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let call_args = args.get("args")
+                .and_then(|v| v.as_array())
+                .cloned()
+                .unwrap_or_default();
+            
+            contract_call(ctx, abi, address, function, &call_args).await
+        },
+        "contract_send" => {
+            let abi = args.get("abi")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let address = args.get("address")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let function = args.get("function")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let call_args = args.get("args")
+                .and_then(|v| v.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let from_address = match account_address(ctx, args).await {
+                Ok(Some(address)) => address,
+                Ok(None) => args.get("from_address")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+            };
+            let from_address = from_address.as_str();
+            let value = args.get("value")
+                .and_then(|v| v.as_str())
+                .unwrap_or("0");
+            let fee_overrides = FeeOverrides {
+                max_fee_per_gas: args.get("max_fee_per_gas").and_then(|v| v.as_str()),
+                max_priority_fee_per_gas: args.get("max_priority_fee_per_gas").and_then(|v| v.as_str()),
+            };
+            
+            let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+            
+            let description = format!("call {} on {}", function, address);
+            if let Some(reply) = wait_for_gas(ctx, "contract_send", args, &description).await {
+                return reply;
+            }
+            
+            let private = args.get("private").and_then(|v| v.as_bool());
+            
+            contract_send(ctx, abi, address, function, &call_args, from_address, value, fee_overrides, force, private).await
+        },
+        "chain_query" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let hash = args.get("hash")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            
+            match operation {
+                "block" => {
+                    let block = args.get("block")
+                        .and_then(|v| v.as_str().map(str::to_string).or_else(|| v.as_u64().map(|number| number.to_string())))
+                        .unwrap_or_default();
+                    
+                    chain_query_block(ctx, &block).await
+                },
+                "transaction" => chain_query_transaction(ctx, hash, true).await,
+                "receipt" => chain_query_transaction(ctx, hash, false).await,
+                "decode" => {
+                    let data = args.get("data")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    
+                    chain_query_decode(ctx, data).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown chain query operation: {}", operation))),
+            }
+        },
+        "dune_query" => {
+            let query = args.get("query")
+                .and_then(|v| v.as_str().map(str::to_string).or_else(|| v.as_u64().map(|id| id.to_string())))
+                .unwrap_or_default();
+            let parameters = args.get("parameters")
+                .and_then(|v| v.as_object())
+                .cloned()
+                .unwrap_or_default();
+            let latest = args.get("latest").and_then(|v| v.as_bool()).unwrap_or(false);
+            let execution_id = args.get("execution_id")
+                .and_then(|v| v.as_str())
+                .filter(|id| !id.is_empty());
+            
+            dune_query(ctx, &query, &parameters, latest, execution_id).await
+        },
+        "web_search" => {
+            let query = args.get("query")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let count = args.get("count")
+                .and_then(|v| v.as_u64())
+                .map(|count| count as usize)
+                .unwrap_or(ctx.config.search.max_results);
+            
+            web_search(ctx, query, count).await
+        },
+        "fetch_url" => {
+            let url = args.get("url")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let max_tokens = args.get("max_tokens")
+                .and_then(|v| v.as_u64())
+                .map(|tokens| (tokens as usize).min(ctx.config.fetch.max_tokens))
+                .unwrap_or(ctx.config.fetch.max_tokens);
+            
+            fetch_url(ctx, url, max_tokens).await
+        },
+        "read_file" | "write_file" | "list_dir" => {
+            let path = args.get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let dir = ctx.workspace_dir();
+            let max_bytes = ctx.config.workspace.max_file_bytes;
+            
+            let result = match name {
+                "read_file" => workspace::read(&dir, path, max_bytes),
+                "write_file" => {
+                    let Some(content) = args.get("content").and_then(|v| v.as_str()) else {
+                        return Err(ToolError::InvalidArgs("Please provide the content to write".to_string()));
+                    };
+                    let append = args.get("append").and_then(|v| v.as_bool()).unwrap_or(false);
+                    workspace::write(&dir, path, content, append, max_bytes)
+                },
+                _ => workspace::list(&dir, path),
+            };
+            result.map_err(|e| ToolError::InvalidArgs(e.to_string()))
+        },
+        "calc" => {
+            let expression = args.get("expression")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            
+            calc::evaluate(expression)
+                .map(|result| format!("{} = {}", expression.trim(), result))
+                .map_err(|e| ToolError::InvalidArgs(e.to_string()))
+        },
+        "generate_report" => {
+            let address = args.get("address")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let date = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).filter(|date| !date.is_empty());
+            let format = args.get("format")
+                .and_then(|v| v.as_str())
+                .unwrap_or("csv");
+            let path = args.get("path")
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|path| !path.is_empty());
+            
+            generate_report(ctx, address, date("from_date"), date("to_date"), format, path).await
+        },
+        "pnl" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("positions");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).filter(|value| !value.is_empty());
+            
+            match operation {
+                "positions" => pnl_positions(ctx, text("account"), text("token")).await,
+                "import" => pnl_import(ctx, text("account").unwrap_or(""), text("from_date"), text("to_date")).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown pnl operation: {}", operation))),
+            }
+        },
+        "snapshot" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("proposals");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            
+            match operation {
+                "proposals" => snapshot_proposals(ctx, Some(text("space")).filter(|space| !space.is_empty())).await,
+                "proposal" => snapshot_proposal(ctx, text("proposal_id")).await,
+                "vote" => {
+                    let confirm = args.get("confirm").and_then(|v| v.as_bool()) == Some(true);
+                    snapshot_vote(ctx, text("proposal_id"), args.get("choice"), text("reason"), text("account"), confirm).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown snapshot operation: {}", operation))),
+            }
+        },
+        "governor" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("proposals");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            let governor = Some(text("governor")).filter(|governor| !governor.is_empty());
+            
+            match operation {
+                "proposals" => governor_proposals(ctx, governor).await,
+                "proposal" => governor_proposal(ctx, governor, text("proposal_id")).await,
+                "voting_power" => governor_voting_power(ctx, governor, text("account"), text("proposal_id")).await,
+                "vote" => {
+                    let vote = GovernorVote {
+                        proposal_id: text("proposal_id"),
+                        support: text("support"),
+                        reason: text("reason"),
+                        account: text("account"),
+                        confirm: args.get("confirm").and_then(|v| v.as_bool()) == Some(true),
+                        force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                        private: args.get("private").and_then(|v| v.as_bool()),
+                    };
+                    governor_vote(ctx, governor, &vote).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown governor operation: {}", operation))),
+            }
+        },
+        "stake" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("rates");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            let protocol = Some(text("protocol")).filter(|protocol| !protocol.is_empty());
+            let request = StakeRequest {
+                amount: text("amount"),
+                account: text("account"),
+                request_id: text("request_id"),
+                confirm: args.get("confirm").and_then(|v| v.as_bool()) == Some(true),
+                force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                private: args.get("private").and_then(|v| v.as_bool()),
+            };
+            
+            match operation {
+                "rates" => stake_rates(ctx, protocol).await,
+                "position" => stake_position(ctx, protocol, request.account).await,
+                "deposit" => stake_deposit(ctx, protocol, &request).await,
+                "withdraw" => stake_withdraw(ctx, protocol, &request).await,
+                "claim" => stake_claim(ctx, protocol, &request).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown stake operation: {}", operation))),
+            }
+        },
+        "lending" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("health_factor");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            let market = Some(text("market")).filter(|market| !market.is_empty());
+            let request = LendingRequest {
+                asset: text("asset"),
+                amount: text("amount"),
+                account: text("account"),
+                confirm: args.get("confirm").and_then(|v| v.as_bool()) == Some(true),
+                accept_risk: args.get("accept_risk").and_then(|v| v.as_bool()) == Some(true),
+                force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                private: args.get("private").and_then(|v| v.as_bool()),
+            };
+            
+            match operation {
+                "health_factor" => lending_health(ctx, market, request.account).await,
+                "supply" => lending_action(ctx, market, lending::Action::Supply, &request).await,
+                "withdraw" => lending_action(ctx, market, lending::Action::Withdraw, &request).await,
+                "borrow" => lending_action(ctx, market, lending::Action::Borrow, &request).await,
+                "repay" => lending_action(ctx, market, lending::Action::Repay, &request).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown lending operation: {}", operation))),
+            }
+        },
+        "bridge" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("quote");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            let request = BridgeRequest {
+                token: text("token"),
+                amount: text("amount"),
+                to_chain: text("to_chain"),
+                to_token: text("to_token"),
+                account: text("account"),
+                recipient: text("recipient"),
+                confirm: args.get("confirm").and_then(|v| v.as_bool()) == Some(true),
+                force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                private: args.get("private").and_then(|v| v.as_bool()),
+            };
+            
+            match operation {
+                "quote" => bridge_quote(ctx, &request).await,
+                "execute" => bridge_execute(ctx, &request).await,
+                "status" => bridge_status(ctx, text("tx_hash")).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown bridge operation: {}", operation))),
+            }
+        },
+        "approvals" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("list");
+            let text = |name: &str| args.get(name).and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+            let request = ApprovalRequest {
+                account: text("account"),
+                token: text("token"),
+                spender: text("spender"),
+                token_id: text("token_id"),
+                confirm: args.get("confirm").and_then(|v| v.as_bool()) == Some(true),
+                force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                private: args.get("private").and_then(|v| v.as_bool()),
+            };
+            
+            match operation {
+                "list" => approvals_list(ctx, request.account).await,
+                "revoke" => approvals_revoke(ctx, &request).await,
+                _ => Err(ToolError::InvalidArgs(format!("Unknown approvals operation: {}", operation))),
+            }
+        },
+        "dex_swap" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("quote");
+            let request = SwapRequest {
+                token_in: args.get("token_in").and_then(|v| v.as_str()).unwrap_or(""),
+                token_out: args.get("token_out").and_then(|v| v.as_str()).unwrap_or(""),
+                amount: args.get("amount").and_then(|v| v.as_str()).unwrap_or(""),
+                fee: args.get("fee").and_then(|v| v.as_u64()).map(|fee| fee as u32),
+                slippage_bps: args.get("slippage_bps").and_then(|v| v.as_u64()).map(|bps| bps as u32),
+            };
+            
+            match operation {
+                "quote" => dex_quote(ctx, &request).await,
+                "execute" => {
+                    if args.get("confirm").and_then(|v| v.as_bool()) != Some(true) {
+                        return Err(ToolError::InvalidArgs("Swaps need confirm=true. Quote first and ask the user to confirm before executing.".to_string()));
+                    }
+                    let from_address = match account_address(ctx, args).await {
+                        Ok(Some(address)) => address,
+                        Ok(None) => args.get("from_address")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+                    };
+                    let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+                    let private = args.get("private").and_then(|v| v.as_bool());
+                    
+                    dex_execute(ctx, &request, &from_address, force, private).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown swap operation: {}", operation))),
+            }
+        },
+        "sign_message" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let request = if let Some(typed_data) = args.get("typed_data").filter(|v| !v.is_null()) {
+                match signing::parse_typed_data(typed_data) {
+                    Ok(typed_data) => SignRequest::TypedData(Box::new(typed_data)),
+                    Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+                }
+            } else {
+                match args.get("message").and_then(|v| v.as_str()) {
+                    Some(message) => SignRequest::Message(signing::message_bytes(message)),
+                    None => return Err(ToolError::InvalidArgs("Either 'message' or 'typed_data' is required".to_string())),
+                }
+            };
+            let address = match account_address(ctx, args).await {
+                Ok(Some(address)) => address,
+                Ok(None) => args.get("address")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+            };
+            
+            match operation {
+                "personal_sign" | "sign_typed_data" => {
+                    if (operation == "personal_sign") != matches!(request, SignRequest::Message(_)) {
+                        return Err(ToolError::InvalidArgs(format!("'{}' needs {}", operation,
+                                          if operation == "personal_sign" { "'message'" } else { "'typed_data'" })));
+                    }
+                    
+                    let confirm = args.get("confirm").and_then(|v| v.as_bool()).unwrap_or(false);
+                    sign_payload(ctx, &request, &address, confirm).await
+                },
+                "verify_signature" => {
+                    let signature = args.get("signature")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    
+                    verify_signature(ctx, &request, signature, &address).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown signing operation: {}", operation))),
+            }
+        },
+        "portfolio" => {
+            let strings = |key: &str| -> Vec<String> {
+                match args.get(key) {
+                    Some(serde_json::Value::Array(items)) => items.iter()
+                        .filter_map(|item| item.as_str().map(str::to_string).or_else(|| item.as_u64().map(|n| n.to_string())))
+                        .collect(),
+                    // A single address given as a string
+                    Some(serde_json::Value::String(item)) => item.split(',').map(|item| item.trim().to_string()).collect(),
+                    _ => Vec::new(),
+                }
+            };
+            let include_zero = args.get("include_zero").and_then(|v| v.as_bool()).unwrap_or(false);
+            portfolio(ctx, &strings("addresses"), &strings("tokens"), include_zero).await
+        },
+        "nft" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let contract = args.get("contract")
+                .and_then(|v| v.as_str())
+                .filter(|contract| !contract.is_empty());
+            let token_id = args.get("token_id")
+                .and_then(|v| v.as_str().map(str::to_string).or_else(|| v.as_u64().map(|id| id.to_string())))
+                .unwrap_or_default();
+            
+            match operation {
+                "owned" => {
+                    let owner = args.get("owner")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    
+                    nft_owned(ctx, owner, contract, Some(token_id.as_str()).filter(|id| !id.is_empty())).await
+                },
+                "metadata" => nft_metadata(ctx, contract.unwrap_or(""), &token_id).await,
+                "transfer" | "mint" => {
+                    let from_address = match account_address(ctx, args).await {
+                        Ok(Some(address)) => address,
+                        Ok(None) => args.get("from_address")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+                    };
+                    let request = NftSend {
+                        from_address: &from_address,
+                        to: args.get("to").and_then(|v| v.as_str()).unwrap_or(""),
+                        amount: args.get("amount")
+                            .and_then(|v| v.as_str().map(str::to_string).or_else(|| v.as_u64().map(|amount| amount.to_string())))
+                            .unwrap_or_else(|| "1".to_string()),
+                        force: args.get("force").and_then(|v| v.as_bool()).unwrap_or(false),
+                        private: args.get("private").and_then(|v| v.as_bool()),
+                    };
+                    
+                    if operation == "transfer" {
+                        nft_transfer(ctx, contract.unwrap_or(""), &token_id, &request).await
+                    } else {
+                        let token_uri = args.get("token_uri").and_then(|v| v.as_str()).filter(|uri| !uri.is_empty());
+                        let value = args.get("value").and_then(|v| v.as_str()).unwrap_or("0");
+                        nft_mint(ctx, contract, token_uri, value, &request).await
+                    }
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown NFT operation: {}", operation))),
+            }
+        },
+        "ipfs" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let name = args.get("name")
+                .and_then(|v| v.as_str())
+                .filter(|name| !name.is_empty());
+            
+            match operation {
+                "pin_json" => ipfs_pin_json(ctx, args.get("content"), name.unwrap_or("metadata.json")).await,
+                "pin_file" => {
+                    let content = args.get("content").and_then(|v| v.as_str());
+                    let path = args.get("path").and_then(|v| v.as_str()).filter(|path| !path.is_empty());
+                    ipfs_pin_file(ctx, path, content, name).await
+                },
+                "fetch" => {
+                    let cid = args.get("cid")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    ipfs_fetch(ctx, cid).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown IPFS operation: {}", operation))),
+            }
+        },
+        "safe_multisig" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let safe = args.get("safe")
+                .and_then(|v| v.as_str());
+            let from_address = match account_address(ctx, args).await {
+                Ok(Some(address)) => address,
+                Ok(None) => args.get("from_address")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+            };
+            let safe_tx_hash = args.get("safe_tx_hash")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            
+            match operation {
+                "info" => safe_info(ctx, safe).await,
+                "pending" => safe_pending(ctx, safe).await,
+                "propose" => safe_propose(ctx, safe, args, &from_address).await,
+                "confirm" => safe_confirm(ctx, safe, safe_tx_hash, &from_address).await,
+                "execute" => {
+                    let force = args.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+                    let private = args.get("private").and_then(|v| v.as_bool());
+                    
+                    safe_execute(ctx, safe, safe_tx_hash, &from_address, force, private).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown Safe operation: {}", operation))),
+            }
+        },
+        "smart_account" => {
+            let operation = args.get("operation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown");
+            let owner_address = match account_address(ctx, args).await {
+                Ok(Some(address)) => address,
+                Ok(None) => args.get("from_address")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+            };
+            let salt = args.get("salt")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            
+            match operation {
+                "address" => smart_account_address(ctx, &owner_address, salt).await,
+                "deploy" | "send" => {
+                    let call = if operation == "send" {
+                        let to = args.get("to").and_then(|v| v.as_str()).unwrap_or("");
+                        let value = args.get("value").and_then(|v| v.as_str()).unwrap_or("0");
+                        let data = call_data_from_args(ctx, args)?;
+                        Some((to, value, data))
+                    } else {
+                        None
+                    };
+                    let sponsored = args.get("sponsored")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(ctx.config.account_abstraction.paymaster_url.is_some());
+                    
+                    smart_account_send(ctx, &owner_address, salt, call, sponsored).await
+                },
+                _ => Err(ToolError::InvalidArgs(format!("Unknown smart account operation: {}", operation))),
+            }
+        },
+        _ => {
+            if let Some(result) = plugins::execute(name, args).await {
+                return result;
+            }
+            match mcp::execute(name, args).await {
+                Some(result) => result,
+                None => Err(ToolError::InvalidArgs(format!("Unknown tool: {}", name))),
+            }
+        },
+    }
+}
+
+// Resolve the optional `account` argument (keystore account name, HD wallet index or label,
+// "ledger"/"ledger:N", or "kms") to its address
+async fn account_address(ctx: &ToolContext, args: &serde_json::Value) -> anyhow::Result<Option<String>> {
+    let selector = match args.get("account") {
+        Some(serde_json::Value::String(selector)) if !selector.is_empty() => selector.clone(),
+        Some(serde_json::Value::Number(index)) => index.to_string(),
+        _ => return Ok(None),
+    };
+    if selector == "kms" {
+        let signer = kms::signer(&ctx.config.kms, ctx.config.chain.chain_id).await?;
+        return Ok(Some(format!("{:?}", signer.address())));
+    }
+    if let Some(account) = wallet::find_ledger_account(&selector) {
+        return Ok(Some(format!("{:?}", account.address)));
+    }
+    if let Ok(signer) = keystore::unlock(&ctx.keystore_dir(), &selector) {
+        return Ok(Some(format!("{:?}", signer.address())));
+    }
+    let account = wallet::find_account(&selector)?;
+    Ok(Some(format!("{:?}", account.address)))
+}
+
+async fn get_weather(city: &str) -> Result<String, ToolError> {
+    // In a real implementation, you would call a weather API
+    // For this example, we'll return mock data
+    
+    // Simulate API call delay
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    
+    // Return mock data
+    match city.to_lowercase().as_str() {
+        "cairo" => Ok("30°C, sunny".to_string()),
+        "london" => Ok("15°C, cloudy with occasional rain".to_string()),
+        "new york" => Ok("22°C, partly cloudy".to_string()),
+        "tokyo" => Ok("25°C, clear skies".to_string()),
+        _ => Ok(format!("Weather data for {} is not available. This is a mock implementation.", city)),
+    }
+}
+
+fn get_time(timezone: Option<&str>) -> Result<String, ToolError> {
+    let now = Local::now();
+    
+    match timezone {
+        Some(tz) => {
+            // In a real implementation, you would handle different timezones
+            // For this example, we'll just return the local time with a note
+            Ok(format!("Current time (local, timezone {} not implemented): {}", 
+                      tz, now.format("%Y-%m-%d %H:%M:%S")))
+        },
+        None => {
+            Ok(format!("Current local time: {}", now.format("%Y-%m-%d %H:%M:%S")))
+        }
+    }
+}
+
+async fn get_token_price(ctx: &ToolContext, token: &str) -> Result<String, ToolError> {
+    if token.trim().is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide a token symbol or contract address".to_string()));
+    }
+    match prices::token_price(&ctx.config.prices, token).await {
+        Ok(price) => Ok(prices::format_price(token.trim(), &price)),
+        Err(e) => Err(ToolError::RpcError(format!("Could not fetch price for {}: {}", token, e))),
+    }
+}
+
+async fn gas_report(ctx: &ToolContext) -> Result<String, ToolError> {
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to {}: {}", ctx.config.chain.name, e))),
+    };
+    match gas::report(&provider, ctx.config.gas.history_blocks).await {
+        Ok(Some(report)) => {
+            // ETH as the native currency of the configured chain; the costs are still shown without it
+            let eth_usd = prices::token_price(&ctx.config.prices, "ETH").await.ok().and_then(|price| price.usd);
+            Ok(gas::format_report(&ctx.config.chain.name, &report, eth_usd))
+        },
+        Ok(None) => match provider.get_gas_price().await {
+            Ok(gas_price) => Ok(format!("{} has no EIP-1559 base fee\nGas price: {} gwei", ctx.config.chain.name, units::format_gwei(gas_price))),
+            Err(e) => Err(ToolError::RpcError(format!("Could not fetch gas price: {}", e))),
+        },
+        Err(e) => Err(ToolError::RpcError(format!("Could not fetch fee history: {}", e))),
+    }
+}
+
+fn gas_waiting(ctx: &ToolContext) -> String {
+    let waiting = gas::waiting(ctx.session_id);
+    if waiting.is_empty() {
+        return "No sends are waiting for cheaper gas".to_string();
+    }
+    let lines: Vec<String> = waiting.iter()
+        .map(|wait| format!("- #{}: {} once the base fee is below {} gwei (until {})",
+                            wait.id, wait.description, units::format_gwei(wait.max_base_fee), wait.expires_at.format("%Y-%m-%d %H:%M")))
+        .collect();
+    format!("Sends waiting for cheaper gas:\n{}", lines.join("\n"))
+}
+
+// Hold a send back if it asks to wait until the base fee is below `max_base_fee`. Returns the
+// reply for the user when it is held (or can't be), or None to send it now.
+async fn wait_for_gas(ctx: &ToolContext, tool: &str, args: &serde_json::Value, description: &str) -> Option<Result<String, ToolError>> {
+    // A dry run is simulated right away; there is nothing to wait for
+    if ctx.dry_run {
+        return None;
+    }
+    let limit = match args.get("max_base_fee")? {
+        serde_json::Value::Number(number) => number.to_string(),
+        serde_json::Value::String(text) => text.clone(),
+        _ => return Some(Err(ToolError::InvalidArgs("max_base_fee must be a number of gwei".to_string()))),
+    };
+    let limit = match parse_gwei(&limit) {
+        Ok(limit) => limit,
+        Err(e) => return Some(Err(ToolError::InvalidArgs(format!("Invalid max_base_fee: {}", e)))),
+    };
+    let current = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => gas::base_fee(&provider).await,
+        Err(e) => Err(e),
+    };
+    let current = match current {
+        Ok(Some(current)) => current,
+        Ok(None) => return Some(Err(ToolError::InvalidArgs(format!("{} has no base fee to wait for; send without max_base_fee", ctx.config.chain.name)))),
+        Err(e) => return Some(Err(ToolError::RpcError(format!("Could not fetch the base fee: {}", e)))),
+    };
+    if current < limit {
+        return None;
+    }
+
+    // The held call is made later without the limit, so it goes straight through
+    let mut args = args.clone();
+    if let Some(args) = args.as_object_mut() {
+        args.remove("max_base_fee");
+    }
+    match gas::defer(ctx, tool, args, limit, description) {
+        Ok(wait) => Some(Ok(format!("The base fee is {} gwei, not below {} gwei yet, so I'll {} once it is (send #{}). \
+                                  Fees are checked in the background until {}; the send is simulated and checked against the policy when it is made. \
+                                  Waiting stops if the agent is shut down.",
+                                 units::format_gwei(current), units::format_gwei(limit), description, wait.id,
+                                 wait.expires_at.format("%Y-%m-%d %H:%M")))),
+        Err(e) => Some(Err(ToolError::Failed(format!("Could not wait for gas: {}", e)))),
+    }
+}
+
+fn alerts_pool(ctx: &ToolContext) -> Result<&Pool<Postgres>, ToolError> {
+    ctx.pool.as_ref().ok_or_else(|| ToolError::NotConfigured("Price alerts are stored in the database; set DATABASE_URL to use them".to_string()))
+}
+
+async fn create_price_alert(ctx: &ToolContext, token: &str, condition: &str, threshold: Option<f64>) -> Result<String, ToolError> {
+    let pool = alerts_pool(ctx)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide the token to watch".to_string()));
+    }
+    if condition != "above" && condition != "below" {
+        return Err(ToolError::InvalidArgs("condition must be 'above' or 'below'".to_string()));
+    }
+    let Some(threshold) = threshold.filter(|price| *price > 0.0) else {
+        return Err(ToolError::InvalidArgs("Please provide a positive USD price threshold".to_string()));
+    };
+    // Fetch the price now so unknown tokens are rejected before anything is stored
+    let current = match prices::token_price(&ctx.config.prices, token).await {
+        Ok(price) => price,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch price for {}: {}", token, e))),
+    };
+    let id = save_price_alert(pool, ctx.session_id, token, condition, threshold).await?;
+
+    let mut message = format!("Created price alert #{}: {} {} ${:.2}
+Currently {}", id, token, condition, threshold,
+                              prices::format_price(token, &current));
+    if let Some(usd) = current.usd
+        && alerts::crossed(condition, threshold, usd)
+    {
+        message.push_str("
+The price is already past the threshold, so the alert will fire at the next check.");
+    }
+    if !ctx.config.alerts.enabled {
+        message.push_str("
+Note: alert checks are disabled under [alerts], so it won't fire until they are enabled.");
+    }
+    Ok(message)
+}
+
+async fn list_alerts(ctx: &ToolContext, include_inactive: bool) -> Result<String, ToolError> {
+    let pool = alerts_pool(ctx)?;
+    let alerts = list_price_alerts(pool, ctx.session_id, include_inactive).await?;
+    if alerts.is_empty() {
+        return Ok(format!("No {}price alerts", if include_inactive { "" } else { "active " }));
+    }
+    let lines: Vec<String> = alerts.iter()
+        .map(|alert| {
+            let state = match (alert.status.as_str(), alert.triggered_price, alert.triggered_at) {
+                ("triggered", Some(price), Some(at)) => format!("fired at ${:.2} on {} UTC", price, at.format("%Y-%m-%d %H:%M")),
+                (status, _, _) => status.to_string(),
+            };
+            format!("- #{}: {} ({}, created {} UTC)", alert.id, alerts::describe_rule(alert), state, alert.created_at.format("%Y-%m-%d %H:%M"))
+        })
+        .collect();
+    Ok(format!("Price alerts:\n{}", lines.join("\n")))
+}
+
+async fn cancel_alert(ctx: &ToolContext, id: i64) -> Result<String, ToolError> {
+    let pool = alerts_pool(ctx)?;
+    if cancel_price_alert(pool, ctx.session_id, id).await? {
+        Ok(format!("Cancelled price alert #{}", id))
+    } else {
+        Err(ToolError::InvalidArgs(format!("No active price alert #{}", id)))
+    }
+}
+
+fn reminders_pool(ctx: &ToolContext) -> Result<&Pool<Postgres>, ToolError> {
+    ctx.pool.as_ref().ok_or_else(|| ToolError::NotConfigured("Notes and reminders are stored in the database; set DATABASE_URL to use them".to_string()))
+}
+
+async fn save_user_note(ctx: &ToolContext, content: &str) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide the note to save".to_string()));
+    }
+    if content.chars().count() > reminders::MAX_MESSAGE_LEN {
+        return Err(ToolError::InvalidArgs(format!("Notes are at most {} characters", reminders::MAX_MESSAGE_LEN)));
+    }
+    let id = save_note(pool, ctx.user.as_deref(), content).await?;
+    Ok(format!("Saved note #{}: {}", id, content))
+}
+
+async fn list_user_notes(ctx: &ToolContext, query: Option<&str>) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    let notes = list_notes(pool, ctx.user.as_deref(), query).await?;
+    if notes.is_empty() {
+        return Ok(match query {
+            Some(query) => format!("No notes mention \"{}\"", query),
+            None => "No notes saved".to_string(),
+        });
+    }
+    let lines: Vec<String> = notes.iter()
+        .map(|note| format!("- #{} ({} UTC): {}", note.id, note.created_at.format("%Y-%m-%d"), note.content))
+        .collect();
+    Ok(format!("Notes:\n{}", lines.join("\n")))
+}
+
+async fn forget_note(ctx: &ToolContext, id: i64) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    if delete_note(pool, ctx.user.as_deref(), id).await? {
+        Ok(format!("Forgot note #{}", id))
+    } else {
+        Err(ToolError::InvalidArgs(format!("No note #{}", id)))
+    }
+}
+
+async fn create_reminder(ctx: &ToolContext, message: &str, when: &str) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    let message = message.trim();
+    if message.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide what to remind the user of".to_string()));
+    }
+    if message.chars().count() > reminders::MAX_MESSAGE_LEN {
+        return Err(ToolError::InvalidArgs(format!("Reminders are at most {} characters", reminders::MAX_MESSAGE_LEN)));
+    }
+    let now = chrono::Utc::now();
+    let due = reminders::due_at(when, now).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let id = save_reminder(pool, ctx.session_id, ctx.user.as_deref(), message, due.naive_utc()).await?;
+
+    let mut reply = format!("Reminder #{} set for {} (in {}): {}", id, due.format("%Y-%m-%d %H:%M UTC"),
+                            reminders::describe_wait(due, now), message);
+    if !ctx.config.reminders.enabled {
+        reply.push_str("\nNote: reminders are disabled under [reminders], so it won't fire until they are enabled.");
+    }
+    Ok(reply)
+}
+
+async fn list_user_reminders(ctx: &ToolContext, include_inactive: bool) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    let reminders = list_reminders(pool, ctx.user.as_deref(), include_inactive).await?;
+    if reminders.is_empty() {
+        return Ok(format!("No {}reminders", if include_inactive { "" } else { "active " }));
+    }
+    let lines: Vec<String> = reminders.iter()
+        .map(|reminder| match (reminder.status.as_str(), reminder.fired_at) {
+            ("active", _) => format!("- {}", reminders::describe(reminder)),
+            ("fired", Some(at)) => format!("- {}, fired {} UTC", reminders::describe(reminder), at.format("%Y-%m-%d %H:%M")),
+            (status, _) => format!("- {}, {}", reminders::describe(reminder), status),
+        })
+        .collect();
+    Ok(format!("Reminders:\n{}", lines.join("\n")))
+}
+
+async fn cancel_user_reminder(ctx: &ToolContext, id: i64) -> Result<String, ToolError> {
+    let pool = reminders_pool(ctx)?;
+    if cancel_reminder(pool, ctx.user.as_deref(), id).await? {
+        Ok(format!("Cancelled reminder #{}", id))
+    } else {
+        Err(ToolError::InvalidArgs(format!("No active reminder #{}", id)))
+    }
+}
+
+fn address_book_pool(ctx: &ToolContext) -> Result<&Pool<Postgres>, ToolError> {
+    ctx.pool.as_ref().ok_or_else(|| ToolError::NotConfigured("The address book is stored in the database; set DATABASE_URL to use it".to_string()))
+}
+
+async fn add_contact(ctx: &ToolContext, label: &str, address: &str, note: Option<&str>) -> Result<String, ToolError> {
+    let pool = address_book_pool(ctx)?;
+    let label = address_book::check_label(label).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    if address.trim().is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide the address to save".to_string()));
+    }
+    // ENS names are resolved now, so that the entry keeps pointing where the user checked
+    let resolved = match Address::from_str(address.trim()) {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let provider = match get_provider(&ctx.config.chain).await {
+                Ok(provider) => provider,
+                Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+            };
+            resolve_address(&provider, address).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?
+        },
+    };
+    let hex = format!("{:?}", resolved);
+    match save_contact(pool, ctx.user.as_deref(), label, &hex, note).await? {
+        Some(previous) => Ok(format!("Updated '{}' in the address book: {} (was {})", label, hex, previous)),
+        None => Ok(format!("Saved '{}' in the address book: {}", label, hex)),
+    }
+}
+
+async fn list_address_book(ctx: &ToolContext) -> Result<String, ToolError> {
+    let pool = address_book_pool(ctx)?;
+    let contacts = list_contacts(pool, ctx.user.as_deref()).await?;
+    if contacts.is_empty() {
+        return Ok("The address book is empty".to_string());
+    }
+    let lines: Vec<String> = contacts.iter()
+        .map(|contact| match &contact.note {
+            Some(note) => format!("- {}: {} ({})", contact.label, contact.address, note),
+            None => format!("- {}: {}", contact.label, contact.address),
+        })
+        .collect();
+    Ok(format!("Address book:\n{}", lines.join("\n")))
+}
+
+async fn remove_contact(ctx: &ToolContext, label: &str) -> Result<String, ToolError> {
+    let pool = address_book_pool(ctx)?;
+    if delete_contact(pool, ctx.user.as_deref(), label.trim()).await? {
+        Ok(format!("Removed '{}' from the address book", label.trim()))
+    } else {
+        Err(ToolError::InvalidArgs(format!("'{}' is not in the address book", label.trim())))
+    }
+}
+
+async fn chainlink_price(ctx: &ToolContext, pair: &str) -> Result<String, ToolError> {
+    let feeds = &ctx.config.prices.chainlink_feeds;
+    let pair = pair.trim();
+    if pair.is_empty() {
+        if feeds.is_empty() {
+            return Err(ToolError::NotConfigured("No Chainlink feeds are configured; add them under [prices.chainlink_feeds] in config.toml".to_string()));
+        }
+        let lines: Vec<String> = feeds.iter().map(|(pair, address)| format!("- {}: {}", pair, address)).collect();
+        return Ok(format!("Chainlink feeds on {}:\n{}", ctx.config.chain.name, lines.join("\n")));
+    }
+    
+    // Accept a configured pair (case-insensitive) or a raw aggregator address
+    let (label, feed) = match feeds.iter().find(|(name, _)| name.eq_ignore_ascii_case(pair)) {
+        Some((name, address)) => (name.as_str(), address.as_str()),
+        None => (pair, pair),
+    };
+    let feed = match Address::from_str(feed) {
+        Ok(address) => address,
+        Err(_) => {
+            let known: Vec<&str> = feeds.keys().map(String::as_str).collect();
+            return Err(ToolError::NotConfigured(format!("No Chainlink feed configured for {} (configured: {})", pair, known.join(", "))));
+        }
+    };
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    match prices::chainlink_price(Arc::new(provider), feed).await {
+        Ok(price) => Ok(prices::format_feed_price(label, &ctx.config.chain.name, &price)),
+        Err(e) => Err(ToolError::RpcError(format!("Could not read Chainlink feed {}: {}", label, e))),
+    }
+}
+
+// Get provider for the configured Ethereum network (HTTP or WebSocket, shared across calls)
+pub async fn get_provider(chain: &ChainConfig) -> anyhow::Result<EthProvider> {
+    let rpc_url = chain.rpc_url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No RPC URL configured; set chain.rpc_url in config.toml or ETH_RPC_URL"))?;
+    provider::connect(rpc_url).await
+}
+
+// get_provider for tools, in an Arc for contract bindings and failing with a ToolError
+async fn chain_provider(ctx: &ToolContext) -> Result<Arc<EthProvider>, ToolError> {
+    match get_provider(&ctx.config.chain).await {
+        Ok(provider) => Ok(Arc::new(provider)),
+        Err(e) => Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    }
+}
+
+// Network description shown in transaction output, e.g. "Sepolia (via https://...)"
+fn network_label(chain: &ChainConfig) -> String {
+    format!("{} (via {})", chain.name, chain.rpc_url.as_deref().unwrap_or("unknown RPC"))
+}
+
+// Ethereum wallet functions
+// A new random key, kept in the keystore under a name; the key itself is never shown, so
+// that it doesn't end up in the conversation
+async fn eth_generate_wallet(ctx: &ToolContext, name: Option<&str>) -> Result<String, ToolError> {
+    let dir = &ctx.keystore_dir();
+    let name = name.map(str::to_string).unwrap_or_else(|| keystore::next_name(dir));
+    let (address, persisted) = match keystore::create(dir, &name) {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    record_wallet(ctx, address, "generated", None, Some(&name)).await;
+    
+    let storage = if persisted {
+        format!("stored encrypted in {}", dir)
+    } else {
+        "kept in memory for this session only; set KEYSTORE_PASSWORD to keep new accounts".to_string()
+    };
+    let mut message = format!("Generated new Ethereum wallet:\nAddress: {:?}\nAccount: {} ({})\n\
+                               Send from it with account \"{}\". Its private key is not shown.", address, name, storage, name);
+    if ctx.config.faucet.fund_new_wallets && faucet::is_testnet(ctx.config.chain.chain_id) {
+        match eth_faucet(ctx, &format!("{:?}", address)).await {
+            Ok(funded) => message.push_str(&format!("\nFunded from the faucet:\n{}", funded)),
+            Err(e) => message.push_str(&format!("\nCould not fund it from the faucet: {}", e.message())),
+        }
+    }
+    Ok(message)
+}
+
+// Test ETH for an address on a testnet: from the faucet APIs serving the chain, or else sent
+// from the faucet account
+async fn eth_faucet(ctx: &ToolContext, address: &str) -> Result<String, ToolError> {
+    let config = &ctx.config.faucet;
+    let chain = &ctx.config.chain;
+    if !faucet::is_testnet(chain.chain_id) {
+        return Err(ToolError::InvalidArgs(format!("The faucet only funds testnets ({}), not {}", faucet::testnets().join(", "), chain.name)));
+    }
+    if address.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide the address or account to fund".to_string()));
+    }
+    let provider = match get_provider(chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let address = match resolve_account(ctx, &provider, address).await {
+        Ok(address) => address,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    faucet::check_cooldown(config, chain.chain_id, address).map_err(ToolError::InvalidArgs)?;
+    
+    let mut failures = Vec::new();
+    for api in faucet::apis(config, chain.chain_id) {
+        if ctx.dry_run {
+            return Ok(format!("Dry run; nothing was requested.\nWould ask {} for test ETH for {:?} on {}", faucet::host(&api.url), address, chain.name));
+        }
+        match faucet::request(api, config.api_key.as_deref(), address).await {
+            Ok(answer) => {
+                faucet::record(chain.chain_id, address);
+                return Ok(format!("Requested test ETH for {:?} on {} from {}: {}", address, chain.name, faucet::host(&api.url), answer));
+            },
+            Err(e) => {
+                tracing::warn!(faucet = %faucet::host(&api.url), error = %e, "Faucet request failed");
+                failures.push(format!("{}: {}", faucet::host(&api.url), e));
+            },
+        }
+    }
+    let Some(account) = &config.account else {
+        if failures.is_empty() {
+            return Err(ToolError::NotConfigured(format!("No faucet API serves {} and no faucet account is set under [faucet]", chain.name)));
+        }
+        return Err(ToolError::Failed(format!("Faucet requests failed: {}", failures.join("; "))));
+    };
+    let sent = eth_send_eth(ctx, account, &format!("{:?}", address), &config.amount, FeeOverrides::default(), false, None).await?;
+    if !ctx.dry_run {
+        faucet::record(chain.chain_id, address);
+    }
+    Ok(sent)
+}
+
+fn describe_account(account: &wallet::DerivedAccount) -> String {
+    match &account.label {
+        Some(label) => format!("#{} {} ({}) {:?}", account.index, label, wallet::derivation_path(account.index), account.address),
+        None => format!("#{} ({}) {:?}", account.index, wallet::derivation_path(account.index), account.address),
+    }
+}
+
+// Remember a wallet address in the `wallets` table (addresses only, never keys)
+async fn record_wallet(ctx: &ToolContext, address: Address, source: &str, index: Option<u32>, label: Option<&str>) {
+    if let Some(pool) = &ctx.pool
+        && let Err(e) = save_wallet(pool, ctx.session_id, &format!("{:?}", address), source, index.map(|i| i as i32), label).await
+    {
+        tracing::error!(error = %e, "Failed to record wallet");
+    }
+}
+
+async fn record_account(ctx: &ToolContext, account: &wallet::DerivedAccount) {
+    record_wallet(ctx, account.address, "hd", Some(account.index), account.label.as_deref()).await;
+}
+
+async fn eth_generate_mnemonic(ctx: &ToolContext) -> Result<String, ToolError> {
+    match wallet::generate_mnemonic() {
+        Ok((phrase, first)) => {
+            record_account(ctx, &first).await;
+            Ok(format!("Generated new HD wallet:\nMnemonic: {}\nFirst account: {}\n\
+                       Store the mnemonic safely; it controls every derived account.",
+                       phrase, describe_account(&first)))
+        },
+        Err(e) => Err(ToolError::Failed(e.to_string())),
+    }
+}
+
+async fn eth_derive_account(ctx: &ToolContext, index: u64, label: Option<&str>) -> Result<String, ToolError> {
+    let index = match u32::try_from(index) {
+        Ok(index) if index < 0x8000_0000 => index,
+        _ => return Err(ToolError::InvalidArgs(format!("Invalid account index: {}", index))),
+    };
+    match wallet::derive_account(index, label) {
+        Ok(account) => {
+            record_account(ctx, &account).await;
+            Ok(format!("Derived account {}", describe_account(&account)))
+        },
+        Err(e) => Err(ToolError::InvalidArgs(e.to_string())),
+    }
+}
+
+fn eth_list_accounts(ctx: &ToolContext) -> Result<String, ToolError> {
+    let hd = match wallet::list_accounts() {
+        Ok(accounts) if accounts.is_empty() => "No HD wallet loaded. Generate or import a mnemonic first.".to_string(),
+        Ok(accounts) => format!("HD wallet accounts:\n{}",
+                                accounts.iter().map(describe_account).collect::<Vec<_>>().join("\n")),
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    // Names only; files that are still locked aren't decrypted just to show their address
+    let names = keystore::names(&ctx.keystore_dir());
+    if names.is_empty() {
+        return Ok(hd);
+    }
+    Ok(format!("Keystore accounts: {}\n{}", names.join(", "), hd))
+}
+
+async fn eth_connect_ledger(ctx: &ToolContext, index: u64) -> Result<String, ToolError> {
+    let index = match u32::try_from(index) {
+        Ok(index) if index < 0x8000_0000 => index,
+        _ => return Err(ToolError::InvalidArgs(format!("Invalid account index: {}", index))),
+    };
+    match wallet::connect_ledger(&ctx.config.ledger.derivation, index, ctx.config.chain.chain_id).await {
+        Ok(account) => {
+            record_wallet(ctx, account.address, "ledger", Some(account.index), None).await;
+            Ok(format!("Connected Ledger account ledger:{} ({}) {:?}
+                       Transactions and signatures from it must be confirmed on the device.",
+                       account.index, account.path, account.address))
+        },
+        Err(e) => Err(ToolError::Failed(e.to_string())),
+    }
+}
+
+async fn eth_check_balance(ctx: &ToolContext, address: &str) -> Result<String, ToolError> {
+    if address.is_empty() {
+        return Err(ToolError::InvalidArgs("Address is required".to_string()));
+    }
+    
+    // Get provider
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    // Parse the address (raw hex, ENS name, or the name of one of the user's accounts)
+    let address = match resolve_account(ctx, &provider, address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let address_display = display_address(&provider, address).await;
+    
+    // Get balance from the network
+    match provider.get_balance(address, None).await {
+        Ok(balance) => {
+            // Convert from Wei to ETH (1 ETH = 10^18 Wei) without losing precision
+            Ok(format!("Balance for address {}: {} ETH on {}", 
+                      address_display, units::format_eth(balance), network_label(&ctx.config.chain)))
+        },
+        Err(e) => Err(ToolError::RpcError(format!("Could not fetch balance of {}: {}", address_display, e))),
+    }
+}
+
+// Resolve an address parameter that may be a raw hex address or an ENS name
+async fn resolve_address(provider: &EthProvider, input: &str) -> anyhow::Result<Address> {
+    let input = input.trim();
+    if let Ok(address) = Address::from_str(input) {
+        return Ok(address);
+    }
+    if !input.contains('.') {
+        return Err(anyhow::anyhow!("Invalid Ethereum address format: {}", input));
+    }
+    provider.resolve_name(input).await
+        .map_err(|e| anyhow::anyhow!("Could not resolve ENS name {}: {}", input, e))
+}
+
+// Resolve a recipient, which may also be a label in the user's address book or the name of
+// one of their own accounts
+async fn resolve_recipient(ctx: &ToolContext, provider: &EthProvider, input: &str) -> anyhow::Result<Recipient> {
+    let input = input.trim();
+    let owner = ctx.user.as_deref();
+    if address_book::is_label(input) {
+        let contact = match &ctx.pool {
+            Some(pool) => find_contact(pool, owner, input).await?,
+            None => None,
+        };
+        if let Some(contact) = contact {
+            return Ok(Recipient { address: Address::from_str(&contact.address)?, label: Some(contact.label), checked: true });
+        }
+        return match own_account(ctx, input) {
+            Some(address) => Ok(Recipient { address, label: Some(input.to_string()), checked: true }),
+            None => Err(anyhow::anyhow!(address_book::unknown_label(input, ctx.pool.is_some()))),
+        };
+    }
+    let address = resolve_address(provider, input).await?;
+    let mut label = match &ctx.pool {
+        Some(pool) => contact_for_address(pool, owner, &format!("{:?}", address)).await?.map(|contact| contact.label),
+        None => None,
+    };
+    if label.is_none() {
+        label = own_accounts(ctx).into_iter().find(|(_, own)| *own == Some(address)).map(|(name, _)| name);
+    }
+    Ok(Recipient { address, label, checked: ctx.pool.is_some() })
+}
+
+// Resolve an address parameter that may also be the name of one of the user's own accounts
+async fn resolve_account(ctx: &ToolContext, provider: &EthProvider, input: &str) -> anyhow::Result<Address> {
+    let input = input.trim();
+    if address_book::is_label(input)
+        && let Some(address) = own_account(ctx, input)
+    {
+        return Ok(address);
+    }
+    resolve_address(provider, input).await
+}
+
+// The address of a keystore account by name, or of an HD wallet account by label or index
+fn own_account(ctx: &ToolContext, name: &str) -> Option<Address> {
+    own_accounts(ctx).into_iter()
+        .find(|(own, _)| own == name || own.strip_prefix('#') == Some(name))
+        .and_then(|(_, address)| address)
+        .or_else(|| keystore::unlock(&ctx.keystore_dir(), name).ok().map(|signer| signer.address()))
+}
+
+// The keystore accounts and derived HD wallet accounts, by the name tool calls use, with
+// their addresses where they are known without unlocking (None for older locked files)
+fn own_accounts(ctx: &ToolContext) -> Vec<(String, Option<Address>)> {
+    let dir = ctx.keystore_dir();
+    let mut accounts: Vec<(String, Option<Address>)> = keystore::names(&dir).into_iter()
+        .map(|name| {
+            let address = keystore::address(&dir, &name);
+            (name, address)
+        })
+        .collect();
+    accounts.extend(wallet::list_accounts().unwrap_or_default().into_iter()
+        .map(|account| (account.label.unwrap_or_else(|| format!("#{}", account.index)), Some(account.address))));
+    accounts
+}
+
+/// Every account the tools can send from, by the name tool calls refer to it with, and its
+/// ETH balance (`wallet list`, `/wallets`)
+pub async fn wallets_report(ctx: &ToolContext) -> Result<String, ToolError> {
+    if let Err(e) = wallet::list_accounts() {
+        return Err(ToolError::InvalidArgs(e.to_string()));
+    }
+    let accounts = own_accounts(ctx);
+    if accounts.is_empty() {
+        return Ok("No accounts yet. Generate one with a name (`agent wallet new --name trading-1`), or import a key with `agent wallet import <name>`.".to_string());
+    }
+    let provider = get_provider(&ctx.config.chain).await;
+    let mut lines = Vec::new();
+    for (name, address) in accounts {
+        let Some(address) = address else {
+            lines.push(format!("- {}: locked; its address is read when it is first used", name));
+            continue;
+        };
+        let balance = match &provider {
+            Ok(provider) => match provider.get_balance(address, None).await {
+                Ok(balance) => format!("{} ETH", units::format_eth(balance)),
+                Err(_) => "balance unavailable".to_string(),
+            },
+            Err(_) => "balance unavailable".to_string(),
+        };
+        lines.push(format!("- {} {:?}: {}", name, address, balance));
+    }
+    let mut report = format!("Accounts on {}:\n{}", ctx.config.chain.name, lines.join("\n"));
+    if let Err(e) = provider {
+        report.push_str(&format!("\nBalances could not be read: {}", e));
+    }
+    Ok(report)
+}
+
+// Format an address with its ENS primary name when one is set, e.g. "vitalik.eth (0xd8dA...)"
+async fn display_address(provider: &EthProvider, address: Address) -> String {
+    match provider.lookup_address(address).await {
+        Ok(name) => format!("{} ({:?})", name, address),
+        Err(_) => format!("{:?}", address),
+    }
+}
+
+async fn eth_resolve_name(ctx: &ToolContext, name: &str) -> Result<String, ToolError> {
+    if name.is_empty() {
+        return Err(ToolError::InvalidArgs("ENS name is required".to_string()));
+    }
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    match provider.resolve_name(name).await {
+        Ok(address) => Ok(format!("{} resolves to {:?}", name, address)),
+        Err(e) => Err(ToolError::InvalidArgs(format!("Could not resolve ENS name {}: {}", name, e))),
+    }
+}
+
+async fn eth_lookup_address(ctx: &ToolContext, address: &str) -> Result<String, ToolError> {
+    let address = match Address::from_str(address.trim()) {
+        Ok(addr) => addr,
+        Err(_) => return Err(ToolError::InvalidArgs(format!("Invalid Ethereum address format: {}", address))),
+    };
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    match provider.lookup_address(address).await {
+        Ok(name) => Ok(format!("Primary ENS name for {:?}: {}", address, name)),
+        Err(_) => Ok(format!("No primary ENS name is set for {:?}", address)),
+    }
+}
+
+// Optional fee overrides (in gwei) for outgoing transactions
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FeeOverrides<'a> {
+    pub max_fee_per_gas: Option<&'a str>,
+    pub max_priority_fee_per_gas: Option<&'a str>,
+}
+
+// Fee values are gwei unless they carry an explicit unit ("2 gwei", "1500000000 wei")
+fn parse_gwei(value: &str) -> anyhow::Result<U256> {
+    if value.chars().any(|c| c.is_ascii_alphabetic()) {
+        units::parse_amount(value)
+    } else {
+        units::parse_amount(&format!("{} gwei", value.trim()))
+    }
+}
+
+// Build the transaction as EIP-1559 when the latest block has a base fee, otherwise as legacy.
+// Returns the transaction together with a human-readable fee summary.
+async fn build_transaction<M: Middleware>(
+    client: &M,
+    from: Address,
+    to: Address,
+    value: U256,
+    data: Option<Bytes>,
+    overrides: FeeOverrides<'_>,
+) -> anyhow::Result<(TypedTransaction, String)> {
+    let latest_block = client.get_block(BlockNumber::Latest).await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let supports_eip1559 = latest_block.and_then(|block| block.base_fee_per_gas).is_some();
+    
+    if supports_eip1559 {
+        // estimate_eip1559_fees derives both values from eth_feeHistory
+        let (estimated_max_fee, estimated_priority_fee) = client.estimate_eip1559_fees(None).await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let max_priority_fee = match overrides.max_priority_fee_per_gas {
+            Some(gwei) => parse_gwei(gwei)?,
+            None => estimated_priority_fee,
+        };
+        let max_fee = match overrides.max_fee_per_gas {
+            Some(gwei) => parse_gwei(gwei)?,
+            None => estimated_max_fee.max(max_priority_fee),
+        };
+        if max_fee < max_priority_fee {
+            return Err(anyhow::anyhow!("max fee per gas must be at least the priority fee"));
+        }
+        
+        let mut tx = Eip1559TransactionRequest::new()
+            .to(to)
+            .value(value)
+            .from(from)
+            .max_fee_per_gas(max_fee)
+            .max_priority_fee_per_gas(max_priority_fee);
+        if let Some(data) = data {
+            tx = tx.data(data);
+        }
+        let summary = format!("Max Fee: {} gwei\nPriority Fee: {} gwei", units::format_gwei(max_fee), units::format_gwei(max_priority_fee));
+        Ok((TypedTransaction::Eip1559(tx), summary))
+    } else {
+        let gas_price = match overrides.max_fee_per_gas {
+            Some(gwei) => parse_gwei(gwei)?,
+            None => client.get_gas_price().await.map_err(|e| anyhow::anyhow!("{}", e))?,
+        };
+        
+        let mut tx = TransactionRequest::new()
+            .to(to)
+            .value(value)
+            .from(from)
+            .gas_price(gas_price);
+        if let Some(data) = data {
+            tx = tx.data(data);
+        }
+        let summary = format!("Gas Price: {} gwei (legacy)", units::format_gwei(gas_price));
+        Ok((TypedTransaction::Legacy(tx), summary))
+    }
+}
+
+// Read-only contract call through eth_call, decoded with the named ABI
+async fn contract_call(ctx: &ToolContext, abi_name: &str, address: &str, function_name: &str, call_args: &[serde_json::Value]) -> Result<String, ToolError> {
+    if abi_name.is_empty() {
+        return match contracts::list_abis(&ctx.config.abi_dir) {
+            Ok(names) if names.is_empty() => Ok(format!("No ABIs found in {}", ctx.config.abi_dir)),
+            Ok(names) => Ok(format!("Available ABIs: {}", names.join(", "))),
+            Err(e) => Err(ToolError::RpcError(format!("Could not list ABIs: {}", e))),
+        };
+    }
+    
+    let abi = match contracts::load_abi(&ctx.config.abi_dir, abi_name) {
+        Ok(abi) => abi,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    if function_name.is_empty() {
+        return Ok(format!("Functions in {}:\n{}", abi_name, contracts::describe_functions(&abi).join("\n")));
+    }
+    
+    let function = match contracts::find_function(&abi, function_name) {
+        Ok(function) => function,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    let calldata = match contracts::encode_call(function, call_args) {
+        Ok(data) => data,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Could not encode arguments: {}", e))),
+    };
+    
+    if address.is_empty() {
+        return Err(ToolError::InvalidArgs("Contract address is required".to_string()));
+    }
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    let contract_address = match resolve_address(&provider, address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(contract_address)
+        .data(calldata)
+        .into();
+    
+    let output = match provider.call(&tx, None).await {
+        Ok(output) => output,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not call {} on {:?}: {}", function.abi_signature(), contract_address, e))),
+    };
+    
+    match contracts::decode_output(function, &output) {
+        Ok(decoded) => Ok(format!("Result of {} on {:?}:\n{}",
+                                  function.abi_signature(), contract_address,
+                                  serde_json::to_string_pretty(&decoded)?)),
+        Err(e) => Err(ToolError::Failed(format!("Could not decode return data 0x{}: {}", hex::encode(&output), e))),
+    }
+}
+
+// A block number, hash, or tag such as "latest"
+fn parse_block_id(block: &str) -> Option<BlockId> {
+    let block = block.trim();
+    let tag = match block.to_lowercase().as_str() {
+        "" | "latest" => Some(BlockNumber::Latest),
+        "finalized" => Some(BlockNumber::Finalized),
+        "safe" => Some(BlockNumber::Safe),
+        "pending" => Some(BlockNumber::Pending),
+        "earliest" => Some(BlockNumber::Earliest),
+        _ => None,
+    };
+    if let Some(tag) = tag {
+        return Some(BlockId::Number(tag));
+    }
+    if block.len() == 66 {
+        return H256::from_str(block).ok().map(BlockId::Hash);
+    }
+    match block.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => block.parse().ok(),
+    }.map(|number| BlockId::Number(BlockNumber::Number(number.into())))
+}
+
+async fn chain_query_block(ctx: &ToolContext, block: &str) -> Result<String, ToolError> {
+    let Some(block_id) = parse_block_id(block) else {
+        return Err(ToolError::InvalidArgs(format!("Invalid block: {} (expected a number, a block hash, or 'latest')", block)));
+    };
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    match provider.get_block(block_id).await {
+        Ok(Some(block)) => Ok(chain_query::format_block(&block)),
+        Ok(None) => Err(ToolError::InvalidArgs(format!("Block {} was not found on {}", block, ctx.config.chain.name))),
+        Err(e) => Err(ToolError::RpcError(format!("Could not fetch block {}: {}", block, e))),
+    }
+}
+
+// A transaction and its receipt, or only the receipt
+async fn chain_query_transaction(ctx: &ToolContext, hash: &str, with_tx: bool) -> Result<String, ToolError> {
+    let tx_hash = match H256::from_str(hash.trim()) {
+        Ok(tx_hash) => tx_hash,
+        Err(_) => return Err(ToolError::InvalidArgs(format!("Invalid transaction hash: {}", hash))),
+    };
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let receipt = match provider.get_transaction_receipt(tx_hash).await {
+        Ok(receipt) => receipt,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch the receipt of {:?}: {}", tx_hash, e))),
+    };
+    let logs = receipt.as_ref().map(|receipt| receipt.logs.as_slice()).unwrap_or_default();
+    if !with_tx && let Some(receipt) = &receipt {
+        let decoders = Decoders::for_transaction(&ctx.config, None, logs).await;
+        return Ok(chain_query::format_receipt(receipt, &decoders));
+    }
+    match provider.get_transaction(tx_hash).await {
+        Ok(Some(tx)) => {
+            let decoders = Decoders::for_transaction(&ctx.config, Some(&tx.input), logs).await;
+            Ok(chain_query::format_transaction(&tx, receipt.as_ref(), &decoders))
+        },
+        Ok(None) => Err(ToolError::InvalidArgs(format!("Transaction {:?} was not found on {}", tx_hash, ctx.config.chain.name))),
+        Err(e) => Err(ToolError::RpcError(format!("Could not fetch transaction {:?}: {}", tx_hash, e))),
+    }
+}
+
+// What calldata, a function selector, or an event topic stands for
+async fn chain_query_decode(ctx: &ToolContext, data: &str) -> Result<String, ToolError> {
+    let data = data.trim();
+    let bytes = match hex::decode(data.strip_prefix("0x").unwrap_or(data)) {
+        Ok(bytes) if bytes.len() >= 4 => bytes,
+        _ => return Err(ToolError::InvalidArgs(format!("Expected calldata, a 4-byte selector, or a 32-byte event topic in hex, not {}", data))),
+    };
+    let hex = format!("0x{}", hex::encode(&bytes));
+    if bytes.len() == 32 {
+        let topic = H256::from_slice(&bytes);
+        let mut found: Vec<String> = Decoders::known(&ctx.config.abi_dir).events.iter()
+            .filter(|event| event.signature() == topic)
+            .map(|event| event.abi_signature())
+            .collect();
+        found.dedup();
+        if found.is_empty() {
+            found = signatures::lookup(&ctx.config.signatures, &hex).await;
+        }
+        return Ok(match found.is_empty() {
+            true => format!("No known event has topic {}", hex),
+            false => format!("Event topic {}: {}", hex, found.join(", ")),
+        });
+    }
+    let decoders = Decoders::for_transaction(&ctx.config, Some(&bytes), &[]).await;
+    if bytes.len() == 4 {
+        let mut found: Vec<String> = decoders.functions.iter()
+            .filter(|function| function.short_signature()[..] == bytes[..])
+            .map(|function| function.abi_signature())
+            .collect();
+        found.dedup();
+        return Ok(match found.is_empty() {
+            true => format!("No known function has selector {}", hex),
+            false => format!("Selector {}: {}", hex, found.join(", ")),
+        });
+    }
+    let lines = chain_query::describe_call(&decoders.functions, Some(Address::zero()), &bytes);
+    match lines.first().is_some_and(|line| line.starts_with("Call: ")) {
+        true => Ok(lines.join("\n")),
+        false => Ok(format!("No known function matches this calldata (selector {})", &hex[..10])),
+    }
+}
+
+async fn dune_query(ctx: &ToolContext, query: &str, parameters: &serde_json::Map<String, serde_json::Value>, latest: bool, execution_id: Option<&str>) -> Result<String, ToolError> {
+    let config = &ctx.config.dune;
+    if config.api_key.as_deref().is_none_or(str::is_empty) {
+        return Err(ToolError::NotConfigured("Dune queries need an API key; set DUNE_API_KEY".to_string()));
+    }
+    let query_id = match dune::query_id(config, query) {
+        Ok(query_id) => query_id,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    if latest {
+        return match dune::latest(config, query_id).await {
+            Ok(result) => Ok(dune::summarize(query_id, &result)),
+            Err(e) => Err(ToolError::RpcError(format!("Could not fetch the latest result of query {}: {}", query_id, e))),
+        };
+    }
+    let execution_id = match execution_id {
+        Some(execution_id) => execution_id.to_string(),
+        None => match dune::execute(config, query_id, parameters).await {
+            Ok(execution_id) => execution_id,
+            Err(e) => return Err(ToolError::RpcError(format!("Could not run Dune query {}: {}", query_id, e))),
+        },
+    };
+    match dune::results(config, &execution_id).await {
+        Ok(dune::Outcome::Finished(result)) => Ok(dune::summarize(query_id, &result)),
+        Ok(dune::Outcome::Running(execution_id)) => Ok(format!(
+            "Dune query {} is still running after {} seconds (execution {}). Call dune_query again with this execution_id to get its result.",
+            query_id, config.wait_secs, execution_id,
+        )),
+        Err(e) => Err(ToolError::Failed(format!("Dune query {} failed: {}", query_id, e))),
+    }
+}
+
+async fn web_search(ctx: &ToolContext, query: &str, count: usize) -> Result<String, ToolError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide a search query".to_string()));
+    }
+    if ctx.config.search.api_key.as_deref().is_none_or(str::is_empty) {
+        return Err(ToolError::NotConfigured(format!("Web search through {} needs an API key; set SEARCH_API_KEY", ctx.config.search.provider)));
+    }
+    match search::search(&ctx.config.search, query, count).await {
+        Ok(results) => Ok(search::format_results(query, &results)),
+        Err(e) => Err(ToolError::RpcError(format!("Web search failed: {}", e))),
+    }
+}
+
+async fn fetch_url(ctx: &ToolContext, url: &str, max_tokens: usize) -> Result<String, ToolError> {
+    if url.trim().is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide a URL".to_string()));
+    }
+    match fetch::fetch(&ctx.config.fetch, url).await {
+        Ok(page) => Ok(fetch::format_page(&page, max_tokens)),
+        Err(e) if e.is::<reqwest::Error>() => Err(ToolError::RpcError(format!("Could not fetch {}: {}", url, e))),
+        Err(e) => Err(ToolError::InvalidArgs(e.to_string())),
+    }
+}
+
+// A date range given as YYYY-MM-DD days in UTC, by default from January 1st of this year until today
+fn date_range(from_date: Option<&str>, to_date: Option<&str>) -> Result<(chrono::NaiveDate, chrono::NaiveDate), ToolError> {
+    let today = chrono::Utc::now().date_naive();
+    let parse_date = |date: &str| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| ToolError::InvalidArgs(format!("Invalid date {}; use YYYY-MM-DD", date)));
+    let from = match from_date {
+        Some(date) => parse_date(date)?,
+        None => chrono::NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap_or(today),
+    };
+    let to = match to_date {
+        Some(date) => parse_date(date)?,
+        None => today,
+    };
+    if from > to || from > today {
+        return Err(ToolError::InvalidArgs(format!("The range {} to {} is empty or in the future", from, to)));
+    }
+    Ok((from, to))
+}
+
+// An address given as hex, an ENS name, or an account name, connecting to the node only for names
+async fn named_address(ctx: &ToolContext, address: &str) -> Result<Address, ToolError> {
+    if let Ok(address) = Address::from_str(address.trim()) {
+        return Ok(address);
+    }
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    resolve_account(ctx, &provider, address).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))
+}
+
+fn require_explorer(ctx: &ToolContext) -> Result<(), ToolError> {
+    match ctx.config.etherscan.api_key.as_deref().is_none_or(str::is_empty) {
+        true => Err(ToolError::NotConfigured("Transaction history comes from the Etherscan API; set ETHERSCAN_API_KEY".to_string())),
+        false => Ok(()),
+    }
+}
+
+async fn generate_report(ctx: &ToolContext, address: &str, from_date: Option<&str>, to_date: Option<&str>, format: &str, path: Option<&str>) -> Result<String, ToolError> {
+    require_explorer(ctx)?;
+    if format != "csv" && format != "markdown" {
+        return Err(ToolError::InvalidArgs("format must be 'csv' or 'markdown'".to_string()));
+    }
+    let (from, to) = date_range(from_date, to_date)?;
+    let address = format!("{:?}", named_address(ctx, address).await?);
+    let report = match report::history(&ctx.config.etherscan, ctx.config.chain.chain_id, &address, from, to).await {
+        Ok(report) => report,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch the history of {}: {}", address, e))),
+    };
+    let (content, extension) = match format {
+        "markdown" => (report::markdown(&report), "md"),
+        _ => (report::csv(&report), "csv"),
+    };
+    let path = path.map(str::to_string).unwrap_or_else(|| format!("reports/{}_{}_{}.{}", &address[..10], from, to, extension));
+    let written = workspace::write(&ctx.workspace_dir(), &path, &content, false, ctx.config.workspace.max_file_bytes)
+        .map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    Ok(format!("{}\n{}", report::summary(&report), written))
+}
+
+/// Cost basis and PnL of the user's positions (`pnl` tool, `/pnl`), optionally of one account or token
+pub async fn pnl_positions(ctx: &ToolContext, account: Option<&str>, token: Option<&str>) -> Result<String, ToolError> {
+    let Some(pool) = &ctx.pool else {
+        return Err(ToolError::NotConfigured("PnL tracking requires a database (DATABASE_URL)".to_string()));
+    };
+    let account = match account {
+        Some(account) => Some(named_address(ctx, account).await?),
+        None => None,
+    };
+    pnl::summary(&ctx.config, pool, ctx.user.as_deref(), account, token).await
+        .map_err(|e| ToolError::Failed(format!("Could not compute PnL: {}", e)))
+}
+
+async fn pnl_import(ctx: &ToolContext, account: &str, from_date: Option<&str>, to_date: Option<&str>) -> Result<String, ToolError> {
+    let Some(pool) = &ctx.pool else {
+        return Err(ToolError::NotConfigured("PnL tracking requires a database (DATABASE_URL)".to_string()));
+    };
+    require_explorer(ctx)?;
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("Please provide the account to import".to_string()));
+    }
+    let (from, to) = date_range(from_date, to_date)?;
+    let account = named_address(ctx, account).await?;
+    let imported = match pnl::import(&ctx.config, pool, ctx.user.as_deref(), account, from, to).await {
+        Ok(imported) => imported,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not import the history of {:?}: {}", account, e))),
+    };
+    let mut message = format!("Imported {} entr{} for {:?} from {} to {}", imported.recorded, if imported.recorded == 1 { "y" } else { "ies" }, account, from, to);
+    if imported.known > 0 {
+        message.push_str(&format!(" ({} already recorded)", imported.known));
+    }
+    if imported.unpriced > 0 {
+        message.push_str(&format!("\n{} had no CoinGecko price, so their cost counts as zero", imported.unpriced));
+    }
+    if imported.truncated {
+        message.push_str("\nNote: the explorer returned as many entries as max_transactions allows, so the end of the range may be missing; import shorter ranges.");
+    }
+    Ok(message)
+}
+
+async fn snapshot_proposals(ctx: &ToolContext, space: Option<&str>) -> Result<String, ToolError> {
+    let spaces = match space {
+        Some(space) => vec![space.to_string()],
+        None => ctx.config.snapshot.spaces.clone(),
+    };
+    if spaces.is_empty() {
+        return Err(ToolError::NotConfigured("No Snapshot spaces are configured; add them under [snapshot] spaces, or name a space".to_string()));
+    }
+    let proposals = match snapshot::active_proposals(&ctx.config.snapshot, &spaces).await {
+        Ok(proposals) => proposals,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not list Snapshot proposals: {}", e))),
+    };
+    if proposals.is_empty() {
+        return Ok(format!("No active proposals in {}", spaces.join(", ")));
+    }
+    Ok(snapshot::list(&proposals, chrono::Utc::now()))
+}
+
+async fn find_snapshot_proposal(ctx: &ToolContext, id: &str) -> Result<snapshot::Proposal, ToolError> {
+    if id.is_empty() {
+        return Err(ToolError::InvalidArgs("proposal_id is required".to_string()));
+    }
+    match snapshot::proposal(&ctx.config.snapshot, id).await {
+        Ok(Some(proposal)) => Ok(proposal),
+        Ok(None) => Err(ToolError::InvalidArgs(format!("No Snapshot proposal {}", id))),
+        Err(e) => Err(ToolError::RpcError(format!("Could not read Snapshot proposal {}: {}", id, e))),
+    }
+}
+
+async fn snapshot_proposal(ctx: &ToolContext, id: &str) -> Result<String, ToolError> {
+    let proposal = find_snapshot_proposal(ctx, id).await?;
+    Ok(snapshot::details(&proposal, chrono::Utc::now()))
+}
+
+// Cast a vote, after checking it fits the proposal and the voter has voting power
+async fn snapshot_vote(ctx: &ToolContext, id: &str, choice: Option<&serde_json::Value>, reason: &str, account: &str, confirm: bool) -> Result<String, ToolError> {
+    let Some(choice) = choice.filter(|choice| !choice.is_null()) else {
+        return Err(ToolError::InvalidArgs("choice is required to vote".to_string()));
+    };
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("account (the voting address or account) is required to vote".to_string()));
+    }
+    let proposal = find_snapshot_proposal(ctx, id).await?;
+    if proposal.state != "active" {
+        return Err(ToolError::InvalidArgs(format!("Voting on \"{}\" is {}, not open", proposal.title, proposal.state)));
+    }
+    let choice = snapshot::encode_choice(&proposal, choice).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let voter = named_address(ctx, account).await?;
+    let description = format!("Vote {} on \"{}\" ({}) as {:?}", snapshot::describe_choice(&proposal, &choice), proposal.title, proposal.space.id, voter);
+    if !confirm {
+        return Err(ToolError::InvalidArgs(format!("Votes need confirm=true. Show the user this vote and cast it only once they confirm:\n{}", description)));
+    }
+    // A vote without voting power would be refused; when it can't be read, the sequencer decides
+    let power = match snapshot::voting_power(&ctx.config.snapshot, voter, &proposal).await {
+        Ok(power) if power <= 0.0 => return Err(ToolError::Failed(format!("{:?} has no voting power on this proposal, so the vote would be refused", voter))),
+        Ok(power) => format!("Voting power: {}", power),
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not read Snapshot voting power");
+            "Voting power: unknown".to_string()
+        },
+    };
+    if ctx.dry_run {
+        return Ok(dry_run_report(&description, &[&power], &ctx.config.chain));
+    }
+    let wallet = match load_signer(ctx, voter).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let vote = snapshot::vote_typed_data(&ctx.config.snapshot, voter, &proposal, &choice, reason, chrono::Utc::now().timestamp());
+    let typed_data = signing::parse_typed_data(&vote).map_err(|e| ToolError::Failed(e.to_string()))?;
+    let signature = match signing::sign(&wallet, &SignRequest::TypedData(Box::new(typed_data))).await {
+        Ok(signature) => signature,
+        Err(e) => return Err(ToolError::Failed(format!("Could not sign the vote: {}", e))),
+    };
+    let receipt = match snapshot::send_vote(&ctx.config.snapshot, voter, &vote, &signature).await {
+        Ok(receipt) => receipt,
+        Err(e) => return Err(ToolError::Failed(format!("{}. The vote was NOT recorded.", e))),
+    };
+    Ok(format!("Voted {} on \"{}\" ({}) as {:?}\n{}\nVote id: {}", snapshot::describe_choice(&proposal, &choice), proposal.title, proposal.space.id, voter, power, receipt))
+}
+
+// The provider and the Governor a call names, or the only configured one
+async fn governor_context(ctx: &ToolContext, governor: Option<&str>) -> Result<(Arc<EthProvider>, governor::GovernorInfo), ToolError> {
+    if governor.is_none() && ctx.config.governor.contracts.is_empty() {
+        return Err(ToolError::NotConfigured("No Governor given and none configured; add them under [governor] contracts, or give an address".to_string()));
+    }
+    let address = governor::resolve(&ctx.config.governor, governor).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => Arc::new(provider),
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    match governor::info(provider.clone(), address).await {
+        Ok(info) => Ok((provider, info)),
+        Err(e) => Err(ToolError::RpcError(e.to_string())),
+    }
+}
+
+async fn governor_proposals(ctx: &ToolContext, governor: Option<&str>) -> Result<String, ToolError> {
+    let (provider, info) = governor_context(ctx, governor).await?;
+    let proposals = match governor::proposals(provider.clone(), &ctx.config.governor, info.address).await {
+        Ok(proposals) => proposals,
+        Err(e) => return Err(ToolError::RpcError(e.to_string())),
+    };
+    if proposals.is_empty() {
+        return Ok(format!("No proposals on {} in the last {} blocks", info.name, ctx.config.governor.lookback_blocks));
+    }
+    let mut listed = Vec::new();
+    for proposal in proposals {
+        match governor::status(provider.clone(), info.address, &info, proposal.id).await {
+            Ok(status) => listed.push((proposal, status)),
+            Err(e) => return Err(ToolError::RpcError(format!("Could not read proposal {}: {}", proposal.id, e))),
+        }
+    }
+    Ok(governor::list(&info, &listed, chrono::Utc::now()))
+}
+
+async fn governor_proposal(ctx: &ToolContext, governor: Option<&str>, id: &str) -> Result<String, ToolError> {
+    if id.is_empty() {
+        return Err(ToolError::InvalidArgs("proposal_id is required".to_string()));
+    }
+    let id = governor::parse_proposal_id(id).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let (provider, info) = governor_context(ctx, governor).await?;
+    let status = governor::status(provider.clone(), info.address, &info, id).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    // The description only exists in the creation event; without it the state is still useful
+    let proposal = match governor::find(provider, &ctx.config.governor, info.address, id).await {
+        Ok(proposal) => proposal,
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not look up the proposal's creation event");
+            None
+        },
+    };
+    Ok(governor::details(&info, id, proposal.as_ref(), &status, chrono::Utc::now()))
+}
+
+async fn governor_voting_power(ctx: &ToolContext, governor: Option<&str>, account: &str, id: &str) -> Result<String, ToolError> {
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("account is required".to_string()));
+    }
+    let id = match id {
+        "" => None,
+        id => Some(governor::parse_proposal_id(id).map_err(|e| ToolError::InvalidArgs(e.to_string()))?),
+    };
+    let voter = named_address(ctx, account).await?;
+    let (provider, info) = governor_context(ctx, governor).await?;
+    let snapshot = match id {
+        Some(id) => Some(governor::status(provider.clone(), info.address, &info, id).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?.snapshot),
+        None => None,
+    };
+    let power = match governor::voting_power(provider, &info, voter, snapshot).await {
+        Ok(power) => power,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read voting power: {}", e))),
+    };
+    let at = match id {
+        Some(id) => format!("on proposal {}", id),
+        None => "now".to_string(),
+    };
+    let mut result = format!("{:?} has {} votes on {} {}", voter, governor::format_votes(&info, power), info.name, at);
+    if power.is_zero() {
+        result.push_str("\nVotes come from delegated tokens; holders must delegate (to themselves or another address) before a proposal's snapshot to vote on it.");
+    }
+    Ok(result)
+}
+
+struct GovernorVote<'a> {
+    proposal_id: &'a str,
+    support: &'a str,
+    reason: &'a str,
+    account: &'a str,
+    confirm: bool,
+    force: bool,
+    private: Option<bool>,
+}
+
+// Cast a vote after checking the proposal is open and the voter has votes, then send it like any
+// other contract call
+async fn governor_vote(ctx: &ToolContext, governor: Option<&str>, vote: &GovernorVote<'_>) -> Result<String, ToolError> {
+    if vote.proposal_id.is_empty() || vote.support.is_empty() || vote.account.is_empty() {
+        return Err(ToolError::InvalidArgs("proposal_id, support, and account are required to vote".to_string()));
+    }
+    let id = governor::parse_proposal_id(vote.proposal_id).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let support = governor::parse_support(vote.support).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let voter = named_address(ctx, vote.account).await?;
+    let (provider, info) = governor_context(ctx, governor).await?;
+    let status = governor::status(provider.clone(), info.address, &info, id).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    if status.state != governor::ACTIVE {
+        return Err(ToolError::InvalidArgs(format!("Voting on proposal {} is {}, not open", id, governor::state_name(status.state))));
+    }
+    match governor::has_voted(provider.clone(), info.address, id, voter).await {
+        Ok(true) => return Err(ToolError::InvalidArgs(format!("{:?} already voted on proposal {}", voter, id))),
+        Ok(false) => {},
+        Err(e) => tracing::warn!(error = %e, "Could not check whether the account voted"),
+    }
+    let power = match governor::voting_power(provider.clone(), &info, voter, Some(status.snapshot)).await {
+        Ok(power) if power.is_zero() => return Err(ToolError::Failed(format!(
+            "{:?} had no delegated votes at the proposal's snapshot, so its vote would count for nothing", voter))),
+        Ok(power) => power,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read voting power: {}", e))),
+    };
+    let title = match governor::find(provider.clone(), &ctx.config.governor, info.address, id).await {
+        Ok(Some(proposal)) => format!(" \"{}\"", governor::title(&proposal.description)),
+        _ => String::new(),
+    };
+    let description = format!("Vote {} on proposal {}{} on {} as {:?}", governor::support_name(support), id, title, info.name, voter);
+    let power = format!("Voting power: {}", governor::format_votes(&info, power));
+    if !vote.confirm {
+        return Err(ToolError::InvalidArgs(format!("Votes need confirm=true. Show the user this vote and cast it only once they confirm:\n{}\n{}", description, power)));
+    }
+    
+    let calldata = governor::vote_call(provider.clone(), info.address, id, support, vote.reason);
+    let labels = [(voter, "voter"), (info.address, "governor")];
+    let (output, _) = send_contract_call(ctx, &provider, voter, info.address, U256::zero(), calldata, &description, &labels, vote.force, vote.private, None).await?;
+    Ok(format!("{}\n{}", output, power))
+}
+
+// The staking protocols a call names, or all of those on the configured chain
+async fn staking_protocols(ctx: &ToolContext, provider: Arc<EthProvider>, name: Option<&str>) -> Result<Vec<staking::Protocol>, ToolError> {
+    let deployments = staking::deployments(&ctx.config.staking, ctx.config.chain.chain_id, name).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    if deployments.is_empty() {
+        return Err(ToolError::NotConfigured(format!("No staking protocols are configured for chain {} ({}); add them under [staking]", ctx.config.chain.chain_id, ctx.config.chain.name)));
+    }
+    let mut protocols = Vec::new();
+    for deployment in deployments {
+        match staking::connect(provider.clone(), deployment).await {
+            Ok(protocol) => protocols.push(protocol),
+            Err(e) => return Err(ToolError::RpcError(e.to_string())),
+        }
+    }
+    Ok(protocols)
+}
+
+// The one protocol a transaction goes to
+async fn staking_protocol(ctx: &ToolContext, provider: Arc<EthProvider>, name: Option<&str>) -> Result<staking::Protocol, ToolError> {
+    let mut protocols = staking_protocols(ctx, provider, name).await?;
+    match protocols.len() {
+        1 => Ok(protocols.remove(0)),
+        _ => Err(ToolError::InvalidArgs(format!("Name the protocol: {}", protocols.iter().map(|protocol| protocol.name.as_str()).collect::<Vec<_>>().join(" or ")))),
+    }
+}
+
+
+async fn stake_rates(ctx: &ToolContext, protocol: Option<&str>) -> Result<String, ToolError> {
+    let provider = chain_provider(ctx).await?;
+    let mut sections = Vec::new();
+    for protocol in staking_protocols(ctx, provider.clone(), protocol).await? {
+        match staking::rates(provider.clone(), &protocol).await {
+            Ok(rates) => sections.push(staking::describe_rates(&protocol, &rates)),
+            Err(e) => return Err(ToolError::RpcError(format!("Could not read {} rates: {}", protocol.name, e))),
+        }
+    }
+    Ok(sections.join("\n\n"))
+}
+
+async fn stake_position(ctx: &ToolContext, protocol: Option<&str>, account: &str) -> Result<String, ToolError> {
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("account is required".to_string()));
+    }
+    let owner = named_address(ctx, account).await?;
+    let provider = chain_provider(ctx).await?;
+    let mut lines = vec![format!("Staking of {:?} on {}:", owner, ctx.config.chain.name)];
+    for protocol in staking_protocols(ctx, provider.clone(), protocol).await? {
+        match staking::position(provider.clone(), &protocol, owner).await {
+            Ok(position) => lines.push(staking::describe_position(&protocol, &position)),
+            Err(e) => return Err(ToolError::RpcError(format!("Could not read the {} position: {}", protocol.name, e))),
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+struct StakeRequest<'a> {
+    amount: &'a str,
+    account: &'a str,
+    request_id: &'a str,
+    confirm: bool,
+    force: bool,
+    private: Option<bool>,
+}
+
+// The amount and account of a deposit or withdrawal
+async fn stake_amount(ctx: &ToolContext, request: &StakeRequest<'_>) -> Result<(U256, Address), ToolError> {
+    if request.amount.is_empty() || request.account.is_empty() {
+        return Err(ToolError::InvalidArgs("amount and account are required".to_string()));
+    }
+    let amount = match units::parse_amount(request.amount) {
+        Ok(amount) if !amount.is_zero() => amount,
+        Ok(_) => return Err(ToolError::InvalidArgs("The amount must be more than zero".to_string())),
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid amount: {}", e))),
+    };
+    Ok((amount, named_address(ctx, request.account).await?))
+}
+
+fn unconfirmed_stake(description: &str, details: &str) -> ToolError {
+    ToolError::InvalidArgs(format!("Staking transactions need confirm=true. Show the user this quote and send it only once they confirm:\n{}\n{}", description, details))
+}
+
+async fn stake_deposit(ctx: &ToolContext, protocol: Option<&str>, request: &StakeRequest<'_>) -> Result<String, ToolError> {
+    let (amount, owner) = stake_amount(ctx, request).await?;
+    let provider = chain_provider(ctx).await?;
+    let protocol = staking_protocol(ctx, provider.clone(), protocol).await?;
+    let rates = match staking::rates(provider.clone(), &protocol).await {
+        Ok(rates) => rates,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read {} rates: {}", protocol.name, e))),
+    };
+    if rates.paused {
+        return Err(ToolError::Failed(format!("{} is not accepting deposits now", protocol.name)));
+    }
+    if amount < rates.minimum_deposit || amount > rates.deposit_limit {
+        return Err(ToolError::InvalidArgs(format!("{} takes deposits of {} to {} ETH now", protocol.name,
+                                                  units::format_eth(rates.minimum_deposit), units::format_eth(rates.deposit_limit))));
+    }
+    let minted = match staking::deposit_output(provider.clone(), &protocol, &rates, amount).await {
+        Ok(minted) => minted,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not quote the deposit: {}", e))),
+    };
+    let description = format!("Stake {} ETH with {} for about {} {} as {:?}", units::format_eth(amount), protocol.name, units::format_eth(minted), protocol.token(), owner);
+    if !request.confirm {
+        return Err(unconfirmed_stake(&description, &staking::describe_rates(&protocol, &rates)));
+    }
+    let (contract, calldata) = staking::deposit_call(provider.clone(), &protocol);
+    let (output, _) = stake_send(ctx, &provider, owner, contract, amount, calldata, &description, request).await?;
+    Ok(with_stake_position(ctx, provider, &protocol, owner, output).await)
+}
+
+async fn stake_withdraw(ctx: &ToolContext, protocol: Option<&str>, request: &StakeRequest<'_>) -> Result<String, ToolError> {
+    let (amount, owner) = stake_amount(ctx, request).await?;
+    let provider = chain_provider(ctx).await?;
+    let protocol = staking_protocol(ctx, provider.clone(), protocol).await?;
+    let position = match staking::position(provider.clone(), &protocol, owner).await {
+        Ok(position) => position,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read the {} position: {}", protocol.name, e))),
+    };
+    if position.balance < amount {
+        return Err(ToolError::InvalidArgs(format!("{:?} holds {} {}, less than {}", owner, units::format_eth(position.balance), protocol.token(), units::format_eth(amount))));
+    }
+    let (contract, calldata) = staking::withdrawal_call(provider.clone(), &protocol, owner, amount).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let payout = match staking::withdrawal_output(provider.clone(), &protocol, amount).await {
+        Ok(payout) => payout,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not quote the withdrawal: {}", e))),
+    };
+    let (description, details) = match protocol.contracts {
+        staking::Contracts::Lido { .. } => (format!("Request the withdrawal of {} stETH from Lido as {:?}", units::format_eth(amount), owner),
+                                            format!("About {} ETH can be claimed once Lido finalizes the request, usually within 1 to 5 days", units::format_eth(payout))),
+        staking::Contracts::RocketPool { .. } => (format!("Burn {} rETH with Rocket Pool for about {} ETH as {:?}", units::format_eth(amount), units::format_eth(payout), owner),
+                                                  "The ETH is paid out in the same transaction".to_string()),
+    };
+    if !request.confirm {
+        return Err(unconfirmed_stake(&description, &details));
+    }
+    
+    // Lido's queue takes the stETH, so it needs an allowance first
+    let approval = match staking::withdrawal_approval(provider.clone(), &protocol, owner, amount).await {
+        Ok(approval) => approval,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not check the stETH allowance: {}", e))),
+    };
+    let mut approval_note = String::new();
+    if let Some((token, approve_data)) = approval {
+        if ctx.dry_run {
+            return Ok(dry_run_report(&format!("Approve {} for the Lido withdrawal queue, then: {}", protocol.token(), description),
+                                     &[&details, "The withdrawal itself can't be simulated until the approval is mined."], &ctx.config.chain));
+        }
+        let approve = format!("Approve {} {} for the Lido withdrawal queue", units::format_eth(amount), protocol.token());
+        let (output, receipt) = stake_send(ctx, &provider, owner, token, U256::zero(), approve_data, &approve, request).await?;
+        if receipt.is_none_or(|receipt| receipt.status != Some(U64::from(1))) {
+            return Err(ToolError::Failed(format!("The approval did not confirm, so the withdrawal was NOT requested.\n{}", output)));
+        }
+        approval_note = format!("{}\n\n", output);
+    }
+    let (output, receipt) = stake_send(ctx, &provider, owner, contract, U256::zero(), calldata, &description, request).await?;
+    let requested = receipt.map(|receipt| staking::requested_ids(&receipt, &protocol)).unwrap_or_default();
+    let output = match requested.is_empty() {
+        true => output,
+        false => format!("{}\nWithdrawal request: {}", output, requested.iter().map(|id| format!("#{}", id)).collect::<Vec<_>>().join(", ")),
+    };
+    Ok(with_stake_position(ctx, provider, &protocol, owner, format!("{}{}", approval_note, output)).await)
+}
+
+async fn stake_claim(ctx: &ToolContext, protocol: Option<&str>, request: &StakeRequest<'_>) -> Result<String, ToolError> {
+    if request.request_id.is_empty() || request.account.is_empty() {
+        return Err(ToolError::InvalidArgs("request_id and account are required".to_string()));
+    }
+    let Ok(request_id) = U256::from_dec_str(request.request_id.trim_start_matches('#')) else {
+        return Err(ToolError::InvalidArgs(format!("Invalid withdrawal request id {}", request.request_id)));
+    };
+    let owner = named_address(ctx, request.account).await?;
+    let provider = chain_provider(ctx).await?;
+    let protocol = staking_protocol(ctx, provider.clone(), protocol.or(Some("lido"))).await?;
+    let (contract, calldata, steth) = staking::claim_call(provider.clone(), &protocol, owner, request_id).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let description = format!("Claim Lido withdrawal request #{} ({} stETH) for {:?}", request_id, units::format_eth(steth), owner);
+    if !request.confirm {
+        return Err(unconfirmed_stake(&description, "The ETH goes to the request's owner"));
+    }
+    let (output, _) = stake_send(ctx, &provider, owner, contract, U256::zero(), calldata, &description, request).await?;
+    Ok(with_stake_position(ctx, provider, &protocol, owner, output).await)
+}
+
+// The balance a staking transaction left, after its report; a dry run left none to report
+async fn with_stake_position(ctx: &ToolContext, provider: Arc<EthProvider>, protocol: &staking::Protocol, owner: Address, output: String) -> String {
+    if ctx.dry_run {
+        return output;
+    }
+    match staking::position(provider, protocol, owner).await {
+        Ok(position) => format!("{}\nBalance after: {}", output, staking::describe_position(protocol, &position)),
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not read the staking position");
+            output
+        },
+    }
+}
+
+// One staking transaction
+#[allow(clippy::too_many_arguments)]
+async fn stake_send(ctx: &ToolContext, provider: &Arc<EthProvider>, from_address: Address, contract: Address, value: U256, calldata: Bytes, description: &str, request: &StakeRequest<'_>) -> Result<(String, Option<TransactionReceipt>), ToolError> {
+    let labels = [(from_address, "staker"), (contract, "staking contract")];
+    send_contract_call(ctx, provider, from_address, contract, value, calldata, description, &labels, request.force, request.private, None).await
+}
+
+// The lending markets a call names, or all of those on the configured chain
+async fn lending_markets(ctx: &ToolContext, provider: Arc<EthProvider>, name: Option<&str>) -> Result<Vec<lending::Market>, ToolError> {
+    let deployments = lending::deployments(&ctx.config.lending, ctx.config.chain.chain_id, name).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    if deployments.is_empty() {
+        return Err(ToolError::NotConfigured(format!("No lending markets are configured for chain {} ({}); add them under [lending]", ctx.config.chain.chain_id, ctx.config.chain.name)));
+    }
+    let mut markets = Vec::new();
+    for deployment in deployments {
+        match lending::connect(provider.clone(), deployment).await {
+            Ok(market) => markets.push(market),
+            Err(e) => return Err(ToolError::RpcError(e.to_string())),
+        }
+    }
+    Ok(markets)
+}
+
+// The one market a transaction goes to
+async fn lending_market(ctx: &ToolContext, provider: Arc<EthProvider>, name: Option<&str>) -> Result<lending::Market, ToolError> {
+    let mut markets = lending_markets(ctx, provider, name).await?;
+    match markets.len() {
+        1 => Ok(markets.remove(0)),
+        _ => Err(ToolError::InvalidArgs(format!("Name the market: {}", markets.iter().map(|market| market.name.as_str()).collect::<Vec<_>>().join(" or ")))),
+    }
+}
+
+async fn lending_health(ctx: &ToolContext, market: Option<&str>, account: &str) -> Result<String, ToolError> {
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("account is required".to_string()));
+    }
+    let owner = named_address(ctx, account).await?;
+    let provider = chain_provider(ctx).await?;
+    let mut sections = vec![format!("Lending of {:?} on {}:", owner, ctx.config.chain.name)];
+    for market in lending_markets(ctx, provider.clone(), market).await? {
+        match lending::health(provider.clone(), &market, owner).await {
+            Ok(health) => sections.push(lending::describe_health(&market, &health, ctx.config.lending.min_health_factor)),
+            Err(e) => return Err(ToolError::RpcError(format!("Could not read the {} position: {}", market.name, e))),
+        }
+    }
+    Ok(sections.join("\n\n"))
+}
+
+struct LendingRequest<'a> {
+    asset: &'a str,
+    amount: &'a str,
+    account: &'a str,
+    confirm: bool,
+    accept_risk: bool,
+    force: bool,
+    private: Option<bool>,
+}
+
+async fn lending_action(ctx: &ToolContext, market: Option<&str>, action: lending::Action, request: &LendingRequest<'_>) -> Result<String, ToolError> {
+    if request.amount.is_empty() || request.account.is_empty() {
+        return Err(ToolError::InvalidArgs("amount and account are required".to_string()));
+    }
+    let owner = named_address(ctx, request.account).await?;
+    let provider = chain_provider(ctx).await?;
+    let market = lending_market(ctx, provider.clone(), market).await?;
+    
+    // Compound lends out one base asset, which borrow and repay are about unless told otherwise
+    let asset = match (&market.contracts, request.asset.is_empty()) {
+        (_, false) => request.asset.to_string(),
+        (lending::Contracts::CompoundV3 { base, .. }, true) if matches!(action, lending::Action::Borrow | lending::Action::Repay) => format!("{:?}", base),
+        _ => return Err(ToolError::InvalidArgs("asset is required".to_string())),
+    };
+    let token = dex::resolve_token(provider.clone(), &ctx.config.dex, &asset).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    if token.native {
+        return Err(ToolError::InvalidArgs("Money markets lend WETH rather than native ETH; wrap it first and use WETH".to_string()));
+    }
+    let reserve = lending::reserve(provider.clone(), &market, token, owner).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let health = match lending::health(provider.clone(), &market, owner).await {
+        Ok(health) => health,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read the {} position: {}", market.name, e))),
+    };
+    let symbol = reserve.token.symbol.clone();
+    
+    // 'all' takes the whole supply or debt
+    let everything = request.amount.eq_ignore_ascii_case("all") || request.amount.eq_ignore_ascii_case("max");
+    let amount = match (action, everything) {
+        (lending::Action::Withdraw, true) => reserve.supplied,
+        (lending::Action::Repay, true) => reserve.borrowed,
+        (_, true) => return Err(ToolError::InvalidArgs("Only withdraw and repay take the amount 'all'".to_string())),
+        (_, false) => dex::parse_token_amount(request.amount, &reserve.token).map_err(|e| ToolError::InvalidArgs(e.to_string()))?,
+    };
+    let held = |amount: U256| dex::format_token_amount(amount, &reserve.token);
+    match action {
+        lending::Action::Supply => {},
+        lending::Action::Withdraw if reserve.supplied.is_zero() => return Err(ToolError::InvalidArgs(format!("{:?} has no {} supplied to {}", owner, symbol, market.name))),
+        lending::Action::Withdraw if amount > reserve.supplied => return Err(ToolError::InvalidArgs(format!("{:?} has only {} supplied to {}{}", owner, held(reserve.supplied), market.name,
+                                                                                                              if reserve.base { "; borrow to take out more" } else { "" }))),
+        lending::Action::Withdraw => {},
+        lending::Action::Borrow if !reserve.borrowable => return Err(ToolError::InvalidArgs(match market.contracts {
+            lending::Contracts::CompoundV3 { .. } => format!("Only the base asset of {} can be borrowed; leave asset out to borrow it", market.name),
+            lending::Contracts::AaveV3 { .. } => format!("{} can't be borrowed from {}", symbol, market.name),
+        })),
+        lending::Action::Borrow if reserve.base && !reserve.supplied.is_zero() => return Err(ToolError::InvalidArgs(format!("{:?} supplies {} to {}; withdraw it before borrowing", owner, held(reserve.supplied), market.name))),
+        lending::Action::Borrow if lending::usd_value(&reserve, amount) > health.available_usd => return Err(ToolError::InvalidArgs(format!("{:?} can borrow only ${:.2} more from {}, less than the ${:.2} of {}", owner, health.available_usd, market.name,
+                                                                                                                                    lending::usd_value(&reserve, amount), held(amount)))),
+        lending::Action::Borrow => {},
+        lending::Action::Repay if reserve.borrowed.is_zero() => return Err(ToolError::InvalidArgs(format!("{:?} owes no {} to {}", owner, symbol, market.name))),
+        lending::Action::Repay if amount > reserve.borrowed => return Err(ToolError::InvalidArgs(format!("{:?} owes only {} to {}; repay 'all' to clear it", owner, held(reserve.borrowed), market.name))),
+        lending::Action::Repay => {},
+    }
+    
+    let before = health.factor();
+    let after = lending::projected_factor(&health, &reserve, action, amount);
+    let minimum = ctx.config.lending.min_health_factor;
+    let lowered = matches!((before, after), (_, Some(after)) if before.is_none_or(|before| after < before));
+    if lowered && after.is_some_and(|after| after < 1.0) {
+        return Err(ToolError::InvalidArgs(format!("{} {} would leave a health factor of {}, below 1, so the position could be liquidated at once. It was NOT sent.",
+                                                  action.verb(), held(amount), lending::format_factor(after))));
+    }
+    let warning = match lowered && after.is_some_and(|after| after < minimum) {
+        true => Some(format!("Warning: this leaves a health factor of {}, below the {} minimum; a price move against the collateral could get it liquidated",
+                             lending::format_factor(after), minimum)),
+        false => None,
+    };
+    let direction = match action {
+        lending::Action::Supply | lending::Action::Repay => "to",
+        lending::Action::Withdraw | lending::Action::Borrow => "from",
+    };
+    let description = format!("{} {} (${:.2}) {} {} as {:?}", action.verb(), held(amount), lending::usd_value(&reserve, amount), direction, market.name, owner);
+    let mut details = format!("Health factor: {} -> {}", lending::format_factor(before), lending::format_factor(after));
+    if let Some(warning) = &warning {
+        details.push_str(&format!("\n{}\nThis also needs accept_risk=true once the user has accepted the risk", warning));
+    }
+    if !request.confirm {
+        return Err(ToolError::InvalidArgs(format!("Lending transactions need confirm=true. Show the user this quote and send it only once they confirm:\n{}\n{}", description, details)));
+    }
+    if let Some(warning) = &warning
+        && !request.accept_risk
+    {
+        return Err(ToolError::InvalidArgs(format!("{}\n{}\nIt was NOT sent; repeat it with accept_risk=true only if the user explicitly accepts the risk", description, warning)));
+    }
+    
+    let call_amount = lending::call_amount(&market, &reserve, action, amount, everything);
+    let (contract, calldata) = lending::action_call(provider.clone(), &market, action, reserve.token.address, call_amount, owner);
+    
+    // Supplies and repayments are pulled by the market, so they need an allowance first; a whole
+    // debt keeps accruing interest until the repayment is mined
+    let mut approval_note = String::new();
+    if matches!(action, lending::Action::Supply | lending::Action::Repay) {
+        let allowance = if everything { amount + amount / 1000 + 1 } else { amount };
+        let approval = match lending::approval_call(provider.clone(), &market, reserve.token.address, owner, allowance).await {
+            Ok(approval) => approval,
+            Err(e) => return Err(ToolError::RpcError(format!("Could not check the {} allowance: {}", symbol, e))),
+        };
+        if let Some((token, approve_data)) = approval {
+            if ctx.dry_run {
+                return Ok(dry_run_report(&format!("Approve {} for {}, then: {}", symbol, market.name, description),
+                                         &[&details, "The transaction itself can't be simulated until the approval is mined."], &ctx.config.chain));
+            }
+            let approve = format!("Approve {} for {}", held(allowance), market.name);
+            let (output, receipt) = lending_send(ctx, &provider, owner, token, approve_data, &approve, request).await?;
+            if receipt.is_none_or(|receipt| receipt.status != Some(U64::from(1))) {
+                return Err(ToolError::Failed(format!("The approval did not confirm, so nothing was {}.\n{}", if action == lending::Action::Supply { "supplied" } else { "repaid" }, output)));
+            }
+            approval_note = format!("{}\n\n", output);
+        }
+    }
+    let (output, _) = lending_send(ctx, &provider, owner, contract, calldata, &description, request).await?;
+    let output = match &warning {
+        Some(warning) => format!("{}{}\n{}", approval_note, output, warning),
+        None => format!("{}{}", approval_note, output),
+    };
+    // A dry run left the position as it was
+    if ctx.dry_run {
+        return Ok(output);
+    }
+    match lending::health(provider, &market, owner).await {
+        Ok(health) => Ok(format!("{}\nHealth after: {}", output, lending::describe_health(&market, &health, minimum))),
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not read the lending position");
+            Ok(output)
+        },
+    }
+}
+
+// One lending transaction
+async fn lending_send(ctx: &ToolContext, provider: &Arc<EthProvider>, from_address: Address, contract: Address, calldata: Bytes, description: &str, request: &LendingRequest<'_>) -> Result<(String, Option<TransactionReceipt>), ToolError> {
+    let labels = [(from_address, "account"), (contract, "lending market")];
+    send_contract_call(ctx, provider, from_address, contract, U256::zero(), calldata, description, &labels, request.force, request.private, None).await
+}
+
+struct BridgeRequest<'a> {
+    token: &'a str,
+    amount: &'a str,
+    to_chain: &'a str,
+    to_token: &'a str,
+    account: &'a str,
+    recipient: &'a str,
+    confirm: bool,
+    force: bool,
+    private: Option<bool>,
+}
+
+// A route for the requested transfer, from its sender, to the destination chain
+async fn bridge_route(ctx: &ToolContext, provider: Arc<EthProvider>, request: &BridgeRequest<'_>) -> Result<(bridge::Quote, Address, u64), ToolError> {
+    if request.token.is_empty() || request.amount.is_empty() || request.to_chain.is_empty() || request.account.is_empty() {
+        return Err(ToolError::InvalidArgs("token, amount, to_chain, and account are required".to_string()));
+    }
+    let from_chain = ctx.config.chain.chain_id;
+    let to_chain = bridge::destination(&ctx.config.bridge, from_chain, request.to_chain).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let sender = named_address(ctx, request.account).await?;
+    let recipient = match request.recipient.is_empty() {
+        true => sender,
+        false => named_address(ctx, request.recipient).await?,
+    };
+    let token = dex::resolve_token(provider, &ctx.config.dex, request.token).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let amount = dex::parse_token_amount(request.amount, &token).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let to_token = match request.to_token.is_empty() {
+        true => token.symbol.as_str(),
+        false => request.to_token,
+    };
+    let transfer = bridge::Transfer {
+        from_chain,
+        to_chain,
+        from_token: if token.native { bridge::NATIVE_TOKEN } else { token.address },
+        to_token,
+        amount,
+        from_address: sender,
+        to_address: recipient,
+    };
+    match bridge::quote(&ctx.config.bridge, &transfer).await {
+        Ok(quote) => Ok((quote, sender, to_chain)),
+        Err(e) => Err(ToolError::Failed(format!("Could not quote the transfer: {}", e))),
+    }
+}
+
+async fn bridge_quote(ctx: &ToolContext, request: &BridgeRequest<'_>) -> Result<String, ToolError> {
+    let provider = chain_provider(ctx).await?;
+    let (quote, sender, to_chain) = bridge_route(ctx, provider, request).await?;
+    let recipient = match request.recipient.is_empty() {
+        true => String::new(),
+        false => format!("\nRecipient: {}", request.recipient),
+    };
+    Ok(format!("{}\nSender: {:?}{}", bridge::describe_quote(&quote, ctx.config.chain.chain_id, to_chain), sender, recipient))
+}
+
+async fn bridge_execute(ctx: &ToolContext, request: &BridgeRequest<'_>) -> Result<String, ToolError> {
+    let provider = chain_provider(ctx).await?;
+    // Routes expire quickly, so the one sent is quoted afresh
+    let (quote, sender, to_chain) = bridge_route(ctx, provider.clone(), request).await?;
+    let from_chain = ctx.config.chain.chain_id;
+    let details = bridge::describe_quote(&quote, from_chain, to_chain);
+    let description = format!("Bridge {} from {} to {} via {}", quote.from_token.format(quote.from_amount), bridge::chain_name(from_chain), bridge::chain_name(to_chain), quote.bridge_name);
+    if !request.confirm {
+        return Err(ToolError::InvalidArgs(format!("Bridge transfers need confirm=true. Show the user this quote and send it only once they confirm:\n{}", details)));
+    }
+    
+    // The bridge contract pulls an ERC-20 from the sender, so it needs an allowance first
+    let mut approval_note = String::new();
+    if let Some(spender) = quote.approval_address {
+        let erc20 = dex::Erc20::new(quote.from_token.address, provider.clone());
+        let allowance = match erc20.allowance(sender, spender).call().await {
+            Ok(allowance) => allowance,
+            Err(e) => return Err(ToolError::RpcError(format!("Could not check the {} allowance: {}", quote.from_token.symbol, e))),
+        };
+        if allowance < quote.from_amount {
+            if ctx.dry_run {
+                return Ok(dry_run_report(&format!("Approve {} for {}, then: {}", quote.from_token.symbol, quote.bridge_name, description),
+                                         &[&details, "The transfer itself can't be simulated until the approval is mined."], &ctx.config.chain));
+            }
+            let approve_data = erc20.approve(spender, quote.from_amount).calldata().unwrap_or_default();
+            let approve = format!("Approve {} for {}", quote.from_token.format(quote.from_amount), quote.bridge_name);
+            let (output, receipt) = bridge_send(ctx, &provider, sender, quote.from_token.address, U256::zero(), approve_data, &approve, request, None).await?;
+            if receipt.is_none_or(|receipt| receipt.status != Some(U64::from(1))) {
+                return Err(ToolError::Failed(format!("The approval did not confirm, so the transfer was NOT sent.\n{}", output)));
+            }
+            approval_note = format!("{}\n\n", output);
+        }
+    }
+    let (output, receipt) = bridge_send(ctx, &provider, sender, quote.to, quote.value, quote.data.clone(), &description, request, Some((&quote.bridge, to_chain))).await?;
+    let output = match receipt {
+        Some(receipt) if receipt.status == Some(U64::from(1)) => format!("{}\nReceive: about {} (at least {}) on {}, in about {}. {}",
+                                                                        output, quote.to_token.format(quote.to_amount), quote.to_token.format(quote.to_amount_min), bridge::chain_name(to_chain),
+                                                                        bridge::describe_duration(quote.duration_secs),
+                                                                        match ctx.pool.is_some() && ctx.config.tracker.enabled {
+                                                                            true => "The tracker reports when it arrives.",
+                                                                            false => "Check its arrival with the 'status' operation.",
+                                                                        }),
+        _ => output,
+    };
+    Ok(format!("{}{}", approval_note, output))
+}
+
+async fn bridge_status(ctx: &ToolContext, tx_hash: &str) -> Result<String, ToolError> {
+    let Ok(hash) = H256::from_str(tx_hash.trim()) else {
+        return Err(ToolError::InvalidArgs("tx_hash must be the 32-byte hash of the transaction that sent the transfer".to_string()));
+    };
+    let tx_hash = format!("{:?}", hash);
+    let record = match ctx.pool.as_ref() {
+        Some(pool) => find_bridge_transfer(pool, &tx_hash).await.unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Could not load the bridge transfer");
+            None
+        }),
+        None => None,
+    };
+    let from_chain = record.as_ref().map_or(ctx.config.chain.chain_id, |record| record.from_chain_id as u64);
+    let status = match bridge::status(&ctx.config.bridge, &tx_hash, record.as_ref().map(|record| record.bridge.as_str()),
+                                      from_chain, record.as_ref().map(|record| record.to_chain_id as u64)).await {
+        Ok(status) => status,
+        Err(e) => return Err(ToolError::Failed(format!("Could not look up the transfer: {}", e))),
+    };
+    
+    // The user sees this outcome now, so the tracker doesn't report it again
+    if let (Some(pool), Some(record)) = (ctx.pool.as_ref(), &record)
+        && let Err(e) = tracker::record_bridge_status(pool, record, &status, true).await
+    {
+        tracing::warn!(error = %e, "Could not record the bridge transfer status");
+    }
+    let header = match &record {
+        Some(record) => format!("Bridge transfer {} ({})", tx_hash, record.description),
+        None => format!("Bridge transfer {} from {}", tx_hash, bridge::chain_name(from_chain)),
+    };
+    Ok(format!("{}\n{}", header, bridge::describe_status(&status)))
+}
+
+// One bridge transaction, and the transfer it starts when it is the bridging call itself
+#[allow(clippy::too_many_arguments)]
+async fn bridge_send(ctx: &ToolContext, provider: &Arc<EthProvider>, from_address: Address, contract: Address, value: U256, calldata: Bytes, description: &str,
+                     request: &BridgeRequest<'_>, transfer: Option<(&str, u64)>) -> Result<(String, Option<TransactionReceipt>), ToolError> {
+    let labels = [(from_address, "sender"), (contract, "bridge")];
+    send_contract_call(ctx, provider, from_address, contract, value, calldata, description, &labels, request.force, request.private, transfer).await
+}
+
+async fn approvals_list(ctx: &ToolContext, account: &str) -> Result<String, ToolError> {
+    if account.is_empty() {
+        return Err(ToolError::InvalidArgs("account is required".to_string()));
+    }
+    let owner = named_address(ctx, account).await?;
+    let provider = chain_provider(ctx).await?;
+    match approvals::audit(provider, &ctx.config.approvals, &ctx.config.etherscan, ctx.config.chain.chain_id, owner).await {
+        Ok(audit) => Ok(approvals::describe_audit(&audit, &ctx.config.chain.name)),
+        Err(e) => Err(ToolError::Failed(format!("Could not list the approvals of {:?}: {}", owner, e))),
+    }
+}
+
+struct ApprovalRequest<'a> {
+    account: &'a str,
+    token: &'a str,
+    spender: &'a str,
+    token_id: &'a str,
+    confirm: bool,
+    force: bool,
+    private: Option<bool>,
+}
+
+async fn approvals_revoke(ctx: &ToolContext, request: &ApprovalRequest<'_>) -> Result<String, ToolError> {
+    if request.account.is_empty() || request.token.is_empty() || request.spender.is_empty() {
+        return Err(ToolError::InvalidArgs("account, token, and spender are required".to_string()));
+    }
+    let owner = named_address(ctx, request.account).await?;
+    let spender = named_address(ctx, request.spender).await?;
+    let token = match ctx.config.dex.tokens.iter().find(|(symbol, _)| symbol.eq_ignore_ascii_case(request.token)) {
+        Some((_, address)) => Address::from_str(address).map_err(|_| ToolError::InvalidArgs(format!("Invalid address for {} in [dex.tokens]: {}", request.token, address)))?,
+        None => Address::from_str(request.token).map_err(|_| ToolError::InvalidArgs(format!("Unknown token {}; use a contract address or a symbol from [dex.tokens]", request.token)))?,
+    };
+    let token_id = match request.token_id.is_empty() {
+        true => None,
+        false => Some(U256::from_dec_str(request.token_id).map_err(|_| ToolError::InvalidArgs(format!("Invalid token_id: {}", request.token_id)))?),
+    };
+    let provider = chain_provider(ctx).await?;
+    let (approval, calldata) = approvals::revoke_call(provider.clone(), token, owner, spender, token_id).await.map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let description = format!("Revoke the {} on {:?} to {:?} from {:?}", approvals::describe_grant(&approval), token, spender, owner);
+    if !request.confirm {
+        return Err(ToolError::InvalidArgs(format!("Revocations need confirm=true. Show the user this and send it only once they confirm:\n{}", description)));
+    }
+    
+    let labels = [(owner, "owner"), (token, "token"), (spender, "spender")];
+    let (output, _) = send_contract_call(ctx, &provider, owner, token, U256::zero(), calldata, &description, &labels, request.force, request.private, None).await?;
+    Ok(output)
+}
+
+// Policy check, simulation, broadcast, and receipt of one contract call. A bridge transfer the
+// call starts is recorded as soon as it is sent, so the tracker follows it to the destination
+// chain even if waiting for the receipt times out.
+#[allow(clippy::too_many_arguments)]
+async fn send_contract_call(ctx: &ToolContext, provider: &Arc<EthProvider>, from_address: Address, contract: Address, value: U256, calldata: Bytes, description: &str,
+                            labels: &[(Address, &str)], force: bool, private: Option<bool>, transfer: Option<(&str, u64)>) -> Result<(String, Option<TransactionReceipt>), ToolError> {
+    let wallet = match load_signer(ctx, from_address).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    // Enforce the transaction policy before anything is signed
+    let intent = match policy_intent(provider, contract, value, &calldata).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+    };
+    let reservation = match ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        Ok(reservation) => reservation,
+        Err(reason) => return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The transaction was NOT sent.", reason))),
+    };
+    
+    let (mut typed_tx, fee_summary) = match build_transaction(provider, from_address, contract, value, Some(calldata), FeeOverrides::default()).await {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not get gas fees: {}", e))),
+    };
+    let simulation_summary = match check_simulation(provider, &mut typed_tx, None, labels, force).await {
+        Ok((_, summary)) => summary,
+        Err(message) => return Err(ToolError::SimulationFailed(format!("{}\n{}", description, message))),
+    };
+    if ctx.dry_run {
+        return Ok((dry_run_report(description, &[&simulation_summary, &fee_summary], &ctx.config.chain), None));
+    }
+    
+    let client = SignerMiddleware::new(provider.clone(), wallet);
+    let pending_tx = match submit_transaction(ctx, &client, from_address, typed_tx, description, private).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => return Err(ToolError::Failed(format!("Could not send transaction: {}", e))),
+    };
+    reservation.commit();
+    let tx_hash = pending_tx.tx_hash();
+    if let Some((bridge, to_chain)) = transfer {
+        tracker::record_bridge(ctx.pool.as_ref(), ctx.session_id, tx_hash, bridge, ctx.config.chain.chain_id, to_chain, description).await;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(60), pending_tx.confirmations(1)).await {
+        Ok(Ok(Some(receipt))) => {
+            tracker::record_receipt(ctx.pool.as_ref(), &receipt).await;
+            let status = if receipt.status == Some(U64::from(1)) { "succeeded" } else { "reverted" };
+            let output = format!("Transaction {}: {}\n\
+                                 {}\n\
+                                 {}\n\
+                                 Gas Used: {}\n\
+                                 Block Number: {}\n\
+                                 Transaction Hash: {:?}",
+                                 status, description, simulation_summary, fee_summary,
+                                 receipt.gas_used.unwrap_or_default(),
+                                 receipt.block_number.unwrap_or_default(), tx_hash);
+            Ok((output, Some(receipt)))
+        },
+        Ok(Ok(None)) => Ok((format!("Transaction submitted but no receipt was found.\n\
+                                    Transaction Hash: {:?}", tx_hash), None)),
+        Ok(Err(e)) => Err(ToolError::Failed(format!("Transaction submitted but failed: {}\n\
+                                                    {}\n\
+                                                    Transaction Hash: {:?}", e, description, tx_hash))),
+        Err(_) => Ok((format!("Transaction submitted but confirmation timed out after 60 seconds.\n\
+                              {}\n\
+                              Transaction Hash: {:?}", description, tx_hash), None)),
+    }
+}
+
+// Submit through the nonce manager and record the transaction, so the tracker reports its
+// outcome even if waiting for the receipt times out
+async fn submit_transaction<'a, M: Middleware>(ctx: &ToolContext, client: &'a M, from: Address, mut tx: TypedTransaction, description: &str, private: Option<bool>) -> anyhow::Result<PendingTransaction<'a, M::Provider>> {
+    let chain_id = ctx.config.chain.chain_id;
+    let relay = private_tx::relay(&ctx.config.chain, private).await?;
+    let pending_tx = NONCE_MANAGER.send(client, chain_id, from, &mut tx, relay.as_ref()).await?;
+    tracker::record_submitted(ctx.pool.as_ref(), ctx.session_id, chain_id, pending_tx.tx_hash(), &tx, from, description).await;
+    Ok(pending_tx)
+}
+
+// Reported in place of a broadcast in dry-run mode
+fn dry_run_report(action: &str, details: &[&str], chain: &ChainConfig) -> String {
+    format!("Dry run; nothing was sent.\n{}\n{}\nNetwork: {}", action, details.join("\n"), network_label(chain))
+}
+
+// Simulate a transaction and decide whether to broadcast it. A reverting simulation stops the
+// send (with the message to report) unless forced, in which case a fixed gas limit is set
+// since gas can't be estimated.
+async fn check_simulation(provider: &EthProvider, tx: &mut TypedTransaction, abi: Option<&ethers::abi::Abi>, labels: &[(Address, &str)], force: bool) -> Result<(Simulation, String), ToolError> {
+    let simulation = simulation::simulate(provider, tx, abi).await;
+    let summary = simulation.summary(labels);
+    if simulation.revert.is_some() {
+        if !force {
+            return Err(ToolError::SimulationFailed(format!("{}\nThe transaction was NOT sent. Set force=true to broadcast it anyway.", summary)));
+        }
+        tracing::warn!("Broadcasting despite a reverting simulation (forced)");
+        if simulation.gas_estimate.is_none() {
+            tx.set_gas(FORCED_GAS_LIMIT);
+        }
+    }
+    Ok((simulation, summary))
+}
+
+// State-changing contract call: encode, simulate with eth_call and estimateGas, then sign and broadcast
+#[allow(clippy::too_many_arguments)]
+async fn contract_send(
+    ctx: &ToolContext,
+    abi_name: &str,
+    address: &str,
+    function_name: &str,
+    call_args: &[serde_json::Value],
+    from_address: &str,
+    value: &str,
+    fee_overrides: FeeOverrides<'_>,
+    force: bool,
+    private: Option<bool>,
+) -> Result<String, ToolError> {
+    if abi_name.is_empty() || address.is_empty() || function_name.is_empty() || from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("ABI, contract address, function, and from address are required".to_string()));
+    }
+    
+    let abi = match contracts::load_abi(&ctx.config.abi_dir, abi_name) {
+        Ok(abi) => abi,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let function = match contracts::find_function(&abi, function_name) {
+        Ok(function) => function,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let calldata = match contracts::encode_call(function, call_args) {
+        Ok(data) => Bytes::from(data),
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Could not encode arguments: {}", e))),
+    };
+    let value_wei = match units::parse_amount(value) {
+        Ok(wei) => wei,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid value: {}", e))),
+    };
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let contract_address = match resolve_address(&provider, address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid contract address: {}", e))),
+    };
+    let from_address = match resolve_address(&provider, from_address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid from address: {}", e))),
+    };
+    
+    let wallet = match load_signer(ctx, from_address).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    // Enforce the transaction policy before anything is signed
+    let intent = match policy_intent(&provider, contract_address, value_wei, &calldata).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+    };
+    let reservation = match ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        Ok(reservation) => reservation,
+        Err(reason) => return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The transaction was NOT sent.", reason))),
+    };
+    
+    let (mut typed_tx, fee_summary) = match build_transaction(&provider, from_address, contract_address, value_wei, Some(calldata), fee_overrides).await {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not get gas fees: {}", e))),
+    };
+    let signature = function.abi_signature();
+    
+    // Simulate before broadcasting so a reverting call never burns gas
+    let labels = [(from_address, "sender"), (contract_address, "contract")];
+    let (simulation, simulation_summary) = match check_simulation(&provider, &mut typed_tx, Some(&abi), &labels, force).await {
+        Ok(result) => result,
+        Err(message) => return Err(ToolError::SimulationFailed(format!("{} on {:?}\n{}", signature, contract_address, message))),
+    };
+    let gas_estimate = simulation.gas_estimate.unwrap_or_default();
+    let simulated_result = contracts::decode_output(function, &simulation.output)
+        .map(|decoded| decoded.to_string())
+        .unwrap_or_else(|_| format!("0x{}", hex::encode(&simulation.output)));
+    
+    let description = format!("{} on {:?}", signature, contract_address);
+    if ctx.dry_run {
+        return Ok(dry_run_report(&description, &[&format!("Simulated Result: {}", simulated_result), &simulation_summary, &fee_summary], &ctx.config.chain));
+    }
+    
+    let client = SignerMiddleware::new(provider, wallet);
+    let pending_tx = match submit_transaction(ctx, &client, from_address, typed_tx, &description, private).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => return Err(ToolError::Failed(format!("Could not send transaction: {}", e))),
+    };
+    reservation.commit();
+    let tx_hash = pending_tx.tx_hash();
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(60), pending_tx.confirmations(1)).await {
+        Ok(Ok(Some(receipt))) => {
+            tracker::record_receipt(ctx.pool.as_ref(), &receipt).await;
+            let status = if receipt.status == Some(U64::from(1)) { "succeeded" } else { "reverted" };
+            Ok(format!("Transaction {}: {} on {:?}\n\
+                       Simulated Result: {}\n\
+                       {}\n\
+                       {}\n\
+                       Gas Used: {}\n\
+                       Block Number: {}\n\
+                       Transaction Hash: {:?}",
+                       status, signature, contract_address,
+                       simulated_result,
+                       simulation_summary,
+                       fee_summary,
+                       receipt.gas_used.unwrap_or_default(),
+                       receipt.block_number.unwrap_or_default(),
+                       tx_hash))
+        },
+        Ok(Ok(None)) => Ok(format!("Transaction submitted but no receipt was found.\n\
+                                   Transaction Hash: {:?}", tx_hash)),
+        Ok(Err(e)) => Err(ToolError::Failed(format!("Transaction submitted but failed: {}\n\
+                                                    {}\n\
+                                                    Transaction Hash: {:?}", e, description, tx_hash))),
+        Err(_) => Ok(format!("Transaction submitted but confirmation timed out after 60 seconds.\n\
+                             {} on {:?}\n\
+                             {}\n\
+                             Gas Estimate: {}\n\
+                             Transaction Hash: {:?}",
+                             signature, contract_address, fee_summary, gas_estimate, tx_hash)),
+    }
+}
+
+struct SwapRequest<'a> {
+    token_in: &'a str,
+    token_out: &'a str,
+    amount: &'a str,
+    fee: Option<u32>,
+    slippage_bps: Option<u32>,
+}
+
+// Resolve both tokens and quote the swap with the configured (or requested) fee tier and slippage
+async fn swap_quote(ctx: &ToolContext, client: Arc<EthProvider>, request: &SwapRequest<'_>) -> anyhow::Result<dex::SwapQuote> {
+    let config = &ctx.config.dex;
+    if request.token_in.is_empty() || request.token_out.is_empty() {
+        return Err(anyhow::anyhow!("token_in and token_out are required"));
+    }
+    let token_in = dex::resolve_token(client.clone(), config, request.token_in).await?;
+    let token_out = dex::resolve_token(client.clone(), config, request.token_out).await?;
+    let amount_in = dex::parse_token_amount(request.amount, &token_in)?;
+    let fee = request.fee.unwrap_or(config.fee_tier);
+    let slippage_bps = request.slippage_bps.unwrap_or(config.slippage_bps);
+    dex::quote(client, config, token_in, token_out, amount_in, fee, slippage_bps).await
+}
+
+async fn dex_quote(ctx: &ToolContext, request: &SwapRequest<'_>) -> Result<String, ToolError> {
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    match swap_quote(ctx, Arc::new(provider), request).await {
+        Ok(quote) => Ok(format!("{}\nNetwork: {}\nThis is a quote only; nothing was sent.",
+                                dex::describe_quote(&quote), network_label(&ctx.config.chain))),
+        Err(e) => Err(ToolError::RpcError(format!("Could not quote swap: {}", e))),
+    }
+}
+
+// Re-quote, check the policy, approve the router if needed, simulate, then sign and send the swap
+async fn dex_execute(ctx: &ToolContext, request: &SwapRequest<'_>, from_address: &str, force: bool, private: Option<bool>) -> Result<String, ToolError> {
+    if from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("from_address or account is required to execute a swap".to_string()));
+    }
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let from_address = match resolve_address(&provider, from_address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid from address: {}", e))),
+    };
+    let wallet = match load_signer(ctx, from_address).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    let provider = Arc::new(provider);
+    let quote = match swap_quote(ctx, provider.clone(), request).await {
+        Ok(quote) => quote,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not quote swap: {}", e))),
+    };
+    let (router, calldata, value) = match dex::swap_call(provider.clone(), &ctx.config.dex, &quote, from_address) {
+        Ok(call) => call,
+        Err(e) => return Err(ToolError::Failed(e.to_string())),
+    };
+    
+    // Enforce the transaction policy before anything is signed
+    let intent = match policy_intent(&provider, router, value, &calldata).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+    };
+    let reservation = match ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        Ok(reservation) => reservation,
+        Err(reason) => return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The swap was NOT sent.", reason))),
+    };
+    
+    let client = SignerMiddleware::new(provider.clone(), wallet);
+    
+    // ERC-20 input needs an allowance for the router, approved for exactly this amount
+    let approval = match dex::approval_call(provider.clone(), &ctx.config.dex, &quote.token_in, from_address, quote.amount_in).await {
+        Ok(approval) => approval,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not check token allowance: {}", e))),
+    };
+    if ctx.dry_run && approval.is_some() {
+        // The router can't move the tokens before the approval is mined, so the swap would revert now
+        return Ok(dry_run_report(&format!("Approve {} for the Uniswap router, then swap", quote.token_in.symbol),
+                                 &[&dex::describe_quote(&quote), "The swap itself can't be simulated until the approval is mined."], &ctx.config.chain));
+    }
+    let mut approval_note = String::new();
+    if let Some(approve_data) = approval {
+        let (approve_tx, _) = match build_transaction(&provider, from_address, quote.token_in.address, U256::zero(), Some(approve_data), FeeOverrides::default()).await {
+            Ok(result) => result,
+            Err(e) => return Err(ToolError::RpcError(format!("Could not get gas fees: {}", e))),
+        };
+        let description = format!("Approve {} for the Uniswap router", quote.token_in.symbol);
+        let pending_tx = match submit_transaction(ctx, &client, from_address, approve_tx, &description, private).await {
+            Ok(pending_tx) => pending_tx,
+            Err(e) => return Err(ToolError::Failed(format!("Could not approve {} for the router: {}. The swap was NOT sent.", quote.token_in.symbol, e))),
+        };
+        let approve_hash = pending_tx.tx_hash();
+        match tokio::time::timeout(std::time::Duration::from_secs(60), pending_tx.confirmations(1)).await {
+            Ok(Ok(Some(receipt))) if receipt.status == Some(U64::from(1)) => tracker::record_receipt(ctx.pool.as_ref(), &receipt).await,
+            Err(_) => return Err(ToolError::Timeout(format!("Approval of {} did not confirm within 60 seconds, so the swap was NOT sent.\n\
+                                                            Approval Transaction Hash: {:?}", quote.token_in.symbol, approve_hash))),
+            _ => return Err(ToolError::Failed(format!("Approval of {} did not confirm, so the swap was NOT sent.\n\
+                                                      Approval Transaction Hash: {:?}", quote.token_in.symbol, approve_hash))),
+        }
+        approval_note = format!("Approval Transaction Hash: {:?}\n", approve_hash);
+    }
+    
+    let (mut typed_tx, fee_summary) = match build_transaction(&provider, from_address, router, value, Some(calldata), FeeOverrides::default()).await {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not get gas fees: {}", e))),
+    };
+    
+    // Simulate before broadcasting so a reverting swap (e.g. slippage exceeded) never burns gas
+    let labels = [(from_address, "sender"), (router, "router")];
+    let simulation_summary = match check_simulation(&provider, &mut typed_tx, None, &labels, force).await {
+        Ok((_, summary)) => summary,
+        Err(message) => return Err(ToolError::SimulationFailed(format!("{}{}", approval_note, message))),
+    };
+    
+    let description = format!("Swap {} for {}", dex::format_token_amount(quote.amount_in, &quote.token_in), quote.token_out.symbol);
+    if ctx.dry_run {
+        return Ok(dry_run_report(&description, &[&dex::describe_quote(&quote), &simulation_summary, &fee_summary], &ctx.config.chain));
+    }
+    let pending_tx = match submit_transaction(ctx, &client, from_address, typed_tx, &description, private).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => return Err(ToolError::Failed(format!("Could not send swap: {}", e))),
+    };
+    reservation.commit();
+    let tx_hash = pending_tx.tx_hash();
+    let summary = dex::describe_quote(&quote);
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(60), pending_tx.confirmations(1)).await {
+        Ok(Ok(Some(receipt))) => {
+            tracker::record_receipt(ctx.pool.as_ref(), &receipt).await;
+            let succeeded = receipt.status == Some(U64::from(1));
+            if succeeded {
+                pnl::record_swap(&ctx.config, ctx.pool.as_ref(), ctx.user.as_deref(), from_address, &quote, &receipt).await;
+            }
+            let status = if succeeded { "succeeded" } else { "reverted" };
+            Ok(format!("Swap {}.\n\
+                       {}\n\
+                       {}\n\
+                       {}\n\
+                       Gas Used: {}\n\
+                       Block Number: {}\n\
+                       {}Transaction Hash: {:?}",
+                       status, summary, simulation_summary, fee_summary,
+                       receipt.gas_used.unwrap_or_default(),
+                       receipt.block_number.unwrap_or_default(),
+                       approval_note, tx_hash))
+        },
+        Ok(Ok(None)) => Ok(format!("Swap submitted but no receipt was found.\n\
+                                   Transaction Hash: {:?}", tx_hash)),
+        Ok(Err(e)) => Err(ToolError::Failed(format!("Swap submitted but failed: {}\n\
+                                                    {}\n\
+                                                    Transaction Hash: {:?}", e, summary, tx_hash))),
+        Err(_) => Ok(format!("Swap submitted but confirmation timed out after 60 seconds.\n\
+                             {}\n\
+                             Transaction Hash: {:?}", summary, tx_hash)),
+    }
+}
+
+// Describe a pending transaction for the policy engine, using the connected chain's ID.
+// The calldata gives the recipient of a token transfer, which the recipient lists also apply to.
+async fn policy_intent(provider: &EthProvider, to: Address, value: U256, data: &[u8]) -> anyhow::Result<TxIntent> {
+    let chain_id = provider.get_chainid().await?;
+    Ok(TxIntent::new(chain_id.as_u64(), to, value, data))
+}
+
+// Load the signer for an address from the configured backend, bound to the configured chain id.
+// The local backend uses the keystore accounts or the HD wallet.
+async fn load_signer(ctx: &ToolContext, from_address: Address) -> anyhow::Result<AgentSigner> {
+    let chain_id = ctx.config.chain.chain_id;
+    // The HD wallet, Ledger, and KMS accounts belong to the deployment rather than to one user,
+    // so only admins (and the modes without users) sign with them
+    let shared = ctx.role.is_none_or(|role| role == Role::Admin);
+    let backend = ctx.config.signer.backend.as_str();
+    if !shared && backend != "local" {
+        return Err(anyhow::anyhow!("Only admins can sign with the {} signer backend", backend));
+    }
+    match backend {
+        "local" => {},
+        "ledger" => {
+            let signer = wallet::ledger_signer(from_address, chain_id).await
+                .ok_or_else(|| anyhow::anyhow!("{:?} is not a connected Ledger account. Connect it with 'connect_ledger' first.", from_address))??;
+            tracing::info!(address = ?from_address, "Waiting for confirmation on the Ledger");
+            return Ok(signer);
+        },
+        "kms" => {
+            let signer = kms::signer(&ctx.config.kms, chain_id).await?;
+            if signer.address() != from_address {
+                return Err(anyhow::anyhow!("Only the KMS account {:?} can sign with the kms signer backend", signer.address()));
+            }
+            return Ok(signer);
+        },
+        other => return Err(anyhow::anyhow!("Unknown signer backend '{}'; use 'local', 'ledger', or 'kms'", other)),
+    }
+    
+    keystore::signer_for_address(&ctx.keystore_dir(), from_address)
+        .or_else(|| if shared { wallet::signer_for_address(from_address) } else { None })
+        .map(|signer| AgentSigner::Local(signer.with_chain_id(chain_id)))
+        .ok_or_else(|| anyhow::anyhow!("No key found for address {:?}. Send from a keystore account (import its key with `wallet import <name>`) or an HD wallet account.", from_address))
+}
+
+// A `private_key` argument, from callers written before keys moved to the keystore. It is
+// refused unless `[signer] allow_private_key_args` is set, in which case the key is kept in
+// memory and the call sends from its account.
+fn legacy_private_key(ctx: &ToolContext, name: &str, args: &serde_json::Value) -> Result<Option<serde_json::Value>, ToolError> {
+    let Some(private_key) = args.get("private_key").and_then(|v| v.as_str()) else { return Ok(None) };
+    if !ctx.config.signer.allow_private_key_args || ctx.config.signer.backend != "local" {
+        return Err(ToolError::InvalidArgs("Private keys are not accepted as tool arguments. Import the key with \
+                                           `wallet import <name>` and send from it with account: \"<name>\"".to_string()));
+    }
+    let address = keystore::add_session_key(&ctx.keystore_dir(), private_key).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    tracing::warn!(tool = name, address = ?address, "Accepted a deprecated private_key argument; import the key into the keystore instead");
+    let mut args = args.clone();
+    if let Some(args) = args.as_object_mut() {
+        args.remove("private_key");
+        args.entry("account").or_insert_with(|| format!("{:?}", address).into());
+    }
+    Ok(Some(args))
+}
+
+// Parse and execute a natural language ETH send command
+async fn parse_and_execute_eth_send_command(ctx: &ToolContext, command: &str) -> Result<String, ToolError> {
+    tracing::debug!("Parsing natural language ETH send command");
+    
+    // Extract amount (look for pattern like "0.1 ETH", "0.1ETH", or "500 gwei")
+    let amount_pattern = regex::Regex::new(r"(?i)(\d+(?:\.\d+)? ?(?:ETH|gwei|wei))\b").unwrap();
+    let amount = match amount_pattern.captures(command) {
+        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
+        None => return Err(ToolError::InvalidArgs("Could not parse ETH amount from command".to_string())),
+    };
+    
+    // Extract from_address (look for pattern like "from 0x...", "from name.eth", or "from trading-1")
+    let from_pattern = regex::Regex::new(r"from (0x[a-fA-F0-9]{40}|[\w-]+(?:\.[\w-]+)*)").unwrap();
+    let from_address = match from_pattern.captures(command) {
+        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
+        None => return Err(ToolError::InvalidArgs("Could not parse from address from command".to_string())),
+    };
+    let from_account = keystore::unlock(&ctx.keystore_dir(), from_address)
+        .map(|signer| format!("{:?}", signer.address()));
+    let from_address = from_account.as_deref().unwrap_or(from_address);
+    
+    // Extract to_address (look for pattern like "to 0x...", "to name.eth", or "to mom" from the address book)
+    let to_pattern = regex::Regex::new(r"\bto (0x[a-fA-F0-9]{40}|[\w-]+(?:\.[\w-]+)*)").unwrap();
+    let to_address = match to_pattern.captures(command) {
+        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
+        None => return Err(ToolError::InvalidArgs("Could not parse to address from command".to_string())),
+    };
+    
+    tracing::debug!(from = from_address, to = to_address, amount, "Parsed ETH send command");
+    
+    // Execute the transaction with the parsed parameters
+    eth_send_eth(ctx, from_address, to_address, amount, FeeOverrides::default(), false, None).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn eth_send_eth(ctx: &ToolContext, from_address: &str, to_address: &str, amount: &str, fee_overrides: FeeOverrides<'_>, force: bool, private: Option<bool>) -> Result<String, ToolError> {
+    if from_address.is_empty() || to_address.is_empty() || amount.is_empty() {
+        return Err(ToolError::InvalidArgs("From address, to address, and amount are required".to_string()));
+    }
+    
+    // Get provider
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    // Parse the addresses (raw hex, ENS names, or the names of the user's accounts)
+    let from_address = match resolve_account(ctx, &provider, from_address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid from address: {}", e))),
+    };
+    
+    // Raw hex, an ENS name, or an address book label
+    let recipient = match resolve_recipient(ctx, &provider, to_address).await {
+        Ok(recipient) => recipient,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid to address: {}", e))),
+    };
+    let to_address = recipient.address;
+    let from_display = display_address(&provider, from_address).await;
+    let to_display = match recipient.display() {
+        Some(display) => display,
+        None => display_address(&provider, to_address).await,
+    };
+    let warning = recipient.warning().map(|warning| format!("\n{}", warning)).unwrap_or_default();
+    
+    // Parse amount into wei (ETH by default, or with an explicit wei/gwei/ETH unit)
+    let wei_amount = match units::parse_amount(amount) {
+        Ok(wei) => wei,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let amount_eth = units::format_eth(wei_amount);
+    
+    // Get the signing wallet from the keystore or the HD wallet
+    let wallet = match load_signer(ctx, from_address).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    // Enforce the transaction policy before anything is signed
+    let intent = match policy_intent(&provider, to_address, wei_amount, &[]).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+    };
+    let reservation = match ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        Ok(reservation) => reservation,
+        Err(reason) => return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The transaction was NOT sent.", reason))),
+    };
+    
+    // Create transaction request, preferring EIP-1559 fees where the chain supports them
+    let (mut typed_tx, fee_summary) = match build_transaction(&provider, from_address, to_address, wei_amount, None, fee_overrides).await {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not get gas fees: {}", e))),
+    };
+    
+    // Simulate the exact transaction (e.g. a recipient contract that rejects ETH) before broadcasting
+    let labels = [(from_address, "sender"), (to_address, "recipient")];
+    let (simulation, simulation_summary) = match check_simulation(&provider, &mut typed_tx, None, &labels, force).await {
+        Ok(result) => result,
+        Err(message) => return Err(ToolError::SimulationFailed(format!("Sending {} ETH from {} to {}\n{}", amount_eth, from_display, to_display, message))),
+    };
+    let gas_estimate = simulation.gas_estimate.unwrap_or_default();
+    
+    if ctx.dry_run {
+        let action = format!("Send {} ETH from {} to {}", amount_eth, from_display, to_display);
+        return Ok(format!("{}{}", dry_run_report(&action, &[&simulation_summary, &fee_summary], &ctx.config.chain), warning));
+    }
+    
+    // Create a client with the wallet
+    let client = SignerMiddleware::new(provider, wallet);
+    let client = Arc::new(client);
+    
+    // Actually send the transaction
+    let description = format!("Send {} ETH to {}", amount_eth, to_display);
+    let sent = match submit_transaction(ctx, client.as_ref(), from_address, typed_tx, &description, private).await {
+        Ok(pending_tx) => {
+            reservation.commit();
+            
+            // Get the transaction hash immediately
+            let tx_hash = pending_tx.tx_hash();
+            
+            // Try to get the transaction receipt with a timeout
+            let receipt_future = pending_tx.confirmations(1);
+            match tokio::time::timeout(std::time::Duration::from_secs(60), receipt_future).await {
+                Ok(receipt_result) => {
+                    match receipt_result {
+                        Ok(receipt) => {
+                            // Transaction was mined successfully
+                            // The receipt is an Option<TransactionReceipt>, so we need to unwrap it first
+                            if let Some(receipt_data) = receipt {
+                                tracker::record_receipt(ctx.pool.as_ref(), &receipt_data).await;
+                                if receipt_data.status == Some(U64::from(1)) {
+                                    pnl::record_send(&ctx.config, ctx.pool.as_ref(), ctx.user.as_deref(), from_address, wei_amount, &receipt_data).await;
+                                }
+                                Ok(format!("Transaction successfully sent {} ETH from {} to {}\n\
+                                          {}\n\
+                                          {}\n\
+                                          Gas Used: {}\n\
+                                          Block Number: {}\n\
+                                          Network: {}\n\
+                                          Transaction Hash: {:?}", 
+                                          amount_eth, from_display, to_display, 
+                                          simulation_summary,
+                                          fee_summary,
+                                          receipt_data.gas_used.unwrap_or_default(),
+                                          receipt_data.block_number.unwrap_or_default(),
+                                          network_label(&ctx.config.chain),
+                                          tx_hash))
+                            } else {
+                                // Transaction was submitted but no receipt was found
+                                Ok(format!("Transaction submitted but no receipt was found.\n\
+                                          {} ETH from {} to {}\n\
+                                          Network: {}\n\
+                                          Transaction Hash: {:?}", 
+                                          amount_eth, from_display, to_display,
+                                          network_label(&ctx.config.chain),
+                                          tx_hash))
+                            }
+                        },
+                        Err(e) => {
+                            // Transaction was submitted but failed during mining
+                            Err(ToolError::Failed(format!("Transaction submitted but failed: {}\n\
+                                                          {} ETH from {} to {}\n\
+                                                          Network: {}\n\
+                                                          Transaction Hash: {:?}",
+                                                          e, amount_eth, from_display, to_display,
+                                                          network_label(&ctx.config.chain), tx_hash)))
+                        }
+                    }
                 },
-                _ => Ok(format!("Unknown Ethereum wallet operation: {}", operation)),
+                Err(_) => {
+                    // Timeout waiting for transaction to be mined
+                    // Return the transaction hash anyway since it was submitted
+                    Ok(format!("Transaction submitted but confirmation timed out after 60 seconds.\n\
+                              {} ETH from {} to {}\n\
+                              {}\n\
+                              Gas Estimate: {}\n\
+                              Network: {}\n\
+                              Transaction Hash: {:?}", 
+                              amount_eth, from_display, to_display, 
+                              fee_summary,
+                              gas_estimate,
+                              network_label(&ctx.config.chain),
+                              tx_hash))
+                }
+            }
+        },
+        Err(e) => {
+            // Failed to send transaction
+            Err(ToolError::Failed(format!("Could not send transaction: {}", e)))
+        }
+    };
+    sent.map(|output| format!("{}{}", output, warning))
+}
+
+// Nodes only accept a replacement whose fees are at least 10% above the original's (Geth's
+// default price bump). Bumping by 15% leaves a margin for nodes and relays that are set to
+// require more.
+const REPLACEMENT_FEE_BUMP_PERCENT: u64 = 15;
+
+// Fee for a replacement: the override if given (refused when below the required bump),
+// otherwise the higher of the bumped original fee and the current estimate
+fn replacement_fee(original: U256, estimate: U256, override_gwei: Option<&str>, what: &str) -> anyhow::Result<U256> {
+    let minimum = original * U256::from(100 + REPLACEMENT_FEE_BUMP_PERCENT) / U256::from(100);
+    match override_gwei {
+        Some(gwei) => {
+            let fee = parse_gwei(gwei)?;
+            if fee < minimum {
+                return Err(anyhow::anyhow!("{} of {} gwei is too low to replace the original; use at least {} gwei",
+                                           what, units::format_gwei(fee), units::format_gwei(minimum)));
+            }
+            Ok(fee)
+        },
+        None => Ok(minimum.max(estimate)),
+    }
+}
+
+// Build the replacement for a pending transaction with the same nonce and type: the original
+// call itself, or a zero-value send to the sender when cancelling
+async fn build_replacement(provider: &EthProvider, original: &Transaction, cancel: bool, overrides: FeeOverrides<'_>) -> anyhow::Result<(TypedTransaction, String)> {
+    let (to, value, data, gas) = if cancel {
+        (Some(original.from), U256::zero(), None, U256::from(21_000))
+    } else {
+        (original.to, original.value, Some(original.input.clone()), original.gas)
+    };
+    
+    if original.transaction_type == Some(U64::from(2)) {
+        let (estimated_max_fee, estimated_priority_fee) = provider.estimate_eip1559_fees(None).await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let max_priority_fee = replacement_fee(original.max_priority_fee_per_gas.unwrap_or_default(), estimated_priority_fee,
+                                               overrides.max_priority_fee_per_gas, "Priority fee")?;
+        let max_fee = replacement_fee(original.max_fee_per_gas.unwrap_or_default(), estimated_max_fee.max(max_priority_fee),
+                                      overrides.max_fee_per_gas, "Max fee")?;
+        if max_fee < max_priority_fee {
+            return Err(anyhow::anyhow!("max fee per gas must be at least the priority fee"));
+        }
+        
+        let mut tx = Eip1559TransactionRequest::new()
+            .from(original.from)
+            .value(value)
+            .nonce(original.nonce)
+            .gas(gas)
+            .max_fee_per_gas(max_fee)
+            .max_priority_fee_per_gas(max_priority_fee);
+        if let Some(to) = to {
+            tx = tx.to(to);
+        }
+        if let Some(data) = data {
+            tx = tx.data(data);
+        }
+        let summary = format!("Max Fee: {} gwei (was {} gwei)\nPriority Fee: {} gwei (was {} gwei)",
+                              units::format_gwei(max_fee), units::format_gwei(original.max_fee_per_gas.unwrap_or_default()),
+                              units::format_gwei(max_priority_fee), units::format_gwei(original.max_priority_fee_per_gas.unwrap_or_default()));
+        Ok((TypedTransaction::Eip1559(tx), summary))
+    } else {
+        let estimated_gas_price = provider.get_gas_price().await.map_err(|e| anyhow::anyhow!("{}", e))?;
+        let gas_price = replacement_fee(original.gas_price.unwrap_or_default(), estimated_gas_price,
+                                        overrides.max_fee_per_gas, "Gas price")?;
+        
+        let mut tx = TransactionRequest::new()
+            .from(original.from)
+            .value(value)
+            .nonce(original.nonce)
+            .gas(gas)
+            .gas_price(gas_price);
+        if let Some(to) = to {
+            tx = tx.to(to);
+        }
+        if let Some(data) = data {
+            tx = tx.data(data);
+        }
+        let summary = format!("Gas Price: {} gwei (was {} gwei, legacy)",
+                              units::format_gwei(gas_price), units::format_gwei(original.gas_price.unwrap_or_default()));
+        Ok((TypedTransaction::Legacy(tx), summary))
+    }
+}
+
+// Unstick a pending transaction by re-submitting its nonce with higher fees, either as the same
+// transaction (speed up) or as a zero-value self-send (cancel). Only one of the two can be mined.
+async fn eth_replace_transaction(ctx: &ToolContext, tx_hash: &str, cancel: bool, fee_overrides: FeeOverrides<'_>, force: bool) -> Result<String, ToolError> {
+    let action = if cancel { "Cancel" } else { "Speed up" };
+    let original_hash = match H256::from_str(tx_hash.trim()) {
+        Ok(hash) => hash,
+        Err(_) => return Err(ToolError::InvalidArgs("The hash of the pending transaction is required".to_string())),
+    };
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    let original = match provider.get_transaction(original_hash).await {
+        Ok(Some(tx)) => tx,
+        Ok(None) => return Err(ToolError::InvalidArgs(format!("Transaction {:?} is not known to the node; it may already have been dropped", original_hash))),
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch transaction {:?}: {}", original_hash, e))),
+    };
+    if let Some(block_number) = original.block_number {
+        return Ok(format!("Transaction {:?} was already mined in block {}, so it can no longer be replaced", original_hash, block_number));
+    }
+    
+    let wallet = match load_signer(ctx, original.from).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    // A speed-up moves the same value again, so it is held to the policy too. It isn't recorded
+    // towards the daily limit a second time, since the original already was.
+    if !cancel {
+        let intent = match policy_intent(&provider, original.to.unwrap_or_default(), original.value, &original.input).await {
+            Ok(intent) => intent,
+            Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+        };
+        if let Err(reason) = ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+            return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The replacement was NOT sent.", reason)));
+        }
+    }
+    
+    let (mut typed_tx, fee_summary) = match build_replacement(&provider, &original, cancel, fee_overrides).await {
+        Ok(result) => result,
+        Err(e) => return Err(ToolError::Failed(e.to_string())),
+    };
+    
+    let labels = [(original.from, "sender")];
+    let simulation_summary = match check_simulation(&provider, &mut typed_tx, None, &labels, force).await {
+        Ok((_, summary)) => summary,
+        Err(message) => return Err(ToolError::SimulationFailed(format!("{} transaction {:?}\n{}", action, original_hash, message))),
+    };
+    
+    let original_description = match &ctx.pool {
+        Some(pool) => find_transaction(pool, &format!("{:?}", original_hash)).await.ok().flatten().map(|record| record.description),
+        None => None,
+    };
+    let description = format!("{}: {}", action, original_description.unwrap_or_else(|| format!("{:?}", original_hash)));
+    if ctx.dry_run {
+        return Ok(dry_run_report(&format!("{} (nonce {})", description, original.nonce), &[&simulation_summary, &fee_summary], &ctx.config.chain));
+    }
+    
+    // The nonce is already fixed, so this is sent directly rather than through the nonce manager
+    let client = SignerMiddleware::new(provider, wallet);
+    let pending_tx = match client.send_transaction(typed_tx.clone(), None).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => return Err(ToolError::Failed(format!("Could not send replacement: {}", e))),
+    };
+    let tx_hash = pending_tx.tx_hash();
+    tracker::record_submitted(ctx.pool.as_ref(), ctx.session_id, ctx.config.chain.chain_id, tx_hash, &typed_tx, original.from, &description).await;
+    
+    let summary = format!("{} transaction {:?} (nonce {})\n{}\n{}", action, original_hash, original.nonce, simulation_summary, fee_summary);
+    match tokio::time::timeout(std::time::Duration::from_secs(60), pending_tx.confirmations(1)).await {
+        Ok(Ok(Some(receipt))) => {
+            tracker::record_receipt(ctx.pool.as_ref(), &receipt).await;
+            let status = if receipt.status == Some(U64::from(1)) { "succeeded" } else { "reverted" };
+            Ok(format!("Replacement {} in block {}; the original transaction will not confirm.\n\
+                       {}\n\
+                       Network: {}\n\
+                       Replacement Transaction Hash: {:?}",
+                       status, receipt.block_number.unwrap_or_default(), summary,
+                       network_label(&ctx.config.chain), tx_hash))
+        },
+        Ok(Ok(None)) => Ok(format!("Replacement submitted but no receipt was found; the original may have been mined first.\n\
+                                   {}\n\
+                                   Replacement Transaction Hash: {:?}", summary, tx_hash)),
+        Ok(Err(e)) => Err(ToolError::Failed(format!("Replacement submitted but failed: {}\n\
+                                                    {}\n\
+                                                    Replacement Transaction Hash: {:?}", e, summary, tx_hash))),
+        Err(_) => Ok(format!("Replacement submitted but confirmation timed out after 60 seconds. Whichever of the two is mined first takes the nonce.\n\
+                             {}\n\
+                             Network: {}\n\
+                             Replacement Transaction Hash: {:?}", summary, network_label(&ctx.config.chain), tx_hash)),
+    }
+}
+
+fn describe_sign_request(request: &SignRequest) -> String {
+    match request {
+        SignRequest::Message(bytes) => match std::str::from_utf8(bytes) {
+            Ok(text) => format!("Message: {}", text),
+            Err(_) => format!("Message: 0x{}", hex::encode(bytes)),
+        },
+        SignRequest::TypedData(typed_data) => signing::describe_typed_data(typed_data),
+    }
+}
+
+// Sign a personal message or EIP-712 typed data with a keystore or HD account, or the
+// configured signer. Typed data can be a permit or an order giving others the signer's tokens,
+// so its verifying contract and spenders are held to the policy and the user must confirm it.
+async fn sign_payload(ctx: &ToolContext, request: &SignRequest, address: &str, confirm: bool) -> Result<String, ToolError> {
+    if address.is_empty() {
+        return Err(ToolError::InvalidArgs("The signing address or account is required".to_string()));
+    }
+    
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let signer_address = match resolve_address(&provider, address).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid address: {}", e))),
+    };
+    let wallet = match load_signer(ctx, signer_address).await {
+        Ok(wallet) => wallet,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    
+    if let SignRequest::TypedData(typed_data) = request {
+        let chain_id = typed_data.domain.chain_id.map(|id| id.low_u64()).unwrap_or(ctx.config.chain.chain_id);
+        for party in signing::typed_data_parties(typed_data) {
+            if let Err(reason) = ctx.policy.check(&TxIntent { chain_id, to: party, value: U256::zero(), recipient: None }, ctx.pool.as_ref()).await {
+                return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. Nothing was signed.", reason)));
+            }
+        }
+        if !confirm {
+            return Err(ToolError::InvalidArgs(format!("Typed data signatures need confirm=true. Show the user this and sign it only once they confirm:\n{}",
+                                                      describe_sign_request(request))));
+        }
+    }
+    
+    let signature = match signing::sign(&wallet, request).await {
+        Ok(signature) => signature,
+        Err(e) => return Err(ToolError::Failed(format!("Could not sign: {}", e))),
+    };
+    let kind = match request {
+        SignRequest::Message(_) => "personal message (EIP-191)",
+        SignRequest::TypedData(_) => "typed data (EIP-712)",
+    };
+    let mut lines = vec![
+        format!("Signed {} with {:?}", kind, signer_address),
+        describe_sign_request(request),
+    ];
+    if let SignRequest::TypedData(typed_data) = request
+        && let Some(chain_id) = typed_data.domain.chain_id
+        && chain_id != U256::from(ctx.config.chain.chain_id)
+    {
+        lines.push(format!("Note: the domain is for chain {}, not the configured chain {}", chain_id, ctx.config.chain.chain_id));
+    }
+    lines.push(format!("Signature: 0x{}", signature));
+    Ok(lines.join("\n"))
+}
+
+// Recover the signer of a message or typed data, comparing it with the expected address if given
+async fn verify_signature(ctx: &ToolContext, request: &SignRequest, signature: &str, expected: &str) -> Result<String, ToolError> {
+    if signature.is_empty() {
+        return Err(ToolError::InvalidArgs("The signature to verify is required".to_string()));
+    }
+    let signature = match signing::parse_signature(signature) {
+        Ok(signature) => signature,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let signer = match signing::recover(request, &signature) {
+        Ok(signer) => signer,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Could not recover signer: {}", e))),
+    };
+    
+    let mut lines = vec![describe_sign_request(request), format!("Recovered Signer: {:?}", signer)];
+    if !expected.is_empty() {
+        let provider = match get_provider(&ctx.config.chain).await {
+            Ok(provider) => provider,
+            Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+        };
+        let expected = match resolve_address(&provider, expected).await {
+            Ok(addr) => addr,
+            Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid expected address: {}", e))),
+        };
+        lines.push(if expected == signer {
+            format!("Valid: the signature was made by {:?}", expected)
+        } else {
+            format!("Invalid: the signature was not made by {:?}", expected)
+        });
+    }
+    Ok(lines.join("\n"))
+}
+
+// Calldata given raw in `data`, or encoded from the `abi`, `function`, and `args` arguments
+fn call_data_from_args(ctx: &ToolContext, args: &serde_json::Value) -> Result<Bytes, ToolError> {
+    match (args.get("abi").and_then(|v| v.as_str()), args.get("function").and_then(|v| v.as_str())) {
+        (Some(abi_name), Some(function_name)) => {
+            let abi = contracts::load_abi(&ctx.config.abi_dir, abi_name)
+                .map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+            let function = contracts::find_function(&abi, function_name)
+                .map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+            let call_args = args.get("args").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+            contracts::encode_call(function, &call_args)
+                .map(Bytes::from)
+                .map_err(|e| ToolError::InvalidArgs(format!("Could not encode arguments: {}", e)))
+        },
+        _ => match args.get("data").and_then(|v| v.as_str()).filter(|data| !data.is_empty()) {
+            Some(data) => Bytes::from_str(data).map_err(|_| ToolError::InvalidArgs("Invalid calldata; expected 0x-prefixed hex".to_string())),
+            None => Ok(Bytes::default()),
+        },
+    }
+}
+
+// Owners given by address, ENS name, or HD wallet account index or label
+async fn portfolio_owner(provider: &EthProvider, input: &str) -> anyhow::Result<Address> {
+    match resolve_address(provider, input).await {
+        Ok(address) => Ok(address),
+        Err(e) if input.starts_with("0x") || input.contains('.') => Err(e),
+        Err(_) => Ok(wallet::find_account(input)?.address),
+    }
+}
+
+// A chain read by the portfolio tool
+struct PortfolioChain<'a> {
+    name: &'a str,
+    rpc_url: Option<&'a str>,
+    multicall: &'a str,
+    native_symbol: &'a str,
+    tokens: Vec<Address>,
+}
+
+// Token addresses given by address or by a symbol in `symbols`
+fn portfolio_tokens(inputs: &[&str], symbols: &BTreeMap<String, String>) -> Result<Vec<Address>, ToolError> {
+    let mut addresses = Vec::new();
+    for token in inputs {
+        let address = symbols.iter()
+            .find(|(symbol, _)| symbol.eq_ignore_ascii_case(token))
+            .map_or(*token, |(_, address)| address.as_str());
+        match Address::from_str(address.trim()) {
+            Ok(address) if !addresses.contains(&address) => addresses.push(address),
+            Ok(_) => {},
+            Err(_) => return Err(ToolError::InvalidArgs(format!("Unknown token {}; use a contract address or a configured symbol", token))),
+        }
+    }
+    Ok(addresses)
+}
+
+// Amount of a formatted balance as a float, for USD values
+fn token_amount(balance: U256, decimals: u8) -> f64 {
+    units::format_token(balance, decimals as u32).parse().unwrap_or(0.0)
+}
+
+async fn portfolio(ctx: &ToolContext, owners: &[String], tokens: &[String], include_zero: bool) -> Result<String, ToolError> {
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    
+    let mut owner_addresses = Vec::new();
+    if owners.is_empty() {
+        match wallet::list_accounts() {
+            Ok(accounts) if !accounts.is_empty() => owner_addresses.extend(accounts.iter().map(|account| account.address)),
+            _ => return Err(ToolError::InvalidArgs("No addresses given and no HD wallet accounts derived".to_string())),
+        }
+    }
+    for owner in owners {
+        match portfolio_owner(&provider, owner).await {
+            Ok(address) => owner_addresses.push(address),
+            Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+        }
+    }
+    
+    // The configured chain with [dex.tokens] (or the tokens asked for), then the [portfolio] chains
+    let token_inputs: Vec<&str> = if tokens.is_empty() {
+        ctx.config.dex.tokens.values().map(String::as_str).collect()
+    } else {
+        tokens.iter().map(String::as_str).collect()
+    };
+    let mut chains = vec![PortfolioChain {
+        name: &ctx.config.chain.name,
+        rpc_url: ctx.config.chain.rpc_url.as_deref(),
+        multicall: &ctx.config.chain.multicall,
+        native_symbol: "ETH",
+        tokens: portfolio_tokens(&token_inputs, &ctx.config.dex.tokens)?,
+    }];
+    for chain in &ctx.config.portfolio.chains {
+        let symbols: Vec<&str> = chain.tokens.keys().map(String::as_str).collect();
+        chains.push(PortfolioChain {
+            name: &chain.name,
+            rpc_url: Some(&chain.rpc_url),
+            multicall: &chain.multicall,
+            native_symbol: &chain.native_symbol,
+            tokens: match portfolio_tokens(&symbols, &chain.tokens) {
+                Ok(addresses) => addresses,
+                Err(message) => return Err(ToolError::InvalidArgs(format!("{} (in [portfolio] chain {})", message, chain.name))),
+            },
+        });
+    }
+    
+    // Each chain is one Multicall3 batch; a chain that can't be read is reported and skipped
+    let mut results = Vec::new();
+    let mut failures = Vec::new();
+    let mut requests = 0;
+    for chain in &chains {
+        let read = async {
+            let rpc_url = chain.rpc_url.ok_or_else(|| anyhow::anyhow!("no RPC URL configured"))?;
+            let multicall = Address::from_str(chain.multicall)
+                .map_err(|_| anyhow::anyhow!("invalid Multicall3 address {}", chain.multicall))?;
+            let client = Arc::new(provider::connect(rpc_url).await?);
+            multicall::portfolio(client, multicall, chain.native_symbol, &owner_addresses, &chain.tokens).await
+        };
+        match read.await {
+            Ok(portfolio) => {
+                requests += portfolio.requests;
+                results.push((chain.name, portfolio));
+            },
+            Err(e) => failures.push(format!("{}: could not read balances: {}", chain.name, e)),
+        }
+    }
+    
+    let symbols: Vec<String> = results.iter()
+        .flat_map(|(_, portfolio)| portfolio.tokens.iter().map(|token| token.symbol.clone()))
+        // Tokens whose symbol couldn't be read are shown by address and have no price
+        .filter(|symbol| !symbol.starts_with("0x"))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let prices = match prices::usd_prices(&ctx.config.prices, &symbols).await {
+        Ok(prices) => prices,
+        Err(e) => {
+            failures.push(format!("Prices unavailable: {}", e));
+            HashMap::new()
+        },
+    };
+    
+    let mut sections = Vec::new();
+    let mut grand_total = 0.0;
+    for (index, owner) in owner_addresses.iter().enumerate() {
+        let mut rows = vec!["Chain | Token | Balance | Price | Value".to_string()];
+        let mut total = 0.0;
+        let mut unpriced = 0;
+        for (chain, portfolio) in &results {
+            let balances = &portfolio.balances[index].1;
+            for (token, balance) in portfolio.tokens.iter().zip(balances) {
+                let Some(balance) = balance else {
+                    rows.push(format!("{} | {} | could not be read | - | -", chain, token.symbol));
+                    continue;
+                };
+                if balance.is_zero() && !include_zero {
+                    continue;
+                }
+                let (price, value) = match prices.get(&token.symbol) {
+                    Some(price) => {
+                        let value = token_amount(*balance, token.decimals) * price;
+                        total += value;
+                        (format!("${:.2}", price), format!("${:.2}", value))
+                    },
+                    None => {
+                        unpriced += 1;
+                        ("-".to_string(), "-".to_string())
+                    },
+                };
+                rows.push(format!("{} | {} | {} | {} | {}", chain, token.symbol, units::format_token(*balance, token.decimals as u32), price, value));
             }
+        }
+        grand_total += total;
+        let rows = if rows.len() == 1 { "No balances".to_string() } else { rows.join("\n") };
+        let unpriced = if unpriced > 0 { format!(" ({} without a price not counted)", unpriced) } else { String::new() };
+        sections.push(format!("{:?}:\n{}\nTotal: ${:.2}{}", owner, rows, total, unpriced));
+    }
+    
+    let mut output = format!("Portfolio:\n{}", sections.join("\n\n"));
+    if owner_addresses.len() > 1 {
+        output.push_str(&format!("\n\nTotal across {} addresses: ${:.2}", owner_addresses.len(), grand_total));
+    }
+    if !failures.is_empty() {
+        output.push_str(&format!("\n\n{}", failures.join("\n")));
+    }
+    output.push_str(&format!("\n(Read {} chain{} in {} request{}; prices from CoinGecko by symbol)",
+                             results.len(), if results.len() == 1 { "" } else { "s" },
+                             requests, if requests == 1 { "" } else { "s" }));
+    Ok(output)
+}
+
+// Token ids are given in decimal or as 0x hex
+fn parse_token_id(token_id: &str) -> Result<U256, ToolError> {
+    let token_id = token_id.trim();
+    let parsed = match token_id.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(token_id).ok(),
+    };
+    parsed.ok_or_else(|| ToolError::InvalidArgs(format!("Invalid token ID '{}'", token_id)))
+}
+
+async fn nft_owned(ctx: &ToolContext, owner: &str, contract: Option<&str>, token_id: Option<&str>) -> Result<String, ToolError> {
+    if owner.is_empty() {
+        return Err(ToolError::InvalidArgs("The owner address is required".to_string()));
+    }
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let owner = match resolve_address(&provider, owner).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid owner address: {}", e))),
+    };
+    let contract = match contract {
+        Some(contract) => match resolve_address(&provider, contract).await {
+            Ok(addr) => Some(addr),
+            Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid contract address: {}", e))),
         },
-        _ => Ok(format!("Unknown tool: {}", name)),
+        None => None,
+    };
+    
+    // A single token is checked directly, which also covers ERC-1155 balances
+    if let (Some(contract), Some(token_id)) = (contract, token_id) {
+        let token_id = parse_token_id(token_id)?;
+        let client = Arc::new(provider);
+        return match nft::standard(client.clone(), contract).await {
+            Ok(nft::Standard::Erc721) => match nft::owner_of(client, contract, token_id).await {
+                Ok(token_owner) if token_owner == owner => Ok(format!("{:?} owns token #{} on {:?}", owner, token_id, contract)),
+                Ok(token_owner) => Ok(format!("{:?} does not own token #{} on {:?}; it is owned by {:?}", owner, token_id, contract, token_owner)),
+                Err(e) => Err(ToolError::InvalidArgs(format!("Token #{} not found: {}", token_id, e))),
+            },
+            Ok(nft::Standard::Erc1155) => match nft::balance_of(client, contract, owner, token_id).await {
+                Ok(balance) => Ok(format!("{:?} holds {} of token #{} on {:?}", owner, balance, token_id, contract)),
+                Err(e) => Err(ToolError::RpcError(format!("Could not read balance: {}", e))),
+            },
+            Err(e) => Err(ToolError::InvalidArgs(e.to_string())),
+        };
+    }
+    
+    let tokens = match nft::owned(Arc::new(provider), &ctx.config.nft, owner, contract).await {
+        Ok(tokens) => tokens,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not list NFTs: {}", e))),
+    };
+    if tokens.is_empty() {
+        return Ok(format!("{:?} owns no NFTs{}", owner, contract.map(|c| format!(" on {:?}", c)).unwrap_or_default()));
+    }
+    let lines = tokens.iter()
+        .map(|token| {
+            let mut line = format!("{} #{} ({:?}{})",
+                                   token.collection.as_deref().unwrap_or("Unknown collection"), token.token_id, token.contract,
+                                   token.standard.as_ref().map(|standard| format!(", {}", standard)).unwrap_or_default());
+            if let Some(name) = &token.name {
+                line.push_str(&format!(": {}", name));
+            }
+            if token.balance > U256::one() {
+                line.push_str(&format!(" x{}", token.balance));
+            }
+            if let Some(image) = &token.image {
+                line.push_str(&format!("\n  Image: {}", image));
+            }
+            line
+        })
+        .collect::<Vec<_>>();
+    Ok(format!("NFTs owned by {:?}:\n{}", owner, lines.join("\n")))
+}
+
+async fn nft_metadata(ctx: &ToolContext, contract: &str, token_id: &str) -> Result<String, ToolError> {
+    if contract.is_empty() || token_id.is_empty() {
+        return Err(ToolError::InvalidArgs("The contract and token_id are required".to_string()));
+    }
+    let token_id = parse_token_id(token_id)?;
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let contract = match resolve_address(&provider, contract).await {
+        Ok(addr) => addr,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid contract address: {}", e))),
+    };
+    
+    let metadata = match nft::metadata(Arc::new(provider), &ctx.config.nft, contract, token_id).await {
+        Ok(metadata) => metadata,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch metadata: {}", e))),
+    };
+    let mut lines = vec![
+        format!("Token #{} on {:?}", token_id, contract),
+        format!("Token URI: {}", if metadata.token_uri.starts_with("data:") { "(inline data URI)" } else { &metadata.token_uri }),
+    ];
+    if let Some(name) = &metadata.name {
+        lines.push(format!("Name: {}", name));
+    }
+    if let Some(description) = &metadata.description {
+        lines.push(format!("Description: {}", description));
+    }
+    if let Some(image) = &metadata.image {
+        lines.push(format!("Image: {}", image));
     }
+    if let Some(animation_url) = &metadata.animation_url {
+        lines.push(format!("Animation: {}", animation_url));
+    }
+    if !metadata.attributes.is_empty() {
+        lines.push(format!("Attributes: {}", metadata.attributes.iter()
+            .map(|(trait_type, value)| format!("{}: {}", trait_type, value))
+            .collect::<Vec<_>>()
+            .join(", ")));
+    }
+    Ok(lines.join("\n"))
 }
 
-async fn get_weather(city: &str) -> anyhow::Result<String> {
-    // In a real implementation, you would call a weather API
-    // For this example, we'll return mock data
-    
-    // Simulate API call delay
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+// Sender and options shared by NFT transfers and mints
+struct NftSend<'a> {
+    from_address: &'a str,
+    to: &'a str,
+    amount: String,
+    force: bool,
+    private: Option<bool>,
+}
+
+async fn nft_transfer(ctx: &ToolContext, contract: &str, token_id: &str, request: &NftSend<'_>) -> Result<String, ToolError> {
+    if contract.is_empty() || token_id.is_empty() || request.to.is_empty() || request.from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("The contract, token_id, to, and from address are required".to_string()));
+    }
+    let token_id = parse_token_id(token_id)?;
+    let amount = match U256::from_dec_str(&request.amount) {
+        Ok(amount) if !amount.is_zero() => amount,
+        _ => return Err(ToolError::InvalidArgs(format!("Invalid amount '{}'", request.amount))),
+    };
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let (contract, from_address, recipient) = match (resolve_address(&provider, contract).await, resolve_address(&provider, request.from_address).await, resolve_recipient(ctx, &provider, request.to).await) {
+        (Ok(contract), Ok(from), Ok(recipient)) => (contract, from, recipient),
+        (Err(e), _, _) => return Err(ToolError::InvalidArgs(format!("Invalid contract address: {}", e))),
+        (_, Err(e), _) => return Err(ToolError::InvalidArgs(format!("Invalid from address: {}", e))),
+        (_, _, Err(e)) => return Err(ToolError::InvalidArgs(format!("Invalid to address: {}", e))),
+    };
+    let to = recipient.address;
     
-    // Return mock data
-    match city.to_lowercase().as_str() {
-        "cairo" => Ok("30°C, sunny".to_string()),
-        "london" => Ok("15°C, cloudy with occasional rain".to_string()),
-        "new york" => Ok("22°C, partly cloudy".to_string()),
-        "tokyo" => Ok("25°C, clear skies".to_string()),
-        _ => Ok(format!("Weather data for {} is not available. This is a mock implementation.", city)),
+    // Check ownership up front for a clearer error than a reverted simulation
+    let client = Arc::new(provider.clone());
+    let standard = match nft::standard(client.clone(), contract).await {
+        Ok(standard) => standard,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    match standard {
+        nft::Standard::Erc721 => match nft::owner_of(client.clone(), contract, token_id).await {
+            Ok(owner) if owner == from_address => {},
+            Ok(owner) => return Err(ToolError::InvalidArgs(format!("Token #{} is owned by {:?}, not {:?}", token_id, owner, from_address))),
+            Err(e) => return Err(ToolError::InvalidArgs(format!("Token #{} not found: {}", token_id, e))),
+        },
+        nft::Standard::Erc1155 => match nft::balance_of(client.clone(), contract, from_address, token_id).await {
+            Ok(balance) if balance >= amount => {},
+            Ok(balance) => return Err(ToolError::InvalidArgs(format!("{:?} holds {} of token #{}, fewer than {}", from_address, balance, token_id, amount))),
+            Err(e) => return Err(ToolError::RpcError(format!("Could not read balance: {}", e))),
+        },
     }
+    
+    let calldata = nft::transfer_call(client, standard, contract, from_address, to, token_id, amount);
+    let description = match standard {
+        nft::Standard::Erc721 => format!("Transfer {} #{} on {:?} to {:?}", standard, token_id, contract, to),
+        nft::Standard::Erc1155 => format!("Transfer {} x {} #{} on {:?} to {:?}", amount, standard, token_id, contract, to),
+    };
+    let sent = nft_send(ctx, provider, from_address, request, contract, calldata, U256::zero(), &description, None).await;
+    sent.map(|output| match recipient.warning() {
+        Some(warning) => format!("{}\n{}", output, warning),
+        None => output,
+    })
 }
 
-fn get_time(timezone: Option<&str>) -> anyhow::Result<String> {
-    let now = Local::now();
+async fn nft_mint(ctx: &ToolContext, contract: Option<&str>, token_uri: Option<&str>, value: &str, request: &NftSend<'_>) -> Result<String, ToolError> {
+    let Some(contract) = contract.or(ctx.config.nft.mint_contract.as_deref()) else {
+        return Err(ToolError::NotConfigured("No contract given and no mint_contract configured under [nft]".to_string()));
+    };
+    if request.to.is_empty() || request.from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("The to and from addresses are required".to_string()));
+    }
+    let function = match nft::mint_function(&ctx.config.nft) {
+        Ok(function) => function,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
+    let quantity = match U256::from_dec_str(&request.amount) {
+        Ok(quantity) if !quantity.is_zero() => quantity,
+        _ => return Err(ToolError::InvalidArgs(format!("Invalid amount '{}'", request.amount))),
+    };
+    let value_wei = match units::parse_amount(value) {
+        Ok(wei) => wei,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid value: {}", e))),
+    };
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
+    };
+    let (contract, from_address, to) = match (resolve_address(&provider, contract).await, resolve_address(&provider, request.from_address).await, resolve_recipient(ctx, &provider, request.to).await) {
+        (Ok(contract), Ok(from), Ok(recipient)) => (contract, from, recipient.address),
+        (Err(e), _, _) => return Err(ToolError::InvalidArgs(format!("Invalid contract address: {}", e))),
+        (_, Err(e), _) => return Err(ToolError::InvalidArgs(format!("Invalid from address: {}", e))),
+        (_, _, Err(e)) => return Err(ToolError::InvalidArgs(format!("Invalid to address: {}", e))),
+    };
+    let calldata = match nft::mint_call(&function, to, token_uri, quantity) {
+        Ok(calldata) => calldata,
+        Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+    };
     
-    match timezone {
-        Some(tz) => {
-            // In a real implementation, you would handle different timezones
-            // For this example, we'll just return the local time with a note
-            Ok(format!("Current time (local, timezone {} not implemented): {}", 
-                      tz, now.format("%Y-%m-%d %H:%M:%S")))
-        },
-        None => {
-            Ok(format!("Current local time: {}", now.format("%Y-%m-%d %H:%M:%S")))
+    let description = format!("Mint on {:?} to {:?} with {}", contract, to, function.signature());
+    nft_send(ctx, provider, from_address, request, contract, calldata, value_wei, &description, Some(to)).await
+}
+
+// Send an NFT transaction like any other contract call. The policy sees the recipient decoded
+// from a transfer's calldata; a mint's calldata is the configured function's, so its recipient
+// `minted_to` is checked here, without the value, which counts once the mint is sent. Mints
+// report the ids of the tokens minted to `minted_to`.
+#[allow(clippy::too_many_arguments)]
+async fn nft_send(ctx: &ToolContext, provider: EthProvider, from_address: Address, request: &NftSend<'_>, contract: Address, calldata: Bytes, value: U256, description: &str, minted_to: Option<Address>) -> Result<String, ToolError> {
+    if let Some(to) = minted_to {
+        let intent = match policy_intent(&provider, contract, value, &calldata).await {
+            Ok(intent) => TxIntent { recipient: Some(to), value: U256::zero(), ..intent },
+            Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+        };
+        if let Err(reason) = ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+            return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The transaction was NOT sent.", reason)));
         }
     }
+    
+    let provider = Arc::new(provider);
+    let labels = [(from_address, "sender"), (contract, "NFT contract")];
+    let (output, receipt) = send_contract_call(ctx, &provider, from_address, contract, value, calldata, description, &labels, request.force, request.private, None).await?;
+    let minted = receipt.zip(minted_to)
+        .map(|(receipt, to)| nft::minted_token_ids(&receipt, contract, to))
+        .filter(|ids| !ids.is_empty());
+    Ok(match minted {
+        Some(ids) => format!("{}\nMinted Token IDs: {}", output, ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")),
+        None => output,
+    })
 }
 
-// In-memory wallet storage (for demo purposes)
-lazy_static::lazy_static! {
-    static ref WALLETS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
-}
+// Longest text returned by an IPFS fetch
+const MAX_FETCHED_CHARS: usize = 20_000;
 
-// Sepolia RPC URL
-fn get_sepolia_rpc_url() -> String {
-    env::var("SEPOLIA_RPC_URL")
-        .expect("SEPOLIA_RPC_URL must be set")
+fn pinned_summary(ctx: &ToolContext, name: &str, cid: &str) -> String {
+    format!("Pinned {} to IPFS ({})\nCID: {}\nURI: ipfs://{}\nGateway: {}",
+            name, ctx.config.ipfs.provider, cid, cid, nft::gateway_url(&format!("ipfs://{}", cid), &ctx.config.nft.ipfs_gateway))
 }
 
-// Get provider for Ethereum network
-async fn get_provider() -> anyhow::Result<Provider<Http>> {
-    // Use environment variable if available, otherwise use default
-    let rpc_url = env::var("ETH_RPC_URL").unwrap_or_else(|_| get_sepolia_rpc_url());
-    
-    // Create provider
-    let provider = Provider::<Http>::try_from(rpc_url)?;
-    Ok(provider)
+async fn ipfs_pin_json(ctx: &ToolContext, content: Option<&serde_json::Value>, name: &str) -> Result<String, ToolError> {
+    // The model sometimes passes the document as a string of JSON
+    let content = match content {
+        Some(serde_json::Value::String(text)) => match serde_json::from_str(text) {
+            Ok(json) => json,
+            Err(e) => return Err(ToolError::InvalidArgs(format!("content is not valid JSON: {}", e))),
+        },
+        Some(serde_json::Value::Null) | None => return Err(ToolError::InvalidArgs("The content to pin is required".to_string())),
+        Some(json) => json.clone(),
+    };
+    match ipfs::pin_json(&ctx.config.ipfs, name, &content).await {
+        Ok(cid) => Ok(pinned_summary(ctx, name, &cid)),
+        Err(e) => Err(ToolError::Failed(format!("Could not pin to IPFS: {}", e))),
+    }
 }
 
-// Ethereum wallet functions
-async fn eth_generate_wallet() -> anyhow::Result<String> {
-    // Generate a new random private key
-    let mut rng = rand::thread_rng();
-    let mut private_key_bytes: [u8; 32] = [0; 32];
-    rng.fill(&mut private_key_bytes);
-    let private_key = hex::encode(&private_key_bytes);
-    
-    // Create wallet from private key
-    let wallet = match LocalWallet::from_bytes(&private_key_bytes) {
-        Ok(wallet) => wallet,
-        Err(_) => return Ok("Failed to generate wallet".to_string()),
+async fn ipfs_pin_file(ctx: &ToolContext, path: Option<&str>, content: Option<&str>, name: Option<&str>) -> Result<String, ToolError> {
+    let (file_name, bytes) = match (path, content) {
+        (Some(path), _) => match ipfs::read_upload(&ctx.uploads_dir(), path) {
+            Ok(file) => file,
+            Err(e) => return Err(ToolError::InvalidArgs(e.to_string())),
+        },
+        (None, Some(content)) => ("file.txt".to_string(), content.as_bytes().to_vec()),
+        (None, None) => return Err(ToolError::InvalidArgs("Either a path or the content to pin is required".to_string())),
     };
-    
-    // Get the wallet address
-    let address = wallet.address();
-    
-    // Store the private key and address pair (for demo purposes)
-    let mut wallets = WALLETS.lock().unwrap();
-    wallets.insert(format!("{:?}", address), private_key.clone());
-    
-    Ok(format!("Generated new Ethereum wallet:\nAddress: {:?}\nPrivate Key: {}", address, private_key))
+    let name = name.map(str::to_string).unwrap_or(file_name);
+    let size = bytes.len();
+    match ipfs::pin_bytes(&ctx.config.ipfs, &name, bytes).await {
+        Ok(cid) => Ok(format!("{}\nSize: {} bytes", pinned_summary(ctx, &name, &cid), size)),
+        Err(e) => Err(ToolError::Failed(format!("Could not pin to IPFS: {}", e))),
+    }
 }
 
-async fn eth_check_balance(address: &str) -> anyhow::Result<String> {
-    if address.is_empty() {
-        return Ok("Error: Address is required".to_string());
+async fn ipfs_fetch(ctx: &ToolContext, cid: &str) -> Result<String, ToolError> {
+    let cid = ipfs::parse_cid(cid);
+    if cid.is_empty() {
+        return Err(ToolError::InvalidArgs("The CID to fetch is required".to_string()));
     }
-    
-    // Parse the address
-    let address_result = Address::from_str(address);
-    let address = match address_result {
-        Ok(addr) => addr,
-        Err(_) => return Ok(format!("Error: Invalid Ethereum address format: {}", address)),
-    };
-    
-    // Get provider
-    let provider = match get_provider().await {
-        Ok(provider) => provider,
-        Err(e) => return Ok(format!("Error connecting to Ethereum node: {}", e)),
+    let fetched = match ipfs::fetch(&ctx.config.ipfs, &ctx.config.nft.ipfs_gateway, &cid).await {
+        Ok(fetched) => fetched,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch from IPFS: {}", e))),
     };
-    
-    // Get balance from the network
-    match provider.get_balance(address, None).await {
-        Ok(balance) => {
-            // Convert from Wei to ETH (1 ETH = 10^18 Wei)
-            let eth_balance = balance.as_u128() as f64 / 1_000_000_000_000_000_000.0;
-            Ok(format!("Balance for address {:?}: {:.6} ETH (via {})", 
-                      address, eth_balance, get_sepolia_rpc_url()))
+    let content_type = fetched.content_type.as_deref().unwrap_or("unknown type");
+    match String::from_utf8(fetched.bytes) {
+        Ok(text) => {
+            let shown: String = text.chars().take(MAX_FETCHED_CHARS).collect();
+            let truncated = if shown.len() < text.len() { "\n... (truncated)" } else { "" };
+            Ok(format!("Content of {} ({}, {} bytes):\n{}{}", cid, content_type, text.len(), shown, truncated))
         },
-        Err(e) => {
-            // Fallback to mock data if there's an error
-            println!("Error fetching balance, using mock data: {}", e);
-            let mock_balance = format!("{}.{} ETH (mock)", 
-                                     rand::thread_rng().gen_range(0..10), 
-                                     rand::thread_rng().gen_range(100000..999999));
-            Ok(format!("Balance for address {:?}: {}", address, mock_balance))
-        }
+        Err(e) => Ok(format!("{} is binary content ({}, {} bytes)\nGateway: {}",
+                             cid, content_type, e.as_bytes().len(),
+                             nft::gateway_url(&format!("ipfs://{}", cid), &ctx.config.nft.ipfs_gateway))),
     }
 }
 
-// Parse and execute a natural language ETH send command
-async fn parse_and_execute_eth_send_command(command: &str) -> anyhow::Result<String> {
-    println!("Parsing ETH send command: {}", command);
-    
-    // Extract amount (look for pattern like "0.1 ETH" or "0.1ETH")
-    let amount_pattern = regex::Regex::new(r"(\d+\.?\d*) ?ETH").unwrap();
-    let amount = match amount_pattern.captures(command) {
-        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
-        None => return Ok("Error: Could not parse ETH amount from command".to_string()),
+// Provider and Safe address shared by the Safe operations
+async fn safe_context(ctx: &ToolContext, safe: Option<&str>) -> Result<(EthProvider, Address), ToolError> {
+    let safe_address = safe::safe_address(&ctx.config.safe, safe).map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let provider = get_provider(&ctx.config.chain).await
+        .map_err(|e| ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e)))?;
+    Ok((provider, safe_address))
+}
+
+// Signing wallet of an owner (or executor) given by address or account
+async fn safe_signer(ctx: &ToolContext, provider: &EthProvider, from_address: &str) -> Result<AgentSigner, ToolError> {
+    if from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("The owner's from_address or account is required".to_string()));
+    }
+    let from_address = resolve_address(provider, from_address).await
+        .map_err(|e| ToolError::InvalidArgs(format!("Invalid from address: {}", e)))?;
+    load_signer(ctx, from_address).await
+        .map_err(|e| ToolError::InvalidArgs(e.to_string()))
+}
+
+fn parse_safe_tx_hash(safe_tx_hash: &str) -> Result<H256, ToolError> {
+    H256::from_str(safe_tx_hash.trim()).map_err(|_| ToolError::InvalidArgs("A valid safe_tx_hash is required".to_string()))
+}
+
+async fn safe_info(ctx: &ToolContext, safe: Option<&str>) -> Result<String, ToolError> {
+    let (provider, safe_address) = safe_context(ctx, safe).await?;
+    match safe::info(Arc::new(provider), safe_address).await {
+        Ok(info) => Ok(format!("Safe {:?} on {}\n\
+                               Threshold: {} of {} owners\n\
+                               Owners: {}\n\
+                               Nonce: {}",
+                               info.address, network_label(&ctx.config.chain),
+                               info.threshold, info.owners.len(),
+                               info.owners.iter().map(|owner| format!("{:?}", owner)).collect::<Vec<_>>().join(", "),
+                               info.nonce)),
+        Err(e) => Err(ToolError::RpcError(format!("Could not read Safe: {}", e))),
+    }
+}
+
+async fn safe_pending(ctx: &ToolContext, safe: Option<&str>) -> Result<String, ToolError> {
+    let (provider, safe_address) = safe_context(ctx, safe).await?;
+    let info = match safe::info(Arc::new(provider), safe_address).await {
+        Ok(info) => info,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read Safe: {}", e))),
+    };
+    match safe::pending_transactions(&ctx.config.safe, safe_address, info.nonce).await {
+        Ok(pending) if pending.is_empty() => Ok(format!("No pending transactions for Safe {:?}", safe_address)),
+        Ok(pending) => Ok(format!("Pending transactions for Safe {:?} (threshold {}):\n{}",
+                                  safe_address, info.threshold,
+                                  pending.iter().map(|tx| safe::describe_transaction(tx, info.threshold)).collect::<Vec<_>>().join("\n"))),
+        Err(e) => Err(ToolError::RpcError(format!("Could not list pending transactions: {}", e))),
+    }
+}
+
+// Propose a call from the Safe, signed by the proposing owner as its first confirmation
+async fn safe_propose(ctx: &ToolContext, safe: Option<&str>, args: &serde_json::Value, from_address: &str) -> Result<String, ToolError> {
+    let (provider, safe_address) = safe_context(ctx, safe).await?;
+    let to = match args.get("to").and_then(|v| v.as_str()) {
+        Some(to) if !to.is_empty() => match resolve_recipient(ctx, &provider, to).await {
+            Ok(recipient) => recipient.address,
+            Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid to address: {}", e))),
+        },
+        _ => return Err(ToolError::InvalidArgs("The 'to' address is required".to_string())),
+    };
+    let value = match units::parse_amount(args.get("value").and_then(|v| v.as_str()).unwrap_or("0")) {
+        Ok(wei) => wei,
+        Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid value: {}", e))),
     };
     
-    // Extract from_address (look for pattern like "from 0x...")
-    let from_pattern = regex::Regex::new(r"from (0x[a-fA-F0-9]{40})").unwrap();
-    let from_address = match from_pattern.captures(command) {
-        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
-        None => return Ok("Error: Could not parse from address from command".to_string()),
+    let data = call_data_from_args(ctx, args)?;
+    
+    // The Safe's funds move only once owners execute, but the policy applies to what is proposed
+    let intent = match policy_intent(&provider, to, value, &data).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
     };
+    if let Err(reason) = ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. Nothing was proposed.", reason)));
+    }
     
-    // Extract to_address (look for pattern like "to 0x...")
-    let to_pattern = regex::Regex::new(r"to (0x[a-fA-F0-9]{40})").unwrap();
-    let to_address = match to_pattern.captures(command) {
-        Some(caps) => caps.get(1).map_or("", |m| m.as_str()),
-        None => return Ok("Error: Could not parse to address from command".to_string()),
+    let wallet = safe_signer(ctx, &provider, from_address).await?;
+    let client = Arc::new(provider);
+    let info = match safe::info(client.clone(), safe_address).await {
+        Ok(info) => info,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read Safe: {}", e))),
+    };
+    if !info.owners.contains(&wallet.address()) {
+        return Err(ToolError::InvalidArgs(format!("{:?} is not an owner of Safe {:?}", wallet.address(), safe_address)));
+    }
+    let pending = match safe::pending_transactions(&ctx.config.safe, safe_address, info.nonce).await {
+        Ok(pending) => pending,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not list pending transactions: {}", e))),
     };
+    let nonce = safe::next_nonce(&info, &pending);
     
-    // Extract private key (look for pattern like "private key ...")
-    let key_pattern = regex::Regex::new(r"private key ([a-fA-F0-9]{64})").unwrap();
-    let private_key = key_pattern.captures(command).map(|caps| caps.get(1).map_or("", |m| m.as_str()));
+    let safe_tx_hash = match safe::transaction_hash(client, safe_address, to, value, data.clone(), nonce).await {
+        Ok(hash) => hash,
+        Err(e) => return Err(ToolError::Failed(format!("Could not compute the Safe transaction hash: {}", e))),
+    };
+    let call = format!("send {} ETH to {:?}{}", units::format_eth(value), to,
+                       if data.is_empty() { String::new() } else { format!(" with {} bytes of calldata", data.len()) });
+    if ctx.dry_run {
+        return Ok(dry_run_report(&format!("Propose transaction #{} to Safe {:?}: {}", nonce, safe_address, call),
+                                 &[&format!("Safe Transaction Hash: {:?}", safe_tx_hash)], &ctx.config.chain));
+    }
     
-    println!("Parsed command - From: {}, To: {}, Amount: {}, Has Private Key: {}", 
-             from_address, to_address, amount, private_key.is_some());
+    let signature = match safe::sign_transaction_hash(&wallet, safe_tx_hash).await {
+        Ok(signature) => signature,
+        Err(e) => return Err(ToolError::Failed(format!("Could not sign: {}", e))),
+    };
+    if let Err(e) = safe::propose(&ctx.config.safe, safe_address, to, value, &data, nonce, safe_tx_hash, wallet.address(), &signature).await {
+        return Err(ToolError::Failed(format!("Could not propose transaction: {}", e)));
+    }
     
-    // Execute the transaction with the parsed parameters
-    eth_send_eth(from_address, to_address, amount, private_key).await
+    Ok(format!("Proposed transaction #{} to Safe {:?}: {}\n\
+               Confirmations: 1 of {} (signed by {:?})\n\
+               Safe Transaction Hash: {:?}",
+               nonce, safe_address, call, info.threshold, wallet.address(), safe_tx_hash))
 }
 
-async fn eth_send_eth(from_address: &str, to_address: &str, amount: &str, provided_private_key: Option<&str>) -> anyhow::Result<String> {
-    if from_address.is_empty() || to_address.is_empty() || amount.is_empty() {
-        return Ok("Error: From address, to address, and amount are required".to_string());
+async fn safe_confirm(ctx: &ToolContext, safe: Option<&str>, safe_tx_hash: &str, from_address: &str) -> Result<String, ToolError> {
+    let safe_tx_hash = parse_safe_tx_hash(safe_tx_hash)?;
+    let (provider, safe_address) = safe_context(ctx, safe).await?;
+    let wallet = safe_signer(ctx, &provider, from_address).await?;
+    let tx = match safe::get_transaction(&ctx.config.safe, safe_tx_hash).await {
+        Ok(tx) => tx,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch proposal: {}", e))),
+    };
+    if tx.is_executed {
+        return Ok(format!("Safe transaction {:?} was already executed", safe_tx_hash));
+    }
+    if tx.confirmations.iter().any(|confirmation| confirmation.owner == wallet.address()) {
+        return Ok(format!("{:?} already confirmed Safe transaction {:?}", wallet.address(), safe_tx_hash));
     }
     
-    // Parse the addresses
-    let from_address_result = Address::from_str(from_address);
-    let from_address = match from_address_result {
-        Ok(addr) => addr,
-        Err(_) => return Ok(format!("Error: Invalid from address format: {}", from_address)),
+    // Another owner proposed it, so the policy is applied to the call before it is signed here
+    let data = tx.data.clone().unwrap_or_default();
+    let intent = match policy_intent(&provider, tx.to, tx.value, &data).await {
+        Ok(intent) => intent,
+        Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
     };
+    if let Err(reason) = ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+        return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. Nothing was signed.", reason)));
+    }
     
-    let to_address_result = Address::from_str(to_address);
-    let to_address = match to_address_result {
-        Ok(addr) => addr,
-        Err(_) => return Ok(format!("Error: Invalid to address format: {}", to_address)),
+    let info = match safe::info(Arc::new(provider), safe_address).await {
+        Ok(info) => info,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read Safe: {}", e))),
     };
+    if !info.owners.contains(&wallet.address()) {
+        return Err(ToolError::InvalidArgs(format!("{:?} is not an owner of Safe {:?}", wallet.address(), safe_address)));
+    }
+    if ctx.dry_run {
+        return Ok(dry_run_report(&format!("Confirm as {:?}", wallet.address()), &[&safe::describe_transaction(&tx, info.threshold)], &ctx.config.chain));
+    }
     
-    // Parse amount
-    let amount_eth = match amount.parse::<f64>() {
-        Ok(val) => val,
-        Err(_) => return Ok(format!("Error: Invalid amount: {}", amount)),
+    let signature = match safe::sign_transaction_hash(&wallet, safe_tx_hash).await {
+        Ok(signature) => signature,
+        Err(e) => return Err(ToolError::Failed(format!("Could not sign: {}", e))),
     };
-    
-    // Get the private key - either from the provided parameter or from stored wallets
-    let private_key = if let Some(key) = provided_private_key {
-        // Use the provided private key
-        key.to_string()
-    } else {
-        // Check if we have the private key for this address in our wallet storage
-        let wallets = WALLETS.lock().unwrap();
-        match wallets.get(&format!("{:?}", from_address)) {
-            Some(key) => key.clone(),
-            None => {
-                return Ok(format!("Error: No private key found for address {:?}. Please provide a private key.", from_address))
-            }
-        }
+    if let Err(e) = safe::confirm(&ctx.config.safe, safe_tx_hash, &signature).await {
+        return Err(ToolError::Failed(format!("Could not confirm transaction: {}", e)));
+    }
+    let mut tx = tx;
+    tx.confirmations.push(safe::Confirmation { owner: wallet.address(), signature });
+    Ok(format!("Confirmed as {:?}\n{}", wallet.address(), safe::describe_transaction(&tx, info.threshold)))
+}
+
+// Execute a proposal that reached the threshold; any account can submit it and pay the gas.
+// The policy checks the call the Safe makes, decoded from the execTransaction calldata.
+async fn safe_execute(ctx: &ToolContext, safe: Option<&str>, safe_tx_hash: &str, from_address: &str, force: bool, private: Option<bool>) -> Result<String, ToolError> {
+    let safe_tx_hash = parse_safe_tx_hash(safe_tx_hash)?;
+    let (provider, safe_address) = safe_context(ctx, safe).await?;
+    if from_address.is_empty() {
+        return Err(ToolError::InvalidArgs("The executor's from_address or account is required".to_string()));
+    }
+    let executor = resolve_address(&provider, from_address).await
+        .map_err(|e| ToolError::InvalidArgs(format!("Invalid from address: {}", e)))?;
+    let tx = match safe::get_transaction(&ctx.config.safe, safe_tx_hash).await {
+        Ok(tx) => tx,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not fetch proposal: {}", e))),
     };
-    // No need to hold the lock anymore if we accessed the wallets
-    let private_key_bytes = match hex::decode(&private_key) {
-        Ok(bytes) => bytes,
-        Err(_) => return Ok("Error: Invalid private key format".to_string()),
+    if tx.is_executed {
+        return Ok(format!("Safe transaction {:?} was already executed", safe_tx_hash));
+    }
+    let provider = Arc::new(provider);
+    let info = match safe::info(provider.clone(), safe_address).await {
+        Ok(info) => info,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not read Safe: {}", e))),
     };
+    let required = tx.confirmations_required.map(U256::from).unwrap_or(info.threshold);
+    if U256::from(tx.confirmations.len()) < required {
+        return Ok(format!("Not enough confirmations to execute yet\n{}", safe::describe_transaction(&tx, info.threshold)));
+    }
+    if tx.nonce != info.nonce {
+        return Ok(format!("Safe transaction #{} can't execute before the Safe's current nonce #{}", tx.nonce, info.nonce));
+    }
     
-    // Get provider
-    let provider = match get_provider().await {
+    let calldata = safe::exec_call(provider.clone(), safe_address, &tx);
+    let description = format!("Execute Safe transaction #{}: send {} ETH to {:?}", tx.nonce, units::format_eth(tx.value), tx.to);
+    let labels = [(executor, "executor"), (safe_address, "Safe"), (tx.to, "recipient")];
+    let (output, _) = send_contract_call(ctx, &provider, executor, safe_address, U256::zero(), calldata, &description, &labels, force, private, None).await?;
+    Ok(output)
+}
+
+// Owner wallet and smart account for the smart_account operations
+async fn smart_account_context(ctx: &ToolContext, provider: &EthProvider, owner_address: &str, salt: u64) -> Result<(AgentSigner, smart_account::SmartAccount), ToolError> {
+    if owner_address.is_empty() {
+        return Err(ToolError::InvalidArgs("The owner's from_address or account is required".to_string()));
+    }
+    let owner_address = resolve_address(provider, owner_address).await
+        .map_err(|e| ToolError::InvalidArgs(format!("Invalid owner address: {}", e)))?;
+    let wallet = load_signer(ctx, owner_address).await
+        .map_err(|e| ToolError::InvalidArgs(e.to_string()))?;
+    let account = smart_account::account(Arc::new(provider.clone()), &ctx.config.account_abstraction, owner_address, U256::from(salt)).await
+        .map_err(|e| ToolError::Failed(e.to_string()))?;
+    Ok((wallet, account))
+}
+
+async fn smart_account_address(ctx: &ToolContext, owner_address: &str, salt: u64) -> Result<String, ToolError> {
+    let provider = match get_provider(&ctx.config.chain).await {
         Ok(provider) => provider,
-        Err(e) => return Ok(format!("Error connecting to Ethereum node: {}", e)),
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
     };
-    
-    // Create wallet from private key
-    let wallet = match LocalWallet::from_bytes(&private_key_bytes) {
-        Ok(wallet) => wallet.with_chain_id(11155111u64), // Sepolia chain ID
-        Err(_) => return Ok("Error: Failed to create wallet from private key".to_string()),
+    let (_, account) = smart_account_context(ctx, &provider, owner_address, salt).await?;
+    let balance = provider.get_balance(account.address, None).await.unwrap_or_default();
+    Ok(format!("Smart Account: {:?}\n\
+               Owner: {:?} (salt {})\n\
+               Deployed: {}\n\
+               Balance: {} ETH\n\
+               Network: {}",
+               account.address, account.owner, account.salt,
+               if account.deployed { "yes" } else { "no (deployed with its first operation)" },
+               units::format_eth(balance), network_label(&ctx.config.chain)))
+}
+
+// Send a call from the smart account as a UserOperation, or deploy it when there is no call.
+// The bundler's gas estimation simulates the operation, so a reverting call is never sent.
+async fn smart_account_send(ctx: &ToolContext, owner_address: &str, salt: u64, call: Option<(&str, &str, Bytes)>, sponsored: bool) -> Result<String, ToolError> {
+    let config = &ctx.config.account_abstraction;
+    let provider = match get_provider(&ctx.config.chain).await {
+        Ok(provider) => provider,
+        Err(e) => return Err(ToolError::RpcError(format!("Could not connect to Ethereum node: {}", e))),
     };
+    let (wallet, account) = smart_account_context(ctx, &provider, owner_address, salt).await?;
     
-    // Create a client with the wallet
-    let client = SignerMiddleware::new(provider, wallet);
-    let client = Arc::new(client);
-    
-    // Convert ETH amount to Wei (1 ETH = 10^18 Wei)
-    let wei_amount = (amount_eth * 1_000_000_000_000_000_000.0) as u128;
-    let wei_amount = U256::from(wei_amount);
+    let (call, reservation, description) = match call {
+        Some((to, value, data)) => {
+            if to.is_empty() {
+                return Err(ToolError::InvalidArgs("The 'to' address is required".to_string()));
+            }
+            let to = match resolve_recipient(ctx, &provider, to).await {
+                Ok(recipient) => recipient.address,
+                Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid to address: {}", e))),
+            };
+            let value = match units::parse_amount(value) {
+                Ok(wei) => wei,
+                Err(e) => return Err(ToolError::InvalidArgs(format!("Invalid value: {}", e))),
+            };
+            
+            // Enforce the transaction policy before anything is signed
+            let intent = match policy_intent(&provider, to, value, &data).await {
+                Ok(intent) => intent,
+                Err(e) => return Err(ToolError::Failed(format!("Could not check transaction policy: {}", e))),
+            };
+            let reservation = match ctx.policy.check(&intent, ctx.pool.as_ref()).await {
+                Ok(reservation) => reservation,
+                Err(reason) => return Err(ToolError::PolicyViolation(format!("Refused by transaction policy: {}. The operation was NOT sent.", reason))),
+            };
+            let description = format!("Send {} ETH to {:?}{}", units::format_eth(value), to,
+                                      if data.is_empty() { String::new() } else { format!(" with {} bytes of calldata", data.len()) });
+            (Some((to, value, data)), Some(reservation), description)
+        },
+        None if account.deployed => return Ok(format!("Smart account {:?} is already deployed", account.address)),
+        None => (None, None, "Deploy the account".to_string()),
+    };
+    let value = call.as_ref().map(|(_, value, _)| *value).unwrap_or_default();
     
-    // Get current gas price
-    let gas_price = match client.get_gas_price().await {
-        Ok(price) => price,
-        Err(e) => return Ok(format!("Error getting gas price: {}", e)),
+    let client = Arc::new(provider.clone());
+    let mut op = match smart_account::build_operation(client.clone(), config, &account, call).await {
+        Ok(op) => op,
+        Err(e) => return Err(ToolError::Failed(format!("Could not build user operation: {}", e))),
     };
+    let chain_id = ctx.config.chain.chain_id;
+    if sponsored && let Err(e) = smart_account::sponsor(config, &mut op, chain_id, true).await {
+        return Err(ToolError::RpcError(format!("Could not get paymaster data: {}", e)));
+    }
+    if let Err(e) = smart_account::estimate_gas(config, &mut op).await {
+        return Err(ToolError::SimulationFailed(format!("{}\nUser operation simulation failed: {}\nThe operation was NOT sent.", description, e)));
+    }
+    if sponsored {
+        if let Err(e) = smart_account::sponsor(config, &mut op, chain_id, false).await {
+            return Err(ToolError::RpcError(format!("Could not get paymaster data: {}", e)));
+        }
+    } else {
+        // Without a paymaster the account prefunds its own gas
+        let balance = provider.get_balance(account.address, None).await.unwrap_or_default();
+        let required = value + smart_account::max_cost(&op);
+        if balance < required {
+            return Ok(format!("Smart account {:?} holds {} ETH but needs up to {} ETH for this operation and its gas. \
+                              Fund it first, or use a paymaster.",
+                              account.address, units::format_eth(balance), units::format_eth(required)));
+        }
+    }
     
-    // Create transaction request
-    let tx = TransactionRequest::new()
-        .to(to_address)
-        .value(wei_amount)
-        .from(from_address);
-            
-    // Convert TransactionRequest to TypedTransaction before estimating gas
-    let typed_tx = TypedTransaction::Legacy(tx);
+    if ctx.dry_run {
+        let gas = format!("Gas: paid by {}, up to {} ETH at {} gwei", if sponsored { "the paymaster" } else { "the account" },
+                          units::format_eth(smart_account::max_cost(&op)), units::format_gwei(op.max_fee_per_gas));
+        return Ok(dry_run_report(&format!("{} from smart account {:?}", description, account.address),
+                                 &["User operation simulation: succeeds", &gas], &ctx.config.chain));
+    }
     
-    // Estimate gas for the transaction
-    let gas_estimate = match client.estimate_gas(&typed_tx, None).await {
-        Ok(estimate) => estimate,
-        Err(e) => return Ok(format!("Error estimating gas: {}", e)),
+    let user_op_hash = match smart_account::sign(client, config, &mut op, &wallet).await {
+        Ok(hash) => hash,
+        Err(e) => return Err(ToolError::Failed(format!("Could not sign user operation: {}", e))),
     };
+    if let Err(e) = smart_account::send(config, &op).await {
+        return Err(ToolError::Failed(format!("Could not send user operation: {}", e)));
+    }
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
     
-    // Actually send the transaction
-    match client.send_transaction(typed_tx, None).await {
-        Ok(pending_tx) => {
-            // Get the transaction hash immediately
-            let tx_hash = pending_tx.tx_hash();
-            
-            // Try to get the transaction receipt with a timeout
-            let receipt_future = pending_tx.confirmations(1);
-            match tokio::time::timeout(std::time::Duration::from_secs(60), receipt_future).await {
-                Ok(receipt_result) => {
-                    match receipt_result {
-                        Ok(receipt) => {
-                            // Transaction was mined successfully
-                            // The receipt is an Option<TransactionReceipt>, so we need to unwrap it first
-                            if let Some(receipt_data) = receipt {
-                                Ok(format!("Transaction successfully sent {} ETH from {:?} to {:?}\n\
-                                          Gas Price: {} gwei\n\
-                                          Gas Used: {}\n\
-                                          Block Number: {}\n\
-                                          Network: Sepolia (via {})\n\
-                                          Transaction Hash: {:?}", 
-                                          amount_eth, from_address, to_address, 
-                                          gas_price.as_u128() / 1_000_000_000, // Convert to gwei
-                                          receipt_data.gas_used.unwrap_or_default(),
-                                          receipt_data.block_number.unwrap_or_default(),
-                                          get_sepolia_rpc_url(),
-                                          tx_hash))
-                            } else {
-                                // Transaction was submitted but no receipt was found
-                                Ok(format!("Transaction submitted but no receipt was found.\n\
-                                          {} ETH from {:?} to {:?}\n\
-                                          Network: Sepolia (via {})\n\
-                                          Transaction Hash: {:?}", 
-                                          amount_eth, from_address, to_address,
-                                          get_sepolia_rpc_url(),
-                                          tx_hash))
-                            }
-                        },
-                        Err(e) => {
-                            // Transaction was submitted but failed during mining
-                            Ok(format!("Transaction submitted but failed: {}\n\
-                                      Transaction Hash: {:?}", e, tx_hash))
-                        }
-                    }
-                },
-                Err(_) => {
-                    // Timeout waiting for transaction to be mined
-                    // Return the transaction hash anyway since it was submitted
-                    Ok(format!("Transaction submitted but confirmation timed out after 60 seconds.\n\
-                              {} ETH from {:?} to {:?}\n\
-                              Gas Price: {} gwei\n\
-                              Gas Estimate: {}\n\
-                              Network: Sepolia (via {})\n\
-                              Transaction Hash: {:?}", 
-                              amount_eth, from_address, to_address, 
-                              gas_price.as_u128() / 1_000_000_000, // Convert to gwei
-                              gas_estimate,
-                              get_sepolia_rpc_url(),
-                              tx_hash))
+    let summary = format!("{} from smart account {:?}\n\
+                          Gas: paid by {}, up to {} ETH at {} gwei",
+                          description, account.address,
+                          if sponsored { "the paymaster" } else { "the account" },
+                          units::format_eth(smart_account::max_cost(&op)), units::format_gwei(op.max_fee_per_gas));
+    match smart_account::wait_for_receipt(config, user_op_hash).await {
+        Ok(Some(receipt)) => Ok(format!("User operation {}.\n\
+                                        {}\n\
+                                        Actual Gas Cost: {} ETH\n\
+                                        Block Number: {}\n\
+                                        Transaction Hash: {:?}\n\
+                                        User Operation Hash: {:?}",
+                                        if receipt.success { "succeeded" } else { "reverted" },
+                                        summary, units::format_eth(receipt.actual_gas_cost),
+                                        receipt.block_number.unwrap_or_default(),
+                                        receipt.transaction_hash.unwrap_or_default(), user_op_hash)),
+        Ok(None) => Ok(format!("User operation submitted but not included after 60 seconds.\n\
+                               {}\n\
+                               User Operation Hash: {:?}", summary, user_op_hash)),
+        Err(e) => Ok(format!("User operation submitted, but its receipt could not be fetched: {}\n\
+                             {}\n\
+                             User Operation Hash: {:?}", e, summary, user_op_hash)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::run_turn;
+    use crate::conversation::Conversation;
+    use crate::db::{create_session, list_tool_calls, save_message};
+    use crate::mock_llm::{tool_use, MockLlm};
+    use crate::policy::Policy;
+    use crate::test_support::{context, database, returns, rpc_node, serve, EthCall};
+    use ethers::abi::Token;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn signs_from_deployment_accounts_only_for_admins() {
+        let hd_account = wallet::import_mnemonic("test test test test test test test test test test test junk").unwrap().address;
+        let operator = ToolContext { user: Some(format!("{:?}", Address::random())), role: Some(Role::Operator), ..context(Config::default()) };
+        let refused = load_signer(&operator, hd_account).await.unwrap_err();
+        assert!(refused.to_string().starts_with("No key found"), "{}", refused);
+
+        let mut config = Config::default();
+        config.signer.backend = "kms".to_string();
+        let operator = ToolContext { role: Some(Role::Operator), ..context(config.clone()) };
+        let refused = load_signer(&operator, hd_account).await.unwrap_err();
+        assert_eq!(refused.to_string(), "Only admins can sign with the kms signer backend");
+        let admin = ToolContext { role: Some(Role::Admin), ..context(config) };
+        assert!(!load_signer(&admin, hd_account).await.unwrap_err().to_string().starts_with("Only admins"));
+    }
+
+    #[tokio::test]
+    async fn lists_wallets_by_name_and_takes_names_as_addresses() {
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("named-wallets").to_string_lossy().into_owned();
+        // Nothing listens there, so balances can't be read
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        let ctx = context(config);
+        let created = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "generate", "label": "savings-1" })).await.unwrap();
+        let address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().to_string();
+
+        let listing = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "wallets" })).await.unwrap();
+        assert!(listing.contains(&format!("- savings-1 {}: balance unavailable", address)), "{}", listing);
+        // The name resolves to the account, though its balance can't be read either
+        let balance = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "balance", "address": "savings-1" })).await.unwrap_err();
+        let address: Address = address.parse().unwrap();
+        assert!(balance.message().starts_with(&format!("Could not fetch balance of {:?}", address)), "{:?}", balance);
+    }
+
+    #[tokio::test]
+    async fn sends_from_named_accounts_instead_of_private_keys() {
+        let ctx = context(Config::default());
+        assert!(!get_tools_as_json().unwrap().contains("private_key"));
+
+        let output = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "generate", "label": "trading-1" })).await.unwrap();
+        assert!(output.contains("Account: trading-1") && !output.contains("Private Key"), "{}", output);
+        let listing = execute_tool(&ctx, "eth_wallet", &json!({ "operation": "accounts" })).await.unwrap();
+        assert!(listing.contains("Keystore accounts: ") && listing.contains("trading-1"), "{}", listing);
+
+        let send = json!({ "operation": "send", "to_address": "0x000000000000000000000000000000000000dEaD", "amount": "0.1", "private_key": "ab".repeat(32) });
+        let error = execute_tool(&ctx, "eth_wallet", &send).await.unwrap_err();
+        assert!(matches!(error, ToolError::InvalidArgs(_)) && error.message().contains("wallet import"), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn imports_mnemonics_only_outside_tool_calls() {
+        let schema = get_available_tools().into_iter().find(|tool| tool.name == "eth_wallet").unwrap().input_schema;
+        assert!(schema["properties"].get("mnemonic").is_none());
+
+        let import = json!({ "operation": "mnemonic_import", "mnemonic": "test test test test test test test test test test test junk" });
+        let error = execute_tool(&context(Config::default()), "eth_wallet", &import).await.unwrap_err();
+        assert!(matches!(error, ToolError::InvalidArgs(_)) && error.message().contains("wallet import-mnemonic"), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn keeps_each_users_keystore_accounts_apart() {
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("users-keystore").to_string_lossy().into_owned();
+        let base = context(config);
+        let alice = ToolContext { user: Some("0x00000000000000000000000000000000000a11ce".to_string()), ..base.clone() };
+        let bob = ToolContext { user: Some("0x0000000000000000000000000000000000000b0b".to_string()), ..base.clone() };
+
+        execute_tool(&alice, "eth_wallet", &json!({ "operation": "generate", "label": "treasury-a11ce" })).await.unwrap();
+        let accounts = |ctx: ToolContext| async move {
+            execute_tool(&ctx, "eth_wallet", &json!({ "operation": "accounts" })).await.unwrap_or_default()
+        };
+        assert!(accounts(alice.clone()).await.contains("treasury-a11ce"));
+        assert!(!accounts(bob.clone()).await.contains("treasury-a11ce"));
+        assert!(!accounts(base).await.contains("treasury-a11ce"));
+        // The name is Alice's only, so Bob can have one of his own
+        execute_tool(&bob, "eth_wallet", &json!({ "operation": "generate", "label": "treasury-a11ce" })).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_nft_transfers_to_denied_recipients() {
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("nft-policy").to_string_lossy().into_owned();
+        let created = execute_tool(&context(config.clone()), "eth_wallet", &json!({ "operation": "generate", "label": "collector-1" })).await.unwrap();
+        let owner: Address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().parse().unwrap();
+        config.chain.rpc_url = Some(rpc_node(move |method, params| match method {
+            "eth_chainId" => Some(json!(format!("{:#x}", Config::default().chain.chain_id))),
+            "eth_call" => {
+                let call = EthCall::parse(params);
+                if call.is("supportsInterface(bytes4)") {
+                    returns(&[Token::Bool(call.data[4..8] == [0x80, 0xac, 0x58, 0xcd])])
+                } else {
+                    returns(&[Token::Address(owner)])
                 }
+            },
+            _ => None,
+        }).await);
+
+        let denied = Address::random();
+        let policy = Policy { denied_recipients: vec![denied], ..Policy::default() };
+        let ctx = ToolContext { policy: Arc::new(PolicyEngine::new(policy)), ..context(config) };
+        let transfer = json!({ "operation": "transfer", "contract": format!("{:?}", Address::random()), "token_id": "7", "to": format!("{:?}", denied), "account": "collector-1" });
+        let error = execute_tool(&ctx, "nft", &transfer).await.unwrap_err();
+        assert!(matches!(error, ToolError::PolicyViolation(_)) && error.message().contains("deny list"), "{:?}", error);
+    }
+
+    #[test]
+    fn bumps_replacement_fees_by_the_required_margin() {
+        let gwei = |value: u64| U256::from(value) * U256::exp10(9);
+        // The bumped original fee unless the current estimate is higher
+        assert_eq!(replacement_fee(gwei(20), gwei(5), None, "Max fee").unwrap(), gwei(23));
+        assert_eq!(replacement_fee(gwei(20), gwei(30), None, "Max fee").unwrap(), gwei(30));
+        assert_eq!(replacement_fee(gwei(20), gwei(5), Some("25"), "Max fee").unwrap(), gwei(25));
+        let refused = replacement_fee(gwei(20), gwei(5), Some("22"), "Max fee").unwrap_err();
+        assert_eq!(refused.to_string(), "Max fee of 22 gwei is too low to replace the original; use at least 23 gwei");
+    }
+
+    #[tokio::test]
+    async fn builds_replacements_with_bumped_fees_and_the_same_nonce() {
+        let gwei = |value: u64| U256::from(value) * U256::exp10(9);
+        // A base fee of 1 gwei, for estimates of 5 gwei max fee and 3 gwei priority fee; legacy
+        // gas price of 4 gwei
+        let url = rpc_node(|method, _| match method {
+            "eth_getBlockByNumber" => Some(json!({ "number": "0x10", "baseFeePerGas": "0x3b9aca00" })),
+            "eth_feeHistory" => Some(json!({ "oldestBlock": "0x7", "baseFeePerGas": ["0x3b9aca00"], "gasUsedRatio": [0.5], "reward": [["0x1"]] })),
+            "eth_gasPrice" => Some(json!("0xee6b2800")),
+            _ => panic!("unexpected RPC call {}", method),
+        }).await;
+        let provider = provider::connect(&url).await.unwrap();
+        let sender = Address::repeat_byte(0x11);
+        let original = Transaction {
+            from: sender,
+            to: Some(Address::repeat_byte(0x22)),
+            value: U256::exp10(18),
+            input: Bytes::from(vec![0xde, 0xad]),
+            nonce: 9.into(),
+            gas: 60_000.into(),
+            transaction_type: Some(U64::from(2)),
+            max_fee_per_gas: Some(gwei(40)),
+            max_priority_fee_per_gas: Some(gwei(10)),
+            ..Transaction::default()
+        };
+
+        // Both fees are bumped by 15%, being above the estimates
+        let (speed_up, summary) = build_replacement(&provider, &original, false, FeeOverrides::default()).await.unwrap();
+        let TypedTransaction::Eip1559(tx) = &speed_up else { panic!("not an EIP-1559 replacement") };
+        assert_eq!((tx.max_fee_per_gas, tx.max_priority_fee_per_gas), (Some(gwei(46)), Some(gwei(11) + gwei(1) / 2)));
+        assert_eq!((tx.nonce, tx.gas, tx.value, tx.to.clone()), (Some(9.into()), Some(60_000.into()), Some(U256::exp10(18)), original.to.map(Into::into)));
+        assert_eq!(summary, "Max Fee: 46 gwei (was 40 gwei)\nPriority Fee: 11.5 gwei (was 10 gwei)");
+        let overrides = FeeOverrides { max_fee_per_gas: Some("50"), max_priority_fee_per_gas: Some("11") };
+        let refused = build_replacement(&provider, &original, false, overrides).await.unwrap_err();
+        assert!(refused.to_string().starts_with("Priority fee of 11 gwei is too low"), "{}", refused);
+
+        // A cancel is a zero-value send to the sender at the same nonce
+        let legacy = Transaction { transaction_type: None, max_fee_per_gas: None, max_priority_fee_per_gas: None, gas_price: Some(gwei(2)), ..original };
+        let (cancel, summary) = build_replacement(&provider, &legacy, true, FeeOverrides::default()).await.unwrap();
+        let TypedTransaction::Legacy(tx) = &cancel else { panic!("not a legacy replacement") };
+        assert_eq!((tx.gas_price, tx.nonce, tx.gas, tx.value, tx.data.clone()), (Some(gwei(4)), Some(9.into()), Some(21_000.into()), Some(U256::zero()), None));
+        assert_eq!(tx.to, Some(sender.into()));
+        assert_eq!(summary, "Gas Price: 4 gwei (was 2 gwei, legacy)");
+        let legacy = Transaction { gas_price: Some(gwei(10)), ..legacy };
+        let (cancel, _) = build_replacement(&provider, &legacy, true, FeeOverrides::default()).await.unwrap();
+        assert_eq!(cancel.gas_price(), Some(gwei(11) + gwei(1) / 2));
+    }
+
+    #[tokio::test]
+    async fn signs_permits_only_once_confirmed_and_allowed_by_the_policy() {
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("permit-signing").to_string_lossy().into_owned();
+        config.chain.rpc_url = Some("http://127.0.0.1:9".to_string());
+        execute_tool(&context(config.clone()), "eth_wallet", &json!({ "operation": "generate", "label": "permits-1" })).await.unwrap();
+        let denied = Address::repeat_byte(0xbd);
+        let policy = Policy { denied_recipients: vec![denied], ..Policy::default() };
+        let ctx = ToolContext { policy: Arc::new(PolicyEngine::new(policy)), ..context(config) };
+
+        let token = Address::repeat_byte(0x70);
+        let permit = |spender: Address, confirm: bool| json!({
+            "operation": "sign_typed_data", "account": "permits-1", "confirm": confirm,
+            "typed_data": {
+                "types": {
+                    "EIP712Domain": [{ "name": "name", "type": "string" }, { "name": "chainId", "type": "uint256" }, { "name": "verifyingContract", "type": "address" }],
+                    "Permit": [{ "name": "owner", "type": "address" }, { "name": "spender", "type": "address" }, { "name": "value", "type": "uint256" },
+                               { "name": "nonce", "type": "uint256" }, { "name": "deadline", "type": "uint256" }]
+                },
+                "primaryType": "Permit",
+                "domain": { "name": "Token", "chainId": 1, "verifyingContract": format!("{:?}", token) },
+                "message": { "owner": format!("{:?}", Address::zero()), "spender": format!("{:?}", spender), "value": "1000", "nonce": 0, "deadline": 1 }
             }
-        },
-        Err(e) => {
-            // Failed to send transaction
-            Ok(format!("Error sending transaction: {}", e))
+        });
+        let refused = execute_tool(&ctx, "sign_message", &permit(denied, true)).await.unwrap_err();
+        assert!(matches!(refused, ToolError::PolicyViolation(_)) && refused.message().contains(&format!("{:?} is on the deny list", denied)), "{:?}", refused);
+
+        let spender = Address::repeat_byte(0x5e);
+        let unconfirmed = execute_tool(&ctx, "sign_message", &permit(spender, false)).await.unwrap_err();
+        assert!(matches!(unconfirmed, ToolError::InvalidArgs(_)), "{:?}", unconfirmed);
+        assert!(unconfirmed.message().contains(&format!("Domain: Token, chain 1, contract {:?}", token))
+                && unconfirmed.message().contains(&format!("\"spender\":\"{:?}\"", spender)), "{}", unconfirmed.message());
+        let signed = execute_tool(&ctx, "sign_message", &permit(spender, true)).await.unwrap();
+        assert!(signed.contains("Signature: 0x"), "{}", signed);
+    }
+
+    // A failed lookup is an error, never a made-up balance, and isn't cached
+    #[tokio::test]
+    async fn reports_failed_balance_lookups_as_errors() {
+        let mut config = Config::default();
+        config.chain.rpc_url = Some(rpc_node(|method, _| match method {
+            "eth_chainId" => Some(json!(format!("{:#x}", Config::default().chain.chain_id))),
+            "eth_blockNumber" => Some(json!("0x10")),
+            _ => None,
+        }).await);
+        let ctx = context(config);
+        let balance = json!({ "operation": "balance", "address": format!("{:?}", Address::repeat_byte(0x22)) });
+        for _ in 0..2 {
+            let error = execute_tool(&ctx, "eth_wallet", &balance).await.unwrap_err();
+            assert!(matches!(error, ToolError::RpcError(_)) && error.message().starts_with("Could not fetch balance of"), "{:?}", error);
         }
     }
+
+    #[tokio::test]
+    async fn stops_tools_that_run_past_their_timeout() {
+        // A price API that accepts the request and never answers
+        let stalled = axum::Router::new().fallback(std::future::pending::<()>);
+        let mut config = Config::default();
+        config.prices.coingecko_url = serve(stalled).await;
+        config.tools.timeouts.insert("get_token_price".to_string(), 1);
+        let started = Instant::now();
+        let error = execute_tool(&context(config), "get_token_price", &json!({ "token": "stalled-token" })).await.unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(error.kind(), "timeout");
+        assert!(error.message().starts_with("get_token_price did not finish within 1 seconds and was stopped."), "{:?}", error);
+    }
+
+    // The node fails the test on any broadcast, so a dry run that sent would not report
+    #[tokio::test]
+    async fn simulates_sends_without_broadcasting_in_dry_runs() {
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("dry-run-senders").to_string_lossy().into_owned();
+        let created = execute_tool(&context(config.clone()), "eth_wallet", &json!({ "operation": "generate", "label": "dry-run-sender" })).await.unwrap();
+        let sender: Address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().parse().unwrap();
+        config.chain.rpc_url = Some(rpc_node(|method, _| match method {
+            "eth_chainId" => Some(json!(format!("{:#x}", Config::default().chain.chain_id))),
+            "eth_blockNumber" => Some(json!("0x10")),
+            "eth_getBlockByNumber" => Some(json!({ "number": "0x10", "baseFeePerGas": "0x3b9aca00" })),
+            "eth_feeHistory" => Some(json!({ "oldestBlock": "0x10", "baseFeePerGas": ["0x3b9aca00", "0x3b9aca00"], "gasUsedRatio": [0.5], "reward": [["0x3b9aca00"]] })),
+            "eth_maxPriorityFeePerGas" | "eth_gasPrice" => Some(json!("0x3b9aca00")),
+            "eth_getTransactionCount" => Some(json!("0x3")),
+            "eth_getBalance" => Some(json!("0xde0b6b3a7640000")),
+            "eth_getCode" | "eth_call" => Some(json!("0x")),
+            "eth_estimateGas" => Some(json!("0x5208")),
+            "eth_sendRawTransaction" | "eth_sendTransaction" => panic!("a dry run broadcast a transaction"),
+            _ => None,
+        }).await);
+        let send = |dry_run: bool| json!({ "operation": "send", "account": "dry-run-sender", "to_address": format!("{:?}", Address::repeat_byte(0x22)), "amount": "0.1", "dry_run": dry_run });
+
+        // Asked for by the call, or on for the whole context
+        let previewed = execute_tool(&context(config.clone()), "eth_wallet", &send(true)).await.unwrap();
+        let ctx = ToolContext { dry_run: true, ..context(config) };
+        // Dry-run mode can't be turned off by a call
+        let simulated = execute_tool(&ctx, "eth_wallet", &send(false)).await.unwrap();
+        for output in [previewed, simulated] {
+            let action = format!("Dry run; nothing was sent.\nSend 0.1 ETH from {:?} to {:?}\n", sender, Address::repeat_byte(0x22));
+            assert!(output.starts_with(&action), "{}", output);
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_safe_proposals_of_other_owners_and_signs_nothing_in_dry_runs() {
+        use axum::extract::Path;
+        use axum::routing::get;
+
+        let mut config = Config::default();
+        config.signer.keystore_dir = std::env::temp_dir().join("safe-owners").to_string_lossy().into_owned();
+        let created = execute_tool(&context(config.clone()), "eth_wallet", &json!({ "operation": "generate", "label": "safe-owner-1" })).await.unwrap();
+        let owner: Address = created.lines().find_map(|line| line.strip_prefix("Address: ")).unwrap().trim().parse().unwrap();
+        config.chain.rpc_url = Some(rpc_node(move |method, params| match method {
+            "eth_chainId" => Some(json!(format!("{:#x}", Config::default().chain.chain_id))),
+            "eth_call" => {
+                let call = EthCall::parse(params);
+                match () {
+                    _ if call.is("getOwners()") => returns(&[Token::Array(vec![Token::Address(owner)])]),
+                    _ if call.is("getTransactionHash(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,uint256)") => returns(&[Token::FixedBytes(vec![0x5a; 32])]),
+                    _ => returns(&[Token::Uint(2.into())]),
+                }
+            },
+            _ => panic!("unexpected RPC call {}", method),
+        }).await);
+
+        // A proposal by another owner to send to a denied address, and one to an allowed address.
+        // Nothing can be posted, so a signed proposal or confirmation fails the call.
+        let denied = Address::repeat_byte(0xbd);
+        let proposal = move |to: Address| json!({
+            "safeTxHash": format!("{:?}", H256::repeat_byte(to.0[0])), "to": format!("{:?}", to), "value": "1000", "data": null, "operation": 0,
+            "safeTxGas": 0, "baseGas": 0, "gasPrice": "0", "gasToken": format!("{:?}", Address::zero()),
+            "refundReceiver": format!("{:?}", Address::zero()), "nonce": 2, "confirmations": [], "confirmationsRequired": 2, "isExecuted": false
+        });
+        let service = axum::Router::new()
+            .route("/api/v1/multisig-transactions/{hash}/", get(move |Path(hash): Path<H256>| async move {
+                axum::Json(proposal(if hash == H256::repeat_byte(0xbd) { denied } else { Address::repeat_byte(0x22) }))
+            }))
+            .route("/api/v1/safes/{safe}/multisig-transactions/", get(|| async { axum::Json(json!({ "results": [] })) }));
+        config.safe.transaction_service_url = serve(service).await;
+        config.safe.address = Some(format!("{:?}", Address::repeat_byte(0x5f)));
+        let policy = Policy { denied_recipients: vec![denied], ..Policy::default() };
+        let ctx = ToolContext { policy: Arc::new(PolicyEngine::new(policy)), dry_run: true, ..context(config) };
+
+        let confirm = |hash: H256| json!({ "operation": "confirm", "safe_tx_hash": format!("{:?}", hash), "account": "safe-owner-1" });
+        let refused = execute_tool(&ctx, "safe_multisig", &confirm(H256::repeat_byte(0xbd))).await.unwrap_err();
+        assert!(matches!(refused, ToolError::PolicyViolation(_)) && refused.message().contains("Nothing was signed"), "{:?}", refused);
+        let confirmed = execute_tool(&ctx, "safe_multisig", &confirm(H256::repeat_byte(0x22))).await.unwrap();
+        assert!(confirmed.starts_with(&format!("Dry run; nothing was sent.\nConfirm as {:?}\n#2 ", owner)), "{}", confirmed);
+
+        let propose = json!({ "operation": "propose", "to": format!("{:?}", Address::repeat_byte(0x22)), "value": "0.5", "account": "safe-owner-1" });
+        let proposed = execute_tool(&ctx, "safe_multisig", &propose).await.unwrap();
+        assert!(proposed.starts_with(&format!("Dry run; nothing was sent.\nPropose transaction #2 to Safe {:?}: send 0.5 ETH to {:?}\nSafe Transaction Hash: {:?}",
+                                              Address::repeat_byte(0x5f), Address::repeat_byte(0x22), H256::repeat_byte(0x5a))), "{}", proposed);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn records_tool_calls_in_the_audit_log() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let session_id = Uuid::new_v4();
+        create_session(&pool, session_id).await.unwrap();
+        let mut ctx = ToolContext::new(Arc::new(config), Arc::new(PolicyEngine::new(Policy::default())), Some(pool.clone()), Some(session_id));
+        let message_id = save_message(&pool, session_id, "user", "What time is it in UTC?", "claude-test", None).await.unwrap();
+        ctx.message_id = Some(message_id);
+        let mock = MockLlm::new(vec![tool_use("t1", "get_time", json!({ "timezone": "UTC" })), json!("Done.")]);
+
+        run_turn(&mock, &ctx.config.anthropic, "What time is it in UTC?", &[], None, &Conversation::default(), &ctx).await.unwrap();
+        let calls = list_tool_calls(&pool, Some(session_id), None, 10).await.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool_name, "get_time");
+        assert!(calls[0].success);
+        // Linked to the message that asked for it
+        assert_eq!(calls[0].message_id, Some(message_id));
+    }
 }
@@ -0,0 +1,195 @@
+use crate::bridge;
+use crate::config::Config;
+use crate::db::{
+    pending_bridge_transfers, pending_transactions, save_bridge_transfer, save_transaction, take_bridge_updates, take_transaction_updates,
+    update_bridge_transfer, update_transaction_status, BridgeTransferRecord, TransactionRecord,
+};
+use crate::notify::{self, Kind, Notification};
+use crate::tools::get_provider;
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Record a submitted transaction as pending so its outcome is reported even if the
+/// sender stops waiting for it
+pub async fn record_submitted(pool: Option<&Pool<Postgres>>, session_id: Option<Uuid>, chain_id: u64, tx_hash: H256, tx: &TypedTransaction, from: Address, description: &str) {
+    let Some(pool) = pool else { return };
+    let to = tx.to_addr().map(|to| format!("{:?}", to));
+    let value = tx.value().copied().unwrap_or_default().to_string();
+    let nonce = tx.nonce().map_or(0, |nonce| nonce.as_u64() as i64);
+    let data = tx.data().map(|data| format!("0x{}", hex::encode(data)));
+    if let Err(e) = save_transaction(pool, session_id, chain_id as i64, &format!("{:?}", tx_hash), &format!("{:?}", from),
+                                     to.as_deref(), &value, nonce, data.as_deref(), description).await
+    {
+        tracing::error!(error = %e, "Failed to record submitted transaction");
+    }
+}
+
+fn receipt_status(receipt: &TransactionReceipt) -> &'static str {
+    if receipt.status == Some(U64::from(1)) { "confirmed" } else { "failed" }
+}
+
+/// Record the receipt of a transaction whose outcome was already shown to the user
+pub async fn record_receipt(pool: Option<&Pool<Postgres>>, receipt: &TransactionReceipt) {
+    let Some(pool) = pool else { return };
+    let block_number = receipt.block_number.map(|block| block.as_u64() as i64);
+    if let Err(e) = update_transaction_status(pool, &format!("{:?}", receipt.transaction_hash), receipt_status(receipt), block_number, true).await {
+        tracing::error!(error = %e, "Failed to record transaction receipt");
+    }
+}
+
+/// Record that a submitted transaction starts a bridge transfer, so its arrival on the
+/// destination chain is followed too
+pub async fn record_bridge(pool: Option<&Pool<Postgres>>, session_id: Option<Uuid>, tx_hash: H256, bridge: &str, from_chain_id: u64, to_chain_id: u64, description: &str) {
+    let Some(pool) = pool else { return };
+    if let Err(e) = save_bridge_transfer(pool, session_id, &format!("{:?}", tx_hash), bridge, from_chain_id as i64, to_chain_id as i64, description).await {
+        tracing::error!(error = %e, "Failed to record bridge transfer");
+    }
+}
+
+/// Record a transfer's status, as the tool or the tracker found it. Returns whether it changed.
+pub async fn record_bridge_status(pool: &Pool<Postgres>, record: &BridgeTransferRecord, status: &bridge::Status, reported: bool) -> anyhow::Result<bool> {
+    if status.outcome() == record.status {
+        return Ok(false);
+    }
+    update_bridge_transfer(pool, &record.tx_hash, status.outcome(), status.destination_tx_hash.as_deref(), status.received.as_deref(), reported).await?;
+    Ok(true)
+}
+
+async fn notify_outcome(config: &Config, record: &TransactionRecord) {
+    let title = format!("Transaction {}", record.status);
+    notify::send(&config.notify, &Notification::new(Kind::Transaction, title, describe_update(record))).await;
+}
+
+// Check every pending transaction and bridge transfer from the configured chain once
+async fn poll(config: &Config, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    poll_transactions(config, pool).await?;
+    poll_bridge_transfers(config, pool).await
+}
+
+async fn poll_transactions(config: &Config, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    let pending = pending_transactions(pool, config.chain.chain_id as i64).await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let provider = get_provider(&config.chain).await?;
+    for mut record in pending {
+        let tx_hash = H256::from_slice(&hex::decode(record.tx_hash.trim_start_matches("0x"))?);
+        if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+            let block_number = receipt.block_number.map(|block| block.as_u64() as i64);
+            tracing::info!(tx_hash = %record.tx_hash, status = receipt_status(&receipt), "Tracked transaction was mined");
+            update_transaction_status(pool, &record.tx_hash, receipt_status(&receipt), block_number, false).await?;
+            record.status = receipt_status(&receipt).to_string();
+            record.block_number = block_number;
+            notify_outcome(config, &record).await;
+            continue;
+        }
+        // Unknown to the node while its nonce was used: dropped, or replaced by another transaction
+        if provider.get_transaction(tx_hash).await?.is_none() {
+            let from: Address = record.from_address.parse()?;
+            let mined_count = provider.get_transaction_count(from, Some(BlockNumber::Latest.into())).await?;
+            if U256::from(record.nonce) < mined_count {
+                tracing::info!(tx_hash = %record.tx_hash, "Tracked transaction was dropped or replaced");
+                update_transaction_status(pool, &record.tx_hash, "dropped", None, false).await?;
+                record.status = "dropped".to_string();
+                notify_outcome(config, &record).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+// A transfer is looked up with the bridge once its source transaction confirmed; one whose
+// source transaction failed never leaves, and that outcome was already reported
+async fn poll_bridge_transfers(config: &Config, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    for mut record in pending_bridge_transfers(pool, config.chain.chain_id as i64).await? {
+        match record.source_status.as_deref() {
+            Some("confirmed") => {},
+            Some("failed" | "dropped") => {
+                update_bridge_transfer(pool, &record.tx_hash, "failed", None, None, true).await?;
+                continue;
+            },
+            _ => continue,
+        }
+        let status = match bridge::status(&config.bridge, &record.tx_hash, Some(&record.bridge), record.from_chain_id as u64, Some(record.to_chain_id as u64)).await {
+            Ok(status) => status,
+            Err(e) => {
+                tracing::warn!(tx_hash = %record.tx_hash, error = %e, "Failed to look up bridge transfer");
+                continue;
+            },
+        };
+        if record_bridge_status(pool, &record, &status, false).await? {
+            tracing::info!(tx_hash = %record.tx_hash, status = status.outcome(), "Tracked bridge transfer completed");
+            record.status = status.outcome().to_string();
+            record.destination_tx_hash = status.destination_tx_hash.clone();
+            record.received = status.received.clone();
+            let title = format!("Bridge transfer {}", record.status);
+            notify::send(&config.notify, &Notification::new(Kind::Transaction, title, describe_bridge_update(&record))).await;
+        }
+    }
+    Ok(())
+}
+
+/// Poll pending transactions in the background until the process exits
+pub fn spawn(config: Arc<Config>, pool: Pool<Postgres>) {
+    if !config.tracker.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(config.tracker.poll_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = poll(&config, &pool).await {
+                tracing::warn!(error = %e, "Failed to poll pending transactions");
+            }
+        }
+    });
+}
+
+fn describe_update(record: &TransactionRecord) -> String {
+    let outcome = match (record.status.as_str(), record.block_number) {
+        ("confirmed", Some(block)) => format!("confirmed in block {}", block),
+        ("failed", Some(block)) => format!("failed (reverted) in block {}", block),
+        ("dropped", _) => "was dropped or replaced and will not confirm".to_string(),
+        (status, _) => status.to_string(),
+    };
+    format!("Your earlier transaction {} ({}) {}", record.tx_hash, record.description, outcome)
+}
+
+fn describe_bridge_update(record: &BridgeTransferRecord) -> String {
+    let to_chain = bridge::chain_name(record.to_chain_id as u64);
+    let mut outcome = match record.status.as_str() {
+        "done" => format!("arrived on {}", to_chain),
+        "partial" => format!("arrived on {}, but as another token than asked for", to_chain),
+        "refunded" => "was refunded on the source chain".to_string(),
+        "failed" => "failed".to_string(),
+        status => status.to_string(),
+    };
+    if let Some(received) = &record.received {
+        outcome.push_str(&format!(" ({})", received));
+    }
+    if let Some(hash) = &record.destination_tx_hash {
+        outcome.push_str(&format!(" in {}", hash));
+    }
+    format!("Your bridge transfer {} ({}) {}", record.tx_hash, record.description, outcome)
+}
+
+/// Outcomes of this session's transactions and bridge transfers that completed since they were
+/// last reported
+pub async fn take_updates(pool: Option<&Pool<Postgres>>, session_id: Uuid) -> Vec<String> {
+    let Some(pool) = pool else { return Vec::new() };
+    let mut updates = match take_transaction_updates(pool, session_id).await {
+        Ok(records) => records.iter().map(describe_update).collect(),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load transaction updates");
+            Vec::new()
+        },
+    };
+    match take_bridge_updates(pool, session_id).await {
+        Ok(records) => updates.extend(records.iter().map(describe_bridge_update)),
+        Err(e) => tracing::error!(error = %e, "Failed to load bridge transfer updates"),
+    }
+    updates
+}
@@ -0,0 +1,373 @@
+// Full-screen chat (`--tui`, built with the `tui` feature): the conversation in one pane, tool
+// calls, transactions, events, and log lines as they happen in another, and the chain's gas
+// price and the wallets' balances in a status bar.
+
+use crate::config::{AnthropicConfig, Config};
+use crate::conversation::Conversation;
+use crate::events::ChainEvent;
+use crate::personality::LivePersonality;
+use crate::redact::redact;
+use crate::scheduler::ScheduledRun;
+use crate::tools::ToolContext;
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+#[cfg(feature = "tui")]
+use crate::anthropic::{call_anthropic_with_personality, model_for};
+#[cfg(feature = "tui")]
+use crate::db::MessageStats;
+#[cfg(feature = "tui")]
+use crate::usage::TurnMeter;
+#[cfg(feature = "tui")]
+use crate::{alerts, gas, keystore, reminders, tools, tracker, units, wallet};
+#[cfg(feature = "tui")]
+use ethers::prelude::*;
+#[cfg(feature = "tui")]
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+#[cfg(feature = "tui")]
+use ratatui::layout::{Constraint, Layout};
+#[cfg(feature = "tui")]
+use ratatui::style::{Color, Modifier, Style};
+#[cfg(feature = "tui")]
+use ratatui::text::{Line, Span};
+#[cfg(feature = "tui")]
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+#[cfg(feature = "tui")]
+use ratatui::{DefaultTerminal, Frame};
+#[cfg(feature = "tui")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "tui")]
+use tokio::task::JoinHandle;
+
+// How often the screen is redrawn and keys are read
+#[cfg(feature = "tui")]
+const TICK: Duration = Duration::from_millis(100);
+
+// How often balances and gas are read again
+#[cfg(feature = "tui")]
+const STATUS_REFRESH: Duration = Duration::from_secs(15);
+
+// How often transaction outcomes, fired alerts, and fired reminders are read from the database
+#[cfg(feature = "tui")]
+const UPDATES_INTERVAL: Duration = Duration::from_secs(2);
+
+// Activity lines kept for the pane
+#[cfg(feature = "tui")]
+const ACTIVITY_LIMIT: usize = 500;
+
+lazy_static! {
+    // Log lines written while the screen is taken over, waiting to be shown in the activity pane
+    static ref LOG_LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Log writer for `--tui`, where lines on stderr would tear the display: each formatted event
+/// is redacted and shown in the activity pane instead
+pub struct LogPane;
+
+impl Write for LogPane {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        LOG_LINES.lock().unwrap().extend(text.lines().map(|line| redact(line).into_owned()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A chat session, set up as for the line-based chat
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub struct ChatSession {
+    pub config: Arc<Config>,
+    pub anthropic: AnthropicConfig,
+    pub pool: Option<PgPool>,
+    pub session_id: Uuid,
+    pub personality: LivePersonality,
+    pub conversation: Conversation,
+    pub ctx: ToolContext,
+    pub chain_events: mpsc::UnboundedReceiver<ChainEvent>,
+    pub scheduled_runs: mpsc::UnboundedReceiver<ScheduledRun>,
+}
+
+#[cfg(not(feature = "tui"))]
+pub async fn run(_session: ChatSession) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("The terminal interface is not enabled; rebuild with `--features tui`"))
+}
+
+/// Run the chat full-screen until the user quits
+#[cfg(feature = "tui")]
+pub async fn run(mut session: ChatSession) -> anyhow::Result<()> {
+    let activity = Arc::new(Mutex::new(Vec::new()));
+    session.ctx.activity = Some(activity.clone());
+    let status = Arc::new(Mutex::new("Reading balances...".to_string()));
+    let refresher = tokio::spawn(refresh_status(session.ctx.clone(), status.clone()));
+
+    let mut terminal = ratatui::init();
+    let result = App::new(session, activity, status).run(&mut terminal).await;
+    ratatui::restore();
+    refresher.abort();
+    result
+}
+
+// A reply being worked on: the prompt, and the task answering it with the history it
+// compacted and what the turn took
+#[cfg(feature = "tui")]
+type Turn = JoinHandle<(Conversation, anyhow::Result<String>, MessageStats)>;
+
+#[cfg(feature = "tui")]
+struct App {
+    session: ChatSession,
+    // Speaker and text of each message shown
+    chat: Vec<(String, String)>,
+    activity: Arc<Mutex<Vec<String>>>,
+    shown_activity: Vec<String>,
+    status: Arc<Mutex<String>>,
+    input: String,
+    // Lines the chat is scrolled up from its end
+    scroll: u16,
+    turn: Option<(String, Turn)>,
+    updates_read: Instant,
+    quit: bool,
+}
+
+#[cfg(feature = "tui")]
+impl App {
+    fn new(session: ChatSession, activity: Arc<Mutex<Vec<String>>>, status: Arc<Mutex<String>>) -> Self {
+        let chat = session.conversation.messages.iter()
+            .map(|message| (message.role().to_string(), message.plain_text()))
+            .collect();
+        App { session, chat, activity, shown_activity: Vec::new(), status, input: String::new(), scroll: 0, turn: None,
+              updates_read: Instant::now() - UPDATES_INTERVAL, quit: false }
+    }
+
+    async fn run(mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        let personality = self.session.personality.current();
+        self.note(format!("I'm {}, your {}. Enter sends, Esc stops a reply, PageUp/PageDown scroll, Ctrl-C quits.", personality.name, personality.role));
+        while !self.quit {
+            self.collect().await;
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.key(key.code, key.modifiers).await;
+            }
+            if self.turn.as_ref().is_some_and(|(_, turn)| turn.is_finished()) {
+                self.finish_turn().await;
+            }
+        }
+        if let Some((_, turn)) = self.turn.take() {
+            turn.abort();
+        }
+        Ok(())
+    }
+
+    fn note(&mut self, text: String) {
+        self.chat.push(("agent".to_string(), text));
+    }
+
+    fn log(&mut self, line: String) {
+        self.shown_activity.push(line);
+        if self.shown_activity.len() > ACTIVITY_LIMIT {
+            self.shown_activity.remove(0);
+        }
+    }
+
+    // Gather what happened since the last tick: tool calls, transaction outcomes, alerts, reminders,
+    // events, scheduled runs, and log lines
+    async fn collect(&mut self) {
+        let lines: Vec<String> = self.activity.lock().unwrap().drain(..).collect();
+        let logs: Vec<String> = LOG_LINES.lock().unwrap().drain(..).collect();
+        for line in lines {
+            self.log(line);
+        }
+        for line in logs {
+            self.log(format!("log: {}", line));
+        }
+        for update in gas::take_updates(self.session.session_id) {
+            self.log(format!("update: {}", update));
+        }
+        if self.updates_read.elapsed() >= UPDATES_INTERVAL {
+            self.updates_read = Instant::now();
+            let session = &self.session;
+            let updates = tracker::take_updates(session.pool.as_ref(), session.session_id).await;
+            let alerts = alerts::take_updates(session.pool.as_ref(), session.session_id).await;
+            let reminders = reminders::take_updates(session.pool.as_ref(), session.session_id).await;
+            for update in updates {
+                self.log(format!("update: {}", update));
+            }
+            for alert in alerts {
+                self.log(format!("alert: {}", alert));
+            }
+            for reminder in reminders {
+                self.log(format!("reminder: {}", reminder));
+            }
+        }
+        while let Ok(run) = self.session.scheduled_runs.try_recv() {
+            self.log(format!("scheduled ({}{}): {}", run.job, if run.success { "" } else { ", failed" }, run.output));
+        }
+        while let Ok(event) = self.session.chain_events.try_recv() {
+            self.log(format!("event: {}", event.description));
+            if self.session.config.events.trigger_agent && self.turn.is_none() {
+                self.start_turn(event.prompt(), false).await;
+            }
+        }
+    }
+
+    async fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Esc => {
+                if let Some((_, turn)) = self.turn.take() {
+                    turn.abort();
+                    self.note("Stopped. Transactions already sent this turn are not undone; /audit lists its tool calls.".to_string());
+                }
+            },
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                let input = input.trim();
+                if input.eq_ignore_ascii_case("exit") {
+                    self.quit = true;
+                } else if !input.is_empty() && self.turn.is_none() {
+                    self.start_turn(input.to_string(), true).await;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Send a prompt from the user, or one an event triggered, to the model in the background
+    async fn start_turn(&mut self, prompt: String, from_user: bool) {
+        let session = &self.session;
+        let personality = session.personality.current();
+        let model = model_for(&session.anthropic, Some(&personality));
+        let mut message_id = None;
+        if from_user {
+            message_id = crate::record_message(&session.ctx, session.session_id, "user", &prompt, &model, None).await;
+            self.chat.push(("you".to_string(), prompt.clone()));
+            self.scroll = 0;
+        }
+        let (anthropic, mut conversation) = (session.anthropic.clone(), session.conversation.clone());
+        let ctx = ToolContext { message_id, ..session.ctx.clone() };
+        let task_prompt = prompt.clone();
+        let turn = tokio::spawn(async move {
+            // Summarize older turns if the history has grown too large for the context window
+            if let Err(e) = conversation.compact(&ctx).await {
+                tracing::warn!(error = %e, "Failed to compact conversation history");
+            }
+            let meter = TurnMeter::start(&ctx);
+            let reply = call_anthropic_with_personality(&anthropic, &task_prompt, &[], Some(&personality), &conversation, &ctx).await;
+            (conversation, reply, meter.stats(&ctx))
+        });
+        self.turn = Some((prompt, turn));
+    }
+
+    async fn finish_turn(&mut self) {
+        let Some((prompt, turn)) = self.turn.take() else { return };
+        let (conversation, reply, stats) = match turn.await {
+            Ok(result) => result,
+            Err(e) => {
+                self.note(format!("Error: {}", e));
+                return;
+            },
+        };
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                self.note(format!("Error: {}", e));
+                return;
+            },
+        };
+        let session = &mut self.session;
+        let model = model_for(&session.anthropic, Some(&session.personality.current()));
+        crate::record_message(&session.ctx, session.session_id, "assistant", &reply, &model, Some(stats)).await;
+        session.conversation = conversation;
+        session.conversation.push("user", &prompt);
+        session.conversation.push("assistant", &reply);
+        let name = session.personality.current().name;
+        self.chat.push((name, reply));
+        self.scroll = 0;
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status, body, input] = Layout::vertical([Constraint::Length(1), Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+        let [chat, activity] = Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)]).areas(body);
+
+        let status_line = format!(" {} | {}", self.session.config.chain.name, self.status.lock().unwrap());
+        frame.render_widget(Paragraph::new(status_line).style(Style::default().bg(Color::Blue).fg(Color::White)), status);
+
+        let lines: Vec<Line> = self.chat.iter().flat_map(|(speaker, text)| {
+            let mut lines = vec![Line::from(Span::styled(format!("{}:", speaker), Style::default().add_modifier(Modifier::BOLD)))];
+            lines.extend(text.lines().map(|line| Line::from(line.to_string())));
+            lines.push(Line::default());
+            lines
+        }).collect();
+        let title = match &self.turn {
+            Some(_) => " Chat (thinking...) ",
+            None => " Chat ",
+        };
+        let chat_widget = Paragraph::new(lines).wrap(Wrap { trim: false }).block(Block::default().borders(Borders::ALL).title(title));
+        let height = chat.height.saturating_sub(2) as usize;
+        let total = chat_widget.line_count(chat.width.saturating_sub(2));
+        let offset = total.saturating_sub(height).saturating_sub(self.scroll as usize);
+        frame.render_widget(chat_widget.scroll((offset as u16, 0)), chat);
+
+        let shown = activity.height.saturating_sub(2) as usize;
+        let recent: Vec<Line> = self.shown_activity.iter()
+            .skip(self.shown_activity.len().saturating_sub(shown))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        let activity_widget = Paragraph::new(recent).wrap(Wrap { trim: false }).block(Block::default().borders(Borders::ALL).title(" Activity "));
+        frame.render_widget(activity_widget, activity);
+
+        let input_widget = Paragraph::new(self.input.as_str()).block(Block::default().borders(Borders::ALL).title(" You "));
+        frame.render_widget(input_widget, input);
+        frame.set_cursor_position((input.x + 1 + self.input.chars().count() as u16, input.y + 1));
+    }
+}
+
+// Read the gas price and the balances of the keystore and HD wallet accounts every
+// STATUS_REFRESH into the status bar
+#[cfg(feature = "tui")]
+async fn refresh_status(ctx: ToolContext, status: Arc<Mutex<String>>) {
+    let mut last = Instant::now() - STATUS_REFRESH;
+    loop {
+        tokio::time::sleep(STATUS_REFRESH.saturating_sub(last.elapsed())).await;
+        last = Instant::now();
+        let text = match tools::get_provider(&ctx.config.chain).await {
+            Ok(provider) => {
+                let gas = match provider.get_gas_price().await {
+                    Ok(price) => format!("gas {} gwei", units::format_gwei(price)),
+                    Err(_) => "gas unavailable".to_string(),
+                };
+                let mut accounts = keystore::unlocked(&ctx.keystore_dir());
+                accounts.extend(wallet::list_accounts().unwrap_or_default().into_iter()
+                    .map(|account| (account.label.unwrap_or_else(|| format!("#{}", account.index)), account.address)));
+                let mut parts = vec![gas];
+                for (name, address) in accounts {
+                    match provider.get_balance(address, None).await {
+                        Ok(balance) => parts.push(format!("{} {} ETH", name, units::format_eth(balance))),
+                        Err(_) => parts.push(format!("{} ?", name)),
+                    }
+                }
+                if parts.len() == 1 {
+                    parts.push("no accounts unlocked".to_string());
+                }
+                parts.join(" | ")
+            },
+            Err(e) => format!("not connected: {}", e),
+        };
+        *status.lock().unwrap() = text;
+    }
+}
@@ -0,0 +1,136 @@
+use ethers::types::{I256, U256};
+use ethers::utils::{format_units, parse_units};
+
+/// Parse an amount with an optional unit suffix into wei.
+///
+/// Accepts `"0.1"`, `"0.1 ETH"`, `"25 gwei"`, `"1000wei"` (units are case-insensitive,
+/// bare numbers are ETH). Fails instead of rounding when the amount has more decimals
+/// than the unit allows, so no value is silently truncated.
+pub fn parse_amount(input: &str) -> anyhow::Result<U256> {
+    let input = input.trim();
+    let split_at = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split_at);
+    let decimals = match unit.trim().to_lowercase().as_str() {
+        "" | "eth" | "ether" => 18,
+        "gwei" => 9,
+        "wei" => 0,
+        other => return Err(anyhow::anyhow!("Unknown unit '{}' (expected ETH, gwei, or wei)", other)),
+    };
+    
+    if number.is_empty() || number.matches('.').count() > 1 || number.starts_with('.') || number.ends_with('.') {
+        return Err(anyhow::anyhow!("Invalid amount: {}", input));
+    }
+    if let Some((_, fraction)) = number.split_once('.')
+        && fraction.len() > decimals
+    {
+        return Err(anyhow::anyhow!(
+            "Amount {} has more than {} decimal places for unit {}",
+            input,
+            decimals,
+            if unit.trim().is_empty() { "ETH" } else { unit.trim() }
+        ));
+    }
+    
+    Ok(parse_units(number, decimals as u32)
+        .map_err(|e| anyhow::anyhow!("Invalid amount {}: {}", input, e))?
+        .into())
+}
+
+/// Format wei as ETH without trailing zeros, e.g. `"0.1"` or `"2"`
+pub fn format_eth(wei: U256) -> String {
+    trim_decimal(format_units(wei, 18).unwrap_or_else(|_| wei.to_string()))
+}
+
+/// Format wei as gwei without trailing zeros, e.g. `"1.5"`
+pub fn format_gwei(wei: U256) -> String {
+    trim_decimal(format_units(wei, 9).unwrap_or_else(|_| wei.to_string()))
+}
+
+/// Format a token amount in its smallest unit with the token's decimals, e.g. `"12.5"`
+pub fn format_token(amount: U256, decimals: u32) -> String {
+    trim_decimal(format_units(amount, decimals).unwrap_or_else(|_| amount.to_string()))
+}
+
+/// Format a signed fixed-point value with `decimals` decimals, e.g. a Chainlink answer
+pub fn format_fixed(value: I256, decimals: u32) -> String {
+    trim_decimal(format_units(value, decimals).unwrap_or_else(|_| value.to_string()))
+}
+
+fn trim_decimal(value: String) -> String {
+    if !value.contains('.') {
+        return value;
+    }
+    value.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_eth_amounts_exactly() {
+        assert_eq!(parse_amount("1").unwrap(), U256::exp10(18));
+        assert_eq!(parse_amount("0.1").unwrap(), U256::exp10(17));
+        assert_eq!(parse_amount("0.1 ETH").unwrap(), U256::exp10(17));
+        assert_eq!(parse_amount("0.1eth").unwrap(), U256::exp10(17));
+        // 0.1 + 0.2 style values that are inexact as f64
+        assert_eq!(parse_amount("0.3").unwrap(), U256::from(300_000_000_000_000_000u64));
+        assert_eq!(parse_amount("1.000000000000000001").unwrap(), U256::exp10(18) + 1);
+    }
+
+    #[test]
+    fn parses_gwei_and_wei() {
+        assert_eq!(parse_amount("25 gwei").unwrap(), U256::from(25_000_000_000u64));
+        assert_eq!(parse_amount("1.5 GWEI").unwrap(), U256::from(1_500_000_000u64));
+        assert_eq!(parse_amount("0.000000001 gwei").unwrap(), U256::one());
+        assert_eq!(parse_amount("1000 wei").unwrap(), U256::from(1000));
+        assert_eq!(parse_amount("1000wei").unwrap(), U256::from(1000));
+    }
+
+    #[test]
+    fn handles_amounts_beyond_u128() {
+        let huge = parse_amount("1000000000000000000000 ETH").unwrap();
+        assert_eq!(huge, U256::exp10(39));
+        assert!(huge > U256::from(u128::MAX));
+    }
+
+    #[test]
+    fn rejects_precision_that_would_be_truncated() {
+        assert!(parse_amount("0.0000000000000000001").is_err());
+        assert!(parse_amount("1.5 wei").is_err());
+        assert!(parse_amount("0.0000000001 gwei").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for input in ["", "ETH", "abc", "1.2.3", ".5", "5.", "-1", "1 btc", "1e18"] {
+            assert!(parse_amount(input).is_err(), "{} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn formats_without_trailing_zeros() {
+        assert_eq!(format_eth(U256::exp10(17)), "0.1");
+        assert_eq!(format_eth(U256::exp10(18) * 2), "2");
+        assert_eq!(format_eth(U256::zero()), "0");
+        assert_eq!(format_eth(U256::one()), "0.000000000000000001");
+        assert_eq!(format_gwei(U256::from(1_500_000_000u64)), "1.5");
+        assert_eq!(format_gwei(U256::from(30_000_000_000u64)), "30");
+    }
+
+    #[test]
+    fn formats_signed_fixed_point() {
+        assert_eq!(format_fixed(I256::from(234_567_000_000i64), 8), "2345.67");
+        assert_eq!(format_fixed(I256::from(100_000_000i64), 8), "1");
+        assert_eq!(format_fixed(I256::from(-150_000_000i64), 8), "-1.5");
+    }
+
+    #[test]
+    fn round_trips() {
+        for input in ["0.1", "123.456", "0.000000000000000001", "99999999.999999999999999999"] {
+            assert_eq!(format_eth(parse_amount(input).unwrap()), input);
+        }
+    }
+}
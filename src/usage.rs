@@ -0,0 +1,135 @@
+use crate::config::{Config, ModelPrice};
+use crate::db::{add_session_usage, MessageStats};
+use crate::tools::ToolContext;
+use serde::Deserialize;
+use std::time::Instant;
+
+// Published list prices in USD per million input/output tokens, matched by model name prefix.
+// Entries in `[anthropic.prices]`, then in the model registry, take precedence.
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-sonnet", 3.0, 15.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-opus-4", 15.0, 75.0),
+];
+
+/// The `usage` block of a Messages API response
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// Input tokens written to the prompt cache, billed above the input price
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    /// Input tokens read from the prompt cache, billed well below the input price
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+/// Tokens and estimated cost accumulated over a session
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    pub fn summary(&self) -> String {
+        let cached = if self.cache_write_tokens + self.cache_read_tokens > 0 {
+            format!(" ({} written to and {} read from the prompt cache)", self.cache_write_tokens, self.cache_read_tokens)
+        } else {
+            String::new()
+        };
+        format!("{} requests, {} input + {} output tokens{}, estimated cost ${:.4}",
+                self.requests, self.input_tokens, self.output_tokens, cached, self.cost_usd)
+    }
+}
+
+/// Measures a turn for the answer's stored metadata: how long it ran, and the tokens of the
+/// model calls made with its context
+pub struct TurnMeter {
+    started: Instant,
+    before: UsageTotals,
+}
+
+impl TurnMeter {
+    pub fn start(ctx: &ToolContext) -> Self {
+        TurnMeter { started: Instant::now(), before: *ctx.usage.lock().unwrap() }
+    }
+
+    pub fn stats(&self, ctx: &ToolContext) -> MessageStats {
+        let after = *ctx.usage.lock().unwrap();
+        // Prompt tokens, whether or not they came from the cache
+        let prompt = |totals: &UsageTotals| totals.input_tokens + totals.cache_write_tokens + totals.cache_read_tokens;
+        MessageStats {
+            input_tokens: prompt(&after).saturating_sub(prompt(&self.before)) as i64,
+            output_tokens: after.output_tokens.saturating_sub(self.before.output_tokens) as i64,
+            latency_ms: self.started.elapsed().as_millis() as i64,
+        }
+    }
+}
+
+pub fn price_for(config: &Config, model: &str) -> Option<ModelPrice> {
+    if let Some(price) = config.anthropic.prices.get(model) {
+        return Some(*price);
+    }
+    if let Some(price) = config.models.get(model).and_then(|model| model.price()) {
+        return Some(price);
+    }
+    // Prefer the most specific matching prefix
+    DEFAULT_PRICES.iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())
+        .map(|(_, input, output)| ModelPrice { input_per_mtok: *input, output_per_mtok: *output })
+}
+
+// Cache writes (5-minute TTL) cost 1.25 times the input price, and cache reads a tenth of it
+const CACHE_WRITE_MULTIPLIER: f64 = 1.25;
+const CACHE_READ_MULTIPLIER: f64 = 0.1;
+
+pub fn estimate_cost(price: ModelPrice, usage: Usage) -> f64 {
+    let input = usage.input_tokens as f64
+        + usage.cache_creation_input_tokens as f64 * CACHE_WRITE_MULTIPLIER
+        + usage.cache_read_input_tokens as f64 * CACHE_READ_MULTIPLIER;
+    (input * price.input_per_mtok + usage.output_tokens as f64 * price.output_per_mtok) / 1_000_000.0
+}
+
+/// Add one response's usage to the running totals and the session row
+pub async fn record(ctx: &ToolContext, model: &str, usage: Usage) {
+    let cost = match price_for(&ctx.config, model) {
+        Some(price) => estimate_cost(price, usage),
+        None => {
+            tracing::warn!(model, "No price known for model; cost is not included in the estimate");
+            0.0
+        }
+    };
+    tracing::debug!(model, input_tokens = usage.input_tokens, output_tokens = usage.output_tokens,
+                    cache_write_tokens = usage.cache_creation_input_tokens, cache_read_tokens = usage.cache_read_input_tokens,
+                    cost, "Recorded token usage");
+
+    {
+        let mut totals = ctx.usage.lock().unwrap();
+        totals.requests += 1;
+        totals.input_tokens += usage.input_tokens;
+        totals.output_tokens += usage.output_tokens;
+        totals.cache_write_tokens += usage.cache_creation_input_tokens;
+        totals.cache_read_tokens += usage.cache_read_input_tokens;
+        totals.cost_usd += cost;
+    }
+
+    if let (Some(pool), Some(session_id)) = (&ctx.pool, ctx.session_id)
+        && let Err(e) = add_session_usage(pool, session_id, usage, cost).await
+    {
+        tracing::error!(error = %e, "Failed to record token usage");
+    }
+}
@@ -0,0 +1,191 @@
+// Users of one server deployment shared by a small team. A user is the address they sign in
+// with (Sign-In with Ethereum), or an API key issued to them with `agent users key`, and has
+// their own sessions and history, their own keystore accounts (see `ToolContext::keystore_dir`),
+// and, if one is set, their own transaction policy instead of the deployment's. Their role
+// decides which tool calls they may have the agent make, checked as each call is dispatched.
+
+use crate::policy::{policy_engine_from_json, PolicyEngine};
+use crate::tool_error::ToolError;
+use ethers::types::Address;
+use ethers::utils::keccak256;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Start of every API key, telling them apart from sign-in tokens
+pub const API_KEY_PREFIX: &str = "agk_";
+
+// Tool calls that only read, which viewers may make
+const READ_CALLS: &[&str] = &[
+    "get_weather", "get_time", "get_token_price", "chainlink_price", "portfolio", "contract_call",
+    "chain_query", "dune_query", "web_search", "fetch_url", "read_file", "list_dir", "calc",
+    "gas_tracker.report", "gas_tracker.waiting", "price_alert.list", "pnl.positions", "remember.list", "remind_me.list", "address_book.list",
+    "eth_wallet.balance", "eth_wallet.resolve", "eth_wallet.lookup", "eth_wallet.accounts", "eth_wallet.wallets",
+    "dex_swap.quote", "sign_message.verify_signature", "nft.owned", "nft.metadata", "ipfs.fetch",
+    "safe_multisig.info", "safe_multisig.pending", "smart_account.address", "snapshot.proposals", "snapshot.proposal",
+    "governor.proposals", "governor.proposal", "governor.voting_power", "stake.rates", "stake.position",
+    "lending.health_factor", "bridge.quote", "bridge.status", "approvals.list",
+];
+
+// Tool calls that change the accounts the agent signs with, which only admins may make
+const ADMIN_CALLS: &[&str] = &[
+    "eth_wallet.generate", "eth_wallet.mnemonic_generate", "eth_wallet.derive",
+    "eth_wallet.connect_ledger",
+];
+
+/// What a user may have the agent do, least first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read-only tools: balances, prices, quotes, lookups
+    Viewer,
+    /// Also sends, swaps, and signs, within the transaction policy
+    Operator,
+    /// Also changes keystore accounts and the HD wallet, and users' policies
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Role> {
+        [Role::Viewer, Role::Operator, Role::Admin].into_iter().find(|role| role.as_str() == text)
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The role a tool call needs. Calls not known to only read, those of plugins and MCP servers
+/// among them, need an operator.
+pub fn required_role(tool: &str, operation: Option<&str>) -> Role {
+    let listed = |calls: &[&str]| calls.iter()
+        .any(|call| *call == tool || operation.is_some_and(|op| *call == format!("{}.{}", tool, op)));
+    if listed(ADMIN_CALLS) {
+        Role::Admin
+    } else if listed(READ_CALLS) {
+        Role::Viewer
+    } else {
+        Role::Operator
+    }
+}
+
+/// Refuse a tool call that `role` doesn't allow; without a role (outside server mode) every
+/// call is allowed
+pub fn check_role(role: Option<Role>, tool: &str, args: &serde_json::Value) -> Result<(), ToolError> {
+    let Some(role) = role else { return Ok(()) };
+    let operation = args.get("operation").and_then(|v| v.as_str());
+    let required = required_role(tool, operation);
+    if role >= required {
+        return Ok(());
+    }
+    tracing::warn!(tool, operation, %role, %required, "Refused tool call above the user's role");
+    Err(ToolError::PolicyViolation(format!(
+        "{}{} needs the {} role, and this user is a {}; ask an admin to make the call or change your role",
+        tool, operation.map(|op| format!(".{}", op)).unwrap_or_default(), required, role
+    )))
+}
+
+/// A new random API key
+pub fn new_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, hex::encode(rand::thread_rng().r#gen::<[u8; 32]>()))
+}
+
+/// What is stored of an API key
+pub fn key_hash(key: &str) -> String {
+    hex::encode(keccak256(key.trim().as_bytes()))
+}
+
+/// A user's address as sessions and wallets record their owner: lowercase, 0x-prefixed
+pub fn user_address(text: &str) -> anyhow::Result<String> {
+    let address: Address = text.trim().parse().map_err(|_| anyhow::anyhow!("Invalid address: {}", text))?;
+    Ok(format!("{:?}", address))
+}
+
+/// Policy engines of the users who have a policy of their own, kept between requests so that
+/// their daily limits count everything they sent
+#[derive(Default)]
+pub struct UserPolicies {
+    // By address, with the policy each was built from; a changed policy starts a new engine
+    engines: Mutex<HashMap<String, (serde_json::Value, Arc<PolicyEngine>)>>,
+}
+
+impl UserPolicies {
+    /// The engine for a user's transactions: that of their own `policy`, or `default` without one
+    pub fn for_user(&self, address: &str, policy: Option<serde_json::Value>, default: &Arc<PolicyEngine>) -> Arc<PolicyEngine> {
+        let Some(policy) = policy else { return default.clone() };
+        let mut engines = self.engines.lock().unwrap();
+        if let Some((current, engine)) = engines.get(address)
+            && *current == policy
+        {
+            return engine.clone();
+        }
+        let engine = Arc::new(policy_engine_from_json(address, &policy));
+        engines.insert(address.to_string(), (policy, engine.clone()));
+        engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::db::{api_key_user, find_user, save_api_key, save_user, set_user_policy};
+    use crate::policy::{Policy, TxIntent};
+    use crate::test_support::context;
+    use crate::test_support::database;
+    use crate::tools::{execute_tool, ToolContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn gives_users_api_keys_and_policies_of_their_own() {
+        let mut config = Config::default();
+        let pool = database(&mut config).await;
+        let address = format!("{:?}", ethers::types::Address::random());
+        save_user(&pool, &address, Some("carol"), "operator").await.unwrap();
+        let key = new_api_key();
+        save_api_key(&pool, &key_hash(&key), &address, Some("tests")).await.unwrap();
+        assert_eq!(api_key_user(&pool, &key_hash(&key)).await.unwrap(), Some(address.clone()));
+        assert_eq!(api_key_user(&pool, &key_hash(&new_api_key())).await.unwrap(), None);
+
+        let deployment = Arc::new(PolicyEngine::new(Policy::default()));
+        let policies = UserPolicies::default();
+        let own_policy = || async { find_user(&pool, &address).await.unwrap().unwrap().policy };
+        let intent = TxIntent::new(1, ethers::types::Address::random(), ethers::utils::parse_ether("1").unwrap(), &[]);
+        assert!(policies.for_user(&address, own_policy().await, &deployment).check(&intent, Some(&pool)).await.is_ok());
+
+        set_user_policy(&pool, &address, Some(&json!({ "max_eth_per_tx": "0.1" }))).await.unwrap();
+        let policy = policies.for_user(&address, own_policy().await, &deployment);
+        assert!(policy.check(&intent, Some(&pool)).await.is_err());
+        // The same engine is used for the user's later requests, so daily limits add up
+        assert!(Arc::ptr_eq(&policy, &policies.for_user(&address, own_policy().await, &deployment)));
+    }
+
+    #[tokio::test]
+    async fn refuses_tool_calls_above_the_users_role() {
+        let base = context(Config::default());
+        let viewer = ToolContext { role: Some(Role::Viewer), ..base.clone() };
+        let operator = ToolContext { role: Some(Role::Operator), ..base };
+
+        assert!(execute_tool(&viewer, "get_time", &json!({ "timezone": "UTC" })).await.is_ok());
+        let send = json!({ "operation": "send", "to_address": "0x000000000000000000000000000000000000dEaD", "amount": "1" });
+        let refused = execute_tool(&viewer, "eth_wallet", &send).await.unwrap_err();
+        assert!(matches!(refused, ToolError::PolicyViolation(_)), "{:?}", refused);
+        assert!(refused.message().contains("eth_wallet.send needs the operator role"));
+
+        let generate = json!({ "operation": "generate", "label": "operator-made" });
+        let refused = execute_tool(&operator, "eth_wallet", &generate).await.unwrap_err();
+        assert!(refused.message().contains("needs the admin role"), "{:?}", refused);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::kms::GcpKmsSigner;
+use ethers::prelude::*;
+use ethers::signers::coins_bip39::{English, Mnemonic};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::utils::hash_message;
+use std::sync::Mutex;
+
+/// BIP-44 derivation path for the Nth Ethereum account
+pub fn derivation_path(index: u32) -> String {
+    format!("m/44'/60'/0'/0/{}", index)
+}
+
+#[derive(Debug, Clone)]
+pub struct DerivedAccount {
+    pub index: u32,
+    pub label: Option<String>,
+    pub address: Address,
+}
+
+// The active mnemonic and the accounts derived from it so far
+struct HdWallet {
+    phrase: String,
+    accounts: Vec<DerivedAccount>,
+}
+
+/// A Ledger account connected in this session
+#[derive(Debug, Clone)]
+pub struct LedgerAccount {
+    pub index: u32,
+    pub path: String,
+    pub address: Address,
+}
+
+// In-memory HD wallet, seeded from MNEMONIC or the keystore's mnemonic, and the connected
+// Ledger accounts (addresses and paths only; their keys never leave the device)
+lazy_static::lazy_static! {
+    static ref HD_WALLET: Mutex<Option<HdWallet>> = Mutex::new(None);
+    static ref LEDGER_ACCOUNTS: Mutex<Vec<LedgerAccount>> = Mutex::new(Vec::new());
+}
+
+/// Signing backend for an account: a key held in memory, a Ledger that signs only after the
+/// user confirms on the device, or a key in a cloud KMS
+#[derive(Debug)]
+pub enum AgentSigner {
+    Local(LocalWallet),
+    #[cfg(feature = "ledger")]
+    Ledger(Ledger),
+    #[cfg(feature = "aws")]
+    Aws(AwsSigner),
+    Gcp(GcpKmsSigner),
+}
+
+#[derive(Debug)]
+pub struct SignerError(String);
+
+impl std::fmt::Display for SignerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+fn signer_error(e: impl std::fmt::Display) -> SignerError {
+    SignerError(e.to_string())
+}
+
+// Message and typed data signatures use v = 27 or 28, which remote signers don't all return
+fn with_electrum_v(signature: Signature) -> Signature {
+    let recovery_id = match signature.v {
+        0 | 1 => signature.v,
+        27 | 28 => signature.v - 27,
+        // EIP-155: chain_id * 2 + 35 + recovery id
+        v => (v - 35) % 2,
+    };
+    Signature { v: 27 + recovery_id, ..signature }
+}
+
+#[async_trait::async_trait]
+impl Signer for AgentSigner {
+    type Error = SignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(&self, message: S) -> Result<Signature, Self::Error> {
+        match self {
+            AgentSigner::Local(wallet) => wallet.sign_message(message).await.map_err(signer_error),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => Signer::sign_message(ledger, message).await.map_err(signer_error),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => aws.sign_message(message).await.map(with_electrum_v).map_err(signer_error),
+            AgentSigner::Gcp(gcp) => gcp.sign_digest(hash_message(message)).await.map(with_electrum_v).map_err(signer_error),
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            AgentSigner::Local(wallet) => wallet.sign_transaction(tx).await.map_err(signer_error),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => ledger.sign_transaction(tx).await.map_err(signer_error),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => aws.sign_transaction(tx).await.map_err(signer_error),
+            AgentSigner::Gcp(gcp) => {
+                let mut tx = tx.clone();
+                let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(gcp.chain_id());
+                tx.set_chain_id(chain_id);
+                let signature = gcp.sign_digest(tx.sighash()).await.map_err(signer_error)?;
+                Ok(Signature { v: chain_id * 2 + 35 + signature.v, ..signature })
+            },
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(&self, payload: &T) -> Result<Signature, Self::Error> {
+        match self {
+            AgentSigner::Local(wallet) => wallet.sign_typed_data(payload).await.map_err(signer_error),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => ledger.sign_typed_data(payload).await.map_err(signer_error),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => aws.sign_typed_data(payload).await.map(with_electrum_v).map_err(signer_error),
+            AgentSigner::Gcp(gcp) => {
+                let digest = payload.encode_eip712().map_err(signer_error)?;
+                gcp.sign_digest(H256::from(digest)).await.map(with_electrum_v).map_err(signer_error)
+            },
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            AgentSigner::Local(wallet) => wallet.address(),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => ledger.address(),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => aws.address(),
+            AgentSigner::Gcp(gcp) => gcp.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            AgentSigner::Local(wallet) => wallet.chain_id(),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => ledger.chain_id(),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => aws.chain_id(),
+            AgentSigner::Gcp(gcp) => gcp.chain_id(),
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            AgentSigner::Local(wallet) => AgentSigner::Local(wallet.with_chain_id(chain_id)),
+            #[cfg(feature = "ledger")]
+            AgentSigner::Ledger(ledger) => AgentSigner::Ledger(ledger.with_chain_id(chain_id)),
+            #[cfg(feature = "aws")]
+            AgentSigner::Aws(aws) => AgentSigner::Aws(aws.with_chain_id(chain_id)),
+            AgentSigner::Gcp(gcp) => AgentSigner::Gcp(gcp.with_chain_id(chain_id.into())),
+        }
+    }
+}
+
+impl AgentSigner {
+    /// The in-memory key, for signing that hardware wallets don't offer (such as raw hashes)
+    pub fn local_wallet(&self) -> Option<&LocalWallet> {
+        match self {
+            AgentSigner::Local(wallet) => Some(wallet),
+            _ => None,
+        }
+    }
+}
+
+fn derive_signer(phrase: &str, index: u32) -> anyhow::Result<LocalWallet> {
+    Ok(MnemonicBuilder::<English>::default()
+        .phrase(phrase)
+        .derivation_path(&derivation_path(index))?
+        .build()?)
+}
+
+// Import MNEMONIC from the environment the first time the HD wallet is used
+fn ensure_loaded(hd_wallet: &mut Option<HdWallet>) -> anyhow::Result<()> {
+    if hd_wallet.is_none()
+        && let Ok(phrase) = std::env::var("MNEMONIC")
+        && !phrase.trim().is_empty()
+    {
+        *hd_wallet = Some(new_hd_wallet(phrase.trim())?);
+    }
+    Ok(())
+}
+
+// Validate a phrase and derive its first account
+fn new_hd_wallet(phrase: &str) -> anyhow::Result<HdWallet> {
+    Mnemonic::<English>::new_from_phrase(phrase)
+        .map_err(|e| anyhow::anyhow!("Invalid mnemonic: {}", e))?;
+    let first = derive_signer(phrase, 0)?;
+    Ok(HdWallet {
+        phrase: phrase.to_string(),
+        accounts: vec![DerivedAccount { index: 0, label: None, address: first.address() }],
+    })
+}
+
+/// Load the mnemonic stored in the keystore (`wallet import-mnemonic`), unless MNEMONIC is set
+pub fn load_stored_mnemonic(keystore_dir: &str) {
+    if std::env::var("MNEMONIC").is_ok_and(|phrase| !phrase.trim().is_empty()) {
+        return;
+    }
+    let loaded = crate::keystore::mnemonic(keystore_dir)
+        .and_then(|phrase| phrase.map(|phrase| import_mnemonic(&phrase)).transpose());
+    if let Err(e) = loaded {
+        tracing::warn!(error = %e, "Could not load the HD wallet's mnemonic from the keystore");
+    }
+}
+
+/// Generate a new random 12-word mnemonic and make it the active HD wallet
+pub fn generate_mnemonic() -> anyhow::Result<(String, DerivedAccount)> {
+    let mut rng = rand::thread_rng();
+    let phrase = Mnemonic::<English>::new_with_count(&mut rng, 12)
+        .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic: {}", e))?
+        .to_phrase();
+    let hd_wallet = new_hd_wallet(&phrase)?;
+    let first = hd_wallet.accounts[0].clone();
+    *HD_WALLET.lock().unwrap() = Some(hd_wallet);
+    Ok((phrase, first))
+}
+
+/// Import an existing mnemonic, replacing the active HD wallet
+pub fn import_mnemonic(phrase: &str) -> anyhow::Result<DerivedAccount> {
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    let hd_wallet = new_hd_wallet(&phrase)?;
+    let first = hd_wallet.accounts[0].clone();
+    *HD_WALLET.lock().unwrap() = Some(hd_wallet);
+    Ok(first)
+}
+
+/// Derive the account at `index`, optionally labelling it. Re-deriving an index updates its label.
+pub fn derive_account(index: u32, label: Option<&str>) -> anyhow::Result<DerivedAccount> {
+    let mut guard = HD_WALLET.lock().unwrap();
+    ensure_loaded(&mut guard)?;
+    let hd_wallet = guard.as_mut()
+        .ok_or_else(|| anyhow::anyhow!("No mnemonic loaded. Generate or import one first."))?;
+    
+    if let Some(label) = label
+        && hd_wallet.accounts.iter().any(|a| a.index != index && a.label.as_deref() == Some(label))
+    {
+        return Err(anyhow::anyhow!("Label '{}' is already used by another account", label));
+    }
+    
+    let address = derive_signer(&hd_wallet.phrase, index)?.address();
+    let account = match hd_wallet.accounts.iter_mut().find(|a| a.index == index) {
+        Some(existing) => {
+            if label.is_some() {
+                existing.label = label.map(str::to_string);
+            }
+            existing.clone()
+        },
+        None => {
+            let account = DerivedAccount { index, label: label.map(str::to_string), address };
+            hd_wallet.accounts.push(account.clone());
+            hd_wallet.accounts.sort_by_key(|a| a.index);
+            account
+        }
+    };
+    Ok(account)
+}
+
+/// Accounts derived from the active mnemonic, ordered by index
+pub fn list_accounts() -> anyhow::Result<Vec<DerivedAccount>> {
+    let mut guard = HD_WALLET.lock().unwrap();
+    ensure_loaded(&mut guard)?;
+    Ok(guard.as_ref().map(|w| w.accounts.clone()).unwrap_or_default())
+}
+
+/// Find a derived account by index ("2") or label ("trading")
+pub fn find_account(selector: &str) -> anyhow::Result<DerivedAccount> {
+    let selector = selector.trim();
+    let accounts = list_accounts()?;
+    if accounts.is_empty() {
+        return Err(anyhow::anyhow!("No mnemonic loaded. Generate or import one first."));
+    }
+    if let Some(account) = accounts.iter().find(|a| a.label.as_deref() == Some(selector)) {
+        return Ok(account.clone());
+    }
+    match selector.parse::<u32>() {
+        Ok(index) => derive_account(index, None),
+        Err(_) => Err(anyhow::anyhow!("No account with label '{}'", selector)),
+    }
+}
+
+/// Signing wallet for a derived account, looked up by address
+pub fn signer_for_address(address: Address) -> Option<LocalWallet> {
+    let mut guard = HD_WALLET.lock().unwrap();
+    ensure_loaded(&mut guard).ok()?;
+    let hd_wallet = guard.as_ref()?;
+    let account = hd_wallet.accounts.iter().find(|a| a.address == address)?;
+    derive_signer(&hd_wallet.phrase, account.index).ok()
+}
+
+/// Ledger derivation path for the Nth account: Ledger Live (`m/44'/60'/N'/0/0`) or the
+/// legacy MEW/MyCrypto layout (`m/44'/60'/0'/N`)
+#[cfg(feature = "ledger")]
+fn ledger_path(derivation: &str, index: u32) -> anyhow::Result<String> {
+    match derivation {
+        "ledger_live" => Ok(format!("m/44'/60'/{}'/0/0", index)),
+        "legacy" => Ok(format!("m/44'/60'/0'/{}", index)),
+        other => Err(anyhow::anyhow!("Unknown Ledger derivation '{}'; use 'ledger_live' or 'legacy'", other)),
+    }
+}
+
+/// Read the address of a Ledger account from the device and remember it for signing. The
+/// Ethereum app must be open on the unlocked device.
+#[cfg(feature = "ledger")]
+pub async fn connect_ledger(derivation: &str, index: u32, chain_id: u64) -> anyhow::Result<LedgerAccount> {
+    let path = ledger_path(derivation, index)?;
+    let ledger = Ledger::new(HDPath::Other(path.clone()), chain_id).await
+        .map_err(|e| anyhow::anyhow!("Could not connect to the Ledger: {}. Is it unlocked with the Ethereum app open?", e))?;
+    let account = LedgerAccount { index, path, address: ledger.address() };
+    // Release the device so other apps (and the next signature) can use it
+    ledger.close();
+
+    let mut accounts = LEDGER_ACCOUNTS.lock().unwrap();
+    accounts.retain(|a| a.address != account.address);
+    accounts.push(account.clone());
+    Ok(account)
+}
+
+#[cfg(not(feature = "ledger"))]
+pub async fn connect_ledger(_derivation: &str, _index: u32, _chain_id: u64) -> anyhow::Result<LedgerAccount> {
+    Err(anyhow::anyhow!("Ledger support is not enabled; rebuild with `--features ledger`"))
+}
+
+/// Find a connected Ledger account by selector: "ledger" for the first one connected, or
+/// "ledger:N" for account index N
+pub fn find_ledger_account(selector: &str) -> Option<LedgerAccount> {
+    let accounts = LEDGER_ACCOUNTS.lock().unwrap();
+    match selector.trim().strip_prefix("ledger") {
+        Some("") => accounts.first().cloned(),
+        Some(index) => {
+            let index: u32 = index.strip_prefix(':')?.parse().ok()?;
+            accounts.iter().find(|a| a.index == index).cloned()
+        },
+        None => None,
+    }
+}
+
+/// Open the Ledger to sign for a connected account, looked up by address
+#[cfg(feature = "ledger")]
+pub async fn ledger_signer(address: Address, chain_id: u64) -> Option<anyhow::Result<AgentSigner>> {
+    let account = LEDGER_ACCOUNTS.lock().unwrap().iter().find(|a| a.address == address).cloned()?;
+    let result = Ledger::new(HDPath::Other(account.path), chain_id).await
+        .map_err(|e| anyhow::anyhow!("Could not connect to the Ledger: {}", e))
+        .and_then(|ledger| {
+            // A different device, or a different seed, is plugged in
+            if ledger.address() != address {
+                return Err(anyhow::anyhow!("The connected Ledger does not hold account {:?}", address));
+            }
+            Ok(AgentSigner::Ledger(ledger))
+        });
+    Some(result)
+}
+
+#[cfg(not(feature = "ledger"))]
+pub async fn ledger_signer(_address: Address, _chain_id: u64) -> Option<anyhow::Result<AgentSigner>> {
+    None
+}
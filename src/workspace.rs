@@ -0,0 +1,161 @@
+// Files the agent reads and writes on request (`read_file`, `write_file`, and `list_dir`
+// tools), such as reports, CSV exports, and generated ABIs. Everything stays inside the
+// workspace directory: paths must be relative and may not step out with `..`, and symlinks
+// that lead outside it are refused. In server mode each user has a workspace of their own.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Entries listed for one directory
+const MAX_ENTRIES: usize = 200;
+
+// The workspace's real path, created on first use
+fn root(dir: &str) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("Cannot create the workspace {}: {}", dir, e))?;
+    Ok(fs::canonicalize(dir)?)
+}
+
+/// The path a workspace-relative path stands for, refused when it would lead outside the
+/// workspace
+pub fn resolve(dir: &str, path: &str) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(path.trim());
+    if relative.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(anyhow::anyhow!("Paths must be relative to the workspace and stay inside it, not {}", path));
+    }
+    let root = root(dir)?;
+    let full = root.join(relative);
+    // The nearest part of the path that exists, with symlinks followed, must still be inside
+    let existing = full.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(&root);
+    // A symlink to nowhere would be written through
+    let dangling = fs::symlink_metadata(&full).is_ok_and(|metadata| metadata.file_type().is_symlink()) && !full.exists();
+    if dangling || !fs::canonicalize(existing)?.starts_with(&root) {
+        return Err(anyhow::anyhow!("{} leads outside the workspace", path));
+    }
+    Ok(full)
+}
+
+// How a path is shown: relative to the workspace
+fn display(dir: &str, full: &Path) -> String {
+    let relative = root(dir).ok()
+        .and_then(|root| full.strip_prefix(root).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| full.to_path_buf());
+    relative.to_string_lossy().into_owned()
+}
+
+/// A text file of the workspace, up to `max_bytes`
+pub fn read(dir: &str, path: &str, max_bytes: u64) -> anyhow::Result<String> {
+    let full = resolve(dir, path)?;
+    let metadata = fs::metadata(&full).map_err(|_| anyhow::anyhow!("No file {} in the workspace", path))?;
+    if metadata.is_dir() {
+        return Err(anyhow::anyhow!("{} is a directory; list it with list_dir", path));
+    }
+    if metadata.len() > max_bytes {
+        return Err(anyhow::anyhow!("{} is {} bytes, over the {} byte limit", path, metadata.len(), max_bytes));
+    }
+    String::from_utf8(fs::read(&full)?).map_err(|_| anyhow::anyhow!("{} is not a text file", path))
+}
+
+/// Write (or append to) a text file of the workspace, creating its directories, and say what
+/// was done
+pub fn write(dir: &str, path: &str, content: &str, append: bool, max_bytes: u64) -> anyhow::Result<String> {
+    let full = resolve(dir, path)?;
+    if full.is_dir() {
+        return Err(anyhow::anyhow!("{} is a directory", path));
+    }
+    let existing = if append { fs::metadata(&full).map(|metadata| metadata.len()).unwrap_or(0) } else { 0 };
+    if existing + content.len() as u64 > max_bytes {
+        return Err(anyhow::anyhow!("Files in the workspace are at most {} bytes", max_bytes));
+    }
+    if let Some(parent) = full.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let existed = full.exists();
+    if append {
+        use std::io::Write;
+        fs::OpenOptions::new().create(true).append(true).open(&full)?.write_all(content.as_bytes())?;
+    } else {
+        fs::write(&full, content)?;
+    }
+    let action = match (append, existed) {
+        (true, true) => "Appended",
+        (false, true) => "Overwrote",
+        _ => "Created",
+    };
+    Ok(format!("{} {} ({} bytes written)", action, display(dir, &full), content.len()))
+}
+
+/// The entries of a workspace directory, directories first
+pub fn list(dir: &str, path: &str) -> anyhow::Result<String> {
+    let full = resolve(dir, path)?;
+    if !full.is_dir() {
+        return Err(anyhow::anyhow!("No directory {} in the workspace", path));
+    }
+    let mut entries: Vec<(bool, String, u64)> = fs::read_dir(&full)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((!metadata.is_dir(), entry.file_name().to_string_lossy().into_owned(), metadata.len()))
+        })
+        .collect();
+    entries.sort();
+    let shown = display(dir, &full);
+    let name = if shown.is_empty() { "the workspace".to_string() } else { shown };
+    if entries.is_empty() {
+        return Ok(format!("{} is empty", name));
+    }
+    let mut lines: Vec<String> = entries.iter().take(MAX_ENTRIES)
+        .map(|(is_file, entry, size)| match is_file {
+            true => format!("- {} ({} bytes)", entry, size),
+            false => format!("- {}/", entry),
+        })
+        .collect();
+    if entries.len() > MAX_ENTRIES {
+        lines.push(format!("- and {} more", entries.len() - MAX_ENTRIES));
+    }
+    Ok(format!("Contents of {}:\n{}", name, lines.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::context;
+    use crate::tools::{execute_tool, ToolContext};
+    use crate::users::Role;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn keeps_file_tools_inside_the_workspace() {
+        let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.workspace.dir = dir.to_string_lossy().into_owned();
+        let ctx = context(config);
+
+        let created = execute_tool(&ctx, "write_file", &json!({ "path": "reports/balances.csv", "content": "account,eth\n" })).await.unwrap();
+        assert_eq!(created, "Created reports/balances.csv (12 bytes written)");
+        execute_tool(&ctx, "write_file", &json!({ "path": "reports/balances.csv", "content": "main,1.5\n", "append": true })).await.unwrap();
+        let read = execute_tool(&ctx, "read_file", &json!({ "path": "./reports/balances.csv" })).await.unwrap();
+        assert_eq!(read, "account,eth\nmain,1.5\n");
+        let listed = execute_tool(&ctx, "list_dir", &json!({})).await.unwrap();
+        assert_eq!(listed, "Contents of the workspace:\n- reports/");
+        let listed = execute_tool(&ctx, "list_dir", &json!({ "path": "reports" })).await.unwrap();
+        assert_eq!(listed, "Contents of reports:\n- balances.csv (21 bytes)");
+
+        // Nothing outside the workspace can be reached, with .., absolute paths, or symlinks
+        std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+        for path in ["../outside.txt", "/etc/passwd", "reports/../../outside.txt", "etc/passwd", "etc/new.txt"] {
+            let read = execute_tool(&ctx, "read_file", &json!({ "path": path })).await.unwrap_err();
+            assert_eq!(read.kind(), "invalid_args", "{}: {:?}", path, read);
+            let written = execute_tool(&ctx, "write_file", &json!({ "path": path, "content": "x" })).await.unwrap_err();
+            assert_eq!(written.kind(), "invalid_args", "{}: {:?}", path, written);
+        }
+        assert!(!dir.parent().unwrap().join("outside.txt").exists());
+
+        // Viewers may read the workspace but not write to it
+        let viewer = ToolContext { role: Some(Role::Viewer), ..ctx.clone() };
+        assert!(execute_tool(&viewer, "read_file", &json!({ "path": "reports/balances.csv" })).await.is_ok());
+        let refused = execute_tool(&viewer, "write_file", &json!({ "path": "notes.txt", "content": "x" })).await.unwrap_err();
+        assert_eq!(refused.kind(), "policy_violation");
+        std::fs::remove_dir_all(dir).ok();
+    }
+}